@@ -0,0 +1,400 @@
+//! Check how well an S3 endpoint supports the semantics Mountpoint relies on.
+//!
+//! This runs a fixed suite of checks (object metadata, listing edge cases, and write behavior)
+//! against a real bucket and prints a compatibility report, so it can be used to evaluate a new S3
+//! personality or an S3-compatible object store before claiming Mountpoint supports it. The checks
+//! create and delete objects under the given prefix, which should not be used for anything else.
+//!
+//! Exits with a non-zero status if any check fails.
+
+use std::ops::Range;
+use std::pin::pin;
+
+use clap::Parser;
+use futures::StreamExt;
+use mountpoint_s3_client::config::{AddressingStyle, EndpointConfig, S3ClientConfig};
+use mountpoint_s3_client::error::{GetObjectError, HeadObjectError, ObjectClientError};
+use mountpoint_s3_client::types::{ETag, GetObjectParams, PutObjectParams};
+use mountpoint_s3_client::{ObjectClient, PutObjectRequest, S3CrtClient};
+use mountpoint_s3_crt::common::allocator::Allocator;
+use mountpoint_s3_crt::common::rust_log_adapter::RustLogAdapter;
+use mountpoint_s3_crt::common::uri::Uri;
+use tracing_subscriber::fmt::Subscriber;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+/// Like `tracing_subscriber::fmt::init` but sends logs to stderr
+fn init_tracing_subscriber() {
+    RustLogAdapter::try_init().expect("unable to install CRT log adapter");
+
+    let subscriber = Subscriber::builder()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .finish();
+
+    subscriber.try_init().expect("unable to install global subscriber");
+}
+
+#[derive(Parser)]
+#[clap(about = "Check an S3 endpoint's compatibility with Mountpoint")]
+struct CliArgs {
+    #[clap(help = "Bucket to run the checks in")]
+    bucket: String,
+
+    #[clap(
+        long,
+        help = "Prefix to create test objects under",
+        default_value = "mountpoint-compatibility/"
+    )]
+    prefix: String,
+
+    #[clap(long, help = "AWS region of the bucket", default_value = "us-east-1")]
+    region: String,
+
+    #[clap(long, help = "S3 endpoint URL to use instead of the default for the region")]
+    endpoint_url: Option<String>,
+
+    #[clap(long, help = "Force path-style addressing")]
+    force_path_style: bool,
+}
+
+type CheckResult = Result<(), String>;
+
+/// Fail the current check with the given message if the condition doesn't hold.
+macro_rules! check {
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            return Err(format!($($arg)+));
+        }
+    };
+}
+
+struct Harness {
+    client: S3CrtClient,
+    bucket: String,
+    prefix: String,
+}
+
+impl Harness {
+    fn key(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    async fn put(&self, name: &str, body: &[u8]) -> CheckResult {
+        let mut request = self
+            .client
+            .put_object(&self.bucket, &self.key(name), &PutObjectParams::new())
+            .await
+            .map_err(|e| format!("PutObject for {name:?} failed: {e:?}"))?;
+        request
+            .write(body)
+            .await
+            .map_err(|e| format!("PutObject for {name:?} failed: {e:?}"))?;
+        request
+            .complete()
+            .await
+            .map_err(|e| format!("PutObject for {name:?} failed: {e:?}"))?;
+        Ok(())
+    }
+
+    async fn get(
+        &self,
+        name: &str,
+        range: Option<Range<u64>>,
+        if_match: Option<ETag>,
+    ) -> Result<Vec<u8>, ObjectClientError<GetObjectError, <S3CrtClient as ObjectClient>::ClientError>> {
+        let request = self
+            .client
+            .get_object(
+                &self.bucket,
+                &self.key(name),
+                &GetObjectParams::new().range(range.clone()).if_match(if_match),
+            )
+            .await?;
+        let mut request = pin!(request);
+        let mut body = Vec::new();
+        let mut next_offset = range.map(|r| r.start).unwrap_or(0);
+        while let Some(part) = request.next().await {
+            let (offset, part) = part?;
+            assert_eq!(offset, next_offset, "GetObject parts should be contiguous");
+            next_offset += part.len() as u64;
+            body.extend_from_slice(&part);
+        }
+        Ok(body)
+    }
+
+    async fn head_etag(&self, name: &str) -> Result<(u64, String), String> {
+        let result = self
+            .client
+            .head_object(&self.bucket, &self.key(name))
+            .await
+            .map_err(|e| format!("HeadObject for {name:?} failed: {e:?}"))?;
+        Ok((result.object.size, result.object.etag))
+    }
+
+    /// List a directory, following continuation tokens. Returns the names of the objects and
+    /// common prefixes relative to the directory, in the order they were returned.
+    async fn list(&self, dir: &str, max_keys: usize) -> Result<(Vec<String>, Vec<String>), String> {
+        let prefix = self.key(dir);
+        let mut objects = Vec::new();
+        let mut prefixes = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let result = self
+                .client
+                .list_objects(&self.bucket, continuation_token.as_deref(), "/", max_keys, &prefix)
+                .await
+                .map_err(|e| format!("ListObjectsV2 for {dir:?} failed: {e:?}"))?;
+            objects.extend(result.objects.into_iter().map(|o| o.key[prefix.len()..].to_owned()));
+            prefixes.extend(result.common_prefixes.into_iter().map(|p| p[prefix.len()..].to_owned()));
+            continuation_token = result.next_continuation_token;
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+        Ok((objects, prefixes))
+    }
+
+    async fn read_after_write(&self) -> CheckResult {
+        let body = b"read after write";
+        self.put("rw/object", body).await?;
+        let (size, etag) = self.head_etag("rw/object").await?;
+        check!(
+            size == body.len() as u64,
+            "HeadObject returned size {size}, expected {}",
+            body.len()
+        );
+        check!(!etag.is_empty(), "HeadObject returned an empty ETag");
+        let read = self.get("rw/object", None, None).await.map_err(|e| format!("{e:?}"))?;
+        check!(read == body, "GetObject returned different contents");
+        Ok(())
+    }
+
+    async fn overwrite(&self) -> CheckResult {
+        self.put("overwrite/object", b"first").await?;
+        let (_, first_etag) = self.head_etag("overwrite/object").await?;
+        self.put("overwrite/object", b"second version").await?;
+        let (size, second_etag) = self.head_etag("overwrite/object").await?;
+        check!(size == 14, "HeadObject returned size {size} of the old version");
+        check!(first_etag != second_etag, "ETag did not change after overwrite");
+        let read = self
+            .get("overwrite/object", None, None)
+            .await
+            .map_err(|e| format!("{e:?}"))?;
+        check!(read == b"second version", "GetObject returned the old version");
+        Ok(())
+    }
+
+    async fn empty_object(&self) -> CheckResult {
+        self.put("empty/object", b"").await?;
+        let (size, _) = self.head_etag("empty/object").await?;
+        check!(size == 0, "HeadObject returned size {size}");
+        let read = self
+            .get("empty/object", None, None)
+            .await
+            .map_err(|e| format!("{e:?}"))?;
+        check!(read.is_empty(), "GetObject returned {} bytes", read.len());
+        Ok(())
+    }
+
+    async fn multipart_upload(&self) -> CheckResult {
+        let part_size = self.client.write_part_size().unwrap_or(8 * 1024 * 1024);
+        let body: Vec<u8> = (0..part_size + 1024).map(|i| i as u8).collect();
+        self.put("multipart/object", &body).await?;
+        let (size, _) = self.head_etag("multipart/object").await?;
+        check!(
+            size == body.len() as u64,
+            "HeadObject returned size {size}, expected {}",
+            body.len()
+        );
+        let read = self
+            .get("multipart/object", None, None)
+            .await
+            .map_err(|e| format!("{e:?}"))?;
+        check!(read == body, "GetObject returned different contents");
+        Ok(())
+    }
+
+    async fn ranged_get(&self) -> CheckResult {
+        self.put("range/object", b"0123456789").await?;
+        let read = self
+            .get("range/object", Some(2..5), None)
+            .await
+            .map_err(|e| format!("{e:?}"))?;
+        check!(read == b"234", "ranged GetObject returned {read:?}");
+        Ok(())
+    }
+
+    async fn conditional_get(&self) -> CheckResult {
+        self.put("conditional/object", b"contents").await?;
+        let (_, etag) = self.head_etag("conditional/object").await?;
+        let etag: ETag = etag.parse().map_err(|e| format!("{e:?}"))?;
+        self.get("conditional/object", None, Some(etag))
+            .await
+            .map_err(|e| format!("GetObject with matching If-Match failed: {e:?}"))?;
+        let wrong_etag: ETag = "\"not-the-etag\"".parse().map_err(|e| format!("{e:?}"))?;
+        match self.get("conditional/object", None, Some(wrong_etag)).await {
+            Err(ObjectClientError::ServiceError(GetObjectError::PreconditionFailed)) => Ok(()),
+            Ok(_) => Err("GetObject with mismatched If-Match succeeded".to_owned()),
+            Err(e) => Err(format!("GetObject with mismatched If-Match returned {e:?}")),
+        }
+    }
+
+    async fn missing_object(&self) -> CheckResult {
+        match self.client.head_object(&self.bucket, &self.key("missing")).await {
+            Err(ObjectClientError::ServiceError(HeadObjectError::NotFound)) => (),
+            other => return Err(format!("HeadObject returned {other:?}, expected NotFound")),
+        }
+        match self.get("missing", None, None).await {
+            Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey)) => (),
+            other => return Err(format!("GetObject returned {other:?}, expected NoSuchKey")),
+        }
+        self.client
+            .delete_object(&self.bucket, &self.key("missing"))
+            .await
+            .map_err(|e| format!("DeleteObject of a missing key failed: {e:?}"))?;
+        Ok(())
+    }
+
+    async fn delete(&self) -> CheckResult {
+        self.put("delete/object", b"contents").await?;
+        self.client
+            .delete_object(&self.bucket, &self.key("delete/object"))
+            .await
+            .map_err(|e| format!("DeleteObject failed: {e:?}"))?;
+        match self.client.head_object(&self.bucket, &self.key("delete/object")).await {
+            Err(ObjectClientError::ServiceError(HeadObjectError::NotFound)) => Ok(()),
+            other => Err(format!("HeadObject after delete returned {other:?}, expected NotFound")),
+        }
+    }
+
+    async fn list_delimiter(&self) -> CheckResult {
+        for name in ["list/a", "list/a-", "list/a/b", "list/b"] {
+            self.put(name, b"").await?;
+        }
+        let (mut objects, prefixes) = self.list("list/", 1000).await?;
+        objects.sort();
+        check!(objects == ["a", "a-", "b"], "listed objects {objects:?}");
+        check!(prefixes == ["a/"], "listed common prefixes {prefixes:?}");
+        Ok(())
+    }
+
+    async fn list_pagination(&self) -> CheckResult {
+        for name in ["paginate/a", "paginate/b", "paginate/c/d", "paginate/e"] {
+            self.put(name, b"").await?;
+        }
+        let (mut objects, prefixes) = self.list("paginate/", 1).await?;
+        objects.sort();
+        check!(objects == ["a", "b", "e"], "listed objects {objects:?}");
+        check!(prefixes == ["c/"], "listed common prefixes {prefixes:?}");
+        Ok(())
+    }
+
+    async fn list_ordering(&self) -> CheckResult {
+        for name in ["order/c", "order/a", "order/b"] {
+            self.put(name, b"").await?;
+        }
+        let (objects, _) = self.list("order/", 1000).await?;
+        check!(
+            objects == ["a", "b", "c"],
+            "listed objects in order {objects:?}; use the directory bucket personality, which does not rely on \
+             lexicographic ordering"
+        );
+        Ok(())
+    }
+
+    async fn special_characters(&self) -> CheckResult {
+        let name = "special/with space+plus%percent=equals&amp";
+        self.put(name, b"contents").await?;
+        let read = self.get(name, None, None).await.map_err(|e| format!("{e:?}"))?;
+        check!(read == b"contents", "GetObject returned different contents");
+        let (objects, _) = self.list("special/", 1000).await?;
+        check!(objects == [&name["special/".len()..]], "listed objects {objects:?}");
+        Ok(())
+    }
+
+    /// Delete every object the checks created.
+    async fn clean_up(&self) {
+        let mut continuation_token = None;
+        loop {
+            let result = match self
+                .client
+                .list_objects(&self.bucket, continuation_token.as_deref(), "", 1000, &self.prefix)
+                .await
+            {
+                Ok(result) => result,
+                Err(e) => {
+                    tracing::warn!(error = ?e, "failed to list objects to clean up");
+                    return;
+                }
+            };
+            for object in result.objects {
+                if let Err(e) = self.client.delete_object(&self.bucket, &object.key).await {
+                    tracing::warn!(key = object.key, error = ?e, "failed to clean up object");
+                }
+            }
+            continuation_token = result.next_continuation_token;
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+    }
+}
+
+fn main() {
+    init_tracing_subscriber();
+
+    let args = CliArgs::parse();
+
+    let mut endpoint_config = EndpointConfig::new(&args.region);
+    if let Some(endpoint_url) = &args.endpoint_url {
+        let uri = Uri::new_from_str(&Allocator::default(), endpoint_url).expect("invalid endpoint URL");
+        endpoint_config = endpoint_config.endpoint(uri);
+    }
+    if args.force_path_style {
+        endpoint_config = endpoint_config.addressing_style(AddressingStyle::Path);
+    }
+    let client =
+        S3CrtClient::new(S3ClientConfig::new().endpoint_config(endpoint_config)).expect("couldn't create client");
+    let harness = Harness {
+        client,
+        bucket: args.bucket,
+        prefix: args.prefix,
+    };
+
+    let results = futures::executor::block_on(async {
+        let results = vec![
+            ("read after write", harness.read_after_write().await),
+            ("overwrite", harness.overwrite().await),
+            ("empty object", harness.empty_object().await),
+            ("multipart upload", harness.multipart_upload().await),
+            ("ranged get", harness.ranged_get().await),
+            ("conditional get", harness.conditional_get().await),
+            ("missing object", harness.missing_object().await),
+            ("delete", harness.delete().await),
+            ("list with delimiter", harness.list_delimiter().await),
+            ("list pagination", harness.list_pagination().await),
+            ("list ordering", harness.list_ordering().await),
+            ("special characters in keys", harness.special_characters().await),
+        ];
+        harness.clean_up().await;
+        results
+    });
+
+    println!("Compatibility report for bucket {:?}", harness.bucket);
+    let mut failures = 0;
+    for (name, result) in &results {
+        match result {
+            Ok(()) => println!("  PASS  {name}"),
+            Err(message) => {
+                failures += 1;
+                println!("  FAIL  {name}: {message}");
+            }
+        }
+    }
+    println!("{} of {} checks passed", results.len() - failures, results.len());
+
+    if failures > 0 {
+        std::process::exit(1);
+    }
+}
@@ -4,7 +4,6 @@
 
 use std::collections::HashMap;
 use std::fmt::Debug;
-use std::ops::Range;
 use std::pin::Pin;
 use std::sync::Mutex;
 use std::task::{Context, Poll};
@@ -14,10 +13,12 @@ use futures::Stream;
 use pin_project::pin_project;
 
 use crate::object_client::{
-    DeleteObjectError, DeleteObjectResult, ETag, GetBodyPart, GetObjectAttributesError, GetObjectAttributesResult,
-    GetObjectError, HeadObjectError, HeadObjectResult, ListObjectsError, ListObjectsResult, ObjectAttribute,
-    ObjectClientError, ObjectClientResult, PutObjectError, PutObjectParams, PutObjectRequest, PutObjectResult,
-    UploadReview,
+    AbortMultipartUploadResult, Checksum, CompletedPart, CopyObjectError, CopyObjectParams, CopyObjectResult,
+    CreateMultipartUploadResult, DeleteObjectError, DeleteObjectResult, GetBodyPart, GetObjectAttributesError,
+    GetObjectAttributesResult, GetObjectError, GetObjectParams, GetObjectRequest, HeadObjectError, HeadObjectResult,
+    ListMultipartUploadsResult, ListObjectVersionsResult, ListObjectsError, ListObjectsResult, MultipartUploadError,
+    ObjectAttribute, ObjectClientError, ObjectClientResult, PutObjectError, PutObjectParams, PutObjectRequest,
+    PutObjectResult, UploadPartCopyParams, UploadPartResult, UploadReview,
 };
 use crate::ObjectClient;
 
@@ -35,8 +36,7 @@ pub struct FailureClient<Client: ObjectClient, State, RequestWrapperState> {
         &mut State,
         &str,
         &str,
-        Option<Range<u64>>,
-        Option<ETag>,
+        &GetObjectParams,
     ) -> Result<
         FailureRequestWrapper<Client, RequestWrapperState>,
         ObjectClientError<GetObjectError, Client::ClientError>,
@@ -73,8 +73,12 @@ where
     type PutObjectRequest = FailurePutObjectRequest<Client, GetWrapperState>;
     type ClientError = Client::ClientError;
 
-    fn part_size(&self) -> Option<usize> {
-        self.client.part_size()
+    fn read_part_size(&self) -> Option<usize> {
+        self.client.read_part_size()
+    }
+
+    fn write_part_size(&self) -> Option<usize> {
+        self.client.write_part_size()
     }
 
     async fn delete_object(
@@ -90,17 +94,10 @@ where
         &self,
         bucket: &str,
         key: &str,
-        range: Option<Range<u64>>,
-        if_match: Option<ETag>,
+        params: &GetObjectParams,
     ) -> ObjectClientResult<Self::GetObjectResult, GetObjectError, Self::ClientError> {
-        let wrapper = (self.get_object_cb)(
-            &mut *self.state.lock().unwrap(),
-            bucket,
-            key,
-            range.clone(),
-            if_match.clone(),
-        )?;
-        let get_result = self.client.get_object(bucket, key, range, if_match).await?;
+        let wrapper = (self.get_object_cb)(&mut *self.state.lock().unwrap(), bucket, key, params)?;
+        let get_result = self.client.get_object(bucket, key, params).await?;
         Ok(FailureGetResult {
             state: wrapper.state,
             result_fn: wrapper.result_fn,
@@ -130,6 +127,21 @@ where
             .await
     }
 
+    async fn list_object_versions(
+        &self,
+        bucket: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectsError, Self::ClientError> {
+        // TODO failure hook for list_object_versions
+        self.client
+            .list_object_versions(bucket, key_marker, version_id_marker, delimiter, max_keys, prefix)
+            .await
+    }
+
     async fn head_object(
         &self,
         bucket: &str,
@@ -167,6 +179,95 @@ where
             .get_object_attributes(bucket, key, max_parts, part_number_marker, object_attributes)
             .await
     }
+
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+        params: &CopyObjectParams,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, Self::ClientError> {
+        // TODO failure hook for copy_object
+        self.client
+            .copy_object(source_bucket, source_key, destination_bucket, destination_key, params)
+            .await
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+    ) -> ObjectClientResult<CreateMultipartUploadResult, MultipartUploadError, Self::ClientError> {
+        // TODO failure hook for create_multipart_upload
+        self.client.create_multipart_upload(bucket, key, params).await
+    }
+
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        data: &[u8],
+    ) -> ObjectClientResult<UploadPartResult, MultipartUploadError, Self::ClientError> {
+        // TODO failure hook for upload_part
+        self.client.upload_part(bucket, key, upload_id, part_number, data).await
+    }
+
+    async fn upload_part_copy(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        source_bucket: &str,
+        source_key: &str,
+        params: &UploadPartCopyParams,
+    ) -> ObjectClientResult<UploadPartResult, MultipartUploadError, Self::ClientError> {
+        // TODO failure hook for upload_part_copy
+        self.client
+            .upload_part_copy(bucket, key, upload_id, part_number, source_bucket, source_key, params)
+            .await
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        parts: &[CompletedPart],
+    ) -> ObjectClientResult<PutObjectResult, MultipartUploadError, Self::ClientError> {
+        // TODO failure hook for complete_multipart_upload
+        self.client
+            .complete_multipart_upload(bucket, key, upload_id, parts)
+            .await
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, MultipartUploadError, Self::ClientError> {
+        // TODO failure hook for abort_multipart_upload
+        self.client.abort_multipart_upload(bucket, key, upload_id).await
+    }
+
+    async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        key_marker: Option<&str>,
+        upload_id_marker: Option<&str>,
+        max_uploads: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, MultipartUploadError, Self::ClientError> {
+        // TODO failure hook for list_multipart_uploads
+        self.client
+            .list_multipart_uploads(bucket, key_marker, upload_id_marker, max_uploads, prefix)
+            .await
+    }
 }
 
 #[pin_project]
@@ -187,6 +288,14 @@ impl<Client: ObjectClient, FailState> Stream for FailureGetResult<Client, FailSt
     }
 }
 
+impl<Client: ObjectClient, FailState: Send> GetObjectRequest for FailureGetResult<Client, FailState> {
+    type ClientError = Client::ClientError;
+
+    fn get_object_checksum(&self) -> Option<Checksum> {
+        self.get_result.get_object_checksum()
+    }
+}
+
 pub struct FailurePutObjectRequest<Client: ObjectClient, PutWrapperState> {
     request: Client::PutObjectRequest,
     state: PutWrapperState,
@@ -285,7 +394,7 @@ pub fn countdown_failure_client<Client: ObjectClient>(
     FailureClient {
         client,
         state,
-        get_object_cb: |state, _bucket, _key, _range, _if_match| {
+        get_object_cb: |state, _bucket, _key, _params| {
             state.get_count += 1;
             let (fail_count, error) = if let Some(result) = state.get_results.remove(&state.get_count) {
                 let (fail_count, error) = result?;
@@ -356,6 +465,7 @@ pub fn countdown_failure_client<Client: ObjectClient>(
 mod tests {
     use super::*;
     use crate::mock_client::{MockClient, MockClientConfig, MockClientError, MockObject};
+    use crate::object_client::ETag;
     use std::collections::HashSet;
 
     #[tokio::test]
@@ -393,7 +503,7 @@ mod tests {
 
         let fail_set = HashSet::from([2, 4, 5]);
         for i in 1..=6 {
-            let r = fail_client.get_object(bucket, key, None, None).await;
+            let r = fail_client.get_object(bucket, key, &GetObjectParams::new()).await;
             if fail_set.contains(&i) {
                 assert!(r.is_err());
             } else {
@@ -18,10 +18,11 @@
 //! # async fn test() {
 //! use futures::TryStreamExt;
 //! use mountpoint_s3_client::{S3CrtClient, ObjectClient};
+//! use mountpoint_s3_client::types::GetObjectParams;
 //!
 //! let client = S3CrtClient::new(Default::default()).expect("client construction failed");
 //!
-//! let response = client.get_object("my-bucket", "my-key", None, None).await.expect("get_object failed");
+//! let response = client.get_object("my-bucket", "my-key", &GetObjectParams::new()).await.expect("get_object failed");
 //! let body = response.map_ok(|(offset, body)| body.to_vec()).try_concat().await.expect("body streaming failed");
 //! # }
 //! ```
@@ -58,9 +59,11 @@ mod s3_crt_client;
 pub mod user_agent;
 mod util;
 
-pub use object_client::{ObjectClient, PutObjectRequest};
+pub use object_client::{GetObjectRequest, ObjectClient, PutObjectRequest};
 
-pub use s3_crt_client::{get_object::S3GetObjectRequest, put_object::S3PutObjectRequest, S3CrtClient, S3RequestError};
+pub use s3_crt_client::{
+    get_object::S3GetObjectRequest, put_object::S3PutObjectRequest, S3CrtClient, S3RequestError, S3RequestIds,
+};
 
 /// Configuration for the S3 client
 pub mod config {
@@ -71,10 +74,12 @@ pub mod config {
 /// Types used by all object clients
 pub mod types {
     pub use super::object_client::{
-        Checksum, ChecksumAlgorithm, DeleteObjectResult, ETag, GetBodyPart, GetObjectAttributesParts,
-        GetObjectAttributesResult, HeadObjectResult, ListObjectsResult, ObjectAttribute, ObjectClientResult,
-        ObjectInfo, ObjectPart, PutObjectParams, PutObjectResult, PutObjectTrailingChecksums, RestoreStatus,
-        UploadReview, UploadReviewPart,
+        AbortMultipartUploadResult, Checksum, ChecksumAlgorithm, ChecksumMode, CompletedPart, CopyObjectParams,
+        CopyObjectResult, CreateMultipartUploadResult, DeleteObjectResult, ETag, GetBodyPart, GetObjectAttributesParts,
+        GetObjectAttributesResult, GetObjectParams, HeadObjectResult, ListMultipartUploadsResult,
+        ListObjectVersionsResult, ListObjectsResult, MultipartUploadInfo, ObjectAttribute, ObjectClientResult,
+        ObjectInfo, ObjectPart, ObjectVersion, PutObjectParams, PutObjectResult, PutObjectTrailingChecksums,
+        RestoreStatus, UploadPartCopyParams, UploadPartResult, UploadReview, UploadReviewPart,
     };
 }
 
@@ -85,8 +90,8 @@ pub mod types {
 /// client errors. See its documentation for more details.
 pub mod error {
     pub use super::object_client::{
-        DeleteObjectError, GetObjectAttributesError, GetObjectError, HeadObjectError, ListObjectsError,
-        ObjectClientError, PutObjectError,
+        CopyObjectError, DeleteObjectError, GetObjectAttributesError, GetObjectError, HeadObjectError,
+        ListObjectsError, MultipartUploadError, ObjectClientError, PutObjectError,
     };
     #[doc(hidden)]
     pub use super::s3_crt_client::HeadBucketError;
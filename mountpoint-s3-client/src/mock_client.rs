@@ -6,6 +6,7 @@ use std::borrow::Cow;
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::ops::Range;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 use std::task::{Context, Poll};
 use std::time::{Duration, SystemTime};
@@ -21,13 +22,16 @@ use thiserror::Error;
 use time::OffsetDateTime;
 use tracing::trace;
 
-use crate::checksums::crc32c_to_base64;
+use crate::checksums::{crc32c_from_base64, crc32c_to_base64};
 use crate::object_client::{
-    Checksum, ChecksumAlgorithm, DeleteObjectError, DeleteObjectResult, ETag, GetBodyPart, GetObjectAttributesError,
-    GetObjectAttributesParts, GetObjectAttributesResult, GetObjectError, HeadObjectError, HeadObjectResult,
-    ListObjectsError, ListObjectsResult, ObjectAttribute, ObjectClient, ObjectClientError, ObjectClientResult,
-    ObjectInfo, ObjectPart, PutObjectError, PutObjectParams, PutObjectRequest, PutObjectResult,
-    PutObjectTrailingChecksums, RestoreStatus, UploadReview, UploadReviewPart,
+    AbortMultipartUploadResult, Checksum, ChecksumAlgorithm, ChecksumMode, CompletedPart, CopyObjectError,
+    CopyObjectParams, CopyObjectResult, CreateMultipartUploadResult, DeleteObjectError, DeleteObjectResult, ETag,
+    GetBodyPart, GetObjectAttributesError, GetObjectAttributesParts, GetObjectAttributesResult, GetObjectError,
+    GetObjectParams, GetObjectRequest, HeadObjectError, HeadObjectResult, ListMultipartUploadsResult,
+    ListObjectVersionsResult, ListObjectsError, ListObjectsResult, MultipartUploadError, MultipartUploadInfo,
+    ObjectAttribute, ObjectClient, ObjectClientError, ObjectClientResult, ObjectInfo, ObjectPart, ObjectVersion,
+    PutObjectError, PutObjectParams, PutObjectRequest, PutObjectResult, PutObjectTrailingChecksums, RestoreStatus,
+    UploadPartCopyParams, UploadPartResult, UploadReview, UploadReviewPart,
 };
 
 mod leaky_bucket;
@@ -58,7 +62,8 @@ lazy_static! {
 pub struct MockClientConfig {
     /// The bucket name this client will connect to
     pub bucket: String,
-    /// The size of the parts that GetObject will respond with
+    /// The size of the parts that GetObject will respond with, and that PutObject requests report
+    /// uploading
     pub part_size: usize,
     /// A seed to randomize the order of ListObjectsV2 results, or None to use ordered list
     pub unordered_list_seed: Option<u64>,
@@ -70,12 +75,50 @@ pub struct MockClientConfig {
 pub struct MockClient {
     config: MockClientConfig,
     objects: Arc<RwLock<BTreeMap<String, MockObject>>>,
-    in_progress_uploads: Arc<RwLock<BTreeSet<String>>>,
+    /// Every version of each key, including delete markers, from oldest to newest
+    object_versions: Arc<RwLock<BTreeMap<String, Vec<MockObjectVersion>>>>,
+    /// Number of PutObject requests in progress for each key
+    in_progress_uploads: Arc<RwLock<BTreeMap<String, usize>>>,
+    multipart_uploads: Arc<RwLock<HashMap<String, MockMultipartUpload>>>,
+    next_upload_id: AtomicU64,
     operation_counts: Arc<RwLock<HashMap<Operation, u64>>>,
 }
 
-fn add_object(objects: &Arc<RwLock<BTreeMap<String, MockObject>>>, key: &str, value: MockObject) {
-    objects.write().unwrap().insert(key.to_owned(), value);
+/// A version of an object in the mock bucket, which is a delete marker if `object` is `None`
+#[derive(Debug, Clone)]
+struct MockObjectVersion {
+    version_id: String,
+    object: Option<MockObject>,
+    last_modified: OffsetDateTime,
+}
+
+fn add_object(
+    objects: &Arc<RwLock<BTreeMap<String, MockObject>>>,
+    object_versions: &Arc<RwLock<BTreeMap<String, Vec<MockObjectVersion>>>>,
+    key: &str,
+    value: MockObject,
+) {
+    let mut objects = objects.write().unwrap();
+    add_version(object_versions, key, Some(value.clone()));
+    objects.insert(key.to_owned(), value);
+}
+
+fn add_version(
+    object_versions: &Arc<RwLock<BTreeMap<String, Vec<MockObjectVersion>>>>,
+    key: &str,
+    object: Option<MockObject>,
+) {
+    let mut object_versions = object_versions.write().unwrap();
+    let history = object_versions.entry(key.to_owned()).or_default();
+    let last_modified = object
+        .as_ref()
+        .map(|object| object.last_modified)
+        .unwrap_or_else(OffsetDateTime::now_utc);
+    history.push(MockObjectVersion {
+        version_id: format!("v{}", history.len() + 1),
+        object,
+        last_modified,
+    });
 }
 
 impl MockClient {
@@ -84,19 +127,35 @@ impl MockClient {
         Self {
             config,
             objects: Default::default(),
+            object_versions: Default::default(),
             in_progress_uploads: Default::default(),
+            multipart_uploads: Default::default(),
+            next_upload_id: AtomicU64::new(1),
             operation_counts: Default::default(),
         }
     }
 
     /// Add an object to this mock client's bucket
     pub fn add_object(&self, key: &str, value: MockObject) {
-        add_object(&self.objects, key, value);
+        add_object(&self.objects, &self.object_versions, key, value);
     }
 
-    /// Remove object for the mock client's bucket
+    /// Remove object for the mock client's bucket. Like a versioned bucket, this leaves a delete
+    /// marker in the object's version history.
     pub fn remove_object(&self, key: &str) {
-        self.objects.write().unwrap().remove(key);
+        let mut objects = self.objects.write().unwrap();
+        if objects.remove(key).is_some() {
+            add_version(&self.object_versions, key, None);
+        }
+    }
+
+    /// Returns the version IDs of the specified key, including delete markers, from oldest to newest
+    pub fn object_version_ids(&self, key: &str) -> Vec<String> {
+        let object_versions = self.object_versions.read().unwrap();
+        object_versions
+            .get(key)
+            .map(|history| history.iter().map(|version| version.version_id.clone()).collect())
+            .unwrap_or_default()
     }
 
     /// Returns `true` if this mock client's bucket contains the specified key
@@ -112,7 +171,13 @@ impl MockClient {
 
     /// Returns `true` if there is an upload in progress for the specified key
     pub fn is_upload_in_progress(&self, key: &str) -> bool {
-        self.in_progress_uploads.read().unwrap().contains(key)
+        self.in_progress_uploads.read().unwrap().contains_key(key)
+            || self
+                .multipart_uploads
+                .read()
+                .unwrap()
+                .values()
+                .any(|upload| upload.key == key)
     }
 
     /// Returns the objects storage class
@@ -166,6 +231,27 @@ impl MockClient {
         op_counts.entry(operation).and_modify(|count| *count += 1).or_insert(1);
     }
 
+    /// Store a part of an in-progress multipart upload, replacing any earlier part with the same number.
+    fn add_multipart_upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        data: Box<[u8]>,
+    ) -> ObjectClientResult<UploadPartResult, MultipartUploadError, MockClientError> {
+        let mut uploads = self.multipart_uploads.write().unwrap();
+        let Some(upload) = uploads.get_mut(upload_id).filter(|upload| upload.key == key) else {
+            return Err(ObjectClientError::ServiceError(MultipartUploadError::NoSuchUpload));
+        };
+        if !(1..=10000).contains(&part_number) {
+            return mock_client_error(format!("invalid part number {part_number}"));
+        }
+
+        let etag = ETag::from_object_bytes(&data);
+        upload.parts.insert(part_number, (etag.clone(), data));
+        Ok(UploadPartResult { etag })
+    }
+
     /// Ordered list implementation
     fn list_objects_ordered(
         &self,
@@ -324,17 +410,125 @@ impl MockClient {
             next_continuation_token,
         }
     }
+
+    /// List object versions in the same order as S3: by key, and then from newest to oldest
+    fn list_object_versions_ordered(
+        &self,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ListObjectVersionsResult {
+        let delimiter = (!delimiter.is_empty()).then_some(delimiter);
+        // The common prefix a key rolls up into, if any
+        let common_prefix = |key: &str| {
+            let no_prefix_key = &key[prefix.len()..];
+            delimiter.and_then(|d| no_prefix_key.split_once(d).map(|(pre, _)| format!("{prefix}{pre}{d}")))
+        };
+
+        let object_versions = self.object_versions.read().unwrap();
+
+        let mut versions = Vec::new();
+        let mut common_prefixes: Vec<String> = Vec::new();
+        // The key and version ID of the last entry we returned, to use as the next markers
+        let mut last_entry: Option<(String, Option<String>)> = None;
+        let mut truncated = false;
+
+        let start = key_marker.filter(|marker| *marker > prefix).unwrap_or(prefix);
+        'keys: for (key, history) in object_versions.range(start.to_owned()..) {
+            // Keys with the same prefix are contiguous, so we're done once we see one without it
+            if !key.starts_with(prefix) {
+                break;
+            }
+
+            if let Some(common_prefix) = common_prefix(key) {
+                // Skip the rest of a common prefix we've already returned, in this page or an earlier one
+                if Some(common_prefix.as_str()) == key_marker || common_prefixes.last() == Some(&common_prefix) {
+                    continue;
+                }
+                if versions.len() + common_prefixes.len() >= max_keys {
+                    truncated = true;
+                    break;
+                }
+                last_entry = Some((common_prefix.clone(), None));
+                common_prefixes.push(common_prefix);
+                continue;
+            }
+
+            let mut history = history.iter().rev();
+            if Some(key.as_str()) == key_marker {
+                match version_id_marker {
+                    // Skip the versions up to and including the marker
+                    Some(version_id_marker) => {
+                        for version in history.by_ref() {
+                            if version.version_id == version_id_marker {
+                                break;
+                            }
+                        }
+                    }
+                    None => continue,
+                }
+            }
+
+            for version in history {
+                if versions.len() + common_prefixes.len() >= max_keys {
+                    truncated = true;
+                    break 'keys;
+                }
+                last_entry = Some((key.clone(), Some(version.version_id.clone())));
+                versions.push(match &version.object {
+                    Some(object) => ObjectVersion::Object {
+                        info: ObjectInfo {
+                            key: key.to_string(),
+                            size: object.len() as u64,
+                            last_modified: object.last_modified,
+                            etag: object.etag.as_str().to_string(),
+                            storage_class: object.storage_class.clone(),
+                            restore_status: object.restore_status,
+                        },
+                        version_id: version.version_id.clone(),
+                    },
+                    None => ObjectVersion::DeleteMarker {
+                        key: key.to_string(),
+                        version_id: version.version_id.clone(),
+                        last_modified: version.last_modified,
+                    },
+                });
+            }
+        }
+
+        let (next_key_marker, next_version_id_marker) = match last_entry {
+            Some((key, version_id)) if truncated => (Some(key), version_id),
+            _ => (None, None),
+        };
+
+        ListObjectVersionsResult {
+            versions,
+            common_prefixes,
+            next_key_marker,
+            next_version_id_marker,
+        }
+    }
 }
 
 /// Operations for use in operation counters.
 #[derive(Debug, Eq, Hash, PartialEq)]
 pub enum Operation {
+    AbortMultipartUpload,
+    CompleteMultipartUpload,
+    CopyObject,
+    CreateMultipartUpload,
     DeleteObject,
     HeadObject,
     GetObject,
     GetObjectAttributes,
+    ListMultipartUploads,
     ListObjectsV2,
+    ListObjectVersions,
     PutObject,
+    UploadPart,
+    UploadPartCopy,
 }
 
 /// Counter for a specific client [Operation].
@@ -366,6 +560,9 @@ pub struct MockObject {
     last_modified: OffsetDateTime,
     etag: ETag,
     parts: Option<MockObjectParts>,
+    object_metadata: HashMap<String, String>,
+    /// The CRC32C checksum GetObject returns for this object, if not the checksum of its data
+    checksum_crc32c: Option<crc32c::Crc32c>,
 }
 
 impl MockObject {
@@ -384,6 +581,8 @@ impl MockObject {
             last_modified: OffsetDateTime::now_utc(),
             etag,
             parts: None,
+            object_metadata: HashMap::new(),
+            checksum_crc32c: None,
         }
     }
 
@@ -396,6 +595,8 @@ impl MockObject {
             last_modified: OffsetDateTime::now_utc(),
             etag,
             parts: None,
+            object_metadata: HashMap::new(),
+            checksum_crc32c: None,
         }
     }
 
@@ -418,6 +619,8 @@ impl MockObject {
             last_modified: OffsetDateTime::now_utc(),
             etag,
             parts: None,
+            object_metadata: HashMap::new(),
+            checksum_crc32c: None,
         }
     }
 
@@ -433,6 +636,54 @@ impl MockObject {
         self.restore_status = restore_status;
     }
 
+    pub fn set_object_metadata(&mut self, object_metadata: HashMap<String, String>) {
+        self.object_metadata = object_metadata;
+    }
+
+    /// Set the CRC32C checksum that GetObject returns for this object. A checksum that doesn't
+    /// match the object's data simulates an object that was corrupted in transit.
+    pub fn set_checksum_crc32c(&mut self, checksum: crc32c::Crc32c) {
+        self.checksum_crc32c = Some(checksum);
+    }
+
+    /// Set the sizes and CRC32C checksums of the parts this object was uploaded in, which
+    /// GetObjectAttributes returns. GetObject then returns a composite checksum for the object, like
+    /// S3 does for multipart uploads. Checksums that don't match the object's data simulate parts
+    /// that were corrupted in transit.
+    pub fn set_part_checksums_crc32c(&mut self, parts: &[(usize, crc32c::Crc32c)]) {
+        let parts = parts
+            .iter()
+            .map(|(size, checksum)| MockObjectPartAttributes {
+                size: *size,
+                checksum: Some(crc32c_to_base64(checksum)),
+            })
+            .collect();
+        self.parts = Some(MockObjectParts::Parts(parts));
+    }
+
+    /// The checksum GetObject returns for this object
+    fn checksum(&self) -> Checksum {
+        let checksum_crc32c = match (&self.parts, self.checksum_crc32c) {
+            (_, Some(crc32c)) => crc32c_to_base64(&crc32c),
+            // Objects uploaded in parts with checksums have a checksum of their parts' checksums
+            (Some(MockObjectParts::Parts(parts)), None) if parts.iter().all(|part| part.checksum.is_some()) => {
+                let mut hasher = crc32c::Hasher::new();
+                for part in parts {
+                    let checksum = crc32c_from_base64(part.checksum.as_deref().unwrap()).unwrap();
+                    hasher.update(&checksum.value().to_be_bytes());
+                }
+                format!("{}-{}", crc32c_to_base64(&hasher.finalize()), parts.len())
+            }
+            _ => crc32c_to_base64(&crc32c::checksum(&self.read(0, self.size))),
+        };
+        Checksum {
+            checksum_crc32: None,
+            checksum_crc32c: Some(checksum_crc32c),
+            checksum_sha1: None,
+            checksum_sha256: None,
+        }
+    }
+
     pub fn len(&self) -> usize {
         self.size
     }
@@ -460,6 +711,7 @@ impl std::fmt::Debug for MockObject {
             .field("last_modified", &self.last_modified)
             .field("etag", &self.etag)
             .field("restored", &self.restore_status)
+            .field("object_metadata", &self.object_metadata)
             .finish()
     }
 }
@@ -470,6 +722,7 @@ pub struct GetObjectResult {
     next_offset: u64,
     length: usize,
     part_size: usize,
+    checksum: Option<Checksum>,
 }
 
 impl GetObjectResult {
@@ -505,6 +758,14 @@ impl Stream for GetObjectResult {
     }
 }
 
+impl GetObjectRequest for GetObjectResult {
+    type ClientError = MockClientError;
+
+    fn get_object_checksum(&self) -> Option<Checksum> {
+        self.checksum.clone()
+    }
+}
+
 #[derive(Debug, Error, PartialEq, Eq)]
 pub struct MockClientError(pub Cow<'static, str>);
 
@@ -524,7 +785,11 @@ impl ObjectClient for MockClient {
     type PutObjectRequest = MockPutObjectRequest;
     type ClientError = MockClientError;
 
-    fn part_size(&self) -> Option<usize> {
+    fn read_part_size(&self) -> Option<usize> {
+        Some(self.config.part_size)
+    }
+
+    fn write_part_size(&self) -> Option<usize> {
         Some(self.config.part_size)
     }
 
@@ -549,43 +814,52 @@ impl ObjectClient for MockClient {
         &self,
         bucket: &str,
         key: &str,
-        range: Option<Range<u64>>,
-        if_match: Option<ETag>,
+        params: &GetObjectParams,
     ) -> ObjectClientResult<Self::GetObjectResult, GetObjectError, Self::ClientError> {
-        trace!(bucket, key, ?range, ?if_match, "GetObject");
+        trace!(bucket, key, ?params, "GetObject");
         self.inc_op_count(Operation::GetObject);
 
         if bucket != self.config.bucket {
             return Err(ObjectClientError::ServiceError(GetObjectError::NoSuchBucket));
         }
 
-        let objects = self.objects.read().unwrap();
+        let object = if let Some(version_id) = params.version_id.as_ref() {
+            let object_versions = self.object_versions.read().unwrap();
+            object_versions
+                .get(key)
+                .and_then(|history| history.iter().find(|version| &version.version_id == version_id))
+                .and_then(|version| version.object.clone())
+        } else {
+            self.objects.read().unwrap().get(key).cloned()
+        };
+        let Some(object) = object else {
+            return Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey));
+        };
 
-        if let Some(object) = objects.get(key) {
-            if let Some(etag_match) = if_match {
-                if etag_match != object.etag {
-                    return Err(ObjectClientError::ServiceError(GetObjectError::PreconditionFailed));
-                }
+        if let Some(etag_match) = params.if_match.as_ref() {
+            if *etag_match != object.etag {
+                return Err(ObjectClientError::ServiceError(GetObjectError::PreconditionFailed));
             }
+        }
 
-            let (next_offset, length) = if let Some(range) = range {
-                if range.start >= object.len() as u64 || range.end > object.len() as u64 {
-                    return mock_client_error(format!("invalid range, length={}", object.len()));
-                }
-                (range.start, (range.end - range.start) as usize)
-            } else {
-                (0, object.len())
-            };
-
-            Ok(GetObjectResult {
-                object: object.clone(),
-                next_offset,
-                length,
-                part_size: self.config.part_size,
-            })
+        let (next_offset, length) = if let Some(range) = params.range.clone() {
+            if range.start >= object.len() as u64 || range.end > object.len() as u64 {
+                return mock_client_error(format!("invalid range, length={}", object.len()));
+            }
+            (range.start, (range.end - range.start) as usize)
         } else {
-            Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey))
-        }
+            (0, object.len())
+        };
+
+        let checksum = (params.checksum_mode == Some(ChecksumMode::Enabled)).then(|| object.checksum());
+
+        Ok(GetObjectResult {
+            object,
+            next_offset,
+            length,
+            part_size: self.config.part_size,
+            checksum,
+        })
     }
 
     async fn head_object(
@@ -612,6 +886,7 @@ impl ObjectClient for MockClient {
                     storage_class: object.storage_class.clone(),
                     restore_status: object.restore_status,
                 },
+                object_metadata: object.object_metadata.clone(),
             })
         } else {
             Err(ObjectClientError::ServiceError(HeadObjectError::NotFound))
@@ -640,6 +915,33 @@ impl ObjectClient for MockClient {
         }
     }
 
+    async fn list_object_versions(
+        &self,
+        bucket: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectsError, Self::ClientError> {
+        trace!(
+            bucket,
+            ?key_marker,
+            ?version_id_marker,
+            delimiter,
+            max_keys,
+            prefix,
+            "ListObjectVersions"
+        );
+        self.inc_op_count(Operation::ListObjectVersions);
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(ListObjectsError::NoSuchBucket));
+        }
+
+        Ok(self.list_object_versions_ordered(key_marker, version_id_marker, delimiter, max_keys, prefix))
+    }
+
     async fn put_object(
         &self,
         bucket: &str,
@@ -658,6 +960,7 @@ impl ObjectClient for MockClient {
             self.config.part_size,
             params,
             &self.objects,
+            &self.object_versions,
             &self.in_progress_uploads,
         );
         Ok(put_request)
@@ -683,7 +986,8 @@ impl ObjectClient for MockClient {
             let mut result = GetObjectAttributesResult::default();
             for attribute in object_attributes.iter() {
                 match attribute {
-                    ObjectAttribute::ETag => result.etag = Some("TODO".to_owned()),
+                    // GetObjectAttributes returns ETags without quotes
+                    ObjectAttribute::ETag => result.etag = Some(object.etag.as_str().trim_matches('"').to_owned()),
                     ObjectAttribute::Checksum => {
                         result.checksum = Some(Checksum {
                             checksum_crc32: Some("TODO".to_owned()),
@@ -739,6 +1043,276 @@ impl ObjectClient for MockClient {
             Err(ObjectClientError::ServiceError(GetObjectAttributesError::NoSuchKey))
         }
     }
+
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+        params: &CopyObjectParams,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, Self::ClientError> {
+        trace!(
+            source_bucket,
+            source_key,
+            destination_bucket,
+            destination_key,
+            ?params,
+            "CopyObject"
+        );
+        self.inc_op_count(Operation::CopyObject);
+
+        if source_bucket != self.config.bucket || destination_bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(CopyObjectError::NotFound));
+        }
+
+        let Some(mut object) = self.objects.read().unwrap().get(source_key).cloned() else {
+            return Err(ObjectClientError::ServiceError(CopyObjectError::NotFound));
+        };
+        object.set_last_modified(OffsetDateTime::now_utc());
+        object.set_storage_class(params.storage_class.clone());
+        object.set_restored(None);
+        add_object(&self.objects, &self.object_versions, destination_key, object);
+
+        Ok(CopyObjectResult {})
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+    ) -> ObjectClientResult<CreateMultipartUploadResult, MultipartUploadError, Self::ClientError> {
+        trace!(bucket, key, "CreateMultipartUpload");
+        self.inc_op_count(Operation::CreateMultipartUpload);
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(MultipartUploadError::NoSuchBucket));
+        }
+
+        let sequence = self.next_upload_id.fetch_add(1, Ordering::SeqCst);
+        let upload_id = format!("upload-{sequence}");
+        let upload = MockMultipartUpload {
+            key: key.to_owned(),
+            params: params.clone(),
+            parts: Default::default(),
+            sequence,
+            initiated: OffsetDateTime::now_utc(),
+        };
+        self.multipart_uploads
+            .write()
+            .unwrap()
+            .insert(upload_id.clone(), upload);
+
+        Ok(CreateMultipartUploadResult { upload_id })
+    }
+
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        data: &[u8],
+    ) -> ObjectClientResult<UploadPartResult, MultipartUploadError, Self::ClientError> {
+        trace!(bucket, key, upload_id, part_number, size = data.len(), "UploadPart");
+        self.inc_op_count(Operation::UploadPart);
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(MultipartUploadError::NoSuchBucket));
+        }
+
+        self.add_multipart_upload_part(key, upload_id, part_number, data.into())
+    }
+
+    async fn upload_part_copy(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        source_bucket: &str,
+        source_key: &str,
+        params: &UploadPartCopyParams,
+    ) -> ObjectClientResult<UploadPartResult, MultipartUploadError, Self::ClientError> {
+        trace!(
+            bucket,
+            key,
+            upload_id,
+            part_number,
+            source_bucket,
+            source_key,
+            range = ?params.range,
+            if_match = ?params.if_match,
+            "UploadPartCopy"
+        );
+        self.inc_op_count(Operation::UploadPartCopy);
+
+        if bucket != self.config.bucket || source_bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(MultipartUploadError::NoSuchBucket));
+        }
+
+        let data = {
+            let objects = self.objects.read().unwrap();
+            let Some(source) = objects.get(source_key) else {
+                return Err(ObjectClientError::ServiceError(MultipartUploadError::NoSuchKey));
+            };
+            if params.if_match.as_ref().is_some_and(|etag| *etag != source.etag) {
+                return Err(ObjectClientError::ServiceError(
+                    MultipartUploadError::PreconditionFailed,
+                ));
+            }
+            match params.range.clone() {
+                Some(range) => {
+                    if range.start >= range.end || range.end > source.len() as u64 {
+                        return mock_client_error(format!("invalid range, length={}", source.len()));
+                    }
+                    source.read(range.start, (range.end - range.start) as usize)
+                }
+                None => source.read(0, source.len()),
+            }
+        };
+        self.add_multipart_upload_part(key, upload_id, part_number, data)
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        parts: &[CompletedPart],
+    ) -> ObjectClientResult<PutObjectResult, MultipartUploadError, Self::ClientError> {
+        trace!(
+            bucket,
+            key,
+            upload_id,
+            num_parts = parts.len(),
+            "CompleteMultipartUpload"
+        );
+        self.inc_op_count(Operation::CompleteMultipartUpload);
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(MultipartUploadError::NoSuchBucket));
+        }
+
+        let mut uploads = self.multipart_uploads.write().unwrap();
+        let Some(upload) = uploads.get(upload_id).filter(|upload| upload.key == key) else {
+            return Err(ObjectClientError::ServiceError(MultipartUploadError::NoSuchUpload));
+        };
+
+        if parts.is_empty() {
+            return mock_client_error("must specify at least one part");
+        }
+        if parts.windows(2).any(|w| w[0].part_number >= w[1].part_number) {
+            return mock_client_error("parts must be in ascending order");
+        }
+
+        let mut buffer = Vec::new();
+        for part in parts {
+            match upload.parts.get(&part.part_number) {
+                Some((etag, data)) if *etag == part.etag => buffer.extend_from_slice(data),
+                _ => return mock_client_error(format!("invalid part {}", part.part_number)),
+            }
+        }
+
+        let upload = uploads.remove(upload_id).unwrap();
+        let mut object: MockObject = buffer.into();
+        object.set_storage_class(upload.params.storage_class.clone());
+        object.set_object_metadata(upload.params.object_metadata.clone());
+        object.parts = Some(MockObjectParts::Count(parts.len()));
+        let etag = object.etag.clone();
+        add_object(&self.objects, &self.object_versions, key, object);
+
+        // Like S3, report the encryption settings the object was created with
+        Ok(PutObjectResult {
+            etag: Some(etag),
+            sse_type: upload.params.server_side_encryption,
+            sse_kms_key_id: upload.params.ssekms_key_id,
+        })
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, MultipartUploadError, Self::ClientError> {
+        trace!(bucket, key, upload_id, "AbortMultipartUpload");
+        self.inc_op_count(Operation::AbortMultipartUpload);
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(MultipartUploadError::NoSuchBucket));
+        }
+
+        let mut uploads = self.multipart_uploads.write().unwrap();
+        if !uploads.get(upload_id).is_some_and(|upload| upload.key == key) {
+            return Err(ObjectClientError::ServiceError(MultipartUploadError::NoSuchUpload));
+        }
+        uploads.remove(upload_id);
+
+        Ok(AbortMultipartUploadResult {})
+    }
+
+    async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        key_marker: Option<&str>,
+        upload_id_marker: Option<&str>,
+        max_uploads: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, MultipartUploadError, Self::ClientError> {
+        trace!(
+            bucket,
+            ?key_marker,
+            ?upload_id_marker,
+            max_uploads,
+            prefix,
+            "ListMultipartUploads"
+        );
+        self.inc_op_count(Operation::ListMultipartUploads);
+
+        if bucket != self.config.bucket {
+            return Err(ObjectClientError::ServiceError(MultipartUploadError::NoSuchBucket));
+        }
+
+        let uploads = self.multipart_uploads.read().unwrap();
+        let marker_sequence = upload_id_marker
+            .and_then(|marker| uploads.get(marker))
+            .map(|upload| upload.sequence);
+        let mut matching = uploads
+            .iter()
+            .filter(|(_, upload)| upload.key.starts_with(prefix))
+            .filter(|(_, upload)| match (key_marker, marker_sequence) {
+                (None, _) => true,
+                (Some(key_marker), None) => upload.key.as_str() > key_marker,
+                (Some(key_marker), Some(sequence)) => {
+                    upload.key.as_str() > key_marker || (upload.key == key_marker && upload.sequence > sequence)
+                }
+            })
+            .collect::<Vec<_>>();
+        matching.sort_by(|(_, a), (_, b)| (&a.key, a.sequence).cmp(&(&b.key, b.sequence)));
+
+        let is_truncated = matching.len() > max_uploads;
+        matching.truncate(max_uploads);
+        let uploads = matching
+            .into_iter()
+            .map(|(upload_id, upload)| MultipartUploadInfo {
+                key: upload.key.clone(),
+                upload_id: upload_id.clone(),
+                initiated: upload.initiated,
+            })
+            .collect::<Vec<_>>();
+        let (next_key_marker, next_upload_id_marker) = match uploads.last() {
+            Some(last) if is_truncated => (Some(last.key.clone()), Some(last.upload_id.clone())),
+            _ => (None, None),
+        };
+
+        Ok(ListMultipartUploadsResult {
+            uploads,
+            next_key_marker,
+            next_upload_id_marker,
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -748,7 +1322,8 @@ pub struct MockPutObjectRequest {
     part_size: usize,
     params: PutObjectParams,
     objects: Arc<RwLock<BTreeMap<String, MockObject>>>,
-    in_progress_uploads: Arc<RwLock<BTreeSet<String>>>,
+    object_versions: Arc<RwLock<BTreeMap<String, Vec<MockObjectVersion>>>>,
+    in_progress_uploads: Arc<RwLock<BTreeMap<String, usize>>>,
 }
 
 impl MockPutObjectRequest {
@@ -757,15 +1332,17 @@ impl MockPutObjectRequest {
         part_size: usize,
         params: &PutObjectParams,
         objects: &Arc<RwLock<BTreeMap<String, MockObject>>>,
-        in_progress_uploads: &Arc<RwLock<BTreeSet<String>>>,
+        object_versions: &Arc<RwLock<BTreeMap<String, Vec<MockObjectVersion>>>>,
+        in_progress_uploads: &Arc<RwLock<BTreeMap<String, usize>>>,
     ) -> Self {
-        in_progress_uploads.write().unwrap().insert(key.to_owned());
+        *in_progress_uploads.write().unwrap().entry(key.to_owned()).or_default() += 1;
         Self {
             key: key.to_owned(),
             buffer: vec![],
             part_size,
             params: params.clone(),
             objects: objects.clone(),
+            object_versions: object_versions.clone(),
             in_progress_uploads: in_progress_uploads.clone(),
         }
     }
@@ -793,23 +1370,33 @@ impl MockPutObjectRequest {
         let buffer = std::mem::take(&mut self.buffer);
         let mut object: MockObject = buffer.into();
         object.set_storage_class(self.params.storage_class.clone());
+        object.set_object_metadata(self.params.object_metadata.clone());
         // For S3 Standard, part attributes are only available when additional checksums are used
         if self.params.trailing_checksums == PutObjectTrailingChecksums::Enabled {
             object.parts = Some(MockObjectParts::Parts(parts));
         } else {
             object.parts = Some(MockObjectParts::Count(parts.len()));
         }
-        add_object(&self.objects, &self.key, object);
+        let etag = object.etag.clone();
+        add_object(&self.objects, &self.object_versions, &self.key, object);
+        // Like S3, report the encryption settings the object was created with
         Ok(PutObjectResult {
-            sse_type: None,
-            sse_kms_key_id: None,
+            etag: Some(etag),
+            sse_type: self.params.server_side_encryption.clone(),
+            sse_kms_key_id: self.params.ssekms_key_id.clone(),
         })
     }
 }
 
 impl Drop for MockPutObjectRequest {
     fn drop(&mut self) {
-        self.in_progress_uploads.write().unwrap().remove(&self.key);
+        let mut in_progress_uploads = self.in_progress_uploads.write().unwrap();
+        if let Some(count) = in_progress_uploads.get_mut(&self.key) {
+            *count -= 1;
+            if *count == 0 {
+                in_progress_uploads.remove(&self.key);
+            }
+        }
     }
 }
 
@@ -855,6 +1442,19 @@ impl PutObjectRequest for MockPutObjectRequest {
     }
 }
 
+/// A multipart upload started with [MockClient::create_multipart_upload] that hasn't been completed
+/// or aborted yet.
+#[derive(Debug)]
+struct MockMultipartUpload {
+    key: String,
+    params: PutObjectParams,
+    /// Uploaded parts by part number, with their ETags
+    parts: BTreeMap<usize, (ETag, Box<[u8]>)>,
+    /// Order the upload was created in, relative to other uploads
+    sequence: u64,
+    initiated: OffsetDateTime,
+}
+
 #[derive(Debug, Clone)]
 struct MockObjectPartAttributes {
     size: usize,
@@ -893,7 +1493,7 @@ mod tests {
         client.add_object(key, MockObject::from_bytes(&body, ETag::for_tests()));
 
         let mut get_request = client
-            .get_object("test_bucket", key, range.clone(), None)
+            .get_object("test_bucket", key, &GetObjectParams::new().range(range.clone()))
             .await
             .expect("should not fail");
 
@@ -917,6 +1517,51 @@ mod tests {
         test_get_object("key1", 10, Some(0..10)).await;
     }
 
+    #[tokio::test]
+    async fn get_object_checksum() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+            unordered_list_seed: None,
+        });
+        let body = vec![42u8; 2000];
+        client.add_object("key1", MockObject::from_bytes(&body, ETag::for_tests()));
+        let mut corrupted = MockObject::from_bytes(&body, ETag::for_tests());
+        corrupted.set_checksum_crc32c(crc32c::Crc32c::new(1234));
+        client.add_object("key2", corrupted);
+        let mut multipart = MockObject::from_bytes(&body, ETag::for_tests());
+        let part_checksum = crc32c::checksum(&body[..1000]);
+        multipart.set_part_checksums_crc32c(&[(1000, part_checksum), (1000, part_checksum)]);
+        client.add_object("key3", multipart);
+
+        let get_request = client
+            .get_object("test_bucket", "key1", &GetObjectParams::new())
+            .await
+            .unwrap();
+        assert!(get_request.get_object_checksum().is_none());
+
+        let params = GetObjectParams::new().checksum_mode(Some(ChecksumMode::Enabled));
+        let get_request = client.get_object("test_bucket", "key1", &params).await.unwrap();
+        let checksum = get_request.get_object_checksum().expect("should have a checksum");
+        assert_eq!(
+            checksum.checksum_crc32c,
+            Some(crc32c_to_base64(&crc32c::checksum(&body)))
+        );
+
+        let get_request = client.get_object("test_bucket", "key2", &params).await.unwrap();
+        let checksum = get_request.get_object_checksum().expect("should have a checksum");
+        assert_eq!(checksum.checksum_crc32c.as_deref(), Some("AAAE0g=="));
+
+        // Objects uploaded in parts have a composite checksum
+        let get_request = client.get_object("test_bucket", "key3", &params).await.unwrap();
+        let checksum = get_request.get_object_checksum().expect("should have a checksum");
+        let mut hasher = crc32c::Hasher::new();
+        hasher.update(&part_checksum.value().to_be_bytes());
+        hasher.update(&part_checksum.value().to_be_bytes());
+        let expected = format!("{}-2", crc32c_to_base64(&hasher.finalize()));
+        assert_eq!(checksum.checksum_crc32c, Some(expected));
+    }
+
     #[allow(clippy::reversed_empty_ranges)]
     #[tokio::test]
     async fn get_object_errors() {
@@ -945,33 +1590,45 @@ mod tests {
         }
 
         assert!(matches!(
-            client.get_object("wrong_bucket", "key1", None, None).await,
+            client.get_object("wrong_bucket", "key1", &GetObjectParams::new()).await,
             Err(ObjectClientError::ServiceError(GetObjectError::NoSuchBucket))
         ));
 
         assert!(matches!(
-            client.get_object("test_bucket", "wrong_key", None, None).await,
+            client
+                .get_object("test_bucket", "wrong_key", &GetObjectParams::new())
+                .await,
             Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey))
         ));
 
         assert_client_error!(
-            client.get_object("test_bucket", "key1", Some(0..2001), None).await,
+            client
+                .get_object("test_bucket", "key1", &GetObjectParams::new().range(Some(0..2001)))
+                .await,
             "invalid range, length=2000"
         );
         assert_client_error!(
-            client.get_object("test_bucket", "key1", Some(2000..2000), None).await,
+            client
+                .get_object("test_bucket", "key1", &GetObjectParams::new().range(Some(2000..2000)))
+                .await,
             "invalid range, length=2000"
         );
         assert_client_error!(
-            client.get_object("test_bucket", "key1", Some(500..2001), None).await,
+            client
+                .get_object("test_bucket", "key1", &GetObjectParams::new().range(Some(500..2001)))
+                .await,
             "invalid range, length=2000"
         );
         assert_client_error!(
-            client.get_object("test_bucket", "key1", Some(5000..2001), None).await,
+            client
+                .get_object("test_bucket", "key1", &GetObjectParams::new().range(Some(5000..2001)))
+                .await,
             "invalid range, length=2000"
         );
         assert_client_error!(
-            client.get_object("test_bucket", "key1", Some(5000..1), None).await,
+            client
+                .get_object("test_bucket", "key1", &GetObjectParams::new().range(Some(5000..1)))
+                .await,
             "invalid range, length=2000"
         );
     }
@@ -1313,6 +1970,88 @@ mod tests {
         assert_eq!(objects, expected_objects);
     }
 
+    #[test_case(1; "single entry pages")]
+    #[test_case(3; "small pages")]
+    #[test_case(1000; "one page")]
+    #[tokio::test]
+    async fn list_object_versions(page_size: usize) {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+            unordered_list_seed: None,
+        });
+
+        client.add_object("a", MockObject::constant(0u8, 5, ETag::for_tests()));
+        client.add_object("a", MockObject::constant(1u8, 5, ETag::for_tests()));
+        client.add_object("b/1", MockObject::constant(2u8, 5, ETag::for_tests()));
+        client.add_object("b/2", MockObject::constant(3u8, 5, ETag::for_tests()));
+        client.add_object("c", MockObject::constant(4u8, 5, ETag::for_tests()));
+        client.remove_object("c");
+        assert_eq!(client.object_version_ids("a"), ["v1", "v2"]);
+        assert_eq!(client.object_version_ids("c"), ["v1", "v2"]);
+
+        let mut key_marker = None;
+        let mut version_id_marker = None;
+        let mut versions = Vec::new();
+        let mut prefixes = Vec::new();
+        loop {
+            let result = client
+                .list_object_versions(
+                    "test_bucket",
+                    key_marker.as_deref(),
+                    version_id_marker.as_deref(),
+                    "/",
+                    page_size,
+                    "",
+                )
+                .await
+                .expect("list should succeed");
+            assert!(result.versions.len() + result.common_prefixes.len() <= page_size);
+            versions.extend(result.versions.iter().map(|version| {
+                let delete_marker = matches!(version, ObjectVersion::DeleteMarker { .. });
+                (version.key().to_owned(), version.version_id().to_owned(), delete_marker)
+            }));
+            prefixes.extend(result.common_prefixes);
+            if result.next_key_marker.is_none() {
+                break;
+            }
+            key_marker = result.next_key_marker;
+            version_id_marker = result.next_version_id_marker;
+        }
+
+        let expected_versions = [
+            ("a", "v2", false),
+            ("a", "v1", false),
+            ("c", "v2", true),
+            ("c", "v1", false),
+        ]
+        .map(|(key, version_id, delete_marker)| (key.to_owned(), version_id.to_owned(), delete_marker));
+        assert_eq!(versions, expected_versions);
+        assert_eq!(prefixes, ["b/"]);
+
+        // Old versions can still be read, but delete markers can't
+        let mut get_request = client
+            .get_object(
+                "test_bucket",
+                "a",
+                &GetObjectParams::new().version_id(Some("v1".to_owned())),
+            )
+            .await
+            .expect("get should succeed");
+        let (_offset, body) = get_request.next().await.unwrap().unwrap();
+        assert_eq!(&body[..], &[0u8; 5]);
+        assert!(matches!(
+            client
+                .get_object(
+                    "test_bucket",
+                    "c",
+                    &GetObjectParams::new().version_id(Some("v2".to_owned()))
+                )
+                .await,
+            Err(ObjectClientError::ServiceError(GetObjectError::NoSuchKey))
+        ));
+    }
+
     #[tokio::test]
     async fn test_put_object() {
         let mut rng = ChaChaRng::seed_from_u64(0x12345678);
@@ -1342,7 +2081,7 @@ mod tests {
         put_request.complete().await.expect("put_object failed");
 
         let mut get_request = client
-            .get_object("test_bucket", "key1", None, None)
+            .get_object("test_bucket", "key1", &GetObjectParams::new())
             .await
             .expect("get_object failed");
 
@@ -1356,6 +2095,281 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_copy_object() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+            unordered_list_seed: None,
+        });
+
+        let obj = MockObject::ramp(0xaa, 2 * RAMP_BUFFER_SIZE, ETag::for_tests());
+        client.add_object("key1", obj.clone());
+
+        client
+            .copy_object("test_bucket", "key1", "test_bucket", "key2", &Default::default())
+            .await
+            .expect("copy_object failed");
+        assert!(client.contains_key("key1"));
+
+        let mut get_request = client
+            .get_object("test_bucket", "key2", &GetObjectParams::new())
+            .await
+            .expect("get_object failed");
+        let mut next_offset = 0;
+        while let Some(r) = get_request.next().await {
+            let (offset, body) = r.expect("get_object body part failed");
+            assert_eq!(offset, next_offset, "wrong body part offset");
+            next_offset += body.len() as u64;
+            assert_eq!(body, obj.read(offset, body.len()));
+        }
+        assert_eq!(next_offset, obj.len() as u64);
+
+        let result = client
+            .copy_object("test_bucket", "missing", "test_bucket", "key3", &Default::default())
+            .await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(CopyObjectError::NotFound))
+        ));
+        assert!(!client.contains_key("key3"));
+    }
+
+    #[tokio::test]
+    async fn test_multipart_upload() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+            unordered_list_seed: None,
+        });
+
+        let obj = MockObject::ramp(0xaa, 2000, ETag::for_tests());
+        client.add_object("key1", obj.clone());
+
+        let upload = client
+            .create_multipart_upload("test_bucket", "key1", &Default::default())
+            .await
+            .expect("create_multipart_upload failed");
+        assert!(client.is_upload_in_progress("key1"));
+
+        let part1 = client
+            .upload_part_copy(
+                "test_bucket",
+                "key1",
+                &upload.upload_id,
+                1,
+                "test_bucket",
+                "key1",
+                &UploadPartCopyParams::new().if_match(Some(ETag::for_tests())),
+            )
+            .await
+            .expect("upload_part_copy failed");
+        let suffix = ramp_bytes(0x12, 500);
+        let part2 = client
+            .upload_part("test_bucket", "key1", &upload.upload_id, 2, &suffix)
+            .await
+            .expect("upload_part failed");
+
+        // The object doesn't change until the upload completes
+        assert_eq!(
+            client.head_object("test_bucket", "key1").await.unwrap().object.size,
+            2000
+        );
+
+        let parts = [
+            CompletedPart {
+                part_number: 1,
+                etag: part1.etag,
+            },
+            CompletedPart {
+                part_number: 2,
+                etag: part2.etag,
+            },
+        ];
+        client
+            .complete_multipart_upload("test_bucket", "key1", &upload.upload_id, &parts)
+            .await
+            .expect("complete_multipart_upload failed");
+        assert!(!client.is_upload_in_progress("key1"));
+
+        let body = client
+            .get_object("test_bucket", "key1", &GetObjectParams::new())
+            .await
+            .expect("get_object failed")
+            .collect()
+            .await
+            .expect("get_object failed");
+        let mut expected = obj.read(0, obj.len()).into_vec();
+        expected.extend_from_slice(&suffix);
+        assert_eq!(&body[..], &expected[..]);
+
+        // The upload is gone once completed
+        let result = client
+            .abort_multipart_upload("test_bucket", "key1", &upload.upload_id)
+            .await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(MultipartUploadError::NoSuchUpload))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_upload_part_copy_params() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+            unordered_list_seed: None,
+        });
+
+        let obj = MockObject::ramp(0xaa, 2000, ETag::for_tests());
+        client.add_object("key1", obj.clone());
+
+        let upload = client
+            .create_multipart_upload("test_bucket", "key2", &Default::default())
+            .await
+            .expect("create_multipart_upload failed");
+
+        let result = client
+            .upload_part_copy(
+                "test_bucket",
+                "key2",
+                &upload.upload_id,
+                1,
+                "test_bucket",
+                "key1",
+                &UploadPartCopyParams::new().if_match(Some(ETag::from_object_bytes(b"other"))),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(
+                MultipartUploadError::PreconditionFailed
+            ))
+        ));
+
+        let mut parts = Vec::new();
+        for (part_number, range) in [(1, 0..1200), (2, 1200..2000)] {
+            let part = client
+                .upload_part_copy(
+                    "test_bucket",
+                    "key2",
+                    &upload.upload_id,
+                    part_number,
+                    "test_bucket",
+                    "key1",
+                    &UploadPartCopyParams::new().range(Some(range)),
+                )
+                .await
+                .expect("upload_part_copy failed");
+            parts.push(CompletedPart {
+                part_number,
+                etag: part.etag,
+            });
+        }
+        client
+            .complete_multipart_upload("test_bucket", "key2", &upload.upload_id, &parts)
+            .await
+            .expect("complete_multipart_upload failed");
+
+        let body = client
+            .get_object("test_bucket", "key2", &GetObjectParams::new())
+            .await
+            .expect("get_object failed")
+            .collect()
+            .await
+            .expect("get_object failed");
+        assert_eq!(&body[..], &obj.read(0, obj.len())[..]);
+    }
+
+    #[tokio::test]
+    async fn test_abort_multipart_upload() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+            unordered_list_seed: None,
+        });
+
+        let upload = client
+            .create_multipart_upload("test_bucket", "key1", &Default::default())
+            .await
+            .expect("create_multipart_upload failed");
+        client
+            .upload_part("test_bucket", "key1", &upload.upload_id, 1, b"hello")
+            .await
+            .expect("upload_part failed");
+
+        let result = client
+            .upload_part_copy(
+                "test_bucket",
+                "key1",
+                &upload.upload_id,
+                2,
+                "test_bucket",
+                "missing",
+                &UploadPartCopyParams::new(),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(ObjectClientError::ServiceError(MultipartUploadError::NoSuchKey))
+        ));
+
+        client
+            .abort_multipart_upload("test_bucket", "key1", &upload.upload_id)
+            .await
+            .expect("abort_multipart_upload failed");
+        assert!(!client.is_upload_in_progress("key1"));
+        assert!(!client.contains_key("key1"));
+    }
+
+    #[tokio::test]
+    async fn test_list_multipart_uploads() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024,
+            unordered_list_seed: None,
+        });
+
+        let mut upload_ids = Vec::new();
+        for key in ["b", "a", "b", "c", "dir/d"] {
+            let upload = client
+                .create_multipart_upload("test_bucket", key, &Default::default())
+                .await
+                .expect("create_multipart_upload failed");
+            upload_ids.push((key, upload.upload_id));
+        }
+        let expected = [&upload_ids[1], &upload_ids[0], &upload_ids[2], &upload_ids[3]];
+
+        // List two at a time
+        let mut listed = Vec::new();
+        let mut key_marker = None;
+        let mut upload_id_marker = None;
+        loop {
+            let result = client
+                .list_multipart_uploads("test_bucket", key_marker.as_deref(), upload_id_marker.as_deref(), 2, "")
+                .await
+                .expect("list_multipart_uploads failed");
+            listed.extend(result.uploads.into_iter().map(|upload| (upload.key, upload.upload_id)));
+            if result.next_key_marker.is_none() {
+                break;
+            }
+            key_marker = result.next_key_marker;
+            upload_id_marker = result.next_upload_id_marker;
+        }
+        assert_eq!(listed.len(), 5);
+        for (listed, (key, upload_id)) in listed.iter().zip(expected) {
+            assert_eq!(listed, &(key.to_string(), upload_id.clone()));
+        }
+
+        let result = client
+            .list_multipart_uploads("test_bucket", None, None, 1000, "dir/")
+            .await
+            .expect("list_multipart_uploads failed");
+        assert_eq!(result.uploads.len(), 1);
+        assert_eq!(result.uploads[0].key, "dir/d");
+        assert_eq!(result.next_key_marker, None);
+    }
+
     proptest::proptest! {
         #[test]
         fn test_ramp(size in 1..2*RAMP_BUFFER_SIZE, read_size in 1..2*RAMP_BUFFER_SIZE, offset in 0..RAMP_BUFFER_SIZE) {
@@ -1400,6 +2414,26 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_object_metadata() {
+        let bucket = "test_bucket";
+        let client = MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 1024,
+            unordered_list_seed: None,
+        });
+
+        let key = "key1";
+        let object_metadata = HashMap::from([("foo".to_owned(), "bar".to_owned())]);
+        let put_params = PutObjectParams::new().object_metadata(object_metadata.clone());
+        let mut put_request = client.put_object(bucket, key, &put_params).await.unwrap();
+        put_request.write(b"hello").await.unwrap();
+        put_request.complete().await.unwrap();
+
+        let head_result = client.head_object(bucket, key).await.unwrap();
+        assert_eq!(head_result.object_metadata, object_metadata);
+    }
+
     #[tokio::test]
     async fn counter_test() {
         let bucket = "test_bucket";
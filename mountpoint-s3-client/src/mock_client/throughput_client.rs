@@ -1,4 +1,3 @@
-use std::ops::Range;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Duration;
@@ -11,11 +10,13 @@ use pin_project::pin_project;
 use crate::mock_client::leaky_bucket::LeakyBucket;
 use crate::mock_client::{MockClient, MockClientConfig, MockClientError, MockObject, MockPutObjectRequest};
 use crate::object_client::{
-    DeleteObjectError, DeleteObjectResult, GetBodyPart, GetObjectAttributesError, GetObjectAttributesResult,
-    GetObjectError, HeadObjectError, HeadObjectResult, ListObjectsError, ListObjectsResult, ObjectAttribute,
-    ObjectClient, ObjectClientResult, PutObjectError, PutObjectParams,
+    AbortMultipartUploadResult, Checksum, CompletedPart, CopyObjectError, CopyObjectParams, CopyObjectResult,
+    CreateMultipartUploadResult, DeleteObjectError, DeleteObjectResult, GetBodyPart, GetObjectAttributesError,
+    GetObjectAttributesResult, GetObjectError, GetObjectParams, GetObjectRequest, HeadObjectError, HeadObjectResult,
+    ListMultipartUploadsResult, ListObjectVersionsResult, ListObjectsError, ListObjectsResult, MultipartUploadError,
+    ObjectAttribute, ObjectClient, ObjectClientResult, PutObjectError, PutObjectParams, PutObjectResult,
+    UploadPartCopyParams, UploadPartResult,
 };
-use crate::types::ETag;
 
 /// A [MockClient] that rate limits overall download throughput to simulate a target network
 /// performance without the jitter or service latency of targeting a real service. Note that while
@@ -60,6 +61,15 @@ impl ThroughputMockClient {
 pub struct GetObjectResult {
     #[pin]
     inner: BoxStream<'static, ObjectClientResult<GetBodyPart, GetObjectError, MockClientError>>,
+    checksum: Option<Checksum>,
+}
+
+impl GetObjectRequest for GetObjectResult {
+    type ClientError = MockClientError;
+
+    fn get_object_checksum(&self) -> Option<Checksum> {
+        self.checksum.clone()
+    }
 }
 
 impl Stream for GetObjectResult {
@@ -77,8 +87,12 @@ impl ObjectClient for ThroughputMockClient {
     type PutObjectRequest = MockPutObjectRequest;
     type ClientError = MockClientError;
 
-    fn part_size(&self) -> Option<usize> {
-        self.inner.part_size()
+    fn read_part_size(&self) -> Option<usize> {
+        self.inner.read_part_size()
+    }
+
+    fn write_part_size(&self) -> Option<usize> {
+        self.inner.write_part_size()
     }
 
     async fn delete_object(
@@ -93,10 +107,10 @@ impl ObjectClient for ThroughputMockClient {
         &self,
         bucket: &str,
         key: &str,
-        range: Option<Range<u64>>,
-        if_match: Option<ETag>,
+        params: &GetObjectParams,
     ) -> ObjectClientResult<Self::GetObjectResult, GetObjectError, Self::ClientError> {
-        let inner = self.inner.get_object(bucket, key, range, if_match).await?;
+        let inner = self.inner.get_object(bucket, key, params).await?;
+        let checksum = inner.get_object_checksum();
         let rate_limiter = self.rate_limiter.clone();
         let stream = inner.then(move |p| {
             let rate_limiter = rate_limiter.clone();
@@ -107,7 +121,10 @@ impl ObjectClient for ThroughputMockClient {
                 Ok(p)
             }
         });
-        Ok(GetObjectResult { inner: stream.boxed() })
+        Ok(GetObjectResult {
+            inner: stream.boxed(),
+            checksum,
+        })
     }
 
     async fn list_objects(
@@ -123,6 +140,20 @@ impl ObjectClient for ThroughputMockClient {
             .await
     }
 
+    async fn list_object_versions(
+        &self,
+        bucket: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectsError, Self::ClientError> {
+        self.inner
+            .list_object_versions(bucket, key_marker, version_id_marker, delimiter, max_keys, prefix)
+            .await
+    }
+
     async fn head_object(
         &self,
         bucket: &str,
@@ -152,6 +183,88 @@ impl ObjectClient for ThroughputMockClient {
             .get_object_attributes(bucket, key, max_parts, part_number_marker, object_attributes)
             .await
     }
+
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+        params: &CopyObjectParams,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, Self::ClientError> {
+        self.inner
+            .copy_object(source_bucket, source_key, destination_bucket, destination_key, params)
+            .await
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+    ) -> ObjectClientResult<CreateMultipartUploadResult, MultipartUploadError, Self::ClientError> {
+        self.inner.create_multipart_upload(bucket, key, params).await
+    }
+
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        data: &[u8],
+    ) -> ObjectClientResult<UploadPartResult, MultipartUploadError, Self::ClientError> {
+        self.inner.upload_part(bucket, key, upload_id, part_number, data).await
+    }
+
+    async fn upload_part_copy(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        source_bucket: &str,
+        source_key: &str,
+        params: &UploadPartCopyParams,
+    ) -> ObjectClientResult<UploadPartResult, MultipartUploadError, Self::ClientError> {
+        self.inner
+            .upload_part_copy(bucket, key, upload_id, part_number, source_bucket, source_key, params)
+            .await
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        parts: &[CompletedPart],
+    ) -> ObjectClientResult<PutObjectResult, MultipartUploadError, Self::ClientError> {
+        self.inner
+            .complete_multipart_upload(bucket, key, upload_id, parts)
+            .await
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, MultipartUploadError, Self::ClientError> {
+        self.inner.abort_multipart_upload(bucket, key, upload_id).await
+    }
+
+    async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        key_marker: Option<&str>,
+        upload_id_marker: Option<&str>,
+        max_uploads: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, MultipartUploadError, Self::ClientError> {
+        self.inner
+            .list_multipart_uploads(bucket, key_marker, upload_id_marker, max_uploads, prefix)
+            .await
+    }
 }
 
 #[cfg(test)]
@@ -162,6 +275,7 @@ mod tests {
     use futures::StreamExt;
 
     use crate::mock_client::MockObject;
+    use crate::types::ETag;
 
     use super::*;
 
@@ -187,7 +301,10 @@ mod tests {
                 let start = Instant::now();
                 let num_bytes = block_on(async move {
                     let mut num_bytes = 0;
-                    let mut get = client.get_object("test_bucket", "testfile", None, None).await.unwrap();
+                    let mut get = client
+                        .get_object("test_bucket", "testfile", &GetObjectParams::new())
+                        .await
+                        .unwrap();
                     while let Some(part) = get.next().await {
                         let (_offset, part) = part.unwrap();
                         num_bytes += part.len();
@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use auto_impl::auto_impl;
 use futures::Stream;
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::time::SystemTime;
 use std::{
@@ -71,13 +72,17 @@ impl FromStr for ETag {
 #[cfg_attr(not(docs_rs), async_trait)]
 #[auto_impl(Arc)]
 pub trait ObjectClient {
-    type GetObjectResult: Stream<Item = ObjectClientResult<GetBodyPart, GetObjectError, Self::ClientError>> + Send;
+    type GetObjectResult: GetObjectRequest<ClientError = Self::ClientError>;
     type PutObjectRequest: PutObjectRequest<ClientError = Self::ClientError>;
     type ClientError: std::error::Error + Send + Sync + 'static;
 
-    /// Query the part size this client uses for PUT and GET operations to the object store. This
-    /// can be `None` if the client does not do multi-part operations.
-    fn part_size(&self) -> Option<usize>;
+    /// Query the part size this client uses for GET operations to the object store. This can be
+    /// `None` if the client does not do multi-part operations.
+    fn read_part_size(&self) -> Option<usize>;
+
+    /// Query the part size this client uses for PUT operations to the object store. This can be
+    /// `None` if the client does not do multi-part operations.
+    fn write_part_size(&self) -> Option<usize>;
 
     /// Delete a single object from the object store.
     ///
@@ -94,8 +99,7 @@ pub trait ObjectClient {
         &self,
         bucket: &str,
         key: &str,
-        range: Option<Range<u64>>,
-        if_match: Option<ETag>,
+        params: &GetObjectParams,
     ) -> ObjectClientResult<Self::GetObjectResult, GetObjectError, Self::ClientError>;
 
     /// List the objects in a bucket under a given prefix
@@ -108,6 +112,20 @@ pub trait ObjectClient {
         prefix: &str,
     ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError>;
 
+    /// List the versions of the objects in a bucket under a given prefix, including delete markers.
+    /// Versions are returned in key order, and from newest to oldest for each key. A listing that
+    /// doesn't fit in one page continues from the next key marker and version ID marker of the
+    /// previous page.
+    async fn list_object_versions(
+        &self,
+        bucket: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectsError, Self::ClientError>;
+
     /// Retrieve object metadata without retrieving the object contents
     async fn head_object(
         &self,
@@ -133,6 +151,87 @@ pub trait ObjectClient {
         part_number_marker: Option<usize>,
         object_attributes: &[ObjectAttribute],
     ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, Self::ClientError>;
+
+    /// Create a copy of an object that is already stored in the object store. The copy is made
+    /// entirely on the server side, without the object's contents passing through this client.
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+        params: &CopyObjectParams,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, Self::ClientError>;
+
+    /// Start a multipart upload to the given key. The new object isn't visible until the upload is
+    /// completed with [`complete_multipart_upload`](Self::complete_multipart_upload), and uploads
+    /// that won't be completed should be aborted with
+    /// [`abort_multipart_upload`](Self::abort_multipart_upload) to discard their parts.
+    ///
+    /// Most callers should use [`put_object`](Self::put_object) instead, which manages the
+    /// multipart upload itself. These lower-level requests are for uploads that it can't express,
+    /// like objects built partly from copies of existing objects.
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+    ) -> ObjectClientResult<CreateMultipartUploadResult, MultipartUploadError, Self::ClientError>;
+
+    /// Upload one part of a multipart upload. Part numbers start at 1, and every part except the
+    /// last must be at least the minimum part size (5 MiB for S3).
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        data: &[u8],
+    ) -> ObjectClientResult<UploadPartResult, MultipartUploadError, Self::ClientError>;
+
+    /// Upload one part of a multipart upload by copying an existing object, or a range of it. The
+    /// copy is made entirely on the server side, without the object's contents passing through
+    /// this client.
+    async fn upload_part_copy(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        source_bucket: &str,
+        source_key: &str,
+        params: &UploadPartCopyParams,
+    ) -> ObjectClientResult<UploadPartResult, MultipartUploadError, Self::ClientError>;
+
+    /// Complete a multipart upload, creating the object from the given parts in order.
+    async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        parts: &[CompletedPart],
+    ) -> ObjectClientResult<PutObjectResult, MultipartUploadError, Self::ClientError>;
+
+    /// Abort a multipart upload, discarding any parts that were already uploaded.
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, MultipartUploadError, Self::ClientError>;
+
+    /// List the multipart uploads in a bucket under a given prefix that have been started but not
+    /// yet completed or aborted. Uploads are returned in key order, and in the order they were
+    /// started for each key. A listing that doesn't fit in one page continues from the next key
+    /// marker and upload ID marker of the previous page.
+    async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        key_marker: Option<&str>,
+        upload_id_marker: Option<&str>,
+        max_uploads: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, MultipartUploadError, Self::ClientError>;
 }
 
 /// The top-level error type returned by calls to an [`ObjectClient`].
@@ -164,6 +263,90 @@ pub enum ObjectClientError<S, C> {
 /// Shorthand type for the result of an object client request
 pub type ObjectClientResult<T, S, C> = Result<T, ObjectClientError<S, C>>;
 
+/// Parameters to a [`get_object`](ObjectClient::get_object) request
+#[derive(Debug, Default, Clone)]
+#[non_exhaustive]
+pub struct GetObjectParams {
+    /// The byte range of the object to get, or `None` for the whole object
+    pub range: Option<Range<u64>>,
+    /// Only return the object if its ETag matches this one
+    pub if_match: Option<ETag>,
+    /// The version of the object to get, or `None` for the latest version
+    pub version_id: Option<String>,
+    /// Whether to ask for the object's additional checksum, or `None` to not ask for it
+    pub checksum_mode: Option<ChecksumMode>,
+}
+
+impl GetObjectParams {
+    /// Create a default [GetObjectParams].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the byte range to get.
+    pub fn range(mut self, value: Option<Range<u64>>) -> Self {
+        self.range = value;
+        self
+    }
+
+    /// Set the ETag the object must match.
+    pub fn if_match(mut self, value: Option<ETag>) -> Self {
+        self.if_match = value;
+        self
+    }
+
+    /// Set the version of the object to get.
+    pub fn version_id(mut self, value: Option<String>) -> Self {
+        self.version_id = value;
+        self
+    }
+
+    /// Set whether to ask for the object's additional checksum.
+    pub fn checksum_mode(mut self, value: Option<ChecksumMode>) -> Self {
+        self.checksum_mode = value;
+        self
+    }
+}
+
+/// Parameters to an [`upload_part_copy`](ObjectClient::upload_part_copy) request
+#[derive(Debug, Default, Clone)]
+#[non_exhaustive]
+pub struct UploadPartCopyParams {
+    /// The byte range of the source object to copy, or `None` for the whole object
+    pub range: Option<Range<u64>>,
+    /// Only copy the source object if its ETag matches this one
+    pub if_match: Option<ETag>,
+}
+
+impl UploadPartCopyParams {
+    /// Create a default [UploadPartCopyParams].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the byte range of the source object to copy.
+    pub fn range(mut self, value: Option<Range<u64>>) -> Self {
+        self.range = value;
+        self
+    }
+
+    /// Set the ETag the source object must match.
+    pub fn if_match(mut self, value: Option<ETag>) -> Self {
+        self.if_match = value;
+        self
+    }
+}
+
+/// Whether a [`get_object`](ObjectClient::get_object) request asks the object store to return the
+/// object's additional checksum. The checksum is available from
+/// [`GetObjectRequest::get_object_checksum`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChecksumMode {
+    /// Return the checksum of the object, if it was uploaded with one
+    Enabled,
+}
+
 /// Errors returned by a [`get_object`](ObjectClient::get_object) request
 #[derive(Debug, Error, PartialEq, Eq)]
 #[non_exhaustive]
@@ -193,6 +376,72 @@ pub struct ListObjectsResult {
     pub next_continuation_token: Option<String>,
 }
 
+/// Result of a [`list_object_versions`](ObjectClient::list_object_versions) request
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ListObjectVersionsResult {
+    /// The list of object versions and delete markers, in key order and then from newest to oldest.
+    pub versions: Vec<ObjectVersion>,
+
+    /// The list of common prefixes. This rolls up all of the versions with a common prefix up to
+    /// the next instance of the delimiter.
+    pub common_prefixes: Vec<String>,
+
+    /// If present, the key marker to use to query more results.
+    pub next_key_marker: Option<String>,
+
+    /// If present, the version ID marker to use to query more results. This can be missing even if
+    /// there are more results, when the page ended with a common prefix.
+    pub next_version_id_marker: Option<String>,
+}
+
+/// A version of an object returned by a
+/// [`list_object_versions`](ObjectClient::list_object_versions) request
+#[derive(Debug, Clone)]
+pub enum ObjectVersion {
+    /// A version of the object with some contents
+    Object {
+        /// Metadata of this version of the object
+        info: ObjectInfo,
+        /// The version ID
+        version_id: String,
+    },
+    /// A marker that the object was deleted
+    DeleteMarker {
+        /// The key of the object that was deleted
+        key: String,
+        /// The version ID of the delete marker
+        version_id: String,
+        /// When the object was deleted
+        last_modified: OffsetDateTime,
+    },
+}
+
+impl ObjectVersion {
+    /// The key of the object
+    pub fn key(&self) -> &str {
+        match self {
+            Self::Object { info, .. } => &info.key,
+            Self::DeleteMarker { key, .. } => key,
+        }
+    }
+
+    /// The version ID of this version
+    pub fn version_id(&self) -> &str {
+        match self {
+            Self::Object { version_id, .. } | Self::DeleteMarker { version_id, .. } => version_id,
+        }
+    }
+
+    /// When this version was created
+    pub fn last_modified(&self) -> OffsetDateTime {
+        match self {
+            Self::Object { info, .. } => info.last_modified,
+            Self::DeleteMarker { last_modified, .. } => *last_modified,
+        }
+    }
+}
+
 /// Errors returned by a [`list_objects`](ObjectClient::list_objects) request
 #[derive(Debug, Error, PartialEq, Eq)]
 #[non_exhaustive]
@@ -210,6 +459,10 @@ pub struct HeadObjectResult {
 
     /// Object metadata
     pub object: ObjectInfo,
+
+    /// User-defined object metadata, from the `x-amz-meta-*` headers. Keys do not include the
+    /// `x-amz-meta-` prefix.
+    pub object_metadata: HashMap<String, String>,
 }
 
 /// Errors returned by a [`head_object`](ObjectClient::head_object) request
@@ -237,6 +490,60 @@ pub enum DeleteObjectError {
     NoSuchBucket,
 }
 
+/// Parameters to a [`copy_object`](ObjectClient::copy_object) request
+#[derive(Debug, Default, Clone)]
+#[non_exhaustive]
+pub struct CopyObjectParams {
+    /// Storage class to be used for the new copy of the object
+    pub storage_class: Option<String>,
+    /// The server-side encryption algorithm to be used for the new copy of the object (for example, AES256, aws:kms, aws:kms:dsse)
+    pub server_side_encryption: Option<String>,
+    /// If `server_side_encryption` has a valid value of aws:kms or aws:kms:dsse, this value may be used to specify AWS KMS key ID to be used
+    /// for the new copy of the object
+    pub ssekms_key_id: Option<String>,
+}
+
+impl CopyObjectParams {
+    /// Create a default [CopyObjectParams].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the storage class.
+    pub fn storage_class(mut self, value: String) -> Self {
+        self.storage_class = Some(value);
+        self
+    }
+
+    /// Set server-side encryption type.
+    pub fn server_side_encryption(mut self, value: Option<String>) -> Self {
+        self.server_side_encryption = value;
+        self
+    }
+
+    /// Set KMS key ID to be used for server-side encryption.
+    pub fn ssekms_key_id(mut self, value: Option<String>) -> Self {
+        self.ssekms_key_id = value;
+        self
+    }
+}
+
+/// Result of a [`copy_object`](ObjectClient::copy_object) request
+// TODO: Populate this struct with return fields from the S3 API, e.g., etag.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct CopyObjectResult {}
+
+/// Errors returned by a [`copy_object`](ObjectClient::copy_object) request
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CopyObjectError {
+    /// Note that CopyObject cannot always distinguish between NoSuchBucket and NoSuchKey errors,
+    /// as the source object may be looked up with a HeadObject request before the copy starts.
+    #[error("The source object was not found")]
+    NotFound,
+}
+
 /// Result of a [`get_object_attributes`](ObjectClient::get_object_attributes) request
 #[derive(Debug, Default)]
 pub struct GetObjectAttributesResult {
@@ -280,6 +587,9 @@ pub struct PutObjectParams {
     /// If `server_side_encryption` has a valid value of aws:kms or aws:kms:dsse, this value may be used to specify AWS KMS key ID to be used
     /// when creating new S3 object
     pub ssekms_key_id: Option<String>,
+    /// User-defined object metadata, sent as `x-amz-meta-*` headers. Keys should not include the
+    /// `x-amz-meta-` prefix.
+    pub object_metadata: HashMap<String, String>,
 }
 
 impl PutObjectParams {
@@ -311,6 +621,12 @@ impl PutObjectParams {
         self.ssekms_key_id = value;
         self
     }
+
+    /// Set user-defined object metadata.
+    pub fn object_metadata(mut self, value: HashMap<String, String>) -> Self {
+        self.object_metadata = value;
+        self
+    }
 }
 
 /// How CRC32c checksums are used for parts of a multi-part PutObject request
@@ -334,6 +650,24 @@ pub type UploadReviewPart = mountpoint_s3_crt::s3::client::UploadReviewPart;
 /// A checksum algorithm used by the object client for integrity checks on uploads and downloads.
 pub type ChecksumAlgorithm = mountpoint_s3_crt::s3::client::ChecksumAlgorithm;
 
+/// A streaming response to a [`get_object`](ObjectClient::get_object) request.
+///
+/// Each item of the stream is a part of the object body together with the part's offset within the
+/// object. Parts are returned in order and contiguously.
+pub trait GetObjectRequest:
+    Stream<Item = ObjectClientResult<GetBodyPart, GetObjectError, Self::ClientError>> + Send
+{
+    type ClientError: std::error::Error + Send + Sync + 'static;
+
+    /// The checksum the object store returned for the object, if the request was made with
+    /// [`GetObjectParams::checksum_mode`] and the object has one. This is the checksum of the whole
+    /// object (or a composite of its parts' checksums), even if the request was for a range of it.
+    ///
+    /// The checksum might not be available until the first part of the body has been returned by
+    /// the stream.
+    fn get_object_checksum(&self) -> Option<Checksum>;
+}
+
 /// A streaming put request which allows callers to asynchronously write the body of the request.
 ///
 /// You can call the [`write`](Self::write) method to write data to the object, and then call
@@ -362,10 +696,11 @@ pub trait PutObjectRequest: Send {
 }
 
 /// Result of a [ObjectClient::put_object] request
-// TODO: Populate this struct with return fields from the S3 API, e.g., etag.
 #[derive(Debug)]
 #[non_exhaustive]
 pub struct PutObjectResult {
+    /// Entity tag of the new object, if S3 returned one
+    pub etag: Option<ETag>,
     /// Server-side encryption type that was used to store new object (reported by S3)
     pub sse_type: Option<String>,
     /// Server-side encryption KMS key ID that was used to store new object (reported by S3)
@@ -380,6 +715,83 @@ pub enum PutObjectError {
     NoSuchBucket,
 }
 
+/// Result of a [`create_multipart_upload`](ObjectClient::create_multipart_upload) request
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct CreateMultipartUploadResult {
+    /// The ID of the new multipart upload, to pass to the other multipart upload requests
+    pub upload_id: String,
+}
+
+/// Result of an [`upload_part`](ObjectClient::upload_part) or
+/// [`upload_part_copy`](ObjectClient::upload_part_copy) request
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct UploadPartResult {
+    /// Entity tag of the uploaded part, needed to complete the upload
+    pub etag: ETag,
+}
+
+/// A part to include in the object when completing a multipart upload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletedPart {
+    /// The number of the part, as passed when uploading it
+    pub part_number: usize,
+    /// The entity tag returned when uploading the part
+    pub etag: ETag,
+}
+
+/// Result of an [`abort_multipart_upload`](ObjectClient::abort_multipart_upload) request
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct AbortMultipartUploadResult {}
+
+/// Result of a [`list_multipart_uploads`](ObjectClient::list_multipart_uploads) request
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ListMultipartUploadsResult {
+    /// The incomplete multipart uploads, in key order and then in the order they were started.
+    pub uploads: Vec<MultipartUploadInfo>,
+
+    /// If present, the key marker to use to query more results.
+    pub next_key_marker: Option<String>,
+
+    /// If present, the upload ID marker to use to query more results.
+    pub next_upload_id_marker: Option<String>,
+}
+
+/// An incomplete multipart upload returned by a
+/// [`list_multipart_uploads`](ObjectClient::list_multipart_uploads) request
+#[derive(Debug, Clone)]
+pub struct MultipartUploadInfo {
+    /// Key of the object the upload will create
+    pub key: String,
+
+    /// The ID of the upload, to pass to the other multipart upload requests
+    pub upload_id: String,
+
+    /// The time the upload was started
+    pub initiated: OffsetDateTime,
+}
+
+/// Errors returned by the multipart upload requests, like
+/// [`create_multipart_upload`](ObjectClient::create_multipart_upload)
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MultipartUploadError {
+    #[error("The bucket does not exist")]
+    NoSuchBucket,
+
+    #[error("The multipart upload does not exist")]
+    NoSuchUpload,
+
+    #[error("The source object does not exist")]
+    NoSuchKey,
+
+    #[error("The source object does not match the ETag given with If-Match")]
+    PreconditionFailed,
+}
+
 /// Restoration status for S3 objects in flexible retrieval storage classes.
 ///
 /// See [Checking restore status and expiration
@@ -461,7 +873,7 @@ impl fmt::Display for ObjectAttribute {
 ///
 /// See [Checksum](https://docs.aws.amazon.com/AmazonS3/latest/API/API_Checksum.html) in the *Amazon
 /// S3 API Reference* for more details.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Checksum {
     /// Base64-encoded, 32-bit CRC32 checksum of the object
     pub checksum_crc32: Option<String>,
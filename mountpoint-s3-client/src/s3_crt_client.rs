@@ -1,6 +1,6 @@
 use std::ffi::{OsStr, OsString};
 use std::future::Future;
-use std::num::NonZeroUsize;
+use std::num::{NonZeroU16, NonZeroUsize};
 use std::ops::Deref;
 use std::ops::Range;
 use std::os::unix::prelude::OsStrExt;
@@ -12,6 +12,7 @@ use std::time::{Duration, Instant};
 
 use mountpoint_s3_crt::auth::credentials::{
     CredentialsProvider, CredentialsProviderChainDefaultOptions, CredentialsProviderProfileOptions,
+    CredentialsProviderSsoOptions, CredentialsProviderStsOptions,
 };
 use mountpoint_s3_crt::auth::signing_config::SigningConfig;
 use mountpoint_s3_crt::common::allocator::Allocator;
@@ -22,6 +23,8 @@ use mountpoint_s3_crt::io::channel_bootstrap::{ClientBootstrap, ClientBootstrapO
 use mountpoint_s3_crt::io::event_loop::EventLoopGroup;
 use mountpoint_s3_crt::io::host_resolver::{AddressKinds, HostResolver, HostResolverDefaultOptions};
 use mountpoint_s3_crt::io::retry_strategy::{ExponentialBackoffJitterMode, RetryStrategy, StandardRetryOptions};
+use mountpoint_s3_crt::io::stream::InputStream;
+use mountpoint_s3_crt::io::tls::TlsContext;
 use mountpoint_s3_crt::s3::client::{
     init_signing_config, ChecksumConfig, Client, ClientConfig, MetaRequest, MetaRequestOptions, MetaRequestResult,
     MetaRequestType, RequestMetrics, RequestType,
@@ -54,11 +57,13 @@ macro_rules! request_span {
     ($self:expr, $method:expr) => { request_span!($self, $method,) };
 }
 
+pub(crate) mod copy_object;
 pub(crate) mod delete_object;
 pub(crate) mod get_object;
 pub(crate) mod get_object_attributes;
 pub(crate) mod head_object;
 pub(crate) mod list_objects;
+pub(crate) mod multipart_upload;
 pub(crate) mod put_object;
 
 pub(crate) mod head_bucket;
@@ -83,12 +88,16 @@ macro_rules! event {
 pub struct S3ClientConfig {
     auth_config: S3ClientAuthConfig,
     throughput_target_gbps: f64,
-    part_size: usize,
+    read_part_size: usize,
+    write_part_size: usize,
     endpoint_config: EndpointConfig,
     user_agent: Option<UserAgent>,
     request_payer: Option<String>,
     bucket_owner: Option<String>,
     max_attempts: Option<NonZeroUsize>,
+    retry_budget: Option<NonZeroUsize>,
+    event_loop_threads: Option<NonZeroU16>,
+    max_buffered_upload_parts: Option<NonZeroUsize>,
 }
 
 impl Default for S3ClientConfig {
@@ -96,12 +105,16 @@ impl Default for S3ClientConfig {
         Self {
             auth_config: Default::default(),
             throughput_target_gbps: 10.0,
-            part_size: 8 * 1024 * 1024,
+            read_part_size: 8 * 1024 * 1024,
+            write_part_size: 8 * 1024 * 1024,
             endpoint_config: EndpointConfig::new("us-east-1"),
             user_agent: None,
             request_payer: None,
             bucket_owner: None,
             max_attempts: None,
+            retry_budget: None,
+            event_loop_threads: None,
+            max_buffered_upload_parts: None,
         }
     }
 }
@@ -121,7 +134,22 @@ impl S3ClientConfig {
     /// Set the part size for multi-part operations to S3 (both PUT and GET)
     #[must_use = "S3ClientConfig follows a builder pattern"]
     pub fn part_size(mut self, part_size: usize) -> Self {
-        self.part_size = part_size;
+        self.read_part_size = part_size;
+        self.write_part_size = part_size;
+        self
+    }
+
+    /// Set the part size for multi-part GET operations to S3
+    #[must_use = "S3ClientConfig follows a builder pattern"]
+    pub fn read_part_size(mut self, read_part_size: usize) -> Self {
+        self.read_part_size = read_part_size;
+        self
+    }
+
+    /// Set the part size for multi-part PUT operations to S3
+    #[must_use = "S3ClientConfig follows a builder pattern"]
+    pub fn write_part_size(mut self, write_part_size: usize) -> Self {
+        self.write_part_size = write_part_size;
         self
     }
 
@@ -167,6 +195,41 @@ impl S3ClientConfig {
         self.max_attempts = Some(max_attempts);
         self
     }
+
+    /// Set the size of the retry budget shared by all requests to the same endpoint.
+    ///
+    /// Each retry spends tokens from the budget (5 tokens, or 10 for timeouts) and each successful
+    /// request returns a token to it. Once the budget is exhausted, failed requests are no longer
+    /// retried, so widespread errors fail quickly instead of every request backing off through all
+    /// its attempts. Defaults to the CRT's default of 500 tokens.
+    #[must_use = "S3ClientConfig follows a builder pattern"]
+    pub fn retry_budget(mut self, retry_budget: NonZeroUsize) -> Self {
+        self.retry_budget = Some(retry_budget);
+        self
+    }
+
+    /// Set the number of threads in the client's event loop group.
+    ///
+    /// These threads do all the client's network I/O, and also run any futures spawned onto the
+    /// client's event loop group. Defaults to the CRT's default of half the number of processors
+    /// on the host.
+    #[must_use = "S3ClientConfig follows a builder pattern"]
+    pub fn event_loop_threads(mut self, event_loop_threads: NonZeroU16) -> Self {
+        self.event_loop_threads = Some(event_loop_threads);
+        self
+    }
+
+    /// Set the maximum number of parts each PutObject request can hold in memory.
+    ///
+    /// The CRT uploads a part as soon as a part's worth of data has been written to the request,
+    /// but keeps accepting writes while earlier parts are still uploading. With this limit, writes
+    /// wait for parts to finish uploading once this many are in flight, so each request buffers at
+    /// most this many parts in memory. By default, the number of buffered parts is not limited.
+    #[must_use = "S3ClientConfig follows a builder pattern"]
+    pub fn max_buffered_upload_parts(mut self, max_buffered_upload_parts: NonZeroUsize) -> Self {
+        self.max_buffered_upload_parts = Some(max_buffered_upload_parts);
+        self
+    }
 }
 
 /// Authentication configuration for the CRT-based S3 client
@@ -179,8 +242,21 @@ pub enum S3ClientAuthConfig {
     NoSigning,
     /// Explicitly load the given profile name from the AWS CLI configuration file
     Profile(String),
+    /// Get role credentials from IAM Identity Center (SSO) using the SSO configuration of the given
+    /// profile in the AWS CLI configuration file, and the access token cached by `aws sso login`
+    Sso(String),
     /// Use a custom credentials provider
     Provider(CredentialsProvider),
+    /// Assume an IAM role with STS, using credentials from another auth configuration. Credentials
+    /// for the role are refreshed automatically before they expire.
+    AssumeRole {
+        /// ARN of the role to assume
+        role_arn: String,
+        /// Name for the role session, which appears in CloudTrail logs
+        session_name: String,
+        /// Where to get the credentials used to assume the role
+        source: Box<S3ClientAuthConfig>,
+    },
 }
 
 /// An S3 client that uses the [AWS Common Runtime (CRT)][crt] to make requests.
@@ -227,7 +303,9 @@ struct S3CrtClientInner {
     /// Here it will add the user agent prefix and s3 client information.
     user_agent_header: String,
     request_payer: Option<String>,
-    part_size: usize,
+    read_part_size: usize,
+    write_part_size: usize,
+    max_buffered_upload_parts: Option<NonZeroUsize>,
     bucket_owner: Option<String>,
     credentials_provider: Option<CredentialsProvider>,
     host_resolver: HostResolver,
@@ -237,7 +315,8 @@ impl S3CrtClientInner {
     fn new(config: S3ClientConfig) -> Result<Self, NewClientError> {
         let allocator = Allocator::default();
 
-        let mut event_loop_group = EventLoopGroup::new_default(&allocator, None, || {}).unwrap();
+        let event_loop_threads = config.event_loop_threads.map(NonZeroU16::get);
+        let mut event_loop_group = EventLoopGroup::new_default(&allocator, event_loop_threads, || {}).unwrap();
 
         let resolver_options = HostResolverDefaultOptions {
             max_entries: 8,
@@ -267,31 +346,14 @@ impl S3CrtClientInner {
             retry_strategy_options.backoff_retry_options.max_retries = max_attempts.saturating_sub(1);
             retry_strategy_options.backoff_retry_options.backoff_scale_factor = Duration::from_millis(500);
             retry_strategy_options.backoff_retry_options.jitter_mode = ExponentialBackoffJitterMode::Full;
+            if let Some(retry_budget) = config.retry_budget {
+                retry_strategy_options.initial_bucket_capacity = retry_budget.get();
+            }
             RetryStrategy::standard(&allocator, &retry_strategy_options).unwrap()
         };
 
         trace!("constructing client with auth config {:?}", config.auth_config);
-        let credentials_provider = match config.auth_config {
-            S3ClientAuthConfig::Default => {
-                let credentials_chain_default_options = CredentialsProviderChainDefaultOptions {
-                    bootstrap: &mut client_bootstrap,
-                };
-                CredentialsProvider::new_chain_default(&allocator, credentials_chain_default_options)
-                    .map_err(NewClientError::ProviderFailure)?
-            }
-            S3ClientAuthConfig::NoSigning => {
-                CredentialsProvider::new_anonymous(&allocator).map_err(NewClientError::ProviderFailure)?
-            }
-            S3ClientAuthConfig::Profile(profile_name) => {
-                let credentials_profile_options = CredentialsProviderProfileOptions {
-                    bootstrap: &mut client_bootstrap,
-                    profile_name_override: &profile_name,
-                };
-                CredentialsProvider::new_profile(&allocator, credentials_profile_options)
-                    .map_err(NewClientError::ProviderFailure)?
-            }
-            S3ClientAuthConfig::Provider(provider) => provider,
-        };
+        let credentials_provider = new_credentials_provider(&allocator, &mut client_bootstrap, config.auth_config)?;
 
         let endpoint_config = config.endpoint_config;
         client_config.region(endpoint_config.get_region());
@@ -311,12 +373,16 @@ impl S3CrtClientInner {
 
         client_config.throughput_target_gbps(config.throughput_target_gbps);
 
-        if !(5 * 1024 * 1024..=5 * 1024 * 1024 * 1024).contains(&config.part_size) {
-            return Err(NewClientError::InvalidConfiguration(
-                "part size must be at between 5MiB and 5GiB".into(),
-            ));
+        for part_size in [config.read_part_size, config.write_part_size] {
+            if !(5 * 1024 * 1024..=5 * 1024 * 1024 * 1024).contains(&part_size) {
+                return Err(NewClientError::InvalidConfiguration(
+                    "part size must be at between 5MiB and 5GiB".into(),
+                ));
+            }
         }
-        client_config.part_size(config.part_size);
+        // The client's part size applies to uploads, and GetObject requests override it with the
+        // read part size
+        client_config.part_size(config.write_part_size);
 
         let user_agent = config.user_agent.unwrap_or_else(|| UserAgent::new(None));
         let user_agent_header = user_agent.build();
@@ -331,7 +397,9 @@ impl S3CrtClientInner {
             next_request_counter: AtomicU64::new(0),
             user_agent_header,
             request_payer: config.request_payer,
-            part_size: config.part_size,
+            read_part_size: config.read_part_size,
+            write_part_size: config.write_part_size,
+            max_buffered_upload_parts: config.max_buffered_upload_parts,
             bucket_owner: config.bucket_owner,
             credentials_provider: Some(credentials_provider),
             host_resolver,
@@ -569,11 +637,9 @@ impl S3CrtClientInner {
                         // Try to parse request header out of the failure. We can't just use the
                         // telemetry callback because there might be multiple requests per meta
                         // request, but these headers are known to be from the failed request.
-                        let request_id = match &request_result.error_response_headers {
-                            Some(headers) => headers.get("x-amz-request-id").map(|s| s.value().to_string_lossy().to_string()).ok(),
-                            None => None,
-                        };
-                        let request_id = request_id.unwrap_or_else(|| "<unknown>".into());
+                        let request_ids = S3RequestIds::from_result(&request_result);
+                        let request_id = request_ids.as_ref().map_or("<unknown>", |ids| ids.request_id.as_str());
+                        let extended_request_id = request_ids.as_ref().and_then(|ids| ids.extended_request_id.as_deref()).unwrap_or("<unknown>");
 
                         let message = if request_result.is_canceled() {
                             "meta request canceled"
@@ -581,10 +647,10 @@ impl S3CrtClientInner {
                             "meta request failed"
                         };
                         if let Some(error) = &maybe_err {
-                            event!(log_level, ?duration, %request_id, ?error, message);
+                            event!(log_level, ?duration, %request_id, %extended_request_id, ?error, message);
                             debug!("meta request result: {:?}", request_result);
                         } else {
-                            event!(log_level, ?duration, %request_id, ?request_result, message);
+                            event!(log_level, ?duration, %request_id, %extended_request_id, ?request_result, message);
                         }
 
                         if request_result.is_canceled() {
@@ -784,6 +850,19 @@ impl S3Message {
     fn set_checksum_config(&mut self, checksum_config: Option<ChecksumConfig>) {
         self.checksum_config = checksum_config;
     }
+
+    /// Set the body of this message, and the matching `Content-Length` header.
+    fn set_body(
+        &mut self,
+        allocator: &Allocator,
+        body: impl Into<Box<[u8]>>,
+    ) -> Result<(), mountpoint_s3_crt::common::error::Error> {
+        let body = InputStream::new_from_bytes(allocator, body)?;
+        self.inner
+            .set_header(&Header::new("Content-Length", body.len().to_string()))?;
+        self.inner.set_body_stream(Some(body));
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -857,9 +936,9 @@ pub enum S3RequestError {
     #[error("Wrong region (expecting {0})")]
     IncorrectRegion(String),
 
-    /// Forbidden
+    /// Forbidden, with the error message from S3 and the IDs of the request that was denied
     #[error("Forbidden: {0}")]
-    Forbidden(String),
+    Forbidden(String, Option<S3RequestIds>),
 
     /// No signing credential is set for requests
     #[error("No signing credentials found")]
@@ -874,6 +953,51 @@ impl S3RequestError {
     fn construction_failure(inner: impl Into<ConstructionError>) -> Self {
         S3RequestError::ConstructionFailure(inner.into())
     }
+
+    /// The IDs S3 assigned to the request that failed, if it got a response from S3
+    pub fn request_ids(&self) -> Option<S3RequestIds> {
+        match self {
+            S3RequestError::ResponseError(request_result) => S3RequestIds::from_result(request_result),
+            S3RequestError::Forbidden(_, request_ids) => request_ids.clone(),
+            _ => None,
+        }
+    }
+}
+
+/// The IDs S3 assigns to a request. AWS Support needs them to investigate a failed request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S3RequestIds {
+    /// The request ID, from the `x-amz-request-id` response header
+    pub request_id: String,
+    /// The extended request ID (or host ID), from the `x-amz-id-2` response header, which some
+    /// endpoints don't return
+    pub extended_request_id: Option<String>,
+}
+
+impl S3RequestIds {
+    fn from_result(request_result: &MetaRequestResult) -> Option<Self> {
+        let headers = request_result.error_response_headers.as_ref()?;
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .ok()
+                .map(|header| header.value().to_string_lossy().into_owned())
+        };
+        Some(Self {
+            request_id: header("x-amz-request-id")?,
+            extended_request_id: header("x-amz-id-2"),
+        })
+    }
+}
+
+impl std::fmt::Display for S3RequestIds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "request ID {}", self.request_id)?;
+        if let Some(extended_request_id) = &self.extended_request_id {
+            write!(f, ", extended request ID {}", extended_request_id)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Error, Debug)]
@@ -925,22 +1049,93 @@ fn extract_range_header(headers: &Headers) -> Option<Range<u64>> {
     Some(start..end + 1)
 }
 
+/// Create the credentials provider for an auth configuration
+fn new_credentials_provider(
+    allocator: &Allocator,
+    client_bootstrap: &mut ClientBootstrap,
+    auth_config: S3ClientAuthConfig,
+) -> Result<CredentialsProvider, NewClientError> {
+    let provider = match auth_config {
+        S3ClientAuthConfig::Default => {
+            let credentials_chain_default_options = CredentialsProviderChainDefaultOptions {
+                bootstrap: client_bootstrap,
+            };
+            CredentialsProvider::new_chain_default(allocator, credentials_chain_default_options)
+        }
+        S3ClientAuthConfig::NoSigning => CredentialsProvider::new_anonymous(allocator),
+        S3ClientAuthConfig::Profile(profile_name) => {
+            let credentials_profile_options = CredentialsProviderProfileOptions {
+                bootstrap: client_bootstrap,
+                profile_name_override: &profile_name,
+            };
+            CredentialsProvider::new_profile(allocator, credentials_profile_options)
+        }
+        S3ClientAuthConfig::Sso(profile_name) => {
+            let tls_context = TlsContext::new_default_client(allocator).map_err(NewClientError::ProviderFailure)?;
+            let credentials_sso_options = CredentialsProviderSsoOptions {
+                bootstrap: client_bootstrap,
+                tls_context: &tls_context,
+                profile_name_override: Some(&profile_name),
+            };
+            CredentialsProvider::new_sso(allocator, credentials_sso_options)
+        }
+        S3ClientAuthConfig::Provider(provider) => Ok(provider),
+        S3ClientAuthConfig::AssumeRole {
+            role_arn,
+            session_name,
+            source,
+        } => {
+            let source_provider = new_credentials_provider(allocator, client_bootstrap, *source)?;
+            let tls_context = TlsContext::new_default_client(allocator).map_err(NewClientError::ProviderFailure)?;
+            let credentials_sts_options = CredentialsProviderStsOptions {
+                bootstrap: client_bootstrap,
+                tls_context: &tls_context,
+                source_provider: &source_provider,
+                role_arn: &role_arn,
+                session_name: &session_name,
+                duration_seconds: None,
+            };
+            CredentialsProvider::new_sts(allocator, credentials_sts_options)
+        }
+    };
+    provider.map_err(NewClientError::ProviderFailure)
+}
+
 /// Try to parse a modeled error out of a failing meta request
 fn try_parse_generic_error(request_result: &MetaRequestResult) -> Option<S3RequestError> {
     /// Look for a redirect header pointing to a different region for the bucket
     fn try_parse_redirect(request_result: &MetaRequestResult) -> Option<S3RequestError> {
-        let headers = request_result.error_response_headers.as_ref()?;
-        let region_header = headers.get("x-amz-bucket-region").ok()?;
-        let region = region_header.value().to_owned().into_string().ok()?;
+        let region = request_result
+            .error_response_headers
+            .as_ref()
+            .and_then(|headers| headers.get("x-amz-bucket-region").ok())
+            .and_then(|header| header.value().to_owned().into_string().ok())
+            .or_else(|| try_parse_region_from_body(request_result))?;
         Some(S3RequestError::IncorrectRegion(region))
     }
 
+    /// Requests signed for the wrong region don't always come back with the region header, but the
+    /// AuthorizationHeaderMalformed error names the expected region in its body
+    fn try_parse_region_from_body(request_result: &MetaRequestResult) -> Option<String> {
+        let body = request_result.error_response_body.as_ref()?;
+        let error_elem = xmltree::Element::parse(body.as_bytes()).ok()?;
+        let error_code = error_elem.get_child("Code")?.get_text()?;
+        if error_code != "AuthorizationHeaderMalformed" {
+            return None;
+        }
+        let region = error_elem.get_child("Region")?.get_text()?;
+        Some(region.into_owned())
+    }
+
     /// Look for access-related errors
     fn try_parse_forbidden(request_result: &MetaRequestResult) -> Option<S3RequestError> {
         let Some(body) = request_result.error_response_body.as_ref() else {
             // Header-only requests like HeadObject and HeadBucket can't give us a more detailed
             // error, so just trust the response code
-            return Some(S3RequestError::Forbidden("<no message>".to_owned()));
+            return Some(S3RequestError::Forbidden(
+                "<no message>".to_owned(),
+                S3RequestIds::from_result(request_result),
+            ));
         };
         let error_elem = xmltree::Element::parse(body.as_bytes()).ok()?;
         let error_code = error_elem.get_child("Code")?;
@@ -957,7 +1152,10 @@ fn try_parse_generic_error(request_result: &MetaRequestResult) -> Option<S3Reque
                 .get_child("Message")
                 .and_then(|e| e.get_text())
                 .unwrap_or(error_code_str);
-            Some(S3RequestError::Forbidden(message.into_owned()))
+            Some(S3RequestError::Forbidden(
+                message.into_owned(),
+                S3RequestIds::from_result(request_result),
+            ))
         } else {
             None
         }
@@ -1011,11 +1209,15 @@ impl ObjectClient for S3CrtClient {
     type PutObjectRequest = S3PutObjectRequest;
     type ClientError = S3RequestError;
 
-    fn part_size(&self) -> Option<usize> {
-        // TODO: the CRT does some clamping to a max size rather than just swallowing the part size
-        // we configured it with, so this might be wrong. Right now the only clamping is to the max
-        // S3 part size (5GiB), so this shouldn't affect the result.
-        Some(self.inner.part_size)
+    // TODO: the CRT does some clamping to a max size rather than just swallowing the part size we
+    // configured it with, so these might be wrong. Right now the only clamping is to the max S3
+    // part size (5GiB), so this shouldn't affect the result.
+    fn read_part_size(&self) -> Option<usize> {
+        Some(self.inner.read_part_size)
+    }
+
+    fn write_part_size(&self) -> Option<usize> {
+        Some(self.inner.write_part_size)
     }
 
     async fn delete_object(
@@ -1030,12 +1232,9 @@ impl ObjectClient for S3CrtClient {
         &self,
         bucket: &str,
         key: &str,
-        range: Option<Range<u64>>,
-        if_match: Option<ETag>,
-        // TODO: If more arguments are added to get object, make a request struct having those arguments
-        // along with bucket and key.
+        params: &GetObjectParams,
     ) -> ObjectClientResult<Self::GetObjectResult, GetObjectError, Self::ClientError> {
-        self.get_object(bucket, key, range, if_match)
+        self.get_object(bucket, key, params)
     }
 
     async fn list_objects(
@@ -1050,6 +1249,19 @@ impl ObjectClient for S3CrtClient {
             .await
     }
 
+    async fn list_object_versions(
+        &self,
+        bucket: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectsError, Self::ClientError> {
+        self.list_object_versions(bucket, key_marker, version_id_marker, delimiter, max_keys, prefix)
+            .await
+    }
+
     async fn head_object(
         &self,
         bucket: &str,
@@ -1078,6 +1290,83 @@ impl ObjectClient for S3CrtClient {
         self.get_object_attributes(bucket, key, max_parts, part_number_marker, object_attributes)
             .await
     }
+
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+        params: &CopyObjectParams,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, Self::ClientError> {
+        self.copy_object(source_bucket, source_key, destination_bucket, destination_key, params)
+            .await
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+    ) -> ObjectClientResult<CreateMultipartUploadResult, MultipartUploadError, Self::ClientError> {
+        self.create_multipart_upload(bucket, key, params).await
+    }
+
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        data: &[u8],
+    ) -> ObjectClientResult<UploadPartResult, MultipartUploadError, Self::ClientError> {
+        self.upload_part(bucket, key, upload_id, part_number, data).await
+    }
+
+    async fn upload_part_copy(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        source_bucket: &str,
+        source_key: &str,
+        params: &UploadPartCopyParams,
+    ) -> ObjectClientResult<UploadPartResult, MultipartUploadError, Self::ClientError> {
+        self.upload_part_copy(bucket, key, upload_id, part_number, source_bucket, source_key, params)
+            .await
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        parts: &[CompletedPart],
+    ) -> ObjectClientResult<PutObjectResult, MultipartUploadError, Self::ClientError> {
+        self.complete_multipart_upload(bucket, key, upload_id, parts).await
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, MultipartUploadError, Self::ClientError> {
+        self.abort_multipart_upload(bucket, key, upload_id).await
+    }
+
+    async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        key_marker: Option<&str>,
+        upload_id_marker: Option<&str>,
+        max_uploads: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, MultipartUploadError, Self::ClientError> {
+        self.list_multipart_uploads(bucket, key_marker, upload_id_marker, max_uploads, prefix)
+            .await
+    }
 }
 
 #[cfg(test)]
@@ -1092,10 +1381,11 @@ mod tests {
     #[test_case(4 * 1024 * 1024; "less than 5MiB")]
     #[test_case(6 * 1024 * 1024 * 1024; "greater than 5GiB")]
     fn client_new_fails_with_invalid_part_size(part_size: usize) {
-        let config = S3ClientConfig {
-            part_size,
-            ..Default::default()
-        };
+        let config = S3ClientConfig::default().part_size(part_size);
+        S3CrtClient::new(config).expect_err("creating a new client should fail");
+        let config = S3ClientConfig::default().read_part_size(part_size);
+        S3CrtClient::new(config).expect_err("creating a new client should fail");
+        let config = S3ClientConfig::default().write_part_size(part_size);
         S3CrtClient::new(config).expect_err("creating a new client should fail");
     }
 
@@ -1227,7 +1517,7 @@ mod tests {
         let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>AccessDenied</Code><Message>Access Denied</Message><RequestId>CM0R497NB0WAQ977</RequestId><HostId>w1TqUKGaIuNAIgzqm/L2azuzgEBINxTngWPbV1iH2IvpLsVCCTKHJTh4HsGp4JnggHqVkA+KN1MGqHDw1+WEuA==</HostId></Error>"#;
         let result = make_result(403, OsStr::from_bytes(&body[..]), None);
         let result = try_parse_generic_error(&result);
-        let Some(S3RequestError::Forbidden(message)) = result else {
+        let Some(S3RequestError::Forbidden(message, _)) = result else {
             panic!("wrong result, got: {:?}", result);
         };
         assert_eq!(message, "Access Denied");
@@ -1238,7 +1528,7 @@ mod tests {
         let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>InvalidToken</Code><Message>The provided token is malformed or otherwise invalid.</Message><Token-0>THEREALTOKENGOESHERE</Token-0><RequestId>CBFNVADDAZ8661HK</RequestId><HostId>rb5dpgYeIFxi8p5BzVK8s8wG/nQ4a7C5kMBp/KWIT4bvOUihugpssMTy7xS0mispbz6IIaX8W1g=</HostId></Error>"#;
         let result = make_result(400, OsStr::from_bytes(&body[..]), None);
         let result = try_parse_generic_error(&result);
-        let Some(S3RequestError::Forbidden(message)) = result else {
+        let Some(S3RequestError::Forbidden(message, _)) = result else {
             panic!("wrong result, got: {:?}", result);
         };
         assert_eq!(message, "The provided token is malformed or otherwise invalid.");
@@ -1249,7 +1539,7 @@ mod tests {
         let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>ExpiredToken</Code><Message>The provided token has expired.</Message><Token-0>THEREALTOKENGOESHERE</Token-0><RequestId>RFXW0E15XSRPJYSW</RequestId><HostId>djitP7S+g43JSzR4pMOJpOO3RYpQUOUsmD4AqhRe3v24+JB/c+vwOEZgI8A35KDUe1cqQ5yKHwg=</HostId></Error>"#;
         let result = make_result(400, OsStr::from_bytes(&body[..]), None);
         let result = try_parse_generic_error(&result);
-        let Some(S3RequestError::Forbidden(message)) = result else {
+        let Some(S3RequestError::Forbidden(message, _)) = result else {
             panic!("wrong result, got: {:?}", result);
         };
         assert_eq!(message, "The provided token has expired.");
@@ -1267,12 +1557,23 @@ mod tests {
         assert_eq!(region, "us-west-2");
     }
 
+    #[test]
+    fn parse_400_redirect_without_header() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>AuthorizationHeaderMalformed</Code><Message>The authorization header is malformed; the region \'us-east-1\' is wrong; expecting \'eu-west-1\'</Message><Region>eu-west-1</Region><RequestId>VR3NH4JF5F39GB66</RequestId><HostId>ZDzYFC1w0E5K34+ZCAnvh9ZiGaAhvx5COyZVYTUnKvSP/694xCiXmJ2AEGZd5T1Epy9vB4EOOjk=</HostId></Error>"#;
+        let result = make_result(400, OsStr::from_bytes(&body[..]), None);
+        let result = try_parse_generic_error(&result);
+        let Some(S3RequestError::IncorrectRegion(region)) = result else {
+            panic!("wrong result, got: {:?}", result);
+        };
+        assert_eq!(region, "eu-west-1");
+    }
+
     #[test]
     fn parse_403_signature_does_not_match() {
         let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>SignatureDoesNotMatch</Code><Message>The request signature we calculated does not match the signature you provided. Check your key and signing method.</Message><AWSAccessKeyId>ASIASMEXAMPLE0000000</AWSAccessKeyId><StringToSign>EXAMPLE</StringToSign><SignatureProvided>EXAMPLE</SignatureProvided><StringToSignBytes>EXAMPLE</StringToSignBytes><CanonicalRequest>EXAMPLE</CanonicalRequest><CanonicalRequestBytes>EXAMPLE</CanonicalRequestBytes><RequestId>A1F516XX5M8AATSQ</RequestId><HostId>qs9dULIp5ABM7U+H8nGfzKtMYTxvqxIVvOYZ8lEFBDyTF4Fe+876Y4bLptG4mb+PTZFyG4yaUjg=</HostId></Error>"#;
         let result = make_result(403, OsStr::from_bytes(&body[..]), None);
         let result = try_parse_generic_error(&result);
-        let Some(S3RequestError::Forbidden(message)) = result else {
+        let Some(S3RequestError::Forbidden(message, _)) = result else {
             panic!("wrong result, got: {:?}", result);
         };
         assert_eq!(message, "The request signature we calculated does not match the signature you provided. Check your key and signing method.");
@@ -1284,12 +1585,44 @@ mod tests {
         let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>NotARealError</Code><Message>This error is made up.</Message><RequestId>CM0R497NB0WAQ977</RequestId><HostId>w1TqUKGaIuNAIgzqm/L2azuzgEBINxTngWPbV1iH2IvpLsVCCTKHJTh4HsGp4JnggHqVkA+KN1MGqHDw1+WEuA==</HostId></Error>"#;
         let result = make_result(403, OsStr::from_bytes(&body[..]), None);
         let result = try_parse_generic_error(&result);
-        let Some(S3RequestError::Forbidden(message)) = result else {
+        let Some(S3RequestError::Forbidden(message, _)) = result else {
             panic!("wrong result, got: {:?}", result);
         };
         assert_eq!(message, "This error is made up.");
     }
 
+    #[test]
+    fn parse_403_request_ids() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>AccessDenied</Code><Message>Access Denied</Message><RequestId>CM0R497NB0WAQ977</RequestId><HostId>w1TqUKGaIuNAIgzqm/L2azuzgEBINxTngWPbV1iH2IvpLsVCCTKHJTh4HsGp4JnggHqVkA+KN1MGqHDw1+WEuA==</HostId></Error>"#;
+        let mut result = make_result(403, OsStr::from_bytes(&body[..]), None);
+        let mut headers = Headers::new(&Allocator::default()).unwrap();
+        headers
+            .add_header(&Header::new("x-amz-request-id", "CM0R497NB0WAQ977"))
+            .unwrap();
+        headers
+            .add_header(&Header::new(
+                "x-amz-id-2",
+                "w1TqUKGaIuNAIgzqm/L2azuzgEBINxTngWPbV1iH2IvpLsVCCTKHJTh4HsGp4JnggHqVkA+KN1MGqHDw1+WEuA==",
+            ))
+            .unwrap();
+        result.error_response_headers = Some(headers);
+
+        let expected = S3RequestIds {
+            request_id: "CM0R497NB0WAQ977".to_owned(),
+            extended_request_id: Some(
+                "w1TqUKGaIuNAIgzqm/L2azuzgEBINxTngWPbV1iH2IvpLsVCCTKHJTh4HsGp4JnggHqVkA+KN1MGqHDw1+WEuA==".to_owned(),
+            ),
+        };
+        let error = try_parse_generic_error(&result).expect("should parse an error");
+        assert!(matches!(error, S3RequestError::Forbidden(..)));
+        assert_eq!(error.request_ids(), Some(expected.clone()));
+        assert_eq!(S3RequestError::ResponseError(result).request_ids(), Some(expected));
+
+        // Without a response, there are no IDs
+        let result = make_result(403, OsStr::from_bytes(&body[..]), None);
+        assert_eq!(try_parse_generic_error(&result).unwrap().request_ids(), None);
+    }
+
     fn make_crt_error_result(response_status: i32, crt_error: Error) -> MetaRequestResult {
         MetaRequestResult {
             response_status,
@@ -0,0 +1,150 @@
+use std::ops::Deref;
+use std::os::unix::prelude::OsStrExt;
+
+use mountpoint_s3_crt::http::request_response::Header;
+use mountpoint_s3_crt::s3::client::{MetaRequestResult, MetaRequestType};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::object_client::{CopyObjectError, CopyObjectParams, CopyObjectResult, ObjectClientResult};
+use crate::s3_crt_client::{S3CrtClient, S3RequestError};
+
+pub(super) const COPY_SOURCE_HEADER_NAME: &str = "x-amz-copy-source";
+const SSE_TYPE_HEADER_NAME: &str = "x-amz-server-side-encryption";
+const SSE_KEY_ID_HEADER_NAME: &str = "x-amz-server-side-encryption-aws-kms-key-id";
+
+/// The copy source is URL-encoded like a request path, so '/' is considered a safe character.
+const URLENCODE_COPY_SOURCE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'/');
+
+impl S3CrtClient {
+    /// Create and begin a new CopyObject request.
+    pub(super) async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+        params: &CopyObjectParams,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, S3RequestError> {
+        let span = request_span!(
+            self.inner,
+            "copy_object",
+            source_bucket,
+            source_key,
+            destination_bucket,
+            destination_key
+        );
+
+        // Scope the endpoint, message, etc. since otherwise rustc thinks we use Message across the await.
+        let request = {
+            let mut message = self
+                .inner
+                .new_request_template("PUT", destination_bucket)
+                .map_err(S3RequestError::construction_failure)?;
+            message
+                .set_request_path(format!("/{destination_key}"))
+                .map_err(S3RequestError::construction_failure)?;
+
+            message
+                .set_header(&Header::new(
+                    COPY_SOURCE_HEADER_NAME,
+                    copy_source(source_bucket, source_key),
+                ))
+                .map_err(S3RequestError::construction_failure)?;
+
+            if let Some(storage_class) = params.storage_class.as_ref() {
+                message
+                    .set_header(&Header::new("x-amz-storage-class", storage_class))
+                    .map_err(S3RequestError::construction_failure)?;
+            }
+            if let Some(sse) = params.server_side_encryption.as_ref() {
+                message
+                    .set_header(&Header::new(SSE_TYPE_HEADER_NAME, sse))
+                    .map_err(S3RequestError::construction_failure)?;
+            }
+            if let Some(key_id) = params.ssekms_key_id.as_ref() {
+                message
+                    .set_header(&Header::new(SSE_KEY_ID_HEADER_NAME, key_id))
+                    .map_err(S3RequestError::construction_failure)?;
+            }
+
+            self.inner
+                .make_simple_http_request(message, MetaRequestType::CopyObject, span, parse_copy_object_error)?
+        };
+
+        let _body = request.await?;
+
+        Ok(CopyObjectResult {})
+    }
+}
+
+/// Build the value of the `x-amz-copy-source` header for the given source object.
+pub(super) fn copy_source(source_bucket: &str, source_key: &str) -> String {
+    format!(
+        "{}/{}",
+        utf8_percent_encode(source_bucket, URLENCODE_COPY_SOURCE),
+        utf8_percent_encode(source_key, URLENCODE_COPY_SOURCE)
+    )
+}
+
+fn parse_copy_object_error(result: &MetaRequestResult) -> Option<CopyObjectError> {
+    match result.response_status {
+        // The CRT may look up the source object with a HeadObject request, which has no error body
+        404 => {
+            let Some(body) = result.error_response_body.as_ref() else {
+                return Some(CopyObjectError::NotFound);
+            };
+            let root = xmltree::Element::parse(body.as_bytes()).ok()?;
+            let error_code = root.get_child("Code")?;
+            let error_str = error_code.get_text()?;
+            match error_str.deref() {
+                "NoSuchBucket" | "NoSuchKey" => Some(CopyObjectError::NotFound),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{OsStr, OsString};
+
+    use super::*;
+
+    fn make_result(response_status: i32, body: Option<impl Into<OsString>>) -> MetaRequestResult {
+        MetaRequestResult {
+            response_status,
+            crt_error: 1i32.into(),
+            error_response_headers: None,
+            error_response_body: body.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn parse_404_no_such_key() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>NoSuchKey</Code><Message>The specified key does not exist.</Message><Key>not-a-key</Key><RequestId>NN7MP8XHFQXJ6BQK</RequestId><HostId>m6Jd0vD9SYsdmyyB2LlbbyFqHBvrNTGQ2HhtLHJAv4QTJ1G6nO7Klz1cmN+Pje6b1Q9GYsTx1ZI=</HostId></Error>"#;
+        let result = make_result(404, Some(OsStr::from_bytes(&body[..])));
+        let result = parse_copy_object_error(&result);
+        assert_eq!(result, Some(CopyObjectError::NotFound));
+    }
+
+    #[test]
+    fn parse_404_no_body() {
+        let result = make_result(404, None::<OsString>);
+        let result = parse_copy_object_error(&result);
+        assert_eq!(result, Some(CopyObjectError::NotFound));
+    }
+
+    #[test]
+    fn parse_403_access_denied() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>AccessDenied</Code><Message>Access Denied</Message><RequestId>CM0Q9QXGK6XWNSV7</RequestId><HostId>ZmRsOQt3eEC3IzNxeLfSbEJq0thAvnVXXqh2K8n80Ecb0vgRJbRVCD1W9wZ0R9DTMfi8J1FomSA=</HostId></Error>"#;
+        let result = make_result(403, Some(OsStr::from_bytes(&body[..])));
+        let result = parse_copy_object_error(&result);
+        assert_eq!(result, None);
+    }
+}
@@ -1,19 +1,23 @@
 use std::future::Future;
 use std::ops::Deref;
-use std::ops::Range;
 use std::os::unix::prelude::OsStrExt;
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 
 use futures::channel::mpsc::UnboundedReceiver;
 use futures::Stream;
 use mountpoint_s3_crt::common::error::Error;
-use mountpoint_s3_crt::http::request_response::Header;
+use mountpoint_s3_crt::http::request_response::{Header, Headers};
 use mountpoint_s3_crt::s3::client::{MetaRequestResult, MetaRequestType};
 use pin_project::pin_project;
 
-use crate::object_client::{ETag, GetBodyPart, GetObjectError, ObjectClientError, ObjectClientResult};
-use crate::s3_crt_client::{S3CrtClient, S3HttpRequest, S3RequestError};
+use crate::object_client::{
+    Checksum, ChecksumMode, GetBodyPart, GetObjectError, GetObjectParams, GetObjectRequest, ObjectClientError,
+    ObjectClientResult,
+};
+use crate::s3_crt_client::put_object::try_get_header_value;
+use crate::s3_crt_client::{S3CrtClient, S3CrtClientInner, S3HttpRequest, S3RequestError};
 
 impl S3CrtClient {
     /// Create and begin a new GetObject request. The returned [GetObjectRequest] is a [Stream] of
@@ -22,10 +26,19 @@ impl S3CrtClient {
         &self,
         bucket: &str,
         key: &str,
-        range: Option<Range<u64>>,
-        if_match: Option<ETag>,
+        params: &GetObjectParams,
     ) -> Result<S3GetObjectRequest, ObjectClientError<GetObjectError, S3RequestError>> {
-        let span = request_span!(self.inner, "get_object", bucket, key, ?range, ?if_match);
+        let range = params.range.clone();
+        let span = request_span!(
+            self.inner,
+            "get_object",
+            bucket,
+            key,
+            ?range,
+            if_match = ?params.if_match,
+            version_id = ?params.version_id,
+            checksum_mode = ?params.checksum_mode
+        );
 
         let mut message = self
             .inner
@@ -37,13 +50,19 @@ impl S3CrtClient {
             .set_header(&Header::new("accept", "*/*"))
             .map_err(S3RequestError::construction_failure)?;
 
-        if let Some(etag) = if_match {
+        if let Some(etag) = &params.if_match {
             // Return the object only if its entity tag (ETag) is matched
             message
                 .set_header(&Header::new("If-Match", etag.as_str()))
                 .map_err(S3RequestError::construction_failure)?;
         }
 
+        if params.checksum_mode == Some(ChecksumMode::Enabled) {
+            message
+                .set_header(&Header::new("x-amz-checksum-mode", "ENABLED"))
+                .map_err(S3RequestError::construction_failure)?;
+        }
+
         // Only use the CRT auto-ranged-get machinery for requests larger than the part size, or
         // unknown lengths. This avoids the machinery's HeadObject requests for small/random
         // requests. For auto-ranged-gets, the CRT takes care of adjusting the offset returned to
@@ -57,7 +76,7 @@ impl S3CrtClient {
                 .map_err(S3RequestError::construction_failure)?;
 
             let length = range.end.saturating_sub(range.start);
-            if length >= self.inner.part_size as u64 {
+            if length >= self.inner.read_part_size as u64 {
                 (MetaRequestType::GetObject, 0)
             } else {
                 (MetaRequestType::Default, range.start)
@@ -67,17 +86,27 @@ impl S3CrtClient {
         };
 
         let key = format!("/{key}");
+        let mut query = Vec::new();
+        if let Some(version_id) = &params.version_id {
+            query.push(("versionId", version_id.as_str()));
+        }
         message
-            .set_request_path(key)
+            .set_request_path_and_query(key, query)
             .map_err(S3RequestError::construction_failure)?;
 
         let (sender, receiver) = futures::channel::mpsc::unbounded();
+        let checksum: Arc<Mutex<Option<Checksum>>> = Default::default();
+        let checksum_clone = checksum.clone();
 
-        let request = self.inner.make_meta_request(
-            message,
-            request_type,
+        let mut options = S3CrtClientInner::new_meta_request_options(message, request_type);
+        options.part_size(self.inner.read_part_size as u64);
+        let request = self.inner.make_meta_request_from_options(
+            options,
             span,
-            |_, _| (),
+            |_| {},
+            move |headers, _| {
+                *checksum_clone.lock().unwrap() = parse_checksum(headers);
+            },
             move |offset, data| {
                 let _ = sender.unbounded_send(Ok((range_start + offset, data.into())));
             },
@@ -94,6 +123,7 @@ impl S3CrtClient {
             request,
             finish_receiver: receiver,
             finished: false,
+            checksum,
         })
     }
 }
@@ -111,6 +141,16 @@ pub struct S3GetObjectRequest {
     #[pin]
     finish_receiver: UnboundedReceiver<Result<GetBodyPart, Error>>,
     finished: bool,
+    /// The checksum returned in the response headers, once they've been received
+    checksum: Arc<Mutex<Option<Checksum>>>,
+}
+
+impl GetObjectRequest for S3GetObjectRequest {
+    type ClientError = S3RequestError;
+
+    fn get_object_checksum(&self) -> Option<Checksum> {
+        self.checksum.lock().unwrap().clone()
+    }
 }
 
 impl Stream for S3GetObjectRequest {
@@ -141,6 +181,21 @@ impl Stream for S3GetObjectRequest {
     }
 }
 
+/// Parse the additional checksums from the headers of a GetObject response, if there are any
+fn parse_checksum(headers: &Headers) -> Option<Checksum> {
+    let checksum = Checksum {
+        checksum_crc32: try_get_header_value(headers, "x-amz-checksum-crc32"),
+        checksum_crc32c: try_get_header_value(headers, "x-amz-checksum-crc32c"),
+        checksum_sha1: try_get_header_value(headers, "x-amz-checksum-sha1"),
+        checksum_sha256: try_get_header_value(headers, "x-amz-checksum-sha256"),
+    };
+    let has_checksum = checksum.checksum_crc32.is_some()
+        || checksum.checksum_crc32c.is_some()
+        || checksum.checksum_sha1.is_some()
+        || checksum.checksum_sha256.is_some();
+    has_checksum.then_some(checksum)
+}
+
 fn parse_get_object_error(result: &MetaRequestResult) -> Option<GetObjectError> {
     match result.response_status {
         404 => {
@@ -163,6 +218,8 @@ fn parse_get_object_error(result: &MetaRequestResult) -> Option<GetObjectError>
 mod tests {
     use std::ffi::{OsStr, OsString};
 
+    use mountpoint_s3_crt::common::allocator::Allocator;
+
     use super::*;
 
     fn make_result(response_status: i32, body: impl Into<OsString>) -> MetaRequestResult {
@@ -197,4 +254,17 @@ mod tests {
         let result = parse_get_object_error(&result);
         assert_eq!(result, None);
     }
+
+    #[test]
+    fn parse_checksum_headers() {
+        let mut headers = Headers::new(&Allocator::default()).unwrap();
+        assert!(parse_checksum(&headers).is_none());
+
+        headers
+            .add_header(&Header::new("x-amz-checksum-crc32c", "AAAE0g=="))
+            .unwrap();
+        let checksum = parse_checksum(&headers).expect("should have a checksum");
+        assert_eq!(checksum.checksum_crc32c.as_deref(), Some("AAAE0g=="));
+        assert_eq!(checksum.checksum_sha256, None);
+    }
 }
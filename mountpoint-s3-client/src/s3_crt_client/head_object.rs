@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::ffi::OsString;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
@@ -53,6 +54,8 @@ fn get_optional_field(headers: &Headers, name: &str) -> Result<Option<String>, P
     })
 }
 
+const OBJECT_METADATA_HEADER_PREFIX: &str = "x-amz-meta-";
+
 lazy_static! {
     // Example: ongoing-request="true"
     static ref RESTORE_IN_PROGRESS_RE: Regex = Regex::new(r#"^ongoing-request="(?<ongoing>[^"]*)"$"#).unwrap();
@@ -85,6 +88,21 @@ impl HeadObjectResult {
         Ok(Some(RestoreStatus::Restored { expiry: expiry.into() }))
     }
 
+    fn parse_object_metadata(headers: &Headers) -> HashMap<String, String> {
+        let mut object_metadata = HashMap::new();
+        for (name, value) in headers.iter() {
+            let (Some(name), Some(value)) = (name.to_str(), value.to_str()) else {
+                continue;
+            };
+            // Header names are case-insensitive, and S3 returns user-defined metadata names in lowercase
+            let name = name.to_ascii_lowercase();
+            if let Some(key) = name.strip_prefix(OBJECT_METADATA_HEADER_PREFIX) {
+                object_metadata.insert(key.to_owned(), value.to_owned());
+            }
+        }
+        object_metadata
+    }
+
     fn parse_from_hdr(bucket: String, key: String, headers: &Headers) -> Result<Self, ParseError> {
         let last_modified = OffsetDateTime::parse(&get_field(headers, "Last-Modified")?, &Rfc2822)
             .map_err(|e| ParseError::OffsetDateTime(e, "LastModified".into()))?;
@@ -93,6 +111,7 @@ impl HeadObjectResult {
         let etag = get_field(headers, "Etag")?;
         let storage_class = get_optional_field(headers, "x-amz-storage-class")?;
         let restore_status = Self::parse_restore_status(headers)?;
+        let object_metadata = Self::parse_object_metadata(headers);
         let object = ObjectInfo {
             key,
             size,
@@ -101,7 +120,11 @@ impl HeadObjectResult {
             restore_status,
             etag,
         };
-        Ok(HeadObjectResult { bucket, object })
+        Ok(HeadObjectResult {
+            bucket,
+            object,
+            object_metadata,
+        })
     }
 }
 
@@ -236,4 +259,18 @@ mod tests {
         let restore_status = HeadObjectResult::parse_restore_status(&headers).expect("failed to parse headers");
         assert!(restore_status.is_none());
     }
+
+    #[test]
+    fn test_parse_object_metadata() {
+        let mut headers = Headers::new(&Allocator::default()).unwrap();
+        headers.add_header(&Header::new("x-amz-meta-foo", "bar")).unwrap();
+        headers.add_header(&Header::new("X-Amz-Meta-Baz", "qux")).unwrap();
+        headers
+            .add_header(&Header::new("x-amz-storage-class", "STANDARD"))
+            .unwrap();
+        let object_metadata = HeadObjectResult::parse_object_metadata(&headers);
+        assert_eq!(object_metadata.len(), 2);
+        assert_eq!(object_metadata.get("foo").map(String::as_str), Some("bar"));
+        assert_eq!(object_metadata.get("baz").map(String::as_str), Some("qux"));
+    }
 }
@@ -10,7 +10,8 @@ use time::OffsetDateTime;
 use tracing::error;
 
 use crate::object_client::{
-    ListObjectsError, ListObjectsResult, ObjectClientError, ObjectClientResult, ObjectInfo, RestoreStatus,
+    ListObjectVersionsResult, ListObjectsError, ListObjectsResult, ObjectClientError, ObjectClientResult, ObjectInfo,
+    ObjectVersion, RestoreStatus,
 };
 use crate::s3_crt_client::{S3CrtClient, S3RequestError};
 
@@ -96,6 +97,57 @@ fn parse_result_from_xml(element: &mut xmltree::Element) -> Result<ListObjectsRe
     })
 }
 
+fn parse_versions_result_from_bytes(bytes: &[u8]) -> Result<ListObjectVersionsResult, ParseError> {
+    parse_versions_result_from_xml(&xmltree::Element::parse(bytes)?)
+}
+
+fn parse_versions_result_from_xml(element: &xmltree::Element) -> Result<ListObjectVersionsResult, ParseError> {
+    let mut versions = Vec::new();
+    let mut common_prefixes = Vec::new();
+
+    // Versions and delete markers are interleaved in key and recency order, so walk the children in
+    // document order rather than collecting each kind separately.
+    for child in element.children.iter().filter_map(|node| node.as_element()) {
+        match child.name.as_str() {
+            "Version" => versions.push(ObjectVersion::Object {
+                info: parse_object_info_from_xml(child)?,
+                version_id: get_field(child, "VersionId")?,
+            }),
+            "DeleteMarker" => {
+                let last_modified = OffsetDateTime::parse(&get_field(child, "LastModified")?, &Rfc3339)
+                    .map_err(|e| ParseError::OffsetDateTime(e, "LastModified".to_string()))?;
+                versions.push(ObjectVersion::DeleteMarker {
+                    key: get_field(child, "Key")?,
+                    version_id: get_field(child, "VersionId")?,
+                    last_modified,
+                });
+            }
+            "CommonPrefixes" => common_prefixes.push(get_field(child, "Prefix")?),
+            _ => {}
+        }
+    }
+
+    let next_key_marker = element.get_child("NextKeyMarker").map(get_text).transpose()?;
+    let next_version_id_marker = element.get_child("NextVersionIdMarker").map(get_text).transpose()?;
+
+    let is_truncated = get_field(element, "IsTruncated")?;
+    let is_truncated = bool::from_str(&is_truncated).map_err(|e| ParseError::Bool(e, "IsTruncated".to_string()))?;
+
+    if is_truncated != next_key_marker.is_some() {
+        return Err(ParseError::InvalidResponse(
+            element.clone(),
+            "IsTruncated doesn't match NextKeyMarker".to_string(),
+        ));
+    }
+
+    Ok(ListObjectVersionsResult {
+        versions,
+        common_prefixes,
+        next_key_marker,
+        next_version_id_marker,
+    })
+}
+
 fn parse_restore_status(element: &xmltree::Element) -> Result<Option<RestoreStatus>, ParseError> {
     let Some(restore_status) = element.get_child("RestoreStatus") else {
         return Ok(None);
@@ -198,6 +250,61 @@ impl S3CrtClient {
     }
 }
 
+impl S3CrtClient {
+    pub(super) async fn list_object_versions(
+        &self,
+        bucket: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectsError, S3RequestError> {
+        // Scope the endpoint, message, etc. since otherwise rustc thinks we use Message across the await.
+        let body = {
+            let mut message = self
+                .inner
+                .new_request_template("GET", bucket)
+                .map_err(S3RequestError::construction_failure)?;
+            let max_keys = format!("{max_keys}");
+            let mut query = vec![
+                ("versions", ""),
+                ("delimiter", delimiter),
+                ("max-keys", &max_keys),
+                ("prefix", prefix),
+            ];
+            if let Some(key_marker) = key_marker {
+                query.push(("key-marker", key_marker));
+            }
+            if let Some(version_id_marker) = version_id_marker {
+                query.push(("version-id-marker", version_id_marker));
+            }
+
+            message
+                .set_request_path_and_query("/", query)
+                .map_err(S3RequestError::construction_failure)?;
+
+            let span = request_span!(
+                self.inner,
+                "list_object_versions",
+                bucket,
+                continued = key_marker.is_some(),
+                delimiter,
+                max_keys,
+                prefix
+            );
+
+            self.inner
+                .make_simple_http_request(message, MetaRequestType::Default, span, parse_list_objects_error)?
+        };
+
+        let body = body.await?;
+
+        parse_versions_result_from_bytes(&body)
+            .map_err(|e| ObjectClientError::ClientError(S3RequestError::InternalError(e.into())))
+    }
+}
+
 fn parse_list_objects_error(result: &MetaRequestResult) -> Option<ListObjectsError> {
     match result.response_status {
         404 => {
@@ -236,4 +343,36 @@ mod tests {
         let result = parse_list_objects_error(&result);
         assert_eq!(result, Some(ListObjectsError::NoSuchBucket));
     }
+
+    #[test]
+    fn parse_list_object_versions() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><ListVersionsResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Name>DOC-EXAMPLE-BUCKET</Name><Prefix></Prefix><KeyMarker></KeyMarker><VersionIdMarker></VersionIdMarker><NextKeyMarker>b.txt</NextKeyMarker><NextVersionIdMarker>3/L4kqtJl40Nr8X8gdRQBpUMLUo</NextVersionIdMarker><MaxKeys>3</MaxKeys><Delimiter>/</Delimiter><IsTruncated>true</IsTruncated><DeleteMarker><Key>a.txt</Key><VersionId>null</VersionId><IsLatest>true</IsLatest><LastModified>2023-01-02T00:00:00.000Z</LastModified></DeleteMarker><Version><Key>a.txt</Key><VersionId>QUpfdndhfd8438MNFDN93jdnJFkdmqnh893</VersionId><IsLatest>false</IsLatest><LastModified>2023-01-01T00:00:00.000Z</LastModified><ETag>&quot;fba9dede5f27731c9771645a39863328&quot;</ETag><Size>434234</Size><StorageClass>STANDARD</StorageClass></Version><Version><Key>b.txt</Key><VersionId>UIORUnfndfhnw89493jJFJ</VersionId><IsLatest>true</IsLatest><LastModified>2023-01-03T00:00:00.000Z</LastModified><ETag>&quot;fba9dede5f27731c9771645a39863328&quot;</ETag><Size>10</Size><StorageClass>STANDARD</StorageClass></Version><CommonPrefixes><Prefix>dir/</Prefix></CommonPrefixes></ListVersionsResult>"#;
+        let result = parse_versions_result_from_bytes(body).expect("valid response");
+
+        let versions = result
+            .versions
+            .iter()
+            .map(|version| {
+                (
+                    version.key(),
+                    version.version_id(),
+                    matches!(version, ObjectVersion::DeleteMarker { .. }),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            versions,
+            vec![
+                ("a.txt", "null", true),
+                ("a.txt", "QUpfdndhfd8438MNFDN93jdnJFkdmqnh893", false),
+                ("b.txt", "UIORUnfndfhnw89493jJFJ", false),
+            ]
+        );
+        assert_eq!(result.common_prefixes, vec!["dir/"]);
+        assert_eq!(result.next_key_marker.as_deref(), Some("b.txt"));
+        assert_eq!(
+            result.next_version_id_marker.as_deref(),
+            Some("3/L4kqtJl40Nr8X8gdRQBpUMLUo")
+        );
+    }
 }
@@ -0,0 +1,566 @@
+use std::ops::Deref;
+use std::os::unix::prelude::OsStrExt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use mountpoint_s3_crt::http::request_response::{Header, Headers};
+use mountpoint_s3_crt::s3::client::{MetaRequestResult, MetaRequestType};
+use thiserror::Error;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::object_client::{
+    AbortMultipartUploadResult, CompletedPart, CreateMultipartUploadResult, ETag, ListMultipartUploadsResult,
+    MultipartUploadError, MultipartUploadInfo, ObjectClientError, ObjectClientResult, PutObjectParams, PutObjectResult,
+    UploadPartCopyParams, UploadPartResult,
+};
+use crate::s3_crt_client::copy_object::{copy_source, COPY_SOURCE_HEADER_NAME};
+use crate::s3_crt_client::put_object::{
+    try_get_header_value, ETAG_HEADER_NAME, OBJECT_METADATA_HEADER_PREFIX, SSE_KEY_ID_HEADER_NAME, SSE_TYPE_HEADER_NAME,
+};
+use crate::s3_crt_client::{S3CrtClient, S3CrtClientInner, S3RequestError};
+
+const COPY_SOURCE_RANGE_HEADER_NAME: &str = "x-amz-copy-source-range";
+const COPY_SOURCE_IF_MATCH_HEADER_NAME: &str = "x-amz-copy-source-if-match";
+
+#[derive(Error, Debug)]
+enum ParseError {
+    #[error("XML parsing error: {0:?}")]
+    Xml(#[from] xmltree::ParseError),
+
+    #[error("Missing field {1} from XML element {0:?}")]
+    MissingField(xmltree::Element, String),
+
+    #[error("Missing header {0} from response")]
+    MissingHeader(String),
+
+    #[error("Request failed with {0}: {1}")]
+    ErrorResponse(String, String),
+
+    #[error("Failed to parse field {1} as bool: {0:?}")]
+    Bool(#[source] std::str::ParseBoolError, String),
+
+    #[error("Failed to parse field {1} as OffsetDateTime: {0:?}")]
+    OffsetDateTime(#[source] time::error::Parse, String),
+}
+
+fn parse_error(err: ParseError) -> ObjectClientError<MultipartUploadError, S3RequestError> {
+    ObjectClientError::ClientError(S3RequestError::InternalError(err.into()))
+}
+
+/// Get the text out of a child node, with the right error type.
+fn get_field(element: &xmltree::Element, name: &str) -> Result<String, ParseError> {
+    element
+        .get_child(name)
+        .and_then(|child| child.get_text())
+        .map(|text| text.to_string())
+        .ok_or_else(|| ParseError::MissingField(element.clone(), name.to_string()))
+}
+
+impl S3CrtClient {
+    /// Create and begin a new CreateMultipartUpload request.
+    pub(super) async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+    ) -> ObjectClientResult<CreateMultipartUploadResult, MultipartUploadError, S3RequestError> {
+        let span = request_span!(self.inner, "create_multipart_upload", bucket, key);
+
+        // Scope the endpoint, message, etc. since otherwise rustc thinks we use Message across the await.
+        let request = {
+            let mut message = self
+                .inner
+                .new_request_template("POST", bucket)
+                .map_err(S3RequestError::construction_failure)?;
+            message
+                .set_request_path_and_query(format!("/{key}"), [("uploads", "")])
+                .map_err(S3RequestError::construction_failure)?;
+
+            if let Some(storage_class) = params.storage_class.as_ref() {
+                message
+                    .set_header(&Header::new("x-amz-storage-class", storage_class))
+                    .map_err(S3RequestError::construction_failure)?;
+            }
+            if let Some(sse) = params.server_side_encryption.as_ref() {
+                message
+                    .set_header(&Header::new(SSE_TYPE_HEADER_NAME, sse))
+                    .map_err(S3RequestError::construction_failure)?;
+            }
+            if let Some(key_id) = params.ssekms_key_id.as_ref() {
+                message
+                    .set_header(&Header::new(SSE_KEY_ID_HEADER_NAME, key_id))
+                    .map_err(S3RequestError::construction_failure)?;
+            }
+            for (name, value) in &params.object_metadata {
+                message
+                    .set_header(&Header::new(format!("{OBJECT_METADATA_HEADER_PREFIX}{name}"), value))
+                    .map_err(S3RequestError::construction_failure)?;
+            }
+
+            self.inner.make_simple_http_request(
+                message,
+                MetaRequestType::Default,
+                span,
+                parse_multipart_upload_error,
+            )?
+        };
+
+        let body = request.await?;
+        let root = parse_response_body(&body).map_err(parse_error)?;
+        let upload_id = get_field(&root, "UploadId").map_err(parse_error)?;
+
+        Ok(CreateMultipartUploadResult { upload_id })
+    }
+
+    /// Create and begin a new UploadPart request.
+    pub(super) async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        data: &[u8],
+    ) -> ObjectClientResult<UploadPartResult, MultipartUploadError, S3RequestError> {
+        let span = request_span!(self.inner, "upload_part", bucket, key, part_number, size = data.len());
+
+        let response_headers: Arc<Mutex<Option<Headers>>> = Default::default();
+        let request = {
+            let mut message = self
+                .inner
+                .new_request_template("PUT", bucket)
+                .map_err(S3RequestError::construction_failure)?;
+            let part_number = part_number.to_string();
+            message
+                .set_request_path_and_query(
+                    format!("/{key}"),
+                    [("partNumber", part_number.as_str()), ("uploadId", upload_id)],
+                )
+                .map_err(S3RequestError::construction_failure)?;
+            message
+                .set_body(&self.inner.allocator, data)
+                .map_err(S3RequestError::construction_failure)?;
+
+            let response_headers_writer = response_headers.clone();
+            let on_headers = move |headers: &Headers, _: i32| {
+                *response_headers_writer.lock().unwrap() = Some(headers.clone());
+            };
+            let options = S3CrtClientInner::new_meta_request_options(message, MetaRequestType::Default);
+            self.inner.make_simple_http_request_from_options(
+                options,
+                span,
+                |_| {},
+                parse_multipart_upload_error,
+                on_headers,
+            )?
+        };
+
+        let _body = request.await?;
+
+        let etag = response_headers
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|headers| try_get_header_value(headers, ETAG_HEADER_NAME))
+            .ok_or_else(|| parse_error(ParseError::MissingHeader(ETAG_HEADER_NAME.to_string())))?;
+
+        Ok(UploadPartResult {
+            etag: ETag::from_str(&etag).expect("ETag parsing is infallible"),
+        })
+    }
+
+    /// Create and begin a new UploadPartCopy request.
+    pub(super) async fn upload_part_copy(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        source_bucket: &str,
+        source_key: &str,
+        params: &UploadPartCopyParams,
+    ) -> ObjectClientResult<UploadPartResult, MultipartUploadError, S3RequestError> {
+        let span = request_span!(
+            self.inner,
+            "upload_part_copy",
+            bucket,
+            key,
+            part_number,
+            source_bucket,
+            source_key,
+            range = ?params.range,
+            if_match = ?params.if_match
+        );
+
+        let request = {
+            let mut message = self
+                .inner
+                .new_request_template("PUT", bucket)
+                .map_err(S3RequestError::construction_failure)?;
+            let part_number = part_number.to_string();
+            message
+                .set_request_path_and_query(
+                    format!("/{key}"),
+                    [("partNumber", part_number.as_str()), ("uploadId", upload_id)],
+                )
+                .map_err(S3RequestError::construction_failure)?;
+            message
+                .set_header(&Header::new(
+                    COPY_SOURCE_HEADER_NAME,
+                    copy_source(source_bucket, source_key),
+                ))
+                .map_err(S3RequestError::construction_failure)?;
+            if let Some(range) = &params.range {
+                // Range header is inclusive on both ends
+                message
+                    .set_header(&Header::new(
+                        COPY_SOURCE_RANGE_HEADER_NAME,
+                        format!("bytes={}-{}", range.start, range.end.saturating_sub(1)),
+                    ))
+                    .map_err(S3RequestError::construction_failure)?;
+            }
+            if let Some(etag) = &params.if_match {
+                message
+                    .set_header(&Header::new(COPY_SOURCE_IF_MATCH_HEADER_NAME, etag.as_str()))
+                    .map_err(S3RequestError::construction_failure)?;
+            }
+
+            self.inner.make_simple_http_request(
+                message,
+                MetaRequestType::Default,
+                span,
+                parse_multipart_upload_error,
+            )?
+        };
+
+        let body = request.await?;
+        let root = parse_response_body(&body).map_err(parse_error)?;
+        let etag = get_field(&root, "ETag").map_err(parse_error)?;
+
+        Ok(UploadPartResult {
+            etag: ETag::from_str(&etag).expect("ETag parsing is infallible"),
+        })
+    }
+
+    /// Create and begin a new CompleteMultipartUpload request.
+    pub(super) async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        parts: &[CompletedPart],
+    ) -> ObjectClientResult<PutObjectResult, MultipartUploadError, S3RequestError> {
+        let span = request_span!(
+            self.inner,
+            "complete_multipart_upload",
+            bucket,
+            key,
+            num_parts = parts.len()
+        );
+
+        let response_headers: Arc<Mutex<Option<Headers>>> = Default::default();
+        let request = {
+            let mut message = self
+                .inner
+                .new_request_template("POST", bucket)
+                .map_err(S3RequestError::construction_failure)?;
+            message
+                .set_request_path_and_query(format!("/{key}"), [("uploadId", upload_id)])
+                .map_err(S3RequestError::construction_failure)?;
+            message
+                .set_body(
+                    &self.inner.allocator,
+                    complete_multipart_upload_body(parts).into_bytes(),
+                )
+                .map_err(S3RequestError::construction_failure)?;
+
+            let response_headers_writer = response_headers.clone();
+            let on_headers = move |headers: &Headers, _: i32| {
+                *response_headers_writer.lock().unwrap() = Some(headers.clone());
+            };
+            let options = S3CrtClientInner::new_meta_request_options(message, MetaRequestType::Default);
+            self.inner.make_simple_http_request_from_options(
+                options,
+                span,
+                |_| {},
+                parse_multipart_upload_error,
+                on_headers,
+            )?
+        };
+
+        let body = request.await?;
+        // CompleteMultipartUpload can fail after sending a 200 OK response, in which case the body
+        // holds an error instead of the result.
+        let root = parse_response_body(&body).map_err(parse_error)?;
+        if root.name == "Error" {
+            let code = get_field(&root, "Code").map_err(parse_error)?;
+            if code == "NoSuchUpload" {
+                return Err(ObjectClientError::ServiceError(MultipartUploadError::NoSuchUpload));
+            }
+            let message = get_field(&root, "Message").unwrap_or_default();
+            return Err(parse_error(ParseError::ErrorResponse(code, message)));
+        }
+
+        let etag = get_field(&root, "ETag").map_err(parse_error)?;
+        let response_headers = response_headers.lock().unwrap().take();
+        let header = |name| response_headers.as_ref().and_then(|h| try_get_header_value(h, name));
+        Ok(PutObjectResult {
+            etag: Some(ETag::from_str(&etag).expect("ETag parsing is infallible")),
+            sse_type: header(SSE_TYPE_HEADER_NAME),
+            sse_kms_key_id: header(SSE_KEY_ID_HEADER_NAME),
+        })
+    }
+
+    /// Create and begin a new AbortMultipartUpload request.
+    pub(super) async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, MultipartUploadError, S3RequestError> {
+        let span = request_span!(self.inner, "abort_multipart_upload", bucket, key);
+
+        let request = {
+            let mut message = self
+                .inner
+                .new_request_template("DELETE", bucket)
+                .map_err(S3RequestError::construction_failure)?;
+            message
+                .set_request_path_and_query(format!("/{key}"), [("uploadId", upload_id)])
+                .map_err(S3RequestError::construction_failure)?;
+
+            self.inner.make_simple_http_request(
+                message,
+                MetaRequestType::Default,
+                span,
+                parse_multipart_upload_error,
+            )?
+        };
+
+        let _body = request.await?;
+
+        Ok(AbortMultipartUploadResult {})
+    }
+
+    /// Create and begin a new ListMultipartUploads request.
+    pub(super) async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        key_marker: Option<&str>,
+        upload_id_marker: Option<&str>,
+        max_uploads: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, MultipartUploadError, S3RequestError> {
+        let span = request_span!(
+            self.inner,
+            "list_multipart_uploads",
+            bucket,
+            continued = key_marker.is_some(),
+            max_uploads,
+            prefix
+        );
+
+        let request = {
+            let mut message = self
+                .inner
+                .new_request_template("GET", bucket)
+                .map_err(S3RequestError::construction_failure)?;
+            let max_uploads = format!("{max_uploads}");
+            let mut query = vec![("uploads", ""), ("max-uploads", &max_uploads), ("prefix", prefix)];
+            if let Some(key_marker) = key_marker {
+                query.push(("key-marker", key_marker));
+            }
+            if let Some(upload_id_marker) = upload_id_marker {
+                query.push(("upload-id-marker", upload_id_marker));
+            }
+            message
+                .set_request_path_and_query("/", query)
+                .map_err(S3RequestError::construction_failure)?;
+
+            self.inner.make_simple_http_request(
+                message,
+                MetaRequestType::Default,
+                span,
+                parse_multipart_upload_error,
+            )?
+        };
+
+        let body = request.await?;
+        let root = parse_response_body(&body).map_err(parse_error)?;
+        parse_list_multipart_uploads_result(&root).map_err(parse_error)
+    }
+}
+
+fn parse_list_multipart_uploads_result(root: &xmltree::Element) -> Result<ListMultipartUploadsResult, ParseError> {
+    let mut uploads = Vec::new();
+    for child in root.children.iter().filter_map(|node| node.as_element()) {
+        if child.name == "Upload" {
+            let initiated = OffsetDateTime::parse(&get_field(child, "Initiated")?, &Rfc3339)
+                .map_err(|e| ParseError::OffsetDateTime(e, "Initiated".to_string()))?;
+            uploads.push(MultipartUploadInfo {
+                key: get_field(child, "Key")?,
+                upload_id: get_field(child, "UploadId")?,
+                initiated,
+            });
+        }
+    }
+
+    let is_truncated = get_field(root, "IsTruncated")?;
+    let is_truncated = bool::from_str(&is_truncated).map_err(|e| ParseError::Bool(e, "IsTruncated".to_string()))?;
+    // S3 returns empty markers on the last page, so only keep them if there are more results.
+    let (next_key_marker, next_upload_id_marker) = if is_truncated {
+        let next_key_marker = get_field(root, "NextKeyMarker")?;
+        let next_upload_id_marker = get_field(root, "NextUploadIdMarker").ok();
+        (Some(next_key_marker), next_upload_id_marker)
+    } else {
+        (None, None)
+    };
+
+    Ok(ListMultipartUploadsResult {
+        uploads,
+        next_key_marker,
+        next_upload_id_marker,
+    })
+}
+
+fn parse_response_body(body: &[u8]) -> Result<xmltree::Element, ParseError> {
+    Ok(xmltree::Element::parse(body)?)
+}
+
+/// Build the XML body of a CompleteMultipartUpload request.
+fn complete_multipart_upload_body(parts: &[CompletedPart]) -> String {
+    fn escape(s: &str) -> String {
+        s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+    }
+
+    let mut body = String::from("<CompleteMultipartUpload>");
+    for part in parts {
+        body.push_str(&format!(
+            "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+            part.part_number,
+            escape(part.etag.as_str())
+        ));
+    }
+    body.push_str("</CompleteMultipartUpload>");
+    body
+}
+
+fn parse_multipart_upload_error(result: &MetaRequestResult) -> Option<MultipartUploadError> {
+    match result.response_status {
+        404 => {
+            let body = result.error_response_body.as_ref()?;
+            let root = xmltree::Element::parse(body.as_bytes()).ok()?;
+            let error_code = root.get_child("Code")?;
+            let error_str = error_code.get_text()?;
+            match error_str.deref() {
+                "NoSuchBucket" => Some(MultipartUploadError::NoSuchBucket),
+                "NoSuchUpload" => Some(MultipartUploadError::NoSuchUpload),
+                "NoSuchKey" => Some(MultipartUploadError::NoSuchKey),
+                _ => None,
+            }
+        }
+        412 => Some(MultipartUploadError::PreconditionFailed),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{OsStr, OsString};
+
+    use super::*;
+
+    fn make_result(response_status: i32, body: impl Into<OsString>) -> MetaRequestResult {
+        MetaRequestResult {
+            response_status,
+            crt_error: 1i32.into(),
+            error_response_headers: None,
+            error_response_body: Some(body.into()),
+        }
+    }
+
+    #[test]
+    fn parse_404_no_such_upload() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>NoSuchUpload</Code><Message>The specified upload does not exist. The upload ID may be invalid, or the upload may have been aborted or completed.</Message><UploadId>not-an-upload</UploadId><RequestId>4442587FB7D0A2F9</RequestId><HostId>F3ZbCIH0OE9DHwvSM+BWOsZB/CO1gEwShsCU7yvdOGyB6hQvXpVGvh1/+X6JrvjXOGyzgm6b5nY=</HostId></Error>"#;
+        let result = make_result(404, OsStr::from_bytes(&body[..]));
+        let result = parse_multipart_upload_error(&result);
+        assert_eq!(result, Some(MultipartUploadError::NoSuchUpload));
+    }
+
+    #[test]
+    fn parse_create_multipart_upload_result() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><InitiateMultipartUploadResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Bucket>test-bucket</Bucket><Key>hello.txt</Key><UploadId>VXBsb2FkIElEIGZvciA2aWWpbmcncyBteS1tb3ZpZS5tMnRzIHVwbG9hZA</UploadId></InitiateMultipartUploadResult>"#;
+        let root = parse_response_body(body).unwrap();
+        assert_eq!(
+            get_field(&root, "UploadId").unwrap(),
+            "VXBsb2FkIElEIGZvciA2aWWpbmcncyBteS1tb3ZpZS5tMnRzIHVwbG9hZA"
+        );
+    }
+
+    #[test]
+    fn parse_list_multipart_uploads() {
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><ListMultipartUploadsResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Bucket>DOC-EXAMPLE-BUCKET</Bucket><KeyMarker></KeyMarker><UploadIdMarker></UploadIdMarker><NextKeyMarker>my-movie.m2ts</NextKeyMarker><NextUploadIdMarker>YW55IGlkZWEgd2h5IGVsdmluZydzIHVwbG9hZCBmYWlsZWQ</NextUploadIdMarker><MaxUploads>2</MaxUploads><IsTruncated>true</IsTruncated><Upload><Key>my-divisor</Key><UploadId>XMgbGlrZSBlbHZpbmcncyBub3QgaGF2aW5nIG11Y2ggbHVjaw</UploadId><StorageClass>STANDARD</StorageClass><Initiated>2010-11-10T20:48:33.000Z</Initiated></Upload><Upload><Key>my-movie.m2ts</Key><UploadId>YW55IGlkZWEgd2h5IGVsdmluZydzIHVwbG9hZCBmYWlsZWQ</UploadId><StorageClass>STANDARD</StorageClass><Initiated>2010-11-10T20:48:34.000Z</Initiated></Upload></ListMultipartUploadsResult>"#;
+        let root = parse_response_body(body).unwrap();
+        let result = parse_list_multipart_uploads_result(&root).unwrap();
+        let uploads = result
+            .uploads
+            .iter()
+            .map(|upload| {
+                (
+                    upload.key.as_str(),
+                    upload.upload_id.as_str(),
+                    upload.initiated.unix_timestamp(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            uploads,
+            vec![
+                (
+                    "my-divisor",
+                    "XMgbGlrZSBlbHZpbmcncyBub3QgaGF2aW5nIG11Y2ggbHVjaw",
+                    1289422113
+                ),
+                (
+                    "my-movie.m2ts",
+                    "YW55IGlkZWEgd2h5IGVsdmluZydzIHVwbG9hZCBmYWlsZWQ",
+                    1289422114
+                ),
+            ]
+        );
+        assert_eq!(result.next_key_marker.as_deref(), Some("my-movie.m2ts"));
+        assert_eq!(
+            result.next_upload_id_marker.as_deref(),
+            Some("YW55IGlkZWEgd2h5IGVsdmluZydzIHVwbG9hZCBmYWlsZWQ")
+        );
+
+        let body = br#"<?xml version="1.0" encoding="UTF-8"?><ListMultipartUploadsResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Bucket>DOC-EXAMPLE-BUCKET</Bucket><KeyMarker></KeyMarker><UploadIdMarker></UploadIdMarker><NextKeyMarker></NextKeyMarker><NextUploadIdMarker></NextUploadIdMarker><MaxUploads>1000</MaxUploads><IsTruncated>false</IsTruncated></ListMultipartUploadsResult>"#;
+        let root = parse_response_body(body).unwrap();
+        let result = parse_list_multipart_uploads_result(&root).unwrap();
+        assert!(result.uploads.is_empty());
+        assert_eq!(result.next_key_marker, None);
+        assert_eq!(result.next_upload_id_marker, None);
+    }
+
+    #[test]
+    fn build_complete_multipart_upload_body() {
+        let parts = [
+            CompletedPart {
+                part_number: 1,
+                etag: ETag::from_str("\"a54357aff0632cce46d942af68356b38\"").unwrap(),
+            },
+            CompletedPart {
+                part_number: 2,
+                etag: ETag::from_str("\"0c78aef83f66abc1fa1e8477f296d394\"").unwrap(),
+            },
+        ];
+        let body = complete_multipart_upload_body(&parts);
+        assert_eq!(
+            body,
+            "<CompleteMultipartUpload>\
+             <Part><PartNumber>1</PartNumber><ETag>\"a54357aff0632cce46d942af68356b38\"</ETag></Part>\
+             <Part><PartNumber>2</PartNumber><ETag>\"0c78aef83f66abc1fa1e8477f296d394\"</ETag></Part>\
+             </CompleteMultipartUpload>"
+        );
+    }
+}
@@ -1,18 +1,24 @@
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
-use crate::object_client::{ObjectClientResult, PutObjectError, PutObjectParams, PutObjectRequest, PutObjectResult};
+use crate::object_client::{
+    ETag, ObjectClientResult, PutObjectError, PutObjectParams, PutObjectRequest, PutObjectResult,
+};
 use crate::s3_crt_client::{emit_throughput_metric, PutObjectTrailingChecksums, S3CrtClient, S3RequestError};
 use async_trait::async_trait;
-use futures::channel::oneshot;
+use futures::channel::{mpsc, oneshot};
+use futures::StreamExt;
 use mountpoint_s3_crt::http::request_response::{Header, Headers};
 use mountpoint_s3_crt::s3::client::{ChecksumConfig, MetaRequestType, RequestType, UploadReview};
 use tracing::error;
 
 use super::{S3CrtClientInner, S3HttpRequest};
 
-const SSE_TYPE_HEADER_NAME: &str = "x-amz-server-side-encryption";
-const SSE_KEY_ID_HEADER_NAME: &str = "x-amz-server-side-encryption-aws-kms-key-id";
+pub(super) const ETAG_HEADER_NAME: &str = "ETag";
+pub(super) const SSE_TYPE_HEADER_NAME: &str = "x-amz-server-side-encryption";
+pub(super) const SSE_KEY_ID_HEADER_NAME: &str = "x-amz-server-side-encryption-aws-kms-key-id";
+pub(super) const OBJECT_METADATA_HEADER_PREFIX: &str = "x-amz-meta-";
 
 impl S3CrtClient {
     pub(super) async fn put_object(
@@ -57,6 +63,11 @@ impl S3CrtClient {
                 .set_header(&Header::new(SSE_KEY_ID_HEADER_NAME, key_id))
                 .map_err(S3RequestError::construction_failure)?;
         }
+        for (name, value) in &params.object_metadata {
+            message
+                .set_header(&Header::new(format!("{OBJECT_METADATA_HEADER_PREFIX}{name}"), value))
+                .map_err(S3RequestError::construction_failure)?;
+        }
         // Variable `response_headers` will be accessed from different threads: from CRT thread which executes `on_headers` callback
         // and from our thread which executes `review_and_complete`. Callback `on_headers` is guaranteed to finish before this
         // variable is accessed in `review_and_complete` (see `S3HttpRequest::poll` implementation).
@@ -77,6 +88,12 @@ impl S3CrtClient {
         let on_mpu_created_sender = Arc::new(Mutex::new(Some(mpu_created_sender)));
         let on_error_sender = on_mpu_created_sender.clone();
 
+        // Each UploadPart request that completes successfully frees the memory of one buffered part.
+        // The channel is closed if the meta-request fails, so writes waiting for parts to upload can
+        // return the failure instead of waiting forever.
+        let (uploaded_parts_sender, uploaded_parts) = mpsc::unbounded();
+        let on_error_uploaded_parts_sender = uploaded_parts_sender.clone();
+
         let body = self.inner.make_simple_http_request_from_options(
             options,
             span,
@@ -87,8 +104,12 @@ impl S3CrtClient {
                         _ = sender.send(Ok(()));
                     }
                 }
+                if metrics.request_type() == RequestType::UploadPart && !metrics.error().is_err() {
+                    _ = uploaded_parts_sender.unbounded_send(());
+                }
             },
             move |result| {
+                on_error_uploaded_parts_sender.close_channel();
                 // Signal that the meta-request failed (unless a CreateMultipartUpload had already completed successfully).
                 if let Some(sender) = on_error_sender.lock().unwrap().take() {
                     _ = sender.send(Err(result.crt_error.into()));
@@ -105,6 +126,10 @@ impl S3CrtClient {
             total_bytes: 0,
             response_headers,
             pending_create_mpu: Some(mpu_created),
+            part_size: self.inner.write_part_size as u64,
+            max_buffered_parts: self.inner.max_buffered_upload_parts.map(|max| max.get() as u64),
+            uploaded_parts,
+            uploaded_parts_count: 0,
         })
     }
 }
@@ -157,9 +182,44 @@ pub struct S3PutObjectRequest {
     /// Signal indicating that CreateMultipartUpload completed successfully, or that the MPU failed.
     /// Set to [None] once awaited on the first write, meaning the MPU was already created or failed.
     pending_create_mpu: Option<oneshot::Receiver<Result<(), S3RequestError>>>,
+    /// Size of the parts the CRT splits the upload into
+    part_size: u64,
+    /// Maximum number of parts that can be written but not yet uploaded, if limited
+    max_buffered_parts: Option<u64>,
+    /// Signals each part that finished uploading. Closed if the meta-request fails.
+    uploaded_parts: mpsc::UnboundedReceiver<()>,
+    /// Number of signals received from [Self::uploaded_parts] so far
+    uploaded_parts_count: u64,
 }
 
-fn try_get_header_value(headers: &Headers, key: &str) -> Option<String> {
+impl S3PutObjectRequest {
+    /// Write `slice` to the meta-request without any limit on buffered parts.
+    async fn write_to_meta_request(&mut self, slice: &[u8]) -> ObjectClientResult<(), PutObjectError, S3RequestError> {
+        // Write will fail if the request has already finished (because of an error).
+        self.body
+            .meta_request
+            .write(slice, false)
+            .await
+            .map_err(S3RequestError::CrtError)?;
+        self.total_bytes += slice.len() as u64;
+        Ok(())
+    }
+
+    /// Wait until fewer than `max_buffered_parts` complete parts are waiting to be uploaded, or
+    /// the meta-request has failed.
+    async fn wait_for_buffered_parts(&mut self, max_buffered_parts: u64) {
+        let written_parts = self.total_bytes / self.part_size;
+        while written_parts.saturating_sub(self.uploaded_parts_count) >= max_buffered_parts {
+            if self.uploaded_parts.next().await.is_none() {
+                // The meta-request failed, so the next write will return its error.
+                return;
+            }
+            self.uploaded_parts_count += 1;
+        }
+    }
+}
+
+pub(super) fn try_get_header_value(headers: &Headers, key: &str) -> Option<String> {
     headers.get(key).ok()?.value().clone().into_string().ok()
 }
 
@@ -174,13 +234,23 @@ impl PutObjectRequest for S3PutObjectRequest {
             create_mpu.await.unwrap()?;
         }
 
-        // Write will fail if the request has already finished (because of an error).
-        self.body
-            .meta_request
-            .write(slice, false)
-            .await
-            .map_err(S3RequestError::CrtError)?;
-        self.total_bytes += slice.len() as u64;
+        let Some(max_buffered_parts) = self.max_buffered_parts else {
+            return self.write_to_meta_request(slice).await;
+        };
+
+        // Split the write at part boundaries, and before starting each new part, wait for earlier
+        // parts to finish uploading so that no more than `max_buffered_parts` are held in memory.
+        let mut remaining = slice;
+        while !remaining.is_empty() {
+            let offset_in_part = self.total_bytes % self.part_size;
+            if offset_in_part == 0 {
+                self.wait_for_buffered_parts(max_buffered_parts).await;
+            }
+            let len = remaining.len().min((self.part_size - offset_in_part) as usize);
+            let (chunk, rest) = remaining.split_at(len);
+            self.write_to_meta_request(chunk).await?;
+            remaining = rest;
+        }
         Ok(())
     }
 
@@ -214,6 +284,8 @@ impl PutObjectRequest for S3PutObjectRequest {
             .take()
             .expect("PUT response headers must be available at this point");
         Ok(PutObjectResult {
+            etag: try_get_header_value(&response_headers, ETAG_HEADER_NAME)
+                .map(|etag| ETag::from_str(&etag).expect("ETag parsing is infallible")),
             sse_type: try_get_header_value(&response_headers, SSE_TYPE_HEADER_NAME),
             sse_kms_key_id: try_get_header_value(&response_headers, SSE_KEY_ID_HEADER_NAME),
         })
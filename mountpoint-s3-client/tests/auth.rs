@@ -15,10 +15,13 @@ use futures::StreamExt;
 use mountpoint_s3_client::config::{EndpointConfig, S3ClientAuthConfig, S3ClientConfig};
 #[cfg(not(feature = "s3express_tests"))]
 use mountpoint_s3_client::error::ObjectClientError;
+use mountpoint_s3_client::types::GetObjectParams;
 #[cfg(not(feature = "s3express_tests"))]
 use mountpoint_s3_client::S3RequestError;
 use mountpoint_s3_client::{ObjectClient, S3CrtClient};
-use mountpoint_s3_crt::auth::credentials::{CredentialsProvider, CredentialsProviderStaticOptions};
+use mountpoint_s3_crt::auth::credentials::{
+    CredentialsProvider, CredentialsProviderStaticOptions, DelegateCredentials,
+};
 use mountpoint_s3_crt::common::allocator::Allocator;
 use rusty_fork::rusty_fork_test;
 use tempfile::NamedTempFile;
@@ -63,7 +66,7 @@ async fn test_static_provider() {
     let client = S3CrtClient::new(config).unwrap();
 
     let result = client
-        .get_object(&bucket, &key, None, None)
+        .get_object(&bucket, &key, &GetObjectParams::new())
         .await
         .expect("get_object should succeed");
     check_get_result(result, None, &body[..]).await;
@@ -83,7 +86,7 @@ async fn test_static_provider() {
     let client = S3CrtClient::new(config).unwrap();
 
     let mut request = client
-        .get_object(&bucket, &key, None, None)
+        .get_object(&bucket, &key, &GetObjectParams::new())
         .await
         .expect("get_object request should be sent");
 
@@ -94,6 +97,63 @@ async fn test_static_provider() {
         .expect_err("bogus credentials should not work");
 }
 
+/// Test creating a client with a delegate credentials provider
+#[tokio::test]
+async fn test_delegate_provider() {
+    let sdk_client = get_test_sdk_client().await;
+    let (bucket, prefix) = get_test_bucket_and_prefix("test_delegate_provider");
+
+    let key = format!("{prefix}/hello");
+    let body = b"hello world!";
+    sdk_client
+        .put_object()
+        .bucket(&bucket)
+        .key(&key)
+        .body(ByteStream::from(Bytes::from_static(body)))
+        .send()
+        .await
+        .unwrap();
+
+    let sdk_provider = DefaultCredentialsChain::builder()
+        .region(Region::new(get_test_region()))
+        .build()
+        .await;
+    let credentials = sdk_provider
+        .provide_credentials()
+        .await
+        .expect("credentials should be available");
+    let credentials = DelegateCredentials {
+        access_key_id: credentials.access_key_id().to_owned(),
+        secret_access_key: credentials.secret_access_key().to_owned(),
+        session_token: credentials.session_token().map(ToOwned::to_owned),
+        expiration: credentials.expiry(),
+    };
+
+    let provider = CredentialsProvider::new_delegate(&Allocator::default(), move || Some(credentials.clone())).unwrap();
+    let config = S3ClientConfig::new()
+        .auth_config(S3ClientAuthConfig::Provider(provider))
+        .endpoint_config(EndpointConfig::new(&get_test_region()));
+    let client = S3CrtClient::new(config).unwrap();
+
+    let result = client
+        .get_object(&bucket, &key, &GetObjectParams::new())
+        .await
+        .expect("get_object should succeed");
+    check_get_result(result, None, &body[..]).await;
+
+    // A delegate that has no credentials fails the request
+    let provider = CredentialsProvider::new_delegate(&Allocator::default(), || None).unwrap();
+    let config = S3ClientConfig::new()
+        .auth_config(S3ClientAuthConfig::Provider(provider))
+        .endpoint_config(EndpointConfig::new(&get_test_region()));
+    let client = S3CrtClient::new(config).unwrap();
+
+    let _error = client
+        .head_object(&bucket, &key)
+        .await
+        .expect_err("request without credentials should fail");
+}
+
 /// Test creating a client with the profile credentials provider
 ///
 /// This is complicated because CLI profiles are inherently global state, but we want to isolate the
@@ -152,7 +212,7 @@ async fn test_profile_provider_async() {
     let client = S3CrtClient::new(config).unwrap();
 
     let result = client
-        .get_object(&bucket, &key, None, None)
+        .get_object(&bucket, &key, &GetObjectParams::new())
         .await
         .expect("get_object should succeed");
     check_get_result(result, None, &body[..]).await;
@@ -168,7 +228,7 @@ async fn test_profile_provider_async() {
     let client = S3CrtClient::new(config).unwrap();
 
     let mut request = client
-        .get_object(&bucket, &key, None, None)
+        .get_object(&bucket, &key, &GetObjectParams::new())
         .await
         .expect("get_object should be sent");
 
@@ -256,7 +316,7 @@ async fn test_scoped_credentials() {
 
     // Inside the prefix, things should be fine
     let _result = client
-        .get_object(&bucket, &format!("{prefix}foo/foo.txt"), None, None)
+        .get_object(&bucket, &format!("{prefix}foo/foo.txt"), &GetObjectParams::new())
         .await
         .expect("get_object should succeed");
     let _result = client
@@ -266,7 +326,7 @@ async fn test_scoped_credentials() {
 
     // Outside the prefix, requests should fail with permissions errors
     let mut request = client
-        .get_object(&bucket, &format!("{prefix}baz.txt"), None, None)
+        .get_object(&bucket, &format!("{prefix}baz.txt"), &GetObjectParams::new())
         .await
         .expect("request should be sent");
     let err = request
@@ -276,7 +336,7 @@ async fn test_scoped_credentials() {
         .expect_err("should fail in different prefix");
     assert!(matches!(
         err,
-        ObjectClientError::ClientError(S3RequestError::Forbidden(_))
+        ObjectClientError::ClientError(S3RequestError::Forbidden(..))
     ));
     let err = client
         .list_objects(&bucket, None, "/", 10, &format!("{prefix}/"))
@@ -284,6 +344,6 @@ async fn test_scoped_credentials() {
         .expect_err("should fail in different prefix");
     assert!(matches!(
         err,
-        ObjectClientError::ClientError(S3RequestError::Forbidden(_))
+        ObjectClientError::ClientError(S3RequestError::Forbidden(..))
     ));
 }
@@ -93,6 +93,6 @@ async fn test_delete_object_no_perm() {
 
     assert!(matches!(
         result,
-        Err(ObjectClientError::ClientError(S3RequestError::Forbidden(_)))
+        Err(ObjectClientError::ClientError(S3RequestError::Forbidden(..)))
     ));
 }
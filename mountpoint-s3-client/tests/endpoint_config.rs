@@ -6,6 +6,7 @@ use aws_sdk_s3::primitives::ByteStream;
 use bytes::Bytes;
 use common::*;
 use mountpoint_s3_client::config::{AddressingStyle, EndpointConfig, S3ClientConfig};
+use mountpoint_s3_client::types::GetObjectParams;
 use mountpoint_s3_client::{ObjectClient, S3CrtClient};
 use test_case::test_case;
 
@@ -30,7 +31,7 @@ async fn run_test<F: FnOnce(&str) -> EndpointConfig>(f: F, prefix: &str, bucket:
     let client = S3CrtClient::new(config).expect("could not create test client");
 
     let result = client
-        .get_object(&bucket, &key, None, None)
+        .get_object(&bucket, &key, &GetObjectParams::new())
         .await
         .expect("get_object should succeed");
     check_get_result(result, None, &body[..]).await;
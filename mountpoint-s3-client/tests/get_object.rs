@@ -10,9 +10,11 @@ use aws_sdk_s3::primitives::ByteStream;
 use bytes::Bytes;
 use common::*;
 use futures::stream::StreamExt;
+use mountpoint_s3_client::checksums::crc32c_to_base64;
 use mountpoint_s3_client::error::{GetObjectError, ObjectClientError};
-use mountpoint_s3_client::types::ETag;
-use mountpoint_s3_client::{ObjectClient, S3CrtClient};
+use mountpoint_s3_client::types::{ChecksumMode, ETag, GetObjectParams};
+use mountpoint_s3_client::{GetObjectRequest, ObjectClient, S3CrtClient};
+use mountpoint_s3_crt::checksums::crc32c;
 
 use test_case::test_case;
 
@@ -42,7 +44,7 @@ async fn test_get_object(size: usize, range: Option<Range<u64>>) {
     let client: S3CrtClient = get_test_client();
 
     let result = client
-        .get_object(&bucket, &key, range.clone(), None)
+        .get_object(&bucket, &key, &GetObjectParams::new().range(range.clone()))
         .await
         .expect("get_object should succeed");
     let expected = match range {
@@ -52,6 +54,47 @@ async fn test_get_object(size: usize, range: Option<Range<u64>>) {
     check_get_result(result, range, expected).await;
 }
 
+#[tokio::test]
+async fn test_get_object_checksum() {
+    let sdk_client = get_test_sdk_client().await;
+    let (bucket, prefix) = get_test_bucket_and_prefix("test_get_object_checksum");
+
+    let key = format!("{prefix}/test");
+    let body = vec![0x42; 1000];
+    sdk_client
+        .put_object()
+        .bucket(&bucket)
+        .key(&key)
+        .body(ByteStream::from(body.clone()))
+        .checksum_algorithm(aws_sdk_s3::types::ChecksumAlgorithm::Crc32C)
+        .send()
+        .await
+        .unwrap();
+
+    let client: S3CrtClient = get_test_client();
+
+    let params = GetObjectParams::new().checksum_mode(Some(ChecksumMode::Enabled));
+    let mut result = client
+        .get_object(&bucket, &key, &params)
+        .await
+        .expect("get_object should succeed");
+    let (_offset, part) = result.next().await.unwrap().expect("part download should succeed");
+    assert_eq!(&part[..], &body[..]);
+    let checksum = result.get_object_checksum().expect("response should have a checksum");
+    assert_eq!(
+        checksum.checksum_crc32c,
+        Some(crc32c_to_base64(&crc32c::checksum(&body)))
+    );
+
+    // Without checksum mode, no checksum is returned
+    let mut result = client
+        .get_object(&bucket, &key, &GetObjectParams::new())
+        .await
+        .expect("get_object should succeed");
+    let _ = result.next().await.unwrap().expect("part download should succeed");
+    assert!(result.get_object_checksum().is_none());
+}
+
 #[tokio::test]
 async fn test_get_object_404_key() {
     let (bucket, prefix) = get_test_bucket_and_prefix("test_get_object_404_key");
@@ -61,7 +104,7 @@ async fn test_get_object_404_key() {
     let client: S3CrtClient = get_test_client();
 
     let mut result = client
-        .get_object(&bucket, &key, None, None)
+        .get_object(&bucket, &key, &GetObjectParams::new())
         .await
         .expect("get_object should succeed");
     let next = StreamExt::next(&mut result).await.expect("stream needs to return Err");
@@ -82,7 +125,7 @@ async fn test_get_object_404_bucket() {
     let client: S3CrtClient = get_test_client();
 
     let mut result = client
-        .get_object("DOC-EXAMPLE-BUCKET", &key, None, None)
+        .get_object("DOC-EXAMPLE-BUCKET", &key, &GetObjectParams::new())
         .await
         .expect("get_object failed");
     let next = StreamExt::next(&mut result).await.expect("stream needs to return Err");
@@ -114,7 +157,7 @@ async fn test_get_object_success_if_match() {
     let etag = Some(ETag::from_str(response.e_tag().expect("E-Tag should be set")).unwrap());
 
     let result = client
-        .get_object(&bucket, &key, None, etag)
+        .get_object(&bucket, &key, &GetObjectParams::new().if_match(etag))
         .await
         .expect("get_object should succeed");
     check_get_result(result, None, &body[..]).await;
@@ -141,7 +184,7 @@ async fn test_get_object_412_if_match() {
     let etag = Some(ETag::from_str("incorrect_etag").unwrap());
 
     let mut result = client
-        .get_object(&bucket, &key, None, etag)
+        .get_object(&bucket, &key, &GetObjectParams::new().if_match(etag))
         .await
         .expect("get_object should succeed");
 
@@ -177,7 +220,7 @@ async fn test_get_object_cancel(read: bool) {
     let client: S3CrtClient = get_test_client();
 
     let mut request = client
-        .get_object(&bucket, &key, None, None)
+        .get_object(&bucket, &key, &GetObjectParams::new())
         .await
         .expect("get_object should succeed");
 
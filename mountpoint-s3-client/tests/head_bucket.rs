@@ -46,7 +46,7 @@ async fn test_head_bucket_forbidden() {
 
     assert!(matches!(
         result,
-        Err(ObjectClientError::ClientError(S3RequestError::Forbidden(_)))
+        Err(ObjectClientError::ClientError(S3RequestError::Forbidden(..)))
     ));
 }
 
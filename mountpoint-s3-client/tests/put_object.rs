@@ -2,6 +2,7 @@
 
 pub mod common;
 
+use std::collections::HashMap;
 use std::time::Duration;
 
 use common::*;
@@ -10,7 +11,8 @@ use mountpoint_s3_client::checksums::crc32c_to_base64;
 use mountpoint_s3_client::config::{EndpointConfig, S3ClientConfig};
 use mountpoint_s3_client::error::{GetObjectError, ObjectClientError};
 use mountpoint_s3_client::types::{
-    ChecksumAlgorithm, ObjectClientResult, PutObjectParams, PutObjectResult, PutObjectTrailingChecksums,
+    ChecksumAlgorithm, GetObjectParams, ObjectClientResult, PutObjectParams, PutObjectResult,
+    PutObjectTrailingChecksums,
 };
 use mountpoint_s3_client::{ObjectClient, PutObjectRequest, S3CrtClient, S3RequestError};
 use mountpoint_s3_crt::checksums::crc32c;
@@ -40,7 +42,7 @@ async fn test_put_object(
     let put_object_result = request.complete().await.unwrap();
 
     let result = client
-        .get_object(bucket, key, None, None)
+        .get_object(bucket, key, &GetObjectParams::new())
         .await
         .expect("get_object should succeed");
     check_get_result(result, None, &contents[..]).await;
@@ -66,7 +68,7 @@ async fn test_put_object_empty(
     let put_object_result = request.complete().await.unwrap();
 
     let result = client
-        .get_object(bucket, key, None, None)
+        .get_object(bucket, key, &GetObjectParams::new())
         .await
         .expect("get_object should succeed");
     check_get_result(result, None, &[]).await;
@@ -101,7 +103,7 @@ async fn test_put_object_multi_part(
     let put_object_result = request.complete().await.unwrap();
 
     let result = client
-        .get_object(bucket, key, None, None)
+        .get_object(bucket, key, &GetObjectParams::new())
         .await
         .expect("get_object failed");
     check_get_result(result, None, &contents[..]).await;
@@ -138,7 +140,7 @@ async fn test_put_object_large(
     let put_object_result = request.complete().await.unwrap();
 
     let result = client
-        .get_object(bucket, key, None, None)
+        .get_object(bucket, key, &GetObjectParams::new())
         .await
         .expect("get_object failed");
     check_get_result(result, None, &contents[..]).await;
@@ -222,7 +224,7 @@ async fn test_put_object_write_cancelled() {
 
     {
         // Write a multiple of `part_size` to ensure the copy is deferred.
-        let size = client.part_size().unwrap() * 10;
+        let size = client.write_part_size().unwrap() * 10;
         let buffer = vec![0u8; size];
         let write = request.write(&buffer);
 
@@ -411,7 +413,7 @@ async fn check_get_object<Client: ObjectClient>(
     bucket: &str,
     key: &str,
 ) -> ObjectClientResult<(), GetObjectError, Client::ClientError> {
-    let result = client.get_object(bucket, key, None, None).await?;
+    let result = client.get_object(bucket, key, &GetObjectParams::new()).await?;
     pin_mut!(result);
     result.next().await.unwrap()?;
     Ok(())
@@ -453,6 +455,26 @@ async fn test_put_object_storage_class(storage_class: &str) {
     assert_eq!(storage_class, attributes.storage_class.unwrap().as_str());
 }
 
+#[tokio::test]
+async fn test_put_object_metadata() {
+    let (bucket, prefix) = get_test_bucket_and_prefix("test_put_object_metadata");
+    let client = get_test_client();
+    let key = format!("{prefix}hello");
+
+    let object_metadata = HashMap::from([("symlink-target".to_owned(), "foo/bar".to_owned())]);
+    let params = PutObjectParams::new().object_metadata(object_metadata.clone());
+    let mut request = client
+        .put_object(&bucket, &key, &params)
+        .await
+        .expect("put_object should succeed");
+
+    request.write(b"hello").await.unwrap();
+    request.complete().await.unwrap();
+
+    let head_result = client.head_object(&bucket, &key).await.unwrap();
+    assert_eq!(head_result.object_metadata, object_metadata);
+}
+
 #[cfg(not(feature = "s3express_tests"))]
 async fn check_sse(
     bucket: &String,
@@ -597,3 +619,43 @@ async fn test_concurrent_put_objects(throughput_target_gbps: f64, max_concurrent
     // Cancel all put_object requests.
     drop(req_vec);
 }
+
+#[test_case(1; "one part")]
+#[test_case(2; "two parts")]
+#[tokio::test]
+async fn test_put_object_max_buffered_parts(max_buffered_parts: usize) {
+    const PART_SIZE: usize = 5 * 1024 * 1024;
+    const OBJECT_SIZE: usize = 4 * PART_SIZE + 1024;
+    // Not a divisor of the part size, so writes straddle part boundaries
+    const CHUNK_SIZE: usize = 1024 * 1024 + 1;
+
+    let bucket = get_test_bucket();
+    let prefix = get_unique_test_prefix("test_put_object_max_buffered_parts");
+    let key = format!("{prefix}hello");
+    let client_config = S3ClientConfig::new()
+        .endpoint_config(EndpointConfig::new(&get_test_region()))
+        .part_size(PART_SIZE)
+        .max_buffered_upload_parts(max_buffered_parts.try_into().unwrap());
+    let client = S3CrtClient::new(client_config).expect("could not create test client");
+
+    let mut contents = vec![0u8; OBJECT_SIZE];
+    rand::thread_rng().fill(&mut contents[..]);
+
+    let mut request = client
+        .put_object(&bucket, &key, &PutObjectParams::new())
+        .await
+        .expect("put_object failed");
+    for chunk in contents.chunks(CHUNK_SIZE) {
+        tokio::time::timeout(Duration::from_secs(60), request.write(chunk))
+            .await
+            .expect("timed out waiting for buffered parts to upload")
+            .unwrap();
+    }
+    request.complete().await.unwrap();
+
+    let result = client
+        .get_object(&bucket, &key, &GetObjectParams::new())
+        .await
+        .expect("get_object failed");
+    check_get_result(result, None, &contents[..]).await;
+}
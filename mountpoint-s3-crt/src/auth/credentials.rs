@@ -2,19 +2,24 @@
 
 use std::fmt::Debug;
 use std::ptr::NonNull;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use mountpoint_s3_crt_sys::{
-    aws_credentials_provider, aws_credentials_provider_acquire, aws_credentials_provider_chain_default_options,
+    aws_auth_errors, aws_credentials, aws_credentials_new, aws_credentials_provider, aws_credentials_provider_acquire,
+    aws_credentials_provider_chain_default_options, aws_credentials_provider_delegate_options,
     aws_credentials_provider_new_anonymous, aws_credentials_provider_new_chain_default,
-    aws_credentials_provider_new_profile, aws_credentials_provider_new_static,
-    aws_credentials_provider_profile_options, aws_credentials_provider_release,
-    aws_credentials_provider_static_options,
+    aws_credentials_provider_new_delegate, aws_credentials_provider_new_profile, aws_credentials_provider_new_sso,
+    aws_credentials_provider_new_static, aws_credentials_provider_new_sts, aws_credentials_provider_profile_options,
+    aws_credentials_provider_release, aws_credentials_provider_shutdown_options, aws_credentials_provider_sso_options,
+    aws_credentials_provider_static_options, aws_credentials_provider_sts_options, aws_credentials_release,
+    aws_last_error, AWS_OP_SUCCESS,
 };
 
 use crate::auth::auth_library_init;
 use crate::common::allocator::Allocator;
 use crate::common::error::Error;
 use crate::io::channel_bootstrap::ClientBootstrap;
+use crate::io::tls::TlsContext;
 use crate::{CrtError as _, ToAwsByteCursor as _};
 
 /// Options for creating a default credentials provider
@@ -53,6 +58,60 @@ impl Debug for CredentialsProviderStaticOptions<'_> {
     }
 }
 
+/// Options for creating an IAM Identity Center (SSO) credentials provider
+#[derive(Debug)]
+pub struct CredentialsProviderSsoOptions<'a> {
+    /// The client bootstrap this credentials provider should use to setup channels
+    pub bootstrap: &'a mut ClientBootstrap,
+    /// The TLS context to use for connections to the SSO portal
+    pub tls_context: &'a TlsContext,
+    /// The name of the profile with the SSO configuration, rather than the default profile.
+    pub profile_name_override: Option<&'a str>,
+}
+
+/// Options for creating a credentials provider that assumes an IAM role with STS
+#[derive(Debug)]
+pub struct CredentialsProviderStsOptions<'a> {
+    /// The client bootstrap this credentials provider should use to setup channels
+    pub bootstrap: &'a mut ClientBootstrap,
+    /// The TLS context to use for connections to STS
+    pub tls_context: &'a TlsContext,
+    /// The credentials provider whose credentials are used to call AssumeRole
+    pub source_provider: &'a CredentialsProvider,
+    /// ARN of the role to assume
+    pub role_arn: &'a str,
+    /// Name for the role session, which appears in CloudTrail logs
+    pub session_name: &'a str,
+    /// How long each set of credentials should last for. The provider refreshes credentials before
+    /// they expire. Uses the CRT's default (15 minutes) if not set.
+    pub duration_seconds: Option<u16>,
+}
+
+/// A set of AWS credentials returned by the callback of a [CredentialsProvider::new_delegate]
+/// provider
+#[derive(Clone)]
+pub struct DelegateCredentials {
+    /// AWS access key ID
+    pub access_key_id: String,
+    /// AWS secret access key
+    pub secret_access_key: String,
+    /// AWS session token (only required for some credentials sources, e.g. STS)
+    pub session_token: Option<String>,
+    /// When the credentials expire, if they do
+    pub expiration: Option<SystemTime>,
+}
+
+impl Debug for DelegateCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DelegateCredentials")
+            .field("access_key_id", &"** redacted **")
+            .field("secret_access_key", &"** redacted **")
+            .field("session_token", &self.session_token.as_ref().map(|_| "** redacted **"))
+            .field("expiration", &self.expiration)
+            .finish()
+    }
+}
+
 /// A credentials provider is an object that has an asynchronous query function for retrieving AWS
 /// credentials
 #[derive(Debug)]
@@ -139,6 +198,150 @@ impl CredentialsProvider {
 
         Ok(Self { inner })
     }
+
+    /// Creates a credentials provider that gets role credentials from IAM Identity Center (SSO),
+    /// using the access token cached by `aws sso login` for a profile's SSO configuration.
+    pub fn new_sso(allocator: &Allocator, options: CredentialsProviderSsoOptions) -> Result<Self, Error> {
+        auth_library_init(allocator);
+
+        // SAFETY: aws_credentials_provider_new_sso acquires references to the bootstrap and TLS
+        // context, and makes a copy of the profile name.
+        let inner = unsafe {
+            let inner_options = aws_credentials_provider_sso_options {
+                bootstrap: options.bootstrap.inner.as_ptr(),
+                tls_ctx: options.tls_context.inner.as_ptr(),
+                profile_name_override: options
+                    .profile_name_override
+                    .map(|name| name.as_aws_byte_cursor())
+                    .unwrap_or_default(),
+                ..Default::default()
+            };
+
+            aws_credentials_provider_new_sso(allocator.inner.as_ptr(), &inner_options).ok_or_last_error()?
+        };
+
+        Ok(Self { inner })
+    }
+
+    /// Creates a credentials provider that assumes an IAM role with STS, using credentials from
+    /// another provider. The provider caches the role's credentials and refreshes them before they
+    /// expire.
+    pub fn new_sts(allocator: &Allocator, options: CredentialsProviderStsOptions) -> Result<Self, Error> {
+        auth_library_init(allocator);
+
+        // SAFETY: aws_credentials_provider_new_sts acquires references to the bootstrap, TLS context,
+        // and source provider, and makes copies of the strings.
+        let inner = unsafe {
+            let inner_options = aws_credentials_provider_sts_options {
+                bootstrap: options.bootstrap.inner.as_ptr(),
+                tls_ctx: options.tls_context.inner.as_ptr(),
+                creds_provider: options.source_provider.inner.as_ptr(),
+                role_arn: options.role_arn.as_aws_byte_cursor(),
+                session_name: options.session_name.as_aws_byte_cursor(),
+                duration_seconds: options.duration_seconds.unwrap_or_default(),
+                ..Default::default()
+            };
+
+            aws_credentials_provider_new_sts(allocator.inner.as_ptr(), &inner_options).ok_or_last_error()?
+        };
+
+        Ok(Self { inner })
+    }
+
+    /// Creates a credentials provider that calls `get_credentials` each time credentials are needed.
+    /// The callback runs on the CRT's event loop threads, so it must return quickly (for example,
+    /// by returning credentials that another thread keeps refreshed). Returning `None` fails the
+    /// request that needed the credentials.
+    pub fn new_delegate<F>(allocator: &Allocator, get_credentials: F) -> Result<Self, Error>
+    where
+        F: Fn() -> Option<DelegateCredentials> + Send + Sync + 'static,
+    {
+        auth_library_init(allocator);
+
+        let user_data: Box<DelegateGetCredentials> = Box::new(Box::new(get_credentials));
+        let user_data = Box::into_raw(user_data) as *mut libc::c_void;
+
+        // SAFETY: `user_data` is leaked by [Box::into_raw] and stays valid until the provider shuts
+        // down, when `delegate_shutdown_callback` reclaims it.
+        let inner = unsafe {
+            let inner_options = aws_credentials_provider_delegate_options {
+                shutdown_options: aws_credentials_provider_shutdown_options {
+                    shutdown_callback: Some(delegate_shutdown_callback),
+                    shutdown_user_data: user_data,
+                },
+                get_credentials: Some(delegate_get_credentials),
+                delegate_user_data: user_data,
+            };
+
+            let inner =
+                aws_credentials_provider_new_delegate(allocator.inner.as_ptr(), &inner_options).ok_or_last_error();
+            if inner.is_err() {
+                // The provider was never created, so its shutdown callback won't run
+                drop(Box::from_raw(user_data as *mut DelegateGetCredentials));
+            }
+            inner?
+        };
+
+        Ok(Self { inner })
+    }
+}
+
+type DelegateGetCredentials = Box<dyn Fn() -> Option<DelegateCredentials> + Send + Sync>;
+
+/// Rust binding for CRT's callback function `aws_credentials_provider_delegate_get_credentials_fn`.
+unsafe extern "C" fn delegate_get_credentials(
+    delegate_user_data: *mut libc::c_void,
+    callback: Option<unsafe extern "C" fn(*mut aws_credentials, i32, *mut libc::c_void)>,
+    callback_user_data: *mut libc::c_void,
+) -> i32 {
+    // SAFETY: `delegate_user_data` is the `DelegateGetCredentials` leaked in `new_delegate`, which
+    // lives until the provider shuts down.
+    let get_credentials = &*(delegate_user_data as *const DelegateGetCredentials);
+    let callback = callback.expect("CRT always provides a credentials callback");
+
+    let Some(credentials) = get_credentials() else {
+        let error_code = aws_auth_errors::AWS_AUTH_CREDENTIALS_PROVIDER_DELEGATE_FAILURE as i32;
+        callback(std::ptr::null_mut(), error_code, callback_user_data);
+        return AWS_OP_SUCCESS;
+    };
+
+    let expiration = credentials
+        .expiration
+        .map(|expiration| {
+            expiration
+                .duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_secs())
+                .unwrap_or(0)
+        })
+        .unwrap_or(u64::MAX);
+    let allocator = Allocator::default();
+    // SAFETY: aws_credentials_new makes copies of the strings. The callback acquires its own
+    // reference to the credentials if it needs to keep them, so we release ours afterwards.
+    let inner = aws_credentials_new(
+        allocator.inner.as_ptr(),
+        credentials.access_key_id.as_aws_byte_cursor(),
+        credentials.secret_access_key.as_aws_byte_cursor(),
+        credentials
+            .session_token
+            .as_ref()
+            .map(|token| token.as_aws_byte_cursor())
+            .unwrap_or_default(),
+        expiration,
+    );
+    if inner.is_null() {
+        callback(std::ptr::null_mut(), aws_last_error(), callback_user_data);
+    } else {
+        callback(inner, 0, callback_user_data);
+        aws_credentials_release(inner);
+    }
+    AWS_OP_SUCCESS
+}
+
+/// Rust binding for CRT's callback function `aws_credentials_provider_shutdown_completed_fn`.
+unsafe extern "C" fn delegate_shutdown_callback(user_data: *mut libc::c_void) {
+    // SAFETY: `user_data` is the `DelegateGetCredentials` leaked in `new_delegate`, and the provider
+    // has shut down so nothing else will use it.
+    drop(Box::from_raw(user_data as *mut DelegateGetCredentials));
 }
 
 impl Clone for CredentialsProvider {
@@ -11,6 +11,7 @@ use thiserror::Error;
 use crate::common::allocator::Allocator;
 use crate::common::error::Error;
 use crate::http::http_library_init;
+use crate::io::stream::InputStream;
 use crate::{aws_byte_cursor_as_slice, CrtError, ToAwsByteCursor};
 
 /// An HTTP header.
@@ -253,6 +254,9 @@ impl<'a> Iterator for HeadersIterator<'a> {
 pub struct Message {
     /// The pointer to the inner `aws_http_message`.
     pub(crate) inner: NonNull<aws_http_message>,
+    /// The body of this message, if any. The inner message holds a reference to this stream, but
+    /// we keep it here so the bytes it reads from live as long as the message.
+    body_stream: Option<InputStream>,
 }
 
 impl Message {
@@ -264,7 +268,10 @@ impl Message {
         // SAFETY: `allocator.inner` is a valid `aws_allocator`.
         let inner = unsafe { aws_http_message_new_request(allocator.inner.as_ptr()).ok_or_last_error()? };
 
-        Ok(Self { inner })
+        Ok(Self {
+            inner,
+            body_stream: None,
+        })
     }
 
     /// Add a header to this message. If the header already exists in the message, this will add a
@@ -306,6 +313,21 @@ impl Message {
         }
     }
 
+    /// Set the body of this message, replacing any existing body. The caller should also set the
+    /// `Content-Length` header to the length of the stream.
+    pub fn set_body_stream(&mut self, body_stream: Option<InputStream>) {
+        let stream_ptr = body_stream
+            .as_ref()
+            .map_or(std::ptr::null_mut(), |stream| stream.inner.as_ptr());
+        // SAFETY: `self.inner` is a valid `aws_http_message`, and `aws_http_message_set_body_stream`
+        // releases any previous body stream and acquires a reference to the new one. We keep the
+        // new stream in `self` so its buffer outlives the message.
+        unsafe {
+            aws_http_message_set_body_stream(self.inner.as_ptr(), stream_ptr);
+        }
+        self.body_stream = body_stream;
+    }
+
     /// get the headers from the message and increases the reference count for the Headers in CRT.
     pub fn get_headers(&mut self) -> Result<Headers, Error> {
         // SAFETY: `aws_http_message_get_headers` is safe because self.inner is a valid NonNull `aws_http_message`.
@@ -0,0 +1,76 @@
+//! Streams of bytes, used for HTTP request bodies
+
+use std::ptr::NonNull;
+
+use mountpoint_s3_crt_sys::*;
+
+use crate::common::allocator::Allocator;
+use crate::common::error::Error;
+use crate::io::io_library_init;
+use crate::{CrtError as _, ToAwsByteCursor as _};
+
+/// An [InputStream] is a readable stream of bytes, for example the body of an HTTP request. This
+/// stream reads from a buffer of bytes that it owns.
+#[derive(Debug)]
+pub struct InputStream {
+    /// The pointer to the inner `aws_input_stream`.
+    pub(crate) inner: NonNull<aws_input_stream>,
+    /// The bytes the inner stream reads from. The stream holds a cursor into this buffer, so it
+    /// must outlive the stream.
+    buffer: Box<[u8]>,
+}
+
+impl InputStream {
+    /// Create a new [InputStream] that reads the given bytes.
+    pub fn new_from_bytes(allocator: &Allocator, bytes: impl Into<Box<[u8]>>) -> Result<Self, Error> {
+        io_library_init(allocator);
+
+        let buffer = bytes.into();
+        // SAFETY: the stream copies the cursor but not the bytes it points to, so we keep `buffer`
+        // alive in this struct for as long as the stream exists. Moving the box doesn't move the
+        // bytes it owns.
+        let inner = unsafe {
+            let cursor = buffer.as_aws_byte_cursor();
+            aws_input_stream_new_from_cursor(allocator.inner.as_ptr(), &cursor).ok_or_last_error()?
+        };
+
+        Ok(Self { inner, buffer })
+    }
+
+    /// The number of bytes in this stream.
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Whether this stream is empty.
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+}
+
+impl Drop for InputStream {
+    fn drop(&mut self) {
+        // SAFETY: `self.inner` is a valid `aws_input_stream`, and on Drop it's safe to decrement
+        // the reference count since we won't use it again through `self`.
+        unsafe {
+            aws_input_stream_release(self.inner.as_ptr());
+        }
+    }
+}
+
+// SAFETY: `aws_input_stream` is reference counted, and the stream only reads from a buffer that it
+// owns and never modifies.
+unsafe impl Send for InputStream {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_input_stream_from_bytes() {
+        let stream = InputStream::new_from_bytes(&Allocator::default(), b"hello world".to_vec())
+            .expect("failed to create stream");
+        assert_eq!(stream.len(), 11);
+        assert!(!stream.is_empty());
+    }
+}
@@ -0,0 +1,72 @@
+//! TLS contexts for making secure connections
+
+use crate::common::allocator::Allocator;
+use crate::common::error::Error;
+use crate::io::io_library_init;
+use crate::CrtError as _;
+use mountpoint_s3_crt_sys::*;
+use std::ptr::NonNull;
+
+/// A [TlsContext] holds the TLS configuration, like trusted certificates, shared by the
+/// connections made with it
+#[derive(Debug)]
+pub struct TlsContext {
+    pub(crate) inner: NonNull<aws_tls_ctx>,
+}
+
+// SAFETY: aws_tls_ctx is immutable once created and reference counted.
+unsafe impl Send for TlsContext {}
+// SAFETY: aws_tls_ctx is immutable once created and reference counted.
+unsafe impl Sync for TlsContext {}
+
+impl TlsContext {
+    /// Create a [TlsContext] for clients, with the platform's default settings and trusted
+    /// certificates
+    pub fn new_default_client(allocator: &Allocator) -> Result<Self, Error> {
+        io_library_init(allocator);
+
+        let mut options: aws_tls_ctx_options = Default::default();
+        // SAFETY: `options` is a valid aws_tls_ctx_options to initialize, and aws_tls_client_ctx_new
+        // takes what it needs from the options, so we can clean them up afterwards.
+        let inner = unsafe {
+            aws_tls_ctx_options_init_default_client(&mut options, allocator.inner.as_ptr());
+            let inner = aws_tls_client_ctx_new(allocator.inner.as_ptr(), &options).ok_or_last_error();
+            aws_tls_ctx_options_clean_up(&mut options);
+            inner?
+        };
+
+        Ok(Self { inner })
+    }
+}
+
+impl Clone for TlsContext {
+    fn clone(&self) -> Self {
+        // SAFETY: self.inner is a valid aws_tls_ctx and aws_tls_ctx_acquire increments the
+        // reference count for it (and always returns a copy of the input, which is non-null).
+        let inner = unsafe { NonNull::new_unchecked(aws_tls_ctx_acquire(self.inner.as_ptr())) };
+
+        Self { inner }
+    }
+}
+
+impl Drop for TlsContext {
+    fn drop(&mut self) {
+        // SAFETY: self.inner is a valid aws_tls_ctx, and we're dropping a reference to it so it's
+        // safe to call release (which will decrement the refcnt).
+        unsafe {
+            aws_tls_ctx_release(self.inner.as_ptr());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new_default_client() {
+        let allocator = Allocator::default();
+        let context = TlsContext::new_default_client(&allocator).unwrap();
+        drop(context.clone());
+    }
+}
@@ -0,0 +1,169 @@
+use clap::{Arg, Command};
+use fuser::{BackgroundSession, MountOption, Session};
+use mountpoint_s3::fs::{AppendUploadConcurrency, DEFAULT_APPEND_UPLOAD_CONCURRENCY};
+use mountpoint_s3::fuse::S3FuseFilesystem;
+use mountpoint_s3::prefetch::default_prefetch;
+use mountpoint_s3::s3::S3Personality;
+use mountpoint_s3::S3FilesystemConfig;
+use mountpoint_s3_client::config::{EndpointConfig, S3ClientConfig};
+use mountpoint_s3_client::S3CrtClient;
+use mountpoint_s3_crt::common::rust_log_adapter::RustLogAdapter;
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    time::Instant,
+};
+use tempfile::tempdir;
+use tracing_subscriber::fmt::Subscriber;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+fn init_tracing_subscriber() {
+    RustLogAdapter::try_init().expect("unable to install CRT log adapter");
+
+    let subscriber = Subscriber::builder()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .finish();
+
+    subscriber.try_init().expect("unable to install global subscriber");
+}
+
+fn main() -> io::Result<()> {
+    init_tracing_subscriber();
+
+    const MB: usize = 1 << 20;
+
+    let matches = Command::new("benchmark")
+        .about("Append to an existing file in an S3 Express One Zone directory bucket")
+        .arg(Arg::new("bucket").required(true))
+        .arg(
+            Arg::new("file_path")
+                .required(true)
+                .help("relative path to the mountpoint of a file that already exists"),
+        )
+        .arg(
+            Arg::new("size-mb")
+                .long("size-mb")
+                .help("Number of MiB to append in each iteration")
+                .default_value("1024"),
+        )
+        .arg(
+            Arg::new("concurrency")
+                .long("concurrency")
+                .help("Maximum number of parts to upload at once"),
+        )
+        .arg(
+            Arg::new("throughput-target-gbps")
+                .long("throughput-target-gbps")
+                .help("Desired throughput in Gbps"),
+        )
+        .arg(
+            Arg::new("iterations")
+                .long("iterations")
+                .help("Number of times to append"),
+        )
+        .arg(Arg::new("region").long("region").default_value("us-east-1"))
+        .get_matches();
+
+    let bucket_name = matches.get_one::<String>("bucket").unwrap();
+    let file_path = matches.get_one::<String>("file_path").unwrap();
+    let size_mb = matches
+        .get_one::<String>("size-mb")
+        .unwrap()
+        .parse::<usize>()
+        .expect("size must be a number");
+    let concurrency = matches
+        .get_one::<String>("concurrency")
+        .map(|s| s.parse::<usize>().expect("concurrency must be a number"));
+    let throughput_target_gbps = matches
+        .get_one::<String>("throughput-target-gbps")
+        .map(|s| s.parse::<f64>().expect("throughput target must be an f64"));
+    let iterations = matches
+        .get_one::<String>("iterations")
+        .map(|s| s.parse::<usize>().expect("iterations must be a number"));
+    let region = matches.get_one::<String>("region").unwrap();
+
+    let session = mount_file_system(bucket_name, region, throughput_target_gbps, concurrency);
+    let mountpoint = &session.mountpoint;
+
+    let data = vec![0xaa; MB];
+    for i in 0..iterations.unwrap_or(1) {
+        let start = Instant::now();
+
+        let mut file = OpenOptions::new().append(true).open(mountpoint.join(file_path))?;
+        for _ in 0..size_mb {
+            file.write_all(&data)?;
+        }
+        // Completes the upload
+        file.sync_all()?;
+        drop(file);
+
+        let elapsed = start.elapsed();
+        let sent_size = size_mb * MB;
+
+        println!(
+            "{}: appended {} bytes in {:.2}s: {:.2} MiB/s",
+            i,
+            sent_size,
+            elapsed.as_secs_f64(),
+            (sent_size as f64) / elapsed.as_secs_f64() / MB as f64
+        );
+    }
+
+    drop(session);
+    Ok(())
+}
+
+fn mount_file_system(
+    bucket_name: &str,
+    region: &str,
+    throughput_target_gbps: Option<f64>,
+    concurrency: Option<usize>,
+) -> BackgroundSession {
+    let temp_dir = tempdir().expect("Should be able to create temp directory");
+    let mountpoint = temp_dir.path();
+
+    let mut config = S3ClientConfig::new().endpoint_config(EndpointConfig::new(region));
+    if let Some(throughput_target_gbps) = throughput_target_gbps {
+        config = config.throughput_target_gbps(throughput_target_gbps);
+    }
+    let client = S3CrtClient::new(config).expect("Failed to create S3 client");
+    let runtime = client.event_loop_group();
+
+    let mut options = vec![MountOption::FSName("mountpoint-s3".to_string())];
+    options.push(MountOption::AutoUnmount);
+
+    let per_file = concurrency.unwrap_or(DEFAULT_APPEND_UPLOAD_CONCURRENCY);
+    let filesystem_config = S3FilesystemConfig {
+        s3_personality: S3Personality::ExpressOneZone,
+        allow_overwrite: true,
+        append_upload_concurrency: AppendUploadConcurrency {
+            per_file,
+            total: per_file,
+        },
+        ..Default::default()
+    };
+
+    println!(
+        "Mounting bucket {} to path {}",
+        bucket_name,
+        mountpoint.to_str().unwrap()
+    );
+    let prefetcher = default_prefetch(runtime.clone(), Default::default());
+    let session = Session::new(
+        S3FuseFilesystem::new(
+            client,
+            prefetcher,
+            runtime,
+            bucket_name,
+            &Default::default(),
+            filesystem_config,
+        ),
+        mountpoint,
+        &options,
+    )
+    .expect("Should have created FUSE session successfully");
+
+    BackgroundSession::new(session).expect("Should have started FUSE session successfully")
+}
@@ -164,9 +164,16 @@ fn mount_file_system(bucket_name: &str, region: &str, throughput_target_gbps: Op
         bucket_name,
         mountpoint.to_str().unwrap()
     );
-    let prefetcher = default_prefetch(runtime, Default::default());
+    let prefetcher = default_prefetch(runtime.clone(), Default::default());
     let session = Session::new(
-        S3FuseFilesystem::new(client, prefetcher, bucket_name, &Default::default(), filesystem_config),
+        S3FuseFilesystem::new(
+            client,
+            prefetcher,
+            runtime,
+            bucket_name,
+            &Default::default(),
+            filesystem_config,
+        ),
         mountpoint,
         &options,
     )
@@ -4,6 +4,7 @@ use std::time::Instant;
 
 use clap::{Arg, Command};
 use futures::executor::{block_on, ThreadPool};
+use mountpoint_s3::object::ObjectId;
 use mountpoint_s3::prefetch::{default_prefetch, Prefetch, PrefetchResult};
 use mountpoint_s3_client::config::{EndpointConfig, S3ClientConfig};
 use mountpoint_s3_client::types::ETag;
@@ -85,7 +86,12 @@ fn main() {
 
         let start = Instant::now();
 
-        let mut request = manager.prefetch(client.clone(), bucket, key, size, ETag::for_tests());
+        let mut request = manager.prefetch(
+            client.clone(),
+            bucket,
+            ObjectId::new(key.to_owned(), ETag::for_tests()),
+            size,
+        );
         block_on(async {
             loop {
                 let offset = received_size.load(Ordering::SeqCst);
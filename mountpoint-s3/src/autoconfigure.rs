@@ -2,6 +2,18 @@ use anyhow::{anyhow, Context};
 
 use mountpoint_s3_client::instance_info::InstanceInfo;
 
+use crate::prefetch::PrefetcherConfig;
+
+/// Bounds on the largest prefetch request, which we otherwise size to what the network can deliver
+/// in a second. Readers rarely consume more than that before the next request is ready.
+const MIN_MAX_REQUEST_SIZE: usize = 64 * 1024 * 1024;
+const MAX_MAX_REQUEST_SIZE: usize = 2 * 1024 * 1024 * 1024;
+
+/// Network throughput that each concurrent request for a read that missed the prefetch window can
+/// make use of
+const GBPS_PER_READ_PART: f64 = 2.5;
+const MAX_READ_PART_CONCURRENCY: usize = 32;
+
 /// Determine the maximum network throughput for the current instance using IMDS. Returns an error
 /// if the instance type either cannot be retrieved using the IMDS client or does not have a known
 /// network throughput.
@@ -28,6 +40,20 @@ fn get_maximum_network_throughput(ec2_instance_type: &str) -> anyhow::Result<f64
         .ok_or_else(|| anyhow!("no throughput configuration for EC2 instance type {ec2_instance_type}"))
 }
 
+/// Scale the prefetcher's limits to the target network throughput. Faster networks fill larger
+/// prefetch windows, and need more concurrent requests to quickly fetch a read that missed the
+/// window. The window never grows beyond the one in `config`, which presets might have shrunk.
+pub fn prefetcher_config(throughput_target_gbps: f64, config: PrefetcherConfig) -> PrefetcherConfig {
+    let bytes_per_second = (throughput_target_gbps * 1e9 / 8.0) as usize;
+    let max_request_size = bytes_per_second.clamp(MIN_MAX_REQUEST_SIZE, MAX_MAX_REQUEST_SIZE);
+    let read_part_concurrency = ((throughput_target_gbps / GBPS_PER_READ_PART) as usize).min(MAX_READ_PART_CONCURRENCY);
+    PrefetcherConfig {
+        max_request_size: max_request_size.min(config.max_request_size),
+        read_part_concurrency: read_part_concurrency.max(config.read_part_concurrency),
+        ..config
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -45,4 +71,25 @@ mod tests {
         let actual = get_maximum_network_throughput(instance_type).ok();
         assert_eq!(actual, throughput);
     }
+
+    #[test_case(1.0, 125_000_000, 4; "1 gbps")]
+    #[test_case(10.0, 1_250_000_000, 4; "10 gbps")]
+    #[test_case(25.0, 2 * 1024 * 1024 * 1024, 10; "25 gbps")]
+    #[test_case(100.0, 2 * 1024 * 1024 * 1024, 32; "100 gbps")]
+    #[test_case(0.1, 64 * 1024 * 1024, 4; "slow network")]
+    fn test_prefetcher_config(throughput_target_gbps: f64, max_request_size: usize, read_part_concurrency: usize) {
+        let config = prefetcher_config(throughput_target_gbps, PrefetcherConfig::default());
+        assert_eq!(config.max_request_size, max_request_size);
+        assert_eq!(config.read_part_concurrency, read_part_concurrency);
+    }
+
+    #[test]
+    fn test_prefetcher_config_keeps_smaller_limits() {
+        let preset = PrefetcherConfig {
+            max_request_size: 16 * 1024 * 1024,
+            ..Default::default()
+        };
+        let config = prefetcher_config(100.0, preset);
+        assert_eq!(config.max_request_size, 16 * 1024 * 1024);
+    }
 }
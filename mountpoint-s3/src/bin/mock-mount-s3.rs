@@ -4,14 +4,15 @@
 //! --maximum-throughput-gbps command-line argument can be used to set the target throughput, which
 //! defaults to 10Gbps.
 //!
-//! As a safety measure, this binary works only if the bucket name begins with "sthree-". This makes
+//! As a safety measure, this binary works only if the bucket names begin with "sthree-". This makes
 //! sure we can't accidentally confuse this binary with a real `mount-s3` in any of our testing or
 //! release workflows, since real bucket names cannot start with this prefix.
 //!
 //! This binary is intended only for use in testing and development of Mountpoint.
 
 use futures::executor::ThreadPool;
-use mountpoint_s3::cli::CliArgs;
+use mountpoint_s3::cli::{CliArgs, ClientDetails};
+use mountpoint_s3::s3::router::BucketRouter;
 use mountpoint_s3::s3::S3Personality;
 use mountpoint_s3_client::mock_client::throughput_client::ThroughputMockClient;
 use mountpoint_s3_client::mock_client::{MockClientConfig, MockObject};
@@ -21,12 +22,19 @@ fn main() -> anyhow::Result<()> {
     mountpoint_s3::cli::main(create_mock_client)
 }
 
-fn create_mock_client(args: &CliArgs) -> anyhow::Result<(ThroughputMockClient, ThreadPool, S3Personality)> {
+fn create_mock_client(
+    args: &CliArgs,
+) -> anyhow::Result<(
+    BucketRouter<ThroughputMockClient>,
+    ThreadPool,
+    S3Personality,
+    ClientDetails,
+)> {
     // An extra little safety thing to make sure we can distinguish the real mount-s3 binary and
     // this one. Buckets starting with "sthree-" are always invalid against real S3:
     // https://docs.aws.amazon.com/AmazonS3/latest/userguide/bucketnamingrules.html
     anyhow::ensure!(
-        args.bucket_name.starts_with("sthree-"),
+        args.buckets().iter().all(|bucket| bucket.starts_with("sthree-")),
         "mock-mount-s3 bucket names must start with `sthree-`"
     );
 
@@ -35,14 +43,20 @@ fn create_mock_client(args: &CliArgs) -> anyhow::Result<(ThroughputMockClient, T
     let max_throughput_gbps = args.maximum_throughput_gbps.unwrap_or(10) as f64;
     tracing::info!("mock client target network throughput {max_throughput_gbps} Gbps");
 
-    let config = MockClientConfig {
-        bucket: args.bucket_name.clone(),
-        part_size: args.part_size as usize,
-        unordered_list_seed: None,
-    };
-    let client = ThroughputMockClient::new(config, max_throughput_gbps);
+    let mut buckets = args.buckets().into_iter();
+    let mut client = BucketRouter::new(create_mock_bucket(
+        args,
+        buckets.next().expect("at least one bucket is mounted"),
+        max_throughput_gbps,
+    ));
+    for bucket in buckets {
+        client.add_bucket(bucket, create_mock_bucket(args, bucket, max_throughput_gbps));
+    }
 
-    let runtime = ThreadPool::builder().name_prefix("runtime").create()?;
+    let runtime = ThreadPool::builder()
+        .name_prefix("runtime")
+        .pool_size(args.event_loop_threads() as usize)
+        .create()?;
 
     let s3_personality = if let Some(bucket_type) = &args.bucket_type {
         bucket_type.to_personality()
@@ -50,7 +64,25 @@ fn create_mock_client(args: &CliArgs) -> anyhow::Result<(ThroughputMockClient, T
         S3Personality::Standard
     };
 
-    // Pre-populate the bucket with some interesting file sizes and a little structure
+    let client_details = ClientDetails {
+        region: String::from("mock"),
+        throughput_target_gbps: max_throughput_gbps,
+        scoped_credentials: None,
+    };
+
+    Ok((client, runtime, s3_personality, client_details))
+}
+
+/// Create a mock client for one bucket, pre-populated with some interesting file sizes and a little
+/// structure
+fn create_mock_bucket(args: &CliArgs, bucket: &str, max_throughput_gbps: f64) -> ThroughputMockClient {
+    let config = MockClientConfig {
+        bucket: bucket.to_owned(),
+        part_size: args.part_size as usize,
+        unordered_list_seed: None,
+    };
+    let client = ThroughputMockClient::new(config, max_throughput_gbps);
+
     for expt in 0..10 {
         let size = 2000 * 10u64.pow(expt);
         let key = if size > 10u64.pow(12) {
@@ -72,6 +104,5 @@ fn create_mock_client(args: &CliArgs) -> anyhow::Result<(ThroughputMockClient, T
         "dir/hello.txt",
         MockObject::from_bytes(b"hello world", ETag::for_tests()),
     );
-
-    Ok((client, runtime, s3_personality))
+    client
 }
@@ -1,18 +1,19 @@
 use std::env;
+use std::ffi::OsString;
 use std::fs::File;
 use std::io::{Read, Write};
-use std::num::NonZeroUsize;
-use std::os::fd::AsRawFd;
+use std::num::{NonZeroU16, NonZeroUsize};
 use std::os::unix::prelude::FromRawFd;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::time::Duration;
 
 use anyhow::{anyhow, Context as _};
-use clap::{value_parser, Parser, ValueEnum};
+use clap::{value_parser, CommandFactory, FromArgMatches, Parser, ValueEnum};
 use fuser::{MountOption, Session};
 use futures::task::Spawn;
 use mountpoint_s3_client::config::{AddressingStyle, EndpointConfig, S3ClientAuthConfig, S3ClientConfig};
-use mountpoint_s3_client::error::ObjectClientError;
+use mountpoint_s3_client::error::{ListObjectsError, ObjectClientError};
 use mountpoint_s3_client::instance_info::InstanceInfo;
 use mountpoint_s3_client::user_agent::UserAgent;
 use mountpoint_s3_client::{ObjectClient, S3CrtClient, S3RequestError};
@@ -24,18 +25,33 @@ use mountpoint_s3_crt::io::event_loop::EventLoopGroup;
 use nix::sys::signal::Signal;
 use nix::unistd::ForkResult;
 use regex::Regex;
+use serde::Serialize;
+use sysinfo::System;
 
 use crate::build_info;
+use crate::config_file::load_config_file;
+use crate::credentials::scoped::{scoped_credentials, ScopedCredentials, ScopedCredentialsConfig};
 use crate::data_cache::{CacheLimit, DiskDataCache, DiskDataCacheConfig, ManagedCacheDir};
-use crate::fs::ServerSideEncryption;
-use crate::fs::{CacheConfig, S3FilesystemConfig};
+use crate::event_notifications::{start_event_invalidation, EventQueueConfig, QueueCredentials};
+use crate::fs::{
+    AccessMode, AppendUploadConcurrency, CacheConfig, CompositeNamespace, ControlDirectoryMode, DeletePolicy,
+    IdentityMap, KernelCacheMode, KernelFeatureError, NamespaceEntry, ObjectVersionPolicy, OverlayDirectory,
+    ReadPolicy, S3FilesystemConfig, ServerSideEncryption, TimeToLive, WriteConflictPolicy,
+    DEFAULT_APPEND_UPLOAD_CONCURRENCY, DEFAULT_MAX_BACKGROUND,
+};
 use crate::fuse::session::FuseSession;
+use crate::fuse::supervisor::{self, WorkerConnection};
 use crate::fuse::S3FuseFilesystem;
-use crate::logging::{init_logging, LoggingConfig};
-use crate::prefetch::{caching_prefetch, default_prefetch, Prefetch};
+use crate::logging::{init_logging, shutdown_otlp, LogRotation, LoggingConfig};
+use crate::metrics::{MetricsConfig, MetricsFormat};
+use crate::mount_helper::rewrite_mount_options;
+use crate::prefetch::{caching_prefetch, default_prefetch, Prefetch, PrefetcherConfig};
 use crate::prefix::Prefix;
+use crate::s3::arn::{BucketArn, BucketArnResource};
+use crate::s3::router::BucketRouter;
 use crate::s3::S3Personality;
-use crate::{autoconfigure, metrics};
+use crate::upload::IncompleteUploadCleaner;
+use crate::{autoconfigure, credentials, daemon, metrics};
 
 const CLIENT_OPTIONS_HEADER: &str = "Client options";
 const MOUNT_OPTIONS_HEADER: &str = "Mount options";
@@ -45,21 +61,60 @@ const LOGGING_OPTIONS_HEADER: &str = "Logging options";
 const CACHING_OPTIONS_HEADER: &str = "Caching options";
 const ADVANCED_OPTIONS_HEADER: &str = "Advanced options";
 
+/// How long to wait for the kernel to initialize the FUSE session after mounting.
+const FUSE_INIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often to look for incomplete multipart uploads to abort, with `--cleanup-incomplete-uploads`
+const INCOMPLETE_UPLOAD_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Exit code when the kernel doesn't support a FUSE feature that the mount configuration requires,
+/// so that scripts can tell this apart from other mount failures.
+pub const KERNEL_FEATURE_ERROR_EXIT_CODE: i32 = 3;
+
 #[derive(Parser, Debug)]
-#[clap(name = "mount-s3", about = "Mountpoint for Amazon S3", version = build_info::FULL_VERSION)]
+#[clap(
+    name = "mount-s3",
+    about = "Mountpoint for Amazon S3",
+    version = build_info::FULL_VERSION,
+    // Options can be repeated, and the last one wins, so that the command line overrides the config file
+    args_override_self = true,
+    // The bucket name can be left out when buckets are given with --bucket (see [CliArgs::try_parse_args])
+    allow_missing_positional = true
+)]
 pub struct CliArgs {
-    #[clap(help = "Name of bucket to mount", value_parser = parse_bucket_name)]
-    pub bucket_name: String,
+    #[clap(help = "Name of bucket to mount", index = 1, value_parser = parse_bucket_name)]
+    pub bucket_name: Option<String>,
 
-    #[clap(help = "Directory to mount the bucket at", value_name = "DIRECTORY")]
+    #[clap(help = "Directory to mount the bucket at", index = 2, value_name = "DIRECTORY")]
     pub mount_point: PathBuf,
 
     #[clap(
         long,
-        help = "Prefix inside the bucket to mount, ending in '/' [default: mount the entire bucket]",
+        help = "TOML file to read options from, keyed by their long names. Options given on the command line take \
+                precedence over the file",
+        value_name = "FILE",
+        help_heading = MOUNT_OPTIONS_HEADER
+    )]
+    pub config_file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Prefix inside the bucket to mount, ending in '/'. Repeat to mount several prefixes, each as a \
+                directory named after its last component [default: mount the entire bucket]",
         help_heading = BUCKET_OPTIONS_HEADER
     )]
-    pub prefix: Option<Prefix>,
+    pub prefix: Vec<Prefix>,
+
+    #[clap(
+        long,
+        help = "Mount a bucket, or a prefix of one, as directory NAME at the root of the mount, instead of giving a \
+                bucket name. Repeat to mount several buckets, each with its own client",
+        value_name = "NAME=s3://BUCKET[/PREFIX/]",
+        value_parser = parse_bucket_mount,
+        conflicts_with_all = ["bucket_name", "prefix", "scope_credentials", "overlay_dir"],
+        help_heading = BUCKET_OPTIONS_HEADER
+    )]
+    pub bucket: Vec<NamespaceEntry>,
 
     #[clap(
         long,
@@ -100,6 +155,36 @@ pub struct CliArgs {
     #[clap(long, help = "Use a specific profile from your credential file.", help_heading = AWS_CREDENTIALS_OPTIONS_HEADER)]
     pub profile: Option<String>,
 
+    #[clap(
+        long,
+        help = "ARN of an IAM role to assume with STS, using the credentials that would otherwise be used for \
+                requests. Credentials for the role are refreshed automatically",
+        help_heading = AWS_CREDENTIALS_OPTIONS_HEADER,
+        value_name = "ARN",
+        conflicts_with = "no_sign_request"
+    )]
+    pub role_arn: Option<String>,
+
+    #[clap(
+        long,
+        help = "Session name to use when assuming --role-arn, which appears in AWS CloudTrail logs \
+                [default: mountpoint-s3]",
+        help_heading = AWS_CREDENTIALS_OPTIONS_HEADER,
+        value_name = "NAME",
+        value_parser = parse_role_session_name,
+        requires = "role_arn"
+    )]
+    pub role_session_name: Option<String>,
+
+    #[clap(
+        long,
+        help = "Use credentials scoped down to the mounted bucket and prefix, by assuming --role-arn (or the role \
+                the credentials belong to) with a session policy",
+        help_heading = AWS_CREDENTIALS_OPTIONS_HEADER,
+        conflicts_with = "no_sign_request"
+    )]
+    pub scope_credentials: bool,
+
     #[clap(
         long,
         help = "Mount file system in read-only mode",
@@ -107,6 +192,15 @@ pub struct CliArgs {
     )]
     pub read_only: bool,
 
+    #[clap(
+        long,
+        help = "Mount file system in write-only mode. Files can be created and written, but not opened for \
+                reading, so object contents are never downloaded",
+        help_heading = MOUNT_OPTIONS_HEADER,
+        conflicts_with = "read_only"
+    )]
+    pub write_only: bool,
+
     #[clap(long, help = "Set the storage class for new objects", help_heading = BUCKET_OPTIONS_HEADER)]
     pub storage_class: Option<String>,
 
@@ -117,6 +211,16 @@ pub struct CliArgs {
     )]
     pub allow_delete: bool,
 
+    #[clap(
+        long,
+        help = "Whether deleting a file deletes its object from S3 (all), \
+                or only hides it from the file system until remount (local-only) [default: all]",
+        help_heading = MOUNT_OPTIONS_HEADER,
+        value_name = "POLICY",
+        requires = "allow_delete",
+    )]
+    pub delete_policy: Option<DeletePolicy>,
+
     #[clap(
         long,
         help = "Allow overwrite operations on file system",
@@ -124,9 +228,137 @@ pub struct CliArgs {
     )]
     pub allow_overwrite: bool,
 
+    #[clap(
+        long,
+        help = "Allow creating symbolic links, stored as empty objects with the link target in their metadata",
+        help_heading = MOUNT_OPTIONS_HEADER
+    )]
+    pub allow_symlinks: bool,
+
+    #[clap(
+        long,
+        help = "Show every key under the prefix as a file at the root of the mount, named after the whole key \
+                with `/` and `%` escaped as `%2F` and `%25`, instead of splitting keys into directories",
+        help_heading = MOUNT_OPTIONS_HEADER
+    )]
+    pub flat_keys: bool,
+
+    #[clap(
+        long,
+        help = "Show a file that is shadowed by a directory of the same name under its name with this suffix added, \
+                like `a.shadowed` for the key `a` alongside `a/b`, instead of hiding it",
+        help_heading = MOUNT_OPTIONS_HEADER,
+        value_name = "SUFFIX",
+        value_parser = parse_shadowed_file_suffix,
+        conflicts_with = "flat_keys",
+    )]
+    pub shadowed_file_suffix: Option<String>,
+
+    #[clap(
+        long,
+        help = "Derive inode numbers from the bucket and key of each file and directory, \
+                so they are the same every time the bucket is mounted",
+        help_heading = MOUNT_OPTIONS_HEADER
+    )]
+    pub stable_inode_numbers: bool,
+
+    #[clap(
+        long,
+        help = "Check whether a file's object was replaced in S3 while the file was being written, \
+                and either overwrite it anyway or fail the upload",
+        help_heading = MOUNT_OPTIONS_HEADER,
+        value_name = "POLICY",
+    )]
+    pub write_conflict_policy: Option<WriteConflictPolicy>,
+
+    #[clap(
+        long,
+        help = "Accept out-of-order writes to files that end up to this many bytes past the data written so far, \
+                and buffer them in memory until the gap before them is filled",
+        default_value = "0",
+        value_name = "BYTES",
+        help_heading = MOUNT_OPTIONS_HEADER
+    )]
+    pub write_reorder_window: u64,
+
+    #[clap(
+        long,
+        help = "Periodically abort old incomplete multipart uploads under the mounted prefix, such as uploads \
+                left behind by a failed write or a crash",
+        help_heading = MOUNT_OPTIONS_HEADER,
+        conflicts_with = "read_only",
+    )]
+    pub cleanup_incomplete_uploads: bool,
+
+    #[clap(
+        long,
+        help = "Time in seconds after an incomplete multipart upload was started before it's aborted. \
+                Must be longer than any client takes to write a file under the mounted prefix",
+        default_value = "86400",
+        value_name = "SECONDS",
+        help_heading = MOUNT_OPTIONS_HEADER,
+        requires = "cleanup_incomplete_uploads",
+    )]
+    pub incomplete_upload_max_age: u64,
+
+    #[clap(
+        long,
+        help = "Local directory whose files shadow objects with the same path in the bucket",
+        help_heading = MOUNT_OPTIONS_HEADER,
+        value_name = "DIRECTORY",
+    )]
+    pub overlay_dir: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "JSON file listing the keys and byte ranges that can be read; other reads fail with EACCES \
+                without making requests to S3",
+        help_heading = MOUNT_OPTIONS_HEADER,
+        value_name = "FILE",
+    )]
+    pub read_policy: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "JSON file assigning owners and permissions to prefixes, which are checked against the user \
+                making each request",
+        help_heading = MOUNT_OPTIONS_HEADER,
+        value_name = "FILE",
+    )]
+    pub identity_map: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Show each object as it was at a point in time, like `latest-at:2024-01-31T12:00:00Z`. \
+                Reads are pinned to that version of the object even if it's overwritten later. \
+                Requires a versioned bucket and --read-only",
+        help_heading = MOUNT_OPTIONS_HEADER,
+        value_name = "POLICY",
+        requires = "read_only",
+        conflicts_with = "allow_symlinks",
+    )]
+    pub object_version_policy: Option<ObjectVersionPolicy>,
+
     #[clap(long, help = "Automatically unmount on exit", help_heading = MOUNT_OPTIONS_HEADER)]
     pub auto_unmount: bool,
 
+    #[clap(
+        long,
+        help = "Serve the file system from a worker process, and restart the worker without unmounting if it crashes. \
+                Requires --stable-inode-numbers [Linux only]",
+        help_heading = MOUNT_OPTIONS_HEADER,
+        requires = "stable_inode_numbers"
+    )]
+    pub restart_on_crash: bool,
+
+    #[clap(
+        long,
+        help = "Don't return file attributes when listing directories. Listings that only need names are \
+                cheaper, but each stat of a listed entry needs a separate lookup",
+        help_heading = MOUNT_OPTIONS_HEADER,
+    )]
+    pub no_readdirplus: bool,
+
     #[clap(long, help = "Allow root user to access file system", help_heading = MOUNT_OPTIONS_HEADER)]
     pub allow_root: bool,
 
@@ -149,13 +381,51 @@ pub struct CliArgs {
 
     #[clap(
         long,
-        help = "Maximum number of FUSE daemon threads",
+        help = "Maximum number of FUSE daemon threads [default: 16]",
         value_name = "N",
-        default_value = "16",
         value_parser = value_parser!(u64).range(1..),
         help_heading = CLIENT_OPTIONS_HEADER
     )]
-    pub max_threads: u64,
+    pub max_threads: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Maximum size of the kernel's readahead for sequential reads, in KiB. \
+                Raising it above the kernel's default requires running as root [default: kernel default]",
+        value_name = "KiB",
+        value_parser = value_parser!(u32).range(1..=1024 * 1024),
+        help_heading = CLIENT_OPTIONS_HEADER
+    )]
+    pub max_readahead: Option<u32>,
+
+    #[clap(
+        long,
+        help = "Maximum number of background requests, like readahead, the kernel sends at once \
+                [default: derived from the target throughput, at least 64]",
+        value_name = "N",
+        value_parser = value_parser!(u16).range(1..),
+        help_heading = CLIENT_OPTIONS_HEADER
+    )]
+    pub max_background: Option<u16>,
+
+    #[clap(
+        long,
+        help = "Number of outstanding background requests at which the kernel starts throttling new ones \
+                [default: 3/4 of --max-background]",
+        value_name = "N",
+        value_parser = value_parser!(u16).range(1..),
+        help_heading = CLIENT_OPTIONS_HEADER
+    )]
+    pub congestion_threshold: Option<u16>,
+
+    #[clap(
+        long,
+        help = "Tune prefetching, metadata caching, and concurrency for a common workload. \
+                Flags that set any of these explicitly take precedence over the preset",
+        value_name = "PRESET",
+        help_heading = CLIENT_OPTIONS_HEADER
+    )]
+    pub profile_preset: Option<ProfilePreset>,
 
     #[clap(
         long,
@@ -166,6 +436,88 @@ pub struct CliArgs {
     )]
     pub part_size: u64,
 
+    #[clap(
+        long,
+        help = "Part size for multi-part GET [default: --part-size]",
+        value_name = "BYTES",
+        value_parser = value_parser!(u64).range(1..),
+        help_heading = CLIENT_OPTIONS_HEADER
+    )]
+    pub read_part_size: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Part size for multi-part PUT, which limits the largest object Mountpoint can write to \
+                10,000 times this size [default: --part-size]",
+        value_name = "BYTES",
+        value_parser = value_parser!(u64).range(1..),
+        help_heading = CLIENT_OPTIONS_HEADER
+    )]
+    pub write_part_size: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Size of the largest object Mountpoint must be able to write. \
+                Fails to mount if the write part size is too small to write it in 10,000 parts",
+        value_name = "BYTES",
+        value_parser = value_parser!(u64).range(1..),
+        help_heading = CLIENT_OPTIONS_HEADER
+    )]
+    pub maximum_object_size: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Maximum number of parts each file being written can buffer in memory. \
+                Once it is reached, writes wait for earlier parts to finish uploading [default: no limit]",
+        value_name = "N",
+        value_parser = value_parser!(u64).range(1..),
+        help_heading = CLIENT_OPTIONS_HEADER
+    )]
+    pub max_buffered_upload_parts: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Maximum number of parts to upload at once for each file being appended to. \
+                The total across all appends is derived from the target throughput",
+        default_value_t = DEFAULT_APPEND_UPLOAD_CONCURRENCY,
+        value_name = "N",
+        value_parser = value_parser!(usize).range(1..=1024),
+        help_heading = CLIENT_OPTIONS_HEADER
+    )]
+    pub append_upload_concurrency: usize,
+
+    #[clap(
+        long,
+        help = "Size of the retry budget, in tokens, shared by all S3 requests. \
+                Once it is exhausted, failed requests are not retried until successful requests replenish it \
+                [default: 500]",
+        value_parser = value_parser!(u64).range(1..),
+        help_heading = CLIENT_OPTIONS_HEADER,
+        value_name = "TOKENS",
+    )]
+    pub retry_budget: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Number of threads for S3 network I/O and prefetching \
+                [default: half the CPUs available to this process, rounded down, and at least 1]",
+        value_name = "N",
+        value_parser = value_parser!(u16).range(1..),
+        help_heading = CLIENT_OPTIONS_HEADER
+    )]
+    pub event_loop_threads: Option<u16>,
+
+    #[clap(
+        long,
+        help = "Target for the total memory used to buffer object data read ahead of applications, in MiB. \
+                Once it is reached, Mountpoint stops reading ahead until applications consume buffered data \
+                [default: 95% of total system memory]",
+        value_name = "MiB",
+        value_parser = value_parser!(u64).range(1..),
+        help_heading = CLIENT_OPTIONS_HEADER
+    )]
+    pub max_memory_target: Option<u64>,
+
     #[clap(
         long,
         help = "Owner UID [default: current user's UID]",
@@ -198,9 +550,33 @@ pub struct CliArgs {
     )]
     pub file_mode: Option<u16>,
 
+    #[clap(
+        long,
+        help = "Total size of the file system reported to tools like `df`, in MiB [default: effectively unlimited]",
+        value_name = "MiB",
+        value_parser = value_parser!(u64).range(1..),
+        help_heading = MOUNT_OPTIONS_HEADER
+    )]
+    pub fs_size: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Free space of the file system reported to tools like `df`, in MiB [default: same as --fs-size]",
+        value_name = "MiB",
+        value_parser = value_parser!(u64),
+        help_heading = MOUNT_OPTIONS_HEADER
+    )]
+    pub fs_free: Option<u64>,
+
     #[clap(short, long, help = "Run as foreground process")]
     pub foreground: bool,
 
+    #[clap(
+        long,
+        help = "Check that the bucket can be mounted with these options, print a report, and exit without mounting"
+    )]
+    pub validate: bool,
+
     #[clap(
         long,
         help = "Account ID of the expected bucket owner. \
@@ -219,9 +595,91 @@ pub struct CliArgs {
     )]
     pub log_directory: Option<PathBuf>,
 
+    #[clap(
+        long,
+        help = "Start a new log file once the current one reaches this size, and compress the old one",
+        help_heading = LOGGING_OPTIONS_HEADER,
+        value_name = "MiB",
+        value_parser = value_parser!(u64).range(1..),
+        requires = "log_directory",
+    )]
+    pub log_max_size: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Start a new log file once the current one is this many seconds old, and compress the old one",
+        help_heading = LOGGING_OPTIONS_HEADER,
+        value_name = "SECONDS",
+        value_parser = value_parser!(u64).range(1..),
+        requires = "log_directory",
+    )]
+    pub log_max_age: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Delete the oldest log files written by this mount once there are more than this many rotated files \
+                [default: keep all]",
+        help_heading = LOGGING_OPTIONS_HEADER,
+        value_name = "N",
+        value_parser = value_parser!(u64).range(1..),
+        requires = "log_directory",
+    )]
+    pub log_max_files: Option<u64>,
+
     #[clap(long, help = "Enable logging of summarized performance metrics", help_heading = LOGGING_OPTIONS_HEADER)]
     pub log_metrics: bool,
 
+    #[clap(
+        long,
+        help = "Format of the metrics logged by --log-metrics: human-readable lines, or CloudWatch Embedded Metric \
+                Format (EMF) JSON [default: log]",
+        help_heading = LOGGING_OPTIONS_HEADER,
+        value_name = "FORMAT"
+    )]
+    pub metrics_format: Option<MetricsFormat>,
+
+    #[clap(
+        long,
+        help = "Append summarized performance metrics to a file as CloudWatch Embedded Metric Format (EMF) JSON lines",
+        help_heading = LOGGING_OPTIONS_HEADER,
+        value_name = "FILE"
+    )]
+    pub metrics_file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Identifier for this mount in the dimensions of EMF metrics [default: the mount point]",
+        help_heading = LOGGING_OPTIONS_HEADER,
+        value_name = "ID"
+    )]
+    pub mount_id: Option<String>,
+
+    #[clap(
+        long,
+        help = "Count the requests and bytes read and written by each process and user accessing the mount, and \
+                include the totals for each user in the metrics",
+        help_heading = LOGGING_OPTIONS_HEADER
+    )]
+    pub io_accounting: bool,
+
+    #[clap(
+        long,
+        help = "Whether the read-only .mountpoint directory describing the mount is available at the root of the \
+                file system, and whether it's listed there [default: off]",
+        value_name = "MODE",
+        help_heading = LOGGING_OPTIONS_HEADER
+    )]
+    pub control_directory: Option<ControlDirectoryMode>,
+
+    #[clap(
+        long,
+        help = "Export traces of file system operations and S3 requests to an OpenTelemetry collector at this \
+                OTLP/HTTP endpoint",
+        help_heading = LOGGING_OPTIONS_HEADER,
+        value_name = "URL"
+    )]
+    pub otlp_endpoint: Option<String>,
+
     #[clap(short, long, help = "Enable debug logging for Mountpoint", help_heading = LOGGING_OPTIONS_HEADER)]
     pub debug: bool,
 
@@ -246,13 +704,33 @@ pub struct CliArgs {
 
     #[clap(
         long,
-        help = "Time-to-live (TTL) for cached metadata in seconds [default: 1s]",
-        value_name = "SECONDS",
-        value_parser = parse_ttl_seconds,
+        help = "Time-to-live (TTL) for cached metadata in seconds, or 'indefinite' to never expire it, or 'minimal' \
+                to check S3 on every lookup [default: 1s with --cache, otherwise minimal]",
+        value_name = "SECONDS|indefinite|minimal",
+        value_parser = parse_ttl,
         help_heading = CACHING_OPTIONS_HEADER,
-        requires = "cache",
     )]
-    pub metadata_ttl: Option<Duration>,
+    pub metadata_ttl: Option<TimeToLive>,
+
+    #[clap(
+        long,
+        help = "Time-to-live (TTL) for cached lookups of names that don't exist, in seconds, or 'indefinite' \
+                to never expire them, or 'minimal' to disable negative caching [default: same as --metadata-ttl]",
+        value_name = "SECONDS|indefinite|minimal",
+        value_parser = parse_ttl,
+        help_heading = CACHING_OPTIONS_HEADER,
+    )]
+    pub negative_metadata_ttl: Option<TimeToLive>,
+
+    #[clap(
+        long,
+        help = "Time-to-live (TTL) for cached metadata of directories, in seconds, or 'indefinite' to never \
+                expire it, or 'minimal' for the default short TTL [default: same as --metadata-ttl]",
+        value_name = "SECONDS|indefinite|minimal",
+        value_parser = parse_ttl,
+        help_heading = CACHING_OPTIONS_HEADER,
+    )]
+    pub dir_metadata_ttl: Option<TimeToLive>,
 
     #[clap(
         long,
@@ -264,6 +742,46 @@ pub struct CliArgs {
     )]
     pub max_cache_size: Option<u64>,
 
+    #[clap(
+        long,
+        help = "Time in seconds to keep cached metadata for files and directories after the kernel evicts them [default: 0s]",
+        value_name = "SECONDS",
+        value_parser = parse_ttl_seconds,
+        help_heading = CACHING_OPTIONS_HEADER,
+        requires = "cache",
+    )]
+    pub forgotten_inode_grace_period: Option<Duration>,
+
+    #[clap(
+        long,
+        help = "Time in milliseconds to reuse file and directory attributes for repeated stat calls, \
+                even when the metadata TTL is zero [default: 0ms]",
+        value_name = "MILLISECONDS",
+        value_parser = value_parser!(u64).range(0..1000),
+        help_heading = CACHING_OPTIONS_HEADER,
+    )]
+    pub attr_cache_ttl_ms: Option<u64>,
+
+    #[clap(
+        long,
+        help = "How much the kernel caches: nothing (none), attributes and directory entries (attrs), \
+                also file contents across opens while objects are unchanged (kernel), \
+                or also directory listings (full) [default: attrs]",
+        value_name = "MODE",
+        help_heading = CACHING_OPTIONS_HEADER,
+    )]
+    pub kernel_cache: Option<KernelCacheMode>,
+
+    #[clap(
+        long,
+        help = "URL of an SQS queue that receives the bucket's S3 event notifications. Cached metadata for \
+                objects that are created, overwritten, or deleted is invalidated as the notifications arrive",
+        value_name = "URL",
+        value_parser = clap::builder::NonEmptyStringValueParser::new(),
+        help_heading = CACHING_OPTIONS_HEADER,
+    )]
+    pub event_queue_url: Option<String>,
+
     #[clap(
         long,
         help = "Configure a string to be prepended to the 'User-Agent' HTTP request header for all S3 requests",
@@ -294,12 +812,21 @@ pub struct CliArgs {
         help_heading = BUCKET_OPTIONS_HEADER,
     )]
     pub disable_upload_checksums: bool,
+
+    #[clap(
+        long,
+        help = "Verify downloaded objects against their S3 additional checksums",
+        help_heading = BUCKET_OPTIONS_HEADER,
+    )]
+    pub verify_download_checksums: bool,
 }
 
 #[derive(Debug, Clone)]
 pub enum BucketType {
     GeneralPurpose,
     Directory,
+    Outposts,
+    ThirdParty,
 }
 
 impl BucketType {
@@ -307,24 +834,206 @@ impl BucketType {
         match self {
             Self::GeneralPurpose => S3Personality::Standard,
             Self::Directory => S3Personality::ExpressOneZone,
+            Self::Outposts => S3Personality::Outposts,
+            Self::ThirdParty => S3Personality::ThirdParty,
         }
     }
 }
 
 impl ValueEnum for BucketType {
     fn value_variants<'a>() -> &'a [Self] {
-        &[Self::GeneralPurpose, Self::Directory]
+        &[Self::GeneralPurpose, Self::Directory, Self::Outposts, Self::ThirdParty]
     }
 
     fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
         match self {
             Self::GeneralPurpose => Some(clap::builder::PossibleValue::new("general-purpose")),
             Self::Directory => Some(clap::builder::PossibleValue::new("directory")),
+            Self::Outposts => Some(clap::builder::PossibleValue::new("outposts")),
+            Self::ThirdParty => Some(clap::builder::PossibleValue::new("third-party")),
         }
     }
 }
 
+/// A coherent bundle of settings tuned for a common workload, so that users don't need to understand
+/// how each of the individual settings interact to get good performance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfilePreset {
+    /// Many concurrent sequential reads of large objects that don't change while mounted, like
+    /// machine learning training data
+    Training,
+    /// Ranged reads of columnar files like Parquet or ORC, which mostly jump between footers and
+    /// column chunks rather than reading whole objects
+    Analytics,
+    /// Bulk copies of whole directory trees in and out of the bucket
+    Archive,
+    /// People browsing and opening files by hand, which should always see fresh metadata and not
+    /// prefetch much more than they read
+    Interactive,
+}
+
+impl ProfilePreset {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Training => "training",
+            Self::Analytics => "analytics",
+            Self::Archive => "archive",
+            Self::Interactive => "interactive",
+        }
+    }
+
+    fn max_threads(&self) -> u64 {
+        match self {
+            Self::Training => 64,
+            Self::Analytics => 32,
+            Self::Archive | Self::Interactive => 16,
+        }
+    }
+
+    /// TTL for cached metadata, if the preset serves lookups from the metadata cache even without
+    /// `--cache`.
+    fn metadata_ttl(&self) -> Option<Duration> {
+        match self {
+            Self::Training => Some(Duration::from_secs(60)),
+            Self::Analytics => Some(Duration::from_secs(5)),
+            Self::Archive | Self::Interactive => None,
+        }
+    }
+
+    fn attr_cache_ttl(&self) -> Duration {
+        match self {
+            Self::Training | Self::Analytics => Duration::ZERO,
+            Self::Archive => Duration::from_millis(500),
+            Self::Interactive => Duration::from_millis(200),
+        }
+    }
+
+    fn prefetcher_config(&self) -> PrefetcherConfig {
+        let default = PrefetcherConfig::default();
+        match self {
+            Self::Training | Self::Archive => default,
+            Self::Analytics => PrefetcherConfig {
+                max_request_size: 64 * 1024 * 1024,
+                sequential_prefetch_multiplier: 2,
+                max_forward_seek_wait_distance: 2 * 1024 * 1024,
+                ..default
+            },
+            Self::Interactive => PrefetcherConfig {
+                max_request_size: 16 * 1024 * 1024,
+                sequential_prefetch_multiplier: 2,
+                ..default
+            },
+        }
+    }
+}
+
+impl ValueEnum for ProfilePreset {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Training, Self::Analytics, Self::Archive, Self::Interactive]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(clap::builder::PossibleValue::new(self.as_str()))
+    }
+}
+
+impl ValueEnum for DeletePolicy {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::All, Self::LocalOnly]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(clap::builder::PossibleValue::new(self.as_str()))
+    }
+}
+
+impl ValueEnum for ControlDirectoryMode {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Off, Self::Hidden, Self::Visible]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(clap::builder::PossibleValue::new(self.as_str()))
+    }
+}
+
+impl ValueEnum for KernelCacheMode {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::None, Self::Attrs, Self::Kernel, Self::Full]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(clap::builder::PossibleValue::new(self.as_str()))
+    }
+}
+
+impl ValueEnum for WriteConflictPolicy {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Overwrite, Self::Fail]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(clap::builder::PossibleValue::new(self.as_str()))
+    }
+}
+
+impl ValueEnum for MetricsFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Log, Self::Emf]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(clap::builder::PossibleValue::new(self.as_str()))
+    }
+}
+
 impl CliArgs {
+    /// Parse the command-line arguments, after expanding any `-o` mount options and merging in the
+    /// options from the `--config-file`, if one is given. Like [Parser::parse], exits the process if
+    /// the arguments are invalid.
+    pub fn parse_from_env() -> anyhow::Result<Self> {
+        let cli_args = rewrite_mount_options(env::args_os())?;
+        let args = Self::parse_args(&cli_args);
+        let Some(config_file) = &args.config_file else {
+            return Ok(args);
+        };
+        let file_args = load_config_file(config_file, &Self::command())?;
+        let (program, cli_args) = cli_args.split_first().expect("arguments include the program name");
+        let mut merged = Self::parse_args(std::iter::once(program).chain(&file_args).chain(cli_args));
+        // Repeated prefixes add up rather than overriding each other, so prefixes from the command
+        // line need to replace the file's explicitly
+        if !args.prefix.is_empty() {
+            merged.prefix = args.prefix;
+        }
+        Ok(merged)
+    }
+
+    /// Like [Parser::parse_from], exiting the process if the arguments are invalid.
+    fn parse_args<T: Into<OsString>>(args: impl IntoIterator<Item = T>) -> Self {
+        Self::try_parse_args(args).unwrap_or_else(|e| e.exit())
+    }
+
+    /// Like [Parser::try_parse_from], but the bucket name is required unless `--bucket` is given.
+    /// Clap can only make a positional argument optional for every command line, and if it were,
+    /// an option between the bucket name and the mount point, like
+    /// `mount-s3 DOC-EXAMPLE-BUCKET --read-only /mnt`, would make clap take the bucket name for the
+    /// mount point.
+    fn try_parse_args<T: Into<OsString>>(args: impl IntoIterator<Item = T>) -> Result<Self, clap::Error> {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let uses_bucket_option = args
+            .iter()
+            .filter_map(|arg| arg.to_str())
+            .any(|arg| arg == "--bucket" || arg.starts_with("--bucket="));
+        let mut command = Self::command();
+        if !uses_bucket_option {
+            command = command
+                .allow_missing_positional(false)
+                .mut_arg("bucket_name", |arg| arg.required(true));
+        }
+        let matches = command.try_get_matches_from(args)?;
+        Self::from_arg_matches(&matches)
+    }
+
     fn addressing_style(&self) -> AddressingStyle {
         if self.force_path_style {
             AddressingStyle::Path
@@ -333,8 +1042,94 @@ impl CliArgs {
         }
     }
 
+    fn max_threads(&self) -> u64 {
+        self.max_threads
+            .or(self.profile_preset.map(|preset| preset.max_threads()))
+            .unwrap_or(16)
+    }
+
+    fn read_part_size(&self) -> u64 {
+        self.read_part_size.unwrap_or(self.part_size)
+    }
+
+    fn write_part_size(&self) -> u64 {
+        self.write_part_size.unwrap_or(self.part_size)
+    }
+
+    /// The prefetcher's memory target in bytes
+    fn max_memory_target(&self) -> u64 {
+        if let Some(target_mib) = self.max_memory_target {
+            return target_mib * 1024 * 1024;
+        }
+        let mut sys = System::new();
+        sys.refresh_memory();
+        (sys.total_memory() as f64 * 0.95) as u64
+    }
+
+    /// The bucket the root of the mount is backed by. With `--bucket`, the root isn't backed by any
+    /// bucket, and this is the first one, which requests go to by default.
+    pub fn bucket_name(&self) -> &str {
+        match &self.bucket_name {
+            Some(bucket_name) => bucket_name,
+            None => &self.bucket.first().expect("clap requires a bucket").bucket,
+        }
+    }
+
+    /// Every mounted bucket, without duplicates, starting with [Self::bucket_name]
+    pub fn buckets(&self) -> Vec<&str> {
+        let mut buckets = vec![self.bucket_name()];
+        for entry in &self.bucket {
+            if !buckets.contains(&entry.bucket.as_str()) {
+                buckets.push(&entry.bucket);
+            }
+        }
+        buckets
+    }
+
+    /// The prefix the root of the mount is backed by. With several prefixes, there isn't one, and
+    /// the root only contains the directories for each prefix.
     fn prefix(&self) -> Prefix {
-        self.prefix.as_ref().cloned().unwrap_or_default()
+        match &self.prefix[..] {
+            [prefix] => prefix.clone(),
+            _ => Prefix::default(),
+        }
+    }
+
+    /// The buckets and prefixes under which the mount can see keys
+    fn mounted_prefixes(&self) -> Vec<(String, Prefix)> {
+        if !self.bucket.is_empty() {
+            self.bucket
+                .iter()
+                .map(|entry| (entry.bucket.clone(), entry.prefix.clone()))
+                .collect()
+        } else if self.prefix.is_empty() {
+            vec![(self.bucket_name().to_owned(), Prefix::default())]
+        } else {
+            self.prefix
+                .iter()
+                .map(|prefix| (self.bucket_name().to_owned(), prefix.clone()))
+                .collect()
+        }
+    }
+
+    /// The composite namespace to mount, if mounting several prefixes or buckets
+    fn namespace(&self) -> anyhow::Result<Option<CompositeNamespace>> {
+        if !self.bucket.is_empty() {
+            let namespace = CompositeNamespace::for_buckets(self.bucket.iter().cloned()).context("invalid --bucket")?;
+            return Ok(Some(namespace));
+        }
+        if self.prefix.len() < 2 {
+            return Ok(None);
+        }
+        if self.scope_credentials {
+            return Err(anyhow!("--scope-credentials can only be used with a single --prefix"));
+        }
+        if self.overlay_dir.is_some() {
+            return Err(anyhow!("--overlay-dir can only be used with a single --prefix"));
+        }
+        let namespace =
+            CompositeNamespace::new(self.bucket_name(), self.prefix.iter().cloned()).context("invalid --prefix")?;
+        Ok(Some(namespace))
     }
 
     fn logging_config(&self) -> LoggingConfig {
@@ -351,22 +1146,97 @@ impl CliArgs {
             if self.log_metrics {
                 filter.push_str(&format!(",{}=info", metrics::TARGET_NAME));
             }
+            filter.push_str(&format!(",{}=info", MOUNT_SUMMARY_TARGET_NAME));
             filter
         };
 
         LoggingConfig {
             log_directory: self.log_directory.clone(),
-            log_to_stdout: self.foreground,
+            log_rotation: LogRotation {
+                max_size: self.log_max_size.map(|mib| mib * 1024 * 1024),
+                max_age: self.log_max_age.map(Duration::from_secs),
+                max_files: self.log_max_files.map(|n| n as usize),
+            },
+            // Validation reports problems to the terminal it was run from
+            log_to_stdout: self.foreground || self.validate,
             default_filter,
+            otlp_endpoint: self.otlp_endpoint.clone(),
+        }
+    }
+
+    fn metrics_config(&self) -> MetricsConfig {
+        let mount_id = self
+            .mount_id
+            .clone()
+            .unwrap_or_else(|| self.mount_point.display().to_string());
+        MetricsConfig {
+            log_format: self.metrics_format.unwrap_or_default(),
+            emf_file: self.metrics_file.clone(),
+            emf_dimensions: vec![
+                ("Bucket".to_owned(), self.bucket_name().to_owned()),
+                ("MountId".to_owned(), mount_id),
+            ],
+        }
+    }
+
+    fn auth_config(&self) -> S3ClientAuthConfig {
+        let profile = credentials::selected_profile(self.profile.as_deref());
+        let auth_config = if self.no_sign_request {
+            S3ClientAuthConfig::NoSigning
+        } else if env::var_os("AWS_ACCESS_KEY_ID").is_none() && credentials::profile_uses_sso(&profile) {
+            // The default chain doesn't support SSO profiles, but credentials in the environment
+            // would still take precedence over one
+            S3ClientAuthConfig::Sso(profile)
+        } else if let Some(profile_name) = &self.profile {
+            S3ClientAuthConfig::Profile(profile_name.to_owned())
+        } else {
+            S3ClientAuthConfig::Default
+        };
+        match &self.role_arn {
+            Some(role_arn) => S3ClientAuthConfig::AssumeRole {
+                role_arn: role_arn.to_owned(),
+                session_name: self.role_session_name(),
+                source: Box::new(auth_config),
+            },
+            None => auth_config,
+        }
+    }
+
+    fn access_mode(&self) -> AccessMode {
+        if self.read_only {
+            AccessMode::ReadOnly
+        } else if self.write_only {
+            AccessMode::WriteOnly
+        } else {
+            AccessMode::ReadWrite
         }
     }
 
+    fn role_session_name(&self) -> String {
+        const DEFAULT_ROLE_SESSION_NAME: &str = "mountpoint-s3";
+
+        self.role_session_name
+            .clone()
+            .unwrap_or_else(|| DEFAULT_ROLE_SESSION_NAME.to_owned())
+    }
+
     /// Human-readable description of the bucket being mounted
     fn bucket_description(&self) -> String {
-        if let Some(prefix) = self.prefix.as_ref() {
-            format!("prefix {} of bucket {}", prefix, self.bucket_name)
-        } else {
-            format!("bucket {}", self.bucket_name)
+        if !self.bucket.is_empty() {
+            let buckets: Vec<_> = self
+                .bucket
+                .iter()
+                .map(|entry| format!("s3://{}/{}", entry.bucket, entry.prefix))
+                .collect();
+            return format!("buckets {}", buckets.join(", "));
+        }
+        match &self.prefix[..] {
+            [] => format!("bucket {}", self.bucket_name()),
+            [prefix] => format!("prefix {} of bucket {}", prefix, self.bucket_name()),
+            prefixes => {
+                let prefixes: Vec<_> = prefixes.iter().map(Prefix::as_str).collect();
+                format!("prefixes {} of bucket {}", prefixes.join(", "), self.bucket_name())
+            }
         }
     }
 
@@ -391,39 +1261,77 @@ impl CliArgs {
         }
 
         let mount_point = self.mount_point.to_owned();
-        let max_threads = self.max_threads as usize;
+        let max_threads = self.max_threads() as usize;
+        let event_queue = self.event_queue_url.as_ref().map(|queue_url| EventQueueConfig {
+            queue_url: queue_url.clone(),
+            credentials: QueueCredentials::Auth(self.auth_config()),
+        });
+        let incomplete_upload_max_age = self
+            .cleanup_incomplete_uploads
+            .then(|| Duration::from_secs(self.incomplete_upload_max_age));
         FuseSessionConfig {
             mount_point,
             options,
             max_threads,
+            event_queue,
+            incomplete_upload_max_age,
         }
     }
+
+    /// The number of threads for the S3 client's event loop group. The CRT's own default counts
+    /// every processor on the host, which oversubscribes containers limited by a CPU quota, so
+    /// instead we default to half the parallelism actually available to this process, rounded
+    /// down, and at least 1.
+    pub fn event_loop_threads(&self) -> u16 {
+        self.event_loop_threads.unwrap_or_else(|| {
+            let available = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+            (available / 2).clamp(1, u16::MAX as usize) as u16
+        })
+    }
 }
 
 pub fn main<ClientBuilder, Client, Runtime>(client_builder: ClientBuilder) -> anyhow::Result<()>
 where
-    ClientBuilder: FnOnce(&CliArgs) -> anyhow::Result<(Client, Runtime, S3Personality)>,
+    ClientBuilder: FnOnce(&CliArgs) -> anyhow::Result<(Client, Runtime, S3Personality, ClientDetails)>,
     Client: ObjectClient + Send + Sync + 'static,
-    Runtime: Spawn + Send + Sync + 'static,
+    Runtime: Spawn + Clone + Send + Sync + 'static,
 {
-    let args = CliArgs::parse();
+    let args = CliArgs::parse_from_env()?;
+    if args.validate {
+        init_logging(args.logging_config()).context("failed to initialize logging")?;
+        return validate(args, client_builder);
+    }
+
+    // Set if we were started by a supervisor to serve its mount (see `--restart-on-crash`)
+    let worker = WorkerConnection::from_env()?;
     let successful_mount_msg = format!(
         "{} is mounted at {}",
         args.bucket_description(),
         args.mount_point.display()
     );
 
-    if args.foreground {
+    if args.foreground || worker.is_some() {
         init_logging(args.logging_config()).context("failed to initialize logging")?;
 
-        let _metrics = metrics::install();
+        let _metrics = metrics::install(args.metrics_config())?;
 
         // mount file system as a foreground process
-        let session = mount(args, client_builder)?;
+        let is_worker = worker.is_some();
+        if is_worker {
+            supervisor::abort_on_panic();
+        }
+        let mount_point = args.mount_point.clone();
+        let mut session = mount(args, client_builder, worker).map_err(exit_on_kernel_feature_error)?;
 
-        println!("{successful_mount_msg}");
+        if !is_worker {
+            println!("{successful_mount_msg}");
+        }
+        daemon::notify_ready();
+        daemon::start_watchdog(&mut session, &mount_point);
+        session.run_on_close(Box::new(daemon::notify_stopping));
 
         session.join().context("failed to join session")?;
+        shutdown_otlp();
     } else {
         // mount file system as a background process
 
@@ -440,12 +1348,25 @@ where
         let pid = unsafe { nix::unistd::fork() };
         match pid.expect("Failed to fork mount process") {
             ForkResult::Child => {
-                let args = CliArgs::parse();
+                // Start a new session so that we're detached from the terminal and don't get its
+                // hangups, then fork again so that the daemon isn't a session leader and can never
+                // acquire a controlling terminal. The new session's process group still has the
+                // intermediate process's PID, so the parent can signal the daemon through it.
+                nix::unistd::setsid().context("Failed to create a new session")?;
+                // SAFETY: We're still single-threaded, and the intermediate process exits immediately.
+                if let ForkResult::Parent { .. } =
+                    unsafe { nix::unistd::fork() }.context("Failed to fork mount process")?
+                {
+                    std::process::exit(0);
+                }
+
+                let args = CliArgs::parse_from_env()?;
                 init_logging(args.logging_config()).context("failed to initialize logging")?;
 
-                let _metrics = metrics::install();
+                let _metrics = metrics::install(args.metrics_config())?;
 
-                let session = mount(args, client_builder);
+                let mount_point = args.mount_point.clone();
+                let session = mount(args, client_builder, None);
 
                 // close unused file descriptor, we only write from this end.
                 nix::unistd::close(read_fd).context("Failed to close unused file descriptor")?;
@@ -455,32 +1376,38 @@ where
 
                 let status_success = [b'0'];
                 let status_failure = [b'1'];
+                let status_kernel_feature_failure = [b'2'];
 
                 match session {
-                    Ok(session) => {
+                    Ok(mut session) => {
                         pipe_file
                             .write(&status_success)
                             .context("Failed to write data to the pipe")?;
                         drop(pipe_file);
+                        daemon::notify_ready();
+                        daemon::start_watchdog(&mut session, &mount_point);
+                        session.run_on_close(Box::new(daemon::notify_stopping));
 
                         // Logging is set up and the mount succeeded, so we can hang up
                         // stdin/out/err now to cleanly daemonize ourselves
-                        nix::unistd::close(std::io::stdin().as_raw_fd()).context("couldn't close stdin")?;
-                        nix::unistd::close(std::io::stdout().as_raw_fd()).context("couldn't close stdout")?;
-                        nix::unistd::close(std::io::stderr().as_raw_fd()).context("couldn't close stderr")?;
+                        daemon::redirect_stdio_to_null()?;
 
                         session.join().context("failed to join session")?;
+                        shutdown_otlp();
                     }
                     Err(e) => {
-                        pipe_file
-                            .write(&status_failure)
-                            .context("Failed to write data to the pipe")?;
+                        let status = if e.downcast_ref::<KernelFeatureError>().is_some() {
+                            &status_kernel_feature_failure
+                        } else {
+                            &status_failure
+                        };
+                        pipe_file.write(status).context("Failed to write data to the pipe")?;
                         return Err(anyhow!(e));
                     }
                 }
             }
             ForkResult::Parent { child } => {
-                let args = CliArgs::parse();
+                let args = CliArgs::parse_from_env()?;
 
                 init_logging(args.logging_config()).context("failed to initialize logging")?;
                 // close unused file descriptor, we only read from this end.
@@ -513,13 +1440,17 @@ where
                         println!("{successful_mount_msg}");
                         tracing::debug!("success status flag received from child process")
                     }
+                    Ok('2') => {
+                        nix::sys::wait::waitpid(child, None).context("Failed to wait for child process to exit")?;
+                        std::process::exit(KERNEL_FEATURE_ERROR_EXIT_CODE);
+                    }
                     Ok(_) => {
                         nix::sys::wait::waitpid(child, None).context("Failed to wait for child process to exit")?;
                         return Err(anyhow!("Failed to create mount process"));
                     }
                     Err(_timeout_err) => {
-                        // kill child process before returning error.
-                        if let Err(e) = nix::sys::signal::kill(child, Signal::SIGTERM) {
+                        // kill the daemon, which is in the process group of the child, before returning error.
+                        if let Err(e) = nix::sys::signal::killpg(child, Signal::SIGTERM) {
                             tracing::error!("Unable to kill hanging child process with SIGTERM: {:?}", e);
                         }
                         return Err(anyhow!(
@@ -535,8 +1466,83 @@ where
     Ok(())
 }
 
-/// Create a real S3 client
-pub fn create_s3_client(args: &CliArgs) -> anyhow::Result<(S3CrtClient, EventLoopGroup, S3Personality)> {
+/// Check that we could mount the bucket with these options, without mounting it, and print a report
+/// of what we found. Building the client resolves credentials, detects the bucket's region, and
+/// sends requests to the bucket, so this fails if any of those would stop the mount.
+fn validate<ClientBuilder, Client, Runtime>(args: CliArgs, client_builder: ClientBuilder) -> anyhow::Result<()>
+where
+    ClientBuilder: FnOnce(&CliArgs) -> anyhow::Result<(Client, Runtime, S3Personality, ClientDetails)>,
+    Client: ObjectClient + Send + Sync + 'static,
+    Runtime: Spawn + Send + Sync + 'static,
+{
+    tracing::info!("mount-s3 {}", build_info::FULL_VERSION);
+    tracing::debug!("{:?}", args);
+
+    validate_sse_args(args.sse.as_deref(), args.sse_kms_key_id.as_deref())?;
+    validate_maximum_object_size(args.write_part_size(), args.maximum_object_size)?;
+    args.namespace()?;
+    // Report a problem with the mount point alongside what we learned about the bucket, rather than
+    // stopping before we've checked the bucket
+    let mount_point_check = validate_mount_point(&args.mount_point)
+        .and_then(|()| match &args.overlay_dir {
+            Some(overlay_dir) => validate_overlay_dir(overlay_dir, &args.mount_point),
+            None => Ok(()),
+        })
+        .and_then(|()| validate_allow_other(args.allow_other, args.allow_root));
+
+    let (_client, _runtime, s3_personality, client_details) = client_builder(&args)?;
+    validate_sse_for_personality(args.sse.as_deref(), s3_personality)?;
+
+    let mut credentials = describe_auth_config(&args.auth_config());
+    if args.scope_credentials {
+        credentials.push_str(", scoped to the mount with a session policy");
+    }
+    if args.bucket.is_empty() {
+        println!("bucket: {}", args.bucket_name());
+        for (_, prefix) in args.mounted_prefixes() {
+            println!("prefix: {prefix}");
+        }
+    } else {
+        for entry in &args.bucket {
+            println!("bucket: {}=s3://{}/{}", entry.name, entry.bucket, entry.prefix);
+        }
+    }
+    println!("region: {}", client_details.region);
+    println!("S3 personality: {s3_personality:?}");
+    println!("credentials: {credentials}");
+    println!("target throughput: {} Gbps", client_details.throughput_target_gbps);
+    match &mount_point_check {
+        Ok(()) => println!("mount point: {}", args.mount_point.display()),
+        Err(e) => println!("mount point: {e:#}"),
+    }
+    mount_point_check?;
+
+    println!(
+        "{} can be mounted at {}",
+        args.bucket_description(),
+        args.mount_point.display()
+    );
+    Ok(())
+}
+
+/// Describe where the client will get its credentials from, for the `--validate` report.
+fn describe_auth_config(auth_config: &S3ClientAuthConfig) -> String {
+    match auth_config {
+        S3ClientAuthConfig::Default => "default credential provider chain".to_owned(),
+        S3ClientAuthConfig::NoSigning => "none, requests are not signed".to_owned(),
+        S3ClientAuthConfig::Profile(profile) => format!("profile {profile}"),
+        S3ClientAuthConfig::Sso(profile) => format!("IAM Identity Center (SSO) with profile {profile}"),
+        S3ClientAuthConfig::Provider(_) => "custom credentials provider".to_owned(),
+        S3ClientAuthConfig::AssumeRole { role_arn, source, .. } => {
+            format!("role {role_arn}, assumed with {}", describe_auth_config(source))
+        }
+    }
+}
+
+/// Create a real S3 client, with a client of its own for each mounted bucket
+pub fn create_s3_client(
+    args: &CliArgs,
+) -> anyhow::Result<(BucketRouter<S3CrtClient>, EventLoopGroup, S3Personality, ClientDetails)> {
     const DEFAULT_TARGET_THROUGHPUT: f64 = 10.0;
 
     // Placeholder region will be filled in by [create_client_for_bucket]
@@ -560,12 +1566,22 @@ pub fn create_s3_client(args: &CliArgs) -> anyhow::Result<(S3CrtClient, EventLoo
     });
     tracing::info!("target network throughput {throughput_target_gbps} Gbps");
 
-    let auth_config = if args.no_sign_request {
-        S3ClientAuthConfig::NoSigning
-    } else if let Some(profile_name) = &args.profile {
-        S3ClientAuthConfig::Profile(profile_name.to_owned())
+    let (auth_config, scoped_credentials) = if args.scope_credentials {
+        let config = ScopedCredentialsConfig {
+            bucket: args.bucket_name().to_owned(),
+            prefix: args.prefix().to_string(),
+            read_only: args.read_only,
+            role_arn: args.role_arn.clone(),
+            session_name: args.role_session_name(),
+            profile: args.profile.clone(),
+            region: args.region.clone(),
+            event_queue_url: args.event_queue_url.clone(),
+        };
+        let credentials = scoped_credentials(config).context("Failed to get credentials scoped to the mount")?;
+        let provider = credentials.crt_provider()?;
+        (S3ClientAuthConfig::Provider(provider), Some(credentials))
     } else {
-        S3ClientAuthConfig::Default
+        (args.auth_config(), None)
     };
 
     let user_agent_prefix = if let Some(custom_prefix) = &args.user_agent_prefix {
@@ -577,18 +1593,27 @@ pub fn create_s3_client(args: &CliArgs) -> anyhow::Result<(S3CrtClient, EventLoo
     if args.read_only {
         user_agent.value("mp-readonly");
     }
+    if args.write_only {
+        user_agent.value("mp-writeonly");
+    }
 
     if args.cache.is_some() {
         user_agent.value("mp-cache");
-        if let Some(ttl) = args.metadata_ttl {
-            user_agent.key_value("mp-cache-ttl", &ttl.as_secs().to_string());
-        }
+    }
+    if let Some(ttl) = args.metadata_ttl {
+        let ttl = match ttl {
+            TimeToLive::Minimal => "minimal".to_string(),
+            TimeToLive::Indefinite => "indefinite".to_string(),
+            TimeToLive::Duration(duration) => duration.as_secs().to_string(),
+        };
+        user_agent.key_value("mp-cache-ttl", &ttl);
     }
 
     let mut client_config = S3ClientConfig::new()
         .auth_config(auth_config)
         .throughput_target_gbps(throughput_target_gbps)
-        .part_size(args.part_size as usize)
+        .read_part_size(args.read_part_size() as usize)
+        .write_part_size(args.write_part_size() as usize)
         .user_agent(user_agent);
     if args.requester_pays {
         client_config = client_config.request_payer("requester");
@@ -600,41 +1625,190 @@ pub fn create_s3_client(args: &CliArgs) -> anyhow::Result<(S3CrtClient, EventLoo
     // let's be more stubborn than the SDK default. With the CRT defaults of 500ms backoff, full
     // jitter, and 20s max backoff time, 10 attempts will take an average of 55 seconds.
     client_config = client_config.max_attempts(NonZeroUsize::new(10).unwrap());
+    if let Some(retry_budget) = args.retry_budget {
+        client_config = client_config.retry_budget(NonZeroUsize::new(retry_budget as usize).unwrap());
+    }
+    client_config = client_config.event_loop_threads(NonZeroU16::new(args.event_loop_threads()).unwrap());
+    if let Some(max_buffered_upload_parts) = args.max_buffered_upload_parts {
+        client_config =
+            client_config.max_buffered_upload_parts(NonZeroUsize::new(max_buffered_upload_parts as usize).unwrap());
+    }
+
+    // Each bucket gets its own client, so that each one targets the bucket's own region
+    let mut buckets = args.buckets().into_iter();
+    let (default_client, region, s3_personality) = create_client_for_mounted_bucket(
+        args,
+        buckets.next().expect("at least one bucket is mounted"),
+        &endpoint_config,
+        &client_config,
+        &instance_info,
+    )?;
+    let mut client = BucketRouter::new(default_client);
+    for bucket in buckets {
+        let (bucket_client, bucket_region, bucket_personality) =
+            create_client_for_mounted_bucket(args, bucket, &endpoint_config, &client_config, &instance_info)?;
+        // The file system behaves the same way in every bucket, so they all need to be the same type
+        if std::mem::discriminant(&bucket_personality) != std::mem::discriminant(&s3_personality) {
+            return Err(anyhow!(
+                "bucket {bucket} ({bucket_personality:?}) can't be mounted together with bucket {} ({s3_personality:?})",
+                args.bucket_name()
+            ));
+        }
+        tracing::info!("bucket {bucket} is in region {bucket_region}");
+        client.add_bucket(bucket, bucket_client);
+    }
+    let runtime = client.default_client().event_loop_group();
+    let client_details = ClientDetails {
+        region,
+        throughput_target_gbps,
+        scoped_credentials,
+    };
+
+    Ok((client, runtime, s3_personality, client_details))
+}
 
+/// Create the client for one of the mounted buckets, and find out the region it targets and the
+/// bucket's personality.
+fn create_client_for_mounted_bucket(
+    args: &CliArgs,
+    bucket: &str,
+    endpoint_config: &EndpointConfig,
+    client_config: &S3ClientConfig,
+    instance_info: &InstanceInfo,
+) -> anyhow::Result<(S3CrtClient, String, S3Personality)> {
+    // Listing any one of the mounted prefixes is enough to check we can reach the bucket
+    let (_, prefix) = args
+        .mounted_prefixes()
+        .into_iter()
+        .find(|(mounted_bucket, _)| mounted_bucket == bucket)
+        .expect("every bucket has a mounted prefix");
     let client = create_client_for_bucket(
-        &args.bucket_name,
-        &args.prefix(),
+        bucket,
+        &prefix,
         args.region.clone(),
         args.endpoint_url.clone(),
-        endpoint_config,
-        client_config,
-        &instance_info,
+        endpoint_config.clone(),
+        client_config.clone(),
+        instance_info,
     )
+    .map_err(|e| add_requester_pays_hint(e, args.requester_pays))
+    .map_err(|e| add_mrap_alias_hint(e, bucket))
+    .map_err(|e| add_credentials_hint(e, args.profile.as_deref()))
     .context("Failed to create S3 client")?;
-    let runtime = client.event_loop_group();
-    let s3_personality = infer_s3_personality(args.bucket_type.clone(), &args.bucket_name, client.endpoint_config());
+    // Creating the client only listed the prefix, so check the bucket itself too when validating.
+    // Scoped credentials can only list the prefix, so they'd never be allowed to.
+    if args.validate && !args.scope_credentials {
+        futures::executor::block_on(client.head_bucket(bucket))
+            .with_context(|| format!("HeadBucket failed for bucket {bucket}"))?;
+    }
+    let endpoint_config = client.endpoint_config();
+    let region = endpoint_config.get_region().to_owned();
+    let s3_personality = infer_s3_personality(args.bucket_type.clone(), bucket, endpoint_config);
+    Ok((client, region, s3_personality))
+}
+
+/// Requester Pays buckets deny every request that doesn't set `x-amz-request-payer`, which looks
+/// just like a missing permission. If access to the bucket was denied and `--requester-pays` isn't
+/// set, suggest it as a possible cause.
+fn add_requester_pays_hint(error: anyhow::Error, requester_pays: bool) -> anyhow::Error {
+    let forbidden = matches!(
+        error.downcast_ref::<ObjectClientError<ListObjectsError, S3RequestError>>(),
+        Some(ObjectClientError::ClientError(S3RequestError::Forbidden(..)))
+    );
+    if forbidden && !requester_pays {
+        error.context("Access denied. If this is a Requester Pays bucket, use the --requester-pays flag")
+    } else {
+        error
+    }
+}
+
+/// Multi-Region Access Points can only be addressed by their ARN, but their alias looks like a
+/// bucket name, so S3 treats it as one. If the bucket looks like an alias, suggest the ARN instead.
+fn add_mrap_alias_hint(error: anyhow::Error, bucket: &str) -> anyhow::Error {
+    let mrap_alias_regex = Regex::new(r"^[a-z0-9]+\.mrap$").unwrap();
+    if mrap_alias_regex.is_match(bucket) {
+        error.context(format!(
+            "Multi-Region Access Point aliases can't be mounted. \
+            Use the access point's ARN instead, like arn:aws:s3::<account-id>:accesspoint/{bucket}"
+        ))
+    } else {
+        error
+    }
+}
+
+/// The CRT's credentials provider chain doesn't say why it couldn't find credentials, so if that's
+/// why the mount failed, list the sources of credentials we looked in and how each was configured.
+fn add_credentials_hint(error: anyhow::Error, profile: Option<&str>) -> anyhow::Error {
+    let no_credentials = matches!(
+        error.downcast_ref::<ObjectClientError<ListObjectsError, S3RequestError>>(),
+        Some(ObjectClientError::ClientError(S3RequestError::NoSigningCredentials))
+    );
+    if !no_credentials {
+        return error;
+    }
+    let profile = credentials::selected_profile(profile);
+    let sources = credentials::describe_credential_sources(&profile);
+    error.context(format!(
+        "No AWS credentials found. Mountpoint looked for credentials in:\n  - {}",
+        sources.join("\n  - ")
+    ))
+}
 
-    Ok((client, runtime, s3_personality))
+/// If the mount failed because the kernel is missing a required FUSE feature, report the error and
+/// exit with [KERNEL_FEATURE_ERROR_EXIT_CODE]. Otherwise, return the error to the caller.
+fn exit_on_kernel_feature_error(error: anyhow::Error) -> anyhow::Error {
+    if error.downcast_ref::<KernelFeatureError>().is_some() {
+        eprintln!("Error: {error:?}");
+        std::process::exit(KERNEL_FEATURE_ERROR_EXIT_CODE);
+    }
+    error
 }
 
-fn mount<ClientBuilder, Client, Runtime>(args: CliArgs, client_builder: ClientBuilder) -> anyhow::Result<FuseSession>
+fn mount<ClientBuilder, Client, Runtime>(
+    args: CliArgs,
+    client_builder: ClientBuilder,
+    worker: Option<WorkerConnection>,
+) -> anyhow::Result<FuseSession>
 where
-    ClientBuilder: FnOnce(&CliArgs) -> anyhow::Result<(Client, Runtime, S3Personality)>,
+    ClientBuilder: FnOnce(&CliArgs) -> anyhow::Result<(Client, Runtime, S3Personality, ClientDetails)>,
     Client: ObjectClient + Send + Sync + 'static,
-    Runtime: Spawn + Send + Sync + 'static,
+    Runtime: Spawn + Clone + Send + Sync + 'static,
 {
     tracing::info!("mount-s3 {}", build_info::FULL_VERSION);
     tracing::debug!("{:?}", args);
 
-    validate_mount_point(&args.mount_point)?;
+    // A worker's mount point is already mounted by its supervisor, and checking it would send
+    // requests to a file system nobody is serving yet
+    if worker.is_none() {
+        validate_mount_point(&args.mount_point)?;
+        if let Some(overlay_dir) = &args.overlay_dir {
+            validate_overlay_dir(overlay_dir, &args.mount_point)?;
+        }
+        validate_allow_other(args.allow_other, args.allow_root)?;
+    }
     {
         validate_sse_args(args.sse.as_deref(), args.sse_kms_key_id.as_deref())?;
+        validate_maximum_object_size(args.write_part_size(), args.maximum_object_size)?;
+    }
+    let namespace = args.namespace()?;
+
+    if args.restart_on_crash && worker.is_none() {
+        let fuse_config = args.fuse_session_config();
+        return supervisor::start(&fuse_config.mount_point, &fuse_config.options, args.foreground);
     }
 
-    let (client, runtime, s3_personality) = client_builder(&args)?;
+    let (client, runtime, s3_personality, client_details) = client_builder(&args)?;
+    validate_sse_for_personality(args.sse.as_deref(), s3_personality)?;
+    let bucket_name = args.bucket_name().to_owned();
+    let prefix = args.prefix();
 
     let bucket_description = args.bucket_description();
-    let fuse_config = args.fuse_session_config();
+    let mut fuse_config = args.fuse_session_config();
+    if let (Some(event_queue), Some(credentials)) = (&mut fuse_config.event_queue, &client_details.scoped_credentials) {
+        event_queue.credentials = QueueCredentials::Scoped(credentials.clone());
+    }
+    let throughput_target_gbps = client_details.throughput_target_gbps;
+    let mut mount_summary = MountSummary::new(&args, s3_personality, client_details);
 
     let mut filesystem_config = S3FilesystemConfig::default();
     if let Some(uid) = args.uid {
@@ -649,9 +1823,25 @@ where
     if let Some(file_mode) = args.file_mode {
         filesystem_config.file_mode = file_mode;
     }
+    if let Some(fs_size_mib) = args.fs_size {
+        filesystem_config.fs_size = fs_size_mib.saturating_mul(1024 * 1024);
+        filesystem_config.fs_free = filesystem_config.fs_size;
+    }
+    if let Some(fs_free_mib) = args.fs_free {
+        filesystem_config.fs_free = fs_free_mib.saturating_mul(1024 * 1024);
+    }
     filesystem_config.storage_class = args.storage_class;
+    filesystem_config.access_mode = args.access_mode();
     filesystem_config.allow_delete = args.allow_delete;
+    filesystem_config.delete_policy = args.delete_policy.unwrap_or_default();
     filesystem_config.allow_overwrite = args.allow_overwrite;
+    filesystem_config.allow_symlinks = args.allow_symlinks;
+    filesystem_config.readdirplus = !args.no_readdirplus;
+    filesystem_config.write_conflict_policy = args.write_conflict_policy;
+    filesystem_config.write_reorder_window = args.write_reorder_window;
+    filesystem_config.append_upload_concurrency =
+        append_upload_concurrency(args.append_upload_concurrency, throughput_target_gbps);
+    filesystem_config.object_version_policy = args.object_version_policy.unwrap_or_default();
     filesystem_config.use_upload_checksums = !args.disable_upload_checksums;
     if !s3_personality.supports_additional_checksums() {
         tracing::info!("disabling upload checksums because target S3 personality does not support them");
@@ -659,18 +1849,49 @@ where
     }
     filesystem_config.s3_personality = s3_personality;
     filesystem_config.server_side_encryption = ServerSideEncryption::new(args.sse, args.sse_kms_key_id);
+    filesystem_config.overlay_directory = args.overlay_dir.map(OverlayDirectory::new);
+    filesystem_config.namespace = namespace;
+    filesystem_config.flat_keys = args.flat_keys;
+    filesystem_config.shadowed_file_suffix = args.shadowed_file_suffix;
+    filesystem_config.stable_inode_numbers = args.stable_inode_numbers;
+    filesystem_config.io_accounting = args.io_accounting;
+    filesystem_config.control_directory = args.control_directory.unwrap_or_default();
+    if let Some(path) = &args.read_policy {
+        let policy = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read read policy file {}", path.display()))?;
+        let policy =
+            ReadPolicy::from_json(&policy).with_context(|| format!("invalid read policy in {}", path.display()))?;
+        filesystem_config.read_policy = Some(policy);
+    }
+    if let Some(path) = &args.identity_map {
+        let map = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read identity map file {}", path.display()))?;
+        let map =
+            IdentityMap::from_json(&map).with_context(|| format!("invalid identity map in {}", path.display()))?;
+        filesystem_config.identity_map = Some(map);
+    }
 
-    let prefetcher_config = Default::default();
+    filesystem_config.cache_config = cache_config(&args);
+    mount_summary.metadata_cache_ttl_secs = metadata_cache_ttl_secs(&filesystem_config.cache_config);
+    filesystem_config.kernel_cache = args.kernel_cache.unwrap_or_default();
+    filesystem_config.max_readahead = args.max_readahead.map(|kib| kib * 1024);
+    filesystem_config.max_background = args
+        .max_background
+        .unwrap_or_else(|| default_max_background(throughput_target_gbps));
+    filesystem_config.congestion_threshold = args.congestion_threshold;
+
+    let prefetcher_config = PrefetcherConfig {
+        max_memory_target: Some(args.max_memory_target()),
+        verify_object_checksums: args.verify_download_checksums && s3_personality.supports_additional_checksums(),
+        ..autoconfigure::prefetcher_config(
+            throughput_target_gbps,
+            args.profile_preset
+                .map(|preset| preset.prefetcher_config())
+                .unwrap_or_default(),
+        )
+    };
 
     if let Some(path) = args.cache {
-        let metadata_cache_ttl = args.metadata_ttl.unwrap_or(Duration::from_secs(1));
-        filesystem_config.cache_config = CacheConfig {
-            serve_lookup_from_cache: true,
-            dir_ttl: metadata_cache_ttl,
-            file_ttl: metadata_cache_ttl,
-            ..Default::default()
-        };
-
         let cache_config = match args.max_cache_size {
             // Fallback to no data cache.
             Some(0) => None,
@@ -683,79 +1904,405 @@ where
             None => Some(DiskDataCacheConfig::default()),
         };
 
+        if let Some(cache_config) = &cache_config {
+            mount_summary.data_cache = Some(DataCacheSummary::new(&path, cache_config));
+        }
+
         if let Some(cache_config) = cache_config {
             let managed_cache_dir =
                 ManagedCacheDir::new_from_parent(path).context("failed to create cache directory")?;
             let cache = DiskDataCache::new(managed_cache_dir.as_path_buf(), cache_config);
-            let prefetcher = caching_prefetch(cache, runtime, prefetcher_config);
+            let prefetcher = caching_prefetch(cache, runtime.clone(), prefetcher_config);
             let mut fuse_session = create_filesystem(
                 client,
                 prefetcher,
-                &args.bucket_name,
-                &args.prefix.unwrap_or_default(),
+                runtime,
+                &bucket_name,
+                &prefix,
                 filesystem_config,
                 fuse_config,
                 &bucket_description,
+                &mount_summary,
+                worker,
             )?;
 
             fuse_session.run_on_close(Box::new(move || {
                 drop(managed_cache_dir);
             }));
-
             return Ok(fuse_session);
         }
     }
 
-    let prefetcher = default_prefetch(runtime, prefetcher_config);
+    let prefetcher = default_prefetch(runtime.clone(), prefetcher_config);
     create_filesystem(
         client,
         prefetcher,
-        &args.bucket_name,
-        &args.prefix.unwrap_or_default(),
+        runtime,
+        &bucket_name,
+        &prefix,
         filesystem_config,
         fuse_config,
         &bucket_description,
+        &mount_summary,
+        worker,
     )
 }
 
-fn create_filesystem<Client, Prefetcher>(
+/// Start a background thread that periodically aborts old incomplete multipart uploads under the
+/// mounted prefixes, until the file system is unmounted.
+fn start_incomplete_upload_cleaner<Client>(cleaner: IncompleteUploadCleaner<Client>, fuse_session: &mut FuseSession)
+where
+    Client: ObjectClient + Send + Sync + 'static,
+{
+    let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+    let spawned = std::thread::Builder::new()
+        .name("upload-cleaner".to_owned())
+        .spawn(move || {
+            // The sender is dropped when the file system is unmounted
+            while stop_receiver.recv_timeout(INCOMPLETE_UPLOAD_CLEANUP_INTERVAL) == Err(RecvTimeoutError::Timeout) {
+                futures::executor::block_on(cleaner.abort_old_uploads());
+            }
+        });
+    match spawned {
+        Ok(_) => fuse_session.run_on_close(Box::new(move || drop(stop_sender))),
+        Err(e) => tracing::warn!("failed to start incomplete upload cleaner: {e:?}"),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_filesystem<Client, Prefetcher, Runtime>(
     client: Client,
     prefetcher: Prefetcher,
+    runtime: Runtime,
     bucket_name: &str,
     prefix: &Prefix,
-    filesystem_config: S3FilesystemConfig,
+    mut filesystem_config: S3FilesystemConfig,
     fuse_session_config: FuseSessionConfig,
     bucket_description: &str,
+    mount_summary: &MountSummary,
+    worker: Option<WorkerConnection>,
 ) -> anyhow::Result<FuseSession>
 where
     Client: ObjectClient + Send + Sync + 'static,
     Prefetcher: Prefetch + Send + Sync + 'static,
+    Runtime: Spawn + Send + Sync + 'static,
 {
-    let fs = S3FuseFilesystem::new(client, prefetcher, bucket_name, prefix, filesystem_config);
-    let session = Session::new(fs, &fuse_session_config.mount_point, &fuse_session_config.options)
-        .context("Failed to create FUSE session")?;
-    let session = FuseSession::new(session, fuse_session_config.max_threads).context("Failed to start FUSE session")?;
+    let max_readahead = filesystem_config.max_readahead;
+    filesystem_config.mount_config = serde_json::to_string_pretty(mount_summary).unwrap_or_default() + "\n";
+    let mut fs = S3FuseFilesystem::new(client, prefetcher, runtime, bucket_name, prefix, filesystem_config);
+    let init_receiver = fs.init_receiver();
+    let invalidator = fs.cache_invalidator();
+    let upload_cleaner = fuse_session_config
+        .incomplete_upload_max_age
+        .map(|max_age| fs.incomplete_upload_cleaner(max_age));
+    let (session, worker_status) = match worker {
+        // A worker serves a mount its supervisor owns, so it doesn't mount anything itself
+        Some(worker) => {
+            let session = Session::from_device(
+                fs,
+                worker.device,
+                &fuse_session_config.mount_point,
+                &fuse_session_config.options,
+                worker.resume,
+            );
+            (session, Some(worker.status))
+        }
+        None => {
+            let session = Session::new(fs, &fuse_session_config.mount_point, &fuse_session_config.options);
+            (session, None)
+        }
+    };
+    let session = session.context("Failed to create FUSE session")?;
+    let notifier = session.notifier();
+    let mut session =
+        FuseSession::new(session, fuse_session_config.max_threads).context("Failed to start FUSE session")?;
+
+    // The kernel sends its init request as soon as the file system is mounted. Wait for it, so that
+    // a kernel missing features we need fails the mount instead of every file operation.
+    match init_receiver.recv_timeout(FUSE_INIT_TIMEOUT) {
+        Ok(Ok(kernel_init)) => {
+            if let Some(worker_status) = worker_status {
+                worker_status
+                    .report_init(kernel_init)
+                    .context("Failed to report FUSE init to supervisor")?;
+            }
+            if let Some(max_readahead) = max_readahead.filter(|&readahead| readahead > kernel_init.max_readahead) {
+                if let Err(e) = raise_kernel_readahead(&fuse_session_config.mount_point, max_readahead) {
+                    tracing::warn!(
+                        "failed to raise readahead above the kernel's default of {} KiB: {e:#}",
+                        kernel_init.max_readahead / 1024
+                    );
+                }
+            }
+        }
+        Ok(Err(e)) => {
+            if let Err(unmount_err) = session.shutdown() {
+                tracing::warn!("failed to unmount after failed FUSE init: {unmount_err:?}");
+            }
+            return Err(e).context("Failed to initialize FUSE session");
+        }
+        Err(_) => tracing::warn!(
+            "kernel did not initialize the FUSE session within {} seconds",
+            FUSE_INIT_TIMEOUT.as_secs()
+        ),
+    }
+
+    if let Some(event_queue) = fuse_session_config.event_queue {
+        if let Err(e) = start_event_invalidation(event_queue, invalidator, notifier, &mut session) {
+            if let Err(unmount_err) = session.shutdown() {
+                tracing::warn!("failed to unmount after failing to receive event notifications: {unmount_err:?}");
+            }
+            return Err(e).context("Failed to start receiving event notifications");
+        }
+    }
+
+    if let Some(upload_cleaner) = upload_cleaner {
+        start_incomplete_upload_cleaner(upload_cleaner, &mut session);
+    }
 
     tracing::info!(
         "successfully mounted {} at {}",
         bucket_description,
         fuse_session_config.mount_point.display()
     );
+    mount_summary.log();
 
     Ok(session)
 }
 
+/// The default number of background requests the kernel can send at once. Instances with faster
+/// networks can serve more readahead at once, so allow about one request in flight for every
+/// 400 Mbps of target throughput.
+fn default_max_background(throughput_target_gbps: f64) -> u16 {
+    ((throughput_target_gbps * 2.5) as u16).clamp(DEFAULT_MAX_BACKGROUND, 1024)
+}
+
+/// Limits on parts in flight for appends that allow `per_file` parts in flight for each file. Like
+/// [default_max_background], allow about one part in flight across all files for every 400 Mbps
+/// of target throughput, but always enough for a single file.
+fn append_upload_concurrency(per_file: usize, throughput_target_gbps: f64) -> AppendUploadConcurrency {
+    AppendUploadConcurrency {
+        per_file,
+        total: ((throughput_target_gbps * 2.5) as usize).max(per_file),
+    }
+}
+
+/// The kernel only lets a FUSE file system lower its readahead when the session is initialized,
+/// so raise it through the backing device of the mount in sysfs instead, which needs root.
+#[cfg(target_os = "linux")]
+fn raise_kernel_readahead(mount_point: &Path, max_readahead: u32) -> anyhow::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = std::fs::metadata(mount_point)
+        .context("failed to find the mount's device")?
+        .dev();
+    let path = format!("/sys/class/bdi/{}:{}/read_ahead_kb", libc::major(dev), libc::minor(dev));
+    std::fs::write(&path, (max_readahead / 1024).to_string()).with_context(|| format!("failed to write {path}"))?;
+    tracing::debug!(path, "raised kernel readahead to {} KiB", max_readahead / 1024);
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn raise_kernel_readahead(_mount_point: &Path, _max_readahead: u32) -> anyhow::Result<()> {
+    Err(anyhow!("readahead can only be raised on Linux"))
+}
+
 /// Configuration for a FUSE background session.
 #[derive(Debug)]
 struct FuseSessionConfig {
     pub mount_point: PathBuf,
     pub options: Vec<MountOption>,
     pub max_threads: usize,
+    /// Where to receive event notifications for invalidating the kernel's and our own caches
+    pub event_queue: Option<EventQueueConfig>,
+    /// How long to give multipart uploads to finish before aborting them, if set by
+    /// `--cleanup-incomplete-uploads`
+    pub incomplete_upload_max_age: Option<Duration>,
+}
+
+/// Details about the S3 client chosen by a client builder that aren't visible through the
+/// [ObjectClient] interface, but that we want to report once the file system is mounted.
+#[derive(Debug, Clone)]
+pub struct ClientDetails {
+    /// The region the client ended up targeting, after any region redirection. With several
+    /// buckets, this is the region of the first one.
+    pub region: String,
+    /// The target network throughput the client was configured with, in Gbps.
+    pub throughput_target_gbps: f64,
+    /// The credentials scoped down to the mount that the client uses with `--scope-credentials`,
+    /// which event notifications are received with too.
+    pub scoped_credentials: Option<ScopedCredentials>,
+}
+
+/// The log target for the [MountSummary] log line. It's logged at INFO level regardless of the
+/// other logging options, so that tools can rely on it being present.
+pub const MOUNT_SUMMARY_TARGET_NAME: &str = "mountpoint_s3::mount_summary";
+
+/// A machine-readable summary of a successful mount, emitted as a single JSON log line so that
+/// fleet inventory tools don't need to parse our free-form log messages.
+#[derive(Debug, Serialize)]
+struct MountSummary {
+    version: &'static str,
+    bucket: String,
+    prefix: String,
+    /// Every mounted prefix as an `s3://` URI, which is more than just [Self::bucket] and
+    /// [Self::prefix] when mounting several prefixes or buckets
+    prefixes: Vec<String>,
+    mount_point: PathBuf,
+    region: String,
+    s3_personality: String,
+    throughput_target_gbps: f64,
+    read_part_size: u64,
+    write_part_size: u64,
+    max_buffered_upload_parts: Option<u64>,
+    max_threads: u64,
+    max_readahead_kib: Option<u32>,
+    max_background: u16,
+    max_memory_target_bytes: u64,
+    profile_preset: Option<&'static str>,
+    event_loop_threads: u16,
+    metadata_cache_ttl_secs: Option<u64>,
+    data_cache: Option<DataCacheSummary>,
+    features: FeatureSummary,
+}
+
+#[derive(Debug, Serialize)]
+struct DataCacheSummary {
+    directory: PathBuf,
+    block_size: u64,
+    max_size_bytes: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct FeatureSummary {
+    read_only: bool,
+    write_only: bool,
+    allow_delete: bool,
+    delete_policy: Option<&'static str>,
+    allow_overwrite: bool,
+    allow_symlinks: bool,
+    flat_keys: bool,
+    shadowed_file_suffix: Option<String>,
+    stable_inode_numbers: bool,
+    event_queue_url: Option<String>,
+    kernel_cache: Option<&'static str>,
+    write_conflict_policy: Option<&'static str>,
+    allow_other: bool,
+    allow_root: bool,
+    auto_unmount: bool,
+    restart_on_crash: bool,
+    readdirplus: bool,
+    requester_pays: bool,
+    no_sign_request: bool,
+    transfer_acceleration: bool,
+    dual_stack: bool,
+    force_path_style: bool,
+    upload_checksums: bool,
+    download_checksums: bool,
+    server_side_encryption: Option<String>,
+    storage_class: Option<String>,
+    overlay_directory: Option<PathBuf>,
+    read_policy: Option<PathBuf>,
+    identity_map: Option<PathBuf>,
+    object_version_policy: Option<String>,
+    cleanup_incomplete_uploads: bool,
+    io_accounting: bool,
+    control_directory: Option<&'static str>,
+}
+
+impl MountSummary {
+    fn new(args: &CliArgs, s3_personality: S3Personality, client_details: ClientDetails) -> Self {
+        let features = FeatureSummary {
+            read_only: args.read_only,
+            write_only: args.write_only,
+            allow_delete: args.allow_delete,
+            delete_policy: args.delete_policy.map(|policy| policy.as_str()),
+            allow_overwrite: args.allow_overwrite,
+            allow_symlinks: args.allow_symlinks,
+            flat_keys: args.flat_keys,
+            shadowed_file_suffix: args.shadowed_file_suffix.clone(),
+            stable_inode_numbers: args.stable_inode_numbers,
+            event_queue_url: args.event_queue_url.clone(),
+            kernel_cache: args.kernel_cache.map(|mode| mode.as_str()),
+            write_conflict_policy: args.write_conflict_policy.map(|policy| policy.as_str()),
+            allow_other: args.allow_other,
+            allow_root: args.allow_root,
+            auto_unmount: args.auto_unmount,
+            restart_on_crash: args.restart_on_crash,
+            readdirplus: !args.no_readdirplus,
+            requester_pays: args.requester_pays,
+            no_sign_request: args.no_sign_request,
+            transfer_acceleration: args.transfer_acceleration,
+            dual_stack: args.dual_stack,
+            force_path_style: args.force_path_style,
+            upload_checksums: !args.disable_upload_checksums && s3_personality.supports_additional_checksums(),
+            download_checksums: args.verify_download_checksums && s3_personality.supports_additional_checksums(),
+            server_side_encryption: args.sse.clone(),
+            storage_class: args.storage_class.clone(),
+            overlay_directory: args.overlay_dir.clone(),
+            read_policy: args.read_policy.clone(),
+            identity_map: args.identity_map.clone(),
+            object_version_policy: args.object_version_policy.map(|policy| policy.to_string()),
+            cleanup_incomplete_uploads: args.cleanup_incomplete_uploads,
+            io_accounting: args.io_accounting,
+            control_directory: args.control_directory.map(|mode| mode.as_str()),
+        };
+        Self {
+            version: build_info::FULL_VERSION,
+            bucket: args.bucket_name().to_owned(),
+            prefix: args.prefix().to_string(),
+            prefixes: args
+                .mounted_prefixes()
+                .iter()
+                .map(|(bucket, prefix)| format!("s3://{bucket}/{prefix}"))
+                .collect(),
+            mount_point: args.mount_point.clone(),
+            region: client_details.region,
+            s3_personality: format!("{s3_personality:?}"),
+            throughput_target_gbps: client_details.throughput_target_gbps,
+            read_part_size: args.read_part_size(),
+            write_part_size: args.write_part_size(),
+            max_buffered_upload_parts: args.max_buffered_upload_parts,
+            max_threads: args.max_threads(),
+            max_readahead_kib: args.max_readahead,
+            max_background: args
+                .max_background
+                .unwrap_or_else(|| default_max_background(client_details.throughput_target_gbps)),
+            max_memory_target_bytes: args.max_memory_target(),
+            profile_preset: args.profile_preset.map(|preset| preset.as_str()),
+            event_loop_threads: args.event_loop_threads(),
+            metadata_cache_ttl_secs: None,
+            data_cache: None,
+            features,
+        }
+    }
+
+    fn log(&self) {
+        match serde_json::to_string(self) {
+            Ok(summary) => tracing::info!(target: MOUNT_SUMMARY_TARGET_NAME, "{summary}"),
+            Err(e) => tracing::warn!("failed to serialize mount summary: {e:?}"),
+        }
+    }
+}
+
+impl DataCacheSummary {
+    fn new(directory: &Path, config: &DiskDataCacheConfig) -> Self {
+        let max_size_bytes = match config.limit {
+            CacheLimit::TotalSize { max_size } => Some(max_size),
+            CacheLimit::Unbounded | CacheLimit::AvailableSpace { .. } => None,
+        };
+        Self {
+            directory: directory.to_owned(),
+            block_size: config.block_size,
+            max_size_bytes,
+        }
+    }
 }
 
 /// Create a client for a bucket in the given region and send a ListObjectsV2 request to validate
 /// that it's accessible. If no region is provided, attempt to infer it by first sending a
-/// ListObjectsV2 to the default region.
+/// ListObjectsV2 to the default region. If S3 tells us the bucket is in a different region than the
+/// one we tried, even one the user provided, recreate the client for that region.
 ///
 /// This also has the nice side effect of triggering the CRT's DNS resolver to start pooling
 /// responses, which means we don't have to wait for the first file read to start the rampup period.
@@ -768,7 +2315,7 @@ fn create_client_for_bucket(
     client_config: S3ClientConfig,
     instance_info: &InstanceInfo,
 ) -> Result<S3CrtClient, anyhow::Error> {
-    let (region_to_try, user_provided_region) = get_region(args_region, instance_info);
+    let (region_to_try, user_provided_region) = get_region(args_region, bucket, instance_info);
     endpoint_config = endpoint_config.region(&region_to_try);
 
     if let Some(uri) = endpoint_url {
@@ -788,9 +2335,15 @@ fn create_client_for_bucket(
     let list_request = client.list_objects(bucket, None, "", 0, prefix.as_str());
     match futures::executor::block_on(list_request) {
         Ok(_) => Ok(client),
-        // Don't try to automatically correct the region if it was manually specified incorrectly
-        Err(ObjectClientError::ClientError(S3RequestError::IncorrectRegion(region))) if !user_provided_region => {
-            tracing::warn!("bucket {bucket} is in region {region}, not {region_to_try}. redirecting...");
+        Err(ObjectClientError::ClientError(S3RequestError::IncorrectRegion(region))) => {
+            if user_provided_region {
+                tracing::warn!(
+                    "bucket {bucket} is in region {region}, not the configured region {region_to_try}. \
+                    redirecting, but consider updating --region or AWS_REGION..."
+                );
+            } else {
+                tracing::warn!("bucket {bucket} is in region {region}, not {region_to_try}. redirecting...");
+            }
             let new_client = S3CrtClient::new(client_config.endpoint_config(endpoint_config.region(&region)))?;
             let list_request = new_client.list_objects(bucket, None, "", 0, prefix.as_str());
             futures::executor::block_on(list_request)
@@ -822,11 +2375,18 @@ fn parse_bucket_name(bucket_name: &str) -> anyhow::Result<String> {
         return Err(anyhow!("bucket name should not be an s3:// URI (provide the bare bucket name instead; use --prefix for prefix mounts)"));
     }
 
+    // The endpoint resolver handles access point ARNs itself, but we can mount a bucket ARN by name
+    if bucket_name.starts_with("arn:") {
+        return match BucketArn::parse(bucket_name)?.resource {
+            BucketArnResource::Bucket(name) => parse_bucket_name(&name),
+            _ => Ok(bucket_name.to_owned()),
+        };
+    }
+
     // Actual bucket names must start/end with a letter, but bucket aliases can end with numbers
     // (-s3), so let's just naively check for invalid characters.
     let bucket_regex = Regex::new(r"^[0-9a-zA-Z\-\._]+$").unwrap();
-    // A simple check for AWS ARN
-    if !bucket_regex.is_match(bucket_name) && !bucket_name.starts_with("arn:") {
+    if !bucket_regex.is_match(bucket_name) {
         return Err(anyhow!(
             "bucket argument should be a valid bucket name(only letters, numbers, . and -) or a valid ARN"
         ));
@@ -835,6 +2395,105 @@ fn parse_bucket_name(bucket_name: &str) -> anyhow::Result<String> {
     Ok(bucket_name.to_owned())
 }
 
+/// Parse a `--bucket` argument of the form `NAME=s3://BUCKET[/PREFIX/]`, which mounts a bucket (or a
+/// prefix of one) as directory `NAME` at the root of the mount.
+fn parse_bucket_mount(bucket_mount: &str) -> anyhow::Result<NamespaceEntry> {
+    let (name, uri) = bucket_mount
+        .split_once('=')
+        .ok_or_else(|| anyhow!("must be of the form NAME=s3://BUCKET[/PREFIX/]"))?;
+    let path = uri
+        .strip_prefix("s3://")
+        .ok_or_else(|| anyhow!("the bucket to mount at {name:?} must be an s3:// URI"))?;
+    let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+    Ok(NamespaceEntry {
+        name: name.to_owned(),
+        bucket: parse_bucket_name(bucket)?,
+        prefix: Prefix::new(prefix)?,
+    })
+}
+
+/// The suffix is added to file names, so it can't contain characters that can't be in a name.
+fn parse_shadowed_file_suffix(suffix: &str) -> anyhow::Result<String> {
+    if suffix.is_empty() {
+        return Err(anyhow!("the suffix must not be empty"));
+    }
+    if suffix.contains(['/', '\0']) {
+        return Err(anyhow!("the suffix must not contain '/' or null characters"));
+    }
+    Ok(suffix.to_owned())
+}
+
+/// STS only accepts role session names of 2-64 characters from a limited set.
+fn parse_role_session_name(session_name: &str) -> anyhow::Result<String> {
+    let session_name_regex = Regex::new(r"^[A-Za-z0-9_+=,.@-]{2,64}$").unwrap();
+    if session_name_regex.is_match(session_name) {
+        Ok(session_name.to_owned())
+    } else {
+        Err(anyhow!(
+            "role session names must be 2-64 characters long, \
+            and only contain ASCII letters, numbers, and the characters +=,.@_-"
+        ))
+    }
+}
+
+/// The metadata cache configuration for the given arguments, including any that come from the
+/// profile preset.
+fn cache_config(args: &CliArgs) -> CacheConfig {
+    let attr_cache_ttl = match (args.attr_cache_ttl_ms, args.profile_preset) {
+        (Some(ms), _) => Duration::from_millis(ms),
+        (None, Some(preset)) => preset.attr_cache_ttl(),
+        (None, None) => Duration::ZERO,
+    };
+
+    // An explicit `--metadata-ttl` takes precedence over the preset, and doesn't require `--cache`.
+    // With `--cache`, metadata is cached for 1 second by default.
+    let metadata_ttl = args
+        .metadata_ttl
+        .or_else(|| {
+            args.profile_preset
+                .and_then(|preset| preset.metadata_ttl())
+                .map(TimeToLive::Duration)
+        })
+        .or_else(|| {
+            args.cache
+                .is_some()
+                .then_some(TimeToLive::Duration(Duration::from_secs(1)))
+        });
+    let mut cache_config = match metadata_ttl {
+        Some(metadata_ttl) => CacheConfig {
+            forgotten_inode_grace_period: args.forgotten_inode_grace_period.unwrap_or_default(),
+            attr_cache_ttl,
+            ..CacheConfig::new(metadata_ttl)
+        },
+        None => CacheConfig {
+            attr_cache_ttl,
+            ..Default::default()
+        },
+    };
+    if let Some(dir_metadata_ttl) = args.dir_metadata_ttl {
+        cache_config = cache_config.with_dir_metadata_ttl(dir_metadata_ttl);
+    }
+    if let Some(negative_metadata_ttl) = args.negative_metadata_ttl {
+        cache_config = cache_config.with_negative_metadata_ttl(negative_metadata_ttl);
+    }
+    cache_config
+}
+
+/// The metadata TTL to report in the mount summary, if lookups are served from the metadata cache.
+fn metadata_cache_ttl_secs(cache_config: &CacheConfig) -> Option<u64> {
+    cache_config
+        .serve_lookup_from_cache
+        .then_some(cache_config.file_ttl.as_secs())
+}
+
+fn parse_ttl(ttl_str: &str) -> anyhow::Result<TimeToLive> {
+    match ttl_str {
+        "indefinite" => Ok(TimeToLive::Indefinite),
+        "minimal" => Ok(TimeToLive::Minimal),
+        _ => parse_ttl_seconds(ttl_str).map(TimeToLive::Duration),
+    }
+}
+
 fn parse_ttl_seconds(seconds_str: &str) -> anyhow::Result<Duration> {
     const MAXIMUM_TTL_YEARS: u64 = 100;
     const MAXIMUM_TTL_SECONDS: u64 = MAXIMUM_TTL_YEARS * 365 * 24 * 60 * 60;
@@ -864,7 +2523,7 @@ fn env_region() -> Option<String> {
 ///
 /// Returns the region name and a bool specifying whether
 /// the region was provided by the user.
-fn get_region(args_region: Option<String>, instance_info: &InstanceInfo) -> (String, bool) {
+fn get_region(args_region: Option<String>, bucket: &str, instance_info: &InstanceInfo) -> (String, bool) {
     const DEFAULT_REGION: &str = "us-east-1";
 
     // Use --region (user-provided).
@@ -878,6 +2537,12 @@ fn get_region(args_region: Option<String>, instance_info: &InstanceInfo) -> (Str
         return (region, true);
     }
 
+    // Use the region in the access point ARN, if there is one.
+    if let Some(region) = BucketArn::parse(bucket).ok().and_then(|arn| arn.region) {
+        tracing::debug!("using region {region} from bucket ARN");
+        return (region, false);
+    }
+
     // Use instance region, if available.
     if let Ok(region) = instance_info.region() {
         tracing::debug!("using instance region {}", region);
@@ -898,6 +2563,22 @@ fn infer_s3_personality(
         return bucket_type.to_personality();
     }
 
+    // Outposts access point ARNs always mean Outposts, even with a custom endpoint
+    if let Ok(BucketArn {
+        resource: BucketArnResource::OutpostsAccessPoint,
+        ..
+    }) = BucketArn::parse(bucket)
+    {
+        return S3Personality::Outposts;
+    }
+
+    // A custom endpoint that isn't an AWS one is some other S3-compatible store
+    if let Some(endpoint) = endpoint_config.get_endpoint() {
+        if !is_aws_host(&endpoint.host_name().to_string_lossy()) {
+            return S3Personality::ThirdParty;
+        }
+    }
+
     let Ok(resolved) = endpoint_config.resolve_for_bucket(bucket) else {
         return S3Personality::Standard;
     };
@@ -913,6 +2594,14 @@ fn infer_s3_personality(
     }
 }
 
+/// Whether a host name belongs to AWS, including VPC endpoints and the China regions.
+fn is_aws_host(host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    ["amazonaws.com", "amazonaws.com.cn", "api.aws"]
+        .iter()
+        .any(|domain| host == *domain || host.ends_with(&format!(".{domain}")))
+}
+
 fn validate_mount_point(path: impl AsRef<Path>) -> anyhow::Result<()> {
     let mount_point = path.as_ref();
 
@@ -946,6 +2635,51 @@ fn validate_mount_point(path: impl AsRef<Path>) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// The overlay directory must exist, and can't be inside the mount point since we would end up
+/// making FUSE requests to ourselves to serve the overlay.
+fn validate_overlay_dir(path: &Path, mount_point: &Path) -> anyhow::Result<()> {
+    if !path.is_dir() {
+        return Err(anyhow!("overlay directory {} is not a directory", path.display()));
+    }
+    let path = path.canonicalize().context("failed to resolve overlay directory")?;
+    let mount_point = mount_point.canonicalize().context("failed to resolve mount point")?;
+    if path.starts_with(mount_point) {
+        return Err(anyhow!(
+            "overlay directory {} is inside the mount point",
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+/// `fusermount` only lets users other than root mount with `allow_other` or `allow_root` if
+/// `user_allow_other` is set in `/etc/fuse.conf`, and otherwise fails the mount with an error that
+/// doesn't say how to fix it. macFUSE doesn't have this restriction.
+fn validate_allow_other(allow_other: bool, allow_root: bool) -> anyhow::Result<()> {
+    const FUSE_CONF_PATH: &str = "/etc/fuse.conf";
+
+    if !(allow_other || allow_root) || cfg!(not(target_os = "linux")) || nix::unistd::geteuid().is_root() {
+        return Ok(());
+    }
+    let allowed = std::fs::read_to_string(FUSE_CONF_PATH).is_ok_and(|contents| fuse_conf_allows_other(&contents));
+    if allowed {
+        return Ok(());
+    }
+    let option = if allow_other { "--allow-other" } else { "--allow-root" };
+    Err(anyhow!(
+        "{option} can only be used by users other than root if user_allow_other is enabled in {FUSE_CONF_PATH}. \
+        Add a line containing user_allow_other to {FUSE_CONF_PATH}, or run Mountpoint as root."
+    ))
+}
+
+/// Whether the contents of a `fuse.conf` file enable the `user_allow_other` option
+fn fuse_conf_allows_other(contents: &str) -> bool {
+    contents.lines().any(|line| {
+        let option = line.split('#').next().unwrap_or_default();
+        option.trim() == "user_allow_other"
+    })
+}
+
 /// Disallow specifying `--sse-kms-key-id` when `--sse=AES256` as this is not allowed by the S3 API.
 /// We are not able to perform this check via clap API (the closest it has is `conflicts_with` method),
 /// thus having a custom validation.
@@ -957,11 +2691,119 @@ fn validate_sse_args(sse_type: Option<&str>, sse_kms_key_id: Option<&str>) -> an
     }
 }
 
+/// Objects can have at most this many parts, so the write part size limits the largest object we
+/// can write
+const MAX_S3_MULTIPART_UPLOAD_PARTS: u64 = 10000;
+
+/// S3 doesn't allow objects larger than this, whatever the part size
+const MAX_S3_OBJECT_SIZE: u64 = 5 * 1024 * 1024 * 1024 * 1024;
+
+fn validate_maximum_object_size(write_part_size: u64, maximum_object_size: Option<u64>) -> anyhow::Result<()> {
+    let Some(maximum_object_size) = maximum_object_size else {
+        return Ok(());
+    };
+    if maximum_object_size > MAX_S3_OBJECT_SIZE {
+        return Err(anyhow!(
+            "--maximum-object-size can't be larger than {MAX_S3_OBJECT_SIZE} bytes, the largest object S3 allows"
+        ));
+    }
+    let min_part_size = maximum_object_size.div_ceil(MAX_S3_MULTIPART_UPLOAD_PARTS);
+    if write_part_size < min_part_size {
+        return Err(anyhow!(
+            "write part size of {write_part_size} bytes is too small to write objects of \
+             --maximum-object-size {maximum_object_size} in {MAX_S3_MULTIPART_UPLOAD_PARTS} parts; \
+             use --write-part-size {min_part_size} or larger"
+        ));
+    }
+    Ok(())
+}
+
+/// Some S3 implementations don't support every kind of server-side encryption, so fail the mount
+/// rather than every upload.
+fn validate_sse_for_personality(sse_type: Option<&str>, s3_personality: S3Personality) -> anyhow::Result<()> {
+    let uses_kms = sse_type.is_some_and(|sse_type| sse_type.starts_with("aws:kms"));
+    if uses_kms && !s3_personality.supports_sse_kms() {
+        Err(anyhow!(
+            "--sse {} is not supported by {s3_personality:?} buckets",
+            sse_type.unwrap()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use test_case::test_case;
 
+    #[test_case(Some("aws:kms"), S3Personality::Standard, true; "kms on standard")]
+    #[test_case(Some("aws:kms"), S3Personality::Outposts, false; "kms on outposts")]
+    #[test_case(Some("aws:kms:dsse"), S3Personality::Outposts, false; "dsse on outposts")]
+    #[test_case(Some("AES256"), S3Personality::Outposts, true; "sse-s3 on outposts")]
+    #[test_case(None, S3Personality::Outposts, true; "no sse on outposts")]
+    fn test_sse_for_personality(sse_type: Option<&str>, s3_personality: S3Personality, valid: bool) {
+        assert_eq!(validate_sse_for_personality(sse_type, s3_personality).is_ok(), valid);
+    }
+
+    #[test_case("s3.us-east-1.amazonaws.com", true; "regional endpoint")]
+    #[test_case("bucket.vpce-1a2b3c4d-5e6f.s3.us-east-1.vpce.amazonaws.com", true; "vpc endpoint")]
+    #[test_case("s3.cn-north-1.amazonaws.com.cn", true; "china endpoint")]
+    #[test_case("s3.dualstack.us-east-1.api.aws", true; "api.aws endpoint")]
+    #[test_case("localhost", false; "localhost")]
+    #[test_case("minio.example.com", false; "minio")]
+    #[test_case("amazonaws.com.example.com", false; "lookalike domain")]
+    fn test_is_aws_host(host: &str, expected: bool) {
+        assert_eq!(is_aws_host(host), expected);
+    }
+
+    #[test]
+    fn custom_endpoint_personality() {
+        let endpoint = Uri::new_from_str(&Allocator::default(), "http://localhost:9000").unwrap();
+        let endpoint_config = EndpointConfig::new("us-east-1").endpoint(endpoint);
+        let personality = infer_s3_personality(None, "test-bucket", endpoint_config.clone());
+        assert!(!personality.supports_additional_checksums());
+
+        // An explicit bucket type wins
+        let personality = infer_s3_personality(Some(BucketType::GeneralPurpose), "test-bucket", endpoint_config);
+        assert!(personality.supports_additional_checksums());
+    }
+
+    #[test]
+    fn outposts_arn_personality() {
+        let arn = "arn:aws:s3-outposts:us-east-1:555555555555:outpost/op-01ac5d28a6a232904/accesspoint/my-access-point";
+        let personality = infer_s3_personality(None, arn, EndpointConfig::new("us-east-1"));
+        assert!(!personality.supports_additional_checksums());
+        assert!(!personality.supports_sse_kms());
+    }
+
+    #[test_case("mfzwi23gnjvgw.mrap", true; "multi-region access point alias")]
+    #[test_case("doc-example-bucket", false; "bucket")]
+    fn test_mrap_alias_hint(bucket: &str, expect_hint: bool) {
+        let error = anyhow!("initial ListObjectsV2 failed");
+        let error = add_mrap_alias_hint(error, bucket);
+        assert_eq!(format!("{error:#}").contains("arn:aws:s3::"), expect_hint);
+    }
+
+    #[test_case(S3RequestError::NoSigningCredentials, true; "no credentials")]
+    #[test_case(S3RequestError::Forbidden("Access Denied".to_owned(), None), false; "forbidden")]
+    fn test_credentials_hint(error: S3RequestError, expect_hint: bool) {
+        let error: ObjectClientError<ListObjectsError, S3RequestError> = ObjectClientError::ClientError(error);
+        let error = anyhow::Error::new(error).context("initial ListObjectsV2 failed");
+        let error = add_credentials_hint(error, Some("test-profile"));
+        assert_eq!(format!("{error:#}").contains("profile test-profile"), expect_hint);
+    }
+
+    #[test_case(S3RequestError::Forbidden("Access Denied".to_owned(), None), false, true; "forbidden")]
+    #[test_case(S3RequestError::Forbidden("Access Denied".to_owned(), None), true, false; "forbidden with requester pays")]
+    #[test_case(S3RequestError::NoSigningCredentials, false, false; "other error")]
+    fn test_requester_pays_hint(error: S3RequestError, requester_pays: bool, expect_hint: bool) {
+        let error: ObjectClientError<ListObjectsError, S3RequestError> = ObjectClientError::ClientError(error);
+        let error = anyhow::Error::new(error).context("initial ListObjectsV2 failed");
+        let error = add_requester_pays_hint(error, requester_pays);
+        assert_eq!(format!("{error:#}").contains("--requester-pays"), expect_hint);
+    }
+
     #[test_case("test-bucket", true; "simple bucket")]
     #[test_case("test-123.buc_ket", true; "bucket name with .")]
     #[test_case("my-access-point-hrzrlukc5m36ft7okagglf3gmwluquse1b-s3alias", true; "access point alias")]
@@ -969,10 +2811,10 @@ mod tests {
     #[test_case("s3://test-bucket", false; "not providing bare bucket name")]
     #[test_case("~/mnt", false; "directory name in place of bucket")]
     #[test_case("arn:aws:s3::00000000:accesspoint/s3-bucket-test.mrap", true; "multiregion accesspoint ARN")]
-    #[test_case("arn:aws:s3:::doc-example-bucket", true; "bucket ARN(maybe rejected by endpoint resolver with error message)")]
     #[test_case("arn:aws-cn:s3:cn-north-2:555555555555:accesspoint/china-region-ap", true; "standard accesspoint ARN in China")]
     #[test_case("arn:aws-us-gov:s3-object-lambda:us-gov-west-1:555555555555:accesspoint/example-olap", true; "S3 object lambda accesspoint in US Gov")]
     #[test_case("arn:aws:s3-outposts:us-east-1:555555555555:outpost/outpost-id/accesspoint/accesspoint-name", true; "S3 outpost accesspoint ARN")]
+    #[test_case("arn:aws:s3:us-east-1:555555555555:doc-example-bucket", false; "bucket ARN with region")]
     fn validate_bucket_name(bucket_name: &str, valid: bool) {
         let parsed = parse_bucket_name(bucket_name);
         if valid {
@@ -981,4 +2823,328 @@ mod tests {
             parsed.expect_err("invalid bucket name");
         }
     }
+
+    #[test]
+    fn bucket_arn_is_mounted_by_name() {
+        let parsed = parse_bucket_name("arn:aws:s3:::doc-example-bucket").unwrap();
+        assert_eq!(parsed, "doc-example-bucket");
+    }
+
+    #[test_case("0", Some(TimeToLive::Duration(Duration::ZERO)); "zero")]
+    #[test_case("60", Some(TimeToLive::Duration(Duration::from_secs(60))); "seconds")]
+    #[test_case("indefinite", Some(TimeToLive::Indefinite); "indefinite")]
+    #[test_case("minimal", Some(TimeToLive::Minimal); "minimal")]
+    #[test_case("forever", None; "unknown keyword")]
+    #[test_case("-1", None; "negative")]
+    fn validate_ttl(ttl_str: &str, expected: Option<TimeToLive>) {
+        let parsed = parse_ttl(ttl_str).ok();
+        assert_eq!(parsed, expected);
+    }
+
+    #[test_case("latest", true; "latest")]
+    #[test_case("latest-at:2024-01-31T12:00:00Z", true; "utc timestamp")]
+    #[test_case("latest-at:2024-01-31T12:00:00.5+09:00", true; "timestamp with offset")]
+    #[test_case("latest-at:2024-01-31", false; "date only")]
+    #[test_case("latest-at:", false; "missing timestamp")]
+    #[test_case("oldest", false; "unknown policy")]
+    fn validate_object_version_policy(policy: &str, valid: bool) {
+        let args = CliArgs::try_parse_from([
+            "mount-s3",
+            "test-bucket",
+            "mnt",
+            "--read-only",
+            "--object-version-policy",
+            policy,
+        ]);
+        assert_eq!(args.is_ok(), valid, "{args:?}");
+    }
+
+    #[test]
+    fn object_version_policy_requires_read_only() {
+        let args = CliArgs::try_parse_from([
+            "mount-s3",
+            "test-bucket",
+            "mnt",
+            "--object-version-policy",
+            "latest-at:2024-01-31T12:00:00Z",
+        ]);
+        assert!(args.is_err());
+    }
+
+    #[test]
+    fn incomplete_upload_max_age_requires_cleanup() {
+        let args = CliArgs::try_parse_from(["mount-s3", "test-bucket", "mnt", "--incomplete-upload-max-age", "60"]);
+        assert!(args.is_err());
+
+        let args = CliArgs::try_parse_from([
+            "mount-s3",
+            "test-bucket",
+            "mnt",
+            "--cleanup-incomplete-uploads",
+            "--read-only",
+        ]);
+        assert!(args.is_err());
+
+        let args = CliArgs::try_parse_from(["mount-s3", "test-bucket", "mnt", "--cleanup-incomplete-uploads"]).unwrap();
+        assert_eq!(args.incomplete_upload_max_age, 86400);
+    }
+
+    #[test]
+    fn later_options_override_earlier_ones() {
+        // Config file options are placed before the command-line ones, so the command line wins
+        let args = CliArgs::try_parse_from([
+            "mount-s3",
+            "test-bucket",
+            "mnt",
+            "--region",
+            "us-east-1",
+            "--allow-delete",
+            "--region",
+            "us-west-2",
+            "--allow-delete",
+        ])
+        .unwrap();
+        assert_eq!(args.region.as_deref(), Some("us-west-2"));
+        assert!(args.allow_delete);
+    }
+
+    #[test]
+    fn repeated_prefixes_are_mounted_together() {
+        let args = CliArgs::try_parse_from(["mount-s3", "test-bucket", "mnt", "--prefix", "data/"]).unwrap();
+        assert_eq!(args.prefix().as_str(), "data/");
+        assert!(args.namespace().unwrap().is_none());
+
+        let args = CliArgs::try_parse_from([
+            "mount-s3",
+            "test-bucket",
+            "mnt",
+            "--prefix",
+            "logs/2024/",
+            "--prefix",
+            "data/",
+        ])
+        .unwrap();
+        assert_eq!(args.prefix().as_str(), "");
+        assert_eq!(args.mounted_prefixes().len(), 2);
+        let namespace = args.namespace().unwrap().expect("several prefixes need a namespace");
+        let names: Vec<_> = namespace.entries().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["2024", "data"]);
+
+        let args = CliArgs::try_parse_from([
+            "mount-s3",
+            "test-bucket",
+            "mnt",
+            "--prefix",
+            "data/",
+            "--prefix",
+            "data/2024/",
+        ])
+        .unwrap();
+        args.namespace()
+            .expect_err("overlapping prefixes can't be mounted together");
+    }
+
+    #[test]
+    fn repeated_buckets_are_mounted_together() {
+        let args = CliArgs::try_parse_from([
+            "mount-s3",
+            "mnt",
+            "--bucket",
+            "data=s3://bucket-a",
+            "--bucket",
+            "logs=s3://bucket-b/logs/",
+            "--bucket",
+            "more-data=s3://bucket-a/more/",
+        ])
+        .unwrap();
+        assert_eq!(args.mount_point, PathBuf::from("mnt"));
+        assert_eq!(args.bucket_name(), "bucket-a");
+        assert_eq!(args.buckets(), vec!["bucket-a", "bucket-b"]);
+        let namespace = args.namespace().unwrap().expect("--bucket needs a namespace");
+        let entries: Vec<_> = namespace
+            .entries()
+            .map(|entry| (entry.name.as_str(), entry.bucket.as_str(), entry.prefix.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("data", "bucket-a", ""),
+                ("logs", "bucket-b", "logs/"),
+                ("more-data", "bucket-a", "more/")
+            ]
+        );
+
+        // --bucket replaces the bucket name and --prefix
+        CliArgs::try_parse_from(["mount-s3", "test-bucket", "mnt", "--bucket", "data=s3://bucket-a"])
+            .expect_err("--bucket can't be used with a bucket name");
+        CliArgs::try_parse_from(["mount-s3", "mnt", "--bucket", "data=s3://bucket-a", "--prefix", "data/"])
+            .expect_err("--bucket can't be used with --prefix");
+        CliArgs::try_parse_args(["mount-s3", "mnt"]).expect_err("a bucket is required");
+
+        // Without --bucket, the bucket name is still required, so options can go between it and
+        // the mount point
+        let args = CliArgs::try_parse_args(["mount-s3", "test-bucket", "--read-only", "mnt"]).unwrap();
+        assert_eq!(args.bucket_name(), "test-bucket");
+        assert_eq!(args.mount_point, PathBuf::from("mnt"));
+        let args = CliArgs::try_parse_args(["mount-s3", "--bucket=data=s3://bucket-a", "--read-only", "mnt"]).unwrap();
+        assert_eq!(args.bucket_name(), "bucket-a");
+        assert_eq!(args.mount_point, PathBuf::from("mnt"));
+    }
+
+    #[test_case("data=s3://bucket-a", true; "bucket")]
+    #[test_case("data=s3://bucket-a/", true; "bucket with slash")]
+    #[test_case("data=s3://bucket-a/logs/", true; "prefix")]
+    #[test_case("data=s3://bucket-a/logs", false; "prefix without slash")]
+    #[test_case("data=bucket-a", false; "not a uri")]
+    #[test_case("s3://bucket-a", false; "no name")]
+    #[test_case("data=s3://a", false; "invalid bucket")]
+    fn test_parse_bucket_mount(bucket_mount: &str, valid: bool) {
+        assert_eq!(parse_bucket_mount(bucket_mount).is_ok(), valid);
+    }
+
+    #[test_case(".shadowed", true; "suffix")]
+    #[test_case("~file", true; "other suffix")]
+    #[test_case("", false; "empty")]
+    #[test_case("/file", false; "slash")]
+    fn test_parse_shadowed_file_suffix(suffix: &str, valid: bool) {
+        assert_eq!(parse_shadowed_file_suffix(suffix).is_ok(), valid);
+    }
+
+    #[test]
+    fn role_arn_assumes_role_with_profile() {
+        let args = CliArgs::try_parse_from([
+            "mount-s3",
+            "test-bucket",
+            "mnt",
+            "--profile",
+            "source",
+            "--role-arn",
+            "arn:aws:iam::111122223333:role/mountpoint",
+        ])
+        .unwrap();
+        let S3ClientAuthConfig::AssumeRole {
+            role_arn,
+            session_name,
+            source,
+        } = args.auth_config()
+        else {
+            panic!("expected to assume a role");
+        };
+        assert_eq!(role_arn, "arn:aws:iam::111122223333:role/mountpoint");
+        assert_eq!(session_name, "mountpoint-s3");
+        assert!(matches!(*source, S3ClientAuthConfig::Profile(profile) if profile == "source"));
+
+        // A session name needs a role to assume
+        CliArgs::try_parse_from(["mount-s3", "test-bucket", "mnt", "--role-session-name", "training"]).unwrap_err();
+    }
+
+    #[test_case("training-job-1", true; "simple")]
+    #[test_case("user@example.com", true; "email")]
+    #[test_case("a", false; "too short")]
+    #[test_case("has spaces", false; "spaces")]
+    #[test_case("sesión", false; "non-ascii")]
+    fn validate_role_session_name(session_name: &str, valid: bool) {
+        assert_eq!(parse_role_session_name(session_name).is_ok(), valid);
+    }
+
+    #[test_case("user_allow_other\n", true; "enabled")]
+    #[test_case("# mount_max = 1000\n  user_allow_other  # allow other users\n", true; "with comments")]
+    #[test_case("#user_allow_other\n", false; "commented out")]
+    #[test_case("mount_max = 1000\n", false; "not set")]
+    #[test_case("", false; "empty")]
+    fn validate_fuse_conf_allows_other(contents: &str, allowed: bool) {
+        assert_eq!(fuse_conf_allows_other(contents), allowed);
+    }
+
+    #[test_case(1.0, 64; "slow network")]
+    #[test_case(25.0, 64; "25 gbps")]
+    #[test_case(100.0, 250; "100 gbps")]
+    #[test_case(3200.0, 1024; "capped")]
+    fn validate_default_max_background(throughput_target_gbps: f64, max_background: u16) {
+        assert_eq!(default_max_background(throughput_target_gbps), max_background);
+    }
+
+    #[test_case(8 * 1024 * 1024, None, true; "no maximum")]
+    #[test_case(8 * 1024 * 1024, Some(8 * 1024 * 1024 * 10000), true; "exactly fits")]
+    #[test_case(8 * 1024 * 1024, Some(8 * 1024 * 1024 * 10000 + 1), false; "one byte too many")]
+    #[test_case(1024 * 1024 * 1024, Some(6 * 1024 * 1024 * 1024 * 1024), false; "larger than S3 allows")]
+    fn validate_maximum_object_size_for_part_size(write_part_size: u64, maximum_object_size: Option<u64>, valid: bool) {
+        assert_eq!(
+            validate_maximum_object_size(write_part_size, maximum_object_size).is_ok(),
+            valid
+        );
+    }
+
+    #[test]
+    fn separate_read_and_write_part_sizes() {
+        let args = CliArgs::try_parse_from(["mount-s3", "test-bucket", "mnt", "--part-size", "16777216"]).unwrap();
+        assert_eq!(args.read_part_size(), 16777216);
+        assert_eq!(args.write_part_size(), 16777216);
+        let args = CliArgs::try_parse_from([
+            "mount-s3",
+            "test-bucket",
+            "mnt",
+            "--read-part-size",
+            "5242880",
+            "--write-part-size",
+            "33554432",
+        ])
+        .unwrap();
+        assert_eq!(args.read_part_size(), 5242880);
+        assert_eq!(args.write_part_size(), 33554432);
+    }
+
+    #[test_case(8, 10.0, 25; "10 gbps")]
+    #[test_case(8, 1.0, 8; "slow network")]
+    #[test_case(32, 10.0, 32; "many parts per file")]
+    fn validate_append_upload_concurrency(per_file: usize, throughput_target_gbps: f64, total: usize) {
+        let concurrency = append_upload_concurrency(per_file, throughput_target_gbps);
+        assert_eq!(concurrency.per_file, per_file);
+        assert_eq!(concurrency.total, total);
+    }
+
+    #[test]
+    fn write_only_access_mode() {
+        let args = CliArgs::try_parse_from(["mount-s3", "test-bucket", "mnt", "--write-only"]).unwrap();
+        assert_eq!(args.access_mode(), AccessMode::WriteOnly);
+        let args = CliArgs::try_parse_from(["mount-s3", "test-bucket", "mnt", "--read-only"]).unwrap();
+        assert_eq!(args.access_mode(), AccessMode::ReadOnly);
+        CliArgs::try_parse_from(["mount-s3", "test-bucket", "mnt", "--read-only", "--write-only"]).unwrap_err();
+    }
+
+    #[test_case(&[], None; "no cache")]
+    #[test_case(&["--cache", "/tmp/cache"], Some(1); "data cache")]
+    #[test_case(&["--profile-preset", "training"], Some(60); "preset without cache")]
+    #[test_case(&["--profile-preset", "archive"], None; "preset without metadata ttl")]
+    #[test_case(&["--profile-preset", "training", "--metadata-ttl", "5"], Some(5); "explicit ttl over preset")]
+    fn summary_metadata_cache_ttl(extra_args: &[&str], ttl_secs: Option<u64>) {
+        let args = CliArgs::try_parse_from(["mount-s3", "test-bucket", "mnt"].iter().chain(extra_args)).unwrap();
+        assert_eq!(metadata_cache_ttl_secs(&cache_config(&args)), ttl_secs);
+    }
+
+    #[test]
+    fn validate_logs_to_stdout() {
+        let args = CliArgs::try_parse_from(["mount-s3", "test-bucket", "mnt", "--validate"]).unwrap();
+        assert!(args.validate);
+        assert!(args.logging_config().log_to_stdout);
+    }
+
+    #[test]
+    fn metrics_dimensions_default_to_mount_point() {
+        let args = CliArgs::try_parse_from(["mount-s3", "test-bucket", "mnt", "--metrics-format", "emf"]).unwrap();
+        let config = args.metrics_config();
+        assert_eq!(config.log_format, MetricsFormat::Emf);
+        assert_eq!(
+            config.emf_dimensions,
+            [
+                ("Bucket".to_owned(), "test-bucket".to_owned()),
+                ("MountId".to_owned(), "mnt".to_owned())
+            ]
+        );
+
+        let args = CliArgs::try_parse_from(["mount-s3", "test-bucket", "mnt", "--mount-id", "train-1"]).unwrap();
+        let config = args.metrics_config();
+        assert_eq!(config.log_format, MetricsFormat::Log);
+        assert_eq!(config.emf_dimensions[1], ("MountId".to_owned(), "train-1".to_owned()));
+    }
 }
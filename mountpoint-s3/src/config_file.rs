@@ -0,0 +1,106 @@
+//! Mount options read from a TOML configuration file.
+//!
+//! A configuration file sets the same options as the command line, with each option's long name
+//! (like `allow-delete` or `allow_delete`) as a key. Rather than duplicating every option as a
+//! serde field, we turn the file back into command-line arguments and let clap parse them
+//! together with the real ones, so the file supports exactly the options the command line does
+//! and validates them the same way. The file's arguments come first and options can override
+//! themselves, so options given on the command line take precedence over the file.
+
+use std::ffi::OsString;
+use std::path::Path;
+
+use anyhow::{anyhow, Context as _};
+use clap::{ArgAction, Command};
+use toml::{Table, Value};
+
+/// Options that can't be set from a configuration file. `--bucket` takes the place of the bucket
+/// name, so like the bucket name it has to be given on the command line.
+const EXCLUDED_OPTIONS: &[&str] = &["bucket", "config-file", "help", "version"];
+
+/// Read the configuration file at `path` and convert its options into command-line arguments
+/// for `command`.
+pub fn load_config_file(path: &Path, command: &Command) -> anyhow::Result<Vec<OsString>> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("failed to read config file {}", path.display()))?;
+    config_to_args(&contents, command).with_context(|| format!("invalid config file {}", path.display()))
+}
+
+/// Convert the options in a TOML configuration into command-line arguments for `command`
+fn config_to_args(contents: &str, command: &Command) -> anyhow::Result<Vec<OsString>> {
+    let table: Table = contents.parse()?;
+    let mut args = Vec::new();
+    for (key, value) in table {
+        let name = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(name.as_str()))
+            .filter(|_| !EXCLUDED_OPTIONS.contains(&name.as_str()))
+            .ok_or_else(|| anyhow!("unknown option `{key}`"))?;
+        let flag = format!("--{name}");
+        let is_flag = matches!(arg.get_action(), ArgAction::SetTrue);
+
+        let values = match value {
+            Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            match (value, is_flag) {
+                (Value::Boolean(true), true) => args.push(flag.clone().into()),
+                (Value::Boolean(false), true) => {}
+                (_, true) => return Err(anyhow!("option `{key}` must be true or false")),
+                (Value::String(s), false) => args.extend([flag.clone().into(), s.into()]),
+                (Value::Integer(i), false) => args.extend([flag.clone().into(), i.to_string().into()]),
+                (Value::Float(f), false) => args.extend([flag.clone().into(), f.to_string().into()]),
+                (Value::Boolean(b), false) => args.extend([flag.clone().into(), b.to_string().into()]),
+                (_, false) => return Err(anyhow!("option `{key}` must be a string, number, or boolean")),
+            }
+        }
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use crate::cli::CliArgs;
+
+    use super::*;
+
+    #[test]
+    fn test_config_to_args() {
+        let config = r#"
+            prefix = "some/prefix/"
+            allow-delete = true
+            allow_overwrite = false
+            part_size = 16777216
+            cache = "/tmp/cache"
+        "#;
+        let args = config_to_args(config, &CliArgs::command()).unwrap();
+        let args: Vec<_> = args.iter().map(|arg| arg.to_str().unwrap()).collect();
+        assert_eq!(
+            args,
+            [
+                "--allow-delete",
+                "--cache",
+                "/tmp/cache",
+                "--part-size",
+                "16777216",
+                "--prefix",
+                "some/prefix/"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let command = CliArgs::command();
+        assert!(config_to_args("not-an-option = true", &command).is_err());
+        assert!(config_to_args("config-file = \"other.toml\"", &command).is_err());
+        assert!(config_to_args("bucket = [\"data=s3://DOC-EXAMPLE-BUCKET/\"]", &command).is_err());
+        assert!(config_to_args("allow-delete = \"yes\"", &command).is_err());
+        assert!(config_to_args("[prefix]\nvalue = 1", &command).is_err());
+        assert!(config_to_args("not toml", &command).is_err());
+    }
+}
@@ -0,0 +1,341 @@
+//! Credentials scoped down to the bucket and prefix being mounted.
+//!
+//! We assume an IAM role with an inline session policy that only allows access to the mounted
+//! bucket and prefix. The role's own policies still apply, so the session can never do more than
+//! the role could. The CRT's STS provider can't pass a session policy, so we call STS ourselves and
+//! hand the credentials to the CRT through a delegate provider, refreshing them from a background
+//! thread before they expire. The SQS client for event notifications uses the same credentials, so
+//! the session policy also allows receiving messages from the event queue, if there is one.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Context as _};
+use aws_config::{BehaviorVersion, Region};
+use aws_credential_types::provider::{future, ProvideCredentials};
+use aws_credential_types::Credentials;
+use aws_sdk_sts::error::DisplayErrorContext;
+use mountpoint_s3_crt::auth::credentials::{CredentialsProvider, DelegateCredentials};
+use mountpoint_s3_crt::common::allocator::Allocator;
+use serde_json::json;
+use tokio::runtime::Runtime;
+
+use super::sdk_runtime;
+use crate::event_notifications::queue_arn;
+
+/// How long each set of scoped credentials lasts for
+const SESSION_DURATION: Duration = Duration::from_secs(60 * 60);
+/// How long before scoped credentials expire that we start trying to refresh them
+const REFRESH_BEFORE_EXPIRATION: Duration = Duration::from_secs(5 * 60);
+/// How long to wait before trying again after failing to refresh scoped credentials
+const REFRESH_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Configuration for scoping credentials down to a mount
+#[derive(Debug, Clone)]
+pub struct ScopedCredentialsConfig {
+    pub bucket: String,
+    pub prefix: String,
+    pub read_only: bool,
+    /// The role to assume. If not set, we assume the role the source credentials belong to.
+    pub role_arn: Option<String>,
+    pub session_name: String,
+    /// The profile to get the source credentials from, rather than the default credentials chain
+    pub profile: Option<String>,
+    /// The region to call STS in, rather than the one from the environment or profile
+    pub region: Option<String>,
+    /// The URL of the SQS queue to receive event notifications from, if any
+    pub event_queue_url: Option<String>,
+}
+
+/// Credentials scoped down to the mounted bucket and prefix, which are refreshed in the background.
+/// The S3 client and the SQS client for event notifications share them.
+#[derive(Debug, Clone)]
+pub struct ScopedCredentials {
+    credentials: Arc<Mutex<DelegateCredentials>>,
+}
+
+impl ScopedCredentials {
+    /// A credentials provider for the CRT's S3 client
+    pub fn crt_provider(&self) -> anyhow::Result<CredentialsProvider> {
+        let credentials = self.credentials.clone();
+        let provider = CredentialsProvider::new_delegate(&Allocator::default(), move || {
+            Some(credentials.lock().unwrap().clone())
+        })?;
+        Ok(provider)
+    }
+}
+
+impl ProvideCredentials for ScopedCredentials {
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        let credentials = self.credentials.lock().unwrap();
+        future::ProvideCredentials::ready(Ok(Credentials::new(
+            &credentials.access_key_id,
+            &credentials.secret_access_key,
+            credentials.session_token.clone(),
+            credentials.expiration,
+            "ScopedCredentials",
+        )))
+    }
+}
+
+/// Get credentials scoped down to the mounted bucket and prefix. Fails if we can't get the first
+/// set of credentials.
+pub fn scoped_credentials(config: ScopedCredentialsConfig) -> anyhow::Result<ScopedCredentials> {
+    validate_bucket(&config.bucket)?;
+
+    let runtime = sdk_runtime()?;
+    let assumer = runtime.block_on(RoleAssumer::new(config))?;
+    let credentials = runtime.block_on(assumer.assume_role())?;
+    tracing::info!(
+        role_arn = %assumer.role_arn,
+        expiration = ?credentials.expiration,
+        "assumed role with credentials scoped to the mount"
+    );
+
+    let credentials = Arc::new(Mutex::new(credentials));
+    spawn_refresher(runtime, assumer, credentials.clone())?;
+    Ok(ScopedCredentials { credentials })
+}
+
+/// Start a thread that refreshes the scoped credentials shortly before they expire. The providers
+/// read them from `credentials`, so they never block the CRT's event loop on STS.
+fn spawn_refresher(
+    runtime: &'static Runtime,
+    assumer: RoleAssumer,
+    credentials: Arc<Mutex<DelegateCredentials>>,
+) -> anyhow::Result<()> {
+    thread::Builder::new()
+        .name("scoped-credentials".to_owned())
+        .spawn(move || loop {
+            let expiration = credentials.lock().unwrap().expiration;
+            let refresh_at = expiration.map_or(SystemTime::now() + SESSION_DURATION, |expiration| {
+                expiration - REFRESH_BEFORE_EXPIRATION
+            });
+            if let Ok(wait) = refresh_at.duration_since(SystemTime::now()) {
+                thread::sleep(wait);
+            }
+            match runtime.block_on(assumer.assume_role()) {
+                Ok(refreshed) => {
+                    tracing::debug!(expiration = ?refreshed.expiration, "refreshed scoped credentials");
+                    *credentials.lock().unwrap() = refreshed;
+                }
+                Err(e) => {
+                    // Keep using the old credentials until they expire; requests will fail after that
+                    tracing::warn!("failed to refresh scoped credentials: {e:#}");
+                    thread::sleep(REFRESH_RETRY_DELAY);
+                }
+            }
+        })
+        .context("failed to start thread to refresh scoped credentials")?;
+    Ok(())
+}
+
+/// Session policies only work for general purpose buckets mounted by name
+fn validate_bucket(bucket: &str) -> anyhow::Result<()> {
+    if bucket.starts_with("arn:") {
+        return Err(anyhow!(
+            "--scope-credentials can only be used with a bucket name, not an access point or bucket ARN"
+        ));
+    }
+    if bucket.ends_with("--x-s3") {
+        return Err(anyhow!("--scope-credentials doesn't support directory buckets"));
+    }
+    Ok(())
+}
+
+/// Calls STS to assume a role with the session policy for a mount
+#[derive(Debug)]
+struct RoleAssumer {
+    client: aws_sdk_sts::Client,
+    role_arn: String,
+    session_name: String,
+    policy: String,
+}
+
+impl RoleAssumer {
+    async fn new(config: ScopedCredentialsConfig) -> anyhow::Result<Self> {
+        let mut loader = aws_config::defaults(BehaviorVersion::latest());
+        if let Some(profile) = &config.profile {
+            loader = loader.profile_name(profile);
+        }
+        if let Some(region) = &config.region {
+            loader = loader.region(Region::new(region.clone()));
+        }
+        let sdk_config = loader.load().await;
+        let mut client_config = aws_sdk_sts::config::Builder::from(&sdk_config);
+        if sdk_config.region().is_none() {
+            // STS is available in every region, so any region will do if none is configured
+            client_config = client_config.region(Region::from_static("us-east-1"));
+        }
+        let client = aws_sdk_sts::Client::from_conf(client_config.build());
+
+        let role_arn = match config.role_arn {
+            Some(role_arn) => role_arn,
+            None => {
+                let identity = client.get_caller_identity().send().await.map_err(|e| {
+                    anyhow!(
+                        "failed to get the identity of the source credentials: {}",
+                        DisplayErrorContext(e)
+                    )
+                })?;
+                let caller_arn = identity.arn().unwrap_or_default();
+                role_arn_for_session(caller_arn).ok_or_else(|| {
+                    anyhow!(
+                        "the source credentials for {caller_arn} don't belong to an IAM role, \
+                        so use --role-arn to choose a role for --scope-credentials to assume"
+                    )
+                })?
+            }
+        };
+        let partition = role_arn.split(':').nth(1).unwrap_or("aws");
+        let queue_arn = config
+            .event_queue_url
+            .as_deref()
+            .and_then(|queue_url| queue_arn(queue_url, partition));
+        let policy = session_policy(
+            partition,
+            &config.bucket,
+            &config.prefix,
+            config.read_only,
+            queue_arn.as_deref(),
+        );
+
+        Ok(Self {
+            client,
+            role_arn,
+            session_name: config.session_name,
+            policy,
+        })
+    }
+
+    async fn assume_role(&self) -> anyhow::Result<DelegateCredentials> {
+        let output = self
+            .client
+            .assume_role()
+            .role_arn(&self.role_arn)
+            .role_session_name(&self.session_name)
+            .policy(&self.policy)
+            .duration_seconds(SESSION_DURATION.as_secs() as i32)
+            .send()
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "failed to assume role {} with a session policy for the mount: {}",
+                    self.role_arn,
+                    DisplayErrorContext(e)
+                )
+            })?;
+        let credentials = output
+            .credentials()
+            .ok_or_else(|| anyhow!("AssumeRole response for role {} had no credentials", self.role_arn))?;
+        Ok(DelegateCredentials {
+            access_key_id: credentials.access_key_id().to_owned(),
+            secret_access_key: credentials.secret_access_key().to_owned(),
+            session_token: Some(credentials.session_token().to_owned()),
+            expiration: SystemTime::try_from(*credentials.expiration()).ok(),
+        })
+    }
+}
+
+/// The IAM role ARN for an assumed role session ARN, like the one for an EC2 instance profile:
+/// `arn:aws:sts::111122223333:assumed-role/my-role/i-0123456789abcdef0` is a session for
+/// `arn:aws:iam::111122223333:role/my-role`. Session ARNs don't include the role's path, so this
+/// only works for roles with the default path.
+fn role_arn_for_session(session_arn: &str) -> Option<String> {
+    let [prefix, partition, "sts", "", account_id, resource] = session_arn.splitn(6, ':').collect::<Vec<_>>()[..]
+    else {
+        return None;
+    };
+    let ["assumed-role", role_name, _session_name] = resource.splitn(3, '/').collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    Some(format!("{prefix}:{partition}:iam::{account_id}:role/{role_name}"))
+}
+
+/// An IAM session policy that only allows the object operations Mountpoint uses, and only on keys
+/// under the mounted prefix, and receiving event notifications from the queue with `queue_arn`.
+fn session_policy(partition: &str, bucket: &str, prefix: &str, read_only: bool, queue_arn: Option<&str>) -> String {
+    let mut object_actions = vec!["s3:GetObject", "s3:GetObjectVersion", "s3:GetObjectAttributes"];
+    if !read_only {
+        object_actions.extend(["s3:PutObject", "s3:DeleteObject", "s3:AbortMultipartUpload"]);
+    }
+    let mut statements = vec![
+        json!({
+            "Effect": "Allow",
+            "Action": object_actions,
+            "Resource": format!("arn:{partition}:s3:::{bucket}/{prefix}*"),
+        }),
+        json!({
+            "Effect": "Allow",
+            "Action": "s3:ListBucket",
+            "Resource": format!("arn:{partition}:s3:::{bucket}"),
+            "Condition": {"StringLike": {"s3:prefix": format!("{prefix}*")}},
+        }),
+    ];
+    if let Some(queue_arn) = queue_arn {
+        statements.push(json!({
+            "Effect": "Allow",
+            "Action": ["sqs:ReceiveMessage", "sqs:DeleteMessage"],
+            "Resource": queue_arn,
+        }));
+    }
+    let policy = json!({
+        "Version": "2012-10-17",
+        "Statement": statements,
+    });
+    policy.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test]
+    fn test_session_policy() {
+        let policy = session_policy("aws", "doc-example-bucket", "data/", false, None);
+        let policy: Value = serde_json::from_str(&policy).unwrap();
+        let statements = policy["Statement"].as_array().unwrap();
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[0]["Resource"], "arn:aws:s3:::doc-example-bucket/data/*");
+        assert!(statements[0]["Action"]
+            .as_array()
+            .unwrap()
+            .contains(&Value::from("s3:PutObject")));
+        assert_eq!(statements[1]["Resource"], "arn:aws:s3:::doc-example-bucket");
+        assert_eq!(statements[1]["Condition"]["StringLike"]["s3:prefix"], "data/*");
+
+        let queue_arn = "arn:aws-cn:sqs:cn-north-1:111122223333:events";
+        let policy = session_policy("aws-cn", "doc-example-bucket", "", true, Some(queue_arn));
+        let policy: Value = serde_json::from_str(&policy).unwrap();
+        let statements = policy["Statement"].as_array().unwrap();
+        assert_eq!(statements[0]["Resource"], "arn:aws-cn:s3:::doc-example-bucket/*");
+        assert_eq!(
+            statements[0]["Action"],
+            Value::from(vec!["s3:GetObject", "s3:GetObjectVersion", "s3:GetObjectAttributes"])
+        );
+        assert_eq!(statements.len(), 3);
+        assert_eq!(statements[2]["Resource"], queue_arn);
+    }
+
+    #[test_case("arn:aws:sts::111122223333:assumed-role/my-role/i-0123456789abcdef0", Some("arn:aws:iam::111122223333:role/my-role"); "instance profile")]
+    #[test_case("arn:aws-cn:sts::111122223333:assumed-role/my-role/session", Some("arn:aws-cn:iam::111122223333:role/my-role"); "china")]
+    #[test_case("arn:aws:iam::111122223333:user/my-user", None; "user")]
+    #[test_case("arn:aws:sts::111122223333:federated-user/my-user", None; "federated user")]
+    fn test_role_arn_for_session(session_arn: &str, role_arn: Option<&str>) {
+        assert_eq!(role_arn_for_session(session_arn).as_deref(), role_arn);
+    }
+
+    #[test_case("doc-example-bucket", true; "bucket name")]
+    #[test_case("arn:aws:s3:us-west-2:111122223333:accesspoint/my-access-point", false; "access point")]
+    #[test_case("doc-example-bucket--usw2-az1--x-s3", false; "directory bucket")]
+    fn test_validate_bucket(bucket: &str, valid: bool) {
+        assert_eq!(validate_bucket(bucket).is_ok(), valid);
+    }
+}
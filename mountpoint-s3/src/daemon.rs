@@ -0,0 +1,176 @@
+//! Helpers for running Mountpoint as a daemon and reporting its status to service managers.
+
+use std::ffi::OsStr;
+use std::fs::OpenOptions;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+use anyhow::Context as _;
+
+use crate::fuse::session::FuseSession;
+
+/// Environment variable systemd sets to the socket that services with `Type=notify` report their
+/// status to
+const NOTIFY_SOCKET_ENV: &str = "NOTIFY_SOCKET";
+
+/// Environment variables systemd sets when a service has `WatchdogSec=` configured: the watchdog
+/// timeout in microseconds, and the PID that's expected to send keep-alive pings
+const WATCHDOG_USEC_ENV: &str = "WATCHDOG_USEC";
+const WATCHDOG_PID_ENV: &str = "WATCHDOG_PID";
+
+/// Environment variables the service manager uses to talk to us, which child processes shouldn't
+/// inherit
+pub const SERVICE_MANAGER_ENV: &[&str] = &[NOTIFY_SOCKET_ENV, WATCHDOG_USEC_ENV, WATCHDOG_PID_ENV];
+
+/// Tell the service manager that the file system is mounted and ready to use, following the
+/// `sd_notify` protocol. Does nothing if we weren't started by a service manager that asked for
+/// notifications.
+pub fn notify_ready() {
+    // Include our PID, since we might have forked away from the process the service manager started
+    notify(&format!("READY=1\nMAINPID={}\n", std::process::id()));
+}
+
+/// Tell the service manager that the file system is being unmounted.
+pub fn notify_stopping() {
+    notify("STOPPING=1\n");
+}
+
+/// If the service manager asked for watchdog keep-alive pings, start a thread that sends them
+/// until `session` is closed. We ping twice per watchdog timeout, as systemd recommends.
+///
+/// Each ping is only sent once the file system at `mount_point` has answered a `statfs`, which the
+/// kernel always sends to us rather than answering from its caches. If the FUSE workers stop
+/// handling requests, the check blocks, the pings stop, and the service manager restarts us.
+pub fn start_watchdog(session: &mut FuseSession, mount_point: &Path) {
+    let Some(timeout) = watchdog_timeout() else {
+        return;
+    };
+    let interval = timeout / 2;
+    let mount_point = mount_point.to_owned();
+    let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+    let spawned = std::thread::Builder::new()
+        .name("watchdog".to_owned())
+        .spawn(move || loop {
+            match nix::sys::statvfs::statvfs(&mount_point) {
+                Ok(_) => notify("WATCHDOG=1\n"),
+                Err(error) => tracing::warn!(?mount_point, ?error, "file system failed watchdog check"),
+            }
+            // The sender is dropped when the file system is unmounted
+            if stop_receiver.recv_timeout(interval) != Err(RecvTimeoutError::Timeout) {
+                break;
+            }
+        });
+    match spawned {
+        Ok(_) => {
+            tracing::debug!(?interval, "sending watchdog keep-alive pings to service manager");
+            session.run_on_close(Box::new(move || drop(stop_sender)));
+        }
+        Err(e) => tracing::warn!("failed to start watchdog thread: {e:?}"),
+    }
+}
+
+/// The watchdog timeout the service manager asked for, if it's meant for this process.
+fn watchdog_timeout() -> Option<Duration> {
+    parse_watchdog_timeout(
+        std::env::var(WATCHDOG_USEC_ENV).ok().as_deref(),
+        std::env::var(WATCHDOG_PID_ENV).ok().as_deref(),
+        std::process::id(),
+    )
+}
+
+fn parse_watchdog_timeout(usec: Option<&str>, watchdog_pid: Option<&str>, pid: u32) -> Option<Duration> {
+    let usec: u64 = usec?.parse().ok()?;
+    // The watchdog is for the process systemd started, so a forked daemon mustn't ping on its behalf
+    if watchdog_pid.is_some_and(|watchdog_pid| watchdog_pid.parse() != Ok(pid)) {
+        return None;
+    }
+    (usec > 0).then_some(Duration::from_micros(usec))
+}
+
+/// Send `state` to the service manager, if it asked for notifications.
+fn notify(state: &str) {
+    let Some(socket) = std::env::var_os(NOTIFY_SOCKET_ENV) else {
+        return;
+    };
+    if let Err(error) = send_notification(&socket, state) {
+        tracing::warn!(?socket, ?error, state, "failed to notify service manager");
+    } else {
+        tracing::trace!(?socket, state, "notified service manager");
+    }
+}
+
+/// Send a state string to the `sd_notify` socket at `socket`, which is either a path or, if it
+/// starts with `@`, the name of a socket in the abstract namespace.
+fn send_notification(socket: &OsStr, state: &str) -> io::Result<()> {
+    let sender = UnixDatagram::unbound()?;
+    match socket.as_bytes() {
+        #[cfg(target_os = "linux")]
+        [b'@', name @ ..] => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            sender.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        _ => {
+            sender.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+/// Point stdin, stdout, and stderr at `/dev/null`, so that a daemon doesn't hold on to the terminal
+/// it was started from. Unlike closing them, this stops the file descriptors from being reused by
+/// files we open later, which would then receive anything written to stdout or stderr.
+pub fn redirect_stdio_to_null() -> anyhow::Result<()> {
+    let dev_null = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")
+        .context("couldn't open /dev/null")?;
+    for fd in [
+        io::stdin().as_raw_fd(),
+        io::stdout().as_raw_fd(),
+        io::stderr().as_raw_fd(),
+    ] {
+        nix::unistd::dup2(dev_null.as_raw_fd(), fd).context("couldn't redirect standard stream to /dev/null")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send_notification() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify.sock");
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        send_notification(path.as_os_str(), "READY=1\n").unwrap();
+
+        let mut buf = [0; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1\n");
+    }
+
+    #[test]
+    fn test_parse_watchdog_timeout() {
+        let timeout = parse_watchdog_timeout(Some("30000000"), Some("42"), 42);
+        assert_eq!(timeout, Some(Duration::from_secs(30)));
+        let timeout = parse_watchdog_timeout(Some("30000000"), None, 42);
+        assert_eq!(timeout, Some(Duration::from_secs(30)));
+
+        // Meant for a different process
+        assert_eq!(parse_watchdog_timeout(Some("30000000"), Some("41"), 42), None);
+
+        // Disabled or invalid
+        assert_eq!(parse_watchdog_timeout(Some("0"), None, 42), None);
+        assert_eq!(parse_watchdog_timeout(Some("invalid"), None, 42), None);
+        assert_eq!(parse_watchdog_timeout(None, Some("42"), 42), None);
+    }
+}
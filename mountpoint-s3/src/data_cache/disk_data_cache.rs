@@ -152,6 +152,13 @@ impl DiskBlockHeader {
                 s3_key_match,
                 etag_match, block_idx_match, "block data did not match expected values",
             );
+            if !s3_key_match || !etag_match {
+                // Blocks are stored at a path derived from a hash of the S3 key and ETag, so a block
+                // for a different object at this path means the hashes collided (or the block was
+                // corrupted). Either way the block is rejected, but we want to know how often it
+                // happens.
+                metrics::counter!("disk_data_cache.cache_key_mismatch").increment(1);
+            }
             Err(DiskBlockAccessError::FieldMismatchError)
         }
     }
@@ -0,0 +1,423 @@
+//! Invalidating cached metadata when objects change, using S3 event notifications.
+//!
+//! The bucket sends [event notifications](https://docs.aws.amazon.com/AmazonS3/latest/userguide/EventNotifications.html)
+//! to an SQS queue, either directly or through an SNS topic, and a background thread long-polls
+//! the queue. For each object that was created, replaced, or removed, we forget our cached
+//! metadata for its key, and tell the kernel to forget its cached directory entries and attributes,
+//! so the next access to the key goes to S3. This makes changes visible within seconds, even with
+//! long metadata TTLs. Each message is deleted from the queue once it's been handled, so a queue
+//! can only be used by one mount. SQS requests use the same credentials as the S3 client.
+//!
+//! Cached object content doesn't need invalidating, since it's cached by ETag, and so a replaced
+//! object's old content is never read from the cache once we've seen its new ETag.
+
+use std::ffi::OsStr;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context as _};
+use aws_config::sts::AssumeRoleProvider;
+use aws_config::{BehaviorVersion, ConfigLoader, Region};
+use aws_sdk_sqs::error::DisplayErrorContext;
+use aws_sdk_sqs::types::DeleteMessageBatchRequestEntry;
+use fuser::Notifier;
+use mountpoint_s3_client::config::S3ClientAuthConfig;
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use tokio::runtime::Runtime;
+
+use crate::credentials::scoped::ScopedCredentials;
+use crate::credentials::sdk_runtime;
+use crate::fs::{CacheInvalidator, KernelInvalidation};
+use crate::fuse::session::FuseSession;
+
+/// The most messages SQS returns from one ReceiveMessage request
+const MAX_MESSAGES_PER_RECEIVE: i32 = 10;
+/// How long each ReceiveMessage request waits for messages to arrive, which is the longest SQS allows
+const RECEIVE_WAIT_TIME: Duration = Duration::from_secs(20);
+/// How long to wait before trying again after failing to receive messages
+const RECEIVE_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Configuration for invalidating cached metadata from event notifications
+#[derive(Debug, Clone)]
+pub struct EventQueueConfig {
+    /// The URL of the SQS queue the bucket's event notifications are sent to
+    pub queue_url: String,
+    /// Where to get credentials for SQS requests, which should be the same as the S3 client's
+    pub credentials: QueueCredentials,
+}
+
+/// Where the SQS client gets its credentials from
+#[derive(Debug, Clone)]
+pub enum QueueCredentials {
+    /// The same auth configuration as the S3 client
+    Auth(S3ClientAuthConfig),
+    /// The credentials scoped down to the mount that the S3 client uses
+    Scoped(ScopedCredentials),
+}
+
+/// Start a background thread that invalidates cached metadata for the keys in the event
+/// notifications sent to a queue, until the file system is unmounted.
+pub fn start_event_invalidation(
+    config: EventQueueConfig,
+    invalidator: CacheInvalidator,
+    notifier: Notifier,
+    fuse_session: &mut FuseSession,
+) -> anyhow::Result<()> {
+    let runtime = sdk_runtime()?;
+    let client = runtime.block_on(sqs_client(&config))?;
+    tracing::info!(queue_url = %config.queue_url, "invalidating cached metadata from event notifications");
+
+    let poller = QueuePoller {
+        runtime,
+        client,
+        queue_url: config.queue_url,
+        invalidator,
+        notifier,
+    };
+    let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+    thread::Builder::new()
+        .name("event-notifications".to_owned())
+        .spawn(move || {
+            // The sender is dropped when the file system is unmounted
+            while stop_receiver.try_recv() == Err(TryRecvError::Empty) {
+                if let Err(e) = poller.poll() {
+                    tracing::warn!("failed to handle event notifications: {e:#}");
+                    thread::sleep(RECEIVE_RETRY_DELAY);
+                }
+            }
+        })
+        .context("failed to start thread to receive event notifications")?;
+    fuse_session.run_on_close(Box::new(move || drop(stop_sender)));
+    Ok(())
+}
+
+async fn sqs_client(config: &EventQueueConfig) -> anyhow::Result<aws_sdk_sqs::Client> {
+    // Requests have to go to the queue's region, which might not be the one from the environment
+    let region = region_from_queue_url(&config.queue_url).map(Region::new);
+    let loader = match &config.credentials {
+        QueueCredentials::Scoped(credentials) => config_loader(region).credentials_provider(credentials.clone()),
+        QueueCredentials::Auth(S3ClientAuthConfig::AssumeRole {
+            role_arn,
+            session_name,
+            source,
+        }) => {
+            let source_config = credentials_loader(source, region.clone())?.load().await;
+            let provider = AssumeRoleProvider::builder(role_arn)
+                .session_name(session_name)
+                .configure(&source_config)
+                .build()
+                .await;
+            config_loader(region).credentials_provider(provider)
+        }
+        QueueCredentials::Auth(auth_config) => credentials_loader(auth_config, region)?,
+    };
+    Ok(aws_sdk_sqs::Client::new(&loader.load().await))
+}
+
+fn config_loader(region: Option<Region>) -> ConfigLoader {
+    let loader = aws_config::defaults(BehaviorVersion::latest());
+    match region {
+        Some(region) => loader.region(region),
+        None => loader,
+    }
+}
+
+/// A config loader that gets credentials the same way as the S3 client does with `auth_config`,
+/// other than by assuming a role
+fn credentials_loader(auth_config: &S3ClientAuthConfig, region: Option<Region>) -> anyhow::Result<ConfigLoader> {
+    let loader = config_loader(region);
+    let loader = match auth_config {
+        S3ClientAuthConfig::Default => loader,
+        S3ClientAuthConfig::NoSigning => loader.no_credentials(),
+        // The SDK's profile provider supports IAM Identity Center (SSO) profiles itself
+        S3ClientAuthConfig::Profile(profile) | S3ClientAuthConfig::Sso(profile) => loader.profile_name(profile),
+        S3ClientAuthConfig::Provider(_) | S3ClientAuthConfig::AssumeRole { .. } => {
+            return Err(anyhow!(
+                "event notifications can't get credentials from {auth_config:?}"
+            ))
+        }
+    };
+    Ok(loader)
+}
+
+/// Receives event notifications from the queue and invalidates the keys in them
+struct QueuePoller {
+    runtime: &'static Runtime,
+    client: aws_sdk_sqs::Client,
+    queue_url: String,
+    invalidator: CacheInvalidator,
+    notifier: Notifier,
+}
+
+impl QueuePoller {
+    /// Wait for a batch of messages, handle them, and then delete them from the queue
+    fn poll(&self) -> anyhow::Result<()> {
+        let output = self
+            .runtime
+            .block_on(
+                self.client
+                    .receive_message()
+                    .queue_url(&self.queue_url)
+                    .max_number_of_messages(MAX_MESSAGES_PER_RECEIVE)
+                    .wait_time_seconds(RECEIVE_WAIT_TIME.as_secs() as i32)
+                    .send(),
+            )
+            .map_err(|e| {
+                anyhow!(
+                    "failed to receive messages from {}: {}",
+                    self.queue_url,
+                    DisplayErrorContext(e)
+                )
+            })?;
+        let messages = output.messages();
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        let mut entries = Vec::with_capacity(messages.len());
+        for (i, message) in messages.iter().enumerate() {
+            match parse_message(message.body().unwrap_or_default()) {
+                Ok(changes) => {
+                    for change in changes {
+                        self.invalidate(&change);
+                    }
+                }
+                // Receiving the message again won't make it any easier to understand
+                Err(e) => tracing::warn!(
+                    message_id = ?message.message_id(),
+                    "ignoring message that isn't an S3 event notification: {e}"
+                ),
+            }
+            if let Some(receipt_handle) = message.receipt_handle() {
+                let entry = DeleteMessageBatchRequestEntry::builder()
+                    .id(i.to_string())
+                    .receipt_handle(receipt_handle)
+                    .build()?;
+                entries.push(entry);
+            }
+        }
+
+        let output = self
+            .runtime
+            .block_on(
+                self.client
+                    .delete_message_batch()
+                    .queue_url(&self.queue_url)
+                    .set_entries(Some(entries))
+                    .send(),
+            )
+            .map_err(|e| {
+                anyhow!(
+                    "failed to delete messages from {}: {}",
+                    self.queue_url,
+                    DisplayErrorContext(e)
+                )
+            })?;
+        for failed in output.failed() {
+            // The message will be received and handled again, which is harmless
+            tracing::debug!(code = failed.code(), "failed to delete message: {:?}", failed.message());
+        }
+        Ok(())
+    }
+
+    fn invalidate(&self, change: &KeyChange) {
+        tracing::debug!(?change, "invalidating key from event notification");
+        metrics::counter!("event_notifications.keys_invalidated").increment(1);
+        for invalidation in self
+            .invalidator
+            .invalidate_key(&change.bucket, &change.key, change.removed)
+        {
+            let result = match &invalidation {
+                KernelInvalidation::Entry { parent, name } => self.notifier.inval_entry(*parent, OsStr::new(name)),
+                // A length of zero invalidates all of the file's cached contents
+                KernelInvalidation::Inode(ino) => self.notifier.inval_inode(*ino, 0, 0),
+                // Unlike invalidating the entry, this also tells inotify and fanotify watchers
+                KernelInvalidation::Delete { parent, child, name } => {
+                    self.notifier.delete(*parent, *child, OsStr::new(name))
+                }
+            };
+            if let Err(e) = result {
+                tracing::debug!(?invalidation, "failed to invalidate kernel cache: {e}");
+            }
+        }
+    }
+}
+
+/// A key that changed in S3, from an event notification
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KeyChange {
+    bucket: String,
+    key: String,
+    removed: bool,
+}
+
+/// A message in the queue, which is either an S3 event notification or an SNS notification
+/// wrapping one
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum QueueMessage {
+    Sns {
+        #[serde(rename = "Message")]
+        message: String,
+    },
+    S3(S3Event),
+}
+
+#[derive(Debug, Deserialize)]
+struct S3Event {
+    /// The test event S3 sends when notifications are first configured doesn't have any records
+    #[serde(rename = "Records", default)]
+    records: Vec<EventRecord>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventRecord {
+    #[serde(rename = "eventName")]
+    event_name: String,
+    s3: S3Entity,
+}
+
+#[derive(Debug, Deserialize)]
+struct S3Entity {
+    bucket: BucketEntity,
+    object: ObjectEntity,
+}
+
+#[derive(Debug, Deserialize)]
+struct BucketEntity {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ObjectEntity {
+    key: String,
+}
+
+/// The keys that changed according to the body of a message in the queue
+fn parse_message(body: &str) -> Result<Vec<KeyChange>, serde_json::Error> {
+    let event: S3Event = match serde_json::from_str(body)? {
+        QueueMessage::Sns { message } => serde_json::from_str(&message)?,
+        QueueMessage::S3(event) => event,
+    };
+    let changes = event
+        .records
+        .into_iter()
+        .filter_map(|record| {
+            let Some(key) = decode_key(&record.s3.object.key) else {
+                tracing::warn!(key = %record.s3.object.key, "ignoring event notification for invalid key");
+                return None;
+            };
+            Some(KeyChange {
+                bucket: record.s3.bucket.name,
+                key,
+                removed: record.event_name.starts_with("ObjectRemoved:")
+                    || record.event_name.starts_with("LifecycleExpiration:"),
+            })
+        })
+        .collect();
+    Ok(changes)
+}
+
+/// Keys in event notifications are URL-encoded like HTML form values, with spaces as `+`
+fn decode_key(key: &str) -> Option<String> {
+    let key = key.replace('+', " ");
+    percent_decode_str(&key).decode_utf8().ok().map(|key| key.into_owned())
+}
+
+/// The region in a queue URL, like `https://sqs.us-east-1.amazonaws.com/111122223333/my-queue` or
+/// the legacy `https://us-east-1.queue.amazonaws.com/111122223333/my-queue`
+fn region_from_queue_url(queue_url: &str) -> Option<String> {
+    let (_, rest) = queue_url.split_once("://")?;
+    let host = rest.split('/').next()?;
+    let labels: Vec<_> = host.split('.').collect();
+    match labels.as_slice() {
+        ["sqs", region, ..] | [region, "queue", ..] => Some(region.to_string()),
+        _ => None,
+    }
+}
+
+/// The ARN of the queue with a URL like `https://sqs.us-east-1.amazonaws.com/111122223333/my-queue`,
+/// in the given partition
+pub fn queue_arn(queue_url: &str, partition: &str) -> Option<String> {
+    let region = region_from_queue_url(queue_url)?;
+    let (_, rest) = queue_url.split_once("://")?;
+    let [_host, account_id, queue_name] = rest.trim_end_matches('/').split('/').collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    Some(format!("arn:{partition}:sqs:{region}:{account_id}:{queue_name}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn change(bucket: &str, key: &str, removed: bool) -> KeyChange {
+        KeyChange {
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            removed,
+        }
+    }
+
+    #[test]
+    fn test_parse_s3_event() {
+        let body = r#"{"Records": [
+            {"eventVersion": "2.1", "eventSource": "aws:s3", "eventName": "ObjectCreated:Put",
+             "s3": {"bucket": {"name": "test-bucket"}, "object": {"key": "dir/my+file%3F.txt", "size": 10}}},
+            {"eventVersion": "2.1", "eventSource": "aws:s3", "eventName": "ObjectRemoved:Delete",
+             "s3": {"bucket": {"name": "test-bucket"}, "object": {"key": "caf%C3%A9%2Bcr%C3%A8me"}}},
+            {"eventVersion": "2.1", "eventSource": "aws:s3", "eventName": "LifecycleExpiration:Delete",
+             "s3": {"bucket": {"name": "other-bucket"}, "object": {"key": "old"}}}
+        ]}"#;
+        assert_eq!(
+            parse_message(body).unwrap(),
+            vec![
+                change("test-bucket", "dir/my file?.txt", false),
+                change("test-bucket", "café+crème", true),
+                change("other-bucket", "old", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_sns_notification() {
+        let event = r#"{"Records": [{"eventName": "ObjectCreated:Copy", "s3": {"bucket": {"name": "test-bucket"}, "object": {"key": "a"}}}]}"#;
+        let body = serde_json::json!({
+            "Type": "Notification",
+            "MessageId": "22b80b92-fdea-4c2c-8f9d-bdfb0c7bf324",
+            "TopicArn": "arn:aws:sns:us-east-1:111122223333:events",
+            "Message": event,
+        })
+        .to_string();
+        assert_eq!(parse_message(&body).unwrap(), vec![change("test-bucket", "a", false)]);
+    }
+
+    #[test]
+    fn test_parse_test_event() {
+        let body = r#"{"Service": "Amazon S3", "Event": "s3:TestEvent", "Time": "2024-01-01T00:00:00.000Z",
+            "Bucket": "test-bucket", "RequestId": "5582815E1AEA5ADF", "HostId": "8cLeGAmw098X5cv4Zkwcmo8vvZa3eH3e"}"#;
+        assert_eq!(parse_message(body).unwrap(), vec![]);
+        parse_message("not json").expect_err("message should be invalid");
+    }
+
+    #[test_case("https://sqs.us-east-1.amazonaws.com/111122223333/my-queue", Some("us-east-1"); "standard")]
+    #[test_case("https://sqs.cn-north-1.amazonaws.com.cn/111122223333/my-queue", Some("cn-north-1"); "china")]
+    #[test_case("https://eu-west-1.queue.amazonaws.com/111122223333/my-queue", Some("eu-west-1"); "legacy")]
+    #[test_case("http://localhost:9324/000000000000/my-queue", None; "local")]
+    #[test_case("my-queue", None; "not a url")]
+    fn test_region_from_queue_url(queue_url: &str, region: Option<&str>) {
+        assert_eq!(region_from_queue_url(queue_url).as_deref(), region);
+    }
+
+    #[test_case("https://sqs.us-east-1.amazonaws.com/111122223333/my-queue", "aws", Some("arn:aws:sqs:us-east-1:111122223333:my-queue"); "standard")]
+    #[test_case("https://sqs.cn-north-1.amazonaws.com.cn/111122223333/my-queue", "aws-cn", Some("arn:aws-cn:sqs:cn-north-1:111122223333:my-queue"); "china")]
+    #[test_case("https://eu-west-1.queue.amazonaws.com/111122223333/my-queue/", "aws", Some("arn:aws:sqs:eu-west-1:111122223333:my-queue"); "legacy")]
+    #[test_case("http://localhost:9324/000000000000/my-queue", "aws", None; "local")]
+    fn test_queue_arn(queue_url: &str, partition: &str, arn: Option<&str>) {
+        assert_eq!(queue_arn(queue_url, partition).as_deref(), arn);
+    }
+}
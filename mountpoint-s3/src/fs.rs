@@ -1,37 +1,79 @@
 //! FUSE file system types and operations, not tied to the _fuser_ library bindings.
 
+use async_trait::async_trait;
 use bytes::Bytes;
+use futures::task::Spawn;
 use mountpoint_s3_crt::checksums::crc32c::{Crc32c, Hasher};
 use nix::unistd::{getgid, getuid};
 use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
+use std::path::Path;
 use std::str::FromStr;
-use std::time::{Duration, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
+use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
-use tracing::{debug, error, trace, Level};
+use tracing::{debug, error, info, trace, warn, Level};
 
-use fuser::consts::FOPEN_DIRECT_IO;
 use fuser::{FileAttr, KernelConfig};
-use mountpoint_s3_client::error::{GetObjectError, ObjectClientError};
-use mountpoint_s3_client::types::ETag;
+use mountpoint_s3_client::error::{GetObjectError, HeadObjectError, ObjectClientError};
+use mountpoint_s3_client::types::{CopyObjectParams, ETag, PutObjectParams};
 use mountpoint_s3_client::ObjectClient;
 
-use crate::inode::{Inode, InodeError, InodeKind, LookedUp, ReaddirHandle, Superblock, SuperblockConfig, WriteHandle};
+use crate::inode::{
+    Inode, InodeError, InodeKind, LookedUp, ReaddirHandle, Superblock, SuperblockConfig, WriteHandle, NEVER_EXPIRE_TTL,
+    SYMLINK_TARGET_METADATA_KEY,
+};
 use crate::logging;
+use crate::object::ObjectId;
 use crate::prefetch::{Prefetch, PrefetchReadError, PrefetchResult};
 use crate::prefix::Prefix;
 use crate::s3::S3Personality;
 use crate::sync::atomic::{AtomicI64, AtomicU64, Ordering};
-use crate::sync::{Arc, AsyncMutex, AsyncRwLock};
-use crate::upload::{UploadRequest, Uploader};
+use crate::sync::{Arc, AsyncMutex, AsyncRwLock, RwLock};
+use crate::upload::{IncompleteUploadCleaner, UploadCompleteError, UploadMetadataError, UploadRequest, Uploader};
 
-pub use crate::inode::InodeNo;
+pub use crate::inode::{
+    CacheInvalidator, CompositeNamespace, InodeNo, KernelInvalidation, NamespaceEntry, NamespaceError,
+};
+pub use crate::upload::{AppendUploadConcurrency, DEFAULT_APPEND_UPLOAD_CONCURRENCY};
 
 #[macro_use]
 mod error;
 pub use error::{Error, ToErrno};
 
+mod accounting;
+pub use accounting::{IoAccounting, IoUsage};
+
+mod attr_cache;
+use attr_cache::AttrCache;
+
+mod control;
+pub(crate) use control::ControlDirectory;
+pub use control::{ControlDirectoryMode, CONTROL_DIRECTORY_NAME};
+
+mod identity;
+use identity::Ownership;
+pub use identity::{Caller, IdentityMap};
+
+mod kernel_cache;
+use kernel_cache::KernelCache;
+pub use kernel_cache::KernelCacheMode;
+
+mod overlay;
+pub use overlay::OverlayDirectory;
+use overlay::{OverlayFile, OverlayLookups, OverlayReader};
+
+mod read_policy;
+pub use read_policy::ReadPolicy;
+use read_policy::ReadableRange;
+
+mod upload_failures;
+use upload_failures::{UploadFailure, UploadFailures};
+
+mod xattr;
+pub use xattr::ENOATTR;
+
 pub const FUSE_ROOT_INODE: InodeNo = 1u64;
 
 #[derive(Debug)]
@@ -74,9 +116,15 @@ where
     Prefetcher: Prefetch,
 {
     /// The file handle has been assigned as a read handle
-    Read(Prefetcher::PrefetchResult<Client>),
+    Read {
+        request: Prefetcher::PrefetchResult<Client>,
+        /// The part of the object the read policy allows reading, if there is a read policy
+        readable_range: Option<ReadableRange>,
+    },
     /// The file handle has been assigned as a write handle
     Write(UploadState<Client>),
+    /// The file handle reads from a file in the overlay directory instead of from S3
+    Overlay(OverlayReader),
 }
 
 impl<Client, Prefetcher> std::fmt::Debug for FileHandleState<Client, Prefetcher>
@@ -86,8 +134,11 @@ where
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            FileHandleState::Read(_) => f.debug_struct("Read").finish(),
+            FileHandleState::Read { readable_range, .. } => {
+                f.debug_struct("Read").field("readable_range", readable_range).finish()
+            }
             FileHandleState::Write(arg0) => f.debug_tuple("Write").field(arg0).finish(),
+            FileHandleState::Overlay(arg0) => f.debug_tuple("Overlay").field(arg0).finish(),
         }
     }
 }
@@ -105,6 +156,7 @@ where
         fs: &S3Filesystem<Client, Prefetcher>,
     ) -> Result<FileHandleState<Client, Prefetcher>, Error> {
         let is_truncate = flags & libc::O_TRUNC != 0;
+        let is_append = fs.is_append(lookup, flags)?;
         let handle = fs
             .superblock
             .write(
@@ -114,16 +166,34 @@ where
                 pid,
                 fs.config.allow_overwrite,
                 is_truncate,
+                is_append,
             )
             .await
             .start_writing()?;
         let key = lookup.inode.full_key();
-        let handle = match fs.uploader.put(&fs.bucket, key).await {
-            Err(e) => {
-                return Err(err!(libc::EIO, source:e, "put failed to start"));
+        let existing_etag = lookup.stat.etag.clone();
+        let request = if is_append {
+            match fs
+                .uploader
+                .append(lookup.inode.bucket(), key, lookup.stat.size as u64, existing_etag)
+                .await
+            {
+                Ok(request) => Ok(request),
+                Err(e) => {
+                    // The object is unchanged, so the file can be opened for writing again
+                    if let Err(err) = handle.finish_writing() {
+                        error!(?err, key, "error updating the inode status");
+                    }
+                    return Err(err!(e.to_errno(), source:e, "append failed to start"));
+                }
             }
-            Ok(request) => FileHandleState::Write(UploadState::InProgress { request, handle }),
-        };
+        } else {
+            fs.uploader
+                .put(lookup.inode.bucket(), key, existing_etag)
+                .await
+                .map_err(|e| err!(libc::EIO, source:e, "put failed to start"))
+        }?;
+        let handle = FileHandleState::Write(UploadState::InProgress { request, handle });
         metrics::gauge!("fs.current_handles", "type" => "write").increment(1.0);
         Ok(handle)
     }
@@ -138,20 +208,51 @@ where
                 "objects in flexible retrieval storage classes are not accessible",
             ));
         }
-        lookup.inode.start_reading()?;
         let full_key = lookup.inode.full_key().to_owned();
-        let object_size = lookup.stat.size as u64;
+        let mut object_size = lookup.stat.size as u64;
+        let readable_range = match &fs.config.read_policy {
+            Some(policy) => match policy.readable_range(&full_key, object_size) {
+                Some(readable_range) => {
+                    // Never prefetch beyond what the policy allows us to read.
+                    object_size = readable_range.end();
+                    Some(readable_range)
+                }
+                None => return Err(err!(libc::EACCES, "object is not readable under the read policy")),
+            },
+            None => None,
+        };
+        lookup.inode.start_reading()?;
         let etag = match &lookup.stat.etag {
             None => return Err(err!(libc::EBADF, "no E-Tag for inode {}", lookup.inode.ino())),
             Some(etag) => ETag::from_str(etag).expect("E-Tag should be set"),
         };
+        let object_id = ObjectId::with_version_id(full_key, etag, lookup.stat.version_id.clone());
         let request = fs
             .prefetcher
-            .prefetch(fs.client.clone(), &fs.bucket, &full_key, object_size, etag.clone());
-        let handle = FileHandleState::Read(request);
+            .prefetch(fs.client.clone(), lookup.inode.bucket(), object_id, object_size);
+        let handle = FileHandleState::Read {
+            request,
+            readable_range,
+        };
         metrics::gauge!("fs.current_handles", "type" => "read").increment(1.0);
         Ok(handle)
     }
+
+    fn new_overlay_handle(
+        lookup: &LookedUp,
+        overlay_file: &OverlayFile,
+    ) -> Result<FileHandleState<Client, Prefetcher>, Error> {
+        lookup.inode.start_reading()?;
+        let reader = match overlay_file.open() {
+            Ok(reader) => reader,
+            Err(e) => {
+                lookup.inode.finish_reading()?;
+                return Err(err!(libc::EIO, source:e, "failed to open overlay file"));
+            }
+        };
+        metrics::gauge!("fs.current_handles", "type" => "read").increment(1.0);
+        Ok(FileHandleState::Overlay(reader))
+    }
 }
 
 #[derive(Debug)]
@@ -160,7 +261,13 @@ enum UploadState<Client: ObjectClient> {
         request: UploadRequest<Client>,
         handle: WriteHandle,
     },
-    Completed,
+    /// The upload was completed, e.g. by `fsync`, but the handle is still open. Remember the
+    /// object's size and ETag and the process that opened the file, in case writing continues.
+    Completed {
+        size: u64,
+        pid: u32,
+        etag: Option<String>,
+    },
     // Remember the failure reason to respond to retries
     Failed(libc::c_int),
 }
@@ -169,7 +276,7 @@ impl<Client: ObjectClient> UploadState<Client> {
     async fn write(&mut self, offset: i64, data: &[u8], key: &str) -> Result<u32, Error> {
         let upload = match self {
             Self::InProgress { request, .. } => request,
-            Self::Completed => return Err(err!(libc::EIO, "upload already completed for key {:?}", key)),
+            Self::Completed { .. } => return Err(err!(libc::EIO, "upload already completed for key {:?}", key)),
             Self::Failed(e) => return Err(err!(*e, "upload already aborted for key {:?}", key)),
         };
 
@@ -178,13 +285,14 @@ impl<Client: ObjectClient> UploadState<Client> {
             Err(e) => {
                 // Abort the request.
                 match std::mem::replace(self, Self::Failed(e.to_errno())) {
-                    UploadState::InProgress { handle, .. } => {
+                    UploadState::InProgress { request, handle } => {
+                        request.abort().await;
                         if let Err(err) = handle.finish_writing() {
                             // Log the issue but still return the write error.
                             error!(?err, ?key, "error updating the inode status");
                         }
                     }
-                    Self::Failed(_) | Self::Completed => unreachable!("checked above"),
+                    Self::Failed(_) | Self::Completed { .. } => unreachable!("checked above"),
                 };
                 Err(e.into())
             }
@@ -194,7 +302,7 @@ impl<Client: ObjectClient> UploadState<Client> {
     async fn complete(&mut self, key: &str, ignore_if_empty: bool, pid: Option<u32>) -> Result<(), Error> {
         let (request_size, open_pid) = match self {
             Self::InProgress { request, handle } => (request.size(), handle.pid()),
-            Self::Completed => return Ok(()),
+            Self::Completed { .. } => return Ok(()),
             Self::Failed(e) => return Err(err!(*e, "upload already aborted for key {:?}", key)),
         };
 
@@ -214,32 +322,53 @@ impl<Client: ObjectClient> UploadState<Client> {
             }
         }
 
-        let (upload, handle) = match std::mem::replace(self, Self::Completed) {
+        let completed = Self::Completed {
+            size: request_size,
+            pid: open_pid,
+            etag: None,
+        };
+        let (upload, handle) = match std::mem::replace(self, completed) {
             Self::InProgress { request, handle } => (request, handle),
-            Self::Failed(_) | Self::Completed => unreachable!("checked above"),
+            Self::Failed(_) | Self::Completed { .. } => unreachable!("checked above"),
         };
 
-        let result = Self::complete_upload(upload, key, handle).await;
-        if let Err(e) = &result {
-            *self = Self::Failed(e.to_errno());
+        match Self::complete_upload(upload, key, handle).await {
+            Ok(etag) => {
+                *self = Self::Completed {
+                    size: request_size,
+                    pid: open_pid,
+                    etag,
+                };
+                Ok(())
+            }
+            Err(e) => {
+                *self = Self::Failed(e.to_errno());
+                Err(e)
+            }
         }
-        result
     }
 
     async fn complete_if_in_progress(self, key: &str) -> Result<(), Error> {
         match self {
-            Self::InProgress { request, handle } => Self::complete_upload(request, key, handle).await,
-            Self::Failed(_) | Self::Completed => Ok(()),
+            Self::InProgress { request, handle } => Self::complete_upload(request, key, handle).await.map(|_| ()),
+            Self::Failed(_) | Self::Completed { .. } => Ok(()),
         }
     }
 
-    async fn complete_upload(upload: UploadRequest<Client>, key: &str, handle: WriteHandle) -> Result<(), Error> {
+    /// Complete the upload, returning the ETag of the new object if S3 reported one.
+    async fn complete_upload(
+        upload: UploadRequest<Client>,
+        key: &str,
+        handle: WriteHandle,
+    ) -> Result<Option<String>, Error> {
         let size = upload.size();
         let put_result = match upload.complete().await {
-            Ok(_) => {
+            Ok(result) => {
                 debug!(key, size, "put succeeded");
-                Ok(())
+                Ok(result.etag.map(ETag::into_inner))
             }
+            Err(e @ UploadCompleteError::Conflict { .. }) => Err(err!(libc::EBUSY, source:e, "put failed")),
+            Err(e @ UploadCompleteError::MissingWrite { .. }) => Err(err!(libc::EINVAL, source:e, "put failed")),
             Err(e) => Err(err!(libc::EIO, source:e, "put failed")),
         };
         if let Err(err) = handle.finish_writing() {
@@ -303,6 +432,15 @@ pub struct CacheConfig {
     pub dir_ttl: Duration,
     /// Maximum number of negative entries to cache.
     pub negative_cache_size: usize,
+    /// How long to remember that a name doesn't exist, so that repeated lookups of it fail
+    /// without sending requests to S3. Zero disables the negative cache.
+    pub negative_cache_ttl: Duration,
+    /// How long to keep an inode's cached metadata after the kernel forgets it, so that it can
+    /// be reused if the kernel looks it up again soon
+    pub forgotten_inode_grace_period: Duration,
+    /// How long to reuse a `getattr` reply for repeated `getattr` calls on the same inode, even
+    /// when the TTLs above are zero. Zero disables this cache.
+    pub attr_cache_ttl: Duration,
 }
 
 impl Default for CacheConfig {
@@ -331,16 +469,82 @@ impl Default for CacheConfig {
             file_ttl,
             dir_ttl,
             negative_cache_size,
+            negative_cache_ttl: Duration::ZERO,
+            forgotten_inode_grace_period: Duration::ZERO,
+            attr_cache_ttl: Duration::ZERO,
+        }
+    }
+}
+
+impl CacheConfig {
+    /// Construct a cache configuration for the given metadata time-to-live.
+    pub fn new(metadata_ttl: TimeToLive) -> Self {
+        match metadata_ttl {
+            TimeToLive::Minimal => Default::default(),
+            TimeToLive::Indefinite => Self {
+                serve_lookup_from_cache: true,
+                file_ttl: NEVER_EXPIRE_TTL,
+                dir_ttl: NEVER_EXPIRE_TTL,
+                negative_cache_ttl: NEVER_EXPIRE_TTL,
+                ..Default::default()
+            },
+            TimeToLive::Duration(ttl) => Self {
+                serve_lookup_from_cache: true,
+                file_ttl: ttl,
+                dir_ttl: ttl,
+                negative_cache_ttl: ttl,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Override how long metadata for directories is cached, independently of the metadata TTL
+    /// for files.
+    pub fn with_dir_metadata_ttl(self, dir_metadata_ttl: TimeToLive) -> Self {
+        let dir_ttl = match dir_metadata_ttl {
+            TimeToLive::Minimal => Self::default().dir_ttl,
+            TimeToLive::Indefinite => NEVER_EXPIRE_TTL,
+            TimeToLive::Duration(ttl) => ttl,
+        };
+        Self { dir_ttl, ..self }
+    }
+
+    /// Override how long lookups of names that don't exist are cached, independently of the
+    /// metadata TTL.
+    pub fn with_negative_metadata_ttl(self, negative_metadata_ttl: TimeToLive) -> Self {
+        let negative_cache_ttl = match negative_metadata_ttl {
+            TimeToLive::Minimal => Duration::ZERO,
+            TimeToLive::Indefinite => NEVER_EXPIRE_TTL,
+            TimeToLive::Duration(ttl) => ttl,
+        };
+        Self {
+            negative_cache_ttl,
+            ..self
         }
     }
 }
 
+/// How long cached metadata remains valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeToLive {
+    /// Check S3 on every lookup, keeping only the short TTLs the kernel needs to behave well
+    Minimal,
+    /// Never expire cached metadata
+    Indefinite,
+    /// Expire cached metadata after the given duration
+    Duration(Duration),
+}
+
 #[derive(Debug)]
 pub struct S3FilesystemConfig {
     /// Kernel cache config
     pub cache_config: CacheConfig,
     /// Readdir page size
     pub readdir_size: usize,
+    /// Ask the kernel to use `readdirplus`, which returns attributes along with each directory
+    /// entry. When disabled, the kernel falls back to plain `readdir`, which is cheaper for
+    /// workloads that only need names.
+    pub readdirplus: bool,
     /// User id
     pub uid: u32,
     /// Group id
@@ -349,10 +553,16 @@ pub struct S3FilesystemConfig {
     pub dir_mode: u16,
     /// File permissions
     pub file_mode: u16,
+    /// Whether the file system allows reads, writes, or both
+    pub access_mode: AccessMode,
     /// Allow delete
     pub allow_delete: bool,
+    /// Whether deleting a file deletes its object from S3, or only hides it
+    pub delete_policy: DeletePolicy,
     /// Allow overwrite
     pub allow_overwrite: bool,
+    /// Allow creating symbolic links, and recognize existing ones
+    pub allow_symlinks: bool,
     /// Storage class to be used for new object uploads
     pub storage_class: Option<String>,
     /// S3 personality (for different S3 semantics)
@@ -361,8 +571,67 @@ pub struct S3FilesystemConfig {
     pub server_side_encryption: ServerSideEncryption,
     /// Use additional checksums for uploads
     pub use_upload_checksums: bool,
+    /// Local directory whose files shadow objects with the same path
+    pub overlay_directory: Option<OverlayDirectory>,
+    /// Restrictions on which objects and byte ranges can be read
+    pub read_policy: Option<ReadPolicy>,
+    /// Ownership and permissions for parts of the bucket, checked against the user making each
+    /// request, or [None] to give everything the same owner and not check requests
+    pub identity_map: Option<IdentityMap>,
+    /// Several prefixes to mount as directories at the root, in place of the single mounted prefix
+    pub namespace: Option<CompositeNamespace>,
+    /// Show every key as a file named after the whole key, with `/` escaped, instead of splitting
+    /// keys into directories
+    pub flat_keys: bool,
+    /// Show files shadowed by directories of the same name under their name with this suffix,
+    /// rather than hiding them
+    pub shadowed_file_suffix: Option<String>,
+    /// Derive inode numbers from keys, so they are the same every time the bucket is mounted
+    pub stable_inode_numbers: bool,
+    /// What to do when an object is replaced in S3 while it is being written, or [None] to skip
+    /// checking for conflicts
+    pub write_conflict_policy: Option<WriteConflictPolicy>,
+    /// How far ahead of the end of the data written so far, in bytes, a write to a file can end
+    /// and still be accepted. Writes ahead of the end are held in memory until the gap before them
+    /// is filled. Zero requires strictly sequential writes.
+    pub write_reorder_window: u64,
+    /// How many parts of appends to have in flight at once, per file and in total
+    pub append_upload_concurrency: AppendUploadConcurrency,
+    /// Which version of each object to show
+    pub object_version_policy: ObjectVersionPolicy,
+    /// Total size of the file system reported by `statfs`, in bytes
+    pub fs_size: u64,
+    /// Free space reported by `statfs`, in bytes. Capped at [Self::fs_size].
+    pub fs_free: u64,
+    /// How much the kernel caches attributes, directory entries, and file contents
+    pub kernel_cache: KernelCacheMode,
+    /// How far ahead of sequential reads the kernel reads, in bytes, or [None] for the kernel's
+    /// default. The kernel won't go above its default when the session is initialized, so larger
+    /// values have to be set through sysfs once the file system is mounted.
+    pub max_readahead: Option<u32>,
+    /// How many background requests, like readahead, the kernel sends to us at once
+    pub max_background: u16,
+    /// How many background requests the kernel can have outstanding before it considers the file
+    /// system congested and slows down, or [None] for three quarters of [Self::max_background]
+    pub congestion_threshold: Option<u16>,
+    /// Attribute requests and the bytes they read and write to the process and user making them
+    pub io_accounting: bool,
+    /// Whether the `.mountpoint` directory of files describing the mount is available, and whether
+    /// it's listed in the root directory
+    pub control_directory: ControlDirectoryMode,
+    /// How the file system was mounted, shown in `.mountpoint/config`
+    pub mount_config: String,
 }
 
+/// The default limit on background requests the kernel sends at once. FUSE's own default of 16
+/// (12 before the kernel considers us congested) is far fewer than the prefetcher can serve.
+pub const DEFAULT_MAX_BACKGROUND: u16 = 64;
+
+/// The size `statfs` reports by default. S3 buckets don't have a capacity, so this just needs to be
+/// big enough that applications never think they'll run out of space, without overflowing
+/// applications that multiply it out in bytes.
+pub const DEFAULT_FS_SIZE: u64 = 1 << 60;
+
 impl Default for S3FilesystemConfig {
     fn default() -> Self {
         let uid = getuid().into();
@@ -371,20 +640,167 @@ impl Default for S3FilesystemConfig {
         Self {
             cache_config: Default::default(),
             readdir_size: 100,
+            readdirplus: true,
             uid,
             gid,
             dir_mode: 0o755,
             file_mode: 0o644,
+            access_mode: Default::default(),
             allow_delete: false,
+            delete_policy: Default::default(),
             allow_overwrite: false,
+            allow_symlinks: false,
             storage_class: None,
             s3_personality: S3Personality::default(),
             server_side_encryption: Default::default(),
             use_upload_checksums: true,
+            overlay_directory: None,
+            read_policy: None,
+            identity_map: None,
+            namespace: None,
+            flat_keys: false,
+            shadowed_file_suffix: None,
+            stable_inode_numbers: false,
+            write_conflict_policy: None,
+            write_reorder_window: 0,
+            append_upload_concurrency: Default::default(),
+            object_version_policy: Default::default(),
+            fs_size: DEFAULT_FS_SIZE,
+            fs_free: DEFAULT_FS_SIZE,
+            kernel_cache: Default::default(),
+            max_readahead: None,
+            max_background: DEFAULT_MAX_BACKGROUND,
+            congestion_threshold: None,
+            io_accounting: false,
+            control_directory: Default::default(),
+            mount_config: String::new(),
+        }
+    }
+}
+
+/// How to complete an upload when the object was created or replaced in S3 by another writer
+/// since the file was opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteConflictPolicy {
+    /// Replace the remote object anyway (last writer wins)
+    Overwrite,
+    /// Abort the upload and fail the close with `EBUSY`, leaving the remote object as it is
+    Fail,
+}
+
+impl WriteConflictPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WriteConflictPolicy::Overwrite => "overwrite",
+            WriteConflictPolicy::Fail => "fail",
+        }
+    }
+}
+
+/// Which kinds of operations the file system allows. The file system enforces this itself, rather
+/// than relying on kernel mount options, and rejects disallowed operations before sending any
+/// requests to S3.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AccessMode {
+    /// Allow both reads and writes
+    #[default]
+    ReadWrite,
+    /// Reject anything that would modify the bucket with `EROFS`
+    ReadOnly,
+    /// Reject opening files for reading, and reading from them, with `EACCES`, so that object
+    /// contents are never downloaded. Metadata is still visible.
+    WriteOnly,
+}
+
+impl AccessMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccessMode::ReadWrite => "read-write",
+            AccessMode::ReadOnly => "read-only",
+            AccessMode::WriteOnly => "write-only",
+        }
+    }
+}
+
+/// What deleting a file in the file system does to its object in S3
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeletePolicy {
+    /// Delete the object from S3
+    #[default]
+    All,
+    /// Leave the object in S3, and only hide it from the file system until it's replaced or the
+    /// file system is remounted
+    LocalOnly,
+}
+
+impl DeletePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeletePolicy::All => "all",
+            DeletePolicy::LocalOnly => "local-only",
+        }
+    }
+}
+
+/// Which version of each object in a versioned bucket the file system shows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ObjectVersionPolicy {
+    /// The current version of each object
+    #[default]
+    Latest,
+    /// The version of each object that was current at the given time. Reads are pinned to that
+    /// version, so objects overwritten or deleted later are still read as they were.
+    LatestAt(OffsetDateTime),
+}
+
+impl ObjectVersionPolicy {
+    /// The point in time the file system shows, or `None` if it shows the current versions
+    pub fn as_of(&self) -> Option<OffsetDateTime> {
+        match self {
+            ObjectVersionPolicy::Latest => None,
+            ObjectVersionPolicy::LatestAt(time) => Some(*time),
+        }
+    }
+}
+
+impl std::fmt::Display for ObjectVersionPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjectVersionPolicy::Latest => write!(f, "latest"),
+            ObjectVersionPolicy::LatestAt(time) => {
+                let time = time.format(&Rfc3339).map_err(|_| std::fmt::Error)?;
+                write!(f, "latest-at:{time}")
+            }
+        }
+    }
+}
+
+impl FromStr for ObjectVersionPolicy {
+    type Err = ObjectVersionPolicyParseError;
+
+    /// Parse either `latest` or `latest-at:<timestamp>`, where the timestamp is in RFC 3339 format
+    /// (like `2024-01-31T12:00:00Z`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "latest" {
+            return Ok(ObjectVersionPolicy::Latest);
         }
+        let timestamp = s
+            .strip_prefix("latest-at:")
+            .ok_or_else(|| ObjectVersionPolicyParseError::UnknownPolicy(s.to_owned()))?;
+        let time = OffsetDateTime::parse(timestamp, &Rfc3339)
+            .map_err(|e| ObjectVersionPolicyParseError::InvalidTimestamp(timestamp.to_owned(), e))?;
+        Ok(ObjectVersionPolicy::LatestAt(time))
     }
 }
 
+#[derive(Debug, Error)]
+pub enum ObjectVersionPolicyParseError {
+    #[error("unknown policy {0:?}, expected `latest` or `latest-at:<timestamp>`")]
+    UnknownPolicy(String),
+    #[error("invalid timestamp {0:?}, expected RFC 3339 format like 2024-01-31T12:00:00Z")]
+    InvalidTimestamp(String, #[source] time::error::Parse),
+}
+
 /// Server-side encryption configuration for newly created objects
 #[derive(Debug, Clone)]
 pub struct ServerSideEncryption {
@@ -486,6 +902,16 @@ pub enum SseCorruptedError {
     KeyMismatch(String, Option<String>),
 }
 
+/// The kernel doesn't support a FUSE feature that the file system configuration requires.
+#[derive(Debug, Clone, Error)]
+pub enum KernelFeatureError {
+    #[error(
+        "the kernel does not support the FUSE_ATOMIC_O_TRUNC capability, which is required to overwrite files. \
+         Upgrade to a newer kernel, or mount without --allow-overwrite"
+    )]
+    AtomicOTruncUnsupported,
+}
+
 #[derive(Debug)]
 pub struct S3Filesystem<Client, Prefetcher>
 where
@@ -498,11 +924,44 @@ where
     prefetcher: Prefetcher,
     uploader: Uploader<Client>,
     bucket: String,
-    #[allow(unused)]
     prefix: Prefix,
     next_handle: AtomicU64,
     dir_handles: AsyncRwLock<HashMap<u64, Arc<DirHandle>>>,
     file_handles: AsyncRwLock<HashMap<u64, Arc<FileHandle<Client, Prefetcher>>>>,
+    session_state: RwLock<SessionState>,
+    attr_cache: AttrCache,
+    kernel_cache: KernelCache,
+    upload_failures: UploadFailures,
+    accounting: IoAccounting,
+    control: ControlDirectory,
+    overlay: Option<OverlayLookups>,
+}
+
+/// The FUSE protocol version and capabilities negotiated with the kernel when the session was
+/// initialized. Features that depend on kernel support should check these rather than assume them,
+/// so that they degrade gracefully on older kernels.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SessionState {
+    /// Minor version of the FUSE protocol supported by the kernel
+    pub protocol_minor: u32,
+    /// Whether the kernel will send `readdirplus` requests
+    pub readdirplus: bool,
+    /// Whether the kernel passes `O_TRUNC` to `open` rather than truncating with `setattr`
+    pub atomic_o_trunc: bool,
+    /// Whether the kernel can send `lseek` requests (protocol 7.24 and later)
+    pub lseek: bool,
+}
+
+impl SessionState {
+    fn new(config: &KernelConfig) -> Self {
+        let enabled = config.enabled_capabilities();
+        Self {
+            protocol_minor: config.protocol_minor(),
+            readdirplus: enabled & fuser::consts::FUSE_DO_READDIRPLUS != 0,
+            atomic_o_trunc: enabled & fuser::consts::FUSE_ATOMIC_O_TRUNC != 0,
+            lseek: config.protocol_minor() >= 24,
+        }
+    }
 }
 
 impl<Client, Prefetcher> S3Filesystem<Client, Prefetcher>
@@ -510,28 +969,60 @@ where
     Client: ObjectClient + Send + Sync + 'static,
     Prefetcher: Prefetch,
 {
-    pub fn new(
+    /// Create a new file system that serves `prefix` of `bucket`. Uploads run their background
+    /// work on `runtime`.
+    pub fn new<Runtime>(
         client: Client,
         prefetcher: Prefetcher,
+        runtime: Runtime,
         bucket: &str,
         prefix: &Prefix,
         config: S3FilesystemConfig,
-    ) -> Self {
+    ) -> Self
+    where
+        Runtime: Spawn + Send + Sync + 'static,
+    {
         trace!(?bucket, ?prefix, ?config, "new filesystem");
 
         let superblock_config = SuperblockConfig {
             cache_config: config.cache_config.clone(),
             s3_personality: config.s3_personality,
+            allow_symlinks: config.allow_symlinks,
+            object_version_policy: config.object_version_policy,
+            delete_policy: config.delete_policy,
+            namespace: config.namespace.clone(),
+            flat_keys: config.flat_keys,
+            shadowed_file_suffix: config.shadowed_file_suffix.clone(),
+            stable_inode_numbers: config.stable_inode_numbers,
+            reserved_inodes: match config.control_directory {
+                ControlDirectoryMode::Off => 0,
+                _ => ControlDirectory::INODE_COUNT,
+            },
         };
         let superblock = Superblock::new(bucket, prefix, superblock_config);
+        let control = ControlDirectory::new(
+            config.control_directory,
+            superblock.reserved_inodes().start,
+            getuid().into(),
+            getgid().into(),
+        );
 
         let client = Arc::new(client);
 
+        let attr_cache = AttrCache::new(config.cache_config.attr_cache_ttl);
+        let kernel_cache = KernelCache::new(config.kernel_cache);
+        let accounting = IoAccounting::new(config.io_accounting);
+        let overlay = config.overlay_directory.clone().map(OverlayLookups::new);
+
         let uploader = Uploader::new(
             client.clone(),
+            runtime,
             config.storage_class.to_owned(),
             config.server_side_encryption.clone(),
             config.use_upload_checksums,
+            config.write_conflict_policy,
+            config.write_reorder_window,
+            config.append_upload_concurrency,
         );
 
         Self {
@@ -540,17 +1031,126 @@ where
             superblock,
             prefetcher,
             uploader,
-            bucket: bucket.to_string(),
+            bucket: bucket.to_owned(),
             prefix: prefix.clone(),
             next_handle: AtomicU64::new(1),
             dir_handles: AsyncRwLock::new(HashMap::new()),
             file_handles: AsyncRwLock::new(HashMap::new()),
+            session_state: Default::default(),
+            attr_cache,
+            kernel_cache,
+            upload_failures: UploadFailures::new(),
+            accounting,
+            control,
+            overlay,
         }
     }
 
     fn next_handle(&self) -> u64 {
         self.next_handle.fetch_add(1, Ordering::SeqCst)
     }
+
+    /// Create a [CacheInvalidator] for invalidating the cached metadata of keys that are changed in
+    /// S3 by someone other than this file system.
+    pub fn cache_invalidator(&self) -> CacheInvalidator {
+        self.superblock.cache_invalidator()
+    }
+
+    /// Create an [IncompleteUploadCleaner] for the multipart uploads under the mounted prefixes,
+    /// which aborts the ones still incomplete after `max_age` unless they're still being written.
+    pub fn incomplete_upload_cleaner(&self, max_age: Duration) -> IncompleteUploadCleaner<Client> {
+        let prefixes = match &self.config.namespace {
+            Some(namespace) => namespace
+                .entries()
+                .map(|entry| (entry.bucket.clone(), entry.prefix.to_string()))
+                .collect(),
+            None => vec![(self.bucket.clone(), self.prefix.to_string())],
+        };
+        self.uploader.incomplete_upload_cleaner(prefixes, max_age)
+    }
+
+    /// The I/O made by each process and user, if [S3FilesystemConfig::io_accounting] is enabled
+    pub fn accounting(&self) -> &IoAccounting {
+        &self.accounting
+    }
+
+    /// Whether a file opened with the given flags should append to the existing object.
+    ///
+    /// Open with O_APPEND is ok for new files because it's the same as creating a new one. Existing
+    /// files can only be appended to if the S3 implementation can seed a new upload with a copy of
+    /// the object, and otherwise we should explicitly say we don't allow it.
+    fn is_append(&self, lookup: &LookedUp, flags: i32) -> Result<bool, Error> {
+        if flags & libc::O_APPEND == 0 || flags & libc::O_TRUNC != 0 || !lookup.inode.is_remote()? {
+            return Ok(false);
+        }
+        if !self.config.s3_personality.supports_append() {
+            return Err(err!(libc::EINVAL, "O_APPEND is not supported on existing files"));
+        }
+        // Appending to a small object downloads it
+        self.check_readable()?;
+        Ok(true)
+    }
+
+    /// Fail if the file system is read-only. Call this before anything that could modify the bucket.
+    fn check_writable(&self) -> Result<(), Error> {
+        if self.config.access_mode == AccessMode::ReadOnly {
+            return Err(err!(libc::EROFS, "file system is mounted read-only"));
+        }
+        Ok(())
+    }
+
+    /// Fail if the file system is write-only. Call this before anything that could read object
+    /// contents.
+    fn check_readable(&self) -> Result<(), Error> {
+        if self.config.access_mode == AccessMode::WriteOnly {
+            return Err(err!(libc::EACCES, "file system is mounted write-only"));
+        }
+        Ok(())
+    }
+
+    /// The owner and permissions of an inode, before accounting for whether it's readable
+    fn ownership(&self, lookup: &LookedUp) -> Ownership {
+        let is_dir = lookup.inode.kind() == InodeKind::Directory;
+        let default = Ownership {
+            uid: self.config.uid,
+            gid: self.config.gid,
+            mode: if is_dir {
+                self.config.dir_mode
+            } else {
+                self.config.file_mode
+            },
+        };
+        match &self.config.identity_map {
+            Some(identity_map) => identity_map.ownership(lookup.inode.full_key(), is_dir, default),
+            None => default,
+        }
+    }
+
+    /// Find the file in the overlay directory, if any, that shadows the given inode.
+    fn overlay_file(&self, inode: &Inode) -> Option<OverlayFile> {
+        let overlay = self.overlay.as_ref()?;
+        if inode.kind() != InodeKind::File {
+            return None;
+        }
+        let relative_key = inode.full_key().strip_prefix(self.prefix.as_str())?;
+        overlay.lookup(relative_key)
+    }
+}
+
+/// Where `lseek` with `SEEK_DATA` or `SEEK_HOLE` from `offset` lands in a file of `size` bytes.
+/// Files never have holes, so all of the file is data and the only hole is at the end.
+fn seek_data_or_hole(size: u64, offset: i64, whence: i32) -> Result<i64, Error> {
+    let Ok(offset) = u64::try_from(offset) else {
+        return Err(err!(libc::EINVAL, "negative offset {}", offset));
+    };
+    if offset >= size {
+        return Err(err!(libc::ENXIO, "offset {} is beyond the end of the file", offset));
+    }
+    match whence {
+        libc::SEEK_DATA => Ok(offset as i64),
+        libc::SEEK_HOLE => Ok(size as i64),
+        _ => Err(err!(libc::EINVAL, "unsupported whence {}", whence)),
+    }
 }
 
 /// Reply to a `lookup` call
@@ -575,11 +1175,40 @@ pub struct Opened {
     pub flags: u32,
 }
 
-/// Reply to a `readdir` or `readdirplus` call
-pub trait DirectoryReplier {
+/// Reply to a `statfs` call
+#[derive(Debug)]
+pub struct StatFs {
+    /// Total number of blocks, in units of `fragment_size`
+    pub total_blocks: u64,
+    /// Number of free blocks
+    pub free_blocks: u64,
+    /// Number of free blocks available to unprivileged users
+    pub available_blocks: u64,
+    /// Total number of inodes
+    pub total_inodes: u64,
+    /// Number of free inodes
+    pub free_inodes: u64,
+    /// Preferred I/O block size
+    pub block_size: u32,
+    /// Maximum length of a file name
+    pub maximum_name_length: u32,
+    /// Size of the blocks counted in `total_blocks` and `free_blocks`
+    pub fragment_size: u32,
+}
+
+/// A destination for the entries returned by [S3Filesystem::readdir] and
+/// [S3Filesystem::readdirplus].
+#[async_trait]
+pub trait DirectoryReplier: Send {
     /// Add a new dentry to the reply. Returns true if the buffer was full and so the entry was not
     /// added.
-    fn add(&mut self, entry: DirectoryEntry) -> bool;
+    ///
+    /// Implementations can apply backpressure by not completing until they are ready for the next
+    /// entry, for example while entries added so far are being consumed. Readdir only looks up the
+    /// next entry once this completes, so a slow consumer doesn't cause looked-up entries to pile
+    /// up. Returning true stops the readdir, which the kernel resumes later from the offset of the
+    /// last added entry.
+    async fn add(&mut self, entry: DirectoryEntry) -> bool;
 }
 
 #[derive(Debug, Clone)]
@@ -590,7 +1219,8 @@ pub struct DirectoryEntry {
     pub attr: FileAttr,
     pub generation: u64,
     pub ttl: Duration,
-    lookup: LookedUp,
+    /// The inode the entry refers to, unless it's in the control directory
+    lookup: Option<LookedUp>,
 }
 
 impl<Client, Prefetcher> S3Filesystem<Client, Prefetcher>
@@ -598,21 +1228,54 @@ where
     Client: ObjectClient + Send + Sync + 'static,
     Prefetcher: Prefetch,
 {
-    pub async fn init(&self, config: &mut KernelConfig) -> Result<(), libc::c_int> {
-        let _ = config.add_capabilities(fuser::consts::FUSE_DO_READDIRPLUS);
-        if self.config.allow_overwrite {
-            // Overwrites require FUSE_ATOMIC_O_TRUNC capability on the host, so we will panic if the
-            // host doesn't support it.
-            //
-            // This should makes it clear to users that they cannot enable overwrite on their host
-            // rather than silently disable it and let users find out later when their writes fail.
-            config
-                .add_capabilities(fuser::consts::FUSE_ATOMIC_O_TRUNC)
-                .expect("The host must support FUSE_ATOMIC_O_TRUNC capability in order to allow overwrites");
+    /// Negotiate capabilities with the kernel. Optional features the kernel doesn't support are
+    /// disabled with a warning, but missing features that the configuration requires fail the
+    /// session, so that users find out at mount time rather than at first use.
+    pub async fn init(&self, config: &mut KernelConfig) -> Result<(), KernelFeatureError> {
+        if self.config.readdirplus && config.add_capabilities(fuser::consts::FUSE_DO_READDIRPLUS).is_err() {
+            warn!("kernel does not support readdirplus, so listing directories will need an extra lookup per entry");
+        }
+        if config.kernel_capabilities() & fuser::consts::FUSE_MAX_PAGES == 0 {
+            warn!("kernel does not support FUSE_MAX_PAGES, so reads and writes will be split into requests of at most 128KiB");
+        }
+        if let Some(max_readahead) = self.config.max_readahead {
+            // Above the kernel's maximum this fails and leaves readahead at the maximum, and the
+            // rest is raised once the file system is mounted
+            let _ = config.set_max_readahead(max_readahead);
+        }
+        // Both only fail for zero, which the configuration doesn't allow
+        let _ = config.set_max_background(self.config.max_background);
+        if let Some(congestion_threshold) = self.config.congestion_threshold {
+            let _ = config.set_congestion_threshold(congestion_threshold);
+        }
+        let kernel_cache_capabilities = self.kernel_cache.mode().capabilities();
+        if config.add_capabilities(kernel_cache_capabilities).is_err() {
+            warn!(
+                "kernel does not support the capabilities for --kernel-cache {}, so it will cache less than requested",
+                self.kernel_cache.mode().as_str()
+            );
+        }
+        // Overwrites rely on the kernel passing O_TRUNC to open, rather than truncating the file
+        // with a separate setattr call.
+        let missing_atomic_o_trunc =
+            self.config.allow_overwrite && config.add_capabilities(fuser::consts::FUSE_ATOMIC_O_TRUNC).is_err();
+
+        let session_state = SessionState::new(config);
+        info!(?session_state, "negotiated FUSE session with the kernel");
+        *self.session_state.write().unwrap() = session_state;
+
+        if missing_atomic_o_trunc {
+            return Err(KernelFeatureError::AtomicOTruncUnsupported);
         }
         Ok(())
     }
 
+    /// The protocol version and capabilities negotiated with the kernel, or the default (nothing
+    /// supported) if the session has not been initialized yet.
+    pub fn session_state(&self) -> SessionState {
+        *self.session_state.read().unwrap()
+    }
+
     fn make_attr(&self, lookup: &LookedUp) -> FileAttr {
         /// From man stat(2): `st_blocks`: "This field indicates the number of blocks allocated to
         /// the file, in 512-byte units."
@@ -623,37 +1286,79 @@ where
 
         // We don't implement hard links, and don't want to have to list a directory to count its
         // hard links, so we just assume one link for files (itself) and two links for directories
-        // (itself + the "." link).
+        // (itself + the "." link). Symlink permissions are ignored, so use the conventional 0o777.
+        let ownership = self.ownership(lookup);
         let (perm, nlink) = match lookup.inode.kind() {
             InodeKind::File => {
                 if lookup.stat.is_readable {
-                    (self.config.file_mode, 1)
+                    (ownership.mode, 1)
                 } else {
                     (0o000, 1)
                 }
             }
-            InodeKind::Directory => (self.config.dir_mode, 2),
+            InodeKind::Directory => (ownership.mode, 2),
+            InodeKind::Symlink => (0o777, 1),
         };
 
+        let mut size = lookup.stat.size as u64;
+        let mut mtime: SystemTime = lookup.stat.mtime.into();
+        if let Some(overlay_file) = self.overlay_file(&lookup.inode) {
+            size = overlay_file.size();
+            mtime = overlay_file.mtime().unwrap_or(mtime);
+        } else if let (InodeKind::File, Some(policy)) = (lookup.inode.kind(), &self.config.read_policy) {
+            // Objects appear to end where the read policy stops allowing reads
+            if lookup.inode.is_remote().unwrap_or(false) {
+                if let Some(readable_range) = policy.readable_range(lookup.inode.full_key(), size) {
+                    size = readable_range.end();
+                }
+            }
+        }
+
         FileAttr {
             ino: lookup.inode.ino(),
-            size: lookup.stat.size as u64,
-            blocks: (lookup.stat.size as u64 + STAT_BLOCK_SIZE - 1) / STAT_BLOCK_SIZE,
+            size,
+            blocks: (size + STAT_BLOCK_SIZE - 1) / STAT_BLOCK_SIZE,
             atime: lookup.stat.atime.into(),
-            mtime: lookup.stat.mtime.into(),
+            mtime,
             ctime: lookup.stat.ctime.into(),
             crtime: UNIX_EPOCH,
             kind: lookup.inode.kind().into(),
             perm,
             nlink,
-            uid: self.config.uid,
-            gid: self.config.gid,
+            uid: ownership.uid,
+            gid: ownership.gid,
             rdev: 0,
             flags: 0,
             blksize: PREFERRED_IO_BLOCK_SIZE,
         }
     }
 
+    /// Check that the caller has all of the access in `mask` (a combination of `R_OK`, `W_OK`, and
+    /// `X_OK`) to an inode, according to the identity map. Without an identity map, every caller
+    /// has full access.
+    pub async fn check_access(&self, ino: InodeNo, caller: Caller, mask: i32) -> Result<(), Error> {
+        if self.config.identity_map.is_none() {
+            return Ok(());
+        }
+        // Ownership only depends on the key, so there's no need to revalidate the inode
+        let lookup = self.superblock.getattr(&self.client, ino, false).await?;
+        let mut ownership = self.ownership(&lookup);
+        if lookup.inode.kind() == InodeKind::File && !lookup.stat.is_readable {
+            ownership.mode = 0o000;
+        }
+        if !ownership.allows(caller, mask) {
+            return Err(err!(
+                libc::EACCES,
+                "uid {} gid {} does not have access {:#o} to {:?}",
+                caller.uid,
+                caller.gid,
+                mask,
+                lookup.inode.full_key()
+            ));
+        }
+        Ok(())
+    }
+
     pub async fn lookup(&self, parent: InodeNo, name: &OsStr) -> Result<Entry, Error> {
         trace!("fs:lookup with parent {:?} name {:?}", parent, name);
 
@@ -670,7 +1375,7 @@ where
             })?;
         let attr = self.make_attr(&lookup);
         Ok(Entry {
-            ttl: lookup.validity(),
+            ttl: self.kernel_cache.ttl(lookup.validity()),
             attr,
             generation: 0,
         })
@@ -679,31 +1384,46 @@ where
     pub async fn getattr(&self, ino: InodeNo) -> Result<Attr, Error> {
         trace!("fs:getattr with ino {:?}", ino);
 
+        if let Some((attr, ttl)) = self.attr_cache.get(ino) {
+            return Ok(Attr { ttl, attr });
+        }
+
         let lookup = self.superblock.getattr(&self.client, ino, false).await?;
-        let attr = self.make_attr(&lookup);
+        let mut attr = self.make_attr(&lookup);
+        // A renamed inode reports the attributes of the file at its new name, but keeps its number
+        attr.ino = ino;
+        let ttl = self.kernel_cache.ttl(lookup.validity());
+
+        // Only cache inodes that can't change locally, so that writes are always visible
+        if matches!(lookup.inode.is_remote(), Ok(true)) {
+            self.attr_cache.insert(ino, attr, ttl);
+        }
 
-        Ok(Attr {
-            ttl: lookup.validity(),
-            attr,
-        })
+        Ok(Attr { ttl, attr })
     }
 
+    #[allow(clippy::too_many_arguments)] // We don't get to choose this interface
     pub async fn setattr(
         &self,
         ino: InodeNo,
         atime: Option<OffsetDateTime>,
         mtime: Option<OffsetDateTime>,
         size: Option<u64>,
+        fh: Option<u64>,
         _flags: Option<u32>,
+        pid: u32,
     ) -> Result<Attr, Error> {
         tracing::info!(
-            "fs:setattr with ino {:?} flags {:?} atime {:?} mtime {:?} size {:?}",
+            "fs:setattr with ino {:?} fh {:?} flags {:?} atime {:?} mtime {:?} size {:?}",
             ino,
+            fh,
             _flags,
             atime,
             mtime,
             size
         );
+        self.check_writable()?;
+        self.attr_cache.invalidate(ino);
         let setattr_result = self.superblock.setattr(&self.client, ino, atime, mtime).await;
         let lookup = match (setattr_result, size) {
             (Ok(lookup), _) => lookup,
@@ -714,23 +1434,105 @@ where
                 return Err(
                     err!(libc::EPERM, "file overwrite is disabled by default, you need to remount with --allow-overwrite flag and open the file in truncate mode (O_TRUNC) to overwrite it"));
             }
+            (Err(InodeError::SetAttrNotPermittedOnRemoteInode(_)), Some(0)) => {
+                self.truncate_remote_file(ino, fh, atime, mtime, pid).await?
+            }
             (Err(e), _) => return Err(e.into()),
         };
         let attr = self.make_attr(&lookup);
 
         Ok(Attr {
-            ttl: lookup.validity(),
+            ttl: self.kernel_cache.ttl(lookup.validity()),
             attr,
         })
     }
 
+    /// Truncate an existing object to zero bytes, for `truncate` and `ftruncate` when overwrites
+    /// are allowed.
+    ///
+    /// If the file is open through `fh` for reading, that handle becomes a write handle for a fresh
+    /// upload, so that following writes through it replace the object like they would after
+    /// opening with `O_TRUNC`. Otherwise there's no handle to write through, so the object is
+    /// replaced with an empty one straight away.
+    async fn truncate_remote_file(
+        &self,
+        ino: InodeNo,
+        fh: Option<u64>,
+        atime: Option<OffsetDateTime>,
+        mtime: Option<OffsetDateTime>,
+        pid: u32,
+    ) -> Result<LookedUp, Error> {
+        let lookup = self.superblock.getattr(&self.client, ino, true).await?;
+        if self.overlay_file(&lookup.inode).is_some() {
+            return Err(err!(
+                libc::EPERM,
+                "file is shadowed by the overlay directory and cannot be truncated"
+            ));
+        }
+        let flags = libc::O_WRONLY | libc::O_TRUNC;
+
+        let handle = match fh {
+            Some(fh) => self.file_handles.read().await.get(&fh).cloned(),
+            None => None,
+        };
+        if let Some(handle) = handle {
+            let mut state = handle.state.lock().await;
+            if let FileHandleState::Read { .. } = &*state {
+                // The handle stops reading before it can start writing
+                lookup.inode.finish_reading()?;
+                match FileHandleState::new_write_handle(&lookup, ino, flags, pid, self).await {
+                    Ok(write_state) => {
+                        metrics::gauge!("fs.current_handles", "type" => "read").decrement(1.0);
+                        *state = write_state;
+                    }
+                    Err(e) => {
+                        lookup.inode.start_reading()?;
+                        return Err(e);
+                    }
+                }
+                debug!(ino, fh, "truncated file, handle is now a write handle");
+                // The inode is now being written, so we can update its times like any local file.
+                return Ok(self.superblock.setattr(&self.client, ino, atime, mtime).await?);
+            }
+        }
+
+        let FileHandleState::Write(mut upload) =
+            FileHandleState::new_write_handle(&lookup, ino, flags, pid, self).await?
+        else {
+            unreachable!("new_write_handle always creates a write handle");
+        };
+        let result = upload.complete(lookup.inode.full_key(), false, None).await;
+        metrics::gauge!("fs.current_handles", "type" => "write").decrement(1.0);
+        result?;
+        debug!(ino, "truncated file by uploading an empty object");
+        Ok(self.superblock.getattr(&self.client, ino, true).await?)
+    }
+
     pub async fn forget(&self, ino: InodeNo, n: u64) {
         trace!("fs:forget with ino {:?} n {:?}", ino, n);
+        self.attr_cache.invalidate(ino);
+        self.kernel_cache.forget(ino);
         self.superblock.forget(ino, n);
     }
 
+    /// Like [S3Filesystem::forget], for the references to many inodes at once
+    pub async fn forget_batch(&self, nodes: &[(InodeNo, u64)]) {
+        trace!("fs:forget_batch with {} inodes", nodes.len());
+        for &(ino, _) in nodes {
+            self.attr_cache.invalidate(ino);
+            self.kernel_cache.forget(ino);
+        }
+        self.superblock.forget_batch(nodes.iter().copied());
+    }
+
     pub async fn open(&self, ino: InodeNo, flags: i32, pid: u32) -> Result<Opened, Error> {
         trace!("fs:open with ino {:?} flags {:#b} pid {:?}", ino, flags, pid);
+        if flags & (libc::O_WRONLY | libc::O_RDWR) != 0 {
+            self.check_writable()?;
+            self.attr_cache.invalidate(ino);
+        } else {
+            self.check_readable()?;
+        }
 
         #[cfg(not(target_os = "linux"))]
         let direct_io = false;
@@ -742,6 +1544,8 @@ where
 
         match lookup.inode.kind() {
             InodeKind::Directory => return Err(InodeError::IsDirectory(lookup.inode.err()).into()),
+            // The kernel resolves symlinks before opening, so this should only happen with O_NOFOLLOW
+            InodeKind::Symlink => return Err(err!(libc::ELOOP, "cannot open a symbolic link")),
             InodeKind::File => (),
         }
 
@@ -749,11 +1553,7 @@ where
         let full_key = lookup.inode.full_key().to_owned();
         let remote_file = lookup.inode.is_remote()?;
 
-        // Open with O_APPEND is ok for new files because it's same as creating a new one.
-        // but we can't support it on existing files and we should explicitly say we don't allow that.
-        if remote_file && (flags & libc::O_APPEND != 0) {
-            return Err(err!(libc::EINVAL, "O_APPEND is not supported on existing files"));
-        }
+        let is_append = self.is_append(&lookup, flags)?;
 
         // We can't support O_SYNC writes because they require the data to go to stable storage
         // at `write` time, but we only commit a PUT at `close` time.
@@ -761,14 +1561,26 @@ where
             return Err(err!(libc::EINVAL, "O_SYNC and O_DSYNC are not supported"));
         }
 
-        let state = if flags & libc::O_RDWR != 0 {
+        let state = if let Some(overlay_file) = self.overlay_file(&lookup.inode) {
+            self.check_readable()?;
+            let is_write = flags & libc::O_WRONLY != 0 || (flags & libc::O_RDWR != 0 && flags & libc::O_TRUNC != 0);
+            if is_write {
+                return Err(err!(
+                    libc::EPERM,
+                    "file is shadowed by the overlay directory and cannot be written"
+                ));
+            }
+            debug!("fs:open choosing overlay handle");
+            FileHandleState::new_overlay_handle(&lookup, &overlay_file)?
+        } else if flags & libc::O_RDWR != 0 {
             let is_truncate = flags & libc::O_TRUNC != 0;
-            if !remote_file || (self.config.allow_overwrite && is_truncate) {
-                // If the file is new or opened in truncate mode, we know it must be a write handle.
+            if !remote_file || (self.config.allow_overwrite && (is_truncate || is_append)) {
+                // If the file is new or opened in truncate or append mode, we know it must be a write handle.
                 debug!("fs:open choosing write handle for O_RDWR");
                 FileHandleState::new_write_handle(&lookup, lookup.inode.ino(), flags, pid, self).await?
             } else {
                 // Otherwise, it must be a read handle.
+                self.check_readable()?;
                 debug!("fs:open choosing read handle for O_RDWR");
                 FileHandleState::new_read_handle(&lookup, self).await?
             }
@@ -778,6 +1590,13 @@ where
             FileHandleState::new_read_handle(&lookup, self).await?
         };
 
+        // Only the contents of objects in S3 can be kept in the kernel's cache across opens
+        let cached_etag = match &state {
+            FileHandleState::Read { .. } => lookup.stat.etag.as_deref(),
+            FileHandleState::Write(_) | FileHandleState::Overlay(_) => None,
+        };
+        let reply_flags = self.kernel_cache.open_flags(ino, cached_etag, direct_io);
+
         let fh = self.next_handle();
         let handle = FileHandle {
             inode,
@@ -787,13 +1606,30 @@ where
         debug!(fh, ino, "new file handle created");
         self.file_handles.write().await.insert(fh, Arc::new(handle));
 
-        let reply_flags = if direct_io { FOPEN_DIRECT_IO } else { 0 };
-
         Ok(Opened { fh, flags: reply_flags })
     }
 
     #[allow(clippy::too_many_arguments)] // We don't get to choose this interface
     pub async fn read(
+        &self,
+        ino: InodeNo,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        flags: i32,
+        lock: Option<u64>,
+    ) -> Result<Bytes, Error> {
+        let mut buffers = self.read_vectored(ino, fh, offset, size, flags, lock).await?;
+        if buffers.len() == 1 {
+            return Ok(buffers.pop().unwrap());
+        }
+        Ok(buffers.concat().into())
+    }
+
+    /// Read from a file like [Self::read], but return the data in the buffers it's already in,
+    /// so that it can be sent to the kernel without first copying it into a single buffer.
+    #[allow(clippy::too_many_arguments)] // We don't get to choose this interface
+    pub async fn read_vectored(
         &self,
         ino: InodeNo,
         fh: u64,
@@ -801,7 +1637,7 @@ where
         size: u32,
         _flags: i32,
         _lock: Option<u64>,
-    ) -> Result<Bytes, Error> {
+    ) -> Result<Vec<Bytes>, Error> {
         trace!(
             "fs:read with ino {:?} fh {:?} offset {:?} size {:?}",
             ino,
@@ -809,6 +1645,7 @@ where
             offset,
             size
         );
+        self.check_readable()?;
 
         let handle = {
             let file_handles = self.file_handles.read().await;
@@ -820,13 +1657,35 @@ where
         logging::record_name(handle.inode.name());
         let mut state = handle.state.lock().await;
         let request = match &mut *state {
-            FileHandleState::Read(request) => request,
+            FileHandleState::Read {
+                request,
+                readable_range,
+            } => {
+                if let Some(readable_range) = readable_range {
+                    if !readable_range.allows(offset as u64) {
+                        return Err(err!(
+                            libc::EACCES,
+                            "read at offset {} is not allowed by the read policy",
+                            offset
+                        ));
+                    }
+                }
+                request
+            }
             FileHandleState::Write(_) => return Err(err!(libc::EBADF, "file handle is not open for reads")),
+            FileHandleState::Overlay(reader) => {
+                return reader
+                    .read(offset as u64, size as usize)
+                    .map(|bytes| vec![bytes])
+                    .map_err(|e| err!(libc::EIO, source:e, "overlay read failed"));
+            }
         };
 
-        match request.read(offset as u64, size as usize).await {
-            Ok(checksummed_bytes) => checksummed_bytes
-                .into_bytes()
+        match request.read_vectored(offset as u64, size as usize).await {
+            Ok(parts) => parts
+                .into_iter()
+                .map(|checksummed_bytes| checksummed_bytes.into_bytes())
+                .collect::<Result<_, _>>()
                 .map_err(|e| err!(libc::EIO, source:e, "integrity error")),
             Err(PrefetchReadError::GetRequestFailed(ObjectClientError::ServiceError(
                 GetObjectError::PreconditionFailed,
@@ -840,6 +1699,42 @@ where
         }
     }
 
+    /// Find the next data or hole in a file, for `lseek` with `SEEK_DATA` or `SEEK_HOLE`. Files
+    /// never have holes, so data runs from any offset to the end of the file, where there's an
+    /// implicit hole. The kernel handles the other `whence` values itself.
+    pub async fn lseek(&self, ino: InodeNo, fh: u64, offset: i64, whence: i32) -> Result<i64, Error> {
+        trace!(
+            "fs:lseek with ino {:?} fh {:?} offset {:?} whence {:?}",
+            ino,
+            fh,
+            offset,
+            whence
+        );
+        let size = self.getattr(ino).await?.attr.size;
+        seek_data_or_hole(size, offset, whence)
+    }
+
+    pub async fn statfs(&self, ino: InodeNo) -> Result<StatFs, Error> {
+        trace!("fs:statfs with ino {:?}", ino);
+        const STATFS_BLOCK_SIZE: u32 = 4096;
+        /// The longest name the kernel will pass us, from `NAME_MAX`
+        const MAXIMUM_NAME_LENGTH: u32 = 255;
+
+        let total_blocks = self.config.fs_size / STATFS_BLOCK_SIZE as u64;
+        let free_blocks = self.config.fs_free.min(self.config.fs_size) / STATFS_BLOCK_SIZE as u64;
+        // There's no limit on the number of objects either, so report as many inodes as blocks
+        Ok(StatFs {
+            total_blocks,
+            free_blocks,
+            available_blocks: free_blocks,
+            total_inodes: total_blocks,
+            free_inodes: free_blocks,
+            block_size: STATFS_BLOCK_SIZE,
+            maximum_name_length: MAXIMUM_NAME_LENGTH,
+            fragment_size: STATFS_BLOCK_SIZE,
+        })
+    }
+
     pub async fn mknod(
         &self,
         parent: InodeNo,
@@ -848,11 +1743,15 @@ where
         _umask: u32,
         _rdev: u32,
     ) -> Result<Entry, Error> {
-        if mode & libc::S_IFMT != libc::S_IFREG {
+        self.check_writable()?;
+        // A zero file type means a regular file, as for mknod(2). S3 has no way to represent other
+        // node types, and EPERM is what mknod(2) returns when the file system doesn't support them.
+        let file_type = mode & libc::S_IFMT;
+        if file_type != 0 && file_type != libc::S_IFREG {
             return Err(err!(
-                libc::EINVAL,
+                libc::EPERM,
                 "invalid mknod type {}; only regular files are supported",
-                mode & libc::S_IFMT
+                file_type
             ));
         }
 
@@ -862,20 +1761,209 @@ where
             .await?;
         let attr = self.make_attr(&lookup);
         Ok(Entry {
-            ttl: lookup.validity(),
+            ttl: self.kernel_cache.ttl(lookup.validity()),
+            attr,
+            generation: 0,
+        })
+    }
+
+    pub async fn symlink(&self, parent: InodeNo, name: &OsStr, target: &Path) -> Result<Entry, Error> {
+        self.check_writable()?;
+        if !self.config.allow_symlinks {
+            return Err(err!(
+                libc::EPERM,
+                "Symbolic links are disabled. Use '--allow-symlinks' mount option to enable them."
+            ));
+        }
+
+        let mut put_params = PutObjectParams::new();
+        if let Some(storage_class) = &self.config.storage_class {
+            put_params = put_params.storage_class(storage_class.clone());
+        }
+        let (sse_type, key_id) = self
+            .config
+            .server_side_encryption
+            .clone()
+            .into_inner()
+            .map_err(|e| err!(libc::EIO, source:e, "server-side encryption settings are corrupted"))?;
+        put_params = put_params.server_side_encryption(sse_type).ssekms_key_id(key_id);
+
+        let lookup = self
+            .superblock
+            .symlink(&self.client, parent, name, target.as_os_str(), put_params)
+            .await?;
+        let attr = self.make_attr(&lookup);
+        Ok(Entry {
+            ttl: self.kernel_cache.ttl(lookup.validity()),
             attr,
             generation: 0,
         })
     }
 
+    pub async fn readlink(&self, ino: InodeNo) -> Result<OsString, Error> {
+        trace!("fs:readlink with ino {:?}", ino);
+        Ok(self.superblock.readlink(ino).await?)
+    }
+
+    /// Get the value of an extended attribute, or `None` if the inode doesn't have it.
+    pub async fn getxattr(&self, ino: InodeNo, name: &OsStr) -> Result<Option<Vec<u8>>, Error> {
+        trace!("fs:getxattr with ino {:?} name {:?}", ino, name);
+        // Skip the lookup for attributes we never have. The kernel asks for some of these (like
+        // `security.capability`) on every write.
+        let Some(name) = name.to_str().filter(|name| name.starts_with("user.")) else {
+            return Ok(None);
+        };
+        let lookup = self.superblock.getattr(&self.client, ino, false).await?;
+        match xattr::user_metadata_key(name) {
+            Some(key) => {
+                let metadata = self.user_metadata(&lookup).await?;
+                Ok(metadata.get(key).map(|value| value.clone().into_bytes()))
+            }
+            None => Ok(xattr::get(&lookup.stat, name)),
+        }
+    }
+
+    /// List the names of the extended attributes of an inode, each followed by a NUL byte.
+    pub async fn listxattr(&self, ino: InodeNo) -> Result<Vec<u8>, Error> {
+        trace!("fs:listxattr with ino {:?}", ino);
+        let lookup = self.superblock.getattr(&self.client, ino, false).await?;
+        let mut names = xattr::list(&lookup.stat);
+        names.extend(xattr::list_user_metadata(&self.user_metadata(&lookup).await?));
+        Ok(names)
+    }
+
+    /// Set an extended attribute in the `user.` namespace, which becomes user-defined metadata of
+    /// the object. This is only possible on files that are open for writing, before any data has
+    /// been written, because S3 needs the metadata when the upload starts.
+    pub async fn setxattr(&self, ino: InodeNo, name: &OsStr, value: &[u8], flags: i32) -> Result<(), Error> {
+        trace!("fs:setxattr with ino {:?} name {:?} flags {:#b}", ino, name, flags);
+        self.check_writable()?;
+        let name = name.to_str().unwrap_or_default();
+        if name.starts_with(xattr::MOUNTPOINT_PREFIX) {
+            return Err(err!(libc::EPERM, "extended attribute {:?} is read-only", name));
+        }
+        let Some(key) = xattr::user_metadata_key(name) else {
+            return Err(err!(
+                libc::ENOTSUP,
+                "only extended attributes in the user namespace are supported"
+            ));
+        };
+        // Setting the symlink target would turn the file into a symlink once it's uploaded
+        if key == SYMLINK_TARGET_METADATA_KEY {
+            return Err(err!(libc::EPERM, "extended attribute {:?} is reserved", name));
+        }
+        if !xattr::is_valid_user_metadata_key(key) {
+            return Err(err!(
+                libc::EINVAL,
+                "extended attribute names can only contain lowercase letters, digits, '-', '_', and '.'"
+            ));
+        }
+        let Some(value) = std::str::from_utf8(value)
+            .ok()
+            .filter(|value| !value.chars().any(char::is_control))
+        else {
+            return Err(err!(libc::EINVAL, "extended attribute values must be printable UTF-8"));
+        };
+
+        // A handle released in the meantime has already completed its upload, so it's skipped
+        for handle in self.file_handles_for(ino).await {
+            let mut state = handle.state.lock().await;
+            let FileHandleState::Write(UploadState::InProgress { request, .. }) = &mut *state else {
+                continue;
+            };
+
+            let mut metadata = request.object_metadata().clone();
+            let exists = metadata.contains_key(key);
+            if flags & libc::XATTR_CREATE != 0 && exists {
+                return Err(err!(libc::EEXIST, "extended attribute {:?} already exists", name));
+            }
+            if flags & libc::XATTR_REPLACE != 0 && !exists {
+                return Err(err!(ENOATTR, "extended attribute {:?} does not exist", name));
+            }
+            metadata.insert(key.to_owned(), value.to_owned());
+            if xattr::user_metadata_size(&metadata) > xattr::MAX_USER_METADATA_SIZE {
+                return Err(err!(
+                    libc::E2BIG,
+                    "user-defined metadata can be at most {} bytes",
+                    xattr::MAX_USER_METADATA_SIZE
+                ));
+            }
+
+            return match request.set_object_metadata(metadata).await {
+                Ok(()) => Ok(()),
+                Err(e @ UploadMetadataError::AlreadyStarted) => Err(err!(
+                    libc::EBUSY,
+                    source:e,
+                    "extended attributes must be set before writing to the file"
+                )),
+                Err(e) => Err(err!(libc::EIO, source:e, "put failed to restart")),
+            };
+        }
+        Err(err!(
+            libc::EPERM,
+            "extended attributes can only be set on files that are open for writing"
+        ))
+    }
+
+    /// The open handles for the file `ino`. The handle map is only locked while they're collected,
+    /// so the caller can wait on the handles without holding up opening and releasing other files.
+    async fn file_handles_for(&self, ino: InodeNo) -> Vec<Arc<FileHandle<Client, Prefetcher>>> {
+        let file_handles = self.file_handles.read().await;
+        file_handles
+            .values()
+            .filter(|handle| handle.inode.ino() == ino)
+            .cloned()
+            .collect()
+    }
+
+    /// The user-defined metadata of the object for a file. Files that are being written have the
+    /// metadata they will be uploaded with, and other files have the metadata of their object in S3.
+    async fn user_metadata(&self, lookup: &LookedUp) -> Result<Arc<HashMap<String, String>>, Error> {
+        if lookup.inode.kind() != InodeKind::File {
+            return Ok(Default::default());
+        }
+
+        for handle in self.file_handles_for(lookup.inode.ino()).await {
+            if let FileHandleState::Write(UploadState::InProgress { request, .. }) = &*handle.state.lock().await {
+                return Ok(Arc::new(request.object_metadata().clone()));
+            }
+        }
+
+        if !matches!(lookup.inode.is_remote(), Ok(true)) {
+            return Ok(Default::default());
+        }
+        // HeadObject only describes the current version of the object, which might not be the
+        // version this file shows
+        if lookup.stat.version_id.is_some() {
+            return Ok(Default::default());
+        }
+        if let Some(user_metadata) = &lookup.stat.user_metadata {
+            return Ok(user_metadata.clone());
+        }
+        // ListObjectsV2 doesn't return metadata, so we need to ask for it, and then keep it with
+        // the rest of the file's stat
+        let (bucket, key) = (lookup.inode.bucket(), lookup.inode.full_key());
+        match self.client.head_object(bucket, key).await {
+            Ok(result) => {
+                let user_metadata = Arc::new(result.object_metadata);
+                self.superblock
+                    .cache_user_metadata(lookup.inode.ino(), &result.object.etag, user_metadata.clone());
+                Ok(user_metadata)
+            }
+            Err(ObjectClientError::ServiceError(HeadObjectError::NotFound)) => Ok(Default::default()),
+            Err(e) => Err(err!(libc::EIO, source:e, "HeadObject failed")),
+        }
+    }
+
     pub async fn mkdir(&self, parent: InodeNo, name: &OsStr, _mode: libc::mode_t, _umask: u32) -> Result<Entry, Error> {
+        self.check_writable()?;
         let lookup = self
             .superblock
             .create(&self.client, parent, name, InodeKind::Directory)
             .await?;
         let attr = self.make_attr(&lookup);
         Ok(Entry {
-            ttl: lookup.validity(),
+            ttl: self.kernel_cache.ttl(lookup.validity()),
             attr,
             generation: 0,
         })
@@ -900,6 +1988,7 @@ where
             offset,
             len
         );
+        self.check_writable()?;
 
         let handle = {
             let file_handles = self.file_handles.read().await;
@@ -914,15 +2003,157 @@ where
             let mut state = handle.state.lock().await;
             let request = match &mut *state {
                 FileHandleState::Read { .. } => return Err(err!(libc::EBADF, "file handle is not open for writes")),
+                // Shadowed files opened with O_RDWR get an overlay handle, which can only read
+                FileHandleState::Overlay(_) => {
+                    return Err(err!(
+                        libc::EPERM,
+                        "file is shadowed by the overlay directory and cannot be written"
+                    ))
+                }
                 FileHandleState::Write(request) => request,
             };
 
+            if let UploadState::Completed { size, pid, etag } = request {
+                if offset as u64 == *size {
+                    let (size, pid, etag) = (*size, *pid, etag.clone());
+                    *request = self.continue_upload(&handle, size, pid, etag).await?;
+                }
+            }
             request.write(offset, data, &handle.full_key).await?
         };
-        handle.inode.inc_file_size(len as usize);
+        handle.inode.current().inc_file_size(len as usize);
         Ok(len)
     }
 
+    /// Start a new upload for a handle whose upload was already completed by `fsync`, so that
+    /// writing can continue at the end of the object. The new upload starts with a copy of the
+    /// object and replaces it, so this is only possible if the S3 implementation supports appending
+    /// and overwrites are allowed.
+    async fn continue_upload(
+        &self,
+        handle: &FileHandle<Client, Prefetcher>,
+        size: u64,
+        pid: u32,
+        etag: Option<String>,
+    ) -> Result<UploadState<Client>, Error> {
+        // The file can be renamed once its upload is complete, so continue at its current key
+        let inode = handle.inode.current();
+        let key = inode.full_key();
+        if !self.config.s3_personality.supports_append() {
+            return Err(err!(
+                libc::EIO,
+                "upload already completed for key {:?}, and writing after fsync is not supported",
+                key
+            ));
+        }
+        // Appending to a small object downloads it
+        self.check_readable()?;
+        // A rename copies the object, so the ETag from fsync is out of date if it was renamed
+        let etag = if key == handle.full_key {
+            etag
+        } else {
+            self.superblock
+                .getattr(&self.client, inode.ino(), false)
+                .await?
+                .stat
+                .etag
+        };
+        debug!(key, size, "continuing upload after it was completed");
+        let write_handle = self
+            .superblock
+            .write(
+                &self.client,
+                inode.ino(),
+                inode.parent(),
+                pid,
+                self.config.allow_overwrite,
+                false,
+                true,
+            )
+            .await
+            .start_writing()?;
+        let request = match self.uploader.append(inode.bucket(), key, size, etag).await {
+            Ok(request) => request,
+            Err(e) => {
+                if let Err(err) = write_handle.finish_writing() {
+                    error!(?err, key, "error updating the inode status");
+                }
+                return Err(err!(e.to_errno(), source:e, "append failed to start"));
+            }
+        };
+        Ok(UploadState::InProgress {
+            request,
+            handle: write_handle,
+        })
+    }
+
+    /// Reserve space for a file that's open for writing.
+    ///
+    /// We can't preallocate space in S3, so this only checks that the upload could grow to the
+    /// requested size, and otherwise does nothing.
+    pub async fn fallocate(&self, ino: InodeNo, fh: u64, offset: i64, length: i64, mode: i32) -> Result<(), Error> {
+        trace!(
+            "fs:fallocate with ino {:?} fh {:?} offset {:?} length {:?} mode {:?}",
+            ino,
+            fh,
+            offset,
+            length,
+            mode
+        );
+        self.check_writable()?;
+
+        if mode != 0 && mode != libc::FALLOC_FL_KEEP_SIZE {
+            return Err(err!(libc::EOPNOTSUPP, "unsupported fallocate mode {:#x}", mode));
+        }
+        let (Ok(offset), Ok(length)) = (u64::try_from(offset), u64::try_from(length)) else {
+            return Err(err!(libc::EINVAL, "invalid offset {} or length {}", offset, length));
+        };
+        if length == 0 {
+            return Err(err!(libc::EINVAL, "length must be greater than zero"));
+        }
+
+        let handle = {
+            let file_handles = self.file_handles.read().await;
+            match file_handles.get(&fh) {
+                Some(handle) => handle.clone(),
+                None => return Err(err!(libc::EBADF, "invalid file handle")),
+            }
+        };
+        logging::record_name(handle.inode.name());
+
+        let state = handle.state.lock().await;
+        let maximum_size = match &*state {
+            FileHandleState::Write(UploadState::InProgress { request, .. }) => request.maximum_size(),
+            FileHandleState::Write(UploadState::Failed(e)) => {
+                return Err(err!(*e, "upload already aborted for key {:?}", handle.full_key))
+            }
+            FileHandleState::Write(UploadState::Completed { .. }) => {
+                return Err(err!(
+                    libc::EIO,
+                    "upload already completed for key {:?}",
+                    handle.full_key
+                ))
+            }
+            FileHandleState::Read { .. } => return Err(err!(libc::EBADF, "file handle is not open for writes")),
+            FileHandleState::Overlay(_) => {
+                return Err(err!(
+                    libc::EPERM,
+                    "file is shadowed by the overlay directory and cannot be written"
+                ))
+            }
+        };
+        match (offset.checked_add(length), maximum_size) {
+            (Some(end), Some(maximum_size)) if end <= maximum_size => Ok(()),
+            (Some(_), None) => Ok(()),
+            _ => Err(err!(
+                libc::EFBIG,
+                "cannot allocate {} bytes at offset {}: object would exceed the maximum upload size",
+                length,
+                offset
+            )),
+        }
+    }
+
     /// Creates a new ReaddirHandle for the provided parent and default page size
     async fn readdir_handle(&self, parent: InodeNo) -> Result<ReaddirHandle, InodeError> {
         self.superblock.readdir(&self.client, parent, 1000).await
@@ -944,7 +2175,10 @@ where
         let mut dir_handles = self.dir_handles.write().await;
         dir_handles.insert(fh, Arc::new(handle));
 
-        Ok(Opened { fh, flags: 0 })
+        let flags = self
+            .kernel_cache
+            .opendir_flags(parent, self.config.cache_config.dir_ttl);
+        Ok(Opened { fh, flags })
     }
 
     pub async fn readdir<R: DirectoryReplier>(
@@ -966,6 +2200,11 @@ where
         reply: R,
     ) -> Result<R, Error> {
         trace!("fs:readdirplus with ino {:?} fh {:?} offset {:?}", parent, fh, offset);
+        if !self.config.readdirplus {
+            // The kernel shouldn't send readdirplus if we didn't ask for it, but if it does,
+            // ENOSYS tells it to fall back to plain readdir.
+            return Err(err!(libc::ENOSYS, "readdirplus is disabled"));
+        }
         self.readdir_impl(parent, fh, offset, true, reply).await
     }
 
@@ -1006,14 +2245,16 @@ where
                 if offset == *last_offset {
                     trace!(offset, "repeating readdir response");
                     for entry in entries {
-                        if reply.add(entry.clone()) {
+                        if reply.add(entry.clone()).await {
                             break;
                         }
                         // We are returning this result a second time, so the contract is that we
                         // must remember it again, except that readdirplus specifies that . and ..
                         // are never incremented.
                         if is_readdirplus && entry.name != "." && entry.name != ".." {
-                            readdir_handle.remember(&entry.lookup);
+                            if let Some(lookup) = &entry.lookup {
+                                readdir_handle.remember(lookup);
+                            }
                         }
                     }
                     return Ok(reply);
@@ -1042,9 +2283,10 @@ where
             }
         }
 
+        #[async_trait]
         impl<R: DirectoryReplier> DirectoryReplier for Reply<R> {
-            fn add(&mut self, entry: DirectoryEntry) -> bool {
-                let result = self.reply.add(entry.clone());
+            async fn add(&mut self, entry: DirectoryEntry) -> bool {
+                let result = self.reply.add(entry.clone()).await;
                 if !result {
                     self.entries.push(entry);
                 }
@@ -1063,10 +2305,10 @@ where
                 name: ".".into(),
                 attr,
                 generation: 0,
-                ttl: lookup.validity(),
-                lookup,
+                ttl: self.kernel_cache.ttl(lookup.validity()),
+                lookup: Some(lookup),
             };
-            if reply.add(entry) {
+            if reply.add(entry).await {
                 return Ok(reply.finish(offset, &dir_handle).await);
             }
             dir_handle.next_offset();
@@ -1083,10 +2325,26 @@ where
                 name: "..".into(),
                 attr,
                 generation: 0,
-                ttl: lookup.validity(),
-                lookup,
+                ttl: self.kernel_cache.ttl(lookup.validity()),
+                lookup: Some(lookup),
+            };
+            if reply.add(entry).await {
+                return Ok(reply.finish(offset, &dir_handle).await);
+            }
+            dir_handle.next_offset();
+        }
+        if parent == FUSE_ROOT_INODE && self.control.is_visible() && dir_handle.offset() < 3 {
+            let attr = self.control.attr(self.control.ino());
+            let entry = DirectoryEntry {
+                ino: attr.ino,
+                offset: dir_handle.offset() + 1,
+                name: CONTROL_DIRECTORY_NAME.into(),
+                attr,
+                generation: 0,
+                ttl: Duration::ZERO,
+                lookup: None,
             };
-            if reply.add(entry) {
+            if reply.add(entry).await {
                 return Ok(reply.finish(offset, &dir_handle).await);
             }
             dir_handle.next_offset();
@@ -1097,6 +2355,10 @@ where
                 None => return Ok(reply.finish(offset, &dir_handle).await),
                 Some(next) => next,
             };
+            // Keys under the control directory's name can't be reached, so don't list them either
+            if self.control.contains_name(parent, next.inode.name().as_ref()) {
+                continue;
+            }
 
             let attr = self.make_attr(&next);
             let entry = DirectoryEntry {
@@ -1105,11 +2367,11 @@ where
                 name: next.inode.name().into(),
                 attr,
                 generation: 0,
-                ttl: next.validity(),
-                lookup: next.clone(),
+                ttl: self.kernel_cache.ttl(next.validity()),
+                lookup: Some(next.clone()),
             };
 
-            if reply.add(entry) {
+            if reply.add(entry).await {
                 readdir_handle.readd(next);
                 return Ok(reply.finish(offset, &dir_handle).await);
             }
@@ -1135,7 +2397,7 @@ where
         }
     }
 
-    pub async fn fsync(&self, _ino: InodeNo, fh: u64, _datasync: bool) -> Result<(), Error> {
+    pub async fn fsync(&self, ino: InodeNo, fh: u64, _datasync: bool) -> Result<(), Error> {
         let file_handle = {
             let file_handles = self.file_handles.read().await;
             match file_handles.get(&fh) {
@@ -1145,14 +2407,14 @@ where
         };
         logging::record_name(file_handle.inode.name());
         let mut state = file_handle.state.lock().await;
-        let request = match &mut *state {
-            FileHandleState::Read { .. } => return Ok(()),
-            FileHandleState::Write(request) => request,
+        let result = match &mut *state {
+            FileHandleState::Read { .. } | FileHandleState::Overlay(_) => Ok(()),
+            FileHandleState::Write(request) => self.complete_upload(request, &file_handle.full_key, false, None).await,
         };
-        self.complete_upload(request, &file_handle.full_key, false, None).await
+        result.and_then(|()| self.check_upload_failures(&file_handle.full_key))
     }
 
-    pub async fn flush(&self, _ino: InodeNo, fh: u64, _lock_owner: u64, pid: u32) -> Result<(), Error> {
+    pub async fn flush(&self, ino: InodeNo, fh: u64, _lock_owner: u64, pid: u32) -> Result<(), Error> {
         // We generally want to complete the upload when users close a file descriptor (and flush
         // is invoked), so that we can notify them of the outcome. However, since different file
         // descriptors can point to the same file handle, flush can be invoked multiple times on
@@ -1175,12 +2437,27 @@ where
         };
         logging::record_name(file_handle.inode.name());
         let mut state = file_handle.state.lock().await;
-        match &mut *state {
-            FileHandleState::Read { .. } => Ok(()),
+        let result = match &mut *state {
+            FileHandleState::Read { .. } | FileHandleState::Overlay(_) => Ok(()),
             FileHandleState::Write(request) => {
                 self.complete_upload(request, &file_handle.full_key, true, Some(pid))
                     .await
             }
+        };
+        result.and_then(|()| self.check_upload_failures(&file_handle.full_key))
+    }
+
+    /// Report an earlier upload of `key` that failed when its file was released, so the failure
+    /// isn't lost just because the kernel doesn't wait for `release`.
+    fn check_upload_failures(&self, key: &str) -> Result<(), Error> {
+        match self.upload_failures.take(key) {
+            Some(failure) => Err(err!(
+                libc::EIO,
+                "an earlier upload of {:?} (upload ID {:?}) failed after the file was closed",
+                key,
+                failure.upload_id
+            )),
+            None => Ok(()),
         }
     }
 
@@ -1213,7 +2490,7 @@ where
         };
 
         let request = match file_handle.state.into_inner() {
-            FileHandleState::Read { .. } => {
+            FileHandleState::Read { .. } | FileHandleState::Overlay(_) => {
                 // TODO make sure we cancel the inflight PrefetchingGetRequest. is just dropping enough?
                 metrics::gauge!("fs.current_handles", "type" => "read").decrement(1.0);
                 file_handle.inode.finish_reading()?;
@@ -1222,19 +2499,36 @@ where
             FileHandleState::Write(request) => request,
         };
 
+        let upload_id = match &request {
+            UploadState::InProgress { request, .. } => request.upload_id().map(str::to_owned),
+            UploadState::Completed { .. } | UploadState::Failed(_) => None,
+        };
         let result = request.complete_if_in_progress(&file_handle.full_key).await;
         metrics::gauge!("fs.current_handles", "type" => "write").decrement(1.0);
-        // Errors won't actually be seen by the user because `release` is async,
-        // but it's the right thing to do.
+        if let Err(err) = &result {
+            // Errors won't actually be seen by the user because `release` is async, so remember
+            // the failure to report it from the next `flush` or `fsync` of the same file.
+            error!(
+                key = file_handle.full_key,
+                ?upload_id,
+                ?err,
+                "upload failed after the file was closed, reporting it on the next flush of the file"
+            );
+            self.upload_failures
+                .record(&file_handle.full_key, UploadFailure { upload_id });
+        }
         result
     }
 
     pub async fn rmdir(&self, parent_ino: InodeNo, name: &OsStr) -> Result<(), Error> {
-        self.superblock.rmdir(&self.client, parent_ino, name).await?;
+        self.check_writable()?;
+        self.superblock
+            .rmdir(&self.client, parent_ino, name, self.config.allow_delete)
+            .await?;
         Ok(())
     }
 
-    pub async fn releasedir(&self, _ino: InodeNo, fh: u64, _flags: i32) -> Result<(), Error> {
+    pub async fn releasedir(&self, ino: InodeNo, fh: u64, _flags: i32) -> Result<(), Error> {
         let mut dir_handles = self.dir_handles.write().await;
         dir_handles
             .remove(&fh)
@@ -1243,6 +2537,7 @@ where
     }
 
     pub async fn unlink(&self, parent_ino: InodeNo, name: &OsStr) -> Result<(), Error> {
+        self.check_writable()?;
         if !self.config.allow_delete {
             return Err(err!(
                 libc::EPERM,
@@ -1251,6 +2546,67 @@ where
         }
         Ok(self.superblock.unlink(&self.client, parent_ino, name).await?)
     }
+
+    pub async fn rename(
+        &self,
+        parent_ino: InodeNo,
+        name: &OsStr,
+        new_parent_ino: InodeNo,
+        new_name: &OsStr,
+        flags: u32,
+    ) -> Result<(), Error> {
+        self.check_writable()?;
+        // Renaming deletes the object at the old key, so it needs deletes to be allowed too
+        if !self.config.allow_delete {
+            return Err(err!(
+                libc::EPERM,
+                "Renames are disabled. Use '--allow-delete' mount option to enable it."
+            ));
+        }
+
+        #[cfg(target_os = "linux")]
+        let no_replace = {
+            if flags & !libc::RENAME_NOREPLACE != 0 {
+                return Err(err!(libc::EINVAL, "rename flags {:#x} are not supported", flags));
+            }
+            flags & libc::RENAME_NOREPLACE != 0
+        };
+        #[cfg(not(target_os = "linux"))]
+        let no_replace = {
+            if flags != 0 {
+                return Err(err!(libc::EINVAL, "rename flags {:#x} are not supported", flags));
+            }
+            false
+        };
+
+        let mut copy_params = CopyObjectParams::new();
+        if let Some(storage_class) = &self.config.storage_class {
+            copy_params = copy_params.storage_class(storage_class.clone());
+        }
+        let (sse_type, key_id) = self
+            .config
+            .server_side_encryption
+            .clone()
+            .into_inner()
+            .map_err(|e| err!(libc::EIO, source:e, "server-side encryption settings are corrupted"))?;
+        copy_params = copy_params.server_side_encryption(sse_type).ssekms_key_id(key_id);
+
+        let allow_overwrite = self.config.allow_overwrite && !no_replace;
+        let renamed_ino = self
+            .superblock
+            .rename(
+                &self.client,
+                parent_ino,
+                name,
+                new_parent_ino,
+                new_name,
+                allow_overwrite,
+                &copy_params,
+            )
+            .await?;
+        self.attr_cache.invalidate(renamed_ino);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -1333,14 +2689,14 @@ mod tests {
         client.add_object("dir1/file1.bin", MockObject::constant(0xa1, 15, ETag::for_tests()));
 
         let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
-        let prefetcher = default_prefetch(runtime, Default::default());
+        let prefetcher = default_prefetch(runtime.clone(), Default::default());
         let server_side_encryption =
             ServerSideEncryption::new(Some("aws:kms".to_owned()), Some("some_key_alias".to_owned()));
         let fs_config = S3FilesystemConfig {
             server_side_encryption,
             ..Default::default()
         };
-        let mut fs = S3Filesystem::new(client, prefetcher, bucket, &Default::default(), fs_config);
+        let mut fs = S3Filesystem::new(client, prefetcher, runtime, bucket, &Default::default(), fs_config);
 
         // Lookup inode of the dir1 directory
         let entry = fs.lookup(FUSE_ROOT_INODE, "dir1".as_ref()).await.unwrap();
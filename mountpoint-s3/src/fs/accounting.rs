@@ -0,0 +1,198 @@
+//! Attributing the file system's I/O to the processes and users that asked for it.
+//!
+//! Every FUSE request carries the pid and uid of the caller that caused it, so on a host shared by
+//! many applications we can tell which of them is responsible for the load on the file system.
+//! Requests the kernel makes on its own behalf, like writing back cached pages, might carry the ids
+//! of a kernel thread instead. We keep running totals for each process and user, and publish the
+//! totals for each user as metrics. Processes come and go, and their ids are reused, so they'd make
+//! too many distinct metrics; their totals are only kept in memory, for the most recently active
+//! processes.
+
+use std::hash::Hash;
+
+use linked_hash_map::LinkedHashMap;
+
+use crate::sync::Mutex;
+
+/// How many processes or users to keep totals for. Beyond this, we forget the ones that made a
+/// request least recently.
+const MAX_CALLERS: usize = 1024;
+
+/// Totals of the I/O made by one process or user
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IoUsage {
+    /// Number of FUSE requests
+    pub requests: u64,
+    /// Bytes returned by reads
+    pub bytes_read: u64,
+    /// Bytes accepted by writes
+    pub bytes_written: u64,
+}
+
+impl IoUsage {
+    fn add(&mut self, other: &IoUsage) {
+        self.requests += other.requests;
+        self.bytes_read += other.bytes_read;
+        self.bytes_written += other.bytes_written;
+    }
+}
+
+/// Running totals of I/O by process and by user
+#[derive(Debug)]
+pub struct IoAccounting {
+    enabled: bool,
+    processes: Mutex<LinkedHashMap<u32, IoUsage>>,
+    users: Mutex<LinkedHashMap<u32, IoUsage>>,
+}
+
+impl IoAccounting {
+    /// Create the totals. If not `enabled`, nothing is ever recorded.
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            processes: Default::default(),
+            users: Default::default(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Record a request from process `pid` running as user `uid`, which read or wrote the given
+    /// number of bytes
+    pub fn record(&self, pid: u32, uid: u32, bytes_read: usize, bytes_written: usize) {
+        if !self.enabled {
+            return;
+        }
+        let usage = IoUsage {
+            requests: 1,
+            bytes_read: bytes_read as u64,
+            bytes_written: bytes_written as u64,
+        };
+        add_usage(&self.processes, pid, &usage);
+        add_usage(&self.users, uid, &usage);
+
+        let uid = uid.to_string();
+        metrics::counter!("accounting.requests", "uid" => uid.clone()).increment(1);
+        if bytes_read > 0 {
+            metrics::counter!("accounting.bytes", "uid" => uid.clone(), "type" => "read").increment(usage.bytes_read);
+        }
+        if bytes_written > 0 {
+            metrics::counter!("accounting.bytes", "uid" => uid, "type" => "write").increment(usage.bytes_written);
+        }
+    }
+
+    /// The totals for each process, by pid, with the most recently active last
+    pub fn processes(&self) -> Vec<(u32, IoUsage)> {
+        snapshot(&self.processes)
+    }
+
+    /// The totals for each user, by uid, with the most recently active last
+    pub fn users(&self) -> Vec<(u32, IoUsage)> {
+        snapshot(&self.users)
+    }
+}
+
+fn add_usage<K: Hash + Eq>(callers: &Mutex<LinkedHashMap<K, IoUsage>>, caller: K, usage: &IoUsage) {
+    let mut callers = callers.lock().unwrap();
+    match callers.get_refresh(&caller) {
+        Some(total) => total.add(usage),
+        None => {
+            callers.insert(caller, *usage);
+            while callers.len() > MAX_CALLERS {
+                callers.pop_front();
+            }
+        }
+    }
+}
+
+fn snapshot<K: Hash + Eq + Copy>(callers: &Mutex<LinkedHashMap<K, IoUsage>>) -> Vec<(K, IoUsage)> {
+    let callers = callers.lock().unwrap();
+    callers.iter().map(|(caller, usage)| (*caller, *usage)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let accounting = IoAccounting::new(true);
+        accounting.record(100, 1000, 10, 0);
+        accounting.record(101, 1000, 0, 20);
+        accounting.record(100, 1000, 5, 0);
+        accounting.record(200, 2000, 0, 0);
+
+        assert_eq!(
+            accounting.processes(),
+            vec![
+                (
+                    101,
+                    IoUsage {
+                        requests: 1,
+                        bytes_read: 0,
+                        bytes_written: 20
+                    }
+                ),
+                (
+                    100,
+                    IoUsage {
+                        requests: 2,
+                        bytes_read: 15,
+                        bytes_written: 0
+                    }
+                ),
+                (
+                    200,
+                    IoUsage {
+                        requests: 1,
+                        bytes_read: 0,
+                        bytes_written: 0
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            accounting.users(),
+            vec![
+                (
+                    1000,
+                    IoUsage {
+                        requests: 3,
+                        bytes_read: 15,
+                        bytes_written: 20
+                    }
+                ),
+                (
+                    2000,
+                    IoUsage {
+                        requests: 1,
+                        bytes_read: 0,
+                        bytes_written: 0
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disabled() {
+        let accounting = IoAccounting::new(false);
+        accounting.record(100, 1000, 10, 0);
+        assert!(accounting.processes().is_empty());
+        assert!(accounting.users().is_empty());
+    }
+
+    #[test]
+    fn test_least_recently_active_forgotten() {
+        let accounting = IoAccounting::new(true);
+        for pid in 0..=MAX_CALLERS as u32 {
+            accounting.record(pid, 1000, 1, 0);
+        }
+        let processes = accounting.processes();
+        assert_eq!(processes.len(), MAX_CALLERS);
+        assert_eq!(processes[0].0, 1);
+        assert_eq!(accounting.users()[0].1.bytes_read, MAX_CALLERS as u64 + 1);
+    }
+}
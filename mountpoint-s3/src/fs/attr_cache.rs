@@ -0,0 +1,140 @@
+//! A very short-lived cache of `getattr` replies.
+//!
+//! Many tools stat the same file several times within a single operation (`cp -r` stats each file
+//! three or four times). When the metadata TTL is zero for strict consistency, each of those stats
+//! would need its own request to S3. This cache absorbs those bursts by reusing a `getattr` reply
+//! for a small fraction of a second, independently of the TTLs given to the kernel.
+
+use std::time::{Duration, Instant};
+
+use fuser::FileAttr;
+use linked_hash_map::LinkedHashMap;
+
+use super::InodeNo;
+use crate::sync::Mutex;
+
+#[derive(Debug)]
+pub(super) struct AttrCache {
+    /// Cached attributes in the order they were inserted, which is also the order they expire in.
+    entries: Mutex<LinkedHashMap<InodeNo, CachedAttr>>,
+    /// How long to keep each entry.
+    ttl: Duration,
+}
+
+#[derive(Debug)]
+struct CachedAttr {
+    inserted: Instant,
+    attr: FileAttr,
+    /// The TTL the kernel was given for the original reply.
+    kernel_ttl: Duration,
+}
+
+impl AttrCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(Default::default()),
+            ttl,
+        }
+    }
+
+    /// Get the cached attributes for an inode, along with the remainder of the TTL the kernel was
+    /// originally given for them.
+    pub fn get(&self, ino: InodeNo) -> Option<(FileAttr, Duration)> {
+        if self.ttl.is_zero() {
+            return None;
+        }
+        let entries = self.entries.lock().unwrap();
+        let cached = entries.get(&ino)?;
+        let elapsed = cached.inserted.elapsed();
+        if elapsed >= self.ttl {
+            return None;
+        }
+        metrics::counter!("metadata_cache.attr_cache.hit").increment(1);
+        Some((cached.attr, cached.kernel_ttl.saturating_sub(elapsed)))
+    }
+
+    pub fn insert(&self, ino: InodeNo, attr: FileAttr, kernel_ttl: Duration) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        while entries
+            .front()
+            .is_some_and(|(_, cached)| cached.inserted.elapsed() >= self.ttl)
+        {
+            entries.pop_front();
+        }
+        entries.remove(&ino);
+        let cached = CachedAttr {
+            inserted: Instant::now(),
+            attr,
+            kernel_ttl,
+        };
+        entries.insert(ino, cached);
+    }
+
+    /// Drop the cached attributes for an inode, because this file system is changing them.
+    pub fn invalidate(&self, ino: InodeNo) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        self.entries.lock().unwrap().remove(&ino);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+
+    use fuser::FileType;
+
+    use super::*;
+
+    fn attr(ino: InodeNo, size: u64) -> FileAttr {
+        let now = std::time::SystemTime::now();
+        FileAttr {
+            ino,
+            size,
+            blocks: 0,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            crtime: now,
+            kind: FileType::RegularFile,
+            perm: 0o644,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        }
+    }
+
+    #[test]
+    fn test_expiry() {
+        let ttl = Duration::from_millis(50);
+        let cache = AttrCache::new(ttl);
+        cache.insert(2, attr(2, 10), Duration::ZERO);
+        cache.insert(3, attr(3, 20), Duration::from_secs(1));
+
+        let (cached, kernel_ttl) = cache.get(2).expect("should be cached");
+        assert_eq!(cached.size, 10);
+        assert_eq!(kernel_ttl, Duration::ZERO);
+        let (_, kernel_ttl) = cache.get(3).expect("should be cached");
+        assert!(kernel_ttl <= Duration::from_secs(1));
+
+        cache.invalidate(3);
+        assert!(cache.get(3).is_none());
+
+        sleep(ttl);
+        assert!(cache.get(2).is_none());
+    }
+
+    #[test]
+    fn test_disabled() {
+        let cache = AttrCache::new(Duration::ZERO);
+        cache.insert(2, attr(2, 10), Duration::ZERO);
+        assert!(cache.get(2).is_none());
+    }
+}
@@ -0,0 +1,609 @@
+//! A `.mountpoint` directory at the root of the file system, with read-only files that describe the
+//! live mount, so that operators can inspect it with `cat`.
+//!
+//! The directory isn't part of the bucket. Its inodes aren't in the superblock, but have numbers
+//! the superblock reserves for them. [S3Filesystem] only serves the bucket: requests for control
+//! inodes, and for the control directory's name, are routed here by [crate::fuse] before they
+//! reach it. The only place the two meet is the listing of the root directory, which skips keys
+//! shadowed by the control directory and, if it's visible, lists it.
+//!
+//! The contents of each file are generated when it's opened, so that everything read through one
+//! file handle is consistent. Files report a size of zero, like those in `/proc`, and are opened
+//! with direct I/O so that the kernel reads them to the end anyway.
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use fuser::consts::FOPEN_DIRECT_IO;
+use fuser::{FileAttr, FileType};
+use mountpoint_s3_client::ObjectClient;
+
+use super::{
+    seek_data_or_hole, Attr, Caller, DirectoryEntry, DirectoryReplier, Entry, Error, FileHandleState, InodeNo,
+    IoAccounting, Opened, Ownership, S3Filesystem, SessionState, UploadState, FUSE_ROOT_INODE,
+};
+use crate::inode::{CachedInode, InodeKind};
+use crate::prefetch::Prefetch;
+use crate::sync::Mutex;
+
+/// The name of the control directory in the root directory
+pub const CONTROL_DIRECTORY_NAME: &str = ".mountpoint";
+
+/// Whether the control directory is available, and whether it's listed in the root directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ControlDirectoryMode {
+    /// There's no control directory, and keys under `.mountpoint/` are visible as usual
+    #[default]
+    Off,
+    /// The control directory can be looked up, but isn't listed by `readdir` of the root directory
+    Hidden,
+    /// The control directory is listed in the root directory like any other directory
+    Visible,
+}
+
+impl ControlDirectoryMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ControlDirectoryMode::Off => "off",
+            ControlDirectoryMode::Hidden => "hidden",
+            ControlDirectoryMode::Visible => "visible",
+        }
+    }
+}
+
+/// The files in the control directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ControlFile {
+    /// How the file system was mounted
+    Config,
+    /// Open handles, the FUSE session, and the I/O of each user and process
+    Stats,
+    /// The inodes in the metadata cache
+    Cache,
+    /// Uploads that are in progress
+    Uploads,
+}
+
+impl ControlFile {
+    const ALL: [ControlFile; 4] = [
+        ControlFile::Config,
+        ControlFile::Stats,
+        ControlFile::Cache,
+        ControlFile::Uploads,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            ControlFile::Config => "config",
+            ControlFile::Stats => "stats",
+            ControlFile::Cache => "cache",
+            ControlFile::Uploads => "uploads",
+        }
+    }
+}
+
+/// An inode in the control directory, including the directory itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ControlInode {
+    Directory,
+    File(ControlFile),
+}
+
+#[derive(Debug)]
+pub(crate) struct ControlDirectory {
+    mode: ControlDirectoryMode,
+    /// The directory's inode number. Its files have the numbers following it.
+    ino: InodeNo,
+    uid: u32,
+    gid: u32,
+    created: SystemTime,
+    /// The contents of each open file, by file handle
+    handles: Mutex<HashMap<u64, Bytes>>,
+}
+
+impl ControlDirectory {
+    /// How many inode numbers the control directory needs the superblock to reserve
+    pub const INODE_COUNT: u64 = 1 + ControlFile::ALL.len() as u64;
+
+    /// Create the control directory, with the inode numbers from `first_ino`, owned by the given
+    /// user and group, which should be those of the user who mounted the file system
+    pub fn new(mode: ControlDirectoryMode, first_ino: InodeNo, uid: u32, gid: u32) -> Self {
+        Self {
+            mode,
+            ino: first_ino,
+            uid,
+            gid,
+            created: SystemTime::now(),
+            handles: Default::default(),
+        }
+    }
+
+    /// The control inode with this number, if any
+    fn inode(&self, ino: InodeNo) -> Option<ControlInode> {
+        if self.mode == ControlDirectoryMode::Off || ino < self.ino {
+            return None;
+        }
+        match (ino - self.ino) as usize {
+            0 => Some(ControlInode::Directory),
+            i => ControlFile::ALL.get(i - 1).copied().map(ControlInode::File),
+        }
+    }
+
+    /// Whether `ino` is the control directory or one of its files
+    pub fn contains(&self, ino: InodeNo) -> bool {
+        self.inode(ino).is_some()
+    }
+
+    /// Whether `name` in `parent` is or would be in the control directory, rather than in the bucket
+    pub fn contains_name(&self, parent: InodeNo, name: &OsStr) -> bool {
+        self.mode != ControlDirectoryMode::Off
+            && (parent == self.ino || (parent == FUSE_ROOT_INODE && name == CONTROL_DIRECTORY_NAME))
+    }
+
+    /// Whether listings of the root directory include the control directory
+    pub fn is_visible(&self) -> bool {
+        self.mode == ControlDirectoryMode::Visible
+    }
+
+    pub fn ino(&self) -> InodeNo {
+        self.ino
+    }
+
+    /// The error for any attempt to change the control directory or its files
+    pub fn read_only(&self) -> Error {
+        err!(libc::EACCES, "the .mountpoint directory is read-only")
+    }
+
+    /// Look up `name` in `parent`, which [Self::contains_name]
+    pub fn lookup(&self, parent: InodeNo, name: &OsStr) -> Result<Entry, Error> {
+        let ino = if parent == FUSE_ROOT_INODE {
+            self.ino
+        } else {
+            ControlFile::ALL
+                .iter()
+                .position(|file| name == file.name())
+                .map(|i| self.ino + 1 + i as u64)
+                .ok_or_else(|| err!(libc::ENOENT, "no such file in the control directory"))?
+        };
+        Ok(Entry {
+            ttl: Duration::ZERO,
+            attr: self.attr(ino),
+            generation: 0,
+        })
+    }
+
+    pub fn getattr(&self, ino: InodeNo) -> Attr {
+        Attr {
+            ttl: Duration::ZERO,
+            attr: self.attr(ino),
+        }
+    }
+
+    /// The owner and permissions of a control inode. Only the user who mounted the file system can
+    /// read the control files, since they describe everyone's use of the mount.
+    fn ownership(&self, ino: InodeNo) -> Ownership {
+        let mode = match self.inode(ino) {
+            Some(ControlInode::Directory) => 0o500,
+            _ => 0o400,
+        };
+        Ownership {
+            uid: self.uid,
+            gid: self.gid,
+            mode,
+        }
+    }
+
+    /// Check that the caller has all of the access in `mask` to a control inode, with the same
+    /// rules as for keys in the bucket. Nobody can write to the control directory, not even root.
+    pub fn check_access(&self, ino: InodeNo, caller: Caller, mask: i32) -> Result<(), Error> {
+        if mask & libc::W_OK != 0 {
+            return Err(self.read_only());
+        }
+        if !self.ownership(ino).allows(caller, mask) {
+            return Err(err!(
+                libc::EACCES,
+                "uid {} gid {} does not have access {:#o} to the .mountpoint directory",
+                caller.uid,
+                caller.gid,
+                mask
+            ));
+        }
+        Ok(())
+    }
+
+    /// The attributes of a control inode
+    pub fn attr(&self, ino: InodeNo) -> FileAttr {
+        let (kind, nlink) = match self.inode(ino) {
+            Some(ControlInode::Directory) => (FileType::Directory, 2),
+            _ => (FileType::RegularFile, 1),
+        };
+        let ownership = self.ownership(ino);
+        FileAttr {
+            ino,
+            size: 0,
+            blocks: 0,
+            atime: self.created,
+            mtime: self.created,
+            ctime: self.created,
+            crtime: UNIX_EPOCH,
+            kind,
+            perm: ownership.mode,
+            nlink,
+            uid: ownership.uid,
+            gid: ownership.gid,
+            rdev: 0,
+            flags: 0,
+            blksize: 4096,
+        }
+    }
+
+    /// List the control directory, whose parent (the root directory) has attributes `parent_attr`.
+    /// Its entries never change, so there's no state to keep in a directory handle.
+    pub async fn readdir<R: DirectoryReplier>(&self, offset: i64, parent_attr: FileAttr, mut reply: R) -> R {
+        let mut entries = vec![
+            (OsString::from("."), self.attr(self.ino)),
+            (OsString::from(".."), parent_attr),
+        ];
+        entries.extend(
+            ControlFile::ALL
+                .iter()
+                .enumerate()
+                .map(|(i, file)| (file.name().into(), self.attr(self.ino + 1 + i as u64))),
+        );
+        for (i, (name, attr)) in entries.into_iter().enumerate().skip(offset.max(0) as usize) {
+            let entry = DirectoryEntry {
+                ino: attr.ino,
+                offset: i as i64 + 1,
+                name,
+                attr,
+                generation: 0,
+                ttl: Duration::ZERO,
+                lookup: None,
+            };
+            if reply.add(entry).await {
+                break;
+            }
+        }
+        reply
+    }
+
+    /// Remember the contents of a newly opened file
+    fn open(&self, fh: u64, contents: String) {
+        self.handles.lock().unwrap().insert(fh, contents.into());
+    }
+
+    pub fn read(&self, fh: u64, offset: u64, size: usize) -> Result<Bytes, Error> {
+        let handles = self.handles.lock().unwrap();
+        let contents = handles
+            .get(&fh)
+            .ok_or_else(|| err!(libc::EBADF, "invalid file handle"))?;
+        let start = (offset as usize).min(contents.len());
+        let end = start.saturating_add(size).min(contents.len());
+        Ok(contents.slice(start..end))
+    }
+
+    /// Seek within the contents of an open file, which are all data
+    pub fn lseek(&self, fh: u64, offset: i64, whence: i32) -> Result<i64, Error> {
+        let handles = self.handles.lock().unwrap();
+        let contents = handles
+            .get(&fh)
+            .ok_or_else(|| err!(libc::EBADF, "invalid file handle"))?;
+        seek_data_or_hole(contents.len() as u64, offset, whence)
+    }
+
+    pub fn release(&self, fh: u64) {
+        self.handles.lock().unwrap().remove(&fh);
+    }
+}
+
+/// The parts of the control directory that need the rest of the file system, either to describe
+/// it or to share its file handle numbers
+impl<Client, Prefetcher> S3Filesystem<Client, Prefetcher>
+where
+    Client: ObjectClient + Send + Sync + 'static,
+    Prefetcher: Prefetch,
+{
+    /// The control directory, for routing requests to it
+    pub(crate) fn control(&self) -> &ControlDirectory {
+        &self.control
+    }
+
+    /// Open a file in the control directory, generating its contents
+    pub(crate) async fn open_control(&self, ino: InodeNo, flags: i32) -> Result<Opened, Error> {
+        let Some(ControlInode::File(file)) = self.control.inode(ino) else {
+            return Err(err!(libc::EISDIR, "can't open a directory as a file"));
+        };
+        if flags & (libc::O_WRONLY | libc::O_RDWR) != 0 {
+            return Err(self.control.read_only());
+        }
+
+        let contents = match file {
+            ControlFile::Config => self.config.mount_config.clone(),
+            ControlFile::Stats => render_stats(
+                self.session_state(),
+                self.file_handles.read().await.len(),
+                self.dir_handles.read().await.len(),
+                &self.accounting,
+            ),
+            ControlFile::Cache => render_cache(&self.superblock.cached_inodes()),
+            ControlFile::Uploads => {
+                let file_handles: Vec<_> = {
+                    let file_handles = self.file_handles.read().await;
+                    file_handles.iter().map(|(fh, handle)| (*fh, handle.clone())).collect()
+                };
+                let mut uploads = Vec::new();
+                for (fh, handle) in file_handles {
+                    if let FileHandleState::Write(UploadState::InProgress { request, .. }) = &*handle.state.lock().await
+                    {
+                        uploads.push(UploadSummary {
+                            fh,
+                            key: handle.full_key.clone(),
+                            size: request.size(),
+                            upload_id: request.upload_id().map(str::to_owned),
+                        });
+                    }
+                }
+                uploads.sort_by_key(|upload| upload.fh);
+                render_uploads(&uploads)
+            }
+        };
+
+        // Take handles from the same sequence as the bucket's, so that a handle is never valid for both
+        let fh = self.next_handle();
+        self.control.open(fh, contents);
+        // The files report a size of zero, so only direct I/O reads them to the end
+        Ok(Opened {
+            fh,
+            flags: FOPEN_DIRECT_IO,
+        })
+    }
+
+    /// Open the control directory
+    pub(crate) fn opendir_control(&self, ino: InodeNo) -> Result<Opened, Error> {
+        match self.control.inode(ino) {
+            Some(ControlInode::Directory) => Ok(Opened {
+                fh: self.next_handle(),
+                flags: 0,
+            }),
+            _ => Err(err!(libc::ENOTDIR, "not a directory")),
+        }
+    }
+}
+
+/// An upload that's in progress, for the `uploads` file
+#[derive(Debug)]
+struct UploadSummary {
+    pub fh: u64,
+    pub key: String,
+    /// Bytes written to the file so far
+    pub size: u64,
+    pub upload_id: Option<String>,
+}
+
+/// The contents of the `stats` file
+fn render_stats(session: SessionState, file_handles: usize, dir_handles: usize, accounting: &IoAccounting) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "file_handles {file_handles}");
+    let _ = writeln!(out, "dir_handles {dir_handles}");
+    let _ = writeln!(out, "\n# FUSE session negotiated with the kernel");
+    let _ = writeln!(out, "protocol_minor {}", session.protocol_minor);
+    let _ = writeln!(out, "readdirplus {}", session.readdirplus);
+    let _ = writeln!(out, "atomic_o_trunc {}", session.atomic_o_trunc);
+    let _ = writeln!(out, "lseek {}", session.lseek);
+    if !accounting.enabled() {
+        let _ = writeln!(out, "\n# I/O accounting is disabled");
+        return out;
+    }
+    for (heading, id, callers) in [
+        ("user", "uid", accounting.users()),
+        ("process", "pid", accounting.processes()),
+    ] {
+        let _ = writeln!(out, "\n# I/O by {heading}\n{id} requests bytes_read bytes_written");
+        for (caller, usage) in callers {
+            let _ = writeln!(
+                out,
+                "{caller} {} {} {}",
+                usage.requests, usage.bytes_read, usage.bytes_written
+            );
+        }
+    }
+    out
+}
+
+/// The contents of the `cache` file
+fn render_cache(inodes: &[CachedInode]) -> String {
+    let mut out = String::from("ino kind lookups ttl_secs key\n");
+    for inode in inodes {
+        let kind = match inode.kind {
+            InodeKind::File => "file",
+            InodeKind::Directory => "dir",
+            InodeKind::Symlink => "symlink",
+        };
+        let _ = writeln!(
+            out,
+            "{} {kind} {} {} {:?}",
+            inode.ino,
+            inode.lookup_count,
+            inode.validity.as_secs(),
+            inode.full_key
+        );
+    }
+    out
+}
+
+/// The contents of the `uploads` file
+fn render_uploads(uploads: &[UploadSummary]) -> String {
+    let mut out = String::from("fh size upload_id key\n");
+    for upload in uploads {
+        let _ = writeln!(
+            out,
+            "{} {} {} {:?}",
+            upload.fh,
+            upload.size,
+            upload.upload_id.as_deref().unwrap_or("-"),
+            upload.key
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+
+    #[test]
+    fn test_inodes() {
+        let control = ControlDirectory::new(ControlDirectoryMode::Hidden, 10, 0, 0);
+        assert_eq!(control.inode(9), None);
+        assert_eq!(control.inode(10), Some(ControlInode::Directory));
+        assert_eq!(control.inode(11), Some(ControlInode::File(ControlFile::Config)));
+        assert_eq!(control.inode(14), Some(ControlInode::File(ControlFile::Uploads)));
+        assert!(!control.contains(10 + ControlDirectory::INODE_COUNT));
+
+        assert!(control.contains_name(FUSE_ROOT_INODE, OsStr::new(".mountpoint")));
+        assert!(control.contains_name(10, OsStr::new("anything")));
+        assert!(!control.contains_name(FUSE_ROOT_INODE, OsStr::new("dir")));
+        assert!(!control.contains_name(2, OsStr::new(".mountpoint")));
+
+        let dir = control.lookup(FUSE_ROOT_INODE, OsStr::new(".mountpoint")).unwrap();
+        assert_eq!(dir.attr.ino, 10);
+        assert_eq!(dir.attr.kind, FileType::Directory);
+        assert_eq!(dir.attr.perm, 0o500);
+        let stats = control.lookup(10, OsStr::new("stats")).unwrap();
+        assert_eq!(stats.attr.ino, 12);
+        assert_eq!(stats.attr.kind, FileType::RegularFile);
+        assert_eq!(stats.attr.perm, 0o400);
+        let err = control.lookup(10, OsStr::new("missing")).unwrap_err();
+        assert_eq!(err.errno, libc::ENOENT);
+
+        let off = ControlDirectory::new(ControlDirectoryMode::Off, 10, 0, 0);
+        assert!(!off.contains(10));
+        assert!(!off.contains_name(FUSE_ROOT_INODE, OsStr::new(".mountpoint")));
+    }
+
+    #[test]
+    fn test_check_access() {
+        let control = ControlDirectory::new(ControlDirectoryMode::Hidden, 10, 1000, 1000);
+        let owner = Caller {
+            uid: 1000,
+            gid: 1000,
+            pid: 0,
+        };
+        let group = Caller {
+            uid: 1001,
+            gid: 1000,
+            pid: 0,
+        };
+        let root = Caller { uid: 0, gid: 0, pid: 0 };
+
+        let denied = |ino, caller, mask| control.check_access(ino, caller, mask).unwrap_err().errno == libc::EACCES;
+
+        control.check_access(10, owner, libc::R_OK | libc::X_OK).unwrap();
+        control.check_access(12, owner, libc::R_OK).unwrap();
+        control.check_access(12, root, libc::R_OK).unwrap();
+        assert!(denied(12, group, libc::R_OK));
+        assert!(denied(10, group, libc::X_OK));
+        // Nobody can write, not even root
+        assert!(denied(12, owner, libc::W_OK));
+        assert!(denied(10, root, libc::W_OK));
+    }
+
+    #[tokio::test]
+    async fn test_readdir() {
+        #[derive(Default)]
+        struct Reply {
+            names: Vec<OsString>,
+            limit: usize,
+        }
+
+        #[async_trait]
+        impl DirectoryReplier for Reply {
+            async fn add(&mut self, entry: DirectoryEntry) -> bool {
+                if self.names.len() == self.limit {
+                    return true;
+                }
+                self.names.push(entry.name);
+                false
+            }
+        }
+
+        let control = ControlDirectory::new(ControlDirectoryMode::Hidden, 10, 0, 0);
+        let root = control.attr(FUSE_ROOT_INODE);
+        let reply = control
+            .readdir(
+                0,
+                root,
+                Reply {
+                    limit: 3,
+                    ..Default::default()
+                },
+            )
+            .await;
+        assert_eq!(reply.names, [".", "..", "config"]);
+        let reply = control
+            .readdir(
+                3,
+                root,
+                Reply {
+                    limit: 10,
+                    ..Default::default()
+                },
+            )
+            .await;
+        assert_eq!(reply.names, ["stats", "cache", "uploads"]);
+    }
+
+    #[test]
+    fn test_read() {
+        let control = ControlDirectory::new(ControlDirectoryMode::Hidden, 10, 0, 0);
+        control.open(1, "hello world\n".to_owned());
+        assert_eq!(&control.read(1, 0, 5).unwrap()[..], b"hello");
+        assert_eq!(&control.read(1, 6, 4096).unwrap()[..], b"world\n");
+        assert!(control.read(1, 100, 10).unwrap().is_empty());
+        assert_eq!(control.lseek(1, 6, libc::SEEK_DATA).unwrap(), 6);
+        assert_eq!(control.lseek(1, 6, libc::SEEK_HOLE).unwrap(), 12);
+        assert_eq!(control.lseek(1, 12, libc::SEEK_DATA).unwrap_err().errno, libc::ENXIO);
+        control.release(1);
+        assert_eq!(control.read(1, 0, 5).unwrap_err().errno, libc::EBADF);
+    }
+
+    #[test]
+    fn test_render() {
+        let accounting = IoAccounting::new(true);
+        accounting.record(100, 1000, 10, 0);
+        let session = SessionState {
+            protocol_minor: 31,
+            readdirplus: true,
+            atomic_o_trunc: false,
+            lseek: true,
+        };
+        let stats = render_stats(session, 1, 2, &accounting);
+        assert!(stats.starts_with("file_handles 1\ndir_handles 2\n"));
+        assert!(stats.contains("protocol_minor 31\nreaddirplus true\natomic_o_trunc false\nlseek true\n"));
+        assert!(stats.contains("uid requests bytes_read bytes_written\n1000 1 10 0\n"));
+        assert!(stats.contains("pid requests bytes_read bytes_written\n100 1 10 0\n"));
+        assert!(render_stats(Default::default(), 0, 0, &IoAccounting::new(false)).contains("disabled"));
+
+        let cache = render_cache(&[CachedInode {
+            ino: 2,
+            kind: InodeKind::File,
+            full_key: "dir/a b".to_owned(),
+            lookup_count: 1,
+            validity: Duration::from_secs(60),
+        }]);
+        assert_eq!(cache, "ino kind lookups ttl_secs key\n2 file 1 60 \"dir/a b\"\n");
+
+        let uploads = render_uploads(&[UploadSummary {
+            fh: 5,
+            key: "dir/a".to_owned(),
+            size: 1024,
+            upload_id: None,
+        }]);
+        assert_eq!(uploads, "fh size upload_id key\n5 1024 - \"dir/a\"\n");
+    }
+}
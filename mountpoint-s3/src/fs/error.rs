@@ -1,9 +1,11 @@
 //! Utilities for handling errors generated by the `fs` module and mapping them to FUSE errors
 
+use mountpoint_s3_client::error::{GetObjectError, MultipartUploadError, ObjectClientError};
+use mountpoint_s3_client::{S3RequestError, S3RequestIds};
 use tracing::Level;
 
 use crate::inode::InodeError;
-use crate::upload::UploadWriteError;
+use crate::upload::{UploadAppendError, UploadWriteError};
 
 /// Generate an error that includes a conversion to a libc errno for use in replies to FUSE.
 ///
@@ -73,6 +75,17 @@ pub struct Error {
     pub(crate) level: Level,
 }
 
+impl Error {
+    /// The IDs of the failed S3 request that caused this error, if there was one. AWS Support needs
+    /// them to investigate why the request failed.
+    pub fn request_ids(&self) -> Option<S3RequestIds> {
+        let source = self.source.as_ref()?;
+        source
+            .chain()
+            .find_map(|error| error.downcast_ref::<S3RequestError>()?.request_ids())
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Some(source) = self.source.as_ref() {
@@ -128,6 +141,7 @@ impl ToErrno for InodeError {
             InodeError::FileDoesNotExist(_, _) => libc::ENOENT,
             InodeError::InodeDoesNotExist(_) => libc::ENOENT,
             InodeError::InvalidFileName(_) => libc::EINVAL,
+            InodeError::NameTooLong(_) => libc::ENAMETOOLONG,
             InodeError::NotADirectory(_) => libc::ENOTDIR,
             InodeError::IsDirectory(_) => libc::EISDIR,
             InodeError::FileAlreadyExists(_) => libc::EEXIST,
@@ -141,8 +155,15 @@ impl ToErrno for InodeError {
             InodeError::CannotRemoveRemoteDirectory(_) => libc::EPERM,
             InodeError::DirectoryNotEmpty(_) => libc::ENOTEMPTY,
             InodeError::UnlinkNotPermittedWhileWriting(_) => libc::EPERM,
+            InodeError::RenameNotPermittedWhileWriting(_) => libc::EPERM,
+            InodeError::CannotRenameDirectory(_) => libc::EPERM,
+            InodeError::NotASymlink(_) => libc::EINVAL,
+            InodeError::SymlinkTargetTooLong(_) => libc::ENAMETOOLONG,
             InodeError::CorruptedMetadata(_) => libc::EIO,
             InodeError::SetAttrNotPermittedOnRemoteInode(_) => libc::EPERM,
+            InodeError::NamespaceRootNotWritable(_) => libc::EPERM,
+            InodeError::CrossBucketRename(_) => libc::EXDEV,
+            InodeError::DirectoryInFlatNamespace(_) => libc::EPERM,
             InodeError::StaleInode { .. } => libc::ESTALE,
         }
     }
@@ -153,7 +174,48 @@ impl<E: std::error::Error> ToErrno for UploadWriteError<E> {
         match self {
             UploadWriteError::PutRequestFailed(_) => libc::EIO,
             UploadWriteError::OutOfOrderWrite { .. } => libc::EINVAL,
+            UploadWriteError::OverlapsBufferedWrite { .. } => libc::EINVAL,
             UploadWriteError::ObjectTooBig { .. } => libc::EFBIG,
         }
     }
 }
+
+impl<E: std::error::Error + Send + Sync + 'static> ToErrno for UploadAppendError<E> {
+    fn to_errno(&self) -> libc::c_int {
+        match self {
+            UploadAppendError::ObjectTooBig { .. } => libc::EFBIG,
+            // The object was replaced since we looked it up
+            UploadAppendError::GetFailed(ObjectClientError::ServiceError(GetObjectError::PreconditionFailed))
+            | UploadAppendError::MultipartUploadFailed(ObjectClientError::ServiceError(
+                MultipartUploadError::PreconditionFailed,
+            )) => libc::ESTALE,
+            _ => libc::EIO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    #[test]
+    fn test_request_ids() {
+        let request_ids = S3RequestIds {
+            request_id: "CM0R497NB0WAQ977".to_owned(),
+            extended_request_id: Some("w1TqUKGaIuNAIgzqm".to_owned()),
+        };
+        let client_error: ObjectClientError<GetObjectError, S3RequestError> = ObjectClientError::ClientError(
+            S3RequestError::Forbidden("Access Denied".to_owned(), Some(request_ids.clone())),
+        );
+
+        // The IDs are found however deep in the chain of sources the S3 error is
+        let inode_error = InodeError::ClientError(anyhow!(client_error).context("HeadObject failed"));
+        let error: Error = inode_error.into();
+        assert_eq!(error.request_ids(), Some(request_ids));
+
+        let error = err!(libc::EIO, "no source");
+        assert_eq!(error.request_ids(), None);
+    }
+}
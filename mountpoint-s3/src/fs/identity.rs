@@ -0,0 +1,261 @@
+//! Ownership and permissions for different parts of the bucket, checked against the user making
+//! each request.
+//!
+//! By default, every file and directory in the mount has the same owner and permissions, and any
+//! user who can reach the mount (with `--allow-other`) can do anything the mount allows. An
+//! [IdentityMap] gives different prefixes different owners and modes, and the file system checks
+//! the `uid` and `gid` in the header of each FUSE request against them, so users sharing a mount
+//! on a multi-user host can get different access to it.
+
+use serde::{Deserialize, Deserializer};
+
+/// A list of rules assigning ownership and permissions to keys.
+///
+/// The map is loaded from JSON of the form:
+///
+/// ```json
+/// {
+///     "rules": [
+///         { "prefix": "home/alice/", "uid": 1001, "gid": 1001, "dir_mode": "0750", "file_mode": "0640" },
+///         { "prefix": "shared/", "gid": 2000, "dir_mode": "0775", "file_mode": "0664" }
+///     ]
+/// }
+/// ```
+///
+/// A key matches a rule if it starts with the rule's prefix; if several rules match, the one with
+/// the longest prefix applies. Fields a rule leaves out, and keys that match no rule, use the
+/// mount's `--uid`, `--gid`, `--dir-mode`, and `--file-mode`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdentityMap {
+    rules: Vec<IdentityRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IdentityRule {
+    prefix: String,
+    #[serde(default)]
+    uid: Option<u32>,
+    #[serde(default)]
+    gid: Option<u32>,
+    #[serde(default, deserialize_with = "deserialize_mode")]
+    dir_mode: Option<u16>,
+    #[serde(default, deserialize_with = "deserialize_mode")]
+    file_mode: Option<u16>,
+}
+
+/// The owner and permissions of a file or directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Ownership {
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u16,
+}
+
+/// The user making a request, from the header of the FUSE request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Caller {
+    pub uid: u32,
+    pub gid: u32,
+    /// The thread that made the request, to look up its supplementary groups
+    pub pid: u32,
+}
+
+impl Caller {
+    /// Whether the caller is a member of the group, either as its primary group or one of its
+    /// supplementary groups. FUSE requests only carry the primary group, so the supplementary ones
+    /// are only looked up if the primary group doesn't match.
+    fn in_group(&self, gid: u32) -> bool {
+        self.gid == gid || supplementary_groups(self.pid).contains(&gid)
+    }
+}
+
+/// Get the supplementary groups of a thread from its task status.
+/// Returns an empty list if unable to find or parse the task status, for example because the
+/// thread has already exited.
+/// Not supported on macOS.
+fn supplementary_groups(pid: u32) -> Vec<u32> {
+    if cfg!(not(target_os = "macos")) {
+        use std::fs::File;
+        use std::io::{BufRead, BufReader};
+
+        let path = format!("/proc/{}/task/{}/status", pid, pid);
+        let Ok(file) = File::open(path) else {
+            return Vec::new();
+        };
+        for line in BufReader::new(file).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if let Some(groups) = line.strip_prefix("Groups:") {
+                return groups.split_whitespace().filter_map(|gid| gid.parse().ok()).collect();
+            }
+        }
+    }
+
+    Vec::new()
+}
+
+impl IdentityMap {
+    /// Parse a map from its JSON representation.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// The ownership of the file or directory with the given (full) key, starting from the
+    /// mount's default ownership.
+    pub(super) fn ownership(&self, key: &str, is_dir: bool, default: Ownership) -> Ownership {
+        let Some(rule) = self
+            .rules
+            .iter()
+            .filter(|rule| key.starts_with(&rule.prefix))
+            .max_by_key(|rule| rule.prefix.len())
+        else {
+            return default;
+        };
+        let mode = if is_dir { rule.dir_mode } else { rule.file_mode };
+        Ownership {
+            uid: rule.uid.unwrap_or(default.uid),
+            gid: rule.gid.unwrap_or(default.gid),
+            mode: mode.unwrap_or(default.mode),
+        }
+    }
+}
+
+impl Ownership {
+    /// Whether the caller has all of the access in `mask`, a combination of `R_OK`, `W_OK`, and
+    /// `X_OK`. Like the kernel, the caller's supplementary groups count as well as its primary
+    /// group, and root can read and write anything, and execute anything executable by anyone.
+    pub(super) fn allows(&self, caller: Caller, mask: i32) -> bool {
+        let mask = (mask & 0o7) as u16;
+        if caller.uid == 0 {
+            return mask & libc::X_OK as u16 == 0 || self.mode & 0o111 != 0;
+        }
+        let permitted = if caller.uid == self.uid {
+            self.mode >> 6
+        } else if caller.in_group(self.gid) {
+            self.mode >> 3
+        } else {
+            self.mode
+        } & 0o7;
+        mask & !permitted == 0
+    }
+}
+
+/// Modes are written as octal strings, like `"0755"`, since JSON doesn't have octal numbers.
+fn deserialize_mode<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u16>, D::Error> {
+    let Some(mode) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    match u16::from_str_radix(&mode, 8) {
+        Ok(mode) if mode <= 0o777 => Ok(Some(mode)),
+        _ => Err(serde::de::Error::custom(format!(
+            "invalid mode {mode:?}: must be an octal string like \"0755\""
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    const DEFAULT: Ownership = Ownership {
+        uid: 1000,
+        gid: 1000,
+        mode: 0o644,
+    };
+
+    #[test]
+    fn test_ownership() {
+        let map = IdentityMap::from_json(
+            r#"{ "rules": [
+                { "prefix": "home/alice/", "uid": 1001, "gid": 1001, "dir_mode": "0750", "file_mode": "0640" },
+                { "prefix": "home/alice/public/", "file_mode": "0644" },
+                { "prefix": "shared/", "gid": 2000 }
+            ] }"#,
+        )
+        .unwrap();
+
+        let alice = map.ownership("home/alice/notes.txt", false, DEFAULT);
+        assert_eq!(
+            alice,
+            Ownership {
+                uid: 1001,
+                gid: 1001,
+                mode: 0o640
+            }
+        );
+        let alice_dir = map.ownership("home/alice/", true, DEFAULT);
+        assert_eq!(alice_dir.mode, 0o750);
+
+        // The longest prefix wins, and fields it doesn't set come from the defaults, not the
+        // shorter rule
+        let public = map.ownership("home/alice/public/index.html", false, DEFAULT);
+        assert_eq!(public, DEFAULT);
+
+        let shared = map.ownership("shared/data.csv", false, DEFAULT);
+        assert_eq!(shared.gid, 2000);
+        assert_eq!(shared.uid, DEFAULT.uid);
+
+        assert_eq!(map.ownership("home/bob/notes.txt", false, DEFAULT), DEFAULT);
+    }
+
+    #[test_case(r#"{ "rules": [{ "prefix": "a/", "file_mode": "0999" }] }"#; "not octal")]
+    #[test_case(r#"{ "rules": [{ "prefix": "a/", "file_mode": "7777" }] }"#; "special bits")]
+    #[test_case(r#"{ "rules": [{ "prefix": "a/", "file_mode": 644 }] }"#; "number")]
+    #[test_case(r#"{ "rules": [{ "prefix": "a/", "owner": 1001 }] }"#; "unknown field")]
+    fn test_invalid_map(json: &str) {
+        IdentityMap::from_json(json).expect_err("map should be invalid");
+    }
+
+    #[test_case(1001, 1001, libc::R_OK | libc::W_OK, true; "owner read write")]
+    #[test_case(1002, 1001, libc::R_OK, true; "group read")]
+    #[test_case(1002, 1001, libc::W_OK, false; "group write")]
+    #[test_case(1003, 1003, libc::R_OK, false; "other read")]
+    #[test_case(0, 0, libc::R_OK | libc::W_OK, true; "root read write")]
+    #[test_case(0, 0, libc::X_OK, false; "root execute")]
+    fn test_allows(uid: u32, gid: u32, mask: i32, allowed: bool) {
+        let ownership = Ownership {
+            uid: 1001,
+            gid: 1001,
+            mode: 0o640,
+        };
+        assert_eq!(ownership.allows(Caller { uid, gid, pid: 0 }, mask), allowed);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_allows_supplementary_groups() {
+        let pid = std::process::id();
+        let mut groups = supplementary_groups(pid);
+        groups.sort();
+        let mut expected: Vec<u32> = nix::unistd::getgroups()
+            .unwrap()
+            .iter()
+            .map(|gid| gid.as_raw())
+            .collect();
+        expected.sort();
+        assert_eq!(groups, expected);
+
+        // A caller whose primary group doesn't match still gets group access through its
+        // supplementary groups
+        for gid in groups {
+            let ownership = Ownership {
+                uid: 1001,
+                gid,
+                mode: 0o640,
+            };
+            let caller = Caller {
+                uid: 1002,
+                gid: gid.wrapping_add(1),
+                pid,
+            };
+            assert!(ownership.allows(caller, libc::R_OK));
+            assert!(!ownership.allows(caller, libc::W_OK));
+        }
+        assert!(supplementary_groups(u32::MAX).is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+//! How much the kernel caches for the file system, beyond what Mountpoint caches itself.
+//!
+//! The kernel caches the attributes and directory entries we give it for as long as the TTL in
+//! each reply, and caches file contents in its page cache. By default it throws away a file's
+//! cached contents every time the file is opened, and never caches directory listings. Keeping
+//! them across opens is only safe while the object hasn't changed, so we remember what the kernel
+//! might have cached for each inode, and only let it keep that if it's still current.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use fuser::consts::{FOPEN_CACHE_DIR, FOPEN_DIRECT_IO, FOPEN_KEEP_CACHE};
+
+use super::InodeNo;
+use crate::sync::Mutex;
+
+/// How much the kernel is allowed to cache, from most to least consistent with S3
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KernelCacheMode {
+    /// Don't let the kernel cache attributes, directory entries, or file contents, so that every
+    /// system call reaches Mountpoint
+    None,
+    /// Let the kernel cache attributes and directory entries for the metadata TTL, and file
+    /// contents only while a file is open
+    #[default]
+    Attrs,
+    /// Also keep a file's contents in the page cache after it's closed, and reuse them the next
+    /// time it's opened if its object hasn't changed
+    Kernel,
+    /// Also cache directory listings and symbolic link targets, reusing listings for up to the
+    /// directory metadata TTL
+    Full,
+}
+
+impl KernelCacheMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KernelCacheMode::None => "none",
+            KernelCacheMode::Attrs => "attrs",
+            KernelCacheMode::Kernel => "kernel",
+            KernelCacheMode::Full => "full",
+        }
+    }
+
+    /// The FUSE capabilities to request from the kernel for this mode
+    pub(super) fn capabilities(&self) -> u32 {
+        match self {
+            KernelCacheMode::None | KernelCacheMode::Attrs => 0,
+            // Drop cached pages when the kernel sees a file's size or modification time change
+            KernelCacheMode::Kernel => fuser::consts::FUSE_AUTO_INVAL_DATA,
+            KernelCacheMode::Full => fuser::consts::FUSE_AUTO_INVAL_DATA | fuser::consts::FUSE_CACHE_SYMLINKS,
+        }
+    }
+}
+
+/// Tracks what the kernel might have cached for each inode, to decide what it can keep
+#[derive(Debug)]
+pub(super) struct KernelCache {
+    mode: KernelCacheMode,
+    /// The ETag of the object each file's cached contents were read from
+    files: Mutex<HashMap<InodeNo, String>>,
+    /// When the kernel started caching each directory's listing
+    dirs: Mutex<HashMap<InodeNo, Instant>>,
+}
+
+impl KernelCache {
+    pub fn new(mode: KernelCacheMode) -> Self {
+        Self {
+            mode,
+            files: Default::default(),
+            dirs: Default::default(),
+        }
+    }
+
+    pub fn mode(&self) -> KernelCacheMode {
+        self.mode
+    }
+
+    /// The TTL to give the kernel for attributes and directory entries that Mountpoint considers
+    /// valid for `ttl`
+    pub fn ttl(&self, ttl: Duration) -> Duration {
+        match self.mode {
+            KernelCacheMode::None => Duration::ZERO,
+            _ => ttl,
+        }
+    }
+
+    /// The flags to open a file with. `etag` is the ETag of the object the file will read, or
+    /// [None] if the file is being written or isn't in S3.
+    pub fn open_flags(&self, ino: InodeNo, etag: Option<&str>, direct_io: bool) -> u32 {
+        if self.mode == KernelCacheMode::None || direct_io {
+            self.files.lock().unwrap().remove(&ino);
+            return FOPEN_DIRECT_IO;
+        }
+        if self.mode < KernelCacheMode::Kernel {
+            return 0;
+        }
+
+        let mut files = self.files.lock().unwrap();
+        match etag {
+            Some(etag) if files.get(&ino).is_some_and(|cached| cached == etag) => {
+                metrics::counter!("kernel_cache.keep_cache").increment(1);
+                FOPEN_KEEP_CACHE
+            }
+            // Without FOPEN_KEEP_CACHE the kernel throws away whatever it has cached, and starts
+            // caching again from this object
+            Some(etag) => {
+                files.insert(ino, etag.to_owned());
+                0
+            }
+            None => {
+                files.remove(&ino);
+                0
+            }
+        }
+    }
+
+    /// The flags to open a directory with, given how long directory metadata is valid for
+    pub fn opendir_flags(&self, ino: InodeNo, dir_ttl: Duration) -> u32 {
+        if self.mode < KernelCacheMode::Full {
+            return 0;
+        }
+
+        let mut dirs = self.dirs.lock().unwrap();
+        match dirs.get(&ino) {
+            Some(cached) if cached.elapsed() < dir_ttl => FOPEN_CACHE_DIR | FOPEN_KEEP_CACHE,
+            _ => {
+                dirs.insert(ino, Instant::now());
+                FOPEN_CACHE_DIR
+            }
+        }
+    }
+
+    /// Forget what the kernel cached for an inode, because the kernel has forgotten the inode
+    pub fn forget(&self, ino: InodeNo) {
+        if self.mode < KernelCacheMode::Kernel {
+            return;
+        }
+        self.files.lock().unwrap().remove(&ino);
+        self.dirs.lock().unwrap().remove(&ino);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keep_cache_while_unchanged() {
+        let cache = KernelCache::new(KernelCacheMode::Kernel);
+        assert_eq!(cache.open_flags(2, Some("etag1"), false), 0);
+        assert_eq!(cache.open_flags(2, Some("etag1"), false), FOPEN_KEEP_CACHE);
+
+        // A new object invalidates the cached contents, and then they're kept again
+        assert_eq!(cache.open_flags(2, Some("etag2"), false), 0);
+        assert_eq!(cache.open_flags(2, Some("etag2"), false), FOPEN_KEEP_CACHE);
+
+        // Writing or opening with O_DIRECT changes the cached contents behind our back
+        assert_eq!(cache.open_flags(2, None, false), 0);
+        assert_eq!(cache.open_flags(2, Some("etag2"), false), 0);
+        assert_eq!(cache.open_flags(2, Some("etag2"), true), FOPEN_DIRECT_IO);
+        assert_eq!(cache.open_flags(2, Some("etag2"), false), 0);
+
+        // Once the kernel forgets the inode, it has nothing cached
+        cache.forget(2);
+        assert_eq!(cache.open_flags(2, Some("etag2"), false), 0);
+    }
+
+    #[test]
+    fn test_modes() {
+        let none = KernelCache::new(KernelCacheMode::None);
+        assert_eq!(none.ttl(Duration::from_secs(1)), Duration::ZERO);
+        assert_eq!(none.open_flags(2, Some("etag"), false), FOPEN_DIRECT_IO);
+        assert_eq!(none.opendir_flags(3, Duration::from_secs(1)), 0);
+
+        let attrs = KernelCache::new(KernelCacheMode::Attrs);
+        assert_eq!(attrs.ttl(Duration::from_secs(1)), Duration::from_secs(1));
+        attrs.open_flags(2, Some("etag"), false);
+        assert_eq!(attrs.open_flags(2, Some("etag"), false), 0);
+        assert_eq!(attrs.opendir_flags(3, Duration::from_secs(1)), 0);
+
+        let full = KernelCache::new(KernelCacheMode::Full);
+        assert_eq!(full.opendir_flags(3, Duration::from_secs(60)), FOPEN_CACHE_DIR);
+        assert_eq!(
+            full.opendir_flags(3, Duration::from_secs(60)),
+            FOPEN_CACHE_DIR | FOPEN_KEEP_CACHE
+        );
+        // Listings are never reused with a zero TTL
+        assert_eq!(full.opendir_flags(4, Duration::ZERO), FOPEN_CACHE_DIR);
+        assert_eq!(full.opendir_flags(4, Duration::ZERO), FOPEN_CACHE_DIR);
+    }
+}
@@ -0,0 +1,151 @@
+//! A local directory whose files shadow objects with the same path in the bucket.
+//!
+//! This lets users patch a handful of files in a large, otherwise read-only dataset without copying
+//! it or setting up a separate overlay file system. Only regular files are shadowed: directories in
+//! the overlay are used to find files but never appear in the file system themselves, and files
+//! that exist only in the overlay (with no object of the same name in the bucket) are not visible.
+
+use std::fs::{File, Metadata};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use bytes::{Bytes, BytesMut};
+use linked_hash_map::LinkedHashMap;
+
+use crate::sync::Mutex;
+
+/// How long to reuse the result of looking for a key in the overlay directory. We need to know
+/// whether a file is shadowed every time we build its attributes, and without this each of those
+/// would `stat` the overlay directory.
+const LOOKUP_TTL: Duration = Duration::from_secs(1);
+
+/// A local directory overlaid on top of the mounted bucket or prefix.
+#[derive(Debug, Clone)]
+pub struct OverlayDirectory {
+    root: PathBuf,
+}
+
+impl OverlayDirectory {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    /// Find the local file that shadows the object with the given key, relative to the mounted
+    /// prefix. Returns [None] if the overlay has no regular file at that path.
+    pub(super) fn lookup(&self, relative_key: &str) -> Option<OverlayFile> {
+        let relative_path = Path::new(relative_key);
+        // Keys are untrusted, so never follow anything that could escape the overlay directory.
+        if !relative_path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return None;
+        }
+        let path = self.root.join(relative_path);
+        let metadata = std::fs::metadata(&path).ok()?;
+        metadata.is_file().then_some(OverlayFile { path, metadata })
+    }
+}
+
+/// Looks up files in an [OverlayDirectory], reusing each result for [LOOKUP_TTL].
+#[derive(Debug)]
+pub(super) struct OverlayLookups {
+    directory: OverlayDirectory,
+    /// Recent lookups in the order they were made, which is also the order they expire in. Keys
+    /// that aren't shadowed are cached too, since they are the common case.
+    entries: Mutex<LinkedHashMap<String, CachedLookup>>,
+}
+
+#[derive(Debug)]
+struct CachedLookup {
+    inserted: Instant,
+    file: Option<OverlayFile>,
+}
+
+impl OverlayLookups {
+    pub fn new(directory: OverlayDirectory) -> Self {
+        Self {
+            directory,
+            entries: Mutex::new(Default::default()),
+        }
+    }
+
+    /// Find the local file that shadows the object with the given key, relative to the mounted
+    /// prefix. See [OverlayDirectory::lookup].
+    pub fn lookup(&self, relative_key: &str) -> Option<OverlayFile> {
+        if let Some(cached) = self.entries.lock().unwrap().get(relative_key) {
+            if cached.inserted.elapsed() < LOOKUP_TTL {
+                return cached.file.clone();
+            }
+        }
+
+        // Don't hold the lock while we look at the overlay directory
+        let file = self.directory.lookup(relative_key);
+
+        let mut entries = self.entries.lock().unwrap();
+        while entries
+            .front()
+            .is_some_and(|(_, cached)| cached.inserted.elapsed() >= LOOKUP_TTL)
+        {
+            entries.pop_front();
+        }
+        entries.remove(relative_key);
+        let cached = CachedLookup {
+            inserted: Instant::now(),
+            file: file.clone(),
+        };
+        entries.insert(relative_key.to_owned(), cached);
+        file
+    }
+}
+
+/// A regular file in an [OverlayDirectory].
+#[derive(Debug, Clone)]
+pub(super) struct OverlayFile {
+    path: PathBuf,
+    metadata: Metadata,
+}
+
+impl OverlayFile {
+    pub fn size(&self) -> u64 {
+        self.metadata.len()
+    }
+
+    pub fn mtime(&self) -> Option<SystemTime> {
+        self.metadata.modified().ok()
+    }
+
+    pub fn open(&self) -> io::Result<OverlayReader> {
+        let file = File::open(&self.path)?;
+        Ok(OverlayReader { file })
+    }
+}
+
+/// An open handle on an [OverlayFile] used to serve reads.
+#[derive(Debug)]
+pub(super) struct OverlayReader {
+    file: File,
+}
+
+impl OverlayReader {
+    pub fn read(&self, offset: u64, size: usize) -> io::Result<Bytes> {
+        let mut buffer = BytesMut::zeroed(size);
+        let mut total = 0;
+        while total < size {
+            match self.file.read_at(&mut buffer[total..], offset + total as u64) {
+                Ok(0) => break,
+                Ok(n) => total += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        buffer.truncate(total);
+        Ok(buffer.freeze())
+    }
+}
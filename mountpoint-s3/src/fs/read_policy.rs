@@ -0,0 +1,146 @@
+//! Restrictions on which objects, and which byte ranges of them, can be read through the mount.
+//!
+//! IAM policies can deny `GetObject` for some keys or byte ranges. Without knowing about these
+//! restrictions, Mountpoint would keep sending requests that S3 is going to deny, including
+//! prefetching requests the application never asked for.
+//! A [ReadPolicy] mirrors the restrictions locally so out-of-policy reads fail fast with `EACCES`,
+//! and so that prefetching never reads beyond what the policy allows.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Deserializer};
+
+/// A list of rules describing the reads the mount is allowed to perform.
+///
+/// The policy is loaded from JSON of the form:
+///
+/// ```json
+/// {
+///     "rules": [
+///         { "prefix": "datasets/public/" },
+///         { "prefix": "datasets/archive/", "range": { "start": 0, "end": 4096 } }
+///     ]
+/// }
+/// ```
+///
+/// A key matches a rule if it starts with the rule's prefix; if several rules match, the one with
+/// the longest prefix applies. A rule without a range allows reading the whole object. Keys that
+/// match no rule cannot be opened for reading.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReadPolicy {
+    rules: Vec<ReadPolicyRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReadPolicyRule {
+    prefix: String,
+    #[serde(default, deserialize_with = "deserialize_range")]
+    range: Option<Range<u64>>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ByteRange {
+    start: u64,
+    end: u64,
+}
+
+impl ReadPolicy {
+    /// Parse a policy from its JSON representation.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// The part of the object with the given (full) key and size that is allowed to be read, or
+    /// [None] if the object cannot be read at all.
+    pub(super) fn readable_range(&self, key: &str, object_size: u64) -> Option<ReadableRange> {
+        let rule = self
+            .rules
+            .iter()
+            .filter(|rule| key.starts_with(&rule.prefix))
+            .max_by_key(|rule| rule.prefix.len())?;
+        let range = rule.range.clone().unwrap_or(0..u64::MAX);
+        Some(ReadableRange { range, object_size })
+    }
+}
+
+/// The part of an object that a [ReadPolicy] allows reading.
+#[derive(Debug, Clone)]
+pub(super) struct ReadableRange {
+    range: Range<u64>,
+    object_size: u64,
+}
+
+impl ReadableRange {
+    /// Where reads of the object must stop, so that prefetching never goes beyond the policy. The
+    /// file reports this as its size.
+    pub fn end(&self) -> u64 {
+        self.range.end.min(self.object_size)
+    }
+
+    /// Whether a read starting at the given offset is allowed. Reads at or past the end of the
+    /// object are always allowed, as they don't need any requests to S3.
+    pub fn allows(&self, offset: u64) -> bool {
+        offset >= self.object_size || self.range.contains(&offset)
+    }
+}
+
+/// Ranges are written as `{ "start": 0, "end": 4096 }`, and must not be empty, since a rule that
+/// allows reading nothing is almost certainly a mistake.
+fn deserialize_range<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Range<u64>>, D::Error> {
+    let Some(ByteRange { start, end }) = Option::<ByteRange>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    if start >= end {
+        return Err(serde::de::Error::custom(format!(
+            "invalid range {start}..{end}: start must be less than end"
+        )));
+    }
+    Ok(Some(start..end))
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    const POLICY: &str = r#"{
+        "rules": [
+            { "prefix": "public/" },
+            { "prefix": "public/headers/", "range": { "start": 0, "end": 4096 } },
+            { "prefix": "tail", "range": { "start": 1024, "end": 2048 } }
+        ]
+    }"#;
+
+    #[test_case("public/file", 8192, &[0, 4096, 8191, 8192], &[]; "prefix without range")]
+    #[test_case("public/headers/file", 8192, &[0, 4095, 8192], &[4096, 8191]; "longest prefix wins")]
+    #[test_case("tail/file", 8192, &[1024, 2047], &[0, 1023, 2048]; "range not starting at zero")]
+    #[test_case("tail/file", 1500, &[1024, 1499, 1500, 2048], &[0]; "range past end of object")]
+    fn test_readable_range(key: &str, object_size: u64, allowed: &[u64], denied: &[u64]) {
+        let policy = ReadPolicy::from_json(POLICY).unwrap();
+        let readable = policy.readable_range(key, object_size).expect("key should be readable");
+        for offset in allowed {
+            assert!(readable.allows(*offset), "offset {offset} should be allowed");
+        }
+        for offset in denied {
+            assert!(!readable.allows(*offset), "offset {offset} should be denied");
+        }
+    }
+
+    #[test]
+    fn test_unreadable_key() {
+        let policy = ReadPolicy::from_json(POLICY).unwrap();
+        assert!(policy.readable_range("private/file", 8192).is_none());
+    }
+
+    #[test_case(r#"{ "rules": [ { "prefixes": "a/" } ] }"#; "unknown field")]
+    #[test_case(r#"{ "rules": [ { "prefix": "a/", "range": { "start": 4096, "end": 0 } } ] }"#; "reversed range")]
+    #[test_case(r#"{ "rules": [ { "prefix": "a/", "range": { "start": 16, "end": 16 } } ] }"#; "empty range")]
+    #[test_case(r#"{ "rules": [ { "prefix": "a/", "range": { "start": 0 } } ] }"#; "missing end")]
+    fn test_invalid_policy(json: &str) {
+        ReadPolicy::from_json(json).expect_err("policy should be invalid");
+    }
+}
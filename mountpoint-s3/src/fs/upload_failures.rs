@@ -0,0 +1,84 @@
+//! Uploads that failed after their file was closed.
+//!
+//! Most uploads complete when the application closes the file (`flush`), so the application sees
+//! the outcome. But some only complete at `release`, for example when the file descriptor was
+//! duplicated and the last `close` came from a different process. The kernel doesn't wait for
+//! `release`, so a failure there would otherwise go unnoticed. We remember these failures and
+//! report them as `EIO` from the next `flush` or `fsync` of the same file, like the kernel does for
+//! writeback errors on local file systems.
+
+use linked_hash_map::LinkedHashMap;
+
+use crate::sync::Mutex;
+
+/// How many failures to remember. Failures for files that are never opened again would otherwise
+/// accumulate forever, so we forget the oldest ones beyond this limit.
+const MAX_FAILURES: usize = 10_000;
+
+#[derive(Debug)]
+pub(super) struct UploadFailures {
+    /// Failed uploads by key, oldest first
+    failures: Mutex<LinkedHashMap<String, UploadFailure>>,
+}
+
+/// An upload that failed at `release`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct UploadFailure {
+    /// The ID of the multipart upload, if known
+    pub upload_id: Option<String>,
+}
+
+impl UploadFailures {
+    pub fn new() -> Self {
+        Self {
+            failures: Mutex::new(Default::default()),
+        }
+    }
+
+    /// Remember that the upload of `key` failed, replacing any earlier failure of the same key.
+    pub fn record(&self, key: &str, failure: UploadFailure) {
+        metrics::counter!("upload.release_failures").increment(1);
+        let mut failures = self.failures.lock().unwrap();
+        failures.remove(key);
+        failures.insert(key.to_owned(), failure);
+        while failures.len() > MAX_FAILURES {
+            failures.pop_front();
+        }
+    }
+
+    /// Take the failure recorded for `key`, if any, so that it's only reported once.
+    pub fn take(&self, key: &str) -> Option<UploadFailure> {
+        self.failures.lock().unwrap().remove(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_take() {
+        let failures = UploadFailures::new();
+        assert_eq!(failures.take("a"), None);
+
+        let failure = UploadFailure {
+            upload_id: Some("upload".to_owned()),
+        };
+        failures.record("a", failure.clone());
+        assert_eq!(failures.take("b"), None);
+        assert_eq!(failures.take("a"), Some(failure));
+        // Failures are only reported once
+        assert_eq!(failures.take("a"), None);
+    }
+
+    #[test]
+    fn test_oldest_failures_forgotten() {
+        let failures = UploadFailures::new();
+        for i in 0..=MAX_FAILURES {
+            failures.record(&format!("key{i}"), UploadFailure { upload_id: None });
+        }
+        assert_eq!(failures.take("key0"), None);
+        assert!(failures.take("key1").is_some());
+        assert!(failures.take(&format!("key{MAX_FAILURES}")).is_some());
+    }
+}
@@ -0,0 +1,153 @@
+//! Extended attributes that expose S3 object metadata.
+//!
+//! Each file backed by an S3 object has a few read-only attributes under the `user.mountpoint.`
+//! namespace, so that tools can see metadata like an object's storage class without their own S3
+//! client. Attributes are only present when we know their value: directories and files that
+//! haven't been uploaded yet have none, and objects don't report a storage class on every request.
+//!
+//! Other attributes in the `user.` namespace map to the object's user-defined metadata, so
+//! `user.color` is the `x-amz-meta-color` header.
+
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use mountpoint_s3_client::types::RestoreStatus;
+use time::macros::format_description;
+use time::OffsetDateTime;
+
+use crate::inode::InodeStat;
+
+/// The errno for an extended attribute that doesn't exist. Linux uses `ENODATA` rather than
+/// defining `ENOATTR`.
+#[cfg(target_os = "linux")]
+pub const ENOATTR: libc::c_int = libc::ENODATA;
+#[cfg(not(target_os = "linux"))]
+pub const ENOATTR: libc::c_int = libc::ENOATTR;
+
+/// Namespace of attributes that map to user-defined object metadata
+const USER_PREFIX: &str = "user.";
+/// Namespace of the read-only attributes below, inside [USER_PREFIX]
+pub const MOUNTPOINT_PREFIX: &str = "user.mountpoint.";
+
+/// S3 limits the total size of the keys and values of an object's user-defined metadata
+pub const MAX_USER_METADATA_SIZE: usize = 2 * 1024;
+
+/// The storage class of the object, like `STANDARD` or `GLACIER`
+pub const STORAGE_CLASS: &str = "user.mountpoint.storage_class";
+/// The ETag of the object
+pub const ETAG: &str = "user.mountpoint.etag";
+/// The status of a restore of the object from a flexible retrieval storage class, in the same
+/// format as the `x-amz-restore` header
+pub const RESTORE_STATUS: &str = "user.mountpoint.restore_status";
+
+/// Get the value of the named attribute for an object with the given stat, or `None` if it doesn't
+/// have that attribute.
+pub fn get(stat: &InodeStat, name: &str) -> Option<Vec<u8>> {
+    match name {
+        STORAGE_CLASS => stat.storage_class.as_ref().map(|class| class.as_bytes().to_vec()),
+        ETAG => stat.etag.as_ref().map(|etag| etag.as_bytes().to_vec()),
+        RESTORE_STATUS => stat
+            .restore_status
+            .map(|status| format_restore_status(status).into_bytes()),
+        _ => None,
+    }
+}
+
+/// List the names of the attributes an object with the given stat has, in the format expected by
+/// `listxattr`: each name followed by a NUL byte.
+pub fn list(stat: &InodeStat) -> Vec<u8> {
+    let mut names = Vec::new();
+    for name in [STORAGE_CLASS, ETAG, RESTORE_STATUS] {
+        if get(stat, name).is_some() {
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+    }
+    names
+}
+
+/// The user-defined metadata key an attribute name maps to, or `None` if the name isn't in the
+/// `user.` namespace or is one of our read-only attributes.
+pub fn user_metadata_key(name: &str) -> Option<&str> {
+    if name.starts_with(MOUNTPOINT_PREFIX) {
+        return None;
+    }
+    name.strip_prefix(USER_PREFIX)
+}
+
+/// Whether a user-defined metadata key can be sent as part of an `x-amz-meta-*` header name. S3
+/// stores keys in lowercase, so we only accept lowercase keys to make sure they read back the same.
+pub fn is_valid_user_metadata_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b"-_.".contains(&b))
+}
+
+/// The total size of user-defined metadata, as S3 counts it towards [MAX_USER_METADATA_SIZE]
+pub fn user_metadata_size(metadata: &HashMap<String, String>) -> usize {
+    metadata.iter().map(|(key, value)| key.len() + value.len()).sum()
+}
+
+/// List the attribute names for the given user-defined metadata, in the same format as [list].
+pub fn list_user_metadata(metadata: &HashMap<String, String>) -> Vec<u8> {
+    let mut keys = metadata.keys().collect::<Vec<_>>();
+    keys.sort();
+    let mut names = Vec::new();
+    for key in keys {
+        names.extend_from_slice(USER_PREFIX.as_bytes());
+        names.extend_from_slice(key.as_bytes());
+        names.push(0);
+    }
+    names
+}
+
+fn format_restore_status(status: RestoreStatus) -> String {
+    match status {
+        RestoreStatus::InProgress => r#"ongoing-request="true""#.to_owned(),
+        RestoreStatus::Restored { expiry } => {
+            format!(r#"ongoing-request="false", expiry-date="{}""#, format_http_date(expiry))
+        }
+    }
+}
+
+fn format_http_date(time: SystemTime) -> String {
+    let format =
+        format_description!("[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT");
+    OffsetDateTime::from(time)
+        .format(format)
+        .expect("HTTP date format is valid for any time")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_user_metadata_key() {
+        assert_eq!(user_metadata_key("user.color"), Some("color"));
+        assert_eq!(user_metadata_key("user.mountpoint.etag"), None);
+        assert_eq!(user_metadata_key("security.capability"), None);
+
+        assert!(is_valid_user_metadata_key("backup-tool.mtime_ns"));
+        assert!(!is_valid_user_metadata_key(""));
+        assert!(!is_valid_user_metadata_key("Color"));
+        assert!(!is_valid_user_metadata_key("a b"));
+    }
+
+    #[test]
+    fn test_restore_status_format() {
+        assert_eq!(
+            format_restore_status(RestoreStatus::InProgress),
+            r#"ongoing-request="true""#
+        );
+        // 2012-12-21 00:00:00 UTC, the example in the S3 documentation
+        let expiry = SystemTime::UNIX_EPOCH + Duration::from_secs(1356048000);
+        assert_eq!(
+            format_restore_status(RestoreStatus::Restored { expiry }),
+            r#"ongoing-request="false", expiry-date="Fri, 21 Dec 2012 00:00:00 GMT""#
+        );
+    }
+}
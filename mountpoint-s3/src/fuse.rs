@@ -1,24 +1,34 @@
 //! Links _fuser_ method calls into Mountpoint's filesystem code in [crate::fs].
 
+use async_trait::async_trait;
 use futures::executor::block_on;
+use futures::task::Spawn;
 use mountpoint_s3_client::ObjectClient;
 use std::ffi::OsStr;
+use std::future::Future;
+use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use time::OffsetDateTime;
-use tracing::{field, instrument, Instrument};
+use tracing::{error, field, instrument, Instrument};
 
-use crate::fs::{DirectoryEntry, DirectoryReplier, InodeNo, S3Filesystem, S3FilesystemConfig, ToErrno};
+use crate::fs::{
+    CacheInvalidator, Caller, ControlDirectory, DirectoryEntry, DirectoryReplier, Error, InodeNo, KernelFeatureError,
+    S3Filesystem, S3FilesystemConfig, ToErrno, ENOATTR, FUSE_ROOT_INODE,
+};
 use crate::prefetch::Prefetch;
 use crate::prefix::Prefix;
+use crate::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use crate::upload::IncompleteUploadCleaner;
 #[cfg(target_os = "macos")]
 use fuser::ReplyXTimes;
 use fuser::{
-    Filesystem, KernelConfig, ReplyAttr, ReplyBmap, ReplyCreate, ReplyData, ReplyEmpty, ReplyEntry, ReplyIoctl,
-    ReplyLock, ReplyLseek, ReplyOpen, ReplyWrite, ReplyXattr, Request, TimeOrNow,
+    fuse_forget_one, Filesystem, KernelConfig, KernelInit, ReplyAttr, ReplyBmap, ReplyCreate, ReplyData, ReplyEmpty,
+    ReplyEntry, ReplyIoctl, ReplyLock, ReplyLseek, ReplyOpen, ReplyStatfs, ReplyWrite, ReplyXattr, Request, TimeOrNow,
 };
 
 pub mod session;
+pub mod supervisor;
 
 /// `tracing` doesn't allow dynamic levels but we want to dynamically choose the log level for
 /// requests based on their response status. https://github.com/tokio-rs/tracing/issues/372
@@ -39,7 +49,12 @@ macro_rules! event {
 macro_rules! fuse_error {
     ($name:literal, $reply:expr, $err:expr) => {{
         let err = $err;
-        event!(err.level, "{} failed: {:#}", $name, err);
+        if let Some(request_ids) = err.request_ids() {
+            let extended_request_id = request_ids.extended_request_id.as_deref().unwrap_or("<unknown>");
+            event!(err.level, request_id = %request_ids.request_id, extended_request_id, "{} failed: {:#}", $name, err);
+        } else {
+            event!(err.level, "{} failed: {:#}", $name, err);
+        }
         ::metrics::counter!("fuse.op_failures", "op" => $name).increment(1);
         $reply.error(err.to_errno());
     }};
@@ -61,6 +76,52 @@ macro_rules! fuse_unsupported {
     };
 }
 
+/// Reply to `getxattr` or `listxattr`. A `size` of zero asks for the size of the value, and
+/// otherwise the value must fit in `size` bytes.
+fn reply_xattr(reply: ReplyXattr, value: &[u8], size: u32) {
+    if size == 0 {
+        reply.size(value.len() as u32);
+    } else if value.len() > size as usize {
+        reply.error(libc::ERANGE);
+    } else {
+        reply.data(value);
+    }
+}
+
+/// The user making a FUSE request, for checking permissions with [S3Filesystem::check_access]
+fn caller(req: &Request<'_>) -> Caller {
+    Caller {
+        uid: req.uid(),
+        gid: req.gid(),
+        pid: req.pid(),
+    }
+}
+
+/// Creating, removing, or renaming an entry needs write and search access to its directory
+const MODIFY_DIRECTORY: i32 = libc::W_OK | libc::X_OK;
+
+/// The access a file needs to allow for it to be opened with the given flags
+fn open_access_mask(flags: i32) -> i32 {
+    let mask = match flags & libc::O_ACCMODE {
+        libc::O_WRONLY => libc::W_OK,
+        libc::O_RDWR => libc::R_OK | libc::W_OK,
+        _ => libc::R_OK,
+    };
+    if flags & libc::O_TRUNC != 0 {
+        mask | libc::W_OK
+    } else {
+        mask
+    }
+}
+
+/// Which part of the file system serves a request
+enum Route<'a> {
+    /// The `.mountpoint` control directory, which isn't part of the bucket
+    Control(&'a ControlDirectory),
+    /// The bucket, which [S3Filesystem] serves
+    Bucket,
+}
+
 /// This is just a thin wrapper around [S3Filesystem] that implements the actual `fuser` protocol,
 /// so that we can test our actual filesystem implementation without having actual FUSE in the loop.
 pub struct S3FuseFilesystem<Client, Prefetcher>
@@ -69,6 +130,7 @@ where
     Prefetcher: Prefetch,
 {
     fs: S3Filesystem<Client, Prefetcher>,
+    init_sender: Option<SyncSender<Result<KernelInit, KernelFeatureError>>>,
 }
 
 impl<Client, Prefetcher> S3FuseFilesystem<Client, Prefetcher>
@@ -76,16 +138,93 @@ where
     Client: ObjectClient + Send + Sync + 'static,
     Prefetcher: Prefetch,
 {
-    pub fn new(
+    pub fn new<Runtime>(
         client: Client,
         prefetcher: Prefetcher,
+        runtime: Runtime,
         bucket: &str,
         prefix: &Prefix,
         config: S3FilesystemConfig,
-    ) -> Self {
-        let fs = S3Filesystem::new(client, prefetcher, bucket, prefix, config);
+    ) -> Self
+    where
+        Runtime: Spawn + Send + Sync + 'static,
+    {
+        let fs = S3Filesystem::new(client, prefetcher, runtime, bucket, prefix, config);
+
+        Self { fs, init_sender: None }
+    }
+
+    /// Returns a receiver that is sent the outcome of the session's initialization, once the
+    /// kernel has sent its `init` request. On success, that's the parameters of the request, which
+    /// a replacement session needs to resume serving the connection.
+    pub fn init_receiver(&mut self) -> Receiver<Result<KernelInit, KernelFeatureError>> {
+        let (sender, receiver) = sync_channel(1);
+        self.init_sender = Some(sender);
+        receiver
+    }
+
+    /// Create a [CacheInvalidator] for invalidating the cached metadata of keys that are changed in
+    /// S3 by someone other than this file system.
+    pub fn cache_invalidator(&self) -> CacheInvalidator {
+        self.fs.cache_invalidator()
+    }
 
-        Self { fs }
+    /// Create an [IncompleteUploadCleaner] for the multipart uploads this file system started, which
+    /// aborts the ones still incomplete after `max_age`.
+    pub fn incomplete_upload_cleaner(&self, max_age: Duration) -> IncompleteUploadCleaner<Client> {
+        self.fs.incomplete_upload_cleaner(max_age)
+    }
+
+    /// Route a request about an inode. This and [Self::route_name] are the only places that decide
+    /// whether a request is for the control directory, so that [S3Filesystem] only serves the bucket.
+    fn route(&self, ino: InodeNo) -> Route<'_> {
+        let control = self.fs.control();
+        if control.contains(ino) {
+            Route::Control(control)
+        } else {
+            Route::Bucket
+        }
+    }
+
+    /// Route a request about the entry `name` in the directory `parent`
+    fn route_name(&self, parent: InodeNo, name: &OsStr) -> Route<'_> {
+        let control = self.fs.control();
+        if control.contains_name(parent, name) {
+            Route::Control(control)
+        } else {
+            Route::Bucket
+        }
+    }
+
+    /// Check that the caller has all of the access in `mask` to an inode
+    async fn check_access(&self, ino: InodeNo, req: &Request<'_>, mask: i32) -> Result<(), Error> {
+        match self.route(ino) {
+            Route::Control(control) => control.check_access(ino, caller(req), mask),
+            Route::Bucket => self.fs.check_access(ino, caller(req), mask).await,
+        }
+    }
+
+    /// Check that the caller can create, remove, or rename the entry `name` in `parent`
+    async fn check_modify(&self, parent: InodeNo, name: &OsStr, req: &Request<'_>) -> Result<(), Error> {
+        match self.route_name(parent, name) {
+            Route::Control(control) => Err(control.read_only()),
+            Route::Bucket => self.fs.check_access(parent, caller(req), MODIFY_DIRECTORY).await,
+        }
+    }
+
+    /// Run a request's handler to completion, and attribute the request to the process and user
+    /// that made it. Reads and writes are attributed once they're done instead, along with the
+    /// bytes they transferred.
+    fn serve<F: Future>(&self, req: &Request<'_>, handler: F) -> F::Output {
+        self.account(req, 0, 0);
+        block_on(handler.in_current_span())
+    }
+
+    /// Attribute a request, and the bytes it read or wrote, to the process and user that made it
+    fn account(&self, req: &Request<'_>, bytes_read: usize, bytes_written: usize) {
+        self.fs
+            .accounting()
+            .record(req.pid(), req.uid(), bytes_read, bytes_written);
     }
 }
 
@@ -96,20 +235,40 @@ where
 {
     #[instrument(level="warn", skip_all, fields(req=_req.unique()))]
     fn init(&self, _req: &Request<'_>, config: &mut KernelConfig) -> Result<(), libc::c_int> {
-        block_on(self.fs.init(config).in_current_span())
+        let result = block_on(self.fs.init(config).in_current_span());
+        if let Err(e) = &result {
+            error!("init failed: {e}");
+        }
+        if let Some(sender) = &self.init_sender {
+            let _ = sender.try_send(result.clone().map(|()| config.kernel_init()));
+        }
+        result.map_err(|_| libc::ENOSYS)
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=parent, name=?name))]
-    fn lookup(&self, _req: &Request<'_>, parent: InodeNo, name: &OsStr, reply: ReplyEntry) {
-        match block_on(self.fs.lookup(parent, name).in_current_span()) {
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=parent, name=?name))]
+    fn lookup(&self, req: &Request<'_>, parent: InodeNo, name: &OsStr, reply: ReplyEntry) {
+        let lookup = async {
+            self.check_access(parent, req, libc::X_OK).await?;
+            match self.route_name(parent, name) {
+                Route::Control(control) => control.lookup(parent, name),
+                Route::Bucket => self.fs.lookup(parent, name).await,
+            }
+        };
+        match self.serve(req, lookup) {
             Ok(entry) => reply.entry(&entry.ttl, &entry.attr, entry.generation),
             Err(e) => fuse_error!("lookup", reply, e),
         }
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=ino, name=field::Empty))]
-    fn getattr(&self, _req: &Request<'_>, ino: InodeNo, reply: ReplyAttr) {
-        match block_on(self.fs.getattr(ino).in_current_span()) {
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino, name=field::Empty))]
+    fn getattr(&self, req: &Request<'_>, ino: InodeNo, reply: ReplyAttr) {
+        let getattr = async {
+            match self.route(ino) {
+                Route::Control(control) => Ok(control.getattr(ino)),
+                Route::Bucket => self.fs.getattr(ino).await,
+            }
+        };
+        match self.serve(req, getattr) {
             Ok(attr) => reply.attr(&attr.ttl, &attr.attr),
             Err(e) => fuse_error!("getattr", reply, e),
         }
@@ -117,21 +276,40 @@ where
 
     #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino, nlookup, name=field::Empty))]
     fn forget(&self, _req: &Request<'_>, ino: u64, nlookup: u64) {
-        block_on(self.fs.forget(ino, nlookup));
+        if let Route::Bucket = self.route(ino) {
+            block_on(self.fs.forget(ino, nlookup));
+        }
+    }
+
+    #[instrument(level="warn", skip_all, fields(req=_req.unique(), count=nodes.len()))]
+    fn batch_forget(&self, _req: &Request<'_>, nodes: &[fuse_forget_one]) {
+        let nodes: Vec<_> = nodes
+            .iter()
+            .filter(|node| matches!(self.route(node.nodeid), Route::Bucket))
+            .map(|node| (node.nodeid, node.nlookup))
+            .collect();
+        block_on(self.fs.forget_batch(&nodes));
     }
 
     #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino, pid=req.pid(), name=field::Empty))]
     fn open(&self, req: &Request<'_>, ino: InodeNo, flags: i32, reply: ReplyOpen) {
-        match block_on(self.fs.open(ino, flags, req.pid()).in_current_span()) {
+        let open = async {
+            self.check_access(ino, req, open_access_mask(flags)).await?;
+            match self.route(ino) {
+                Route::Control(_) => self.fs.open_control(ino, flags).await,
+                Route::Bucket => self.fs.open(ino, flags, req.pid()).await,
+            }
+        };
+        match self.serve(req, open) {
             Ok(opened) => reply.opened(opened.fh, opened.flags),
             Err(e) => fuse_error!("open", reply, e),
         }
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=ino, fh=fh, offset=offset, size=size, name=field::Empty))]
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino, fh=fh, offset=offset, size=size, name=field::Empty))]
     fn read(
         &self,
-        _req: &Request<'_>,
+        req: &Request<'_>,
         ino: InodeNo,
         fh: u64,
         offset: i64,
@@ -142,35 +320,51 @@ where
     ) {
         let mut bytes_sent = 0;
 
-        match block_on(self.fs.read(ino, fh, offset, size, flags, lock).in_current_span()) {
-            Ok(data) => {
-                bytes_sent = data.len();
-                reply.data(&data);
+        let read = async {
+            match self.route(ino) {
+                Route::Control(control) => control.read(fh, offset as u64, size as usize).map(|bytes| vec![bytes]),
+                Route::Bucket => self.fs.read_vectored(ino, fh, offset, size, flags, lock).await,
+            }
+        };
+        match block_on(read.in_current_span()) {
+            Ok(buffers) => {
+                bytes_sent = buffers.iter().map(|buffer| buffer.len()).sum();
+                let slices: Vec<&[u8]> = buffers.iter().map(|buffer| &buffer[..]).collect();
+                reply.data_vectored(&slices);
             }
             Err(err) => fuse_error!("read", reply, err),
         }
 
+        self.account(req, bytes_sent, 0);
         metrics::counter!("fuse.total_bytes", "type" => "read").increment(bytes_sent as u64);
         metrics::histogram!("fuse.io_size", "type" => "read").record(bytes_sent as f64);
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=parent, name=field::Empty))]
-    fn opendir(&self, _req: &Request<'_>, parent: InodeNo, flags: i32, reply: ReplyOpen) {
-        match block_on(self.fs.opendir(parent, flags).in_current_span()) {
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=parent, name=field::Empty))]
+    fn opendir(&self, req: &Request<'_>, parent: InodeNo, flags: i32, reply: ReplyOpen) {
+        let opendir = async {
+            self.check_access(parent, req, libc::R_OK).await?;
+            match self.route(parent) {
+                Route::Control(_) => self.fs.opendir_control(parent),
+                Route::Bucket => self.fs.opendir(parent, flags).await,
+            }
+        };
+        match self.serve(req, opendir) {
             Ok(opened) => reply.opened(opened.fh, opened.flags),
             Err(e) => fuse_error!("opendir", reply, e),
         }
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=parent, fh=fh, offset=offset))]
-    fn readdir(&self, _req: &Request<'_>, parent: InodeNo, fh: u64, offset: i64, mut reply: fuser::ReplyDirectory) {
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=parent, fh=fh, offset=offset))]
+    fn readdir(&self, req: &Request<'_>, parent: InodeNo, fh: u64, offset: i64, mut reply: fuser::ReplyDirectory) {
         struct ReplyDirectory<'a> {
             inner: &'a mut fuser::ReplyDirectory,
             count: &'a mut usize,
         }
 
+        #[async_trait]
         impl<'a> DirectoryReplier for ReplyDirectory<'a> {
-            fn add(&mut self, entry: DirectoryEntry) -> bool {
+            async fn add(&mut self, entry: DirectoryEntry) -> bool {
                 let result = self.inner.add(entry.ino, entry.offset, entry.attr.kind, entry.name);
                 if !result {
                     *self.count += 1;
@@ -185,7 +379,16 @@ where
             count: &mut count,
         };
 
-        match block_on(self.fs.readdir(parent, fh, offset, replier).in_current_span()) {
+        let readdir = async {
+            match self.route(parent) {
+                Route::Control(control) => {
+                    let root = self.fs.getattr(FUSE_ROOT_INODE).await?;
+                    Ok(control.readdir(offset, root.attr, replier).await)
+                }
+                Route::Bucket => self.fs.readdir(parent, fh, offset, replier).await,
+            }
+        };
+        match self.serve(req, readdir) {
             Ok(_) => {
                 reply.ok();
                 metrics::counter!("fuse.readdir.entries").increment(count as u64);
@@ -194,10 +397,10 @@ where
         }
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=parent, fh=fh, offset=offset))]
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=parent, fh=fh, offset=offset))]
     fn readdirplus(
         &self,
-        _req: &Request<'_>,
+        req: &Request<'_>,
         parent: InodeNo,
         fh: u64,
         offset: i64,
@@ -208,8 +411,9 @@ where
             count: &'a mut usize,
         }
 
+        #[async_trait]
         impl<'a> DirectoryReplier for ReplyDirectoryPlus<'a> {
-            fn add(&mut self, entry: DirectoryEntry) -> bool {
+            async fn add(&mut self, entry: DirectoryEntry) -> bool {
                 let result = self.inner.add(
                     entry.ino,
                     entry.offset,
@@ -231,7 +435,16 @@ where
             count: &mut count,
         };
 
-        match block_on(self.fs.readdirplus(parent, fh, offset, replier).in_current_span()) {
+        let readdirplus = async {
+            match self.route(parent) {
+                Route::Control(control) => {
+                    let root = self.fs.getattr(FUSE_ROOT_INODE).await?;
+                    Ok(control.readdir(offset, root.attr, replier).await)
+                }
+                Route::Bucket => self.fs.readdirplus(parent, fh, offset, replier).await,
+            }
+        };
+        match self.serve(req, readdirplus) {
             Ok(_) => {
                 reply.ok();
                 metrics::counter!("fuse.readdirplus.entries").increment(count as u64);
@@ -240,9 +453,15 @@ where
         }
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=ino, fh=fh, datasync=datasync, name=field::Empty))]
-    fn fsync(&self, _req: &Request<'_>, ino: u64, fh: u64, datasync: bool, reply: ReplyEmpty) {
-        match block_on(self.fs.fsync(ino, fh, datasync).in_current_span()) {
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino, fh=fh, datasync=datasync, name=field::Empty))]
+    fn fsync(&self, req: &Request<'_>, ino: u64, fh: u64, datasync: bool, reply: ReplyEmpty) {
+        let fsync = async {
+            match self.route(ino) {
+                Route::Control(_) => Ok(()),
+                Route::Bucket => self.fs.fsync(ino, fh, datasync).await,
+            }
+        };
+        match self.serve(req, fsync) {
             Ok(()) => reply.ok(),
             Err(e) => fuse_error!("fsync", reply, e),
         }
@@ -250,16 +469,22 @@ where
 
     #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino, fh=fh, pid=req.pid(), name=field::Empty))]
     fn flush(&self, req: &Request<'_>, ino: u64, fh: u64, lock_owner: u64, reply: ReplyEmpty) {
-        match block_on(self.fs.flush(ino, fh, lock_owner, req.pid()).in_current_span()) {
+        let flush = async {
+            match self.route(ino) {
+                Route::Control(_) => Ok(()),
+                Route::Bucket => self.fs.flush(ino, fh, lock_owner, req.pid()).await,
+            }
+        };
+        match self.serve(req, flush) {
             Ok(()) => reply.ok(),
             Err(e) => fuse_error!("flush", reply, e),
         }
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=ino, fh=fh, name=field::Empty))]
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino, fh=fh, name=field::Empty))]
     fn release(
         &self,
-        _req: &Request<'_>,
+        req: &Request<'_>,
         ino: InodeNo,
         fh: u64,
         flags: i32,
@@ -267,24 +492,39 @@ where
         flush: bool,
         reply: ReplyEmpty,
     ) {
-        match block_on(self.fs.release(ino, fh, flags, lock_owner, flush).in_current_span()) {
+        let release = async {
+            match self.route(ino) {
+                Route::Control(control) => {
+                    control.release(fh);
+                    Ok(())
+                }
+                Route::Bucket => self.fs.release(ino, fh, flags, lock_owner, flush).await,
+            }
+        };
+        match self.serve(req, release) {
             Ok(()) => reply.ok(),
             Err(e) => fuse_error!("release", reply, e),
         }
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=ino, fh=fh))]
-    fn releasedir(&self, _req: &Request<'_>, ino: u64, fh: u64, flags: i32, reply: ReplyEmpty) {
-        match block_on(self.fs.releasedir(ino, fh, flags).in_current_span()) {
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino, fh=fh))]
+    fn releasedir(&self, req: &Request<'_>, ino: u64, fh: u64, flags: i32, reply: ReplyEmpty) {
+        let releasedir = async {
+            match self.route(ino) {
+                Route::Control(_) => Ok(()),
+                Route::Bucket => self.fs.releasedir(ino, fh, flags).await,
+            }
+        };
+        match self.serve(req, releasedir) {
             Ok(()) => reply.ok(),
             Err(e) => fuse_error!("releasedir", reply, e),
         }
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), parent=parent, name=?name))]
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), parent=parent, name=?name))]
     fn mknod(
         &self,
-        _req: &Request<'_>,
+        req: &Request<'_>,
         parent: InodeNo,
         name: &OsStr,
         mode: u32,
@@ -295,27 +535,35 @@ where
         // mode_t is u32 on Linux but u16 on macOS, so cast it here
         let mode = mode as libc::mode_t;
 
-        match block_on(self.fs.mknod(parent, name, mode, umask, rdev).in_current_span()) {
+        let mknod = async {
+            self.check_modify(parent, name, req).await?;
+            self.fs.mknod(parent, name, mode, umask, rdev).await
+        };
+        match self.serve(req, mknod) {
             Ok(entry) => reply.entry(&entry.ttl, &entry.attr, entry.generation),
             Err(e) => fuse_error!("mknod", reply, e),
         }
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), parent=parent, name=?name))]
-    fn mkdir(&self, _req: &Request<'_>, parent: u64, name: &OsStr, mode: u32, umask: u32, reply: ReplyEntry) {
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), parent=parent, name=?name))]
+    fn mkdir(&self, req: &Request<'_>, parent: u64, name: &OsStr, mode: u32, umask: u32, reply: ReplyEntry) {
         // mode_t is u32 on Linux but u16 on macOS, so cast it here
         let mode = mode as libc::mode_t;
 
-        match block_on(self.fs.mkdir(parent, name, mode, umask).in_current_span()) {
+        let mkdir = async {
+            self.check_modify(parent, name, req).await?;
+            self.fs.mkdir(parent, name, mode, umask).await
+        };
+        match self.serve(req, mkdir) {
             Ok(entry) => reply.entry(&entry.ttl, &entry.attr, entry.generation),
             Err(e) => fuse_error!("mkdir", reply, e),
         }
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=ino, fh=fh, offset=offset, length=data.len(), pid=_req.pid(), name=field::Empty))]
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino, fh=fh, offset=offset, length=data.len(), pid=req.pid(), name=field::Empty))]
     fn write(
         &self,
-        _req: &Request<'_>,
+        req: &Request<'_>,
         ino: InodeNo,
         fh: u64,
         offset: i64,
@@ -325,40 +573,148 @@ where
         lock_owner: Option<u64>,
         reply: ReplyWrite,
     ) {
+        let mut bytes_accepted = 0;
+
         match block_on(
             self.fs
                 .write(ino, fh, offset, data, write_flags, flags, lock_owner)
                 .in_current_span(),
         ) {
             Ok(bytes_written) => {
+                bytes_accepted = bytes_written as usize;
                 reply.written(bytes_written);
                 metrics::counter!("fuse.total_bytes", "type" => "write").increment(bytes_written as u64);
                 metrics::histogram!("fuse.io_size", "type" => "write").record(bytes_written as f64);
             }
             Err(e) => fuse_error!("write", reply, e),
         }
+
+        self.account(req, 0, bytes_accepted);
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), parent=parent, name=?name))]
-    fn rmdir(&self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
-        match block_on(self.fs.rmdir(parent, name).in_current_span()) {
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), parent=parent, name=?name))]
+    fn rmdir(&self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let rmdir = async {
+            self.check_modify(parent, name, req).await?;
+            self.fs.rmdir(parent, name).await
+        };
+        match self.serve(req, rmdir) {
             Ok(()) => reply.ok(),
             Err(e) => fuse_error!("rmdir", reply, e),
         }
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), parent=parent, name=?name))]
-    fn unlink(&self, _req: &Request<'_>, parent: InodeNo, name: &OsStr, reply: ReplyEmpty) {
-        match block_on(self.fs.unlink(parent, name).in_current_span()) {
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), parent=parent, name=?name))]
+    fn unlink(&self, req: &Request<'_>, parent: InodeNo, name: &OsStr, reply: ReplyEmpty) {
+        let unlink = async {
+            self.check_modify(parent, name, req).await?;
+            self.fs.unlink(parent, name).await
+        };
+        match self.serve(req, unlink) {
             Ok(()) => reply.ok(),
             Err(e) => fuse_error!("unlink", reply, e),
         }
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=ino, name=field::Empty))]
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), parent=parent, name=?name, link=?link))]
+    fn symlink(&self, req: &Request<'_>, parent: u64, name: &OsStr, link: &Path, reply: ReplyEntry) {
+        let symlink = async {
+            self.check_modify(parent, name, req).await?;
+            self.fs.symlink(parent, name, link).await
+        };
+        match self.serve(req, symlink) {
+            Ok(entry) => reply.entry(&entry.ttl, &entry.attr, entry.generation),
+            Err(e) => fuse_error!("symlink", reply, e),
+        }
+    }
+
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino, name=field::Empty))]
+    fn readlink(&self, req: &Request<'_>, ino: InodeNo, reply: ReplyData) {
+        match self.serve(req, self.fs.readlink(ino)) {
+            Ok(target) => reply.data(target.as_bytes()),
+            Err(e) => fuse_error!("readlink", reply, e),
+        }
+    }
+
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino, name=?name))]
+    fn getxattr(&self, req: &Request<'_>, ino: InodeNo, name: &OsStr, size: u32, reply: ReplyXattr) {
+        let getxattr = async {
+            match self.route(ino) {
+                Route::Control(_) => Ok(None),
+                Route::Bucket => {
+                    self.check_access(ino, req, libc::R_OK).await?;
+                    self.fs.getxattr(ino, name).await
+                }
+            }
+        };
+        match self.serve(req, getxattr) {
+            Ok(Some(value)) => reply_xattr(reply, &value, size),
+            Ok(None) => reply.error(ENOATTR),
+            Err(e) => fuse_error!("getxattr", reply, e),
+        }
+    }
+
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino, name=?name))]
+    fn setxattr(
+        &self,
+        req: &Request<'_>,
+        ino: InodeNo,
+        name: &OsStr,
+        value: &[u8],
+        flags: i32,
+        _position: u32,
+        reply: ReplyEmpty,
+    ) {
+        let setxattr = async {
+            self.check_access(ino, req, libc::W_OK).await?;
+            self.fs.setxattr(ino, name, value, flags).await
+        };
+        match self.serve(req, setxattr) {
+            Ok(()) => reply.ok(),
+            Err(e) => fuse_error!("setxattr", reply, e),
+        }
+    }
+
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino))]
+    fn listxattr(&self, req: &Request<'_>, ino: InodeNo, size: u32, reply: ReplyXattr) {
+        let listxattr = async {
+            match self.route(ino) {
+                Route::Control(_) => Ok(Vec::new()),
+                Route::Bucket => self.fs.listxattr(ino).await,
+            }
+        };
+        match self.serve(req, listxattr) {
+            Ok(names) => reply_xattr(reply, &names, size),
+            Err(e) => fuse_error!("listxattr", reply, e),
+        }
+    }
+
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), parent=parent, name=?name, newparent=newparent, newname=?newname))]
+    fn rename(
+        &self,
+        req: &Request<'_>,
+        parent: InodeNo,
+        name: &OsStr,
+        newparent: InodeNo,
+        newname: &OsStr,
+        flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        let rename = async {
+            self.check_modify(parent, name, req).await?;
+            self.check_modify(newparent, newname, req).await?;
+            self.fs.rename(parent, name, newparent, newname, flags).await
+        };
+        match self.serve(req, rename) {
+            Ok(()) => reply.ok(),
+            Err(e) => fuse_error!("rename", reply, e),
+        }
+    }
+
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino, fh=fh, pid=req.pid(), name=field::Empty))]
     fn setattr(
         &self,
-        _req: &Request<'_>,
+        req: &Request<'_>,
         ino: u64,
         _mode: Option<u32>,
         _uid: Option<u32>,
@@ -367,7 +723,7 @@ where
         atime: Option<TimeOrNow>,
         mtime: Option<TimeOrNow>,
         _ctime: Option<SystemTime>,
-        _fh: Option<u64>,
+        fh: Option<u64>,
         _crtime: Option<SystemTime>,
         _chgtime: Option<SystemTime>,
         _bkuptime: Option<SystemTime>,
@@ -382,39 +738,71 @@ where
             TimeOrNow::SpecificTime(st) => OffsetDateTime::from(st),
             TimeOrNow::Now => OffsetDateTime::now_utc(),
         });
-        match block_on(self.fs.setattr(ino, atime, mtime, size, flags).in_current_span()) {
+        let setattr = async {
+            self.check_access(ino, req, libc::W_OK).await?;
+            self.fs.setattr(ino, atime, mtime, size, fh, flags, req.pid()).await
+        };
+        match self.serve(req, setattr) {
             Ok(attr) => reply.attr(&attr.ttl, &attr.attr),
             Err(e) => fuse_error!("setattr", reply, e),
         }
     }
 
-    // Everything below here is stubs for unsupported functions so we log them correctly
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino, fh=fh, offset=offset, whence=whence))]
+    fn lseek(&self, req: &Request<'_>, ino: u64, fh: u64, offset: i64, whence: i32, reply: ReplyLseek) {
+        let lseek = async {
+            match self.route(ino) {
+                Route::Control(control) => control.lseek(fh, offset, whence),
+                Route::Bucket => self.fs.lseek(ino, fh, offset, whence).await,
+            }
+        };
+        match self.serve(req, lseek) {
+            Ok(offset) => reply.offset(offset),
+            Err(e) => fuse_error!("lseek", reply, e),
+        }
+    }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=ino))]
-    fn readlink(&self, _req: &Request<'_>, ino: u64, reply: ReplyData) {
-        fuse_unsupported!("readlink", reply);
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino, fh=fh, offset=offset, length=length, mode=mode))]
+    fn fallocate(&self, req: &Request<'_>, ino: u64, fh: u64, offset: i64, length: i64, mode: i32, reply: ReplyEmpty) {
+        let fallocate = async {
+            match self.route(ino) {
+                Route::Control(control) => Err(control.read_only()),
+                Route::Bucket => self.fs.fallocate(ino, fh, offset, length, mode).await,
+            }
+        };
+        match self.serve(req, fallocate) {
+            Ok(()) => reply.ok(),
+            Err(e) => fuse_error!("fallocate", reply, e),
+        }
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), parent=parent, name=?name, link=?link))]
-    fn symlink(&self, _req: &Request<'_>, parent: u64, name: &OsStr, link: &Path, reply: ReplyEntry) {
-        // Userspace expects EPERM for link/symlink if unsupported
-        fuse_unsupported!("symlink", reply, libc::EPERM);
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino, mask=mask))]
+    fn access(&self, req: &Request<'_>, ino: u64, mask: i32, reply: ReplyEmpty) {
+        match self.serve(req, self.check_access(ino, req, mask)) {
+            Ok(()) => reply.ok(),
+            Err(e) => fuse_error!("access", reply, e),
+        }
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), parent=parent, name=?name, newparent=newparent, newname=?newname))]
-    fn rename(
-        &self,
-        _req: &Request<'_>,
-        parent: u64,
-        name: &OsStr,
-        newparent: u64,
-        newname: &OsStr,
-        _flags: u32,
-        reply: ReplyEmpty,
-    ) {
-        fuse_unsupported!("rename", reply);
+    #[instrument(level="warn", skip_all, fields(req=req.unique(), ino=ino))]
+    fn statfs(&self, req: &Request<'_>, ino: u64, reply: ReplyStatfs) {
+        match self.serve(req, self.fs.statfs(ino)) {
+            Ok(statfs) => reply.statfs(
+                statfs.total_blocks,
+                statfs.free_blocks,
+                statfs.available_blocks,
+                statfs.total_inodes,
+                statfs.free_inodes,
+                statfs.block_size,
+                statfs.maximum_name_length,
+                statfs.fragment_size,
+            ),
+            Err(e) => fuse_error!("statfs", reply, e),
+        }
     }
 
+    // Everything below here is stubs for unsupported functions so we log them correctly
+
     #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=ino, newparent=newparent, newname=?newname))]
     fn link(&self, _req: &Request<'_>, ino: u64, newparent: u64, newname: &OsStr, reply: ReplyEntry) {
         // Userspace expects EPERM for link/symlink if unsupported
@@ -426,40 +814,11 @@ where
         fuse_unsupported!("fsyncdir", reply);
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=ino, name=?name))]
-    fn setxattr(
-        &self,
-        _req: &Request<'_>,
-        ino: u64,
-        name: &OsStr,
-        _value: &[u8],
-        _flags: i32,
-        _position: u32,
-        reply: ReplyEmpty,
-    ) {
-        fuse_unsupported!("setxattr", reply);
-    }
-
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=ino, name=?name))]
-    fn getxattr(&self, _req: &Request<'_>, ino: u64, name: &OsStr, _size: u32, reply: ReplyXattr) {
-        fuse_unsupported!("getxattr", reply);
-    }
-
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=ino))]
-    fn listxattr(&self, _req: &Request<'_>, ino: u64, _size: u32, reply: ReplyXattr) {
-        fuse_unsupported!("listxattr", reply);
-    }
-
     #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=ino, name=?name))]
     fn removexattr(&self, _req: &Request<'_>, ino: u64, name: &OsStr, reply: ReplyEmpty) {
         fuse_unsupported!("removexattr", reply);
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=ino, mask=mask))]
-    fn access(&self, _req: &Request<'_>, ino: u64, mask: i32, reply: ReplyEmpty) {
-        fuse_unsupported!("access", reply);
-    }
-
     #[instrument(level="warn", skip_all, fields(req=_req.unique(), parent=parent, name=?name))]
     fn create(
         &self,
@@ -527,25 +886,6 @@ where
         fuse_unsupported!("ioctl", reply);
     }
 
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=ino, fh=fh, offset=offset, length=length))]
-    fn fallocate(
-        &self,
-        _req: &Request<'_>,
-        ino: u64,
-        fh: u64,
-        offset: i64,
-        length: i64,
-        _mode: i32,
-        reply: ReplyEmpty,
-    ) {
-        fuse_unsupported!("fallocate", reply);
-    }
-
-    #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino=ino, fh=fh, offset=offset, whence=whence))]
-    fn lseek(&self, _req: &Request<'_>, ino: u64, fh: u64, offset: i64, whence: i32, reply: ReplyLseek) {
-        fuse_unsupported!("lseek", reply);
-    }
-
     #[instrument(level="warn", skip_all, fields(req=_req.unique(), ino_in=ino_in, fh_in=fh_in, offset_in=offset_in, ino_out=ino_out, fh_out=fh_out, offset_out=offset_out, len=len))]
     fn copy_file_range(
         &self,
@@ -1,7 +1,7 @@
 use std::io;
 
 use anyhow::Context;
-use fuser::{Filesystem, Session, SessionUnmounter};
+use fuser::{Filesystem, Session, SessionEvent};
 use tracing::{debug, error, trace, warn};
 
 use crate::sync::atomic::{AtomicUsize, Ordering};
@@ -12,7 +12,7 @@ use crate::sync::Arc;
 /// A multi-threaded FUSE session that can be joined to wait for the FUSE filesystem to unmount or
 /// this process to be interrupted.
 pub struct FuseSession {
-    unmounter: SessionUnmounter,
+    unmounter: Unmounter,
     /// Waits for messages from threads or signal handler.
     receiver: mpsc::Receiver<Message>,
     /// List of closures or functions to call when session is exiting.
@@ -21,6 +21,9 @@ pub struct FuseSession {
 
 type OnClose = Box<dyn FnOnce()>;
 
+/// Unmounts the file system when the session closes
+type Unmounter = Box<dyn FnMut() -> io::Result<()>>;
+
 impl FuseSession {
     /// Create worker threads to dispatch requests for a FUSE session.
     pub fn new<FS: Filesystem + Send + Sync + 'static>(
@@ -29,7 +32,8 @@ impl FuseSession {
     ) -> anyhow::Result<Self> {
         assert!(max_worker_threads > 0);
 
-        let unmounter = session.unmount_callable();
+        session.set_event_callback(record_session_event);
+        let mut unmounter = session.unmount_callable();
 
         let (tx, rx) = mpsc::channel();
 
@@ -67,15 +71,42 @@ impl FuseSession {
                 .context("failed to spawn waiter thread")?
         };
 
-        ctrlc::set_handler(move || {
-            let _ = tx.send(Message::Interrupted);
-        })
-        .context("failed to set interrupt handler")?;
+        set_interrupt_handler(tx)?;
 
         WorkerPool::start(session, workers_tx, max_worker_threads).context("failed to start worker thread pool")?;
 
         Ok(Self {
-            unmounter,
+            unmounter: Box::new(move || unmounter.unmount()),
+            receiver: rx,
+            on_close: Default::default(),
+        })
+    }
+
+    /// Create a session for a file system that's served by some other means, like another process,
+    /// while `serve` runs on a new thread. The session ends when `serve` returns or this process is
+    /// interrupted, and `unmount` is called to unmount the file system.
+    pub fn with_server<S, U>(serve: S, unmount: U) -> anyhow::Result<Self>
+    where
+        S: FnOnce() + Send + 'static,
+        U: FnMut() -> io::Result<()> + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+
+        let _server = {
+            let tx = tx.clone();
+            thread::Builder::new()
+                .name("fuse-server".to_owned())
+                .spawn(move || {
+                    serve();
+                    let _ = tx.send(Message::WorkersExited);
+                })
+                .context("failed to spawn server thread")?
+        };
+
+        set_interrupt_handler(tx)?;
+
+        Ok(Self {
+            unmounter: Box::new(unmount),
             receiver: rx,
             on_close: Default::default(),
         })
@@ -88,20 +119,33 @@ impl FuseSession {
 
     /// Block until the file system is unmounted or this process is interrupted via SIGTERM/SIGINT.
     /// When that happens, unmount the file system (if it hasn't been already unmounted).
-    pub fn join(mut self) -> anyhow::Result<()> {
+    pub fn join(self) -> anyhow::Result<()> {
         let msg = self.receiver.recv();
         trace!("received message {msg:?}, closing filesystem session");
+        self.shutdown()
+    }
 
+    /// Close the session and unmount the file system without waiting for it to be unmounted or for
+    /// this process to be interrupted.
+    pub fn shutdown(mut self) -> anyhow::Result<()> {
         trace!("executing {} handler(s) on close", self.on_close.len());
         for handler in self.on_close {
             handler();
         }
 
         trace!("unmounting filesystem");
-        self.unmounter.unmount().context("failed to unmount FUSE session")
+        (self.unmounter)().context("failed to unmount FUSE session")
     }
 }
 
+/// Send [Message::Interrupted] on SIGINT or SIGTERM.
+fn set_interrupt_handler(tx: Sender<Message>) -> anyhow::Result<()> {
+    ctrlc::set_handler(move || {
+        let _ = tx.send(Message::Interrupted);
+    })
+    .context("failed to set interrupt handler")
+}
+
 #[derive(Debug)]
 enum Message {
     WorkersExited,
@@ -239,6 +283,26 @@ where
     }
 }
 
+/// Record statistics about the communication with the kernel, so that transport-level issues can be
+/// told apart from slow file system operations (which are measured by `fuse.op_latency_us`).
+fn record_session_event(event: SessionEvent) {
+    match event {
+        SessionEvent::RequestReceived { size } => {
+            metrics::counter!("fuse.session.requests_received").increment(1);
+            metrics::histogram!("fuse.session.request_size").record(size as f64);
+        }
+        SessionEvent::RequestParseFailed { .. } => {
+            metrics::counter!("fuse.session.parse_failures").increment(1);
+        }
+        SessionEvent::ReplySent { .. } => {
+            metrics::counter!("fuse.session.replies_sent").increment(1);
+        }
+        SessionEvent::ReplyFailed { errno } => {
+            metrics::counter!("fuse.session.reply_failures", "errno" => errno.to_string()).increment(1);
+        }
+    }
+}
+
 #[cfg(target_os = "linux")]
 fn get_thread_id_string() -> String {
     // SAFETY: this syscall is available since Linux 2.4.11 but glibc didn't
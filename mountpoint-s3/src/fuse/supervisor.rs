@@ -0,0 +1,388 @@
+//! Recovering from crashes without unmounting the file system.
+//!
+//! With `--restart-on-crash`, the process that mounts the file system doesn't serve it. Instead it
+//! becomes a supervisor that holds the mount's FUSE device open, and starts a worker process (a new
+//! copy of `mount-s3` with the same arguments) to serve the file system on a clone of the device.
+//! If the worker crashes, the kernel fails the requests it was in the middle of, but the connection
+//! isn't aborted because the supervisor still holds it, and so the supervisor can start a new worker
+//! to take over. Without a supervisor, a crash leaves behind a mount that fails every request with
+//! "Transport endpoint is not connected" until it's unmounted.
+//!
+//! The kernel only sends its `init` request once per connection, so the first worker reports the
+//! request's parameters to the supervisor, and later workers replay them instead.
+//!
+//! Workers don't share any state, so a new worker starts with empty caches, and file handles that
+//! were open in a crashed worker can't be used any more. The kernel still has the inode numbers the
+//! crashed worker gave it, though, and keeps using them for the names it has cached. If a new worker
+//! numbered its inodes from scratch, those numbers would refer to whatever it happened to look up
+//! first, and reads through a cached name could return another file's data. So workers need
+//! `--stable-inode-numbers`, which gives every key the same number in every worker.
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context as _};
+use fuser::{DeviceMount, KernelInit, MountOption};
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+use tracing::{error, info, warn};
+
+use crate::daemon;
+use crate::fuse::session::FuseSession;
+use crate::sync::atomic::{AtomicBool, Ordering};
+use crate::sync::{Arc, Mutex};
+
+/// Environment variable that tells a worker which file descriptors it inherited from its supervisor
+const WORKER_ENV: &str = "MOUNTPOINT_S3_SUPERVISED_WORKER";
+
+/// A worker that crashes sooner than this after starting counts as a quick failure
+const MIN_WORKER_UPTIME: Duration = Duration::from_secs(10);
+
+/// Give up after this many quick failures in a row, rather than restarting a worker that can't stay up
+const MAX_QUICK_FAILURES: u32 = 5;
+
+/// How long to wait before restarting a crashed worker
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Mount the file system and serve it from a worker process, restarting the worker whenever it
+/// crashes. Returns once the first worker has initialized the file system. The returned session
+/// ends when the worker exits normally (for example, because the file system was unmounted), or
+/// after the worker keeps crashing as soon as it starts.
+pub fn start(mount_point: &Path, options: &[MountOption], foreground: bool) -> anyhow::Result<FuseSession> {
+    let mount = DeviceMount::new(mount_point, options).context("Failed to create FUSE mount")?;
+    let mut worker = Worker::spawn(&mount, None, foreground)?;
+    let kernel_init = match worker.wait_for_init() {
+        Ok(kernel_init) => kernel_init,
+        Err(e) => {
+            worker.kill();
+            return Err(e).context("file system worker failed to start");
+        }
+    };
+    info!(pid = worker.child.id(), "started file system worker");
+
+    let mount = Arc::new(Mutex::new(mount));
+    let stopping = Arc::new(AtomicBool::new(false));
+    let worker_pid = Arc::new(Mutex::new(Some(worker.pid())));
+    let supervisor = Supervisor {
+        mount: mount.clone(),
+        kernel_init,
+        foreground,
+        stopping: stopping.clone(),
+        worker_pid: worker_pid.clone(),
+    };
+
+    let mut session = FuseSession::with_server(
+        move || supervisor.run(worker),
+        move || {
+            mount.lock().unwrap().unmount();
+            Ok(())
+        },
+    )?;
+    session.run_on_close(Box::new(move || {
+        // Stop restarting workers, and stop the current one before the file system is unmounted
+        stopping.store(true, Ordering::SeqCst);
+        if let Some(pid) = *worker_pid.lock().unwrap() {
+            if let Err(e) = signal::kill(pid, Signal::SIGTERM) {
+                warn!("failed to stop file system worker: {e:?}");
+            }
+        }
+    }));
+    Ok(session)
+}
+
+/// Restarts workers when they crash
+struct Supervisor {
+    mount: Arc<Mutex<DeviceMount>>,
+    kernel_init: KernelInit,
+    foreground: bool,
+    stopping: Arc<AtomicBool>,
+    worker_pid: Arc<Mutex<Option<Pid>>>,
+}
+
+impl Supervisor {
+    /// Wait for `worker` to exit, and restart it if it crashed, until a worker exits normally or
+    /// we're stopped.
+    fn run(self, mut worker: Worker) {
+        let mut quick_failures = 0;
+        loop {
+            let status = match worker.child.wait() {
+                Ok(status) => status,
+                Err(e) => {
+                    error!("failed to wait for file system worker: {e:?}");
+                    return;
+                }
+            };
+            *self.worker_pid.lock().unwrap() = None;
+            if status.success() || self.stopping.load(Ordering::SeqCst) {
+                info!(%status, "file system worker exited");
+                return;
+            }
+
+            metrics::counter!("supervisor.worker_crashes").increment(1);
+            if worker.started.elapsed() < MIN_WORKER_UPTIME {
+                quick_failures += 1;
+            } else {
+                quick_failures = 1;
+            }
+            if quick_failures >= MAX_QUICK_FAILURES {
+                error!(%status, "file system worker crashed {quick_failures} times in a row, giving up");
+                return;
+            }
+            error!(%status, "file system worker crashed, restarting it");
+            std::thread::sleep(RESTART_DELAY);
+
+            worker = match self.restart() {
+                Ok(worker) => worker,
+                Err(e) => {
+                    error!("failed to restart file system worker: {e:?}");
+                    return;
+                }
+            };
+        }
+    }
+
+    /// Start a new worker that resumes serving the connection
+    fn restart(&self) -> anyhow::Result<Worker> {
+        let mut worker = Worker::spawn(&self.mount.lock().unwrap(), Some(self.kernel_init), self.foreground)?;
+        *self.worker_pid.lock().unwrap() = Some(worker.pid());
+        // We might have been stopped while starting the worker, too late for the stop handler to see it
+        if self.stopping.load(Ordering::SeqCst) {
+            worker.kill();
+            return Ok(worker);
+        }
+        // If the worker fails to initialize, it exits and we'll see that when we wait for it
+        match worker.wait_for_init() {
+            Ok(_) => info!(pid = worker.child.id(), "restarted file system worker"),
+            Err(e) => warn!("restarted file system worker failed to initialize: {e:?}"),
+        }
+        Ok(worker)
+    }
+}
+
+/// A worker process, as seen by its supervisor
+struct Worker {
+    child: Child,
+    /// Receives the parameters of the kernel's `init` request once the worker has initialized
+    status: BufReader<UnixStream>,
+    started: Instant,
+}
+
+impl Worker {
+    /// Start a worker process that serves the file system on a clone of `mount`'s device
+    fn spawn(mount: &DeviceMount, resume: Option<KernelInit>, foreground: bool) -> anyhow::Result<Self> {
+        let device = mount.clone_device().context("failed to clone FUSE device")?;
+        let (status_reader, status_writer) = UnixStream::pair().context("failed to create status socket")?;
+        let device_fd = device.as_raw_fd();
+        let status_fd = status_writer.as_raw_fd();
+
+        // Run the same program with the same arguments, including the program name, since that
+        // affects how we parse the arguments when we're used as a `mount` helper
+        let mut args = env::args_os();
+        let mut command = Command::new(env::current_exe().context("failed to find current executable")?);
+        if let Some(program) = args.next() {
+            command.arg0(program);
+        }
+        command
+            .args(args)
+            .env(WORKER_ENV, format_worker_env(device_fd, status_fd, resume));
+        // Only the supervisor talks to the service manager
+        for var in daemon::SERVICE_MANAGER_ENV {
+            command.env_remove(var);
+        }
+        if !foreground {
+            command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+        }
+        // SAFETY: The closure only makes async-signal-safe system calls.
+        unsafe {
+            command.pre_exec(move || {
+                inherit_fd(device_fd)?;
+                inherit_fd(status_fd)?;
+                set_parent_death_signal()
+            });
+        }
+        let child = command.spawn().context("failed to start file system worker")?;
+
+        // The worker has its own copies of the device and the status socket now
+        drop(device);
+        drop(status_writer);
+        Ok(Self {
+            child,
+            status: BufReader::new(status_reader),
+            started: Instant::now(),
+        })
+    }
+
+    fn pid(&self) -> Pid {
+        Pid::from_raw(self.child.id() as i32)
+    }
+
+    /// Wait for the worker to report that it has initialized the file system
+    fn wait_for_init(&mut self) -> anyhow::Result<KernelInit> {
+        let mut line = String::new();
+        self.status
+            .read_line(&mut line)
+            .context("failed to read status from file system worker")?;
+        if line.is_empty() {
+            return Err(anyhow!("file system worker exited before initializing the file system"));
+        }
+        parse_kernel_init(line.trim_end()).ok_or_else(|| anyhow!("invalid status from file system worker: {line:?}"))
+    }
+
+    fn kill(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The connection to the kernel that a supervisor handed to this worker process
+#[derive(Debug)]
+pub struct WorkerConnection {
+    /// A clone of the supervisor's FUSE device
+    pub device: File,
+    /// The parameters of the kernel's `init` request, if an earlier worker already initialized the
+    /// connection
+    pub resume: Option<KernelInit>,
+    /// Reports back to the supervisor once the file system is initialized
+    pub status: WorkerStatus,
+}
+
+impl WorkerConnection {
+    /// If this process is a worker started by a supervisor, take ownership of the connection the
+    /// supervisor gave it. Must be called at most once.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(value) = env::var_os(WORKER_ENV) else {
+            return Ok(None);
+        };
+        let (device_fd, status_fd, resume) = value
+            .to_str()
+            .and_then(parse_worker_env)
+            .ok_or_else(|| anyhow!("invalid {WORKER_ENV}: {value:?}"))?;
+        // SAFETY: Our supervisor opened these file descriptors for us, and nothing else in this
+        // process owns them.
+        let (device, status) = unsafe { (File::from_raw_fd(device_fd), UnixStream::from_raw_fd(status_fd)) };
+        Ok(Some(Self {
+            device,
+            resume,
+            status: WorkerStatus(status),
+        }))
+    }
+}
+
+/// Reports a worker's status to its supervisor
+#[derive(Debug)]
+pub struct WorkerStatus(UnixStream);
+
+impl WorkerStatus {
+    /// Tell the supervisor that the file system is initialized, with the parameters of the kernel's
+    /// `init` request for later workers to resume from.
+    pub fn report_init(mut self, kernel_init: KernelInit) -> io::Result<()> {
+        writeln!(self.0, "{}", format_kernel_init(&kernel_init))
+    }
+}
+
+/// Make panics abort the process, rather than just ending the thread that panicked, so that our
+/// supervisor restarts us instead of leaving us running with some requests never answered.
+pub fn abort_on_panic() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        error!("file system worker panicked: {info}");
+        default_hook(info);
+        std::process::abort();
+    }));
+}
+
+/// Clear `FD_CLOEXEC` on `fd` so that it's inherited by the program we're about to execute
+fn inherit_fd(fd: RawFd) -> io::Result<()> {
+    // SAFETY: `fcntl` doesn't access memory, and fails with EBADF if `fd` isn't open.
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFD);
+        if flags < 0 || libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Ask the kernel to terminate a worker if its supervisor dies, so it doesn't outlive the mount
+fn set_parent_death_signal() -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        // SAFETY: `PR_SET_PDEATHSIG` only takes a signal number.
+        if unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn format_worker_env(device_fd: RawFd, status_fd: RawFd, resume: Option<KernelInit>) -> String {
+    match resume {
+        Some(kernel_init) => format!("{device_fd},{status_fd},{}", format_kernel_init(&kernel_init)),
+        None => format!("{device_fd},{status_fd}"),
+    }
+}
+
+fn parse_worker_env(value: &str) -> Option<(RawFd, RawFd, Option<KernelInit>)> {
+    let mut parts = value.splitn(3, ',');
+    let device_fd = parts.next()?.parse().ok()?;
+    let status_fd = parts.next()?.parse().ok()?;
+    let resume = match parts.next() {
+        Some(kernel_init) => Some(parse_kernel_init(kernel_init)?),
+        None => None,
+    };
+    Some((device_fd, status_fd, resume))
+}
+
+fn format_kernel_init(kernel_init: &KernelInit) -> String {
+    format!(
+        "{},{},{},{}",
+        kernel_init.major, kernel_init.minor, kernel_init.max_readahead, kernel_init.flags
+    )
+}
+
+fn parse_kernel_init(value: &str) -> Option<KernelInit> {
+    let fields: Vec<u32> = value
+        .split(',')
+        .map(|field| field.parse().ok())
+        .collect::<Option<_>>()?;
+    let [major, minor, max_readahead, flags] = fields[..] else {
+        return None;
+    };
+    Some(KernelInit {
+        major,
+        minor,
+        max_readahead,
+        flags,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_worker_env() {
+        assert_eq!(parse_worker_env(&format_worker_env(5, 7, None)), Some((5, 7, None)));
+
+        let kernel_init = KernelInit {
+            major: 7,
+            minor: 31,
+            max_readahead: 131072,
+            flags: 0x73fffffb,
+        };
+        let value = format_worker_env(5, 7, Some(kernel_init));
+        assert_eq!(value, "5,7,7,31,131072,1946157051");
+        assert_eq!(parse_worker_env(&value), Some((5, 7, Some(kernel_init))));
+
+        assert_eq!(parse_worker_env("5"), None);
+        assert_eq!(parse_worker_env("5,7,7,31"), None);
+        assert_eq!(parse_worker_env("five,7"), None);
+    }
+}
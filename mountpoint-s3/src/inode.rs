@@ -24,45 +24,69 @@
 use std::collections::{HashMap, HashSet};
 use std::ffi::{OsStr, OsString};
 use std::fmt::{Debug, Display};
-use std::os::unix::prelude::OsStrExt;
+use std::ops::Range;
+use std::os::unix::prelude::{OsStrExt, OsStringExt};
 use std::sync::atomic::AtomicBool;
 use std::time::{Duration, SystemTime};
 
 use anyhow::anyhow;
 use fuser::FileType;
+use futures::future::OptionFuture;
 use futures::{select_biased, FutureExt};
-use mountpoint_s3_client::error::{HeadObjectError, ObjectClientError};
-use mountpoint_s3_client::types::{HeadObjectResult, RestoreStatus};
-use mountpoint_s3_client::ObjectClient;
+use mountpoint_s3_client::error::{CopyObjectError, HeadObjectError, ObjectClientError};
+use mountpoint_s3_client::types::{CopyObjectParams, HeadObjectResult, PutObjectParams, RestoreStatus};
+use mountpoint_s3_client::{ObjectClient, PutObjectRequest};
 use mountpoint_s3_crt::checksums::crc32c::{self, Crc32c};
+use percent_encoding::{percent_decode_str, percent_encode, AsciiSet, CONTROLS};
 use thiserror::Error;
 use time::OffsetDateTime;
 use tracing::{debug, error, trace, warn};
 
-use crate::fs::CacheConfig;
+use crate::fs::{CacheConfig, DeletePolicy, ObjectVersionPolicy};
 use crate::logging;
 use crate::prefix::Prefix;
 use crate::s3::S3Personality;
 use crate::sync::atomic::{AtomicU64, Ordering};
 use crate::sync::RwLockReadGuard;
 use crate::sync::RwLockWriteGuard;
-use crate::sync::{Arc, RwLock};
+use crate::sync::{Arc, Mutex, RwLock};
 
 mod expiry;
 use expiry::Expiry;
 
+mod flat;
+
+mod forgotten;
+use forgotten::ForgottenInodes;
+
+mod invalidation;
+pub use invalidation::{CacheInvalidator, KernelInvalidation};
+
+mod namespace;
+pub use namespace::{CompositeNamespace, NamespaceEntry, NamespaceError};
+
 mod negative_cache;
 use negative_cache::NegativeCache;
 
 mod readdir;
 pub use readdir::ReaddirHandle;
 
+mod stable_ino;
+use stable_ino::StableInodeNumbers;
+
+mod versions;
+use versions::{prefix_existed_at, VersionsAt, LOOKUP_PAGE_SIZE};
+
 pub type InodeNo = u64;
 
 pub const ROOT_INODE_NO: InodeNo = 1;
 
 // 200 years seems long enough
-const NEVER_EXPIRE_TTL: Duration = Duration::from_secs(200 * 365 * 24 * 60 * 60);
+pub(crate) const NEVER_EXPIRE_TTL: Duration = Duration::from_secs(200 * 365 * 24 * 60 * 60);
+
+/// The longest file name, in bytes, that the kernel will look up or that we can list, from
+/// `NAME_MAX`. Keys can be longer than this, especially once escaped with `--flat-keys`.
+pub const MAX_NAME_LENGTH: usize = 255;
 
 pub fn valid_inode_name<T: AsRef<OsStr>>(name: T) -> bool {
     let name = name.as_ref();
@@ -85,10 +109,17 @@ pub struct Superblock {
 
 #[derive(Debug)]
 struct SuperblockInner {
-    bucket: String,
     inodes: RwLock<InodeMap>,
     negative_cache: NegativeCache,
+    forgotten_inodes: ForgottenInodes,
+    /// Buckets and keys of objects that were unlinked without being deleted from S3, and so should
+    /// not be visible until they are replaced or the file system is remounted
+    hidden_keys: Mutex<HashSet<(String, String)>>,
     next_ino: AtomicU64,
+    /// Numbers the superblock never gives to its own inodes, for inodes that live outside it
+    reserved_inos: Range<InodeNo>,
+    /// Allocates inode numbers from keys, instead of from `next_ino`, if enabled
+    stable_inos: Option<StableInodeNumbers>,
     mount_time: OffsetDateTime,
     config: SuperblockConfig,
 }
@@ -98,8 +129,43 @@ struct SuperblockInner {
 pub struct SuperblockConfig {
     pub cache_config: CacheConfig,
     pub s3_personality: S3Personality,
+    /// Whether objects with a symbolic link target in their metadata are treated as symbolic links
+    pub allow_symlinks: bool,
+    /// Which version of each object to show
+    pub object_version_policy: ObjectVersionPolicy,
+    /// Whether unlinking a file deletes its object from S3
+    pub delete_policy: DeletePolicy,
+    /// Prefixes to mount as directories at the root, in place of the single mounted prefix
+    pub namespace: Option<CompositeNamespace>,
+    /// Whether every key under the mounted prefix is a file named after the whole key, with `/`
+    /// escaped, rather than keys being split into directories on `/`
+    pub flat_keys: bool,
+    /// A suffix to add to the name of a file that is shadowed by a directory of the same name, so
+    /// that the file can still be reached under that name. Without one, shadowed files are hidden.
+    pub shadowed_file_suffix: Option<String>,
+    /// Whether inode numbers are derived from the bucket and key of each inode, so that they are the
+    /// same every time the bucket is mounted, rather than allocated in the order inodes are created
+    pub stable_inode_numbers: bool,
+    /// How many inode numbers to reserve for inodes outside the superblock, like the control
+    /// directory
+    pub reserved_inodes: u64,
 }
 
+/// User-defined object metadata key that stores the (percent-encoded) target of a symbolic link
+pub const SYMLINK_TARGET_METADATA_KEY: &str = "symlink-target";
+
+/// S3 limits the total size of user-defined metadata to 2 KiB, including the keys
+const MAX_SYMLINK_TARGET_METADATA_LEN: usize = 2048 - SYMLINK_TARGET_METADATA_KEY.len();
+
+/// Symlink targets are stored in an HTTP header, so encode everything that isn't printable ASCII,
+/// spaces (which could be trimmed from the header value), and '%' itself.
+const SYMLINK_TARGET_ENCODE_SET: &AsciiSet = &CONTROLS.add(b' ').add(b'%');
+
+/// How many keys to list at a time when checking that a directory is empty before removing it. Two
+/// keys would be enough to tell an empty directory with a marker from a non-empty one, but keys we
+/// hid with [DeletePolicy::LocalOnly] don't count, so we may need to list past them.
+const REMOVE_DIRECTORY_LIST_PAGE_SIZE: usize = 1000;
+
 impl Superblock {
     /// Create a new Superblock that targets the given bucket/prefix
     pub fn new(bucket: &str, prefix: &Prefix, config: SuperblockConfig) -> Self {
@@ -110,6 +176,7 @@ impl Superblock {
             ROOT_INODE_NO,
             String::new(),
             prefix.to_string(),
+            bucket.into(),
             InodeKind::Directory,
             InodeState {
                 // The root inode never expires because there's no remote to consult for its
@@ -119,83 +186,142 @@ impl Superblock {
                 kind_data: InodeKindData::default_for(InodeKind::Directory),
                 lookup_count: 1,
                 reader_count: 0,
+                renamed_to: None,
             },
         );
 
         let mut inodes = InodeMap::default();
+        let mut next_ino = ROOT_INODE_NO + 1;
+        if let Some(namespace) = &config.namespace {
+            // Like the root, the directories for each prefix are never forgotten or refreshed
+            let mut root_state = root.inner.sync.write().unwrap();
+            let InodeKindData::Directory { children, .. } = &mut root_state.kind_data else {
+                unreachable!("root is always a directory");
+            };
+            for entry in namespace.entries() {
+                let inode = Inode::new(
+                    next_ino,
+                    ROOT_INODE_NO,
+                    entry.name.clone(),
+                    entry.prefix.to_string(),
+                    entry.bucket.as_str().into(),
+                    InodeKind::Directory,
+                    InodeState {
+                        stat: InodeStat::for_directory(mount_time, NEVER_EXPIRE_TTL),
+                        write_status: WriteStatus::Remote,
+                        kind_data: InodeKindData::default_for(InodeKind::Directory),
+                        lookup_count: 1,
+                        reader_count: 0,
+                        renamed_to: None,
+                    },
+                );
+                children.insert(entry.name.clone(), inode.clone());
+                inodes.insert(next_ino, inode);
+                next_ino += 1;
+            }
+        }
         inodes.insert(ROOT_INODE_NO, root);
+        let reserved_inos = next_ino..next_ino + config.reserved_inodes;
+        next_ino = reserved_inos.end;
 
-        let negative_cache = NegativeCache::new(config.cache_config.negative_cache_size, config.cache_config.file_ttl);
+        let negative_cache = NegativeCache::new(
+            config.cache_config.negative_cache_size,
+            config.cache_config.negative_cache_ttl,
+        );
+
+        let forgotten_inodes = ForgottenInodes::new(config.cache_config.forgotten_inode_grace_period);
+        let stable_inos = config.stable_inode_numbers.then(|| StableInodeNumbers::new(next_ino));
 
         let inner = SuperblockInner {
-            bucket: bucket.to_owned(),
             inodes: RwLock::new(inodes),
             negative_cache,
-            next_ino: AtomicU64::new(2),
+            forgotten_inodes,
+            hidden_keys: Mutex::new(HashSet::new()),
+            next_ino: AtomicU64::new(next_ino),
+            reserved_inos,
+            stable_inos,
             mount_time,
             config,
         };
         Self { inner: Arc::new(inner) }
     }
 
+    /// Create a [CacheInvalidator] that can invalidate the cached metadata of keys that are changed
+    /// in S3, from outside the file system's own operations.
+    pub fn cache_invalidator(&self) -> CacheInvalidator {
+        CacheInvalidator::new(self.inner.clone())
+    }
+
+    /// The inode numbers reserved by [SuperblockConfig::reserved_inodes]
+    pub fn reserved_inodes(&self) -> Range<InodeNo> {
+        self.inner.reserved_inos.clone()
+    }
+
+    /// The inodes in the metadata cache, in order of their numbers
+    pub fn cached_inodes(&self) -> Vec<CachedInode> {
+        // Don't hold the lock on the map while locking each inode
+        let inodes: Vec<Inode> = self.inner.inodes.read().unwrap().map.values().cloned().collect();
+        let mut cached: Vec<_> = inodes
+            .iter()
+            .filter_map(|inode| {
+                let state = inode.get_inode_state().ok()?;
+                Some(CachedInode {
+                    ino: inode.ino(),
+                    kind: inode.kind(),
+                    full_key: inode.full_key().to_owned(),
+                    lookup_count: state.lookup_count,
+                    validity: state.stat.expiry.remaining_ttl(),
+                })
+            })
+            .collect();
+        cached.sort_by_key(|inode| inode.ino);
+        cached
+    }
+
     /// The kernel tells us when it removes a reference to an [InodeNo] from its internal caches via a forget call.
     /// The kernel may forget a number of references (`n`) in one forget message to our FUSE implementation.
     /// If the lookup count reaches zero, it is safe for the [Superblock] to delete the [Inode].
     pub fn forget(&self, ino: InodeNo, n: u64) {
-        let inode = {
-            if let Some(inode) = self.inner.inodes.read().unwrap().get(&ino).cloned() {
-                inode
-            } else {
-                debug_assert!(
-                    false,
-                    "forget should not be called on inode already removed from superblock"
-                );
-                error!("forget called on inode {ino} already removed from the superblock");
-                return;
-            }
-        };
+        self.forget_batch([(ino, n)]);
+    }
 
-        logging::record_name(inode.name());
-        let new_lookup_count = inode.dec_lookup_count(n);
-        if new_lookup_count == 0 {
-            // Safe to remove, kernel no longer has a reference to it.
-            trace!(ino, "removing inode from superblock");
-            let Some(inode) = self.inner.inodes.write().unwrap().remove(&ino) else {
-                error!("forget called on inode {ino} already removed from the superblock");
-                return;
-            };
+    /// Like [Superblock::forget], but for the references to many inodes at once, as the kernel
+    /// sends them when it shrinks its caches. Each item is an [InodeNo] and the number of
+    /// references to it that the kernel forgot. Children must come before their parents, which is
+    /// the order the kernel forgets them in.
+    pub fn forget_batch(&self, nodes: impl IntoIterator<Item = (InodeNo, u64)>) {
+        let mut removable = Vec::new();
+        {
+            let inodes = self.inner.inodes.read().unwrap();
+            for (ino, n) in nodes {
+                let Some(inode) = inodes.get(&ino) else {
+                    debug_assert!(
+                        false,
+                        "forget should not be called on inode already removed from superblock"
+                    );
+                    error!("forget called on inode {ino} already removed from the superblock");
+                    continue;
+                };
 
-            let parent = {
-                if let Some(parent) = self.inner.inodes.read().unwrap().get(&inode.parent()).cloned() {
-                    parent
-                } else {
-                    // Should be impossible for this to fail (VFS inodes reference their parent, so
-                    // children need to be freed first), but let's not crash in a `forget` function...
-                    debug_assert!(false, "children should be forgotten before parents");
-                    return;
+                logging::record_name(inode.name());
+                if inode.dec_lookup_count(n) > 0 {
+                    continue;
                 }
-            };
-            let mut parent_state = parent.inner.sync.write().unwrap();
-            let InodeKindData::Directory {
-                children,
-                writing_children,
-                ..
-            } = &mut parent_state.kind_data
-            else {
-                unreachable!("parent is always a directory");
-            };
-            if let Some(child) = children.get(inode.name()) {
-                // Don't accidentally remove a newer inode (e.g. remote shadowing local)
-                if child.ino() == ino {
-                    children.remove(inode.name());
+                if self.inner.forgotten_inodes.is_enabled() {
+                    // Keep the inode around for a while in case the kernel looks it up again soon.
+                    trace!(ino, "starting grace period for forgotten inode");
+                    self.inner.forgotten_inodes.insert(ino);
+                } else {
+                    removable.push(ino);
                 }
             }
-            writing_children.remove(&ino);
+        }
 
-            if let Ok(state) = inode.get_inode_state() {
-                metrics::counter!("metadata_cache.inode_forgotten_before_expiry")
-                    .increment(state.stat.is_valid().into());
-            };
+        for ino in removable {
+            self.inner.remove_forgotten_inode(ino);
+        }
+        for ino in self.inner.forgotten_inodes.take_expired() {
+            self.inner.remove_forgotten_inode(ino);
         }
     }
 
@@ -272,7 +398,7 @@ impl Superblock {
         }
 
         let validity = match inode.kind() {
-            InodeKind::File => self.inner.config.cache_config.file_ttl,
+            InodeKind::File | InodeKind::Symlink => self.inner.config.cache_config.file_ttl,
             InodeKind::Directory => self.inner.config.cache_config.dir_ttl,
         };
 
@@ -292,6 +418,7 @@ impl Superblock {
     }
 
     /// Create a new write handle to be used for state transition
+    #[allow(clippy::too_many_arguments)]
     pub async fn write<OC: ObjectClient>(
         &self,
         _client: &OC,
@@ -300,10 +427,19 @@ impl Superblock {
         pid: u32,
         allow_overwrite: bool,
         is_truncate: bool,
+        is_append: bool,
     ) -> WriteHandle {
         trace!(?ino, parent=?parent_ino, "write");
 
-        WriteHandle::new(self.inner.clone(), ino, parent_ino, pid, allow_overwrite, is_truncate)
+        WriteHandle::new(
+            self.inner.clone(),
+            ino,
+            parent_ino,
+            pid,
+            allow_overwrite,
+            is_truncate,
+            is_append,
+        )
     }
 
     /// Start a readdir stream for the given directory inode
@@ -339,6 +475,10 @@ impl Superblock {
         kind: InodeKind,
     ) -> Result<LookedUp, InodeError> {
         trace!(parent=?dir, ?name, "create");
+        self.inner.check_namespace_writable(dir)?;
+        if kind == InodeKind::Directory && self.inner.config.flat_keys {
+            return Err(InodeError::DirectoryInFlatNamespace(self.inner.get(dir)?.err()));
+        }
 
         let existing = self
             .inner
@@ -359,6 +499,7 @@ impl Superblock {
         let name = name
             .to_str()
             .ok_or_else(|| InodeError::InvalidFileName(name.to_owned()))?;
+        self.inner.check_not_shadowed_file_name(name)?;
 
         // Put inode creation in a block so we don't hold the lock on the parent state longer than needed.
         let lookup = {
@@ -388,6 +529,7 @@ impl Superblock {
                 InodeKind::Directory => {
                     InodeStat::for_directory(self.inner.mount_time, self.inner.config.cache_config.dir_ttl)
                 }
+                InodeKind::Symlink => unreachable!("symlinks are created with Superblock::symlink"),
             };
 
             let state = InodeState {
@@ -396,25 +538,164 @@ impl Superblock {
                 write_status: WriteStatus::LocalUnopened,
                 lookup_count: 0,
                 reader_count: 0,
+                renamed_to: None,
             };
             let inode = self
                 .inner
                 .create_inode_locked(&parent_inode, &mut parent_state, name, kind, state, true)?;
             LookedUp { inode, stat }
         };
+        if self.inner.negative_cache_enabled() {
+            self.inner.negative_cache.remove(dir, name);
+        }
+
+        self.inner.remember(&lookup.inode);
+        Ok(lookup)
+    }
+
+    /// Create a symbolic link named `name` in the directory `parent_ino`, pointing at `target`.
+    ///
+    /// S3 has no symbolic links, so we represent one as an empty object whose user-defined metadata
+    /// holds the link target. Unlike regular files, the object is uploaded straight away, so the
+    /// new inode is remote.
+    pub async fn symlink<OC: ObjectClient>(
+        &self,
+        client: &OC,
+        parent_ino: InodeNo,
+        name: &OsStr,
+        target: &OsStr,
+        params: PutObjectParams,
+    ) -> Result<LookedUp, InodeError> {
+        trace!(parent=?parent_ino, ?name, ?target, "symlink");
+        self.inner.check_namespace_writable(parent_ino)?;
+
+        let existing = self
+            .inner
+            .lookup_by_name(
+                client,
+                parent_ino,
+                name,
+                self.inner.config.cache_config.serve_lookup_from_cache,
+            )
+            .await;
+        match existing {
+            Ok(lookup) => return Err(InodeError::FileAlreadyExists(lookup.inode.err())),
+            Err(InodeError::FileDoesNotExist(_, _)) => (),
+            Err(e) => return Err(e),
+        }
+
+        // Should be impossible to fail since [lookup] does this check, but let's be sure
+        let name = name
+            .to_str()
+            .ok_or_else(|| InodeError::InvalidFileName(name.to_owned()))?;
+        self.inner.check_not_shadowed_file_name(name)?;
+
+        let encoded_target = percent_encode(target.as_bytes(), SYMLINK_TARGET_ENCODE_SET).to_string();
+        if encoded_target.len() > MAX_SYMLINK_TARGET_METADATA_LEN {
+            return Err(InodeError::SymlinkTargetTooLong(target.to_owned()));
+        }
+
+        let parent = self.inner.get(parent_ino)?;
+        let key = self
+            .inner
+            .child_key(&parent, name)
+            .ok_or_else(|| InodeError::InvalidFileName(name.into()))?;
+        let params = params.object_metadata(HashMap::from([(
+            SYMLINK_TARGET_METADATA_KEY.to_owned(),
+            encoded_target,
+        )]));
+        debug!(parent=?parent_ino, ?name, ?target, "symlink will upload empty key {}", key);
+        let request = client
+            .put_object(parent.bucket(), &key, &params)
+            .await
+            .map_err(|e| InodeError::ClientError(anyhow!(e).context("PutObject failed")))?;
+        request
+            .complete()
+            .await
+            .map_err(|e| InodeError::ClientError(anyhow!(e).context("PutObject failed")))?;
+        // The symlink replaced any object we were hiding at this key
+        self.inner.unhide_key(parent.bucket(), &key);
+
+        let stat = InodeStat::for_file(
+            target.len(),
+            OffsetDateTime::now_utc(),
+            None,
+            params.storage_class.clone(),
+            None,
+            self.inner.config.cache_config.file_ttl,
+        );
+        let lookup = {
+            // The new key exists in S3 now, so any local directories above it are now remote
+            let ancestors = self.inner.local_ancestors(parent_ino)?;
+            let mut ancestors_states = ancestors
+                .iter()
+                .rev()
+                .map(|inode| inode.get_mut_inode_state())
+                .collect::<Result<Vec<_>, _>>()?;
+            let parent_state = ancestors_states.last_mut().expect("parent is always an ancestor");
+            let state = InodeState {
+                stat: stat.clone(),
+                kind_data: InodeKindData::Symlink {
+                    target: target.to_owned(),
+                },
+                write_status: WriteStatus::Remote,
+                lookup_count: 0,
+                reader_count: 0,
+                renamed_to: None,
+            };
+            let inode =
+                self.inner
+                    .create_inode_locked(&parent, parent_state, name, InodeKind::Symlink, state, false)?;
+            SuperblockInner::set_ancestors_remote(inode.ino(), &ancestors, &mut ancestors_states);
+            LookedUp { inode, stat }
+        };
+        if self.inner.negative_cache_enabled() {
+            self.inner.negative_cache.remove(parent_ino, name);
+        }
 
         self.inner.remember(&lookup.inode);
         Ok(lookup)
     }
 
-    /// Remove local-only empty directory, i.e., the ones created by mkdir.
-    /// It does not affect empty directories represented remotely with directory markers.
+    /// Remember the user-defined metadata of the object with ETag `etag` for the file `ino`, until
+    /// its stat next expires. Nothing is remembered if the file now shows a different object.
+    pub fn cache_user_metadata(&self, ino: InodeNo, etag: &str, user_metadata: Arc<HashMap<String, String>>) {
+        let Ok(inode) = self.inner.get(ino) else {
+            return;
+        };
+        let Ok(mut state) = inode.get_mut_inode_state() else {
+            return;
+        };
+        if state.write_status == WriteStatus::Remote && state.stat.etag.as_deref() == Some(etag) {
+            state.stat.user_metadata = Some(user_metadata);
+        }
+    }
+
+    /// Read the target of the symbolic link `ino`
+    pub async fn readlink(&self, ino: InodeNo) -> Result<OsString, InodeError> {
+        let inode = self.inner.get(ino)?;
+        logging::record_name(inode.name());
+
+        match &inode.get_inode_state()?.kind_data {
+            InodeKindData::Symlink { target } => Ok(target.clone()),
+            _ => Err(InodeError::NotASymlink(inode.err())),
+        }
+    }
+
+    /// Remove an empty directory.
+    ///
+    /// Local directories, i.e., the ones created by mkdir, are only removed from the superblock.
+    /// Remote directories are checked with a ListObjectsV2 request to make sure no keys remain
+    /// under them. If the only key left is a directory marker (a zero-byte object whose key is the
+    /// directory's name with a trailing `/`), it is deleted from S3, which needs `allow_delete`.
     pub async fn rmdir<OC: ObjectClient>(
         &self,
         client: &OC,
         parent_ino: InodeNo,
         name: &OsStr,
+        allow_delete: bool,
     ) -> Result<(), InodeError> {
+        self.inner.check_namespace_writable(parent_ino)?;
         let LookedUp { inode, .. } = self
             .inner
             .lookup_by_name(
@@ -425,36 +706,37 @@ impl Superblock {
             )
             .await?;
 
-        if inode.kind() == InodeKind::File {
+        if inode.kind() != InodeKind::Directory {
             return Err(InodeError::NotADirectory(inode.err()));
         }
 
+        let write_status = inode.get_inode_state()?.write_status;
+        if write_status == WriteStatus::Remote {
+            self.inner.remove_remote_directory(client, &inode, allow_delete).await?;
+        }
+
         let parent = self.inner.get(parent_ino)?;
         let mut parent_state = parent.get_mut_inode_state()?;
         let mut inode_state = inode.get_mut_inode_state()?;
 
-        match &inode_state.write_status {
-            WriteStatus::LocalOpen => unreachable!("A directory cannot be in Local open state"),
-            WriteStatus::Remote => {
-                return Err(InodeError::CannotRemoveRemoteDirectory(inode.err()));
+        match &mut inode_state.kind_data {
+            InodeKindData::File { .. } | InodeKindData::Symlink { .. } => {
+                unreachable!("Already checked that inode is a directory")
             }
-            WriteStatus::LocalUnopened => match &mut inode_state.kind_data {
-                InodeKindData::File {} => unreachable!("Already checked that inode is a directory"),
-                InodeKindData::Directory {
-                    writing_children,
-                    deleted,
-                    ..
-                } => {
-                    if !writing_children.is_empty() {
-                        return Err(InodeError::DirectoryNotEmpty(inode.err()));
-                    }
-                    *deleted = true;
+            InodeKindData::Directory {
+                writing_children,
+                deleted,
+                ..
+            } => {
+                if !writing_children.is_empty() {
+                    return Err(InodeError::DirectoryNotEmpty(inode.err()));
                 }
-            },
+                *deleted = true;
+            }
         }
 
         match &mut parent_state.kind_data {
-            InodeKindData::File {} => {
+            InodeKindData::File { .. } | InodeKindData::Symlink { .. } => {
                 debug_assert!(false, "inodes never change kind");
                 return Err(InodeError::NotADirectory(parent.err()));
             }
@@ -465,7 +747,7 @@ impl Superblock {
             } => {
                 let removed = writing_children.remove(&inode.ino());
                 debug_assert!(
-                    removed,
+                    removed || write_status == WriteStatus::Remote,
                     "should be able to remove the directory from its parents writing children as it was local"
                 );
                 children.remove(inode.name());
@@ -477,7 +759,8 @@ impl Superblock {
 
     /// Unlink the entry described by `parent_ino` and `name`.
     ///
-    /// If the entry exists, delete it from S3 and the superblock.
+    /// If the entry exists, delete it from S3 and the superblock. With [DeletePolicy::LocalOnly],
+    /// the object is left in S3 and only hidden from lookups and listings instead.
     ///
     /// We know that the Linux Kernel's VFS will lock both the parent and child,
     /// so we can safely ignore concurrent operations within the same Mountpoint process to the file and its parent.
@@ -518,8 +801,12 @@ impl Superblock {
                 );
                 return Err(InodeError::UnlinkNotPermittedWhileWriting(inode.err()));
             }
+            WriteStatus::Remote if self.inner.config.delete_policy == DeletePolicy::LocalOnly => {
+                debug!(parent=?parent_ino, ?name, "unlink on remote file will hide key {}", inode.full_key());
+                self.inner.hide_key(inode.bucket(), inode.full_key());
+            }
             WriteStatus::Remote => {
-                let (bucket, s3_key) = (self.inner.bucket.as_str(), inode.full_key());
+                let (bucket, s3_key) = (inode.bucket(), inode.full_key());
                 debug!(parent=?parent_ino, ?name, "unlink on remote file will delete key {}", s3_key);
                 let delete_obj_result = client.delete_object(bucket, s3_key).await;
 
@@ -539,7 +826,7 @@ impl Superblock {
 
         let mut parent_state = parent.get_mut_inode_state()?;
         match &mut parent_state.kind_data {
-            InodeKindData::File { .. } => {
+            InodeKindData::File { .. } | InodeKindData::Symlink { .. } => {
                 debug_assert!(false, "inodes never change kind");
                 return Err(InodeError::NotADirectory(parent.err()));
             }
@@ -561,9 +848,280 @@ impl Superblock {
 
         Ok(())
     }
+
+    /// Rename the file described by `src_parent_ino` and `src_name` to `dst_parent_ino` and `dst_name`.
+    ///
+    /// S3 has no rename operation, so remote files are copied to their new key and then deleted
+    /// from their old one. Local files have not been uploaded yet, so they are only moved in the
+    /// superblock. Directories cannot be renamed, as that would mean copying every key under them.
+    ///
+    /// If `allow_overwrite` is false, the destination must not already exist. Returns the number of
+    /// the renamed inode. The kernel keeps using the same inode at the new name, so the inode moves
+    /// there and keeps its number (see [SuperblockInner::move_inode]).
+    ///
+    /// As in [Superblock::unlink], we rely on the Linux Kernel's VFS to lock both parents and the
+    /// child, so we can ignore concurrent operations on them within the same Mountpoint process.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn rename<OC: ObjectClient>(
+        &self,
+        client: &OC,
+        src_parent_ino: InodeNo,
+        src_name: &OsStr,
+        dst_parent_ino: InodeNo,
+        dst_name: &OsStr,
+        allow_overwrite: bool,
+        copy_params: &CopyObjectParams,
+    ) -> Result<InodeNo, InodeError> {
+        self.inner.check_namespace_writable(src_parent_ino)?;
+        self.inner.check_namespace_writable(dst_parent_ino)?;
+        let allow_cache = self.inner.config.cache_config.serve_lookup_from_cache;
+
+        // Prefer the inode we already have for the source, even if its stat has expired. It's the
+        // one the kernel most likely has for this name, and so the one that needs to become stale.
+        let src_parent = self.inner.get(src_parent_ino)?;
+        let known_child = match &src_parent.get_inode_state()?.kind_data {
+            InodeKindData::File { .. } | InodeKindData::Symlink { .. } => {
+                return Err(InodeError::NotADirectory(src_parent.err()))
+            }
+            InodeKindData::Directory { children, .. } => src_name.to_str().and_then(|name| children.get(name).cloned()),
+        };
+        let inode = match known_child {
+            Some(inode) => inode,
+            None => {
+                self.inner
+                    .lookup_by_name(client, src_parent_ino, src_name, allow_cache)
+                    .await?
+                    .inode
+            }
+        };
+
+        if inode.kind() == InodeKind::Directory {
+            return Err(InodeError::CannotRenameDirectory(inode.err()));
+        }
+
+        let dst_parent = self.inner.get(dst_parent_ino)?;
+        if dst_parent.kind() != InodeKind::Directory {
+            return Err(InodeError::NotADirectory(dst_parent.err()));
+        }
+        let dst_name = dst_name
+            .to_str()
+            .ok_or_else(|| InodeError::InvalidFileName(dst_name.to_owned()))?;
+        if !valid_inode_name(dst_name) {
+            return Err(InodeError::InvalidFileName(dst_name.into()));
+        }
+        self.inner.check_not_shadowed_file_name(dst_name)?;
+        if inode.bucket() != dst_parent.bucket() {
+            return Err(InodeError::CrossBucketRename(inode.err()));
+        }
+
+        if src_parent_ino == dst_parent_ino && src_name == dst_name {
+            return Ok(inode.ino());
+        }
+
+        let existing = match self
+            .inner
+            .lookup_by_name(client, dst_parent_ino, dst_name.as_ref(), allow_cache)
+            .await
+        {
+            Ok(lookup) => Some(lookup.inode),
+            Err(InodeError::FileDoesNotExist(_, _)) => None,
+            Err(e) => return Err(e),
+        };
+        if let Some(existing) = &existing {
+            if existing.kind() == InodeKind::Directory {
+                return Err(InodeError::IsDirectory(existing.err()));
+            }
+            if !allow_overwrite {
+                return Err(InodeError::FileAlreadyExists(existing.err()));
+            }
+            if !existing.is_remote()? {
+                return Err(InodeError::RenameNotPermittedWhileWriting(existing.err()));
+            }
+        }
+
+        let write_status = inode.get_inode_state()?.write_status;
+        let stat = match write_status {
+            WriteStatus::LocalOpen => {
+                warn!(
+                    parent = src_parent_ino,
+                    name = ?src_name,
+                    "rename on local file not allowed until write is complete",
+                );
+                return Err(InodeError::RenameNotPermittedWhileWriting(inode.err()));
+            }
+            WriteStatus::LocalUnopened => {
+                debug!(
+                    parent=?src_parent_ino,
+                    name=?src_name,
+                    new_parent=?dst_parent_ino,
+                    new_name=?dst_name,
+                    "rename of local file only updates the superblock",
+                );
+                inode.get_inode_state()?.stat.clone()
+            }
+            WriteStatus::Remote => {
+                let bucket = inode.bucket();
+                let src_key = inode.full_key();
+                let dst_key = self
+                    .inner
+                    .child_key(&dst_parent, dst_name)
+                    .ok_or_else(|| InodeError::InvalidFileName(dst_name.into()))?;
+                debug!(
+                    parent=?src_parent_ino,
+                    name=?src_name,
+                    "rename on remote file will copy key {} to {}",
+                    src_key,
+                    dst_key,
+                );
+
+                match client.copy_object(bucket, src_key, bucket, &dst_key, copy_params).await {
+                    Ok(_res) => (),
+                    Err(ObjectClientError::ServiceError(CopyObjectError::NotFound)) => {
+                        // The object was deleted from S3 since we last looked it up
+                        return Err(InodeError::FileDoesNotExist(inode.name().to_owned(), src_parent.err()));
+                    }
+                    Err(e) => {
+                        error!(inode=%inode.err(), error=?e, "CopyObject failed for rename");
+                        return Err(InodeError::ClientError(anyhow!(e).context("CopyObject failed")));
+                    }
+                }
+
+                // The new key exists in S3 now, so any local directories above it are now remote
+                {
+                    let ancestors = self.inner.local_ancestors(dst_parent_ino)?;
+                    let mut ancestors_states = ancestors
+                        .iter()
+                        .rev()
+                        .map(|inode| inode.get_mut_inode_state())
+                        .collect::<Result<Vec<_>, _>>()?;
+                    // The renamed inode is remote, so it is not one of the writing children of the
+                    // destination parent, but passing it keeps the walk the same as for writes.
+                    SuperblockInner::set_ancestors_remote(inode.ino(), &ancestors, &mut ancestors_states);
+                }
+                if self.inner.negative_cache_enabled() {
+                    self.inner.negative_cache.remove(dst_parent_ino, dst_name);
+                }
+                self.inner.unhide_key(bucket, &dst_key);
+
+                if let Err(e) = client.delete_object(bucket, src_key).await {
+                    error!(
+                        inode=%inode.err(),
+                        error=?e,
+                        "DeleteObject failed for rename, object now exists at both {} and {}",
+                        src_key,
+                        dst_key,
+                    );
+                    return Err(InodeError::ClientError(anyhow!(e).context("DeleteObject failed")));
+                }
+
+                // The copy is a new object with a new ETag, which the moved inode needs to match
+                // or it will be replaced by a new inode the next time it's looked up.
+                match client.head_object(bucket, &dst_key).await {
+                    Ok(HeadObjectResult { object, .. }) => InodeStat::for_file(
+                        object.size as usize,
+                        object.last_modified,
+                        Some(object.etag),
+                        object.storage_class,
+                        object.restore_status,
+                        self.inner.config.cache_config.file_ttl,
+                    ),
+                    Err(e) => {
+                        warn!(inode=%inode.err(), error=?e, "HeadObject failed after rename");
+                        let mut stat = inode.get_inode_state()?.stat.clone();
+                        stat.update_validity(Duration::from_secs(0));
+                        stat
+                    }
+                }
+            }
+        };
+
+        let mut src_parent_state = src_parent.get_mut_inode_state()?;
+        let InodeKindData::Directory {
+            children,
+            writing_children,
+            ..
+        } = &mut src_parent_state.kind_data
+        else {
+            debug_assert!(false, "inodes never change kind");
+            return Err(InodeError::NotADirectory(src_parent.err()));
+        };
+        // As in `unlink`, we don't hold the parent lock over remote calls, so instead we panic if
+        // the assumption that the VFS prevents concurrent operations appears broken.
+        let removed_inode = children
+            .remove(inode.name())
+            .expect("parent should contain child assuming VFS does not permit concurrent op on parent");
+        assert_eq!(
+            removed_inode.ino(),
+            inode.ino(),
+            "child ino number shouldn't change assuming VFS does not permit concurrent op on parent",
+        );
+        writing_children.remove(&inode.ino());
+        drop(src_parent_state);
+
+        if self.inner.negative_cache_enabled() {
+            self.inner.negative_cache.insert(src_parent_ino, inode.name());
+        }
+
+        let moved = self.inner.move_inode(&inode, &dst_parent, dst_name, stat)?;
+        Ok(moved.ino())
+    }
 }
 
 impl SuperblockInner {
+    /// Remove a forgotten inode from the superblock, unless the kernel has looked it up again
+    /// since it was forgotten.
+    fn remove_forgotten_inode(&self, ino: InodeNo) {
+        let inode = {
+            // Check the lookup count under the same lock as the removal, so a lookup can't revive the
+            // inode in between
+            let mut inodes = self.inodes.write().unwrap();
+            let Some(inode) = inodes.get(&ino) else {
+                error!("forget called on inode {ino} already removed from the superblock");
+                return;
+            };
+            // Only possible if the inode was looked up again during its grace period
+            if inode.get_inode_state().map_or(false, |state| state.lookup_count > 0) {
+                trace!(ino, "forgotten inode was looked up again during its grace period");
+                return;
+            }
+
+            // Safe to remove, kernel no longer has a reference to it.
+            trace!(ino, "removing inode from superblock");
+            inodes.remove(&ino).expect("inode was just found")
+        };
+
+        let parent = {
+            if let Some(parent) = self.inodes.read().unwrap().get(&inode.parent()).cloned() {
+                parent
+            } else {
+                // Should be impossible for this to fail (VFS inodes reference their parent, so
+                // children need to be freed first), but let's not crash in a `forget` function...
+                debug_assert!(false, "children should be forgotten before parents");
+                return;
+            }
+        };
+        let mut parent_state = parent.inner.sync.write().unwrap();
+        let InodeKindData::Directory {
+            children,
+            writing_children,
+            ..
+        } = &mut parent_state.kind_data
+        else {
+            unreachable!("parent is always a directory");
+        };
+        if let Some(child) = children.get(inode.name()) {
+            // Don't accidentally remove a newer inode (e.g. remote shadowing local)
+            if child.ino() == ino {
+                children.remove(inode.name());
+            }
+        }
+        writing_children.remove(&ino);
+
+        if let Ok(state) = inode.get_inode_state() {
+            metrics::counter!("metadata_cache.inode_forgotten_before_expiry").increment(state.stat.is_valid().into());
+        };
+    }
+
     /// Retrieve the inode for the given number if it exists.
     ///
     /// The expiry of its stat field is not checked.
@@ -585,8 +1143,12 @@ impl SuperblockInner {
     pub fn remember(&self, inode: &Inode) -> u64 {
         let lookup_count = inode.inc_lookup_count();
         if lookup_count == 1 {
+            // A forgotten inode stays registered during its grace period, so it may still be there
             let previous = self.inodes.write().unwrap().insert(inode.ino(), inode.clone());
-            assert!(previous.is_none(), "inode numbers are never reused");
+            assert!(
+                previous.map_or(true, |previous| Arc::ptr_eq(&previous.inner, &inode.inner)),
+                "inode numbers are never reused"
+            );
         }
         lookup_count
     }
@@ -612,11 +1174,22 @@ impl SuperblockInner {
         if name.ends_with('/') {
             return Err(InodeError::InvalidFileName(name.into()));
         }
+        // The kernel never asks for longer names, and readdir never lists them
+        if name.len() > MAX_NAME_LENGTH {
+            warn!(?name, "lookup of a name longer than {MAX_NAME_LENGTH} bytes");
+            return Err(InodeError::NameTooLong(name.into()));
+        }
+
+        // The root of a composite namespace only contains the directories for each prefix, and
+        // there's nothing in S3 to look up there
+        if self.is_namespace_root(parent_ino) {
+            return self.namespace_lookup(name);
+        }
 
         let lookup = if allow_cache {
             self.cache_lookup(parent_ino, name)
         } else {
-            None
+            self.negative_cache_lookup(parent_ino, name)
         };
 
         let lookup = match lookup {
@@ -631,6 +1204,61 @@ impl SuperblockInner {
         Ok(lookup)
     }
 
+    /// Whether the given inode is the root of a composite namespace, which only contains the
+    /// directories for each mounted prefix
+    fn is_namespace_root(&self, ino: InodeNo) -> bool {
+        ino == ROOT_INODE_NO && self.config.namespace.is_some()
+    }
+
+    /// Look up one of the directories at the root of a composite namespace
+    fn namespace_lookup(&self, name: &str) -> Result<LookedUp, InodeError> {
+        let root = self.get(ROOT_INODE_NO)?;
+        let root_state = root.get_inode_state()?;
+        let InodeKindData::Directory { children, .. } = &root_state.kind_data else {
+            unreachable!("root is always a directory");
+        };
+        let Some(inode) = children.get(name) else {
+            return Err(InodeError::FileDoesNotExist(name.to_owned(), root.err()));
+        };
+        let stat = inode.get_inode_state()?.stat.clone();
+        Ok(LookedUp {
+            inode: inode.clone(),
+            stat,
+        })
+    }
+
+    /// Entries can't be added to or removed from the root of a composite namespace
+    fn check_namespace_writable(&self, parent_ino: InodeNo) -> Result<(), InodeError> {
+        if self.is_namespace_root(parent_ino) {
+            return Err(InodeError::NamespaceRootNotWritable(self.get(parent_ino)?.err()));
+        }
+        Ok(())
+    }
+
+    /// Check whether the given `name` is in the negative cache. This is used when lookups can't be
+    /// served from cached entries, but a recent lookup miss can still be trusted.
+    /// If the parent has a local entry with this name, returns [None] so that it's found by the
+    /// remote lookup. If an entry is found in the negative cache, returns
+    /// [Some(Err(InodeError::FileDoesNotExist))].
+    fn negative_cache_lookup(&self, parent_ino: InodeNo, name: &str) -> Option<Result<LookedUp, InodeError>> {
+        if !self.negative_cache_enabled() || !self.negative_cache.contains(parent_ino, name) {
+            return None;
+        }
+        let parent = self.get(parent_ino).ok()?;
+        if let InodeKindData::Directory { children, .. } = &parent.get_inode_state().ok()?.kind_data {
+            if children.contains_key(name) {
+                return None;
+            }
+        }
+        trace!("lookup returned from negative cache");
+        Some(Err(InodeError::FileDoesNotExist(name.to_owned(), parent.err())))
+    }
+
+    /// Whether lookup misses should be remembered in the negative cache.
+    fn negative_cache_enabled(&self) -> bool {
+        !self.config.cache_config.negative_cache_ttl.is_zero()
+    }
+
     /// Lookup an [Inode] against known directory entries in the parent,
     /// verifying any returned entry has not expired.
     /// If no record for the given `name` is found, returns [None].
@@ -642,7 +1270,9 @@ impl SuperblockInner {
             name: &str,
         ) -> Option<Result<LookedUp, InodeError>> {
             match &parent.get_inode_state().ok()?.kind_data {
-                InodeKindData::File { .. } => unreachable!("parent should be a directory!"),
+                InodeKindData::File { .. } | InodeKindData::Symlink { .. } => {
+                    unreachable!("parent should be a directory!")
+                }
                 InodeKindData::Directory { children, .. } => {
                     if let Some(inode) = children.get(name) {
                         let inode_stat = &inode.get_inode_state().ok()?.stat;
@@ -678,6 +1308,130 @@ impl SuperblockInner {
         lookup
     }
 
+    /// Check that a remote directory has no keys left under it, and delete its directory marker
+    /// if it has one.
+    async fn remove_remote_directory<OC: ObjectClient>(
+        &self,
+        client: &OC,
+        inode: &Inode,
+        allow_delete: bool,
+    ) -> Result<(), InodeError> {
+        // Don't bother asking S3 if there are files being written in the directory
+        if let InodeKindData::Directory { writing_children, .. } = &inode.get_inode_state()?.kind_data {
+            if !writing_children.is_empty() {
+                return Err(InodeError::DirectoryNotEmpty(inode.err()));
+            }
+        }
+
+        let marker_key = inode.full_key();
+
+        // Keys we've hidden don't count, so keep listing past them until we find a visible key
+        let mut has_marker = false;
+        let mut continuation_token = None;
+        loop {
+            let result = client
+                .list_objects(
+                    inode.bucket(),
+                    continuation_token.as_deref(),
+                    "",
+                    REMOVE_DIRECTORY_LIST_PAGE_SIZE,
+                    marker_key,
+                )
+                .await
+                .map_err(|e| InodeError::ClientError(anyhow!(e).context("ListObjectsV2 failed")))?;
+            for object in &result.objects {
+                if object.key == marker_key {
+                    has_marker = true;
+                } else if !self.is_key_hidden(inode.bucket(), &object.key) {
+                    return Err(InodeError::DirectoryNotEmpty(inode.err()));
+                }
+            }
+            continuation_token = result.next_continuation_token;
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+
+        if has_marker {
+            if !allow_delete {
+                return Err(InodeError::CannotRemoveRemoteDirectory(inode.err()));
+            }
+            debug!(inode=%inode.err(), "rmdir on remote directory will delete marker {}", marker_key);
+            if let Err(e) = client.delete_object(inode.bucket(), marker_key).await {
+                error!(inode=%inode.err(), error=?e, "DeleteObject failed for rmdir");
+                return Err(InodeError::ClientError(anyhow!(e).context("DeleteObject failed")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Hide the object at `key` from lookups and listings, until it's replaced
+    fn hide_key(&self, bucket: &str, key: &str) {
+        self.hidden_keys
+            .lock()
+            .unwrap()
+            .insert((bucket.to_owned(), key.to_owned()));
+    }
+
+    /// Make the object at `key` visible again, because it has been replaced
+    fn unhide_key(&self, bucket: &str, key: &str) {
+        self.hidden_keys
+            .lock()
+            .unwrap()
+            .remove(&(bucket.to_owned(), key.to_owned()));
+    }
+
+    fn is_key_hidden(&self, bucket: &str, key: &str) -> bool {
+        self.hidden_keys
+            .lock()
+            .unwrap()
+            .contains(&(bucket.to_owned(), key.to_owned()))
+    }
+
+    /// The hidden keys in the directory with the given full path
+    fn hidden_keys_under(&self, bucket: &str, full_path: &str) -> HashSet<String> {
+        self.hidden_keys
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(hidden_bucket, key)| {
+                hidden_bucket == bucket
+                    && key.starts_with(full_path)
+                    && (self.config.flat_keys || !key[full_path.len()..].contains('/'))
+            })
+            .map(|(_, key)| key.clone())
+            .collect()
+    }
+
+    /// The full key of the file named `name` in the directory `parent`, or [None] if no key can have
+    /// that name in a flat namespace. A directory's key is this plus a trailing `/`.
+    fn child_key(&self, parent: &Inode, name: &str) -> Option<String> {
+        let mut key = parent.full_key().to_owned();
+        assert!(key.is_empty() || key.ends_with('/'));
+        if self.config.flat_keys {
+            key.push_str(&flat::key_for_name(name)?);
+        } else {
+            key.push_str(self.shadowed_file_base(name).unwrap_or(name));
+        }
+        Some(key)
+    }
+
+    /// If `name` is the name a file shadowed by a directory appears under, the name of that file
+    /// and directory
+    fn shadowed_file_base<'a>(&self, name: &'a str) -> Option<&'a str> {
+        let suffix = self.config.shadowed_file_suffix.as_deref()?;
+        name.strip_suffix(suffix).filter(|base| valid_inode_name(base))
+    }
+
+    /// Names ending in the shadowed file suffix only ever refer to shadowed files, so nothing else
+    /// can be created with them
+    fn check_not_shadowed_file_name(&self, name: &str) -> Result<(), InodeError> {
+        if self.shadowed_file_base(name).is_some() {
+            return Err(InodeError::InvalidFileName(name.into()));
+        }
+        Ok(())
+    }
+
     /// Lookup an inode in the parent directory with the given name
     /// on the remote client.
     async fn remote_lookup<OC: ObjectClient>(
@@ -690,13 +1444,22 @@ impl SuperblockInner {
         if parent.kind() != InodeKind::Directory {
             return Err(InodeError::NotADirectory(parent.err()));
         }
-        let mut full_path = parent.full_key().to_owned();
-        assert!(full_path.is_empty() || full_path.ends_with('/'));
-        full_path.push_str(name);
+        let Some(full_path) = self.child_key(&parent, name) else {
+            trace!(parent = ?parent_ino, ?name, "name doesn't belong to any key");
+            return Ok(None);
+        };
+        // A shadowed file's name only refers to the file while a directory shadows it
+        let is_shadowed_file_name = self.shadowed_file_base(name).is_some();
 
         let mut full_path_suffixed = full_path.clone();
         full_path_suffixed.push('/');
 
+        if let Some(as_of) = self.config.object_version_policy.as_of() {
+            return self
+                .remote_lookup_at(client, &parent, name, &full_path, &full_path_suffixed, as_of)
+                .await;
+        }
+
         // We need to try two requests here, one to find an object with the given name, and one to
         // discover a possible shadowing (implicit) directory with the same name. There's a few
         // different cases we need to consider here:
@@ -721,19 +1484,28 @@ impl SuperblockInner {
         //       "/" to the prefix in the request, the first common prefix we'll get back will be
         //       "dir-1/", because that precedes "dir/" in lexicographic order. Doing the
         //       ListObjects with "/" appended makes sure we always observe the correct prefix.
-        let mut file_lookup = client.head_object(&self.bucket, &full_path).fuse();
-        let mut dir_lookup = client
-            .list_objects(&self.bucket, None, "/", 1, &full_path_suffixed)
-            .fuse();
+        //
+        // In a flat namespace there are no directories, so only the HeadObject is needed.
+        let mut file_lookup = client.head_object(parent.bucket(), &full_path).fuse();
+        let mut dir_lookup = OptionFuture::from(
+            (!self.config.flat_keys).then(|| client.list_objects(parent.bucket(), None, "/", 1, &full_path_suffixed)),
+        )
+        .fuse();
 
         let mut file_state = None;
+        let mut symlink_target = None;
+        let mut found_shadowing_directory = false;
 
         for _ in 0..2 {
             select_biased! {
                 result = file_lookup => {
                     match result {
-                        Ok(HeadObjectResult { object, .. }) => {
-                            let stat = InodeStat::for_file(object.size as usize, object.last_modified, Some(object.etag.clone()), object.storage_class, object.restore_status, self.config.cache_config.file_ttl);
+                        Ok(HeadObjectResult { object, object_metadata, .. }) => {
+                            let mut stat = InodeStat::for_file(object.size as usize, object.last_modified, Some(object.etag.clone()), object.storage_class, object.restore_status, self.config.cache_config.file_ttl);
+                            if self.config.allow_symlinks {
+                                symlink_target = symlink_target_from_metadata(&object_metadata);
+                            }
+                            stat.user_metadata = Some(Arc::new(object_metadata));
                             file_state = Some(stat);
                         }
                         // If the object is not found, might be a directory, so keep going
@@ -743,6 +1515,9 @@ impl SuperblockInner {
                 }
 
                 result = dir_lookup => {
+                    let Some(result) = result else {
+                        continue;
+                    };
                     let result = result.map_err(|e| InodeError::ClientError(anyhow!(e).context("ListObjectsV2 failed")))?;
 
                     let found_directory = if result
@@ -780,11 +1555,14 @@ impl SuperblockInner {
                     };
 
                     // We don't have to wait for the HeadObject to complete because in our
-                    // semantics, directories always shadow files.
-                    if found_directory {
+                    // semantics, directories always shadow files. Unless we're looking for the
+                    // shadowed file, which needs both.
+                    if found_directory && is_shadowed_file_name {
+                        found_shadowing_directory = true;
+                    } else if found_directory {
                         trace!(parent = ?parent_ino, ?name, "lookup ListObjects found a directory");
                         let stat = InodeStat::for_directory(self.mount_time, self.config.cache_config.dir_ttl);
-                        return Ok(Some(RemoteLookup { kind: InodeKind::Directory, stat }));
+                        return Ok(Some(RemoteLookup::for_directory(stat)));
                     }
                 }
             }
@@ -792,20 +1570,95 @@ impl SuperblockInner {
 
         // If we reach here, the ListObjects didn't find a shadowing directory, so we know we either
         // have a valid file, or both requests failed to find the object so the file must not exist remotely
+        if is_shadowed_file_name && !found_shadowing_directory {
+            trace!(parent = ?parent_ino, ?name, "file is not shadowed by a directory");
+            file_state = None;
+        }
+        if self.is_key_hidden(parent.bucket(), &full_path) {
+            trace!(parent = ?parent_ino, ?name, "object was unlinked locally");
+            file_state = None;
+        }
         if let Some(mut stat) = file_state {
-            trace!(parent = ?parent_ino, ?name, etag =? stat.etag, "found a regular file in S3");
+            trace!(parent = ?parent_ino, ?name, etag =? stat.etag, symlink = symlink_target.is_some(), "found a file in S3");
             // Update the validity of the stat in case the racing ListObjects took a long time
             stat.update_validity(self.config.cache_config.file_ttl);
-            Ok(Some(RemoteLookup {
-                kind: InodeKind::File,
-                stat,
-            }))
+            // A symbolic link's size is the length of its target, not of its (empty) object
+            if let Some(target) = &symlink_target {
+                stat.size = target.len();
+            }
+            Ok(Some(RemoteLookup::for_object(stat, symlink_target)))
         } else {
             trace!(parent = ?parent_ino, ?name, "not found");
             Ok(None)
         }
     }
 
+    /// Lookup an inode on the remote client as it was at the given time, using ListObjectVersions.
+    /// Follows the same shadowing rules as [Self::remote_lookup], but neither request can be cut
+    /// short: a key's history has to be listed until we find its version at `as_of`.
+    async fn remote_lookup_at<OC: ObjectClient>(
+        &self,
+        client: &OC,
+        parent: &Inode,
+        name: &str,
+        full_path: &str,
+        full_path_suffixed: &str,
+        as_of: OffsetDateTime,
+    ) -> Result<Option<RemoteLookup>, InodeError> {
+        let (bucket, parent_ino) = (parent.bucket(), parent.ino());
+        // A directory existed at `as_of` if any key under it did.
+        let dir_lookup = async {
+            if self.config.flat_keys {
+                return Ok(false);
+            }
+            prefix_existed_at(client, bucket, full_path_suffixed, as_of).await
+        };
+
+        // Every other key with the file's name as a prefix is longer than it, so the file's versions
+        // come first in the listing. We can stop as soon as a page ends past them.
+        let file_lookup = async {
+            let mut versions = VersionsAt::new(bucket, full_path, "/", as_of);
+            while !versions.is_finished() {
+                let page = versions.next_page(client, LOOKUP_PAGE_SIZE).await?;
+                if let Some(object) = page.objects.into_iter().find(|object| object.info.key == full_path) {
+                    return Ok(Some(object));
+                }
+                if versions.key_marker() != Some(full_path) {
+                    break;
+                }
+            }
+            Ok::<_, InodeError>(None)
+        };
+
+        let (found_directory, mut file) = futures::try_join!(dir_lookup, file_lookup)?;
+        let is_shadowed_file_name = self.shadowed_file_base(name).is_some();
+        if found_directory && !is_shadowed_file_name {
+            trace!(parent = ?parent_ino, ?name, ?as_of, "lookup ListObjectVersions found a directory");
+            let stat = InodeStat::for_directory(self.mount_time, self.config.cache_config.dir_ttl);
+            return Ok(Some(RemoteLookup::for_directory(stat)));
+        }
+
+        if self.is_key_hidden(bucket, full_path) || (is_shadowed_file_name && !found_directory) {
+            file = None;
+        }
+        if let Some(object) = file {
+            trace!(parent = ?parent_ino, ?name, ?as_of, version_id = object.version_id, "found a file version in S3");
+            let mut stat = InodeStat::for_file(
+                object.info.size as usize,
+                object.info.last_modified,
+                Some(object.info.etag),
+                object.info.storage_class,
+                object.info.restore_status,
+                self.config.cache_config.file_ttl,
+            );
+            stat.version_id = Some(object.version_id);
+            Ok(Some(RemoteLookup::for_object(stat, None)))
+        } else {
+            trace!(parent = ?parent_ino, ?name, ?as_of, "not found");
+            Ok(None)
+        }
+    }
+
     /// Update the inode with the given name in a parent directory with the remote data.
     /// It may update or delete an existing inode, or insert a new one.
     pub fn update_from_remote(
@@ -821,7 +1674,7 @@ impl SuperblockInner {
             return Err(InodeError::NotADirectory(parent.err()));
         }
 
-        if self.config.cache_config.serve_lookup_from_cache {
+        if self.negative_cache_enabled() {
             match &remote {
                 // Remove negative cache entry.
                 Some(_) => self.negative_cache.remove(parent_ino, name),
@@ -847,7 +1700,7 @@ impl SuperblockInner {
     ) -> Result<Option<LookedUp>, InodeError> {
         let parent_state = parent.get_inode_state()?;
         let inode = match &parent_state.kind_data {
-            InodeKindData::File { .. } => unreachable!("we know parent is a directory"),
+            InodeKindData::File { .. } | InodeKindData::Symlink { .. } => unreachable!("we know parent is a directory"),
             InodeKindData::Directory { children, .. } => children.get(name),
         };
         match (remote, inode) {
@@ -855,9 +1708,11 @@ impl SuperblockInner {
             (Some(remote), Some(existing_inode)) => {
                 let mut existing_state = existing_inode.get_mut_inode_state()?;
                 let existing_is_remote = existing_state.write_status == WriteStatus::Remote;
+                // Empty objects all have the same ETag, so symlinks also need to compare targets
                 if remote.kind == existing_inode.kind()
                     && existing_is_remote
                     && existing_state.stat.etag == remote.stat.etag
+                    && existing_state.kind_data.symlink_target() == remote.symlink_target.as_ref()
                 {
                     trace!(parent=?existing_inode.parent(), name=?existing_inode.name(), ino=?existing_inode.ino(), "updating inode in place");
                     existing_state.stat = remote.stat.clone();
@@ -884,7 +1739,7 @@ impl SuperblockInner {
     ) -> Result<LookedUp, InodeError> {
         let mut parent_state = parent.get_mut_inode_state()?;
         let inode = match &parent_state.kind_data {
-            InodeKindData::File { .. } => unreachable!("we know parent is a directory"),
+            InodeKindData::File { .. } | InodeKindData::Symlink { .. } => unreachable!("we know parent is a directory"),
             InodeKindData::Directory { children, .. } => children.get(name).cloned(),
         };
         match (remote, inode) {
@@ -902,7 +1757,7 @@ impl SuperblockInner {
                     let mut sync = existing_inode.get_mut_inode_state()?;
 
                     let validity = match existing_inode.kind() {
-                        InodeKind::File => self.config.cache_config.file_ttl,
+                        InodeKind::File | InodeKind::Symlink => self.config.cache_config.file_ttl,
                         InodeKind::Directory => self.config.cache_config.dir_ttl,
                     };
                     sync.stat.update_validity(validity);
@@ -924,10 +1779,11 @@ impl SuperblockInner {
             (Some(remote), None) => {
                 let state = InodeState {
                     stat: remote.stat.clone(),
-                    kind_data: InodeKindData::default_for(remote.kind),
+                    kind_data: remote.kind_data(),
                     write_status: WriteStatus::Remote,
                     lookup_count: 0,
                     reader_count: 0,
+                    renamed_to: None,
                 };
                 self.create_inode_locked(&parent, &mut parent_state, name, remote.kind, state, false)
                     .map(|inode| LookedUp {
@@ -945,7 +1801,7 @@ impl SuperblockInner {
 
                 // Remote files are always shadowed by existing local files/directories, so do
                 // nothing and return the existing inode.
-                if remote.kind == InodeKind::File && !existing_is_remote {
+                if remote.kind != InodeKind::Directory && !existing_is_remote {
                     return Ok(LookedUp {
                         inode: existing_inode.clone(),
                         stat: existing_state.stat.clone(),
@@ -957,7 +1813,9 @@ impl SuperblockInner {
                 // updating the parent.
                 let same_kind = remote.kind == existing_inode.kind();
                 let same_etag = existing_state.stat.etag == remote.stat.etag;
-                if same_kind && same_etag && (existing_is_remote || remote.kind == InodeKind::Directory) {
+                let same_target = existing_state.kind_data.symlink_target() == remote.symlink_target.as_ref();
+                if same_kind && same_etag && same_target && (existing_is_remote || remote.kind == InodeKind::Directory)
+                {
                     trace!(parent=?existing_inode.parent(), name=?existing_inode.name(), ino=?existing_inode.ino(), "updating inode in place (slow path)");
                     existing_state.stat = remote.stat.clone();
                     if remote.kind == InodeKind::Directory && !existing_is_remote {
@@ -977,6 +1835,7 @@ impl SuperblockInner {
                 trace!(
                     same_kind,
                     same_etag,
+                    same_target,
                     existing_is_remote,
                     remote_is_dir = remote.kind == InodeKind::Directory,
                     "inode could not be updated in place",
@@ -993,10 +1852,11 @@ impl SuperblockInner {
                 );
                 let state = InodeState {
                     stat: remote.stat.clone(),
-                    kind_data: InodeKindData::default_for(remote.kind),
+                    kind_data: remote.kind_data(),
                     write_status: WriteStatus::Remote,
                     lookup_count: 0,
                     reader_count: 0,
+                    renamed_to: None,
                 };
                 let new_inode =
                     self.create_inode_locked(&parent, &mut parent_state, name, remote.kind, state, false)?;
@@ -1008,6 +1868,122 @@ impl SuperblockInner {
         }
     }
 
+    /// Collect the ancestors of a child of `parent_ino` that may need updating when the child is
+    /// written to S3, from the parent up to the first remote ancestor.
+    fn local_ancestors(&self, parent_ino: InodeNo) -> Result<Vec<Inode>, InodeError> {
+        let mut ancestors = Vec::new();
+        let mut ancestor_ino = parent_ino;
+        let mut visited = HashSet::new();
+        loop {
+            assert!(visited.insert(ancestor_ino), "cycle detected in inode ancestors");
+            let ancestor = self.get(ancestor_ino)?;
+            ancestors.push(ancestor.clone());
+            if ancestor.ino() == ROOT_INODE_NO || ancestor.get_inode_state()?.write_status == WriteStatus::Remote {
+                break;
+            }
+            ancestor_ino = ancestor.parent();
+        }
+        Ok(ancestors)
+    }
+
+    /// Walk up the ancestors from parent to first remote ancestor to transition all "local"
+    /// containing directories of `child_ino` to "remote". The `ancestors_states` must be the
+    /// locked states of `ancestors`, in descending order.
+    fn set_ancestors_remote(
+        child_ino: InodeNo,
+        ancestors: &[Inode],
+        ancestors_states: &mut [RwLockWriteGuard<InodeState>],
+    ) {
+        let children_inos = std::iter::once(child_ino).chain(ancestors.iter().map(|ancestor| ancestor.ino()));
+        for (ancestor_state, child_ino) in ancestors_states.iter_mut().rev().zip(children_inos) {
+            match &mut ancestor_state.kind_data {
+                InodeKindData::File { .. } | InodeKindData::Symlink { .. } => {
+                    unreachable!("we know the ancestor is a directory")
+                }
+                InodeKindData::Directory { writing_children, .. } => {
+                    writing_children.remove(&child_ino);
+                }
+            }
+            ancestor_state.write_status = WriteStatus::Remote;
+        }
+    }
+
+    /// Move a renamed inode to `dst_name` in `dst_parent`, keeping its number. The kernel keeps
+    /// using the same inode at its new name, so from now on the superblock and the destination
+    /// directory resolve its number to a new [Inode] with the new key, replacing whatever was at
+    /// that name before. The caller must already have removed the inode from its old parent.
+    ///
+    /// Open file handles still hold the old [Inode], and can find the moved one with
+    /// [Inode::current].
+    fn move_inode(
+        &self,
+        inode: &Inode,
+        dst_parent: &Inode,
+        dst_name: &str,
+        stat: InodeStat,
+    ) -> Result<Inode, InodeError> {
+        let full_key = self
+            .child_key(dst_parent, dst_name)
+            .ok_or_else(|| InodeError::InvalidFileName(dst_name.into()))?;
+
+        let mut old_state = inode.get_mut_inode_state()?;
+        let kind_data = match &old_state.kind_data {
+            InodeKindData::File {} => InodeKindData::File {},
+            InodeKindData::Symlink { target } => InodeKindData::Symlink { target: target.clone() },
+            InodeKindData::Directory { .. } => unreachable!("directories cannot be renamed"),
+        };
+        let write_status = old_state.write_status;
+        let state = InodeState {
+            stat,
+            kind_data,
+            write_status,
+            lookup_count: old_state.lookup_count,
+            // Handles that are already reading hold the old inode, and stop reading from that one
+            reader_count: 0,
+            renamed_to: None,
+        };
+        trace!(ino=?inode.ino(), parent=?dst_parent.ino(), name=?dst_name, ?full_key, "moving renamed inode");
+        let moved = Inode::new(
+            inode.ino(),
+            dst_parent.ino(),
+            dst_name.to_owned(),
+            full_key,
+            dst_parent.inner.bucket.clone(),
+            inode.kind(),
+            state,
+        );
+        // The kernel's references now belong to the moved inode
+        old_state.lookup_count = 0;
+        old_state.renamed_to = Some(moved.clone());
+        drop(old_state);
+
+        {
+            let mut dst_parent_state = dst_parent.get_mut_inode_state()?;
+            let InodeKindData::Directory {
+                children,
+                writing_children,
+                ..
+            } = &mut dst_parent_state.kind_data
+            else {
+                unreachable!("we know parent is a directory");
+            };
+            if let Some(replaced) = children.insert(dst_name.to_owned(), moved.clone()) {
+                writing_children.remove(&replaced.ino());
+            }
+            if write_status != WriteStatus::Remote {
+                writing_children.insert(moved.ino());
+            }
+        }
+
+        if let Some(stable_inos) = &self.stable_inos {
+            stable_inos.replace(&moved);
+        }
+        if let Some(remembered) = self.inodes.write().unwrap().get_mut(&moved.ino()) {
+            *remembered = moved.clone();
+        }
+        Ok(moved)
+    }
+
     /// Create a new inode in the parent directory, which is already write-locked.
     ///
     /// Don't use this directly unless you need to do inode creation without re-acquiring the parent
@@ -1026,21 +2002,38 @@ impl SuperblockInner {
             return Err(InodeError::InvalidFileName(OsString::from(name)));
         }
 
-        let next_ino = self.next_ino.fetch_add(1, Ordering::SeqCst);
+        let Some(mut full_key) = self.child_key(parent, name) else {
+            warn!(
+                ?name,
+                "name doesn't belong to any key; {} will not be available",
+                kind.as_str()
+            );
+            return Err(InodeError::InvalidFileName(OsString::from(name)));
+        };
 
-        let mut full_key = parent.full_key().to_owned();
-        assert!(full_key.is_empty() || full_key.ends_with('/'));
-        full_key.push_str(name);
         if kind == InodeKind::Directory {
             full_key.push('/');
         }
 
-        trace!(parent=?parent.ino(), ?name, ?kind, new_ino=?next_ino, ?full_key, "creating new inode");
-
-        let inode = Inode::new(next_ino, parent.ino(), name.to_owned(), full_key, kind, state);
+        let new_inode = |ino: InodeNo| {
+            trace!(parent=?parent.ino(), ?name, ?kind, new_ino=?ino, ?full_key, "creating new inode");
+            Inode::new(
+                ino,
+                parent.ino(),
+                name.to_owned(),
+                full_key.clone(),
+                parent.inner.bucket.clone(),
+                kind,
+                state,
+            )
+        };
+        let inode = match &self.stable_inos {
+            Some(stable_inos) => stable_inos.create(parent.bucket(), &full_key, new_inode),
+            None => new_inode(self.next_ino.fetch_add(1, Ordering::SeqCst)),
+        };
 
         match &mut parent_locked.kind_data {
-            InodeKindData::File {} => {
+            InodeKindData::File { .. } | InodeKindData::Symlink { .. } => {
                 debug_assert!(false, "inodes never change kind");
                 return Err(InodeError::NotADirectory(parent.err()));
             }
@@ -1051,7 +2044,7 @@ impl SuperblockInner {
             } => {
                 let existing_inode = children.insert(name.to_owned(), inode.clone());
                 if is_new_file {
-                    writing_children.insert(next_ino);
+                    writing_children.insert(inode.ino());
                 }
                 if let Some(existing_inode) = existing_inode {
                     writing_children.remove(&existing_inode.ino());
@@ -1068,6 +2061,46 @@ impl SuperblockInner {
 pub struct RemoteLookup {
     kind: InodeKind,
     stat: InodeStat,
+    /// The target of the object if it is a symbolic link
+    symlink_target: Option<OsString>,
+}
+
+impl RemoteLookup {
+    /// Build a [RemoteLookup] for an object, which is a symbolic link if it has a target
+    fn for_object(stat: InodeStat, symlink_target: Option<OsString>) -> Self {
+        let kind = if symlink_target.is_some() {
+            InodeKind::Symlink
+        } else {
+            InodeKind::File
+        };
+        Self {
+            kind,
+            stat,
+            symlink_target,
+        }
+    }
+
+    fn for_directory(stat: InodeStat) -> Self {
+        Self {
+            kind: InodeKind::Directory,
+            stat,
+            symlink_target: None,
+        }
+    }
+
+    fn kind_data(&self) -> InodeKindData {
+        match &self.symlink_target {
+            Some(target) => InodeKindData::Symlink { target: target.clone() },
+            None => InodeKindData::default_for(self.kind),
+        }
+    }
+}
+
+/// Get the target of a symbolic link from the user-defined metadata of its object, if it has one
+fn symlink_target_from_metadata(object_metadata: &HashMap<String, String>) -> Option<OsString> {
+    let encoded_target = object_metadata.get(SYMLINK_TARGET_METADATA_KEY)?;
+    let target: Vec<u8> = percent_decode_str(encoded_target).collect();
+    Some(OsString::from_vec(target))
 }
 
 /// Result of a call to [Superblock::lookup] or [Superblock::getattr]. `stat` is a copy of the
@@ -1086,6 +2119,18 @@ impl LookedUp {
     }
 }
 
+/// An inode in the metadata cache, as returned by [Superblock::cached_inodes]
+#[derive(Debug, Clone)]
+pub struct CachedInode {
+    pub ino: InodeNo,
+    pub kind: InodeKind,
+    pub full_key: String,
+    /// How many references to the inode the kernel holds
+    pub lookup_count: u64,
+    /// How much longer the inode's metadata is cached for
+    pub validity: Duration,
+}
+
 /// Handle for a file writing that we use to interact with [Superblock]
 #[derive(Debug, Clone)]
 pub struct WriteHandle {
@@ -1095,6 +2140,8 @@ pub struct WriteHandle {
     pid: u32,
     allow_overwrite: bool,
     is_truncate: bool,
+    /// Whether writes go to the end of the existing object instead of replacing it
+    is_append: bool,
 }
 
 impl WriteHandle {
@@ -1106,6 +2153,7 @@ impl WriteHandle {
         pid: u32,
         allow_overwrite: bool,
         is_truncate: bool,
+        is_append: bool,
     ) -> Self {
         Self {
             inner,
@@ -1114,6 +2162,7 @@ impl WriteHandle {
             pid,
             allow_overwrite,
             is_truncate,
+            is_append,
         }
     }
 
@@ -1139,15 +2188,18 @@ impl WriteHandle {
                     return Err(InodeError::InodeNotWritable(inode.err()));
                 }
 
-                if !self.is_truncate {
+                if !self.is_truncate && !self.is_append {
                     tracing::warn!(
-                        "modifying an existing file is only allowed when the file is opened in truncate mode (O_TRUNC)"
+                        "modifying an existing file is only allowed when the file is opened in truncate mode (O_TRUNC) or, on S3 Express One Zone, append mode (O_APPEND)"
                     );
                     return Err(InodeError::InodeNotWritable(inode.err()));
                 }
 
                 state.write_status = WriteStatus::LocalOpen;
-                state.stat.size = 0;
+                // Appends keep the existing contents, so the file keeps its size until it's written to.
+                if !self.is_append {
+                    state.stat.size = 0;
+                }
                 Ok(self)
             }
         }
@@ -1164,21 +2216,7 @@ impl WriteHandle {
 
         // Collect ancestor inodes that may need updating,
         // from parent to first remote ancestor.
-        let ancestors = {
-            let mut ancestors = Vec::new();
-            let mut ancestor_ino = self.parent_ino;
-            let mut visited = HashSet::new();
-            loop {
-                assert!(visited.insert(ancestor_ino), "cycle detected in inode ancestors");
-                let ancestor = self.inner.get(ancestor_ino)?;
-                ancestors.push(ancestor.clone());
-                if ancestor.ino() == ROOT_INODE_NO || ancestor.get_inode_state()?.write_status == WriteStatus::Remote {
-                    break;
-                }
-                ancestor_ino = ancestor.parent();
-            }
-            ancestors
-        };
+        let ancestors = self.inner.local_ancestors(self.parent_ino)?;
 
         // Acquire locks on ancestors in descending order to avoid deadlocks.
         let mut ancestors_states = ancestors
@@ -1192,21 +2230,13 @@ impl WriteHandle {
             WriteStatus::LocalOpen => {
                 state.write_status = WriteStatus::Remote;
 
+                // The upload replaced any object we were hiding at this key
+                self.inner.unhide_key(inode.bucket(), inode.full_key());
+
                 // Invalidate the inode's stats so we refresh them from S3 when next queried
                 state.stat.update_validity(Duration::from_secs(0));
 
-                // Walk up the ancestors from parent to first remote ancestor to transition
-                // the inode and all "local" containing directories to "remote".
-                let children_inos = std::iter::once(self.ino).chain(ancestors.iter().map(|ancestor| ancestor.ino()));
-                for (ancestor_state, child_ino) in ancestors_states.iter_mut().rev().zip(children_inos) {
-                    match &mut ancestor_state.kind_data {
-                        InodeKindData::File { .. } => unreachable!("we know the ancestor is a directory"),
-                        InodeKindData::Directory { writing_children, .. } => {
-                            writing_children.remove(&child_ino);
-                        }
-                    }
-                    ancestor_state.write_status = WriteStatus::Remote;
-                }
+                SuperblockInner::set_ancestors_remote(self.ino, &ancestors, &mut ancestors_states);
 
                 Ok(())
             }
@@ -1228,6 +2258,9 @@ struct InodeInner {
     name: String,
     // TODO deduplicate keys by string interning or something -- many keys will have common prefixes
     full_key: String,
+    /// The bucket the key is in. Only the directories of a multi-bucket namespace differ from the
+    /// mounted bucket, and their descendants share the same string.
+    bucket: Arc<str>,
     kind: InodeKind,
     checksum: Crc32c,
 
@@ -1265,6 +2298,10 @@ impl Inode {
         &self.inner.full_key
     }
 
+    pub fn bucket(&self) -> &str {
+        &self.inner.bucket
+    }
+
     /// Increment lookup count for [Inode] by 1, returning the new value.
     /// This should be called whenever we pass a `fuse_reply_entry` or `fuse_reply_create` struct to the FUSE driver.
     ///
@@ -1297,6 +2334,19 @@ impl Inode {
         *lookup_count
     }
 
+    /// The inode this one was moved to by `rename`, following any later renames too, or this inode
+    /// if it was never renamed. The moved inode has the same number, but a new parent and key.
+    pub fn current(&self) -> Inode {
+        let mut inode = self.clone();
+        loop {
+            let renamed_to = inode.inner.sync.read().unwrap().renamed_to.clone();
+            match renamed_to {
+                Some(moved) => inode = moved,
+                None => return inode,
+            }
+        }
+    }
+
     pub fn is_remote(&self) -> Result<bool, InodeError> {
         let state = self.get_inode_state()?;
         Ok(state.write_status == WriteStatus::Remote)
@@ -1343,7 +2393,15 @@ impl Inode {
         }
     }
 
-    fn new(ino: InodeNo, parent: InodeNo, name: String, full_key: String, kind: InodeKind, state: InodeState) -> Self {
+    fn new(
+        ino: InodeNo,
+        parent: InodeNo,
+        name: String,
+        full_key: String,
+        bucket: Arc<str>,
+        kind: InodeKind,
+        state: InodeState,
+    ) -> Self {
         let checksum = Self::compute_checksum(ino, &full_key);
         let sync = RwLock::new(state);
         let inner = InodeInner {
@@ -1351,6 +2409,7 @@ impl Inode {
             parent,
             name,
             full_key,
+            bucket,
             kind,
             checksum,
             sync,
@@ -1418,12 +2477,16 @@ struct InodeState {
     lookup_count: u64,
     /// Number of active prefetching streams on the [Inode].
     reader_count: u64,
+    /// The inode that replaced this one when it was renamed (`rename`). It has the same number, but
+    /// a new parent and key.
+    renamed_to: Option<Inode>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InodeKind {
     File,
     Directory,
+    Symlink,
 }
 
 impl InodeKind {
@@ -1431,6 +2494,7 @@ impl InodeKind {
         match self {
             InodeKind::File => "file",
             InodeKind::Directory => "directory",
+            InodeKind::Symlink => "symlink",
         }
     }
 }
@@ -1440,6 +2504,7 @@ impl From<InodeKind> for FileType {
         match kind {
             InodeKind::File => FileType::RegularFile,
             InodeKind::Directory => FileType::Directory,
+            InodeKind::Symlink => FileType::Symlink,
         }
     }
 }
@@ -1461,6 +2526,10 @@ enum InodeKindData {
         /// True if this directory has been deleted (`rmdir`) from its parent
         deleted: bool,
     },
+    Symlink {
+        /// The path this symbolic link points to
+        target: OsString,
+    },
 }
 
 impl InodeKindData {
@@ -1472,6 +2541,14 @@ impl InodeKindData {
                 writing_children: Default::default(),
                 deleted: false,
             },
+            InodeKind::Symlink => unreachable!("symlinks always have a target"),
+        }
+    }
+
+    fn symlink_target(&self) -> Option<&OsString> {
+        match self {
+            Self::Symlink { target } => Some(target),
+            _ => None,
         }
     }
 }
@@ -1492,10 +2569,22 @@ pub struct InodeStat {
     pub atime: OffsetDateTime,
     /// Etag for the file (object)
     pub etag: Option<String>,
+    /// Storage class of the object, if S3 reported one
+    pub storage_class: Option<String>,
+    /// Restoration status of the object, if it is in a flexible retrieval storage class and a
+    /// restore has been requested
+    pub restore_status: Option<RestoreStatus>,
+    /// Version of the object that reads should be pinned to, or `None` to read the object's
+    /// current version
+    pub version_id: Option<String>,
     /// Inodes corresponding to S3 objects with GLACIER or DEEP_ARCHIVE storage classes
     /// are only readable after restoration. For objects with other storage classes
     /// this field should be always `true`.
     pub is_readable: bool,
+    /// User-defined metadata of the object, if we've fetched it. ListObjectsV2 doesn't return
+    /// metadata, so this is only known once we've done a HeadObject, and is dropped with the rest
+    /// of the stat when it's refreshed.
+    pub user_metadata: Option<Arc<HashMap<String, String>>>,
 }
 
 /// Inode write status (local vs remote)
@@ -1518,9 +2607,9 @@ impl InodeStat {
     /// restored, and so we override their permissions to 000 and reject reads to them. We also warn
     /// the first time we see an object like this, because FUSE enforces the 000 permissions on our
     /// behalf so we might not see an attempted `open` call.
-    fn is_readable(storage_class: Option<String>, restore_status: Option<RestoreStatus>) -> bool {
+    fn is_readable(storage_class: Option<&str>, restore_status: Option<RestoreStatus>) -> bool {
         static HAS_SENT_WARNING: AtomicBool = AtomicBool::new(false);
-        match storage_class.as_deref() {
+        match storage_class {
             Some("GLACIER") | Some("DEEP_ARCHIVE") => {
                 let restored =
                     matches!(restore_status, Some(RestoreStatus::Restored { expiry }) if expiry > SystemTime::now());
@@ -1544,7 +2633,7 @@ impl InodeStat {
         restore_status: Option<RestoreStatus>,
         validity: Duration,
     ) -> InodeStat {
-        let is_readable = Self::is_readable(storage_class, restore_status);
+        let is_readable = Self::is_readable(storage_class.as_deref(), restore_status);
         InodeStat {
             expiry: Expiry::from_now(validity),
             size,
@@ -1552,7 +2641,11 @@ impl InodeStat {
             ctime: datetime,
             mtime: datetime,
             etag,
+            storage_class,
+            restore_status,
+            version_id: None,
             is_readable,
+            user_metadata: None,
         }
     }
 
@@ -1565,7 +2658,11 @@ impl InodeStat {
             ctime: datetime,
             mtime: datetime,
             etag: None,
+            storage_class: None,
+            restore_status: None,
+            version_id: None,
             is_readable: true,
+            user_metadata: None,
         }
     }
 
@@ -1612,6 +2709,8 @@ pub enum InodeError {
     InodeDoesNotExist(InodeNo),
     #[error("invalid file name {0:?}")]
     InvalidFileName(OsString),
+    #[error("file name {0:?} is too long")]
+    NameTooLong(OsString),
     #[error("inode {0} is not a directory")]
     NotADirectory(InodeErrorInfo),
     #[error("inode {0} is a directory")]
@@ -1628,14 +2727,28 @@ pub enum InodeError {
     InodeNotReadableWhileWriting(InodeErrorInfo),
     #[error("inode {0} is not writable while being read")]
     InodeNotWritableWhileReading(InodeErrorInfo),
-    #[error("remote directory cannot be removed at inode {0}")]
+    #[error("remote directory cannot be removed without deleting its directory marker at inode {0}")]
     CannotRemoveRemoteDirectory(InodeErrorInfo),
     #[error("non-empty directory cannot be removed at inode {0}")]
     DirectoryNotEmpty(InodeErrorInfo),
     #[error("inode {0} cannot be unlinked while being written")]
     UnlinkNotPermittedWhileWriting(InodeErrorInfo),
+    #[error("inode {0} cannot be renamed while being written")]
+    RenameNotPermittedWhileWriting(InodeErrorInfo),
+    #[error("directory cannot be renamed at inode {0}")]
+    CannotRenameDirectory(InodeErrorInfo),
+    #[error("inode {0} is not a symbolic link")]
+    NotASymlink(InodeErrorInfo),
+    #[error("symbolic link target {0:?} is too long")]
+    SymlinkTargetTooLong(OsString),
     #[error("corrupted metadata for inode {0}")]
     CorruptedMetadata(InodeErrorInfo),
+    #[error("inode {0} is the root of a composite namespace and cannot be modified")]
+    NamespaceRootNotWritable(InodeErrorInfo),
+    #[error("inode {0} cannot be renamed into a different bucket")]
+    CrossBucketRename(InodeErrorInfo),
+    #[error("directories cannot be created in inode {0}, which is in a flat namespace")]
+    DirectoryInFlatNamespace(InodeErrorInfo),
     #[error("inode {0} is a remote inode and its attributes cannot be modified")]
     SetAttrNotPermittedOnRemoteInode(InodeErrorInfo),
     #[error("inode {old_inode} for remote key {remote_key:?} is stale, replaced by inode {new_inode}")]
@@ -1651,13 +2764,14 @@ mod tests {
     use std::str::FromStr;
 
     use mountpoint_s3_client::{
-        mock_client::{MockClient, MockClientConfig, MockObject},
+        mock_client::{MockClient, MockClientConfig, MockObject, Operation},
         types::ETag,
     };
     use test_case::test_case;
     use time::{Duration, OffsetDateTime};
 
     use crate::fs::{ToErrno, FUSE_ROOT_INODE};
+    use crate::s3::router::BucketRouter;
 
     use super::*;
 
@@ -1828,6 +2942,7 @@ mod tests {
                     ..Default::default()
                 },
                 s3_personality: S3Personality::Standard,
+                ..Default::default()
             },
         );
 
@@ -1880,9 +2995,11 @@ mod tests {
                     serve_lookup_from_cache: true,
                     dir_ttl: ttl,
                     file_ttl: ttl,
+                    negative_cache_ttl: ttl,
                     ..Default::default()
                 },
                 s3_personality: S3Personality::Standard,
+                ..Default::default()
             },
         );
 
@@ -1929,6 +3046,7 @@ mod tests {
             ROOT_INODE_NO,
             inode_name.to_owned(),
             inode_name.to_owned(),
+            "test_bucket".into(),
             InodeKind::File,
             InodeState {
                 write_status: WriteStatus::Remote,
@@ -1936,6 +3054,7 @@ mod tests {
                 kind_data: InodeKindData::File {},
                 lookup_count: 5,
                 reader_count: 0,
+                renamed_to: None,
             },
         );
         superblock.inner.inodes.write().unwrap().insert(ino, inode.clone());
@@ -2005,6 +3124,104 @@ mod tests {
         assert_eq!(lookup_count, 1);
     }
 
+    #[tokio::test]
+    async fn test_forget_batch() {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        client.add_object("dir/a", b"a".into());
+        client.add_object("dir/b", b"b".into());
+
+        let superblock = Superblock::new("test_bucket", &Default::default(), Default::default());
+
+        let dir = superblock.lookup(&client, ROOT_INODE_NO, "dir".as_ref()).await.unwrap();
+        let dir_ino = dir.inode.ino();
+        let a = superblock.lookup(&client, dir_ino, "a".as_ref()).await.unwrap();
+        superblock.lookup(&client, dir_ino, "a".as_ref()).await.unwrap();
+        let b = superblock.lookup(&client, dir_ino, "b".as_ref()).await.unwrap();
+        let (a_ino, b_ino) = (a.inode.ino(), b.inode.ino());
+        drop((dir, a, b));
+
+        // Forgetting only some of the references keeps the inode
+        superblock.forget_batch([(a_ino, 1), (b_ino, 1)]);
+        superblock
+            .getattr(&client, a_ino, false)
+            .await
+            .expect("a is still referenced");
+        let err = superblock
+            .getattr(&client, b_ino, false)
+            .await
+            .expect_err("b should be removed");
+        assert!(matches!(err, InodeError::InodeDoesNotExist(_)));
+
+        // Children are forgotten before their parents in the same batch
+        superblock.forget_batch([(a_ino, 1), (dir_ino, 1)]);
+        for ino in [a_ino, dir_ino] {
+            let err = superblock
+                .getattr(&client, ino, false)
+                .await
+                .expect_err("inode should be removed");
+            assert!(matches!(err, InodeError::InodeDoesNotExist(_)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forget_grace_period() {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+
+        let name = "foo";
+        client.add_object(name, b"foo".into());
+        client.add_object("bar", b"bar".into());
+
+        let grace_period = std::time::Duration::from_millis(100);
+        let superblock = Superblock::new(
+            "test_bucket",
+            &Default::default(),
+            SuperblockConfig {
+                cache_config: CacheConfig {
+                    serve_lookup_from_cache: true,
+                    dir_ttl: std::time::Duration::from_secs(600),
+                    file_ttl: std::time::Duration::from_secs(600),
+                    forgotten_inode_grace_period: grace_period,
+                    ..Default::default()
+                },
+                s3_personality: S3Personality::Standard,
+                ..Default::default()
+            },
+        );
+
+        let head_counter = client.new_counter(Operation::HeadObject);
+
+        let lookup = superblock.lookup(&client, ROOT_INODE_NO, name.as_ref()).await.unwrap();
+        let ino = lookup.inode.ino();
+        assert_eq!(head_counter.count(), 1);
+        superblock.forget(ino, 1);
+
+        // The forgotten inode is still cached, so looking it up again doesn't go to S3
+        let lookup = superblock.lookup(&client, ROOT_INODE_NO, name.as_ref()).await.unwrap();
+        assert_eq!(lookup.inode.ino(), ino);
+        assert_eq!(head_counter.count(), 1);
+        superblock.forget(ino, 1);
+
+        // Once the grace period ends, the next forget removes the inode
+        let other = superblock.lookup(&client, ROOT_INODE_NO, "bar".as_ref()).await.unwrap();
+        std::thread::sleep(grace_period);
+        superblock.forget(other.inode.ino(), 1);
+        let err = superblock
+            .getattr(&client, ino, false)
+            .await
+            .expect_err("Inode should not be valid");
+        assert!(matches!(err, InodeError::InodeDoesNotExist(_)));
+    }
+
     #[tokio::test]
     async fn test_forget_shadowed_inode() {
         let client_config = MockClientConfig {
@@ -2112,41 +3329,94 @@ mod tests {
         }
     }
 
-    #[test_case(""; "unprefixed")]
-    #[test_case("test_prefix/"; "prefixed")]
+    #[test_case(true; "ordered")]
+    #[test_case(false; "unordered")]
     #[tokio::test]
-    async fn test_readdir_no_remote_keys(prefix: &str) {
+    async fn test_readdir_lists_pages_lazily(ordered: bool) {
         let client_config = MockClientConfig {
             bucket: "test_bucket".to_string(),
             part_size: 1024 * 1024,
-            ..Default::default()
+            unordered_list_seed: (!ordered).then_some(123456),
         };
         let client = Arc::new(MockClient::new(client_config));
+        let list_counter = client.new_counter(Operation::ListObjectsV2);
 
-        let prefix = Prefix::new(prefix).expect("valid prefix");
-        let superblock = Superblock::new("test_bucket", &prefix, Default::default());
-
-        let mut expected_list = Vec::new();
-
-        // Create local keys
-        for i in 0..5 {
-            let filename = format!("file{i}.txt");
-            let new_inode = superblock
-                .create(
-                    &client,
-                    FUSE_ROOT_INODE,
-                    OsStr::from_bytes(filename.as_bytes()),
-                    InodeKind::File,
-                )
-                .await
-                .unwrap();
-            superblock
-                .write(&client, new_inode.inode.ino(), FUSE_ROOT_INODE, 0, false, false)
-                .await;
-            expected_list.push(filename);
+        // Mix of files and directories so that pages contain both objects and common prefixes
+        let keys = ["a", "b/file.txt", "c", "d/file.txt", "e", "f/file.txt", "g"];
+        for key in keys {
+            client.add_object(key, MockObject::constant(0xaa, 30, ETag::for_tests()));
         }
 
-        // Try it all twice to test inode reuse
+        let s3_personality = if ordered {
+            S3Personality::Standard
+        } else {
+            S3Personality::ExpressOneZone
+        };
+        let superblock = Superblock::new(
+            "test_bucket",
+            &Default::default(),
+            SuperblockConfig {
+                s3_personality,
+                ..Default::default()
+            },
+        );
+
+        let page_size = 2;
+        let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, page_size).await.unwrap();
+        assert_eq!(list_counter.count(), 0, "opening the handle should not list");
+
+        // Each page is only requested once the previous one has been fully consumed
+        let mut names = vec![];
+        for i in 0..keys.len() {
+            let entry = dir_handle.next(&client).await.unwrap().expect("entry should exist");
+            names.push(entry.inode.name().to_owned());
+            assert_eq!(list_counter.count(), (i / page_size + 1) as u64);
+        }
+        assert!(dir_handle.next(&client).await.unwrap().is_none());
+
+        let mut expected = vec!["a", "b", "c", "d", "e", "f", "g"];
+        if !ordered {
+            names.sort();
+            expected.sort();
+        }
+        assert_eq!(names, expected);
+    }
+
+    #[test_case(""; "unprefixed")]
+    #[test_case("test_prefix/"; "prefixed")]
+    #[tokio::test]
+    async fn test_readdir_no_remote_keys(prefix: &str) {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+
+        let prefix = Prefix::new(prefix).expect("valid prefix");
+        let superblock = Superblock::new("test_bucket", &prefix, Default::default());
+
+        let mut expected_list = Vec::new();
+
+        // Create local keys
+        for i in 0..5 {
+            let filename = format!("file{i}.txt");
+            let new_inode = superblock
+                .create(
+                    &client,
+                    FUSE_ROOT_INODE,
+                    OsStr::from_bytes(filename.as_bytes()),
+                    InodeKind::File,
+                )
+                .await
+                .unwrap();
+            superblock
+                .write(&client, new_inode.inode.ino(), FUSE_ROOT_INODE, 0, false, false, false)
+                .await;
+            expected_list.push(filename);
+        }
+
+        // Try it all twice to test inode reuse
         for _ in 0..2 {
             let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 2).await.unwrap();
             let entries = dir_handle.collect(&client).await.unwrap();
@@ -2197,7 +3467,7 @@ mod tests {
                 .await
                 .unwrap();
             superblock
-                .write(&client, new_inode.inode.ino(), FUSE_ROOT_INODE, 0, false, false)
+                .write(&client, new_inode.inode.ino(), FUSE_ROOT_INODE, 0, false, false, false)
                 .await;
             expected_list.push(filename);
         }
@@ -2279,7 +3549,7 @@ mod tests {
             .expect("Should be able to create directory");
 
         superblock
-            .rmdir(&client, FUSE_ROOT_INODE, dirname.as_ref())
+            .rmdir(&client, FUSE_ROOT_INODE, dirname.as_ref(), false)
             .await
             .expect("rmdir on empty local directory should succeed");
 
@@ -2299,6 +3569,401 @@ mod tests {
             .expect_err("should not do getattr on removed directory");
     }
 
+    #[tokio::test]
+    async fn test_composite_namespace() {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        client.add_object("logs/2024/app.log", MockObject::constant(0xaa, 30, ETag::for_tests()));
+        client.add_object("data/file.txt", MockObject::constant(0xaa, 30, ETag::for_tests()));
+        client.add_object("unmounted/file.txt", MockObject::constant(0xaa, 30, ETag::for_tests()));
+
+        let namespace = CompositeNamespace::new(
+            "test_bucket",
+            ["logs/2024/", "data/"].map(|prefix| Prefix::new(prefix).unwrap()),
+        )
+        .expect("prefixes should be disjoint");
+        let superblock = Superblock::new(
+            "test_bucket",
+            &Default::default(),
+            SuperblockConfig {
+                namespace: Some(namespace),
+                ..Default::default()
+            },
+        );
+
+        // The root only contains the directories for each prefix
+        let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 2).await.unwrap();
+        let entries = dir_handle.collect(&client).await.unwrap();
+        assert_eq!(
+            entries.iter().map(|entry| entry.inode.name()).collect::<Vec<_>>(),
+            vec!["2024", "data"]
+        );
+        let err = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "unmounted".as_ref())
+            .await
+            .expect_err("unmounted prefix should not be visible");
+        assert_eq!(err.to_errno(), libc::ENOENT);
+
+        // Below the root, each directory is backed by its prefix
+        let logs = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "2024".as_ref())
+            .await
+            .unwrap();
+        assert_eq!(logs.inode.full_key(), "logs/2024/");
+        let log = superblock
+            .lookup(&client, logs.inode.ino(), "app.log".as_ref())
+            .await
+            .unwrap();
+        assert_eq!(log.inode.full_key(), "logs/2024/app.log");
+        let data = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "data".as_ref())
+            .await
+            .unwrap();
+        let dir_handle = superblock.readdir(&client, data.inode.ino(), 2).await.unwrap();
+        let entries = dir_handle.collect(&client).await.unwrap();
+        assert_eq!(
+            entries.iter().map(|entry| entry.inode.full_key()).collect::<Vec<_>>(),
+            vec!["data/file.txt"]
+        );
+
+        // Nothing can be added to or removed from the root, but the directories are writable
+        let err = superblock
+            .create(&client, FUSE_ROOT_INODE, "new_dir".as_ref(), InodeKind::Directory)
+            .await
+            .expect_err("root should not be writable");
+        assert_eq!(err.to_errno(), libc::EPERM);
+        let err = superblock
+            .rmdir(&client, FUSE_ROOT_INODE, "data".as_ref(), true)
+            .await
+            .expect_err("prefix directory should not be removable");
+        assert_eq!(err.to_errno(), libc::EPERM);
+        let new_dir = superblock
+            .create(&client, data.inode.ino(), "new_dir".as_ref(), InodeKind::Directory)
+            .await
+            .unwrap();
+        assert_eq!(new_dir.inode.full_key(), "data/new_dir/");
+    }
+
+    #[tokio::test]
+    async fn test_multi_bucket_namespace() {
+        let mock_client = |bucket: &str| {
+            MockClient::new(MockClientConfig {
+                bucket: bucket.to_string(),
+                part_size: 1024 * 1024,
+                ..Default::default()
+            })
+        };
+        let mut client = BucketRouter::new(mock_client("bucket-a"));
+        client.add_bucket("bucket-b", mock_client("bucket-b"));
+        client
+            .client("bucket-a")
+            .add_object("a.txt", MockObject::constant(0xaa, 10, ETag::for_tests()));
+        client
+            .client("bucket-b")
+            .add_object("logs/b.txt", MockObject::constant(0xbb, 20, ETag::for_tests()));
+
+        let namespace = CompositeNamespace::for_buckets([
+            NamespaceEntry {
+                name: "data".to_owned(),
+                bucket: "bucket-a".to_owned(),
+                prefix: Default::default(),
+            },
+            NamespaceEntry {
+                name: "logs".to_owned(),
+                bucket: "bucket-b".to_owned(),
+                prefix: Prefix::new("logs/").unwrap(),
+            },
+        ])
+        .unwrap();
+        let superblock = Superblock::new(
+            "bucket-a",
+            &Default::default(),
+            SuperblockConfig {
+                namespace: Some(namespace),
+                ..Default::default()
+            },
+        );
+
+        // Each directory is backed by its own bucket
+        let data = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "data".as_ref())
+            .await
+            .unwrap();
+        let a = superblock
+            .lookup(&client, data.inode.ino(), "a.txt".as_ref())
+            .await
+            .unwrap();
+        assert_eq!((a.inode.bucket(), a.stat.size), ("bucket-a", 10));
+        let logs = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "logs".as_ref())
+            .await
+            .unwrap();
+        let dir_handle = superblock.readdir(&client, logs.inode.ino(), 2).await.unwrap();
+        let entries = dir_handle.collect(&client).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].inode.bucket(), "bucket-b");
+        assert_eq!(entries[0].inode.full_key(), "logs/b.txt");
+
+        // Files can't be renamed from one bucket to another
+        let err = superblock
+            .rename(
+                &client,
+                data.inode.ino(),
+                "a.txt".as_ref(),
+                logs.inode.ino(),
+                "a.txt".as_ref(),
+                false,
+                &Default::default(),
+            )
+            .await
+            .expect_err("can't rename across buckets");
+        assert_eq!(err.to_errno(), libc::EXDEV);
+    }
+
+    #[test_case(""; "unprefixed")]
+    #[test_case("test_prefix/"; "prefixed")]
+    #[tokio::test]
+    async fn test_flat_keys(prefix: &str) {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        for key in ["file.txt", "logs/2024/01.log", "logs/2024/02.log", "50%off", "a//b"] {
+            client.add_object(
+                &format!("{prefix}{key}"),
+                MockObject::constant(0xaa, 30, ETag::for_tests()),
+            );
+        }
+        // Escaping makes this key's name longer than any file name can be
+        let long_key = format!("{}end", "d/".repeat(100));
+        client.add_object(
+            &format!("{prefix}{long_key}"),
+            MockObject::constant(0xaa, 30, ETag::for_tests()),
+        );
+
+        let prefix = Prefix::new(prefix).expect("valid prefix");
+        let superblock = Superblock::new(
+            "test_bucket",
+            &prefix,
+            SuperblockConfig {
+                flat_keys: true,
+                ..Default::default()
+            },
+        );
+
+        // Every key is a file at the root, with `/` and `%` escaped
+        let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 2).await.unwrap();
+        let entries = dir_handle.collect(&client).await.unwrap();
+        assert!(entries.iter().all(|entry| entry.inode.kind() == InodeKind::File));
+        let mut names = entries.iter().map(|entry| entry.inode.name()).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "50%25off",
+                "a%2F%2Fb",
+                "file.txt",
+                "logs%2F2024%2F01.log",
+                "logs%2F2024%2F02.log"
+            ]
+        );
+
+        let log = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "logs%2F2024%2F01.log".as_ref())
+            .await
+            .unwrap();
+        assert_eq!(log.inode.full_key(), format!("{prefix}logs/2024/01.log"));
+        assert_eq!(log.inode.kind(), InodeKind::File);
+
+        // Names that are too long aren't listed, and can't be looked up either
+        let long_name = flat::name_for_key(&long_key);
+        assert!(long_name.len() > MAX_NAME_LENGTH);
+        let err = superblock
+            .lookup(&client, FUSE_ROOT_INODE, long_name.as_ref())
+            .await
+            .expect_err("name is too long");
+        assert_eq!(err.to_errno(), libc::ENAMETOOLONG);
+
+        // There are no directories, and names that aren't how a key is escaped don't exist
+        for name in ["logs", "50%off", "logs%2f2024%2f01.log"] {
+            let err = superblock
+                .lookup(&client, FUSE_ROOT_INODE, name.as_ref())
+                .await
+                .expect_err("name should not exist");
+            assert_eq!(err.to_errno(), libc::ENOENT, "lookup of {name:?}");
+        }
+
+        let err = superblock
+            .create(&client, FUSE_ROOT_INODE, "new_dir".as_ref(), InodeKind::Directory)
+            .await
+            .expect_err("directories can't be created");
+        assert_eq!(err.to_errno(), libc::EPERM);
+        let new_file = superblock
+            .create(&client, FUSE_ROOT_INODE, "new%2Ffile.txt".as_ref(), InodeKind::File)
+            .await
+            .unwrap();
+        assert_eq!(new_file.inode.full_key(), format!("{prefix}new/file.txt"));
+        let err = superblock
+            .create(&client, FUSE_ROOT_INODE, "new%file.txt".as_ref(), InodeKind::File)
+            .await
+            .expect_err("name doesn't belong to any key");
+        assert_eq!(err.to_errno(), libc::EINVAL);
+    }
+
+    #[tokio::test]
+    async fn test_stable_inode_numbers() {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        for key in ["dir/a", "dir/b", "c"] {
+            client.add_object(key, MockObject::constant(0xaa, 10, ETag::for_tests()));
+        }
+        let config = SuperblockConfig {
+            stable_inode_numbers: true,
+            ..Default::default()
+        };
+
+        // Find every inode by lookup on one superblock
+        let superblock = Superblock::new("test_bucket", &Prefix::default(), config.clone());
+        let dir = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "dir".as_ref())
+            .await
+            .unwrap();
+        let mut inos = HashMap::new();
+        inos.insert("dir".to_owned(), dir.inode.ino());
+        for name in ["a", "b"] {
+            let lookup = superblock
+                .lookup(&client, dir.inode.ino(), name.as_ref())
+                .await
+                .unwrap();
+            inos.insert(format!("dir/{name}"), lookup.inode.ino());
+        }
+        let lookup = superblock.lookup(&client, FUSE_ROOT_INODE, "c".as_ref()).await.unwrap();
+        inos.insert("c".to_owned(), lookup.inode.ino());
+
+        // A new superblock, like after remounting, finds the same numbers in a different order
+        let superblock = Superblock::new("test_bucket", &Prefix::default(), config);
+        let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 2).await.unwrap();
+        let entries = dir_handle.collect(&client).await.unwrap();
+        let mut remounted = HashMap::new();
+        for entry in entries {
+            remounted.insert(entry.inode.name().to_owned(), entry.inode.ino());
+        }
+        let dir_handle = superblock.readdir(&client, remounted["dir"], 2).await.unwrap();
+        let entries = dir_handle.collect(&client).await.unwrap();
+        for entry in entries {
+            remounted.insert(format!("dir/{}", entry.inode.name()), entry.inode.ino());
+        }
+        assert_eq!(remounted, inos);
+    }
+
+    #[test_case("", true; "unprefixed ordered")]
+    #[test_case("test_prefix/", true; "prefixed ordered")]
+    #[test_case("", false; "unprefixed unordered")]
+    #[test_case("test_prefix/", false; "prefixed unordered")]
+    #[tokio::test]
+    async fn test_shadowed_file_suffix(prefix: &str, ordered: bool) {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            unordered_list_seed: (!ordered).then_some(123456),
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        client.add_object(&format!("{prefix}a"), MockObject::constant(0xaa, 10, ETag::for_tests()));
+        client.add_object(
+            &format!("{prefix}a/b"),
+            MockObject::constant(0xaa, 20, ETag::for_tests()),
+        );
+        client.add_object(&format!("{prefix}c"), MockObject::constant(0xaa, 30, ETag::for_tests()));
+        client.add_object(
+            &format!("{prefix}d.shadowed"),
+            MockObject::constant(0xaa, 40, ETag::for_tests()),
+        );
+
+        let prefix = Prefix::new(prefix).expect("valid prefix");
+        let superblock = Superblock::new(
+            "test_bucket",
+            &prefix,
+            SuperblockConfig {
+                s3_personality: if ordered {
+                    S3Personality::Standard
+                } else {
+                    S3Personality::ExpressOneZone
+                },
+                shadowed_file_suffix: Some(".shadowed".to_owned()),
+                ..Default::default()
+            },
+        );
+
+        // The shadowed file appears with the suffix, and keys with names reserved for shadowed
+        // files are hidden
+        let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 20).await.unwrap();
+        let entries = dir_handle.collect(&client).await.unwrap();
+        let mut entries = entries
+            .iter()
+            .map(|entry| (entry.inode.name(), entry.inode.full_key(), entry.inode.kind()))
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|(name, _, _)| *name);
+        assert_eq!(
+            entries,
+            vec![
+                ("a", format!("{prefix}a/").as_str(), InodeKind::Directory),
+                ("a.shadowed", format!("{prefix}a").as_str(), InodeKind::File),
+                ("c", format!("{prefix}c").as_str(), InodeKind::File),
+            ]
+        );
+
+        let shadowed = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "a.shadowed".as_ref())
+            .await
+            .unwrap();
+        assert_eq!(shadowed.inode.full_key(), format!("{prefix}a"));
+        assert_eq!(shadowed.stat.size, 10);
+
+        // Files that aren't shadowed only have their own name
+        for name in ["c.shadowed", "d.shadowed"] {
+            let err = superblock
+                .lookup(&client, FUSE_ROOT_INODE, name.as_ref())
+                .await
+                .expect_err("name should not exist");
+            assert_eq!(err.to_errno(), libc::ENOENT, "lookup of {name:?}");
+        }
+        let err = superblock
+            .create(&client, FUSE_ROOT_INODE, "new.shadowed".as_ref(), InodeKind::File)
+            .await
+            .expect_err("names with the suffix are reserved");
+        assert_eq!(err.to_errno(), libc::EINVAL);
+
+        // Renaming the shadowed file makes it visible under its new name
+        superblock
+            .rename(
+                &client,
+                FUSE_ROOT_INODE,
+                "a.shadowed".as_ref(),
+                FUSE_ROOT_INODE,
+                "a2".as_ref(),
+                false,
+                &Default::default(),
+            )
+            .await
+            .unwrap();
+        let renamed = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "a2".as_ref())
+            .await
+            .unwrap();
+        assert_eq!(renamed.inode.full_key(), format!("{prefix}a2"));
+        assert_eq!(renamed.stat.size, 10);
+    }
+
     #[test_case("", true; "unprefixed ordered")]
     #[test_case("test_prefix/", true; "prefixed ordered")]
     #[test_case("", false; "unprefixed unordered")]
@@ -2354,7 +4019,7 @@ mod tests {
                 .await
                 .unwrap();
             superblock
-                .write(&client, new_inode.inode.ino(), FUSE_ROOT_INODE, 0, false, false)
+                .write(&client, new_inode.inode.ino(), FUSE_ROOT_INODE, 0, false, false, false)
                 .await;
         }
 
@@ -2446,7 +4111,7 @@ mod tests {
             .expect("Should be able to create directory");
 
         superblock
-            .rmdir(&client, FUSE_ROOT_INODE, dirname.as_ref())
+            .rmdir(&client, FUSE_ROOT_INODE, dirname.as_ref(), false)
             .await
             .expect("rmdir on empty local directory should succeed");
 
@@ -2455,7 +4120,9 @@ mod tests {
             .get_inode_state()
             .expect("should get parent state with read lock");
         match &parent_state.kind_data {
-            InodeKindData::File {} => unreachable!("Parent can only be a Directory"),
+            InodeKindData::File { .. } | InodeKindData::Symlink { .. } => {
+                unreachable!("Parent can only be a Directory")
+            }
             InodeKindData::Directory {
                 children,
                 writing_children,
@@ -2474,7 +4141,7 @@ mod tests {
     #[test_case(""; "unprefixed")]
     #[test_case("test_prefix/"; "prefixed")]
     #[tokio::test]
-    async fn test_parent_readdir_after_rmdir(prefix: &str) {
+    async fn test_rmdir_remote_directory(prefix: &str) {
         let client_config = MockClientConfig {
             bucket: "test_bucket".to_string(),
             part_size: 1024 * 1024,
@@ -2484,13 +4151,109 @@ mod tests {
         let prefix = Prefix::new(prefix).expect("valid prefix");
         let superblock = Superblock::new("test_bucket", &prefix, Default::default());
 
-        // Create local directory
-        let dirname = "local_dir";
-        superblock
-            .create(&client, FUSE_ROOT_INODE, dirname.as_ref(), InodeKind::Directory)
-            .await
-            .expect("Should be able to create directory");
-
+        let marker_key = format!("{prefix}marker_dir/");
+        client.add_object(&marker_key, MockObject::constant(0, 0, ETag::for_tests()));
+        client.add_object(
+            &format!("{prefix}full_dir/file.txt"),
+            MockObject::constant(0xaa, 30, ETag::for_tests()),
+        );
+
+        let err = superblock
+            .rmdir(&client, FUSE_ROOT_INODE, "full_dir".as_ref(), true)
+            .await
+            .expect_err("directory is not empty");
+        assert_eq!(err.to_errno(), libc::ENOTEMPTY);
+
+        // Deleting the directory marker needs deletes to be allowed
+        let err = superblock
+            .rmdir(&client, FUSE_ROOT_INODE, "marker_dir".as_ref(), false)
+            .await
+            .expect_err("deletes are not allowed");
+        assert_eq!(err.to_errno(), libc::EPERM);
+        assert!(client.contains_key(&marker_key));
+
+        superblock
+            .rmdir(&client, FUSE_ROOT_INODE, "marker_dir".as_ref(), true)
+            .await
+            .expect("rmdir on empty remote directory should succeed");
+        assert!(!client.contains_key(&marker_key));
+
+        let err = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "marker_dir".as_ref())
+            .await
+            .expect_err("directory should be gone");
+        assert_eq!(err.to_errno(), libc::ENOENT);
+    }
+
+    #[tokio::test]
+    async fn test_rmdir_with_hidden_keys() {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        let superblock_config = SuperblockConfig {
+            delete_policy: DeletePolicy::LocalOnly,
+            ..Default::default()
+        };
+        let superblock = Superblock::new("test_bucket", &Default::default(), superblock_config);
+
+        client.add_object("dir/", MockObject::constant(0, 0, ETag::for_tests()));
+        for name in ["a.txt", "b.txt"] {
+            client.add_object(
+                &format!("dir/{name}"),
+                MockObject::constant(0xaa, 30, ETag::for_tests()),
+            );
+        }
+        let dir = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "dir".as_ref())
+            .await
+            .unwrap();
+
+        superblock
+            .unlink(&client, dir.inode.ino(), "a.txt".as_ref())
+            .await
+            .expect("unlink should succeed");
+        let err = superblock
+            .rmdir(&client, FUSE_ROOT_INODE, "dir".as_ref(), true)
+            .await
+            .expect_err("directory still has a visible file");
+        assert_eq!(err.to_errno(), libc::ENOTEMPTY);
+
+        // Once every file is hidden, the directory counts as empty
+        superblock
+            .unlink(&client, dir.inode.ino(), "b.txt".as_ref())
+            .await
+            .expect("unlink should succeed");
+        superblock
+            .rmdir(&client, FUSE_ROOT_INODE, "dir".as_ref(), true)
+            .await
+            .expect("rmdir should ignore hidden keys");
+        assert!(!client.contains_key("dir/"));
+        assert!(client.contains_key("dir/a.txt"), "hidden objects are not deleted");
+    }
+
+    #[test_case(""; "unprefixed")]
+    #[test_case("test_prefix/"; "prefixed")]
+    #[tokio::test]
+    async fn test_parent_readdir_after_rmdir(prefix: &str) {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        let prefix = Prefix::new(prefix).expect("valid prefix");
+        let superblock = Superblock::new("test_bucket", &prefix, Default::default());
+
+        // Create local directory
+        let dirname = "local_dir";
+        superblock
+            .create(&client, FUSE_ROOT_INODE, dirname.as_ref(), InodeKind::Directory)
+            .await
+            .expect("Should be able to create directory");
+
         let dirname_to_stay = "staying_local_dir";
         superblock
             .create(&client, FUSE_ROOT_INODE, dirname_to_stay.as_ref(), InodeKind::Directory)
@@ -2498,7 +4261,7 @@ mod tests {
             .expect("Should be able to create directory");
 
         superblock
-            .rmdir(&client, FUSE_ROOT_INODE, dirname.as_ref())
+            .rmdir(&client, FUSE_ROOT_INODE, dirname.as_ref(), false)
             .await
             .expect("rmdir on empty local directory should succeed");
 
@@ -2547,6 +4310,468 @@ mod tests {
         assert_eq!(libc::ENOENT, err, "lookup should return no existing entry error");
     }
 
+    #[test_case(""; "unprefixed")]
+    #[test_case("test_prefix/"; "prefixed")]
+    #[tokio::test]
+    async fn test_unlink_local_only(prefix: &str) {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        let prefix = Prefix::new(prefix).expect("valid prefix");
+        let superblock_config = SuperblockConfig {
+            delete_policy: DeletePolicy::LocalOnly,
+            ..Default::default()
+        };
+        let superblock = Superblock::new("test_bucket", &prefix, superblock_config);
+
+        let file_key = format!("{prefix}file.txt");
+        client.add_object(&file_key, MockObject::constant(0xaa, 30, ETag::for_tests()));
+        client.add_object(
+            &format!("{prefix}other.txt"),
+            MockObject::constant(0xaa, 30, ETag::for_tests()),
+        );
+
+        superblock
+            .unlink(&client, FUSE_ROOT_INODE, "file.txt".as_ref())
+            .await
+            .expect("unlink should succeed");
+        assert!(client.contains_key(&file_key), "object should not be deleted");
+
+        let err = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "file.txt".as_ref())
+            .await
+            .expect_err("lookup should no longer find unlinked file");
+        assert_eq!(err.to_errno(), libc::ENOENT);
+
+        let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 2).await.unwrap();
+        let entries = dir_handle.collect(&client).await.unwrap();
+        assert_eq!(
+            entries.iter().map(|entry| entry.inode.name()).collect::<Vec<_>>(),
+            &["other.txt"]
+        );
+
+        // Writing a new file with the same name makes the key visible again
+        let new_file = superblock
+            .create(&client, FUSE_ROOT_INODE, "file.txt".as_ref(), InodeKind::File)
+            .await
+            .expect("file should be created");
+        let writehandle = superblock
+            .write(&client, new_file.inode.ino(), FUSE_ROOT_INODE, 0, false, false, false)
+            .await
+            .start_writing()
+            .expect("should be able to start writing");
+        writehandle.finish_writing().unwrap();
+
+        let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 2).await.unwrap();
+        let entries = dir_handle.collect(&client).await.unwrap();
+        assert_eq!(
+            entries.iter().map(|entry| entry.inode.name()).collect::<Vec<_>>(),
+            &["file.txt", "other.txt"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_symlink_replaces_hidden_key() {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        let superblock_config = SuperblockConfig {
+            delete_policy: DeletePolicy::LocalOnly,
+            allow_symlinks: true,
+            ..Default::default()
+        };
+        let superblock = Superblock::new("test_bucket", &Default::default(), superblock_config);
+
+        client.add_object("file.txt", MockObject::constant(0xaa, 30, ETag::for_tests()));
+        superblock
+            .unlink(&client, FUSE_ROOT_INODE, "file.txt".as_ref())
+            .await
+            .expect("unlink should succeed");
+
+        let target: &OsStr = "other.txt".as_ref();
+        let link = superblock
+            .symlink(
+                &client,
+                FUSE_ROOT_INODE,
+                "file.txt".as_ref(),
+                target,
+                Default::default(),
+            )
+            .await
+            .expect("symlink should replace the hidden file");
+
+        // The symlink is visible, rather than hidden along with the file it replaced
+        let lookup = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "file.txt".as_ref())
+            .await
+            .expect("lookup should find the symlink");
+        assert_eq!(lookup.inode.ino(), link.inode.ino());
+        assert_eq!(lookup.inode.kind(), InodeKind::Symlink);
+        let dir_handle = superblock.readdir(&client, FUSE_ROOT_INODE, 2).await.unwrap();
+        let entries = dir_handle.collect(&client).await.unwrap();
+        assert_eq!(
+            entries.iter().map(|entry| entry.inode.name()).collect::<Vec<_>>(),
+            &["file.txt"]
+        );
+    }
+
+    #[test_case(""; "unprefixed")]
+    #[test_case("test_prefix/"; "prefixed")]
+    #[tokio::test]
+    async fn test_rename_remote_file(prefix: &str) {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        let prefix = Prefix::new(prefix).expect("valid prefix");
+        let superblock = Superblock::new("test_bucket", &prefix, Default::default());
+
+        client.add_object(
+            &format!("{prefix}dir/file.txt"),
+            MockObject::constant(0xaa, 30, ETag::for_tests()),
+        );
+        client.add_object(
+            &format!("{prefix}other.txt"),
+            MockObject::constant(0xbb, 10, ETag::for_tests()),
+        );
+
+        let dir = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "dir".as_ref())
+            .await
+            .expect("dir should exist");
+        let file = superblock
+            .lookup(&client, dir.inode.ino(), "file.txt".as_ref())
+            .await
+            .expect("file should exist");
+
+        // Can't replace an existing file unless overwrites are allowed
+        let err = superblock
+            .rename(
+                &client,
+                dir.inode.ino(),
+                "file.txt".as_ref(),
+                FUSE_ROOT_INODE,
+                "other.txt".as_ref(),
+                false,
+                &Default::default(),
+            )
+            .await
+            .expect_err("can't overwrite without allow_overwrite");
+        assert_eq!(err.to_errno(), libc::EEXIST);
+
+        let renamed_ino = superblock
+            .rename(
+                &client,
+                dir.inode.ino(),
+                "file.txt".as_ref(),
+                FUSE_ROOT_INODE,
+                "other.txt".as_ref(),
+                true,
+                &Default::default(),
+            )
+            .await
+            .expect("rename should succeed");
+        assert_eq!(renamed_ino, file.inode.ino());
+
+        assert!(!client.contains_key(&format!("{prefix}dir/file.txt")));
+        assert!(client.contains_key(&format!("{prefix}other.txt")));
+
+        let err = superblock
+            .lookup(&client, dir.inode.ino(), "file.txt".as_ref())
+            .await
+            .expect_err("old name should no longer exist");
+        assert_eq!(err.to_errno(), libc::ENOENT);
+
+        // The inode moved to the new name and key, and keeps its number
+        let renamed = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "other.txt".as_ref())
+            .await
+            .expect("new name should exist");
+        assert_eq!(renamed.inode.ino(), file.inode.ino());
+        assert_eq!(renamed.inode.full_key(), format!("{prefix}other.txt"));
+        assert_eq!(renamed.stat.size, 30);
+
+        let lookup = superblock
+            .getattr(&client, file.inode.ino(), true)
+            .await
+            .expect("renamed inode should be at its new key");
+        assert_eq!(lookup.inode.full_key(), format!("{prefix}other.txt"));
+        assert_eq!(lookup.stat.size, 30);
+        assert_eq!(file.inode.current().full_key(), format!("{prefix}other.txt"));
+
+        // Renaming it again moves it from its new name
+        superblock
+            .rename(
+                &client,
+                FUSE_ROOT_INODE,
+                "other.txt".as_ref(),
+                dir.inode.ino(),
+                "again.txt".as_ref(),
+                false,
+                &Default::default(),
+            )
+            .await
+            .expect("second rename should succeed");
+        assert!(!client.contains_key(&format!("{prefix}other.txt")));
+        assert!(client.contains_key(&format!("{prefix}dir/again.txt")));
+        let lookup = superblock
+            .getattr(&client, file.inode.ino(), true)
+            .await
+            .expect("renamed inode should follow both renames");
+        assert_eq!(lookup.inode.full_key(), format!("{prefix}dir/again.txt"));
+        assert_eq!(lookup.stat.size, 30);
+        assert_eq!(file.inode.current().full_key(), format!("{prefix}dir/again.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_rename_local_file() {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        let superblock = Superblock::new("test_bucket", &Default::default(), Default::default());
+
+        let file = superblock
+            .create(&client, FUSE_ROOT_INODE, "file.txt".as_ref(), InodeKind::File)
+            .await
+            .unwrap();
+        let dir = superblock
+            .create(&client, FUSE_ROOT_INODE, "dir".as_ref(), InodeKind::Directory)
+            .await
+            .unwrap();
+
+        let copy_count = client.new_counter(Operation::CopyObject);
+        superblock
+            .rename(
+                &client,
+                FUSE_ROOT_INODE,
+                "file.txt".as_ref(),
+                dir.inode.ino(),
+                "renamed.txt".as_ref(),
+                false,
+                &Default::default(),
+            )
+            .await
+            .expect("rename should succeed");
+
+        // Nothing was uploaded or copied
+        assert_eq!(copy_count.count(), 0);
+        assert!(!client.contains_key("dir/renamed.txt"));
+
+        let renamed = superblock
+            .lookup(&client, dir.inode.ino(), "renamed.txt".as_ref())
+            .await
+            .expect("new name should exist");
+        assert_eq!(renamed.inode.ino(), file.inode.ino());
+        assert_eq!(renamed.inode.full_key(), "dir/renamed.txt");
+        assert!(!renamed.inode.is_remote().unwrap());
+
+        let err = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "file.txt".as_ref())
+            .await
+            .expect_err("old name should no longer exist");
+        assert_eq!(err.to_errno(), libc::ENOENT);
+
+        // Writing the file at its new name uploads it to the new key
+        let writehandle = superblock
+            .write(&client, renamed.inode.ino(), dir.inode.ino(), 0, false, false, false)
+            .await
+            .start_writing()
+            .expect("should be able to start writing");
+        writehandle.finish_writing().unwrap();
+        assert!(dir.inode.is_remote().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_rename_into_local_dir() {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        let superblock = Superblock::new("test_bucket", &Default::default(), Default::default());
+
+        client.add_object("file.txt", MockObject::constant(0xaa, 30, ETag::for_tests()));
+        let outer = superblock
+            .create(&client, FUSE_ROOT_INODE, "outer".as_ref(), InodeKind::Directory)
+            .await
+            .unwrap();
+        let inner = superblock
+            .create(&client, outer.inode.ino(), "inner".as_ref(), InodeKind::Directory)
+            .await
+            .unwrap();
+
+        superblock
+            .rename(
+                &client,
+                FUSE_ROOT_INODE,
+                "file.txt".as_ref(),
+                inner.inode.ino(),
+                "file.txt".as_ref(),
+                false,
+                &Default::default(),
+            )
+            .await
+            .expect("rename should succeed");
+
+        assert!(client.contains_key("outer/inner/file.txt"));
+        assert!(outer.inode.is_remote().unwrap());
+        assert!(inner.inode.is_remote().unwrap());
+
+        // Directories can't be renamed
+        let err = superblock
+            .rename(
+                &client,
+                FUSE_ROOT_INODE,
+                "outer".as_ref(),
+                FUSE_ROOT_INODE,
+                "renamed".as_ref(),
+                false,
+                &Default::default(),
+            )
+            .await
+            .expect_err("directory rename should fail");
+        assert!(matches!(err, InodeError::CannotRenameDirectory(_)));
+    }
+
+    #[test_case(""; "unprefixed")]
+    #[test_case("test_prefix/"; "prefixed")]
+    #[tokio::test]
+    async fn test_symlink(prefix: &str) {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        let prefix = Prefix::new(prefix).expect("valid prefix");
+        let superblock_config = SuperblockConfig {
+            allow_symlinks: true,
+            ..Default::default()
+        };
+        let superblock = Superblock::new("test_bucket", &prefix, superblock_config.clone());
+
+        let dir = superblock
+            .create(&client, FUSE_ROOT_INODE, "dir".as_ref(), InodeKind::Directory)
+            .await
+            .unwrap();
+        let target: &OsStr = "../some target/ü".as_ref();
+        let link = superblock
+            .symlink(&client, dir.inode.ino(), "link".as_ref(), target, Default::default())
+            .await
+            .expect("symlink should succeed");
+        assert_eq!(link.inode.kind(), InodeKind::Symlink);
+        assert_eq!(link.stat.size, target.len());
+        assert!(dir.inode.is_remote().unwrap(), "directory should be remote now");
+        assert_eq!(superblock.readlink(link.inode.ino()).await.unwrap(), target);
+        assert_eq!(
+            superblock.readlink(dir.inode.ino()).await.unwrap_err().to_errno(),
+            libc::EINVAL
+        );
+
+        let err = superblock
+            .symlink(&client, dir.inode.ino(), "link".as_ref(), target, Default::default())
+            .await
+            .expect_err("can't replace an existing file");
+        assert_eq!(err.to_errno(), libc::EEXIST);
+
+        // The link should survive a remount, both when looked up and when listed
+        let key = format!("{prefix}dir/link");
+        let object = client.head_object("test_bucket", &key).await.unwrap();
+        assert_eq!(object.object.size, 0);
+        for readdir in [false, true] {
+            let superblock = Superblock::new("test_bucket", &prefix, superblock_config.clone());
+            let dir = superblock
+                .lookup(&client, FUSE_ROOT_INODE, "dir".as_ref())
+                .await
+                .unwrap();
+            let link = if readdir {
+                let dir_handle = superblock.readdir(&client, dir.inode.ino(), 10).await.unwrap();
+                let entries = dir_handle.collect(&client).await.unwrap();
+                assert_eq!(entries.len(), 1);
+                entries.into_iter().next().unwrap()
+            } else {
+                superblock
+                    .lookup(&client, dir.inode.ino(), "link".as_ref())
+                    .await
+                    .unwrap()
+            };
+            assert_eq!(link.inode.kind(), InodeKind::Symlink);
+            assert_eq!(link.stat.size, target.len());
+            assert_eq!(superblock.readlink(link.inode.ino()).await.unwrap(), target);
+        }
+
+        // Without symlinks enabled, the link is just an empty file
+        let superblock = Superblock::new("test_bucket", &prefix, Default::default());
+        let dir = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "dir".as_ref())
+            .await
+            .unwrap();
+        let link = superblock
+            .lookup(&client, dir.inode.ino(), "link".as_ref())
+            .await
+            .unwrap();
+        assert_eq!(link.inode.kind(), InodeKind::File);
+        assert_eq!(link.stat.size, 0);
+    }
+
+    #[tokio::test]
+    async fn test_symlink_target_replaced() {
+        let client_config = MockClientConfig {
+            bucket: "test_bucket".to_string(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(client_config));
+        let superblock_config = SuperblockConfig {
+            allow_symlinks: true,
+            ..Default::default()
+        };
+        let superblock = Superblock::new("test_bucket", &Default::default(), superblock_config);
+
+        let mut object = MockObject::constant(0, 0, ETag::for_tests());
+        object.set_object_metadata(HashMap::from([(
+            SYMLINK_TARGET_METADATA_KEY.to_owned(),
+            "a".to_owned(),
+        )]));
+        client.add_object("link", object.clone());
+        let link = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "link".as_ref())
+            .await
+            .unwrap();
+        assert_eq!(superblock.readlink(link.inode.ino()).await.unwrap(), "a");
+
+        // Another client points the link somewhere else. The object's ETag doesn't change, but the
+        // inode still has to.
+        object.set_object_metadata(HashMap::from([(
+            SYMLINK_TARGET_METADATA_KEY.to_owned(),
+            "b%25".to_owned(),
+        )]));
+        client.add_object("link", object);
+        let err = superblock
+            .getattr(&client, link.inode.ino(), true)
+            .await
+            .expect_err("inode should be stale");
+        assert_eq!(err.to_errno(), libc::ESTALE);
+        let new_link = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "link".as_ref())
+            .await
+            .unwrap();
+        assert_ne!(new_link.inode.ino(), link.inode.ino());
+        assert_eq!(superblock.readlink(new_link.inode.ino()).await.unwrap(), "b%");
+    }
+
     #[tokio::test]
     async fn test_unlink_verify_checksum() {
         let client_config = MockClientConfig {
@@ -2585,6 +4810,7 @@ mod tests {
                     kind_data: InodeKindData::File {},
                     lookup_count: 1,
                     reader_count: 0,
+                    renamed_to: None,
                 }),
             }),
         };
@@ -2596,7 +4822,7 @@ mod tests {
             let parent = inodes.get(&parent_ino).unwrap();
             let mut parent_state = parent.get_mut_inode_state().unwrap();
             match &mut parent_state.kind_data {
-                InodeKindData::File {} => panic!("root is always a directory"),
+                InodeKindData::File { .. } | InodeKindData::Symlink { .. } => panic!("root is always a directory"),
                 InodeKindData::Directory { children, .. } => _ = children.insert(file_name.into(), inode.clone()),
             }
         }
@@ -2654,7 +4880,7 @@ mod tests {
             .unwrap();
 
         let writehandle = superblock
-            .write(&client, new_inode.inode.ino(), leaf_dir_ino, 0, false, false)
+            .write(&client, new_inode.inode.ino(), leaf_dir_ino, 0, false, false, false)
             .await;
         let writehandle = writehandle.start_writing().expect("should be able to start writing");
 
@@ -2827,7 +5053,7 @@ mod tests {
             .unwrap();
 
         let writehandle = superblock
-            .write(&client, new_inode.inode.ino(), FUSE_ROOT_INODE, 0, false, false)
+            .write(&client, new_inode.inode.ino(), FUSE_ROOT_INODE, 0, false, false, false)
             .await;
         let writehandle = writehandle.start_writing().expect("should be able to start writing");
 
@@ -2891,6 +5117,7 @@ mod tests {
                     kind_data: InodeKindData::File {},
                     lookup_count: 5,
                     reader_count: 0,
+                    renamed_to: None,
                 }),
             }),
         };
@@ -0,0 +1,56 @@
+//! File names for a flat namespace, where every key under the mounted prefix is a file at the root
+//! of the mount rather than being split into directories on `/`.
+//!
+//! Keys can contain `/`, which can't appear in a file name, so the name of each file is its key
+//! (after the prefix) with `/`, `%`, and control characters percent-encoded. The keys `.` and `..`
+//! would collide with the directory entries of the same names, so they're encoded entirely. Every
+//! key has exactly one name: a name that decodes to a key but isn't how that key would be encoded,
+//! like `a%2fb` or `50%off`, doesn't belong to any key.
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+
+const FLAT_NAME_ENCODE_SET: &AsciiSet = &CONTROLS.add(b'/').add(b'%');
+
+/// The file name for a key, relative to the mounted prefix
+pub(super) fn name_for_key(key: &str) -> String {
+    match key {
+        "." => "%2E".to_owned(),
+        ".." => "%2E%2E".to_owned(),
+        _ => utf8_percent_encode(key, FLAT_NAME_ENCODE_SET).to_string(),
+    }
+}
+
+/// The key, relative to the mounted prefix, for a file name, or [None] if no key has that name
+pub(super) fn key_for_name(name: &str) -> Option<String> {
+    let key = percent_decode_str(name).decode_utf8().ok()?;
+    (name_for_key(&key) == name).then(|| key.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case("file.txt", "file.txt"; "plain")]
+    #[test_case("logs/2024/01.log", "logs%2F2024%2F01.log"; "slashes")]
+    #[test_case("a//b/", "a%2F%2Fb%2F"; "empty components")]
+    #[test_case("50%off", "50%25off"; "percent")]
+    #[test_case("tab\there", "tab%09here"; "control character")]
+    #[test_case("café/menü", "café%2Fmenü"; "unicode")]
+    #[test_case(".", "%2E"; "dot")]
+    #[test_case("..", "%2E%2E"; "dot dot")]
+    #[test_case("...", "..."; "three dots")]
+    fn test_round_trip(key: &str, name: &str) {
+        assert_eq!(name_for_key(key), name);
+        assert_eq!(key_for_name(name).as_deref(), Some(key));
+    }
+
+    #[test_case("50%off"; "unencoded percent")]
+    #[test_case("a%2fb"; "lowercase escape")]
+    #[test_case("%61"; "unnecessary escape")]
+    #[test_case("%FF"; "invalid utf8")]
+    fn test_not_a_key(name: &str) {
+        assert_eq!(key_for_name(name), None);
+    }
+}
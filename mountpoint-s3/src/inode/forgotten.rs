@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use linked_hash_map::LinkedHashMap;
+
+use super::{expiry::Expiry, InodeNo};
+
+use crate::sync::Mutex;
+
+/// Inodes the kernel has forgotten, but that we keep in the superblock for a grace period in case
+/// they are looked up again soon. Kernels under memory pressure can evict dentries aggressively,
+/// and without a grace period the next access to the same path would need new S3 requests even
+/// though the cached metadata is still valid.
+#[derive(Debug)]
+pub struct ForgottenInodes {
+    /// Holds inodes in order from least to most recently forgotten.
+    map: Mutex<LinkedHashMap<InodeNo, Expiry>>,
+    /// How long to keep an inode after it was forgotten.
+    grace_period: Duration,
+}
+
+impl ForgottenInodes {
+    pub fn new(grace_period: Duration) -> Self {
+        Self {
+            map: Mutex::new(Default::default()),
+            grace_period,
+        }
+    }
+
+    /// Whether inodes should be kept at all after being forgotten.
+    pub fn is_enabled(&self) -> bool {
+        !self.grace_period.is_zero()
+    }
+
+    /// Start the grace period for an inode that was just forgotten. If the inode was already
+    /// waiting out a grace period, restart it.
+    pub fn insert(&self, ino: InodeNo) {
+        let mut map = self.map.lock().unwrap();
+        map.remove(&ino);
+        map.insert(ino, Expiry::from_now(self.grace_period));
+        metrics::gauge!("metadata_cache.forgotten_inodes").set(map.len() as f64);
+    }
+
+    /// Remove and return all the inodes whose grace period has ended.
+    pub fn take_expired(&self) -> Vec<InodeNo> {
+        let mut map = self.map.lock().unwrap();
+        let mut expired = Vec::new();
+        while map.front().is_some_and(|(_, e)| e.is_expired()) {
+            let Some((ino, _)) = map.pop_front() else {
+                break;
+            };
+            expired.push(ino);
+        }
+        if !expired.is_empty() {
+            metrics::gauge!("metadata_cache.forgotten_inodes").set(map.len() as f64);
+        }
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use super::ForgottenInodes;
+
+    #[test]
+    fn test_take_expired() {
+        let grace_period = Duration::from_millis(50);
+        let forgotten = ForgottenInodes::new(grace_period);
+        assert!(forgotten.is_enabled());
+
+        forgotten.insert(2);
+        forgotten.insert(3);
+        assert!(forgotten.take_expired().is_empty());
+
+        sleep(grace_period);
+        // Restart the grace period for inode 3
+        forgotten.insert(3);
+        assert_eq!(forgotten.take_expired(), vec![2]);
+
+        sleep(grace_period);
+        assert_eq!(forgotten.take_expired(), vec![3]);
+        assert!(forgotten.take_expired().is_empty());
+    }
+
+    #[test]
+    fn test_disabled() {
+        let forgotten = ForgottenInodes::new(Duration::ZERO);
+        assert!(!forgotten.is_enabled());
+    }
+}
@@ -0,0 +1,318 @@
+//! Invalidating cached metadata for keys that were changed in S3 by someone other than this mount,
+//! like when we're told about the change by an S3 event notification.
+//!
+//! We find the cached metadata for a key by walking down the directories along the key from the
+//! directory the key's bucket and prefix are mounted at, only following inodes we already know
+//! about. There's nothing to invalidate below a directory we don't know about, since the next
+//! lookup in it goes to S3 anyway. The kernel keeps its own caches of directory entries and file
+//! attributes, so we return the entries in those caches that might also be out of date, for the
+//! caller to invalidate with the kernel. When a key we know about as a file was deleted, the kernel
+//! can also be told about the deletion itself, which it reports to inotify and fanotify watchers of
+//! the file and its directory just like a deletion made through the mount. There's no equivalent
+//! for keys that were created or replaced, so those changes only refresh the caches.
+
+use std::time::Duration;
+
+use super::{
+    flat, valid_inode_name, Inode, InodeKind, InodeKindData, InodeNo, SuperblockInner, WriteStatus, ROOT_INODE_NO,
+};
+
+use crate::sync::Arc;
+
+/// Invalidates the cached metadata for keys, from outside the file system's own operations
+#[derive(Debug, Clone)]
+pub struct CacheInvalidator {
+    inner: Arc<SuperblockInner>,
+}
+
+/// An entry in the kernel's caches that might be out of date after a key changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KernelInvalidation {
+    /// The entry for `name` in the directory `parent`, which might be a cached lookup miss
+    Entry { parent: InodeNo, name: String },
+    /// The attributes and cached contents of a file
+    Inode(InodeNo),
+    /// The entry for `name` in the directory `parent`, which was the file `child` until it was
+    /// deleted
+    Delete {
+        parent: InodeNo,
+        child: InodeNo,
+        name: String,
+    },
+}
+
+impl CacheInvalidator {
+    pub(super) fn new(inner: Arc<SuperblockInner>) -> Self {
+        Self { inner }
+    }
+
+    /// Forget the cached metadata for a key that was created, replaced, or (if `removed`) deleted
+    /// in S3, so that the next access to it goes to S3. Returns the entries in the kernel's caches
+    /// that might also be out of date.
+    pub fn invalidate_key(&self, bucket: &str, key: &str, removed: bool) -> Vec<KernelInvalidation> {
+        let mut invalidations = Vec::new();
+        for dir in self.mount_roots() {
+            if dir.bucket() != bucket {
+                continue;
+            }
+            if let Some(relative) = key.strip_prefix(dir.full_key()) {
+                self.invalidate_path(dir, relative, removed, &mut invalidations);
+            }
+        }
+        invalidations
+    }
+
+    /// The directories that buckets and prefixes are mounted at
+    fn mount_roots(&self) -> Vec<Inode> {
+        let Ok(root) = self.inner.get(ROOT_INODE_NO) else {
+            return Vec::new();
+        };
+        if self.inner.config.namespace.is_none() {
+            return vec![root];
+        }
+        let Ok(root_state) = root.get_inode_state() else {
+            return Vec::new();
+        };
+        match &root_state.kind_data {
+            InodeKindData::Directory { children, .. } => children.values().cloned().collect(),
+            _ => unreachable!("root is always a directory"),
+        }
+    }
+
+    fn invalidate_path(
+        &self,
+        mut dir: Inode,
+        relative: &str,
+        removed: bool,
+        invalidations: &mut Vec<KernelInvalidation>,
+    ) {
+        // Removing a directory marker doesn't remove any file, and doesn't always remove the
+        // directory either
+        let removed_file = removed && (self.inner.config.flat_keys || !relative.ends_with('/'));
+        let names = if self.inner.config.flat_keys {
+            vec![flat::name_for_key(relative)]
+        } else {
+            // A directory marker like `a/b/` belongs to the directory `b`
+            let relative = relative.strip_suffix('/').unwrap_or(relative);
+            relative.split('/').map(str::to_owned).collect()
+        };
+        let Some((leaf, ancestors)) = names.split_last() else {
+            return;
+        };
+
+        for name in ancestors {
+            if !valid_inode_name(name) {
+                return;
+            }
+            match child(&dir, name) {
+                Some(child) if child.kind() == InodeKind::Directory => {
+                    // Creating a key always leaves its ancestors in place, but removing the last
+                    // key under a directory removes the directory too
+                    if removed {
+                        self.invalidate_name(&dir, name, false, invalidations);
+                    }
+                    dir = child;
+                }
+                // Nothing below here is cached, but we or the kernel might remember that the name
+                // didn't exist, or that it was a file that a new directory now shadows
+                _ => {
+                    self.invalidate_name(&dir, name, false, invalidations);
+                    return;
+                }
+            }
+        }
+
+        if !valid_inode_name(leaf) {
+            return;
+        }
+        // A removed key is the shadowed file only if a directory shadows it
+        let shadowed = child(&dir, leaf).is_some_and(|inode| inode.kind() == InodeKind::Directory);
+        self.invalidate_name(&dir, leaf, removed_file, invalidations);
+        if let Some(suffix) = &self.inner.config.shadowed_file_suffix {
+            let name = format!("{leaf}{suffix}");
+            self.invalidate_name(&dir, &name, removed_file && shadowed, invalidations);
+        }
+    }
+
+    /// Forget whether `name` exists in `dir`, and the metadata of the inode it refers to if it does.
+    /// If `removed_file` and that inode is a file in S3, it was deleted.
+    fn invalidate_name(
+        &self,
+        dir: &Inode,
+        name: &str,
+        removed_file: bool,
+        invalidations: &mut Vec<KernelInvalidation>,
+    ) {
+        self.inner.negative_cache.remove(dir.ino(), name);
+
+        let Some(inode) = child(dir, name) else {
+            invalidations.push(KernelInvalidation::Entry {
+                parent: dir.ino(),
+                name: name.to_owned(),
+            });
+            return;
+        };
+        let mut is_remote = false;
+        if let Ok(mut state) = inode.get_mut_inode_state() {
+            // Inodes that are still local aren't in S3 yet, so there's nothing newer to get from it
+            if state.write_status == WriteStatus::Remote {
+                state.stat.update_validity(Duration::ZERO);
+                is_remote = true;
+            }
+        }
+        if removed_file && is_remote && inode.kind() == InodeKind::File {
+            invalidations.push(KernelInvalidation::Delete {
+                parent: dir.ino(),
+                child: inode.ino(),
+                name: name.to_owned(),
+            });
+            return;
+        }
+        invalidations.push(KernelInvalidation::Entry {
+            parent: dir.ino(),
+            name: name.to_owned(),
+        });
+        if inode.kind() == InodeKind::File {
+            invalidations.push(KernelInvalidation::Inode(inode.ino()));
+        }
+    }
+}
+
+/// The cached child of a directory with the given name
+fn child(dir: &Inode, name: &str) -> Option<Inode> {
+    let state = dir.get_inode_state().ok()?;
+    match &state.kind_data {
+        InodeKindData::Directory { children, .. } => children.get(name).cloned(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use mountpoint_s3_client::mock_client::{MockClient, MockClientConfig, MockObject};
+    use mountpoint_s3_client::types::ETag;
+
+    use super::*;
+    use crate::fs::{CacheConfig, ToErrno, FUSE_ROOT_INODE};
+    use crate::inode::{Superblock, SuperblockConfig};
+    use crate::prefix::Prefix;
+
+    #[tokio::test]
+    async fn test_invalidate_key() {
+        let client = MockClient::new(MockClientConfig {
+            bucket: "test_bucket".to_owned(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        });
+        client.add_object(
+            "prefix/dir/a",
+            MockObject::constant(0xaa, 10, ETag::from_str("etag1").unwrap()),
+        );
+        let ttl = Duration::from_secs(60 * 60);
+        let superblock = Superblock::new(
+            "test_bucket",
+            &Prefix::new("prefix/").unwrap(),
+            SuperblockConfig {
+                cache_config: CacheConfig {
+                    serve_lookup_from_cache: true,
+                    file_ttl: ttl,
+                    dir_ttl: ttl,
+                    negative_cache_ttl: ttl,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        let invalidator = superblock.cache_invalidator();
+
+        let dir = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "dir".as_ref())
+            .await
+            .unwrap();
+        let dir_ino = dir.inode.ino();
+        let a = superblock.lookup(&client, dir_ino, "a".as_ref()).await.unwrap();
+        assert_eq!(a.stat.size, 10);
+        superblock
+            .lookup(&client, dir_ino, "b".as_ref())
+            .await
+            .expect_err("b doesn't exist yet");
+
+        // Changes in S3 aren't visible until the keys are invalidated
+        client.add_object(
+            "prefix/dir/a",
+            MockObject::constant(0xaa, 20, ETag::from_str("etag2").unwrap()),
+        );
+        client.add_object(
+            "prefix/dir/b",
+            MockObject::constant(0xaa, 30, ETag::from_str("etag3").unwrap()),
+        );
+        let a = superblock.lookup(&client, dir_ino, "a".as_ref()).await.unwrap();
+        assert_eq!(a.stat.size, 10);
+        superblock
+            .lookup(&client, dir_ino, "b".as_ref())
+            .await
+            .expect_err("b is cached as not existing");
+
+        let invalidations = invalidator.invalidate_key("test_bucket", "prefix/dir/a", false);
+        assert_eq!(
+            invalidations,
+            vec![
+                KernelInvalidation::Entry {
+                    parent: dir_ino,
+                    name: "a".to_owned()
+                },
+                KernelInvalidation::Inode(a.inode.ino()),
+            ]
+        );
+        let invalidations = invalidator.invalidate_key("test_bucket", "prefix/dir/b", false);
+        assert_eq!(
+            invalidations,
+            vec![KernelInvalidation::Entry {
+                parent: dir_ino,
+                name: "b".to_owned()
+            }]
+        );
+        let a = superblock.lookup(&client, dir_ino, "a".as_ref()).await.unwrap();
+        assert_eq!(a.stat.size, 20);
+        let b = superblock.lookup(&client, dir_ino, "b".as_ref()).await.unwrap();
+        assert_eq!(b.stat.size, 30);
+
+        // Keys in other buckets or outside the prefix aren't mounted
+        assert!(invalidator
+            .invalidate_key("other_bucket", "prefix/dir/a", false)
+            .is_empty());
+        assert!(invalidator.invalidate_key("test_bucket", "dir/a", false).is_empty());
+
+        // Removing keys might remove their directories too
+        client.remove_object("prefix/dir/a");
+        client.remove_object("prefix/dir/b");
+        let invalidations = invalidator.invalidate_key("test_bucket", "prefix/dir/a", true);
+        assert_eq!(
+            invalidations,
+            vec![
+                KernelInvalidation::Entry {
+                    parent: FUSE_ROOT_INODE,
+                    name: "dir".to_owned()
+                },
+                KernelInvalidation::Delete {
+                    parent: dir_ino,
+                    child: a.inode.ino(),
+                    name: "a".to_owned()
+                },
+            ]
+        );
+        let err = superblock
+            .lookup(&client, dir_ino, "a".as_ref())
+            .await
+            .expect_err("a was removed");
+        assert_eq!(err.to_errno(), libc::ENOENT);
+        let err = superblock
+            .lookup(&client, FUSE_ROOT_INODE, "dir".as_ref())
+            .await
+            .expect_err("dir has no keys left");
+        assert_eq!(err.to_errno(), libc::ENOENT);
+    }
+}
@@ -0,0 +1,188 @@
+//! A composite namespace that mounts several prefixes, possibly of different buckets, into one file
+//! system.
+//!
+//! Each prefix appears as a directory at the root of the mount. When mounting several prefixes of
+//! one bucket, the directory is named after the last component of the prefix, so mounting
+//! `logs/2024/` and `data/` shows `2024/` and `data/` at the root. When mounting several buckets,
+//! each directory gets the name it was configured with. The root itself isn't backed by any
+//! prefix: it only contains these directories, and nothing can be created in or removed from it.
+//! Below the root, each directory behaves exactly like a mount of its own bucket and prefix.
+
+use thiserror::Error;
+
+use crate::prefix::Prefix;
+
+use super::valid_inode_name;
+
+/// The prefixes mounted at the root of a composite namespace, and the directory names they appear
+/// under.
+#[derive(Debug, Clone)]
+pub struct CompositeNamespace {
+    entries: Vec<NamespaceEntry>,
+}
+
+/// A directory at the root of a composite namespace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceEntry {
+    pub name: String,
+    pub bucket: String,
+    pub prefix: Prefix,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum NamespaceError {
+    #[error("the bucket root can't be mounted alongside other prefixes")]
+    EmptyPrefix,
+    #[error("{0} can't appear as directory {1:?}, which isn't a valid name")]
+    InvalidName(String, String),
+    #[error("{0} and {1} would both appear as directory {2:?}")]
+    DuplicateName(String, String, String),
+    #[error("{0} and {1} overlap")]
+    OverlappingPrefixes(String, String),
+}
+
+impl CompositeNamespace {
+    /// Create a namespace for several prefixes of one bucket, which must be disjoint and have
+    /// distinct names.
+    pub fn new(bucket: &str, prefixes: impl IntoIterator<Item = Prefix>) -> Result<Self, NamespaceError> {
+        let mut namespace = Self { entries: Vec::new() };
+        for prefix in prefixes {
+            if prefix.as_str().is_empty() {
+                return Err(NamespaceError::EmptyPrefix);
+            }
+            let name = prefix
+                .as_str()
+                .trim_end_matches('/')
+                .rsplit('/')
+                .next()
+                .unwrap_or_default();
+            namespace.push(NamespaceEntry {
+                name: name.to_owned(),
+                bucket: bucket.to_owned(),
+                prefix,
+            })?;
+        }
+        Ok(namespace)
+    }
+
+    /// Create a namespace for prefixes of any number of buckets, each with its own directory name.
+    /// The names must be distinct, and prefixes of the same bucket must be disjoint.
+    pub fn for_buckets(entries: impl IntoIterator<Item = NamespaceEntry>) -> Result<Self, NamespaceError> {
+        let mut namespace = Self { entries: Vec::new() };
+        for entry in entries {
+            namespace.push(entry)?;
+        }
+        Ok(namespace)
+    }
+
+    fn push(&mut self, entry: NamespaceEntry) -> Result<(), NamespaceError> {
+        if !valid_inode_name(&entry.name) {
+            return Err(NamespaceError::InvalidName(entry.description(), entry.name));
+        }
+        for other in &self.entries {
+            if other.name == entry.name {
+                return Err(NamespaceError::DuplicateName(
+                    other.description(),
+                    entry.description(),
+                    entry.name,
+                ));
+            }
+            if other.bucket == entry.bucket
+                && (other.prefix.as_str().starts_with(entry.prefix.as_str())
+                    || entry.prefix.as_str().starts_with(other.prefix.as_str()))
+            {
+                return Err(NamespaceError::OverlappingPrefixes(
+                    other.description(),
+                    entry.description(),
+                ));
+            }
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// The directories at the root of the namespace
+    pub fn entries(&self) -> impl Iterator<Item = &NamespaceEntry> {
+        self.entries.iter()
+    }
+
+    /// Whether the namespace includes more than one bucket
+    pub fn is_multi_bucket(&self) -> bool {
+        self.entries.iter().any(|entry| entry.bucket != self.entries[0].bucket)
+    }
+}
+
+impl NamespaceEntry {
+    /// How to describe this entry in an error message
+    fn description(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn namespace(prefixes: &[&str]) -> Result<CompositeNamespace, NamespaceError> {
+        CompositeNamespace::new(
+            "test_bucket",
+            prefixes.iter().map(|prefix| Prefix::new(prefix).unwrap()),
+        )
+    }
+
+    fn entry(name: &str, bucket: &str, prefix: &str) -> NamespaceEntry {
+        NamespaceEntry {
+            name: name.to_owned(),
+            bucket: bucket.to_owned(),
+            prefix: Prefix::new(prefix).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_entry_names() {
+        let namespace = namespace(&["logs/2024/", "data/", "a/b/c/"]).unwrap();
+        let entries: Vec<_> = namespace
+            .entries()
+            .map(|entry| (entry.name.as_str(), entry.prefix.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![("2024", "logs/2024/"), ("data", "data/"), ("c", "a/b/c/")]
+        );
+        assert!(!namespace.is_multi_bucket());
+    }
+
+    #[test_case(&["data/", ""], NamespaceError::EmptyPrefix; "bucket root")]
+    #[test_case(&["/"], NamespaceError::InvalidName("s3://test_bucket//".to_owned(), "".to_owned()); "no name")]
+    #[test_case(&["a/data/", "b/data/"], NamespaceError::DuplicateName("s3://test_bucket/a/data/".to_owned(), "s3://test_bucket/b/data/".to_owned(), "data".to_owned()); "duplicate name")]
+    #[test_case(&["data/", "data/2024/"], NamespaceError::OverlappingPrefixes("s3://test_bucket/data/".to_owned(), "s3://test_bucket/data/2024/".to_owned()); "nested")]
+    #[test_case(&["data/2024/", "data/"], NamespaceError::OverlappingPrefixes("s3://test_bucket/data/2024/".to_owned(), "s3://test_bucket/data/".to_owned()); "nested reversed")]
+    fn test_invalid_namespace(prefixes: &[&str], expected: NamespaceError) {
+        assert_eq!(namespace(prefixes).unwrap_err(), expected);
+    }
+
+    #[test]
+    fn test_multi_bucket_namespace() {
+        // Whole buckets, and the same prefix of different buckets, don't overlap
+        let namespace = CompositeNamespace::for_buckets([
+            entry("data", "bucket-a", ""),
+            entry("logs", "bucket-b", ""),
+            entry("more-logs", "bucket-c", "logs/"),
+            entry("other-logs", "bucket-d", "logs/"),
+        ])
+        .unwrap();
+        assert_eq!(namespace.entries().count(), 4);
+        assert!(namespace.is_multi_bucket());
+
+        let err = CompositeNamespace::for_buckets([entry("data", "bucket-a", ""), entry("data", "bucket-b", "")])
+            .unwrap_err();
+        assert!(matches!(err, NamespaceError::DuplicateName(_, _, _)));
+        let err =
+            CompositeNamespace::for_buckets([entry("a", "bucket-a", ""), entry("b", "bucket-a", "logs/")]).unwrap_err();
+        assert!(matches!(err, NamespaceError::OverlappingPrefixes(_, _)));
+        let err = CompositeNamespace::for_buckets([entry("a/b", "bucket-a", "")]).unwrap_err();
+        assert!(matches!(err, NamespaceError::InvalidName(_, _)));
+    }
+}
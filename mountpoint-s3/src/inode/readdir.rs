@@ -29,23 +29,30 @@
 //!   [LocalIter], to handle point 2. While merging, [ReaddirIter] also deduplicates the entries it
 //!   returns to handle point 1.
 //! * [RemoteIter] is an iterator over [ReaddirEntry]s returned by paginated calls to ListObjectsV2.
-//!   Rather than directly streaming the entries out of the list call, it collects them in memory
-//!   and re-sorts them to handle point 3.
+//!   It holds one page of results at a time and re-sorts the common prefixes in that page to handle
+//!   point 3, then lazily merges them with the page's objects as entries are consumed.
 //! * [LocalIter] is an iterator over [ReaddirEntry]s that are local children of the directory.
 //!   These children are listed only once, at the start of the readdir operation, and so are a
 //!   snapshot in time of the directory.
 
 use std::cmp::Ordering;
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
+use std::ffi::OsString;
 
+use anyhow::anyhow;
+use mountpoint_s3_client::error::{HeadObjectError, ObjectClientError};
 use mountpoint_s3_client::types::ObjectInfo;
 use mountpoint_s3_client::ObjectClient;
+use time::OffsetDateTime;
 use tracing::{error, trace, warn};
 
 use crate::sync::{Arc, AsyncMutex, Mutex};
 
+use super::flat::name_for_key;
+use super::versions::{prefix_existed_at, VersionsAt};
 use super::{
-    valid_inode_name, InodeError, InodeKind, InodeKindData, InodeNo, InodeStat, LookedUp, RemoteLookup, SuperblockInner,
+    symlink_target_from_metadata, valid_inode_name, InodeError, InodeKind, InodeKindData, InodeNo, InodeStat, LookedUp,
+    RemoteLookup, SuperblockInner, MAX_NAME_LENGTH, ROOT_INODE_NO,
 };
 
 /// Handle for an inflight directory listing
@@ -54,6 +61,8 @@ pub struct ReaddirHandle {
     inner: Arc<SuperblockInner>,
     dir_ino: InodeNo,
     parent_ino: InodeNo,
+    /// The bucket the directory is in
+    bucket: String,
     iter: AsyncMutex<ReaddirIter>,
     readded: Mutex<Option<LookedUp>>,
 }
@@ -66,11 +75,25 @@ impl ReaddirHandle {
         full_path: String,
         page_size: usize,
     ) -> Result<Self, InodeError> {
+        let dir = inner.get(dir_ino)?;
+        let bucket = dir.bucket().to_owned();
+        if inner.is_namespace_root(dir_ino) {
+            return Ok(Self {
+                iter: AsyncMutex::new(ReaddirIter::namespace(&inner)?),
+                inner,
+                dir_ino,
+                parent_ino,
+                bucket,
+                readded: Default::default(),
+            });
+        }
+
         let local_entries = {
-            let inode = inner.get(dir_ino)?;
-            let kind_data = &inode.get_inode_state()?.kind_data;
+            let kind_data = &dir.get_inode_state()?.kind_data;
             let local_files = match kind_data {
-                InodeKindData::File { .. } => return Err(InodeError::NotADirectory(inode.err())),
+                InodeKindData::File { .. } | InodeKindData::Symlink { .. } => {
+                    return Err(InodeError::NotADirectory(dir.err()))
+                }
                 InodeKindData::Directory { writing_children, .. } => writing_children.iter().map(|ino| {
                     let inode = inner.get(*ino)?;
                     let stat = inode.get_inode_state()?.stat.clone();
@@ -92,16 +115,40 @@ impl ReaddirHandle {
             }
         };
 
-        let iter = if inner.config.s3_personality.is_list_ordered() {
-            ReaddirIter::ordered(&inner.bucket, &full_path, page_size, local_entries.into())
+        // Like the local entries, the keys hidden by unlink are a snapshot taken when listing starts
+        let hidden_keys = inner.hidden_keys_under(&bucket, &full_path);
+
+        let as_of = inner.config.object_version_policy.as_of();
+        let shadowed_file_suffix = inner.config.shadowed_file_suffix.clone();
+        let iter = if inner.config.flat_keys {
+            ReaddirIter::flat(&bucket, &full_path, page_size, local_entries.into(), hidden_keys, as_of)
+        } else if inner.config.s3_personality.is_list_ordered() || as_of.is_some() {
+            // ListObjectVersions is always ordered
+            ReaddirIter::ordered(
+                &bucket,
+                &full_path,
+                page_size,
+                local_entries.into(),
+                hidden_keys,
+                as_of,
+                shadowed_file_suffix,
+            )
         } else {
-            ReaddirIter::unordered(&inner.bucket, &full_path, page_size, local_entries.into())
+            ReaddirIter::unordered(
+                &bucket,
+                &full_path,
+                page_size,
+                local_entries.into(),
+                hidden_keys,
+                shadowed_file_suffix,
+            )
         };
 
         Ok(Self {
             inner,
             dir_ino,
             parent_ino,
+            bucket,
             iter: AsyncMutex::new(iter),
             readded: Default::default(),
         })
@@ -127,8 +174,21 @@ impl ReaddirHandle {
                 // Short-circuit the update if we know it'll fail because the name is invalid
                 if !valid_inode_name(next.name()) {
                     warn!("{} has an invalid name and will be unavailable", next.description());
+                } else if next.name().len() > MAX_NAME_LENGTH {
+                    // The kernel can't look up a longer name, and fails the whole listing if it's
+                    // longer than the most FUSE allows
+                    warn!(
+                        "{} has a name longer than {MAX_NAME_LENGTH} bytes and will be unavailable",
+                        next.description()
+                    );
+                } else if !next.is_shadowed() && self.inner.shadowed_file_base(next.name()).is_some() {
+                    warn!(
+                        "{} has a name reserved for shadowed files and will be unavailable",
+                        next.description()
+                    );
                 } else {
-                    let lookup = self.instantiate_remote_inode(next)?;
+                    let symlink_target = self.symlink_target(client, &next).await?;
+                    let lookup = self.instantiate_remote_inode(next, symlink_target)?;
                     return Ok(Some(lookup));
                 }
             } else {
@@ -154,8 +214,39 @@ impl ReaddirHandle {
         self.parent_ino
     }
 
+    /// Find the target of a remote object if it is a symbolic link.
+    ///
+    /// ListObjectsV2 doesn't return object metadata, so when symlinks are enabled we need a
+    /// HeadObject request for every empty object, as that's how symlinks are stored.
+    async fn symlink_target<OC: ObjectClient>(
+        &self,
+        client: &OC,
+        entry: &ReaddirEntry,
+    ) -> Result<Option<OsString>, InodeError> {
+        let ReaddirEntry::RemoteObject { object_info, .. } = entry else {
+            return Ok(None);
+        };
+        if !self.inner.config.allow_symlinks || object_info.size > 0 {
+            return Ok(None);
+        }
+        match client.head_object(&self.bucket, &object_info.key).await {
+            Ok(result) => Ok(symlink_target_from_metadata(&result.object_metadata)),
+            // The object was deleted since we listed it. Report it as listed; a lookup will find out.
+            Err(ObjectClientError::ServiceError(HeadObjectError::NotFound)) => Ok(None),
+            Err(e) => Err(InodeError::ClientError(anyhow!(e).context("HeadObject failed"))),
+        }
+    }
+
     /// Create or update an inode for the given ReaddirEntry.
-    fn instantiate_remote_inode(&self, entry: ReaddirEntry) -> Result<LookedUp, InodeError> {
+    fn instantiate_remote_inode(
+        &self,
+        entry: ReaddirEntry,
+        symlink_target: Option<OsString>,
+    ) -> Result<LookedUp, InodeError> {
+        if self.inner.is_namespace_root(self.dir_ino) {
+            return self.inner.namespace_lookup(entry.name());
+        }
+
         let remote_lookup = match &entry {
             // If we made it this far with a local inode, we know there's nothing on the remote with
             // the same name, because [LocalInode] is last in the ordering and so otherwise would
@@ -163,24 +254,27 @@ impl ReaddirHandle {
             ReaddirEntry::LocalInode { .. } => None,
             ReaddirEntry::RemotePrefix { .. } => {
                 let stat = InodeStat::for_directory(self.inner.mount_time, self.inner.config.cache_config.dir_ttl);
-                Some(RemoteLookup {
-                    stat,
-                    kind: InodeKind::Directory,
-                })
+                Some(RemoteLookup::for_directory(stat))
             }
-            ReaddirEntry::RemoteObject { object_info, .. } => {
-                let stat = InodeStat::for_file(
-                    object_info.size as usize,
+            ReaddirEntry::RemoteObject {
+                object_info,
+                version_id,
+                ..
+            } => {
+                // A symbolic link's size is the length of its target, not of its (empty) object
+                let size = symlink_target
+                    .as_ref()
+                    .map_or(object_info.size as usize, |target| target.len());
+                let mut stat = InodeStat::for_file(
+                    size,
                     object_info.last_modified,
                     Some(object_info.etag.clone()),
                     object_info.storage_class.clone(),
                     object_info.restore_status,
                     self.inner.config.cache_config.file_ttl,
                 );
-                Some(RemoteLookup {
-                    stat,
-                    kind: InodeKind::File,
-                })
+                stat.version_id = version_id.clone();
+                Some(RemoteLookup::for_object(stat, symlink_target))
             }
         };
         self.inner.update_from_remote(self.dir_ino, entry.name(), remote_lookup)
@@ -200,9 +294,20 @@ impl ReaddirHandle {
 /// should be done lazily by the consumer of the entry.
 #[derive(Debug, Clone)]
 enum ReaddirEntry {
-    RemotePrefix { name: String },
-    RemoteObject { name: String, object_info: ObjectInfo },
-    LocalInode { lookup: LookedUp },
+    RemotePrefix {
+        name: String,
+    },
+    RemoteObject {
+        name: String,
+        object_info: ObjectInfo,
+        /// The version to pin reads to, if the directory is listed at a point in time
+        version_id: Option<String>,
+        /// Whether the object is shadowed by a directory, and so named with the shadowed file suffix
+        shadowed: bool,
+    },
+    LocalInode {
+        lookup: LookedUp,
+    },
 }
 
 // This looks a little silly but makes the [Ord] implementation for [ReaddirEntry] a bunch clearer
@@ -222,6 +327,29 @@ impl ReaddirEntry {
         }
     }
 
+    fn is_shadowed(&self) -> bool {
+        matches!(self, Self::RemoteObject { shadowed: true, .. })
+    }
+
+    /// If this is a remote object, the entry for it under its shadowed file name, for when a remote
+    /// directory has the same name
+    fn shadowed_file(&self, suffix: &str) -> Option<ReaddirEntry> {
+        match self {
+            Self::RemoteObject {
+                name,
+                object_info,
+                version_id,
+                shadowed: false,
+            } => Some(Self::RemoteObject {
+                name: format!("{name}{suffix}"),
+                object_info: object_info.clone(),
+                version_id: version_id.clone(),
+                shadowed: true,
+            }),
+            _ => None,
+        }
+    }
+
     fn kind(&self) -> ReaddirEntryKind {
         match self {
             Self::RemotePrefix { .. } => ReaddirEntryKind::RemotePrefix,
@@ -236,13 +364,14 @@ impl ReaddirEntry {
             Self::RemotePrefix { name } => {
                 format!("directory '{name}'")
             }
-            Self::RemoteObject { name, object_info } => {
+            Self::RemoteObject { name, object_info, .. } => {
                 format!("file '{}' (full key {:?})", name, object_info.key)
             }
             Self::LocalInode { lookup } => {
                 let kind = match lookup.inode.kind() {
                     InodeKind::Directory => "directory",
                     InodeKind::File => "file",
+                    InodeKind::Symlink => "symlink",
                 };
                 format!("local {} '{}'", kind, lookup.inode.name())
             }
@@ -279,21 +408,84 @@ impl Ord for ReaddirEntry {
 enum ReaddirIter {
     Ordered(ordered::ReaddirIter),
     Unordered(unordered::ReaddirIter),
+    /// The directories at the root of a composite namespace, which is never listed remotely
+    Namespace(VecDeque<ReaddirEntry>),
 }
 
 impl ReaddirIter {
-    fn ordered(bucket: &str, full_path: &str, page_size: usize, local_entries: VecDeque<ReaddirEntry>) -> Self {
-        Self::Ordered(ordered::ReaddirIter::new(bucket, full_path, page_size, local_entries))
+    fn ordered(
+        bucket: &str,
+        full_path: &str,
+        page_size: usize,
+        local_entries: VecDeque<ReaddirEntry>,
+        hidden_keys: HashSet<String>,
+        as_of: Option<OffsetDateTime>,
+        shadowed_file_suffix: Option<String>,
+    ) -> Self {
+        Self::Ordered(ordered::ReaddirIter::new(
+            bucket,
+            full_path,
+            page_size,
+            local_entries,
+            hidden_keys,
+            as_of,
+            shadowed_file_suffix,
+        ))
+    }
+
+    fn unordered(
+        bucket: &str,
+        full_path: &str,
+        page_size: usize,
+        local_entries: VecDeque<ReaddirEntry>,
+        hidden_keys: HashSet<String>,
+        shadowed_file_suffix: Option<String>,
+    ) -> Self {
+        let remote = RemoteIter::new(bucket, full_path, page_size, false, false, hidden_keys, None);
+        Self::Unordered(unordered::ReaddirIter::new(remote, local_entries, shadowed_file_suffix))
+    }
+
+    /// Every key under `full_path` as a file with a flat name. Escaping `/` as `%2F` changes how
+    /// names sort compared to their keys, so even ordered listings are treated as unordered.
+    fn flat(
+        bucket: &str,
+        full_path: &str,
+        page_size: usize,
+        local_entries: VecDeque<ReaddirEntry>,
+        hidden_keys: HashSet<String>,
+        as_of: Option<OffsetDateTime>,
+    ) -> Self {
+        let remote = RemoteIter::new(bucket, full_path, page_size, false, true, hidden_keys, as_of);
+        Self::Unordered(unordered::ReaddirIter::new(remote, local_entries, None))
     }
 
-    fn unordered(bucket: &str, full_path: &str, page_size: usize, local_entries: VecDeque<ReaddirEntry>) -> Self {
-        Self::Unordered(unordered::ReaddirIter::new(bucket, full_path, page_size, local_entries))
+    fn namespace(inner: &SuperblockInner) -> Result<Self, InodeError> {
+        let root = inner.get(ROOT_INODE_NO)?;
+        let root_state = root.get_inode_state()?;
+        let InodeKindData::Directory { children, .. } = &root_state.kind_data else {
+            unreachable!("root is always a directory");
+        };
+        let mut entries = children
+            .values()
+            .map(|inode| {
+                let stat = inode.get_inode_state()?.stat.clone();
+                Ok(ReaddirEntry::LocalInode {
+                    lookup: LookedUp {
+                        inode: inode.clone(),
+                        stat,
+                    },
+                })
+            })
+            .collect::<Result<Vec<_>, InodeError>>()?;
+        entries.sort();
+        Ok(Self::Namespace(entries.into()))
     }
 
     async fn next(&mut self, client: &impl ObjectClient) -> Result<Option<ReaddirEntry>, InodeError> {
         match self {
             Self::Ordered(iter) => iter.next(client).await,
             Self::Unordered(iter) => iter.next(client).await,
+            Self::Namespace(entries) => Ok(entries.pop_front()),
         }
     }
 }
@@ -310,30 +502,61 @@ enum RemoteIterState {
 /// handles combining directories (common prefixes) and files (objects) into a single stream,
 /// and re-sorting that stream to account for common prefixes not being in lexicographic order (see
 /// the module comment).
+///
+/// Only one ListObjects page is held at a time, and entries are built from it one at a time as the
+/// stream is consumed. The next page is only requested once the current one is exhausted, so a
+/// `readdir` that stops mid-page resumes from where it left off without re-listing.
+///
+/// When the file system shows the bucket at a point in time, the pages come from ListObjectVersions
+/// instead, and each object carries the version that was current at that time.
 #[derive(Debug)]
 struct RemoteIter {
-    entries: VecDeque<ReaddirEntry>,
+    /// Common prefixes remaining in the current page, already trimmed and sorted by name
+    prefixes: VecDeque<String>,
+    /// Objects remaining in the current page, with the version to pin reads to
+    objects: VecDeque<(ObjectInfo, Option<String>)>,
     bucket: String,
     full_path: String,
     page_size: usize,
     state: RemoteIterState,
     ordered: bool,
+    /// Whether to list every key under `full_path` as a file with a flat name, rather than
+    /// splitting keys into directories on `/`
+    flat: bool,
+    /// The listing to use instead of ListObjects, if listing at a point in time
+    versions: Option<VersionsAt>,
+    /// Keys of objects to leave out of the listing
+    hidden_keys: HashSet<String>,
 }
 
 impl RemoteIter {
-    fn new(bucket: &str, full_path: &str, page_size: usize, ordered: bool) -> Self {
+    fn new(
+        bucket: &str,
+        full_path: &str,
+        page_size: usize,
+        ordered: bool,
+        flat: bool,
+        hidden_keys: HashSet<String>,
+        as_of: Option<OffsetDateTime>,
+    ) -> Self {
+        let delimiter = if flat { "" } else { "/" };
         Self {
-            entries: VecDeque::new(),
+            prefixes: VecDeque::new(),
+            objects: VecDeque::new(),
             bucket: bucket.to_owned(),
             full_path: full_path.to_owned(),
             page_size,
             state: RemoteIterState::InProgress(None),
             ordered,
+            flat,
+            versions: as_of.map(|as_of| VersionsAt::new(bucket, full_path, delimiter, as_of)),
+            hidden_keys,
         }
     }
 
     async fn next(&mut self, client: &impl ObjectClient) -> Result<Option<ReaddirEntry>, InodeError> {
-        if self.entries.is_empty() {
+        // A page can be empty without being the last one, e.g. if all its versions were too new
+        while self.prefixes.is_empty() && self.objects.is_empty() {
             let continuation_token = match &mut self.state {
                 RemoteIterState::Finished => {
                     trace!(self=?self as *const _, prefix=?self.full_path, "remote iter finished");
@@ -344,52 +567,92 @@ impl RemoteIter {
 
             trace!(self=?self as *const _, prefix=?self.full_path, ?continuation_token, "continuing remote iter");
 
-            let result = client
-                .list_objects(
-                    &self.bucket,
-                    continuation_token.as_deref(),
-                    "/",
-                    self.page_size,
-                    self.full_path.as_str(),
-                )
-                .await
-                .map_err(|e| InodeError::ClientError(anyhow::Error::new(e)))?;
-
-            self.state = match result.next_continuation_token {
-                Some(token) => RemoteIterState::InProgress(Some(token)),
-                None => RemoteIterState::Finished,
+            let (common_prefixes, objects) = if let Some(versions) = &mut self.versions {
+                let page = versions.next_page(client, self.page_size).await?;
+                self.state = if versions.is_finished() {
+                    RemoteIterState::Finished
+                } else {
+                    RemoteIterState::InProgress(None)
+                };
+                // Only keep the directories that had a key in them at the point in time
+                let mut common_prefixes = Vec::new();
+                for prefix in page.common_prefixes {
+                    if prefix_existed_at(client, &self.bucket, &prefix, versions.as_of()).await? {
+                        common_prefixes.push(prefix);
+                    }
+                }
+                let objects = page
+                    .objects
+                    .into_iter()
+                    .map(|object| (object.info, Some(object.version_id)))
+                    .collect::<Vec<_>>();
+                (common_prefixes, objects)
+            } else {
+                let result = client
+                    .list_objects(
+                        &self.bucket,
+                        continuation_token.as_deref(),
+                        if self.flat { "" } else { "/" },
+                        self.page_size,
+                        self.full_path.as_str(),
+                    )
+                    .await
+                    .map_err(|e| InodeError::ClientError(anyhow::Error::new(e)))?;
+
+                self.state = match result.next_continuation_token {
+                    Some(token) => RemoteIterState::InProgress(Some(token)),
+                    None => RemoteIterState::Finished,
+                };
+                let objects = result.objects.into_iter().map(|object| (object, None)).collect();
+                (result.common_prefixes, objects)
             };
 
-            let prefixes = result
-                .common_prefixes
-                .into_iter()
-                .map(|prefix| ReaddirEntry::RemotePrefix {
-                    name: prefix[self.full_path.len()..prefix.len() - 1].to_owned(),
-                });
-
-            let objects = result
-                .objects
+            let mut prefixes = common_prefixes
                 .into_iter()
-                .map(|object_info| ReaddirEntry::RemoteObject {
-                    name: object_info.key[self.full_path.len()..].to_owned(),
-                    object_info,
-                });
-
+                .map(|prefix| prefix[self.full_path.len()..prefix.len() - 1].to_owned())
+                .collect::<Vec<_>>();
             if self.ordered {
-                // ListObjectsV2 results are sorted, so ideally we'd just merge-sort the two streams.
-                // But `prefixes` isn't quite in sorted order any more because we trimmed off the
-                // trailing `/` from the names. There's still probably a less naive way to do this sort,
-                // but this should be good enough.
-                let mut new_entries = prefixes.chain(objects).collect::<Vec<_>>();
-                new_entries.sort();
-
-                self.entries.extend(new_entries);
-            } else {
-                self.entries.extend(prefixes.chain(objects));
+                // ListObjectsV2 results are sorted, but `prefixes` isn't quite in sorted order any
+                // more because we trimmed off the trailing `/` from the names. Objects are still
+                // sorted, so once prefixes are re-sorted we can merge the two lazily in `next`.
+                prefixes.sort();
             }
+
+            self.prefixes.extend(prefixes);
+            self.objects.extend(
+                objects
+                    .into_iter()
+                    .filter(|(object, _)| !self.hidden_keys.contains(&object.key)),
+            );
         }
 
-        Ok(self.entries.pop_front())
+        // When both a prefix and an object have the same name, the prefix comes first, matching the
+        // [Ord] implementation for [ReaddirEntry]. Unordered listings just return prefixes first.
+        let take_prefix = match (self.prefixes.front(), self.objects.front()) {
+            (Some(prefix), Some((object, _))) => {
+                !self.ordered || prefix.as_str() <= &object.key[self.full_path.len()..]
+            }
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+
+        let entry = if take_prefix {
+            self.prefixes
+                .pop_front()
+                .map(|name| ReaddirEntry::RemotePrefix { name })
+        } else {
+            self.objects.pop_front().map(|(object_info, version_id)| {
+                let key = &object_info.key[self.full_path.len()..];
+                let name = if self.flat { name_for_key(key) } else { key.to_owned() };
+                ReaddirEntry::RemoteObject {
+                    name,
+                    object_info,
+                    version_id,
+                    shadowed: false,
+                }
+            })
+        };
+        Ok(entry)
     }
 }
 
@@ -407,6 +670,7 @@ mod ordered {
         next_remote: Option<ReaddirEntry>,
         next_local: Option<ReaddirEntry>,
         last_entry: Option<ReaddirEntry>,
+        shadowed_file_suffix: Option<String>,
     }
 
     impl ReaddirIter {
@@ -415,13 +679,17 @@ mod ordered {
             full_path: &str,
             page_size: usize,
             local_entries: VecDeque<ReaddirEntry>,
+            hidden_keys: HashSet<String>,
+            as_of: Option<OffsetDateTime>,
+            shadowed_file_suffix: Option<String>,
         ) -> Self {
             Self {
-                remote: RemoteIter::new(bucket, full_path, page_size, true),
+                remote: RemoteIter::new(bucket, full_path, page_size, true, false, hidden_keys, as_of),
                 local: LocalIter::new(local_entries),
                 next_remote: None,
                 next_local: None,
                 last_entry: None,
+                shadowed_file_suffix,
             }
         }
 
@@ -457,6 +725,16 @@ mod ordered {
                 match (next, &self.last_entry) {
                     (Some(entry), Some(last_entry)) => {
                         if last_entry.name() == entry.name() {
+                            // A file shadowed by a remote directory can still be returned under its
+                            // shadowed file name. The directory stays the last entry, so any other
+                            // duplicates are still omitted.
+                            let shadowed_file = match (last_entry, &self.shadowed_file_suffix) {
+                                (ReaddirEntry::RemotePrefix { .. }, Some(suffix)) => entry.shadowed_file(suffix),
+                                _ => None,
+                            };
+                            if shadowed_file.is_some() {
+                                return Ok(shadowed_file);
+                            }
                             warn!(
                                 "{} is omitted because another {} exist with the same name",
                                 entry.description(),
@@ -509,14 +787,18 @@ mod unordered {
         remote: RemoteIter,
         local: HashMap<String, ReaddirEntry>,
         local_iter: VecDeque<ReaddirEntry>,
+        shadowed_file_suffix: Option<String>,
+        /// Names of the remote directories returned so far, if objects they shadow are to be
+        /// returned under their shadowed file names. An object is only known to be shadowed if its
+        /// directory comes first, which is always the case when they're in the same page.
+        remote_prefixes: HashSet<String>,
     }
 
     impl ReaddirIter {
         pub(super) fn new(
-            bucket: &str,
-            full_path: &str,
-            page_size: usize,
+            remote: RemoteIter,
             local_entries: VecDeque<ReaddirEntry>,
+            shadowed_file_suffix: Option<String>,
         ) -> Self {
             let local_map = local_entries
                 .into_iter()
@@ -529,9 +811,11 @@ mod unordered {
                 .collect::<HashMap<_, _>>();
 
             Self {
-                remote: RemoteIter::new(bucket, full_path, page_size, false),
+                remote,
                 local: local_map,
                 local_iter: VecDeque::new(),
+                shadowed_file_suffix,
+                remote_prefixes: HashSet::new(),
             }
         }
 
@@ -540,6 +824,13 @@ mod unordered {
         pub(super) async fn next(&mut self, client: &impl ObjectClient) -> Result<Option<ReaddirEntry>, InodeError> {
             if let Some(remote) = self.remote.next(client).await? {
                 self.local.remove(remote.name());
+                if let Some(suffix) = &self.shadowed_file_suffix {
+                    if let ReaddirEntry::RemotePrefix { name } = &remote {
+                        self.remote_prefixes.insert(name.clone());
+                    } else if self.remote_prefixes.contains(remote.name()) {
+                        return Ok(Some(remote.shadowed_file(suffix).unwrap_or(remote)));
+                    }
+                }
                 return Ok(Some(remote));
             }
 
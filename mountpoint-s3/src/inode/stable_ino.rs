@@ -0,0 +1,195 @@
+//! Inode numbers derived from the key of each inode, so that the same file or directory has the
+//! same number every time the bucket is mounted.
+//!
+//! The number for a key is the first 8 bytes of the SHA-256 hash of the bucket name and the key.
+//! Two inodes can't have the same number while both are alive, which happens if two keys hash to
+//! the same number, or if a file is replaced while the inode for its old object is still in use.
+//! The second inode instead gets the first free number from a sequence of further hashes of its
+//! key, which is still derived from the key, but depends on the order the two inodes were created
+//! in and so isn't stable across mounts.
+
+use std::collections::HashMap;
+
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use super::{Inode, InodeInner, InodeNo};
+
+use crate::sync::{Arc, Mutex, Weak};
+
+/// How many inodes to track before the first sweep for inodes that are no longer alive
+const MIN_SWEEP_LEN: usize = 1024;
+
+/// Allocates inode numbers from the keys of inodes, and tracks which numbers are in use
+#[derive(Debug)]
+pub struct StableInodeNumbers {
+    /// Numbers below this one are reserved for the inodes created along with the superblock
+    first_ino: InodeNo,
+    live: Mutex<LiveInodes>,
+}
+
+#[derive(Debug)]
+struct LiveInodes {
+    /// The inode each number was last given to, which might no longer be alive
+    map: HashMap<InodeNo, Weak<InodeInner>>,
+    /// How large the map can grow before removing the inodes that are no longer alive
+    sweep_at: usize,
+}
+
+impl StableInodeNumbers {
+    pub fn new(first_ino: InodeNo) -> Self {
+        Self {
+            first_ino,
+            live: Mutex::new(LiveInodes {
+                map: HashMap::new(),
+                sweep_at: MIN_SWEEP_LEN,
+            }),
+        }
+    }
+
+    /// Create an inode for a key with `new_inode`, giving it the number for that key if no other
+    /// inode is using it.
+    pub fn create(&self, bucket: &str, key: &str, new_inode: impl FnOnce(InodeNo) -> Inode) -> Inode {
+        let mut live = self.live.lock().unwrap();
+        let mut attempt = 0;
+        let ino = loop {
+            let ino = ino_for_key(bucket, key, attempt);
+            if ino >= self.first_ino && !live.is_alive(ino) {
+                break ino;
+            }
+            attempt += 1;
+        };
+        if attempt > 0 {
+            debug!(
+                ?key,
+                ino, attempt, "inode number for key is already in use, using another number"
+            );
+            metrics::counter!("metadata_cache.stable_inode_number_collisions").increment(1);
+        }
+
+        let inode = new_inode(ino);
+        live.insert(ino, &inode);
+        inode
+    }
+
+    /// Record that `inode` now holds its number, because it replaced the inode that had it before,
+    /// like when an inode moves to a new key in a rename.
+    pub fn replace(&self, inode: &Inode) {
+        self.live.lock().unwrap().insert(inode.ino(), inode);
+    }
+}
+
+impl LiveInodes {
+    fn is_alive(&self, ino: InodeNo) -> bool {
+        self.map.get(&ino).is_some_and(|inode| inode.strong_count() > 0)
+    }
+
+    fn insert(&mut self, ino: InodeNo, inode: &Inode) {
+        self.map.insert(ino, Arc::downgrade(&inode.inner));
+        if self.map.len() >= self.sweep_at {
+            self.map.retain(|_, inode| inode.strong_count() > 0);
+            self.sweep_at = (self.map.len() * 2).max(MIN_SWEEP_LEN);
+        }
+    }
+}
+
+/// The inode number for a key, or for later attempts, the next number to try if earlier ones were
+/// in use
+fn ino_for_key(bucket: &str, key: &str, attempt: u64) -> InodeNo {
+    let mut hasher = Sha256::new();
+    hasher.update(bucket);
+    hasher.update([0]);
+    hasher.update(key);
+    if attempt > 0 {
+        hasher.update(attempt.to_be_bytes());
+    }
+    let hash = hasher.finalize();
+    InodeNo::from_be_bytes(hash[..8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use time::OffsetDateTime;
+
+    use super::*;
+    use crate::inode::{InodeKind, InodeKindData, InodeStat, InodeState, WriteStatus, ROOT_INODE_NO};
+
+    fn create(numbers: &StableInodeNumbers, bucket: &str, key: &str) -> Inode {
+        numbers.create(bucket, key, |ino| {
+            let stat = InodeStat::for_file(0, OffsetDateTime::UNIX_EPOCH, None, None, None, Duration::ZERO);
+            Inode::new(
+                ino,
+                ROOT_INODE_NO,
+                key.to_owned(),
+                key.to_owned(),
+                bucket.into(),
+                InodeKind::File,
+                InodeState {
+                    stat,
+                    write_status: WriteStatus::Remote,
+                    kind_data: InodeKindData::default_for(InodeKind::File),
+                    lookup_count: 0,
+                    reader_count: 0,
+                    renamed_to: None,
+                },
+            )
+        })
+    }
+
+    #[test]
+    fn test_same_key_same_number() {
+        let a = create(&StableInodeNumbers::new(2), "test_bucket", "dir/a");
+        let ino = a.ino();
+        assert_eq!(ino, ino_for_key("test_bucket", "dir/a", 0));
+        drop(a);
+
+        // A new superblock, like after remounting, gives the key the same number
+        let a = create(&StableInodeNumbers::new(2), "test_bucket", "dir/a");
+        assert_eq!(a.ino(), ino);
+
+        let other_bucket = create(&StableInodeNumbers::new(2), "other_bucket", "dir/a");
+        assert_ne!(other_bucket.ino(), ino);
+        let other_key = create(&StableInodeNumbers::new(2), "test_bucket", "dir/b");
+        assert_ne!(other_key.ino(), ino);
+    }
+
+    #[test]
+    fn test_number_in_use() {
+        let numbers = StableInodeNumbers::new(2);
+        let old = create(&numbers, "test_bucket", "a");
+        let ino = old.ino();
+
+        // While the old inode is alive, a new inode for the same key needs another number
+        let new = create(&numbers, "test_bucket", "a");
+        assert_ne!(new.ino(), ino);
+        assert_eq!(new.ino(), ino_for_key("test_bucket", "a", 1));
+
+        // Once it's gone, the key gets its own number back
+        drop(old);
+        let newer = create(&numbers, "test_bucket", "a");
+        assert_eq!(newer.ino(), ino);
+    }
+
+    #[test]
+    fn test_reserved_numbers() {
+        // Reserve every number below the one the key hashes to
+        let ino = ino_for_key("test_bucket", "a", 0);
+        let numbers = StableInodeNumbers::new(ino + 1);
+        let inode = create(&numbers, "test_bucket", "a");
+        assert!(inode.ino() > ino);
+    }
+
+    #[test]
+    fn test_sweep() {
+        let numbers = StableInodeNumbers::new(2);
+        let alive = create(&numbers, "test_bucket", "alive");
+        for i in 0..MIN_SWEEP_LEN {
+            create(&numbers, "test_bucket", &format!("dead{i}"));
+        }
+        let live = numbers.live.lock().unwrap();
+        assert!(live.map.len() < MIN_SWEEP_LEN);
+        assert!(live.is_alive(alive.ino()));
+    }
+}
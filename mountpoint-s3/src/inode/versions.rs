@@ -0,0 +1,139 @@
+//! Listing a versioned bucket as it was at a point in time.
+//!
+//! ListObjectVersions returns every version of each key, including delete markers, sorted by key
+//! and then from newest to oldest. The bucket as it was at some time `T` contains the newest
+//! version of each key that was created at or before `T`, unless that version is a delete marker.
+//! Keys with no versions that old didn't exist yet.
+
+use anyhow::anyhow;
+use mountpoint_s3_client::types::{ObjectInfo, ObjectVersion};
+use mountpoint_s3_client::ObjectClient;
+use time::OffsetDateTime;
+
+use super::InodeError;
+
+/// Page size for ListObjectVersions requests that look for a single key or directory
+pub(super) const LOOKUP_PAGE_SIZE: usize = 1000;
+
+/// An object as it was at the listing's point in time
+#[derive(Debug, Clone)]
+pub(super) struct VersionedObject {
+    pub info: ObjectInfo,
+    pub version_id: String,
+}
+
+/// One page of a [VersionsAt] listing
+#[derive(Debug, Default)]
+pub(super) struct VersionsAtPage {
+    /// Keys that existed at the listing's point in time, in key order
+    pub objects: Vec<VersionedObject>,
+    /// Common prefixes in the page. These are not filtered by time, so they include prefixes whose
+    /// keys were all created later or deleted earlier. Use [prefix_existed_at] to check them.
+    pub common_prefixes: Vec<String>,
+}
+
+/// A paginated ListObjectVersions listing that keeps only the version of each key that was current
+/// at a point in time
+#[derive(Debug)]
+pub(super) struct VersionsAt {
+    bucket: String,
+    prefix: String,
+    delimiter: &'static str,
+    as_of: OffsetDateTime,
+    /// Key and version ID markers for the next request, or `None` once the listing is finished
+    next_markers: Option<(Option<String>, Option<String>)>,
+    /// The last key whose version at `as_of` we've found. A key's versions can span pages, so its
+    /// older versions may still be in the next page.
+    resolved_key: Option<String>,
+}
+
+impl VersionsAt {
+    pub fn new(bucket: &str, prefix: &str, delimiter: &'static str, as_of: OffsetDateTime) -> Self {
+        Self {
+            bucket: bucket.to_owned(),
+            prefix: prefix.to_owned(),
+            delimiter,
+            as_of,
+            next_markers: Some((None, None)),
+            resolved_key: None,
+        }
+    }
+
+    /// The point in time the listing is at
+    pub fn as_of(&self) -> OffsetDateTime {
+        self.as_of
+    }
+
+    /// Whether there are no more pages to list
+    pub fn is_finished(&self) -> bool {
+        self.next_markers.is_none()
+    }
+
+    /// The key (or common prefix) the previous page ended at, if there are more pages to list
+    pub fn key_marker(&self) -> Option<&str> {
+        self.next_markers
+            .as_ref()
+            .and_then(|(key_marker, _)| key_marker.as_deref())
+    }
+
+    /// List the next page. Returns an empty page if the listing is finished.
+    pub async fn next_page(
+        &mut self,
+        client: &impl ObjectClient,
+        page_size: usize,
+    ) -> Result<VersionsAtPage, InodeError> {
+        let Some((key_marker, version_id_marker)) = self.next_markers.take() else {
+            return Ok(Default::default());
+        };
+
+        let result = client
+            .list_object_versions(
+                &self.bucket,
+                key_marker.as_deref(),
+                version_id_marker.as_deref(),
+                self.delimiter,
+                page_size,
+                &self.prefix,
+            )
+            .await
+            .map_err(|e| InodeError::ClientError(anyhow!(e).context("ListObjectVersions failed")))?;
+
+        let mut objects = Vec::new();
+        for version in result.versions {
+            if self.resolved_key.as_deref() == Some(version.key()) || version.last_modified() > self.as_of {
+                continue;
+            }
+            self.resolved_key = Some(version.key().to_owned());
+            if let ObjectVersion::Object { info, version_id } = version {
+                objects.push(VersionedObject { info, version_id });
+            }
+        }
+
+        self.next_markers = result
+            .next_key_marker
+            .map(|key_marker| (Some(key_marker), result.next_version_id_marker));
+
+        Ok(VersionsAtPage {
+            objects,
+            common_prefixes: result.common_prefixes,
+        })
+    }
+}
+
+/// Whether any key under `prefix` existed at `as_of`, which is when a directory for the prefix
+/// existed. Lists the prefix's versions until one is found, so this is cheap for most prefixes but
+/// can take several requests if its keys were created later or deleted earlier.
+pub(super) async fn prefix_existed_at(
+    client: &impl ObjectClient,
+    bucket: &str,
+    prefix: &str,
+    as_of: OffsetDateTime,
+) -> Result<bool, InodeError> {
+    let mut versions = VersionsAt::new(bucket, prefix, "", as_of);
+    while !versions.is_finished() {
+        if !versions.next_page(client, LOOKUP_PAGE_SIZE).await?.objects.is_empty() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
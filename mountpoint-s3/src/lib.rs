@@ -2,13 +2,18 @@ pub mod autoconfigure;
 mod build_info;
 mod checksums;
 pub mod cli;
+mod config_file;
+mod credentials;
+mod daemon;
 pub mod data_cache;
+mod event_notifications;
 pub mod fs;
 pub mod fuse;
 mod inode;
 pub mod logging;
 pub mod metrics;
-mod object;
+mod mount_helper;
+pub mod object;
 pub mod prefetch;
 pub mod prefix;
 pub mod s3;
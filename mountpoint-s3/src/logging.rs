@@ -1,23 +1,25 @@
 use std::backtrace::Backtrace;
-use std::fs::{DirBuilder, OpenOptions};
+use std::fs::DirBuilder;
 use std::os::unix::fs::DirBuilderExt;
-use std::os::unix::prelude::OpenOptionsExt;
 use std::panic::{self, PanicInfo};
 use std::path::PathBuf;
+use std::sync::Mutex;
 use std::thread;
 
 use crate::metrics::metrics_tracing_span_layer;
 use anyhow::Context;
 use mountpoint_s3_crt::common::rust_log_adapter::RustLogAdapter;
-use time::format_description::FormatItem;
-use time::macros;
-use time::OffsetDateTime;
 use tracing::Span;
 use tracing_subscriber::filter::{EnvFilter, Filtered, LevelFilter};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{Layer, Registry};
 
+mod otlp;
+pub use self::otlp::shutdown_otlp;
+mod rotating_file;
+pub use self::rotating_file::LogRotation;
+use self::rotating_file::RotatingFile;
 mod syslog;
 use self::syslog::SyslogLayer;
 
@@ -26,11 +28,15 @@ use self::syslog::SyslogLayer;
 pub struct LoggingConfig {
     /// A directory to create log files in. If unspecified, logs will be routed to syslog.
     pub log_directory: Option<PathBuf>,
+    /// When to rotate the files in the log directory.
+    pub log_rotation: LogRotation,
     /// Whether to duplicate logs to stdout in addition to syslog or the log directory.
     pub log_to_stdout: bool,
     /// The default filter directive (in the sense of [tracing_subscriber::filter::EnvFilter]) to
     /// use for logs. Will be overridden by the `MOUNTPOINT_LOG` environment variable if set.
     pub default_filter: String,
+    /// An OTLP/HTTP endpoint to export tracing spans to, if any.
+    pub otlp_endpoint: Option<String>,
 }
 
 /// Set up all our logging infrastructure.
@@ -85,34 +91,23 @@ fn init_tracing_subscriber(config: LoggingConfig) -> anyhow::Result<()> {
     }
 
     let env_filter = create_env_filter(&config.default_filter);
-    // Don't create the files or subscribers if we'll never emit any logs
-    if env_filter.max_level_hint() == Some(LevelFilter::OFF) {
+    // Don't create the files or subscribers if we'll never emit any logs or spans
+    if env_filter.max_level_hint() == Some(LevelFilter::OFF) && config.otlp_endpoint.is_none() {
         return Ok(());
     }
 
     RustLogAdapter::try_init().context("failed to initialize CRT logger")?;
 
     let file_layer = if let Some(path) = &config.log_directory {
-        const LOG_FILE_NAME_FORMAT: &[FormatItem<'static>] =
-            macros::format_description!("mountpoint-s3-[year]-[month]-[day]T[hour]-[minute]-[second]Z.log");
-        let filename = OffsetDateTime::now_utc()
-            .format(LOG_FILE_NAME_FORMAT)
-            .context("couldn't format log file name")?;
-
         // log directories and files created by Mountpoint should not be accessible by other users
         let mut dir_builder = DirBuilder::new();
         dir_builder.recursive(true).mode(0o750);
-        let mut file_options = OpenOptions::new();
-        file_options.mode(0o640).append(true).create(true);
-
         dir_builder.create(path).context("failed to create log folder")?;
-        let file = file_options
-            .open(path.join(filename))
-            .context("failed to create log file")?;
+        let file = RotatingFile::new(path, config.log_rotation.clone()).context("failed to create log file")?;
 
         let file_layer = tracing_subscriber::fmt::layer()
             .with_ansi(false)
-            .with_writer(file)
+            .with_writer(Mutex::new(file))
             .with_filter(env_filter);
         Some(file_layer)
     } else {
@@ -138,10 +133,13 @@ fn init_tracing_subscriber(config: LoggingConfig) -> anyhow::Result<()> {
         None
     };
 
+    let otlp_layer = config.otlp_endpoint.as_deref().map(otlp::otlp_layer).transpose()?;
+
     let registry = tracing_subscriber::registry()
         .with(syslog_layer)
         .with(console_layer)
         .with(file_layer)
+        .with(otlp_layer)
         .with(metrics_tracing_span_layer());
 
     registry.init();
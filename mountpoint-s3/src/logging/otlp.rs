@@ -0,0 +1,178 @@
+//! Export of [tracing] spans to an OpenTelemetry collector over OTLP.
+//!
+//! FUSE operations and the S3 requests made on their behalf already have spans, so we hand those
+//! spans to [tracing_opentelemetry] and export them as traces. S3 request spans created while a
+//! FUSE operation's span is active are children of it, so a trace follows a system call through
+//! to the S3 requests that served it.
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::Context as _;
+use opentelemetry::trace::{TraceResult, TracerProvider as _};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::export::trace::{SpanData, SpanExporter};
+use opentelemetry_sdk::{trace, Resource};
+use tracing::warn;
+use tracing::{Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::build_info;
+
+/// The name we report to the collector for the service producing the traces
+const SERVICE_NAME: &str = "mountpoint-s3";
+
+/// Most finished spans to hold while they wait to be exported. Spans that finish while the queue is
+/// full are dropped, so a slow or unreachable collector can't make us use unbounded memory.
+const MAX_QUEUE_SIZE: usize = 2048;
+
+/// Most spans to send to the collector in one request
+const MAX_EXPORT_BATCH_SIZE: usize = 512;
+
+/// Longest a finished span waits before we export a partial batch
+const SCHEDULED_DELAY: Duration = Duration::from_secs(5);
+
+/// Create a [Layer] that exports spans to the OTLP/HTTP collector at `endpoint`, like
+/// `http://localhost:4318`.
+pub fn otlp_layer<S>(endpoint: &str) -> anyhow::Result<impl Layer<S>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let exporter = opentelemetry_otlp::new_exporter()
+        .http()
+        .with_endpoint(endpoint)
+        .build_span_exporter()
+        .context("failed to create OTLP exporter")?;
+    let resource = Resource::new([
+        KeyValue::new("service.name", SERVICE_NAME),
+        KeyValue::new("service.version", build_info::FULL_VERSION),
+    ]);
+    let provider = trace::TracerProvider::builder()
+        .with_span_processor(BatchSpanProcessor::new(exporter)?)
+        .with_config(trace::config().with_resource(resource))
+        .build();
+    let tracer = provider.tracer(SERVICE_NAME);
+    let _ = opentelemetry::global::set_tracer_provider(provider);
+
+    // FUSE operation and S3 request spans are emitted at WARN level, so that they exist whenever
+    // any of their events might be logged
+    let targets = Targets::new()
+        .with_target("mountpoint_s3", Level::WARN)
+        .with_target("mountpoint_s3_client", Level::WARN);
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer).with_filter(targets))
+}
+
+/// Export any spans that haven't been exported yet and stop the exporter. Does nothing if we
+/// weren't exporting spans.
+pub fn shutdown_otlp() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// A [trace::SpanProcessor] that exports finished spans in batches from a dedicated thread.
+///
+/// The SDK's own batch processor needs an async runtime, which we don't otherwise have, and its
+/// simple processor sends one request per span through an unbounded queue, which can't keep up
+/// with a busy file system. This one queues at most [MAX_QUEUE_SIZE] spans and drops any more.
+#[derive(Debug)]
+struct BatchSpanProcessor {
+    sender: SyncSender<Message>,
+    worker: Option<JoinHandle<()>>,
+}
+
+enum Message {
+    Span(SpanData),
+    Flush(mpsc::Sender<()>),
+    Shutdown,
+}
+
+impl BatchSpanProcessor {
+    fn new<E: SpanExporter + 'static>(exporter: E) -> anyhow::Result<Self> {
+        let (sender, receiver) = mpsc::sync_channel(MAX_QUEUE_SIZE);
+        let worker = thread::Builder::new()
+            .name("otlp-exporter".to_owned())
+            .spawn(move || export_batches(exporter, receiver))
+            .context("failed to start OTLP exporter thread")?;
+        Ok(Self {
+            sender,
+            worker: Some(worker),
+        })
+    }
+}
+
+impl trace::SpanProcessor for BatchSpanProcessor {
+    fn on_start(&self, _span: &mut trace::Span, _cx: &Context) {}
+
+    fn on_end(&self, span: SpanData) {
+        if !span.span_context.is_sampled() {
+            return;
+        }
+        match self.sender.try_send(Message::Span(span)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => metrics::counter!("otlp.dropped_spans").increment(1),
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        let (done, wait) = mpsc::channel();
+        self.sender
+            .send(Message::Flush(done))
+            .map_err(|_| "OTLP exporter thread has stopped")?;
+        wait.recv().map_err(|_| "OTLP exporter thread has stopped")?;
+        Ok(())
+    }
+
+    fn shutdown(&mut self) -> TraceResult<()> {
+        let Some(worker) = self.worker.take() else {
+            return Ok(());
+        };
+        // If the thread has already stopped, joining it below is all that's left to do
+        let _ = self.sender.send(Message::Shutdown);
+        worker.join().map_err(|_| "OTLP exporter thread panicked")?;
+        Ok(())
+    }
+}
+
+/// Collect spans from `receiver` into batches and export them, until the processor shuts down
+fn export_batches<E: SpanExporter>(mut exporter: E, receiver: Receiver<Message>) {
+    let mut batch = Vec::with_capacity(MAX_EXPORT_BATCH_SIZE);
+    let mut deadline = Instant::now() + SCHEDULED_DELAY;
+    loop {
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(Message::Span(span)) => {
+                batch.push(span);
+                if batch.len() >= MAX_EXPORT_BATCH_SIZE {
+                    export_batch(&mut exporter, &mut batch);
+                    deadline = Instant::now() + SCHEDULED_DELAY;
+                }
+            }
+            Ok(Message::Flush(done)) => {
+                export_batch(&mut exporter, &mut batch);
+                let _ = done.send(());
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                export_batch(&mut exporter, &mut batch);
+                deadline = Instant::now() + SCHEDULED_DELAY;
+            }
+            Ok(Message::Shutdown) | Err(RecvTimeoutError::Disconnected) => {
+                export_batch(&mut exporter, &mut batch);
+                exporter.shutdown();
+                return;
+            }
+        }
+    }
+}
+
+fn export_batch<E: SpanExporter>(exporter: &mut E, batch: &mut Vec<SpanData>) {
+    if batch.is_empty() {
+        return;
+    }
+    let spans = std::mem::replace(batch, Vec::with_capacity(MAX_EXPORT_BATCH_SIZE));
+    if let Err(err) = futures::executor::block_on(exporter.export(spans)) {
+        warn!(?err, "failed to export spans to OTLP collector");
+    }
+}
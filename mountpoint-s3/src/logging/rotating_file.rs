@@ -0,0 +1,244 @@
+//! A log file that's rotated once it gets too big or too old.
+//!
+//! When the file being written reaches its size or age limit, we start writing to a new file and
+//! hand the old one to a background thread that compresses it with gzip. Several mounts can share
+//! a log directory, so the retention limit only counts the files rotated by this process, and we
+//! never delete log files written by anyone else.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::prelude::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use time::format_description::FormatItem;
+use time::macros;
+use time::OffsetDateTime;
+
+const LOG_FILE_NAME_FORMAT: &[FormatItem<'static>] =
+    macros::format_description!("mountpoint-s3-[year]-[month]-[day]T[hour]-[minute]-[second]Z");
+
+/// Log files created by Mountpoint should not be accessible by other users
+const LOG_FILE_MODE: u32 = 0o640;
+
+/// When to rotate log files, and how many rotated files to keep
+#[derive(Debug, Clone, Default)]
+pub struct LogRotation {
+    /// Rotate the log file once it's at least this many bytes
+    pub max_size: Option<u64>,
+    /// Rotate the log file once it was created at least this long ago
+    pub max_age: Option<Duration>,
+    /// Delete the oldest rotated files once there are more than this many
+    pub max_files: Option<usize>,
+}
+
+/// A [Write] implementation that appends to a log file in a directory, and rotates it according to
+/// a [LogRotation] policy.
+#[derive(Debug)]
+pub struct RotatingFile {
+    directory: PathBuf,
+    rotation: LogRotation,
+    file: File,
+    path: PathBuf,
+    size: u64,
+    created: Instant,
+    /// Created on the first rotation
+    compressor: Option<Sender<PathBuf>>,
+}
+
+impl RotatingFile {
+    /// Create a new log file in `directory`, which must already exist.
+    pub fn new(directory: &Path, rotation: LogRotation) -> io::Result<Self> {
+        let (file, path) = create_log_file(directory)?;
+        Ok(Self {
+            directory: directory.to_owned(),
+            rotation,
+            file,
+            path,
+            size: 0,
+            created: Instant::now(),
+            compressor: None,
+        })
+    }
+
+    fn should_rotate(&self) -> bool {
+        let too_big = self.rotation.max_size.is_some_and(|max_size| self.size >= max_size);
+        let too_old = self
+            .rotation
+            .max_age
+            .is_some_and(|max_age| self.created.elapsed() >= max_age);
+        too_big || too_old
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let (file, path) = create_log_file(&self.directory)?;
+        let old_path = std::mem::replace(&mut self.path, path);
+        self.file = file;
+        self.size = 0;
+        self.created = Instant::now();
+
+        if self.compressor.is_none() {
+            self.compressor = Some(spawn_compressor(self.rotation.max_files)?);
+        }
+        // Not much we can do if the compressor thread is gone; the old file stays uncompressed
+        let _ = self.compressor.as_ref().unwrap().send(old_path);
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // We can't log failures from inside the logger. If we can't start a new file, keep writing
+        // to the old one rather than losing logs.
+        if self.should_rotate() {
+            let _ = self.rotate();
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Create a new log file in `directory`, named after the current time. If a file with that name
+/// already exists, add a counter to the name so we never append to someone else's file.
+fn create_log_file(directory: &Path) -> io::Result<(File, PathBuf)> {
+    let name = OffsetDateTime::now_utc()
+        .format(LOG_FILE_NAME_FORMAT)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    for attempt in 0.. {
+        let path = if attempt == 0 {
+            directory.join(format!("{name}.log"))
+        } else {
+            directory.join(format!("{name}-{attempt}.log"))
+        };
+        match OpenOptions::new()
+            .mode(LOG_FILE_MODE)
+            .append(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => return Ok((file, path)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("loop only exits by returning")
+}
+
+/// Start a thread that compresses each rotated log file it receives, and deletes the oldest ones
+/// once there are more than `max_files`.
+fn spawn_compressor(max_files: Option<usize>) -> io::Result<Sender<PathBuf>> {
+    let (sender, receiver) = mpsc::channel::<PathBuf>();
+    thread::Builder::new().name("log-rotation".to_owned()).spawn(move || {
+        let mut rotated = VecDeque::new();
+        for path in receiver {
+            // If compression fails, keep the uncompressed file instead
+            let path = compress(&path).unwrap_or(path);
+            rotated.push_back(path);
+            while max_files.is_some_and(|max_files| rotated.len() > max_files) {
+                let oldest = rotated.pop_front().unwrap();
+                let _ = fs::remove_file(oldest);
+            }
+        }
+    })?;
+    Ok(sender)
+}
+
+/// Compress the file at `path` with gzip, replacing it with a `.gz` file, and return the path of
+/// the compressed file.
+fn compress(path: &Path) -> io::Result<PathBuf> {
+    let mut compressed_path = path.as_os_str().to_owned();
+    compressed_path.push(".gz");
+    let compressed_path = PathBuf::from(compressed_path);
+
+    let mut input = File::open(path)?;
+    let output = OpenOptions::new()
+        .mode(LOG_FILE_MODE)
+        .write(true)
+        .create_new(true)
+        .open(&compressed_path)?;
+    let mut encoder = GzEncoder::new(output, Compression::default());
+    let result = io::copy(&mut input, &mut encoder).and_then(|_| encoder.finish());
+    if let Err(e) = result {
+        let _ = fs::remove_file(&compressed_path);
+        return Err(e);
+    }
+    fs::remove_file(path)?;
+    Ok(compressed_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    fn files_in(directory: &Path) -> Vec<PathBuf> {
+        let mut files: Vec<_> = fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_rotate_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let rotation = LogRotation {
+            max_size: Some(10),
+            max_age: None,
+            max_files: Some(2),
+        };
+        let mut file = RotatingFile::new(dir.path(), rotation).unwrap();
+        for i in 0..4 {
+            file.write_all(format!("line {i} of the log\n").as_bytes()).unwrap();
+        }
+        let current = file.path.clone();
+        drop(file);
+
+        // The compressor runs in the background, so wait for it to catch up
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let files = files_in(dir.path());
+            let compressed: Vec<_> = files
+                .iter()
+                .filter(|path| path.extension().is_some_and(|ext| ext == "gz"))
+                .collect();
+            if compressed.len() == 2 && files.len() == 3 {
+                // The oldest rotated file was deleted, so the newest compressed file has the third line
+                let mut contents = String::new();
+                GzDecoder::new(File::open(compressed[1]).unwrap())
+                    .read_to_string(&mut contents)
+                    .unwrap();
+                assert_eq!(contents, "line 2 of the log\n");
+                assert_eq!(fs::read_to_string(&current).unwrap(), "line 3 of the log\n");
+                break;
+            }
+            assert!(Instant::now() < deadline, "log files weren't rotated: {files:?}");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_no_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::new(dir.path(), LogRotation::default()).unwrap();
+        for i in 0..100 {
+            file.write_all(format!("line {i}\n").as_bytes()).unwrap();
+        }
+        assert_eq!(files_in(dir.path()).len(), 1);
+        assert!(file.compressor.is_none());
+    }
+}
@@ -1,11 +1,16 @@
 //! Metrics infrastructure
 //!
 //! This module hooks up the [metrics](https://docs.rs/metrics) facade to a metrics sink that
-//! currently just emits them to a tracing log entry.
+//! emits them to a tracing log entry, and optionally to a file in CloudWatch Embedded Metric
+//! Format.
 
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
+use anyhow::Context as _;
 use dashmap::DashMap;
 use metrics::{Key, Metadata, Recorder};
 use sysinfo::{get_current_pid, MemoryRefreshKind, ProcessRefreshKind, System};
@@ -16,6 +21,9 @@ use crate::sync::Arc;
 mod data;
 use data::*;
 
+mod emf;
+use emf::EmfFormatter;
+
 mod tracing_span;
 pub use tracing_span::metrics_tracing_span_layer;
 
@@ -25,14 +33,46 @@ const AGGREGATION_PERIOD: Duration = Duration::from_secs(5);
 /// The log target to use for emitted metrics
 pub const TARGET_NAME: &str = "mountpoint_s3::metrics";
 
+/// The format of the metrics written to the log
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MetricsFormat {
+    /// One human-readable line per metric
+    #[default]
+    Log,
+    /// CloudWatch Embedded Metric Format (EMF) JSON documents
+    Emf,
+}
+
+impl MetricsFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Log => "log",
+            Self::Emf => "emf",
+        }
+    }
+}
+
+/// Configuration for where and how metrics are published
+#[derive(Debug, Default)]
+pub struct MetricsConfig {
+    /// The format of the metrics written to the log
+    pub log_format: MetricsFormat,
+    /// A file to append metrics to in EMF, in addition to the log
+    pub emf_file: Option<PathBuf>,
+    /// Dimensions to add to every metric in EMF output, like the bucket name
+    pub emf_dimensions: Vec<(String, String)>,
+}
+
 /// Initialize and install the global metrics sink, and return a handle that can be used to shut
 /// the sink down. The sink should only be shut down after any threads that generate metrics are
 /// done with their work; metrics generated after shutting down the sink will be lost.
 ///
-/// Panics if a sink has already been installed.
-pub fn install() -> MetricsSinkHandle {
+/// Returns an error if the metrics file can't be opened, and panics if a sink has already been
+/// installed.
+pub fn install(config: MetricsConfig) -> anyhow::Result<MetricsSinkHandle> {
     let sink = Arc::new(MetricsSink::new());
     let mut sys = System::new();
+    let mut publisher = MetricsPublisher::new(config)?;
 
     let (tx, rx) = channel();
 
@@ -44,7 +84,7 @@ pub fn install() -> MetricsSinkHandle {
                     Ok(()) | Err(RecvTimeoutError::Disconnected) => break,
                     Err(RecvTimeoutError::Timeout) => {
                         poll_process_metrics(&mut sys);
-                        inner.publish()
+                        publisher.publish(inner.take_values());
                     }
                 }
             }
@@ -52,7 +92,7 @@ pub fn install() -> MetricsSinkHandle {
             // any new metrics data after the sink shuts down, but we assume a clean shutdown
             // stops generating new metrics before shutting down the sink.
             poll_process_metrics(&mut sys);
-            inner.publish();
+            publisher.publish(inner.take_values());
         })
     };
 
@@ -64,7 +104,7 @@ pub fn install() -> MetricsSinkHandle {
     let recorder = MetricsRecorder { sink };
     metrics::set_global_recorder(recorder).unwrap();
 
-    handle
+    Ok(handle)
 }
 
 /// Report process level metrics
@@ -110,35 +150,96 @@ impl MetricsSink {
         entry.as_histogram()
     }
 
-    /// Publish all this sink's metrics to `tracing` log messages
-    fn publish(&self) {
-        // Collect the output lines so we can sort them to make reading easier
-        let mut metrics = vec![];
-
-        for mut entry in self.metrics.iter_mut() {
-            let (key, metric) = entry.pair_mut();
-            let Some(metric) = metric.fmt_and_reset() else {
-                continue;
-            };
-            let labels = if key.labels().len() == 0 {
-                String::new()
-            } else {
-                format!(
-                    "[{}]",
-                    key.labels()
-                        .map(|label| format!("{}={}", label.key(), label.value()))
-                        .collect::<Vec<_>>()
-                        .join(",")
-                )
-            };
-            metrics.push(format!("{}{}: {}", key.name(), labels, metric));
+    /// Take the values of all this sink's metrics that have changed since the last call
+    fn take_values(&self) -> Vec<(Key, MetricValue)> {
+        self.metrics
+            .iter()
+            .filter_map(|entry| Some((entry.key().clone(), entry.value().take_value()?)))
+            .collect()
+    }
+}
+
+/// Writes the metrics collected in each aggregation period to their destinations
+#[derive(Debug)]
+struct MetricsPublisher {
+    log_format: MetricsFormat,
+    emf: EmfFormatter,
+    emf_file: Option<File>,
+}
+
+impl MetricsPublisher {
+    fn new(config: MetricsConfig) -> anyhow::Result<Self> {
+        let emf_file = config
+            .emf_file
+            .map(|path| {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .with_context(|| format!("failed to open metrics file {}", path.display()))
+            })
+            .transpose()?;
+        Ok(Self {
+            log_format: config.log_format,
+            emf: EmfFormatter::new(config.emf_dimensions),
+            emf_file,
+        })
+    }
+
+    fn publish(&mut self, metrics: Vec<(Key, MetricValue)>) {
+        if metrics.is_empty() {
+            return;
         }
 
-        metrics.sort();
+        let emf_documents = (self.log_format == MetricsFormat::Emf || self.emf_file.is_some())
+            .then(|| self.emf.format(&metrics, SystemTime::now()));
 
-        for metric in metrics {
-            tracing::info!(target: TARGET_NAME, "{}", metric);
+        if let (Some(file), Some(documents)) = (&mut self.emf_file, &emf_documents) {
+            let mut output = String::new();
+            for document in documents {
+                output.push_str(document);
+                output.push('\n');
+            }
+            if let Err(error) = file.write_all(output.as_bytes()) {
+                tracing::warn!(?error, "failed to write metrics file");
+            }
         }
+
+        match self.log_format {
+            MetricsFormat::Log => log_metrics(&metrics),
+            MetricsFormat::Emf => {
+                for document in emf_documents.iter().flatten() {
+                    tracing::info!(target: TARGET_NAME, "{}", document);
+                }
+            }
+        }
+    }
+}
+
+/// Publish metrics to `tracing` log messages, one per metric
+fn log_metrics(metrics: &[(Key, MetricValue)]) {
+    // Collect the output lines so we can sort them to make reading easier
+    let mut lines = vec![];
+
+    for (key, metric) in metrics {
+        let labels = if key.labels().len() == 0 {
+            String::new()
+        } else {
+            format!(
+                "[{}]",
+                key.labels()
+                    .map(|label| format!("{}={}", label.key(), label.value()))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            )
+        };
+        lines.push(format!("{}{}: {}", key.name(), labels, metric));
+    }
+
+    lines.sort();
+
+    for line in lines {
+        tracing::info!(target: TARGET_NAME, "{}", line);
     }
 }
 
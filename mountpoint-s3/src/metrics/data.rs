@@ -43,38 +43,67 @@ impl Metric {
         metrics::Histogram::from_arc(inner.clone())
     }
 
-    /// Generate a string representation of this metric, or None if the metric has had no values
-    /// emitted since the last call to this function.
-    pub fn fmt_and_reset(&self) -> Option<String> {
+    /// Take a snapshot of this metric's value, or None if the metric has had no values emitted
+    /// since the last call to this function.
+    pub fn take_value(&self) -> Option<MetricValue> {
         match self {
-            Metric::Counter(inner) => {
-                let (sum, n) = inner.load_and_reset()?;
-                if n == 1 {
-                    Some(format!("{}", sum))
-                } else {
-                    Some(format!("{} (n={})", sum, n))
-                }
-            }
+            Metric::Counter(inner) => inner.load_and_reset().map(|(sum, n)| MetricValue::Counter { sum, n }),
             // Gauges can't reset because they can be incremented/decremented
-            Metric::Gauge(inner) => inner.load_if_changed().map(|value| format!("{}", value)),
+            Metric::Gauge(inner) => inner.load_if_changed().map(MetricValue::Gauge),
             Metric::Histogram(histogram) => histogram.run_and_reset(|histogram| {
-                format!(
-                    "n={}: min={} p10={} p50={} avg={:.2} p90={} p99={} p99.9={} max={}",
-                    histogram.len(),
-                    histogram.min(),
-                    histogram.value_at_quantile(0.1),
-                    histogram.value_at_quantile(0.5),
-                    histogram.mean(),
-                    histogram.value_at_quantile(0.9),
-                    histogram.value_at_quantile(0.99),
-                    histogram.value_at_quantile(0.999),
-                    histogram.max(),
-                )
+                MetricValue::Histogram(HistogramSummary {
+                    n: histogram.len(),
+                    min: histogram.min(),
+                    p10: histogram.value_at_quantile(0.1),
+                    p50: histogram.value_at_quantile(0.5),
+                    avg: histogram.mean(),
+                    p90: histogram.value_at_quantile(0.9),
+                    p99: histogram.value_at_quantile(0.99),
+                    p999: histogram.value_at_quantile(0.999),
+                    max: histogram.max(),
+                })
             }),
         }
     }
 }
 
+/// A snapshot of the values a metric received during one aggregation period
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetricValue {
+    Counter { sum: u64, n: usize },
+    Gauge(f64),
+    Histogram(HistogramSummary),
+}
+
+/// Summary statistics of the values recorded into a histogram
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSummary {
+    pub n: u64,
+    pub min: u64,
+    pub p10: u64,
+    pub p50: u64,
+    pub avg: f64,
+    pub p90: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub max: u64,
+}
+
+impl std::fmt::Display for MetricValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetricValue::Counter { sum, n: 1 } => write!(f, "{}", sum),
+            MetricValue::Counter { sum, n } => write!(f, "{} (n={})", sum, n),
+            MetricValue::Gauge(value) => write!(f, "{}", value),
+            MetricValue::Histogram(h) => write!(
+                f,
+                "n={}: min={} p10={} p50={} avg={:.2} p90={} p99={} p99.9={} max={}",
+                h.n, h.min, h.p10, h.p50, h.avg, h.p90, h.p99, h.p999, h.max,
+            ),
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct ValueAndCount {
     pub sum: AtomicU64,
@@ -0,0 +1,169 @@
+//! Output of metrics in CloudWatch Embedded Metric Format (EMF).
+//!
+//! [EMF](https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/CloudWatch_Embedded_Metric_Format_Specification.html)
+//! is a structured log format that CloudWatch Logs extracts metrics from, so metrics written in it
+//! show up in CloudWatch as soon as the log is shipped there, without running a separate metrics
+//! agent. Every metric in an EMF document shares the document's dimensions, so we write one
+//! document per distinct set of metric labels, with the labels as extra dimensions.
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use metrics::Key;
+use serde_json::{json, Map, Value};
+
+use super::data::MetricValue;
+
+/// The CloudWatch namespace metrics are published to
+pub const EMF_NAMESPACE: &str = "Mountpoint";
+
+/// EMF allows at most this many metrics in a single document
+const MAX_METRICS_PER_DOCUMENT: usize = 100;
+
+/// Formats metrics as EMF documents with a fixed set of dimensions, like the bucket name.
+#[derive(Debug, Clone)]
+pub struct EmfFormatter {
+    dimensions: Vec<(String, String)>,
+}
+
+impl EmfFormatter {
+    pub fn new(dimensions: Vec<(String, String)>) -> Self {
+        Self { dimensions }
+    }
+
+    /// Format metrics collected at `timestamp` as EMF documents, each serialized on a single line.
+    /// Counters are reported as their sum and gauges as their latest value. CloudWatch can't
+    /// aggregate our histograms, so each is reported as several metrics for its summary statistics,
+    /// like `fuse.op_latency_us.p99`.
+    pub fn format(&self, metrics: &[(Key, MetricValue)], timestamp: SystemTime) -> Vec<String> {
+        let timestamp_ms = timestamp.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+
+        let mut groups: BTreeMap<Vec<(String, String)>, Vec<(String, f64, Option<&str>)>> = BTreeMap::new();
+        for (key, value) in metrics {
+            let labels = key
+                .labels()
+                .map(|label| (label.key().to_owned(), label.value().to_owned()))
+                .collect();
+            let group = groups.entry(labels).or_default();
+            let name = key.name();
+            match value {
+                MetricValue::Counter { sum, .. } => group.push((name.to_owned(), *sum as f64, Some("Count"))),
+                MetricValue::Gauge(value) => group.push((name.to_owned(), *value, None)),
+                MetricValue::Histogram(h) => {
+                    group.push((format!("{name}.count"), h.n as f64, Some("Count")));
+                    group.push((format!("{name}.avg"), h.avg, None));
+                    group.push((format!("{name}.p50"), h.p50 as f64, None));
+                    group.push((format!("{name}.p90"), h.p90 as f64, None));
+                    group.push((format!("{name}.p99"), h.p99 as f64, None));
+                    group.push((format!("{name}.max"), h.max as f64, None));
+                }
+            }
+        }
+
+        let mut documents = Vec::new();
+        for (labels, mut values) in groups {
+            values.sort_by(|a, b| a.0.cmp(&b.0));
+            let dimensions: Vec<_> = self.dimensions.iter().chain(&labels).collect();
+            let dimension_names: Vec<_> = dimensions.iter().map(|(name, _)| name.as_str()).collect();
+            for chunk in values.chunks(MAX_METRICS_PER_DOCUMENT) {
+                let mut document = Map::new();
+                for (name, value) in &dimensions {
+                    document.insert(name.clone(), Value::String(value.clone()));
+                }
+                let mut definitions = Vec::with_capacity(chunk.len());
+                for (name, value, unit) in chunk {
+                    document.insert(name.clone(), json!(value));
+                    definitions.push(match unit {
+                        Some(unit) => json!({ "Name": name, "Unit": unit }),
+                        None => json!({ "Name": name }),
+                    });
+                }
+                document.insert(
+                    "_aws".to_owned(),
+                    json!({
+                        "Timestamp": timestamp_ms,
+                        "CloudWatchMetrics": [{
+                            "Namespace": EMF_NAMESPACE,
+                            "Dimensions": [dimension_names],
+                            "Metrics": definitions,
+                        }],
+                    }),
+                );
+                documents.push(Value::Object(document).to_string());
+            }
+        }
+        documents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use metrics::Label;
+
+    use super::super::data::HistogramSummary;
+    use super::*;
+
+    #[test]
+    fn test_format_emf() {
+        let formatter = EmfFormatter::new(vec![
+            ("Bucket".to_owned(), "DOC-EXAMPLE-BUCKET".to_owned()),
+            ("MountId".to_owned(), "/mnt/bucket".to_owned()),
+        ]);
+        let metrics = vec![
+            (
+                Key::from_parts("fuse.op_latency_us", vec![Label::new("op", "read")]),
+                MetricValue::Histogram(HistogramSummary {
+                    n: 10,
+                    min: 1,
+                    p10: 1,
+                    p50: 5,
+                    avg: 5.5,
+                    p90: 9,
+                    p99: 10,
+                    p999: 10,
+                    max: 10,
+                }),
+            ),
+            (
+                Key::from_parts("fuse.op_failures", vec![Label::new("op", "read")]),
+                MetricValue::Counter { sum: 3, n: 2 },
+            ),
+            (Key::from_name("process.memory_usage"), MetricValue::Gauge(1024.0)),
+        ];
+        let timestamp = UNIX_EPOCH + Duration::from_millis(1_700_000_000_000);
+        let documents = formatter.format(&metrics, timestamp);
+        let documents: Vec<Value> = documents
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(documents.len(), 2);
+
+        // Metrics without labels only have the mount's dimensions
+        let unlabeled = &documents[0];
+        assert_eq!(unlabeled["Bucket"], "DOC-EXAMPLE-BUCKET");
+        assert_eq!(unlabeled["process.memory_usage"], 1024.0);
+        let metadata = &unlabeled["_aws"];
+        assert_eq!(metadata["Timestamp"], 1_700_000_000_000u64);
+        assert_eq!(metadata["CloudWatchMetrics"][0]["Namespace"], EMF_NAMESPACE);
+        assert_eq!(
+            metadata["CloudWatchMetrics"][0]["Dimensions"],
+            json!([["Bucket", "MountId"]])
+        );
+
+        // Labels become extra dimensions
+        let labeled = &documents[1];
+        assert_eq!(labeled["op"], "read");
+        assert_eq!(labeled["fuse.op_failures"], 3.0);
+        assert_eq!(labeled["fuse.op_latency_us.p99"], 10.0);
+        assert_eq!(labeled["fuse.op_latency_us.avg"], 5.5);
+        let metadata = &labeled["_aws"]["CloudWatchMetrics"][0];
+        assert_eq!(metadata["Dimensions"], json!([["Bucket", "MountId", "op"]]));
+        assert_eq!(metadata["Metrics"].as_array().unwrap().len(), 7);
+        assert_eq!(
+            metadata["Metrics"][0],
+            json!({ "Name": "fuse.op_failures", "Unit": "Count" })
+        );
+    }
+}
@@ -60,3 +60,40 @@ where
 /// The time at which a request started
 #[derive(Debug, Clone, Copy)]
 struct RequestTime(Instant);
+
+#[cfg(test)]
+mod tests {
+    use metrics::with_local_recorder;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::metrics::{MetricValue, MetricsRecorder, MetricsSink};
+    use crate::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn records_latency_of_fuse_operations() {
+        let sink = Arc::new(MetricsSink::new());
+        let recorder = MetricsRecorder { sink: sink.clone() };
+        let subscriber = tracing_subscriber::registry().with(metrics_tracing_span_layer());
+        with_local_recorder(&recorder, || {
+            tracing::subscriber::with_default(subscriber, || {
+                let lookup = tracing::warn_span!(target: FUSE_MODULE_NAME, "lookup");
+                lookup.in_scope(|| {
+                    // Spans inside an operation aren't operations themselves
+                    let _child = tracing::warn_span!(target: FUSE_MODULE_NAME, "child").entered();
+                });
+                drop(lookup);
+                // Neither are spans outside the FUSE module
+                let _other = tracing::warn_span!(target: "mountpoint_s3::fs", "read");
+            });
+        });
+
+        let values = sink.take_values();
+        assert_eq!(values.len(), 1);
+        let (key, value) = &values[0];
+        assert_eq!(key.name(), "fuse.op_latency_us");
+        assert_eq!(key.labels().next().unwrap().value(), "lookup");
+        assert!(matches!(value, MetricValue::Histogram(histogram) if histogram.n == 1));
+    }
+}
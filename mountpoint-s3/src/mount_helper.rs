@@ -0,0 +1,145 @@
+//! Support for running as a `mount` helper, so that buckets can be mounted from `/etc/fstab` and
+//! systemd `.mount` units.
+//!
+//! `mount -t mount-s3 <bucket> <directory> -o <options>` runs `/sbin/mount.mount-s3` with the
+//! arguments `<bucket> <directory> [-sfnv] [-o <options>] [-t <type>]`, and FUSE's `mount.fuse`
+//! helper runs `mount-s3 <bucket> <directory> -o <options>` for `fuse.mount-s3` mounts. In both
+//! cases the options are a single comma-separated list, like `ro,allow-other,region=us-east-1`,
+//! that mixes our own options with generic mount options like `nofail`. We rewrite that list into
+//! the equivalent command-line arguments before parsing them with clap.
+
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+
+use anyhow::anyhow;
+
+/// Generic mount options that are interpreted by `mount` or systemd rather than by us
+const IGNORED_OPTIONS: &[&str] = &[
+    "defaults", "auto", "noauto", "user", "nouser", "users", "owner", "group", "_netdev", "nofail", "rw", "dev",
+    "nodev", "suid", "nosuid", "exec", "noexec", "async", "atime", "noatime", "relatime",
+];
+
+/// Prefix of the name the binary is installed as to run as a `mount` helper
+const MOUNT_HELPER_PREFIX: &str = "mount.";
+
+/// Rewrite the process arguments (including the program name) so that every `-o <options>` list
+/// becomes the equivalent command-line options. When the program was invoked as a `mount` helper,
+/// also drop the flags that `mount` passes to every helper.
+pub fn rewrite_mount_options(args: impl IntoIterator<Item = OsString>) -> anyhow::Result<Vec<OsString>> {
+    let mut args = args.into_iter();
+    let Some(program) = args.next() else {
+        return Ok(Vec::new());
+    };
+    let is_mount_helper = Path::new(&program)
+        .file_name()
+        .and_then(OsStr::to_str)
+        .is_some_and(|name| name.starts_with(MOUNT_HELPER_PREFIX));
+
+    let mut rewritten = vec![program];
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            rewritten.push(arg);
+            rewritten.extend(args);
+            break;
+        }
+        let Some(arg_str) = arg.to_str() else {
+            rewritten.push(arg);
+            continue;
+        };
+        if arg_str == "-o" {
+            let options = args
+                .next()
+                .ok_or_else(|| anyhow!("-o requires a list of mount options"))?;
+            let options = options
+                .to_str()
+                .ok_or_else(|| anyhow!("mount options must be valid UTF-8: {options:?}"))?;
+            rewritten.extend(options_to_args(options));
+        } else if let Some(options) = arg_str.strip_prefix("-o") {
+            rewritten.extend(options_to_args(options));
+        } else if is_mount_helper && matches!(arg_str, "-s" | "-f" | "-n" | "-v") {
+            // Flags `mount` forwards to its helpers (sloppy, fake, no mtab, verbose) that don't apply to us
+        } else if is_mount_helper && arg_str == "-t" {
+            args.next();
+        } else {
+            rewritten.push(arg);
+        }
+    }
+    Ok(rewritten)
+}
+
+/// Convert a comma-separated list of mount options into command-line options. `key=value` becomes
+/// `--key value`, and a bare `key` becomes the flag `--key`.
+fn options_to_args(options: &str) -> Vec<OsString> {
+    let mut args = Vec::new();
+    for option in options.split(',') {
+        let (key, value) = match option.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (option, None),
+        };
+        if key.is_empty() || IGNORED_OPTIONS.contains(&key) || key.starts_with("x-") || key == "comment" {
+            continue;
+        }
+        let key = match key {
+            "ro" => "read-only".to_owned(),
+            key => key.replace('_', "-"),
+        };
+        args.push(format!("--{key}").into());
+        if let Some(value) = value {
+            args.push(value.into());
+        }
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(args: &[&str]) -> Vec<String> {
+        rewrite_mount_options(args.iter().map(OsString::from))
+            .unwrap()
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_mount_helper_args() {
+        let args = rewrite(&[
+            "/sbin/mount.mount-s3",
+            "bucket",
+            "/mnt",
+            "-n",
+            "-o",
+            "rw,_netdev,nofail,x-systemd.automount,allow_delete,region=us-east-1,cache=/tmp/cache",
+            "-t",
+            "mount-s3",
+        ]);
+        assert_eq!(
+            args,
+            [
+                "/sbin/mount.mount-s3",
+                "bucket",
+                "/mnt",
+                "--allow-delete",
+                "--region",
+                "us-east-1",
+                "--cache",
+                "/tmp/cache"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mount_fuse_args() {
+        // Only mount helpers get `mount`'s flags, so `-f` still means foreground here
+        let args = rewrite(&["mount-s3", "bucket", "/mnt", "-f", "-oro,prefix=a=b/"]);
+        assert_eq!(
+            args,
+            ["mount-s3", "bucket", "/mnt", "-f", "--read-only", "--prefix", "a=b/"]
+        );
+
+        let args = rewrite(&["mount-s3", "bucket", "/mnt", "--", "-o", "ro"]);
+        assert_eq!(args, ["mount-s3", "bucket", "/mnt", "--", "-o", "ro"]);
+    }
+}
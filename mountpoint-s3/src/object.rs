@@ -3,7 +3,8 @@ use mountpoint_s3_client::types::ETag;
 use crate::sync::Arc;
 
 /// Identifier for a specific version of an S3 object.
-/// Formed by the object key and etag. Holds its components in an [Arc], so it can be cheaply cloned.
+/// Formed by the object key and etag, and the version ID when reads should be pinned to a specific
+/// version of the object. Holds its components in an [Arc], so it can be cheaply cloned.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct ObjectId {
     inner: Arc<InnerObjectId>,
@@ -13,12 +14,17 @@ pub struct ObjectId {
 struct InnerObjectId {
     key: String,
     etag: ETag,
+    version_id: Option<String>,
 }
 
 impl ObjectId {
     pub fn new(key: String, etag: ETag) -> Self {
+        Self::with_version_id(key, etag, None)
+    }
+
+    pub fn with_version_id(key: String, etag: ETag, version_id: Option<String>) -> Self {
         Self {
-            inner: Arc::new(InnerObjectId { key, etag }),
+            inner: Arc::new(InnerObjectId { key, etag, version_id }),
         }
     }
 
@@ -29,4 +35,8 @@ impl ObjectId {
     pub fn etag(&self) -> &ETag {
         &self.inner.etag
     }
+
+    pub fn version_id(&self) -> Option<&str> {
+        self.inner.version_id.as_deref()
+    }
 }
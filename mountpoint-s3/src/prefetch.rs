@@ -4,13 +4,24 @@
 //! large enough that they can make effective use of the CRT's fan-out parallelism across the S3
 //! frontend, but small enough that we don't accumulate a lot of unread object data in memory or
 //! wastefully download data we'll never read. As the reader continues to make sequential reads,
-//! we increase the size of the GetObject requests up to some maximum. If the reader ever makes a
-//! non-sequential read, we abandon the prefetching and start again with the minimum request size.
+//! we increase the size of the GetObject requests up to some maximum, unless the reader is
+//! consuming data more slowly than S3 delivers it (see [readahead]). If the reader ever makes a
+//! non-sequential read, we abandon the prefetching and start again with a small request.
+//!
+//! Some readers never read sequentially for long, like a Parquet reader that fetches a file's
+//! footer and then jumps between column chunks. Ramping up for each of their reads wastes
+//! bandwidth, so after a few short runs of sequential reads, a stream switches to random read
+//! mode. In this mode each read is served by a request for just the range it needs, aligned to
+//! [PrefetcherConfig::random_read_alignment], and nothing is prefetched ahead of the reader. If
+//! the reader goes back to reading sequentially for long enough, the stream goes back to
+//! prefetching.
 
 mod caching_stream;
+mod mem_limiter;
 mod part;
 mod part_queue;
 mod part_stream;
+mod readahead;
 mod seek_window;
 mod task;
 
@@ -22,7 +33,6 @@ use async_trait::async_trait;
 use futures::task::Spawn;
 use metrics::{counter, histogram};
 use mountpoint_s3_client::error::{GetObjectError, ObjectClientError};
-use mountpoint_s3_client::types::ETag;
 use mountpoint_s3_client::ObjectClient;
 use thiserror::Error;
 use tracing::trace;
@@ -31,11 +41,24 @@ use crate::checksums::{ChecksummedBytes, IntegrityError};
 use crate::data_cache::DataCache;
 use crate::object::ObjectId;
 use crate::prefetch::caching_stream::CachingPartStream;
+use crate::prefetch::mem_limiter::MemoryLimiter;
 use crate::prefetch::part_stream::{ClientPartStream, ObjectPartStream, RequestRange};
+use crate::prefetch::readahead::ReadaheadController;
 use crate::prefetch::seek_window::SeekWindow;
 use crate::prefetch::task::RequestTask;
 use crate::sync::Arc;
 
+/// Reads at least this large that miss the prefetch window are split into concurrent requests, see
+/// [PrefetcherConfig::read_part_concurrency]
+const CONCURRENT_READ_THRESHOLD: usize = 1024 * 1024;
+
+/// Smallest request a read is split into, so that small reads don't turn into many tiny requests
+const MIN_CONCURRENT_REQUEST_SIZE: usize = 256 * 1024;
+
+/// Number of consecutive short runs of sequential reads after which a stream switches to random
+/// read mode. A run is short if it ends before reading [PrefetcherConfig::first_request_size] bytes.
+const RANDOM_READ_THRESHOLD: usize = 2;
+
 /// Generic interface to handle reading data from an object.
 pub trait Prefetch {
     type PrefetchResult<Client: ObjectClient + Send + Sync + 'static>: PrefetchResult<Client>;
@@ -45,9 +68,8 @@ pub trait Prefetch {
         &self,
         client: Arc<Client>,
         bucket: &str,
-        key: &str,
+        object_id: ObjectId,
         size: u64,
-        etag: ETag,
     ) -> Self::PrefetchResult<Client>
     where
         Client: ObjectClient + Send + Sync + 'static;
@@ -64,6 +86,15 @@ pub trait PrefetchResult<Client: ObjectClient>: Send + Sync {
         offset: u64,
         length: usize,
     ) -> Result<ChecksummedBytes, PrefetchReadError<Client::ClientError>>;
+
+    /// Read some bytes from the object, like [PrefetchResult::read], but return them in the
+    /// buffers they were downloaded into rather than copying them into a single buffer when the
+    /// read spans more than one. The buffers are in order and their checksums aren't validated.
+    async fn read_vectored(
+        &mut self,
+        offset: u64,
+        length: usize,
+    ) -> Result<Vec<ChecksummedBytes>, PrefetchReadError<Client::ClientError>>;
 }
 
 #[derive(Debug, Error)]
@@ -88,7 +119,7 @@ pub fn default_prefetch<Runtime>(runtime: Runtime, prefetcher_config: Prefetcher
 where
     Runtime: Spawn + Send + Sync + 'static,
 {
-    let part_stream = ClientPartStream::new(runtime);
+    let part_stream = ClientPartStream::new(runtime).verify_object_checksums(prefetcher_config.verify_object_checksums);
     Prefetcher::new(part_stream, prefetcher_config)
 }
 
@@ -112,6 +143,9 @@ where
 pub struct PrefetcherConfig {
     /// Size of the first request in a prefetch run
     pub first_request_size: usize,
+    /// Minimum size of a single prefetch request. The first request of a run shrinks towards this
+    /// size when the reader keeps making short runs of sequential reads.
+    pub min_request_size: usize,
     /// Maximum size of a single prefetch request
     pub max_request_size: usize,
     /// Factor to increase the request size by whenever the reader continues making sequential reads
@@ -124,6 +158,19 @@ pub struct PrefetcherConfig {
     /// The maximum distance the prefetcher will seek backwards before resetting and starting a new
     /// S3 request. We keep this much data in memory in addition to any inflight requests.
     pub max_backward_seek_distance: u64,
+    /// Target for the total size of inflight requests across all prefetch streams, or [None] for no
+    /// limit. Once it's reached, streams stop prefetching ahead of their readers.
+    pub max_memory_target: Option<u64>,
+    /// Alignment of the requests made in random read mode, which are rounded out to multiples of
+    /// this size
+    pub random_read_alignment: usize,
+    /// Maximum number of concurrent requests to split a large read into when it misses the prefetch
+    /// window, rather than waiting for a single request to return all its data
+    pub read_part_concurrency: usize,
+    /// Whether to verify the data of objects against the checksums S3 stores for them and their
+    /// parts, fetching it again if it doesn't match. Verifying parts takes extra GetObjectAttributes
+    /// requests, so this is off by default.
+    pub verify_object_checksums: bool,
 }
 
 impl Default for PrefetcherConfig {
@@ -139,6 +186,8 @@ impl Default for PrefetcherConfig {
             // waiting for the readahead hurts random IO. So we add 128k to the first request size
             // to avoid the latency hit of the second request.
             first_request_size: 1 * 1024 * 1024 + 128 * 1024,
+            // Linux's readahead size, the smallest read we expect to see from the kernel
+            min_request_size: 128 * 1024,
             max_request_size: 2 * 1024 * 1024 * 1024,
             sequential_prefetch_multiplier: 8,
             read_timeout: Duration::from_secs(60),
@@ -148,6 +197,10 @@ impl Default for PrefetcherConfig {
             // just start a new request instead.
             max_forward_seek_wait_distance: 16 * 1024 * 1024,
             max_backward_seek_distance: 1 * 1024 * 1024,
+            max_memory_target: None,
+            random_read_alignment: 64 * 1024,
+            read_part_concurrency: 4,
+            verify_object_checksums: false,
         }
     }
 }
@@ -157,6 +210,7 @@ impl Default for PrefetcherConfig {
 pub struct Prefetcher<Stream> {
     part_stream: Arc<Stream>,
     config: PrefetcherConfig,
+    mem_limiter: Arc<MemoryLimiter>,
 }
 
 impl<Stream> Prefetcher<Stream>
@@ -166,7 +220,12 @@ where
     /// Create a new [Prefetcher] from the given [ObjectPartStream] instance.
     pub fn new(part_stream: Stream, config: PrefetcherConfig) -> Self {
         let part_stream = Arc::new(part_stream);
-        Self { part_stream, config }
+        let mem_limiter = Arc::new(MemoryLimiter::new(config.max_memory_target.unwrap_or(u64::MAX)));
+        Self {
+            part_stream,
+            config,
+            mem_limiter,
+        }
     }
 }
 
@@ -180,9 +239,8 @@ where
         &self,
         client: Arc<Client>,
         bucket: &str,
-        key: &str,
+        object_id: ObjectId,
         size: u64,
-        etag: ETag,
     ) -> Self::PrefetchResult<Client>
     where
         Client: ObjectClient + Send + Sync + 'static,
@@ -191,10 +249,10 @@ where
             client.clone(),
             self.part_stream.clone(),
             self.config,
+            self.mem_limiter.clone(),
             bucket,
-            key,
+            object_id,
             size,
-            etag,
         )
     }
 }
@@ -206,6 +264,7 @@ pub struct PrefetchGetObject<Stream: ObjectPartStream, Client: ObjectClient> {
     client: Arc<Client>,
     part_stream: Arc<Stream>,
     config: PrefetcherConfig,
+    mem_limiter: Arc<MemoryLimiter>,
     // Invariant: the offset of the first byte in this task's part queue is always
     // self.next_sequential_read_offset.
     current_task: Option<RequestTask<Client::ClientError>>,
@@ -221,6 +280,11 @@ pub struct PrefetchGetObject<Stream: ObjectPartStream, Client: ObjectClient> {
     /// Start offset for sequential read, used for calculating contiguous read metric
     sequential_read_start_offset: u64,
     next_sequential_read_offset: u64,
+    /// Number of consecutive short runs of sequential reads, see [RANDOM_READ_THRESHOLD]
+    short_runs: usize,
+    /// Whether reads are served by requests for just the data they need, without prefetching
+    random_mode: bool,
+    readahead: ReadaheadController,
     next_request_size: usize,
     next_request_offset: u64,
     size: u64,
@@ -240,6 +304,29 @@ where
         offset: u64,
         length: usize,
     ) -> Result<ChecksummedBytes, PrefetchReadError<Client::ClientError>> {
+        // A single buffer is returned as is, so this only copies if the read spans several. That
+        // should be uncommon as long as part size is larger than read size, which it almost always
+        // is for real S3 clients and FUSE.
+        let mut response = ChecksummedBytes::default();
+        for part_bytes in self.read_vectored(offset, length).await? {
+            match response.extend(part_bytes) {
+                Ok(()) => {}
+                Err(e @ IntegrityError::ChecksumMismatch(_, _)) => {
+                    // cancel inflight tasks
+                    self.current_task = None;
+                    self.future_tasks.drain(..);
+                    return Err(e.into());
+                }
+            }
+        }
+        Ok(response)
+    }
+
+    async fn read_vectored(
+        &mut self,
+        offset: u64,
+        length: usize,
+    ) -> Result<Vec<ChecksummedBytes>, PrefetchReadError<Client::ClientError>> {
         trace!(
             offset,
             length,
@@ -259,7 +346,7 @@ where
 
         let remaining = self.size.saturating_sub(offset);
         if remaining == 0 {
-            return Ok(ChecksummedBytes::default());
+            return Ok(Vec::new());
         }
         let mut to_read = (length as u64).min(remaining);
 
@@ -278,15 +365,28 @@ where
 
                 // This is an approximation, tolerating some seeking caused by concurrent readahead.
                 self.record_contiguous_read_metric();
-
-                self.reset_prefetch_to_offset(offset);
+                let run_length = self.next_sequential_read_offset - self.sequential_read_start_offset;
+                self.readahead.record_out_of_order(run_length);
+                self.record_run_for_read_mode(run_length);
+
+                if self.random_mode {
+                    if let Err(e) = self.reset_prefetch_for_random_read(offset, length).await {
+                        self.reset_prefetch_to_offset(offset);
+                        return Err(e);
+                    }
+                } else {
+                    self.reset_prefetch_to_offset(offset);
+                    if length >= CONCURRENT_READ_THRESHOLD {
+                        self.spawn_concurrent_requests(length.max(self.next_request_size));
+                    }
+                }
             }
         }
         assert_eq!(self.next_sequential_read_offset, offset);
 
         self.prepare_requests();
 
-        let mut response = ChecksummedBytes::default();
+        let mut response = Vec::new();
         while to_read > 0 {
             let Some(current_task) = self.current_task.as_mut() else {
                 // If [prepare_requests] didn't spawn a request, we've reached the end of the object.
@@ -302,33 +402,22 @@ where
                 }
                 Ok(part) => part,
             };
+            if let Some(sample) = current_task.sample() {
+                self.readahead.record_request(sample);
+            }
             self.backward_seek_window.push(part.clone());
             let part_bytes = part
                 .into_bytes(&self.object_id, self.next_sequential_read_offset)
                 .unwrap();
 
             self.next_sequential_read_offset += part_bytes.len() as u64;
-            self.prepare_requests();
-
-            // If we can complete the read with just a single buffer, early return to avoid copying
-            // into a new buffer. This should be the common case as long as part size is larger than
-            // read size, which it almost always is for real S3 clients and FUSE.
-            if response.is_empty() && part_bytes.len() == to_read as usize {
-                return Ok(part_bytes);
+            // In random read mode, only request more data if this read needs it
+            if !self.random_mode || part_bytes.len() < to_read as usize {
+                self.prepare_requests();
             }
 
-            let part_len = part_bytes.len() as u64;
-            let result = response.extend(part_bytes);
-            match result {
-                Ok(()) => {}
-                Err(e @ IntegrityError::ChecksumMismatch(_, _)) => {
-                    // cancel inflight tasks
-                    self.current_task = None;
-                    self.future_tasks.drain(..);
-                    return Err(e.into());
-                }
-            }
-            to_read -= part_len;
+            to_read -= part_bytes.len() as u64;
+            response.push(part_bytes);
         }
 
         Ok(response)
@@ -345,25 +434,30 @@ where
         client: Arc<Client>,
         part_stream: Arc<Stream>,
         config: PrefetcherConfig,
+        mem_limiter: Arc<MemoryLimiter>,
         bucket: &str,
-        key: &str,
+        object_id: ObjectId,
         size: u64,
-        etag: ETag,
     ) -> Self {
+        let readahead = ReadaheadController::new(&config);
         PrefetchGetObject {
             client,
             part_stream,
             config,
+            mem_limiter,
             current_task: None,
             future_tasks: Default::default(),
             backward_seek_window: SeekWindow::new(config.max_backward_seek_distance as usize),
             preferred_part_size: 128 * 1024,
             sequential_read_start_offset: 0,
             next_sequential_read_offset: 0,
-            next_request_size: config.first_request_size,
+            short_runs: 0,
+            random_mode: false,
+            next_request_size: readahead.first_request_size(),
+            readahead,
             next_request_offset: 0,
             bucket: bucket.to_owned(),
-            object_id: ObjectId::new(key.to_owned(), etag),
+            object_id,
             size,
         }
     }
@@ -377,7 +471,7 @@ where
                 self.current_task = Some(next_task);
                 return;
             }
-            self.current_task = self.spawn_next_request();
+            self.current_task = self.spawn_next_request(true);
         } else if current_task
             .map(|task| {
                 // Don't trigger prefetch if we're in a fake task created by backward streaming
@@ -385,48 +479,159 @@ where
             })
             .unwrap_or(false)
             && self.future_tasks.is_empty()
+            && !self.random_mode
         {
             // The current task is nearing completion, so pre-spawn the next request in anticipation
             // of it completing.
-            if let Some(task) = self.spawn_next_request() {
+            if let Some(task) = self.spawn_next_request(false) {
                 self.future_tasks.push_back(task);
             }
         }
     }
 
-    /// Spawn the next required request
-    fn spawn_next_request(&mut self) -> Option<RequestTask<Client::ClientError>> {
+    /// Spawn the next required request. If the reader isn't `waiting` for it yet, the request is
+    /// only made if it fits in the memory limit.
+    fn spawn_next_request(&mut self, waiting: bool) -> Option<RequestTask<Client::ClientError>> {
         let start = self.next_request_offset;
         if start >= self.size {
             return None;
         }
 
-        let range = RequestRange::new(self.size as usize, start, self.next_request_size);
-        let task = self.part_stream.spawn_get_object_request(
+        let mut range = RequestRange::new(self.size as usize, start, self.next_request_size);
+        let available_mem = self.mem_limiter.available_mem();
+        if available_mem < range.len() as u64 {
+            if !waiting {
+                trace!(available_mem, "memory limit reached, not prefetching");
+                counter!("prefetch.mem_limited").increment(1);
+                return None;
+            }
+            // The reader needs this data now, so make the request anyway, but no larger than what's
+            // left of the budget
+            let size = (available_mem as usize).max(self.config.min_request_size);
+            range = RequestRange::new(self.size as usize, start, size.min(range.len()));
+        }
+
+        let task = self.spawn_request(range);
+
+        // [read] will reset these if the reader stops making sequential requests
+        self.next_request_offset += task.total_size() as u64;
+        if self.random_mode
+            && self.next_request_offset - self.sequential_read_start_offset >= self.config.first_request_size as u64
+        {
+            trace!("reader is sequential again, leaving random read mode");
+            self.random_mode = false;
+            self.short_runs = 0;
+        }
+        self.next_request_size = if self.random_mode {
+            // Keep making requests the size of the reader's reads until we leave random read mode
+            task.total_size().max(self.config.random_read_alignment)
+        } else {
+            self.get_next_request_size(task.total_size())
+        };
+
+        Some(task)
+    }
+
+    /// Spawn requests for the next `length` bytes all at once, splitting them into up to
+    /// [PrefetcherConfig::read_part_concurrency] requests that S3 can serve concurrently. The first
+    /// request becomes the current task and the rest are queued behind it. This is for a read that
+    /// missed the prefetch window, so there must be no requests in flight.
+    fn spawn_concurrent_requests(&mut self, length: usize) {
+        debug_assert!(self.current_task.is_none() && self.future_tasks.is_empty());
+        let start = self.next_request_offset;
+        let total = RequestRange::new(self.size as usize, start, length).len();
+        let concurrency = self.config.read_part_concurrency.max(1);
+        let request_size = total.div_ceil(concurrency).max(MIN_CONCURRENT_REQUEST_SIZE);
+
+        let end = start + total as u64;
+        let mut tasks = VecDeque::new();
+        while self.next_request_offset < end {
+            let size = request_size.min((end - self.next_request_offset) as usize);
+            let range = RequestRange::new(self.size as usize, self.next_request_offset, size);
+            // The part stream can trim the range to part boundaries, so carry on from where this
+            // request actually ends
+            let task = self.spawn_request(range);
+            self.next_request_offset += task.total_size() as u64;
+            tasks.push_back(task);
+        }
+        trace!(
+            start,
+            total,
+            requests = tasks.len(),
+            "split read into concurrent requests"
+        );
+
+        self.next_request_size = if self.random_mode {
+            request_size.max(self.config.random_read_alignment)
+        } else {
+            self.get_next_request_size(total)
+        };
+        self.current_task = tasks.pop_front();
+        self.future_tasks = tasks;
+    }
+
+    /// Spawn a request for the given range, reserving memory for it
+    fn spawn_request(&mut self, range: RequestRange) -> RequestTask<Client::ClientError> {
+        let mut task = self.part_stream.spawn_get_object_request(
             &self.client,
             &self.bucket,
-            self.object_id.key(),
-            self.object_id.etag().clone(),
+            self.object_id.clone(),
             range,
             self.preferred_part_size,
         );
 
-        // [read] will reset these if the reader stops making sequential requests
-        self.next_request_offset += task.total_size() as u64;
-        self.next_request_size = self.get_next_request_size(task.total_size());
-
-        Some(task)
+        // Other streams may have reserved memory since we checked, so this can go over the limit
+        task.set_reservation(self.mem_limiter.reserve(task.total_size() as u64));
+        histogram!("prefetch.request_size").record(task.total_size() as f64);
+        task
     }
 
     /// Suggest next request size.
-    /// The next request size is the current request size multiplied by sequential prefetch multiplier.
+    /// The next request size is the current request size multiplied by sequential prefetch multiplier,
+    /// unless the [ReadaheadController] has seen that the reader can't keep up with that much data.
     fn get_next_request_size(&self, request_size: usize) -> usize {
-        // TODO: this logic doesn't work well right now in the case where part_size <
-        // first_request_size and sequential_prefetch_multiplier = 1. It ends up just repeatedly
-        // shrinking the request size until it reaches 1. But this isn't a configuration we
-        // currently expect to ever run in (part_size will always be >= 5MB for MPU reasons, and a
-        // prefetcher with multiplier 1 is not very good).
-        (request_size * self.config.sequential_prefetch_multiplier).min(self.config.max_request_size)
+        self.readahead.next_request_size(request_size)
+    }
+
+    /// Update the read mode after an out-of-order read ended a run of `run_length` sequential bytes.
+    /// Empty runs, like a seek straight after opening the file, don't count.
+    fn record_run_for_read_mode(&mut self, run_length: u64) {
+        if run_length == 0 {
+            return;
+        }
+        if run_length >= self.config.first_request_size as u64 {
+            self.short_runs = 0;
+            return;
+        }
+        self.short_runs += 1;
+        if self.short_runs >= RANDOM_READ_THRESHOLD && !self.random_mode {
+            trace!(short_runs = self.short_runs, "switching to random read mode");
+            counter!("prefetch.random_read_mode").increment(1);
+            self.random_mode = true;
+        }
+    }
+
+    /// Reset this prefetch request for a random read of `length` bytes at `offset`. Rather than
+    /// starting to prefetch, we request just the aligned range the read needs.
+    async fn reset_prefetch_for_random_read(
+        &mut self,
+        offset: u64,
+        length: usize,
+    ) -> Result<(), PrefetchReadError<Client::ClientError>> {
+        let alignment = self.config.random_read_alignment.max(1) as u64;
+        let start = offset - offset % alignment;
+        let end = (offset + length.max(1) as u64).div_ceil(alignment) * alignment;
+        self.reset_prefetch_to_offset(start);
+        self.next_request_size = (end - start) as usize;
+        if length >= CONCURRENT_READ_THRESHOLD {
+            self.spawn_concurrent_requests(self.next_request_size);
+        }
+
+        // Skip to the read's offset, keeping the data before it for backwards seeks
+        self.prepare_requests();
+        self.skip_forward_to(offset).await?;
+        self.sequential_read_start_offset = offset;
+        Ok(())
     }
 
     /// Reset this prefetch request to a new offset, clearing any existing tasks queued.
@@ -436,7 +641,7 @@ where
         self.backward_seek_window.clear();
         self.sequential_read_start_offset = offset;
         self.next_sequential_read_offset = offset;
-        self.next_request_size = self.config.first_request_size;
+        self.next_request_size = self.readahead.first_request_size();
         self.next_request_offset = offset;
     }
 
@@ -502,6 +707,17 @@ where
             );
             return Ok(false);
         }
+        self.skip_forward_to(offset).await?;
+        Ok(true)
+    }
+
+    /// Read and discard data from the current task up to `offset`, which the task must contain. The
+    /// data is kept in the backwards seek window.
+    async fn skip_forward_to(&mut self, offset: u64) -> Result<(), PrefetchReadError<Client::ClientError>> {
+        let current_task = self
+            .current_task
+            .as_mut()
+            .expect("a request existed that covered this seek offset");
         let mut seek_distance = offset - self.next_sequential_read_offset;
         while seek_distance > 0 {
             let part = current_task.read(seek_distance as usize).await?;
@@ -509,7 +725,7 @@ where
             self.next_sequential_read_offset += part.len() as u64;
             self.backward_seek_window.push(part);
         }
-        Ok(true)
+        Ok(())
     }
 
     fn try_seek_backward(&mut self, offset: u64) -> Result<bool, PrefetchReadError<Client::ClientError>> {
@@ -563,7 +779,11 @@ mod tests {
     use futures::executor::{block_on, ThreadPool};
     use mountpoint_s3_client::error::{GetObjectError, ObjectClientError};
     use mountpoint_s3_client::failure_client::{countdown_failure_client, RequestFailureMap};
-    use mountpoint_s3_client::mock_client::{ramp_bytes, MockClient, MockClientConfig, MockClientError, MockObject};
+    use mountpoint_s3_client::mock_client::{
+        ramp_bytes, MockClient, MockClientConfig, MockClientError, MockObject, Operation,
+    };
+    use mountpoint_s3_client::types::ETag;
+    use mountpoint_s3_crt::checksums::crc32c::Crc32c;
     use proptest::proptest;
     use proptest::strategy::{Just, Strategy};
     use proptest_derive::Arbitrary;
@@ -623,10 +843,11 @@ mod tests {
             read_timeout: Duration::from_secs(5),
             max_forward_seek_wait_distance: test_config.max_forward_seek_wait_distance,
             max_backward_seek_distance: test_config.max_backward_seek_distance,
+            ..Default::default()
         };
 
         let prefetcher = Prefetcher::new(part_stream, prefetcher_config);
-        let mut request = prefetcher.prefetch(client, "test-bucket", "hello", size, etag);
+        let mut request = prefetcher.prefetch(client, "test-bucket", ObjectId::new("hello".to_owned(), etag), size);
 
         let mut next_offset = 0;
         loop {
@@ -722,7 +943,12 @@ mod tests {
         };
 
         let prefetcher = Prefetcher::new(part_stream, prefetcher_config);
-        let mut request = prefetcher.prefetch(Arc::new(client), "test-bucket", "hello", size, etag);
+        let mut request = prefetcher.prefetch(
+            Arc::new(client),
+            "test-bucket",
+            ObjectId::new("hello".to_owned(), etag),
+            size,
+        );
 
         let mut next_offset = 0;
         loop {
@@ -849,7 +1075,12 @@ mod tests {
         };
 
         let prefetcher = Prefetcher::new(part_stream, prefetcher_config);
-        let mut request = prefetcher.prefetch(client, "test-bucket", "hello", object_size, etag);
+        let mut request = prefetcher.prefetch(
+            client,
+            "test-bucket",
+            ObjectId::new("hello".to_owned(), etag),
+            object_size,
+        );
 
         for (offset, length) in reads {
             assert!(offset < object_size);
@@ -995,8 +1226,12 @@ mod tests {
 
         // Try every possible seek from first_read_size
         for offset in first_read_size + 1..OBJECT_SIZE {
-            let mut request =
-                prefetcher.prefetch(client.clone(), "test-bucket", "hello", OBJECT_SIZE as u64, etag.clone());
+            let mut request = prefetcher.prefetch(
+                client.clone(),
+                "test-bucket",
+                ObjectId::new("hello".to_owned(), etag.clone()),
+                OBJECT_SIZE as u64,
+            );
             if first_read_size > 0 {
                 let _first_read = block_on(request.read(0, first_read_size)).unwrap();
             }
@@ -1033,8 +1268,12 @@ mod tests {
 
         // Try every possible seek from first_read_size
         for offset in 0..first_read_size {
-            let mut request =
-                prefetcher.prefetch(client.clone(), "test-bucket", "hello", OBJECT_SIZE as u64, etag.clone());
+            let mut request = prefetcher.prefetch(
+                client.clone(),
+                "test-bucket",
+                ObjectId::new("hello".to_owned(), etag.clone()),
+                OBJECT_SIZE as u64,
+            );
             if first_read_size > 0 {
                 let _first_read = block_on(request.read(0, first_read_size)).unwrap();
             }
@@ -1045,6 +1284,235 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_random_read_mode() {
+        const OBJECT_SIZE: usize = 16 * MB;
+        const ALIGNMENT: usize = 64 * 1024;
+
+        let config = MockClientConfig {
+            bucket: "test-bucket".to_string(),
+            part_size: 8 * MB,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(config));
+        let object = MockObject::ramp(0xaa, OBJECT_SIZE, ETag::for_tests());
+        let etag = object.etag();
+        client.add_object("hello", object);
+
+        let prefetcher_config = PrefetcherConfig {
+            first_request_size: 1 * MB,
+            random_read_alignment: ALIGNMENT,
+            ..Default::default()
+        };
+        let prefetcher = Prefetcher::new(default_stream(), prefetcher_config);
+        let mut request = prefetcher.prefetch(
+            client,
+            "test-bucket",
+            ObjectId::new("hello".to_owned(), etag),
+            OBJECT_SIZE as u64,
+        );
+        fn read<Client: ObjectClient + Send + Sync + 'static>(
+            request: &mut PrefetchGetObject<ClientPartStream<ThreadPool>, Client>,
+            offset: usize,
+            length: usize,
+        ) {
+            let buf = block_on(request.read(offset as u64, length)).unwrap();
+            let expected = ramp_bytes(0xaa + offset, length);
+            assert_eq!(buf.into_bytes().unwrap()[..], expected[..]);
+        }
+
+        // The first short runs reset the sequential prefetcher
+        read(&mut request, 1 * MB, 4096);
+        read(&mut request, 5 * MB, 4096);
+        assert!(!request.random_mode);
+
+        // After that, each read gets a request for just the aligned range it needs
+        read(&mut request, 9 * MB, 4096);
+        assert!(request.random_mode);
+        assert_eq!(request.current_task.as_ref().unwrap().total_size(), ALIGNMENT);
+        read(&mut request, 13 * MB + 100, 4096);
+        let current_task = request.current_task.as_ref().unwrap();
+        assert_eq!(current_task.start_offset(), 13 * MB as u64);
+        assert_eq!(current_task.total_size(), ALIGNMENT);
+        assert!(request.future_tasks.is_empty());
+
+        // Reading sequentially for long enough goes back to prefetching
+        let mut offset = 13 * MB + 100 + 4096;
+        while offset < 15 * MB {
+            read(&mut request, offset, 128 * 1024);
+            offset += 128 * 1024;
+        }
+        assert!(!request.random_mode);
+    }
+
+    #[test]
+    fn test_read_vectored() {
+        const OBJECT_SIZE: usize = 1 * MB;
+        const READ_SIZE: usize = 100 * 1024;
+
+        let config = MockClientConfig {
+            bucket: "test-bucket".to_string(),
+            part_size: 8 * MB,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(config));
+        let object = MockObject::ramp(0xaa, OBJECT_SIZE, ETag::for_tests());
+        let etag = object.etag();
+        client.add_object("hello", object);
+
+        let prefetcher = Prefetcher::new(default_stream(), Default::default());
+        let mut request = prefetcher.prefetch(
+            client,
+            "test-bucket",
+            ObjectId::new("hello".to_owned(), etag),
+            OBJECT_SIZE as u64,
+        );
+
+        let mut offset = 0;
+        let mut spanning_reads = 0;
+        while offset < OBJECT_SIZE {
+            let parts = block_on(request.read_vectored(offset as u64, READ_SIZE)).unwrap();
+            if parts.len() > 1 {
+                spanning_reads += 1;
+            }
+            let buf: Vec<u8> = parts.into_iter().flat_map(|part| part.into_bytes().unwrap()).collect();
+            let expected = ramp_bytes(0xaa + offset, READ_SIZE.min(OBJECT_SIZE - offset));
+            assert_eq!(buf[..], expected[..]);
+            offset += buf.len();
+        }
+        // Data arrives in chunks of at least 128KiB, so some of these reads span two of them
+        assert!(spanning_reads > 0);
+    }
+
+    #[test_case(4, 4; "split")]
+    #[test_case(1, 1; "not split")]
+    #[test_case(16, 8; "at least minimum request size")]
+    fn test_concurrent_requests_for_large_read(read_part_concurrency: usize, expected_requests: u64) {
+        const OBJECT_SIZE: usize = 4 * MB;
+        const READ_SIZE: usize = 2 * MB;
+
+        let config = MockClientConfig {
+            bucket: "test-bucket".to_string(),
+            part_size: 8 * MB,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(config));
+        let object = MockObject::ramp(0xaa, OBJECT_SIZE, ETag::for_tests());
+        let etag = object.etag();
+        client.add_object("hello", object);
+        let get_counter = client.new_counter(Operation::GetObject);
+
+        let prefetcher_config = PrefetcherConfig {
+            read_part_concurrency,
+            ..Default::default()
+        };
+        let prefetcher = Prefetcher::new(default_stream(), prefetcher_config);
+        let mut request = prefetcher.prefetch(
+            client.clone(),
+            "test-bucket",
+            ObjectId::new("hello".to_owned(), etag),
+            OBJECT_SIZE as u64,
+        );
+
+        // A read that misses the prefetch window, up to the end of the object so that nothing is
+        // prefetched after it
+        let offset = OBJECT_SIZE - READ_SIZE;
+        let buf = block_on(request.read(offset as u64, READ_SIZE)).unwrap();
+        let expected = ramp_bytes(0xaa + offset, READ_SIZE);
+        assert_eq!(buf.into_bytes().unwrap()[..], expected[..]);
+        assert_eq!(get_counter.count(), expected_requests);
+    }
+
+    #[test_case(false, true; "valid checksum")]
+    #[test_case(true, true; "corrupted object")]
+    #[test_case(true, false; "corrupted object without verification")]
+    fn test_object_checksum_verification(corrupted: bool, verify_object_checksums: bool) {
+        const OBJECT_SIZE: usize = 64 * 1024;
+
+        let config = MockClientConfig {
+            bucket: "test-bucket".to_string(),
+            part_size: 16 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(config));
+        let mut object = MockObject::ramp(0xaa, OBJECT_SIZE, ETag::for_tests());
+        if corrupted {
+            object.set_checksum_crc32c(Crc32c::new(1234));
+        }
+        let etag = object.etag();
+        client.add_object("hello", object);
+        let get_counter = client.new_counter(Operation::GetObject);
+
+        let part_stream = default_stream().verify_object_checksums(verify_object_checksums);
+        let prefetcher = Prefetcher::new(part_stream, Default::default());
+        let mut request = prefetcher.prefetch(
+            client.clone(),
+            "test-bucket",
+            ObjectId::new("hello".to_owned(), etag),
+            OBJECT_SIZE as u64,
+        );
+
+        let result = block_on(request.read(0, OBJECT_SIZE));
+        if corrupted && verify_object_checksums {
+            // The object is fetched again before giving up
+            assert!(matches!(result, Err(PrefetchReadError::Integrity(_))));
+            assert_eq!(get_counter.count(), 2);
+        } else {
+            let expected = ramp_bytes(0xaa, OBJECT_SIZE);
+            assert_eq!(result.unwrap().into_bytes().unwrap()[..], expected[..]);
+            assert_eq!(get_counter.count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_memory_limit_shared_by_streams() {
+        const OBJECT_SIZE: usize = 4 * MB;
+        const READ_SIZE: usize = 64 * 1024;
+        const MEMORY_TARGET: u64 = 512 * 1024;
+
+        let config = MockClientConfig {
+            bucket: "test-bucket".to_string(),
+            part_size: 256 * 1024,
+            ..Default::default()
+        };
+        let client = Arc::new(MockClient::new(config));
+        let object = MockObject::ramp(0xaa, OBJECT_SIZE, ETag::for_tests());
+        let etag = object.etag();
+        client.add_object("hello", object);
+
+        let prefetcher_config = PrefetcherConfig {
+            first_request_size: 256 * 1024,
+            min_request_size: READ_SIZE,
+            max_memory_target: Some(MEMORY_TARGET),
+            ..Default::default()
+        };
+        let prefetcher = Prefetcher::new(default_stream(), prefetcher_config);
+
+        // Two streams reading the same object in lockstep both make progress within the budget
+        let mut requests = [0, 1].map(|_| {
+            prefetcher.prefetch(
+                client.clone(),
+                "test-bucket",
+                ObjectId::new("hello".to_owned(), etag.clone()),
+                OBJECT_SIZE as u64,
+            )
+        });
+        for offset in (0..OBJECT_SIZE).step_by(READ_SIZE) {
+            for request in requests.iter_mut() {
+                let buf = block_on(request.read(offset as u64, READ_SIZE)).unwrap();
+                let expected = ramp_bytes(0xaa + offset, READ_SIZE);
+                assert_eq!(buf.into_bytes().unwrap()[..], expected[..]);
+            }
+            if offset + READ_SIZE < OBJECT_SIZE {
+                // Data the streams haven't read yet is still reserved
+                assert!(prefetcher.mem_limiter.available_mem() < MEMORY_TARGET);
+            }
+        }
+
+        drop(requests);
+        assert_eq!(prefetcher.mem_limiter.available_mem(), MEMORY_TARGET);
+    }
+
     #[cfg(feature = "shuttle")]
     mod shuttle_tests {
         use super::*;
@@ -1092,7 +1560,12 @@ mod tests {
             };
 
             let prefetcher = Prefetcher::new(ClientPartStream::new(ShuttleRuntime), prefetcher_config);
-            let mut request = prefetcher.prefetch(client, "test-bucket", "hello", object_size, file_etag);
+            let mut request = prefetcher.prefetch(
+                client,
+                "test-bucket",
+                ObjectId::new("hello".to_owned(), file_etag),
+                object_size,
+            );
 
             let mut next_offset = 0;
             loop {
@@ -1149,7 +1622,12 @@ mod tests {
             };
 
             let prefetcher = Prefetcher::new(ClientPartStream::new(ShuttleRuntime), prefetcher_config);
-            let mut request = prefetcher.prefetch(client, "test-bucket", "hello", object_size, file_etag);
+            let mut request = prefetcher.prefetch(
+                client,
+                "test-bucket",
+                ObjectId::new("hello".to_owned(), file_etag),
+                object_size,
+            );
 
             let num_reads = rng.gen_range(10usize..50);
             for _ in 0..num_reads {
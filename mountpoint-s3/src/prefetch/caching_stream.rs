@@ -4,7 +4,7 @@ use std::{ops::Range, sync::Arc};
 use bytes::Bytes;
 use futures::task::{Spawn, SpawnExt};
 use futures::{pin_mut, StreamExt};
-use mountpoint_s3_client::{types::ETag, ObjectClient};
+use mountpoint_s3_client::{types::GetObjectParams, ObjectClient};
 use tracing::{debug_span, trace, warn, Instrument};
 
 use crate::checksums::ChecksummedBytes;
@@ -42,8 +42,7 @@ where
         &self,
         client: &Client,
         bucket: &str,
-        key: &str,
-        if_match: ETag,
+        object_id: ObjectId,
         range: RequestRange,
         _preferred_part_size: usize,
     ) -> RequestTask<<Client as ObjectClient>::ClientError>
@@ -63,8 +62,7 @@ where
                 client.clone(),
                 self.cache.clone(),
                 bucket.to_owned(),
-                key.to_owned(),
-                if_match,
+                object_id,
                 part_queue_producer,
             );
             let span = debug_span!("prefetch", ?range);
@@ -95,11 +93,9 @@ where
         client: Client,
         cache: Arc<Cache>,
         bucket: String,
-        key: String,
-        etag: ETag,
+        cache_key: ObjectId,
         part_queue_producer: PartQueueProducer<Client::ClientError>,
     ) -> Self {
-        let cache_key = ObjectId::new(key, etag);
         Self {
             client,
             cache,
@@ -169,8 +165,10 @@ where
             .get_object(
                 &self.bucket,
                 key,
-                Some(block_aligned_byte_range),
-                Some(self.cache_key.etag().clone()),
+                &GetObjectParams::new()
+                    .range(Some(block_aligned_byte_range))
+                    .if_match(Some(self.cache_key.etag().clone()))
+                    .version_id(self.cache_key.version_id().map(str::to_owned)),
             )
             .await
         {
@@ -321,6 +319,7 @@ mod tests {
 
     use futures::executor::{block_on, ThreadPool};
     use mountpoint_s3_client::mock_client::{MockClient, MockClientConfig, MockObject, Operation};
+    use mountpoint_s3_client::types::ETag;
     use test_case::test_case;
 
     use crate::data_cache::InMemoryDataCache;
@@ -347,7 +346,6 @@ mod tests {
         let key = "object";
         let seed = 0xaa;
         let object = MockObject::ramp(seed, object_size, ETag::for_tests());
-        let etag = object.etag();
         let id = ObjectId::new(key.to_owned(), object.etag());
 
         let cache = InMemoryDataCache::new(block_size as u64);
@@ -367,7 +365,7 @@ mod tests {
         let first_read_count = {
             // First request (from client)
             let get_object_counter = mock_client.new_counter(Operation::GetObject);
-            let request_task = stream.spawn_get_object_request(&mock_client, bucket, key, etag.clone(), range, 0);
+            let request_task = stream.spawn_get_object_request(&mock_client, bucket, id.clone(), range, 0);
             compare_read(&id, &object, request_task);
             get_object_counter.count()
         };
@@ -376,7 +374,7 @@ mod tests {
         let second_read_count = {
             // Second request (from cache)
             let get_object_counter = mock_client.new_counter(Operation::GetObject);
-            let request_task = stream.spawn_get_object_request(&mock_client, bucket, key, etag.clone(), range, 0);
+            let request_task = stream.spawn_get_object_request(&mock_client, bucket, id.clone(), range, 0);
             compare_read(&id, &object, request_task);
             get_object_counter.count()
         };
@@ -392,7 +390,6 @@ mod tests {
         let object_size = 16 * MB;
         let seed = 0xaa;
         let object = MockObject::ramp(seed, object_size, ETag::for_tests());
-        let etag = object.etag();
         let id = ObjectId::new(key.to_owned(), object.etag());
 
         let cache = InMemoryDataCache::new(block_size as u64);
@@ -411,7 +408,7 @@ mod tests {
         for offset in [0, 512 * KB, 1 * MB, 4 * MB, 9 * MB] {
             for preferred_size in [1 * KB, 512 * KB, 4 * MB, 12 * MB, 16 * MB] {
                 let range = RequestRange::new(object_size, offset as u64, preferred_size);
-                let request_task = stream.spawn_get_object_request(&mock_client, bucket, key, etag.clone(), range, 0);
+                let request_task = stream.spawn_get_object_request(&mock_client, bucket, id.clone(), range, 0);
                 compare_read(&id, &object, request_task);
             }
         }
@@ -0,0 +1,100 @@
+use metrics::gauge;
+
+use crate::sync::atomic::{AtomicU64, Ordering};
+use crate::sync::Arc;
+
+/// A budget for the data buffered by all the prefetch streams of a [super::Prefetcher].
+///
+/// Streams reserve memory for each request they make, and the reservation is released as
+/// the reader consumes the request's data or when the request is dropped. When the budget is
+/// exhausted, streams stop prefetching ahead of the reader until other streams release memory.
+/// Requests the reader is already waiting for are made anyway, so the limit is a target rather
+/// than a hard cap.
+#[derive(Debug)]
+pub struct MemoryLimiter {
+    mem_limit: u64,
+    mem_reserved: AtomicU64,
+}
+
+impl MemoryLimiter {
+    pub fn new(mem_limit: u64) -> Self {
+        Self {
+            mem_limit,
+            mem_reserved: AtomicU64::new(0),
+        }
+    }
+
+    /// Reserve `size` bytes. Callers should check [Self::available_mem] first, but the reservation
+    /// always succeeds, even if it exceeds the limit.
+    pub fn reserve(self: &Arc<Self>, size: u64) -> MemoryReservation {
+        self.mem_reserved.fetch_add(size, Ordering::SeqCst);
+        self.update_metrics();
+        MemoryReservation {
+            limiter: self.clone(),
+            size,
+        }
+    }
+
+    /// The number of bytes that can still be reserved within the limit
+    pub fn available_mem(&self) -> u64 {
+        self.mem_limit.saturating_sub(self.mem_reserved.load(Ordering::SeqCst))
+    }
+
+    fn release(&self, size: u64) {
+        self.mem_reserved.fetch_sub(size, Ordering::SeqCst);
+        self.update_metrics();
+    }
+
+    fn update_metrics(&self) {
+        gauge!("prefetch.bytes_reserved").set(self.mem_reserved.load(Ordering::SeqCst) as f64);
+    }
+}
+
+/// Memory reserved from a [MemoryLimiter], which is returned to the limiter when dropped
+#[derive(Debug)]
+pub struct MemoryReservation {
+    limiter: Arc<MemoryLimiter>,
+    size: u64,
+}
+
+impl MemoryReservation {
+    /// Return up to `size` bytes of this reservation to the limiter
+    pub fn release(&mut self, size: u64) {
+        let size = size.min(self.size);
+        self.size -= size;
+        self.limiter.release(size);
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.limiter.release(self.size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve_and_release() {
+        let limiter = Arc::new(MemoryLimiter::new(100));
+
+        let mut first = limiter.reserve(60);
+        assert_eq!(limiter.available_mem(), 40);
+
+        // Reservations can exceed the limit
+        let second = limiter.reserve(50);
+        assert_eq!(limiter.available_mem(), 0);
+        drop(second);
+        assert_eq!(limiter.available_mem(), 40);
+
+        // Releasing more than the reservation holds only releases what it holds
+        first.release(20);
+        assert_eq!(limiter.available_mem(), 60);
+        first.release(100);
+        assert_eq!(limiter.available_mem(), 100);
+        drop(first);
+        assert_eq!(limiter.available_mem(), 100);
+    }
+}
@@ -1,17 +1,22 @@
+use std::collections::VecDeque;
 use std::{fmt::Debug, ops::Range};
 
 use bytes::Bytes;
 use futures::task::SpawnExt;
 use futures::{pin_mut, task::Spawn, StreamExt};
-use mountpoint_s3_client::{types::ETag, ObjectClient};
-use tracing::{debug_span, error, trace, Instrument};
+use mountpoint_s3_client::checksums::crc32c_from_base64;
+use mountpoint_s3_client::types::{ChecksumMode, GetObjectParams, ObjectAttribute};
+use mountpoint_s3_client::{GetObjectRequest, ObjectClient};
+use mountpoint_s3_crt::checksums::crc32c::{self, Crc32c};
+use tracing::{debug, debug_span, error, trace, warn, Instrument};
 
-use crate::checksums::ChecksummedBytes;
+use crate::checksums::{combine_checksums, ChecksummedBytes, IntegrityError};
 use crate::object::ObjectId;
 use crate::prefetch::part::Part;
-use crate::prefetch::part_queue::unbounded_part_queue;
+use crate::prefetch::part_queue::{unbounded_part_queue, PartQueueProducer};
 use crate::prefetch::task::RequestTask;
 use crate::prefetch::PrefetchReadError;
+use crate::sync::{Arc, Mutex};
 
 /// A generic interface to retrieve data from objects in a S3-like store.
 pub trait ObjectPartStream {
@@ -22,8 +27,7 @@ pub trait ObjectPartStream {
         &self,
         client: &Client,
         bucket: &str,
-        key: &str,
-        if_match: ETag,
+        object_id: ObjectId,
         range: RequestRange,
         preferred_part_size: usize,
     ) -> RequestTask<Client::ClientError>
@@ -144,10 +148,22 @@ impl Debug for RequestRange {
     }
 }
 
+/// Number of times to fetch data again after it didn't match the checksum S3 returned for it,
+/// before failing the read
+const MAX_CHECKSUM_MISMATCH_RETRIES: usize = 1;
+
+/// Maximum number of parts to ask for in each GetObjectAttributes request
+const MAX_ATTRIBUTES_PARTS: usize = 1000;
+
+/// Number of objects to remember the part checksums of
+const PART_CHECKSUM_CACHE_SIZE: usize = 64;
+
 /// [ObjectPartStream] implementation which delegates retrieving object data to a [Client].
 #[derive(Debug)]
 pub struct ClientPartStream<Runtime> {
     runtime: Runtime,
+    verify_object_checksums: bool,
+    part_checksums: Arc<PartChecksumCache>,
 }
 
 impl<Runtime> ClientPartStream<Runtime>
@@ -155,7 +171,18 @@ where
     Runtime: Spawn,
 {
     pub fn new(runtime: Runtime) -> Self {
-        Self { runtime }
+        Self {
+            runtime,
+            verify_object_checksums: false,
+            part_checksums: Default::default(),
+        }
+    }
+
+    /// Set whether to ask S3 for the checksums of objects and verify the data we receive against
+    /// them. Off by default.
+    pub fn verify_object_checksums(mut self, value: bool) -> Self {
+        self.verify_object_checksums = value;
+        self
     }
 }
 
@@ -167,8 +194,7 @@ where
         &self,
         client: &Client,
         bucket: &str,
-        key: &str,
-        if_match: ETag,
+        object_id: ObjectId,
         range: RequestRange,
         preferred_part_size: usize,
     ) -> RequestTask<Client::ClientError>
@@ -176,63 +202,46 @@ where
         Client: ObjectClient + Clone + Send + Sync + 'static,
     {
         assert!(preferred_part_size > 0);
-        let request_range = range.align(client.part_size().unwrap_or(8 * 1024 * 1024) as u64, true);
+        let request_range = range.align(client.read_part_size().unwrap_or(8 * 1024 * 1024) as u64, true);
         let start = request_range.start();
         let size = request_range.len();
 
         let (part_queue, part_queue_producer) = unbounded_part_queue();
         trace!(range=?request_range, "spawning request");
 
+        let verify_checksum = self.verify_object_checksums;
         let request_task = {
             let client = client.clone();
+            let part_checksums = self.part_checksums.clone();
             let bucket = bucket.to_owned();
-            let id = ObjectId::new(key.to_owned(), if_match);
+            let id = object_id;
             let span = debug_span!("prefetch", range=?request_range);
 
             async move {
-                let get_object_result = match client
-                    .get_object(&bucket, id.key(), Some(request_range.into()), Some(id.etag().clone()))
-                    .await
+                let mut range = request_range;
+                let mut retries = 0;
+                while let Err(mismatch) = fetch_parts(
+                    &client,
+                    &bucket,
+                    &id,
+                    range,
+                    preferred_part_size,
+                    verify_checksum.then_some(&*part_checksums),
+                    &part_queue_producer,
+                )
+                .await
                 {
-                    Ok(get_object_result) => get_object_result,
-                    Err(e) => {
-                        error!(key=id.key(), error=?e, "GetObject request failed");
-                        part_queue_producer.push(Err(PrefetchReadError::GetRequestFailed(e)));
-                        return;
-                    }
-                };
-
-                pin_mut!(get_object_result);
-                loop {
-                    match get_object_result.next().await {
-                        Some(Ok((offset, body))) => {
-                            trace!(offset, length = body.len(), "received GetObject part");
-                            metrics::counter!("s3.client.total_bytes", "type" => "read").increment(body.len() as u64);
-                            // pre-split the body into multiple parts as suggested by preferred part size
-                            // in order to avoid validating checksum on large parts at read.
-                            let mut body: Bytes = body.into();
-                            let mut curr_offset = offset;
-                            loop {
-                                let chunk_size = preferred_part_size.min(body.len());
-                                if chunk_size == 0 {
-                                    break;
-                                }
-                                let chunk = body.split_to(chunk_size);
-                                // S3 doesn't provide checksum for us if the request range is not aligned to
-                                // object part boundaries, so we're computing our own checksum here.
-                                let checksum_bytes = ChecksummedBytes::new(chunk);
-                                let part = Part::new(id.clone(), curr_offset, checksum_bytes);
-                                curr_offset += part.len() as u64;
-                                part_queue_producer.push(Ok(part));
-                            }
-                        }
-                        Some(Err(e)) => {
-                            error!(key=id.key(), error=?e, "GetObject body part failed");
-                            part_queue_producer.push(Err(PrefetchReadError::GetRequestFailed(e)));
-                            break;
-                        }
-                        None => break,
+                    metrics::counter!("prefetch.checksum_mismatches").increment(1);
+                    if retries < MAX_CHECKSUM_MISMATCH_RETRIES {
+                        warn!(key=id.key(), offset=mismatch.offset, error=?mismatch.error, "object data did not match its checksum, fetching it again");
+                        retries += 1;
+                        // Everything before the mismatched data was verified and pushed already
+                        range = range.trim_start(mismatch.offset);
+                        continue;
                     }
+                    error!(key=id.key(), offset=mismatch.offset, error=?mismatch.error, "object data did not match its checksum");
+                    part_queue_producer.push(Err(PrefetchReadError::Integrity(mismatch.error)));
+                    break;
                 }
                 trace!("request finished");
             }
@@ -245,6 +254,306 @@ where
     }
 }
 
+/// Data that didn't match its checksum
+#[derive(Debug)]
+struct ChecksumMismatch {
+    /// Offset in the object that the mismatched data starts at
+    offset: u64,
+    error: IntegrityError,
+}
+
+/// A range of an object, with the CRC32C checksum S3 stores for it
+#[derive(Debug, Clone)]
+struct ChecksummedRange {
+    range: Range<u64>,
+    checksum: Crc32c,
+}
+
+/// The part checksums of the objects read most recently, so that each request to an object
+/// doesn't need its own GetObjectAttributes requests
+#[derive(Debug, Default)]
+struct PartChecksumCache {
+    /// The checksums of the parts of each object, most recently used last
+    objects: Mutex<VecDeque<(String, ObjectId, Arc<[ChecksummedRange]>)>>,
+}
+
+impl PartChecksumCache {
+    /// Get the CRC32C checksums of the parts of an object that lie entirely within `range`
+    async fn get<Client>(
+        &self,
+        client: &Client,
+        bucket: &str,
+        id: &ObjectId,
+        range: RequestRange,
+    ) -> Vec<ChecksummedRange>
+    where
+        Client: ObjectClient,
+    {
+        let cached = {
+            let mut objects = self.objects.lock().unwrap();
+            let position = objects
+                .iter()
+                .position(|(cached_bucket, cached_id, _)| cached_bucket == bucket && cached_id == id);
+            let entry = position.and_then(|position| objects.remove(position));
+            entry.map(|entry| {
+                let checksums = entry.2.clone();
+                objects.push_back(entry);
+                checksums
+            })
+        };
+        let checksums = match cached {
+            Some(checksums) => checksums,
+            None => {
+                // Failed requests aren't remembered, so the next request tries again
+                let Some(checksums) = part_checksums(client, bucket, id).await else {
+                    return Vec::new();
+                };
+                let checksums: Arc<[ChecksummedRange]> = checksums.into();
+                let mut objects = self.objects.lock().unwrap();
+                if objects.len() >= PART_CHECKSUM_CACHE_SIZE {
+                    objects.pop_front();
+                }
+                objects.push_back((bucket.to_owned(), id.clone(), checksums.clone()));
+                checksums
+            }
+        };
+        checksums
+            .iter()
+            .filter(|part| part.range.start >= range.start() && part.range.end <= range.end())
+            .cloned()
+            .collect()
+    }
+}
+
+/// Fetch `range` of an object and push its parts to `part_queue_producer`. Request failures are
+/// pushed to the queue too.
+///
+/// If `part_checksums` is given, we look for the CRC32C checksums S3 stores for the object: the
+/// checksum of the whole object if `range` covers it, or otherwise the checksums of each part it
+/// was uploaded in that `range` covers. The data in each checksummed range is held back until all
+/// of it has arrived, and is only pushed if it matches. Otherwise, the mismatch is returned, and
+/// nothing from that range onwards is pushed, so the caller can fetch it again. The held back data
+/// is within `range`, so it's still part of the memory the prefetcher reserved for this request,
+/// which is only released as the reader consumes it.
+async fn fetch_parts<Client>(
+    client: &Client,
+    bucket: &str,
+    id: &ObjectId,
+    range: RequestRange,
+    preferred_part_size: usize,
+    part_checksums: Option<&PartChecksumCache>,
+    part_queue_producer: &PartQueueProducer<Client::ClientError>,
+) -> Result<(), ChecksumMismatch>
+where
+    Client: ObjectClient,
+{
+    let whole_object = range.start() == 0 && range.end() == range.object_size() as u64;
+    let request = client.get_object(
+        bucket,
+        id.key(),
+        &GetObjectParams::new()
+            .range(Some(range.into()))
+            .if_match(Some(id.etag().clone()))
+            .version_id(id.version_id().map(str::to_owned))
+            .checksum_mode((part_checksums.is_some() && whole_object).then_some(ChecksumMode::Enabled)),
+    );
+    // GetObject only returns the checksum of the whole object, so for other ranges we need the
+    // checksums of the object's parts
+    let (get_object_result, checksums) = match part_checksums {
+        Some(part_checksums) if !whole_object => futures::join!(request, part_checksums.get(client, bucket, id, range)),
+        _ => (request.await, Vec::new()),
+    };
+    let get_object_result = match get_object_result {
+        Ok(get_object_result) => get_object_result,
+        Err(e) => {
+            error!(key=id.key(), error=?e, "GetObject request failed");
+            part_queue_producer.push(Err(PrefetchReadError::GetRequestFailed(e)));
+            return Ok(());
+        }
+    };
+
+    pin_mut!(get_object_result);
+    let mut verifier = ChecksumVerifier::new(checksums);
+    loop {
+        match get_object_result.next().await {
+            Some(Ok((offset, body))) => {
+                trace!(offset, length = body.len(), "received GetObject part");
+                metrics::counter!("s3.client.total_bytes", "type" => "read").increment(body.len() as u64);
+                if let Some(part_checksums) = part_checksums.filter(|_| whole_object && offset == range.start()) {
+                    verifier = match get_object_result.get_object_checksum().and_then(|c| c.checksum_crc32c) {
+                        Some(checksum) => match crc32c_from_base64(&checksum) {
+                            Ok(checksum) => ChecksumVerifier::new(vec![ChecksummedRange {
+                                range: range.into(),
+                                checksum,
+                            }]),
+                            // Objects uploaded in multiple parts have a composite checksum, which
+                            // fails to parse, so we verify each of their parts instead
+                            Err(_) => ChecksumVerifier::new(part_checksums.get(client, bucket, id, range).await),
+                        },
+                        None => ChecksumVerifier::new(Vec::new()),
+                    };
+                }
+                // pre-split the body into multiple parts as suggested by preferred part size
+                // in order to avoid validating checksum on large parts at read.
+                let mut body: Bytes = body.into();
+                let mut curr_offset = offset;
+                loop {
+                    let mut chunk_size = preferred_part_size.min(body.len());
+                    if let Some(boundary) = verifier.next_boundary(curr_offset) {
+                        chunk_size = chunk_size.min((boundary - curr_offset) as usize);
+                    }
+                    if chunk_size == 0 {
+                        break;
+                    }
+                    let chunk = body.split_to(chunk_size);
+                    // S3 doesn't provide checksum for us if the request range is not aligned to
+                    // object part boundaries, so we're computing our own checksum here.
+                    let chunk_checksum = crc32c::checksum(&chunk);
+                    let checksum_bytes = ChecksummedBytes::new_from_inner_data(chunk, chunk_checksum);
+                    let part = Part::new(id.clone(), curr_offset, checksum_bytes);
+                    let part_offset = curr_offset;
+                    curr_offset += part.len() as u64;
+                    for part in verifier.add(part_offset, part, chunk_checksum)? {
+                        part_queue_producer.push(Ok(part));
+                    }
+                }
+            }
+            Some(Err(e)) => {
+                error!(key=id.key(), error=?e, "GetObject body part failed");
+                part_queue_producer.push(Err(PrefetchReadError::GetRequestFailed(e)));
+                return Ok(());
+            }
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+/// Get the CRC32C checksums of the parts of a multipart object using GetObjectAttributes. Returns
+/// no checksums if the object wasn't uploaded in multiple parts with CRC32C checksums or if it's no
+/// longer the object `id` refers to, and [None] if the request fails.
+async fn part_checksums<Client>(client: &Client, bucket: &str, id: &ObjectId) -> Option<Vec<ChecksummedRange>>
+where
+    Client: ObjectClient,
+{
+    let mut checksums = Vec::new();
+    let mut part_start = 0;
+    let mut part_number_marker = None;
+    loop {
+        let attributes = match client
+            .get_object_attributes(
+                bucket,
+                id.key(),
+                Some(MAX_ATTRIBUTES_PARTS),
+                part_number_marker,
+                &[ObjectAttribute::ETag, ObjectAttribute::ObjectParts],
+            )
+            .await
+        {
+            Ok(attributes) => attributes,
+            Err(e) => {
+                debug!(key=id.key(), error=?e, "GetObjectAttributes failed, not verifying part checksums");
+                return None;
+            }
+        };
+        // GetObjectAttributes returns the latest version of the object, which may not be ours, and
+        // returns its ETag without the quotes that other requests include
+        if attributes.etag.as_deref().map(|etag| etag.trim_matches('"')) != Some(id.etag().as_str().trim_matches('"')) {
+            debug!(key=id.key(), etag=?attributes.etag, "object changed, not verifying part checksums");
+            return Some(Vec::new());
+        }
+        let Some(parts) = attributes
+            .object_parts
+            .as_ref()
+            .and_then(|object_parts| object_parts.parts.as_ref())
+        else {
+            return Some(Vec::new());
+        };
+        for part in parts {
+            let part_range = part_start..part_start + part.size as u64;
+            part_start = part_range.end;
+            let checksum = part
+                .checksum
+                .as_ref()
+                .and_then(|checksum| checksum.checksum_crc32c.as_deref());
+            if let Some(Ok(checksum)) = checksum.map(crc32c_from_base64) {
+                checksums.push(ChecksummedRange {
+                    range: part_range,
+                    checksum,
+                });
+            }
+        }
+        match attributes.object_parts.and_then(|object_parts| {
+            object_parts
+                .is_truncated
+                .unwrap_or(false)
+                .then_some(object_parts.next_part_number_marker)
+                .flatten()
+        }) {
+            Some(marker) => part_number_marker = Some(marker),
+            None => return Some(checksums),
+        }
+    }
+}
+
+/// Holds back the parts of each checksummed range of an object until all of the range has arrived
+/// and matches its checksum. Parts outside the checksummed ranges are passed through unverified.
+#[derive(Debug)]
+struct ChecksumVerifier {
+    /// The ranges still to verify, in order
+    ranges: VecDeque<ChecksummedRange>,
+    held_parts: Vec<Part>,
+    checksum: Crc32c,
+}
+
+impl ChecksumVerifier {
+    fn new(ranges: Vec<ChecksummedRange>) -> Self {
+        Self {
+            ranges: ranges.into(),
+            held_parts: Vec::new(),
+            checksum: Crc32c::new(0),
+        }
+    }
+
+    /// The next offset after `offset` where a checksummed range starts or ends, which a part must
+    /// not cross
+    fn next_boundary(&self, offset: u64) -> Option<u64> {
+        let next = self.ranges.front()?;
+        Some(if offset < next.range.start {
+            next.range.start
+        } else {
+            next.range.end
+        })
+    }
+
+    /// Add a part at `offset` that doesn't cross [Self::next_boundary], and return the parts that
+    /// are now ready to push
+    fn add(&mut self, offset: u64, part: Part, part_checksum: Crc32c) -> Result<Vec<Part>, ChecksumMismatch> {
+        let Some(next) = self.ranges.front() else {
+            return Ok(vec![part]);
+        };
+        if offset < next.range.start {
+            return Ok(vec![part]);
+        }
+        let end = offset + part.len() as u64;
+        self.checksum = combine_checksums(self.checksum, part_checksum, part.len());
+        self.held_parts.push(part);
+        if end < next.range.end {
+            return Ok(Vec::new());
+        }
+
+        let next = self.ranges.pop_front().unwrap();
+        let checksum = std::mem::replace(&mut self.checksum, Crc32c::new(0));
+        if checksum != next.checksum {
+            return Err(ChecksumMismatch {
+                offset: next.range.start,
+                error: IntegrityError::ChecksumMismatch(next.checksum, checksum),
+            });
+        }
+        Ok(std::mem::take(&mut self.held_parts))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     // It's convenient to write test constants like "1 * 1024 * 1024" for symmetry
@@ -252,6 +561,11 @@ mod tests {
 
     use super::*;
 
+    use std::sync::Arc;
+
+    use futures::executor::{block_on, ThreadPool};
+    use mountpoint_s3_client::mock_client::{MockClient, MockClientConfig, MockObject, Operation};
+    use mountpoint_s3_client::types::ETag;
     use test_case::test_case;
 
     const KB: usize = 1024;
@@ -291,4 +605,117 @@ mod tests {
 
         assert_eq!(aligned_range.len(), expected_size);
     }
+
+    #[test_case(None, false; "valid checksums")]
+    #[test_case(Some(1), false; "covered part corrupted")]
+    #[test_case(Some(3), false; "partly covered part corrupted")]
+    #[test_case(None, true; "whole object with composite checksum")]
+    #[test_case(Some(3), true; "whole object with a corrupted part")]
+    fn test_part_checksum_verification(corrupted_part: Option<usize>, whole_object: bool) {
+        const UPLOAD_PART_SIZE: usize = 16 * KB;
+        const OBJECT_SIZE: usize = 4 * UPLOAD_PART_SIZE;
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: "test-bucket".to_string(),
+            part_size: 8 * KB,
+            ..Default::default()
+        }));
+        let mut object = MockObject::ramp(0xaa, OBJECT_SIZE, ETag::for_tests());
+        let parts = (0..4)
+            .map(|i| {
+                let checksum = if corrupted_part == Some(i) {
+                    Crc32c::new(1234)
+                } else {
+                    crc32c::checksum(&object.read((i * UPLOAD_PART_SIZE) as u64, UPLOAD_PART_SIZE))
+                };
+                (UPLOAD_PART_SIZE, checksum)
+            })
+            .collect::<Vec<_>>();
+        object.set_part_checksums_crc32c(&parts);
+        let expected = object.read(0, OBJECT_SIZE);
+        let id = ObjectId::new("hello".to_owned(), object.etag());
+        client.add_object("hello", object);
+        let get_counter = client.new_counter(Operation::GetObject);
+        let attributes_counter = client.new_counter(Operation::GetObjectAttributes);
+
+        let range = if whole_object {
+            RequestRange::new(OBJECT_SIZE, 0, OBJECT_SIZE)
+        } else {
+            // Covers the second and third parts, and half of the first and last
+            RequestRange::new(OBJECT_SIZE, (UPLOAD_PART_SIZE / 2) as u64, 3 * UPLOAD_PART_SIZE)
+        };
+        let part_stream =
+            ClientPartStream::new(ThreadPool::builder().pool_size(1).create().unwrap()).verify_object_checksums(true);
+        let mut request = part_stream.spawn_get_object_request(&client, "test-bucket", id.clone(), range, 4 * KB);
+
+        let mut data = Vec::new();
+        let result = block_on(async {
+            while data.len() < range.len() {
+                let offset = range.start() + data.len() as u64;
+                let part = request.read(range.len() - data.len()).await?;
+                data.extend_from_slice(&part.into_bytes(&id, offset).unwrap().into_bytes().unwrap());
+            }
+            Ok::<_, PrefetchReadError<_>>(())
+        });
+
+        // Parts the range only partly covers can't be verified
+        let verified_corrupted_part = corrupted_part.filter(|part| whole_object || (1..=2).contains(part));
+        if let Some(part) = verified_corrupted_part {
+            // The data before the corrupted part is returned, and the rest is fetched again before
+            // giving up
+            assert!(matches!(result, Err(PrefetchReadError::Integrity(_))));
+            assert_eq!(data.len(), part * UPLOAD_PART_SIZE - range.start() as usize);
+            assert_eq!(get_counter.count(), 2);
+        } else {
+            result.unwrap();
+            assert_eq!(data[..], expected[range.start() as usize..range.end() as usize]);
+            assert_eq!(get_counter.count(), 1);
+        }
+        // Fetching the data again reuses the part checksums
+        assert_eq!(attributes_counter.count(), 1);
+    }
+
+    #[test]
+    fn test_part_checksums_cached() {
+        const UPLOAD_PART_SIZE: usize = 16 * KB;
+        const OBJECT_SIZE: usize = 8 * UPLOAD_PART_SIZE;
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: "test-bucket".to_string(),
+            part_size: 8 * KB,
+            ..Default::default()
+        }));
+        let mut object = MockObject::ramp(0xaa, OBJECT_SIZE, ETag::for_tests());
+        let parts = (0..8)
+            .map(|i| {
+                let checksum = crc32c::checksum(&object.read((i * UPLOAD_PART_SIZE) as u64, UPLOAD_PART_SIZE));
+                (UPLOAD_PART_SIZE, checksum)
+            })
+            .collect::<Vec<_>>();
+        object.set_part_checksums_crc32c(&parts);
+        let expected = object.read(0, OBJECT_SIZE);
+        let id = ObjectId::new("hello".to_owned(), object.etag());
+        client.add_object("hello", object);
+        let attributes_counter = client.new_counter(Operation::GetObjectAttributes);
+
+        let part_stream =
+            ClientPartStream::new(ThreadPool::builder().pool_size(1).create().unwrap()).verify_object_checksums(true);
+
+        // Read the object in a series of requests, like a prefetcher would
+        for start in (0..OBJECT_SIZE).step_by(2 * UPLOAD_PART_SIZE) {
+            let range = RequestRange::new(OBJECT_SIZE, start as u64, 2 * UPLOAD_PART_SIZE);
+            let mut request = part_stream.spawn_get_object_request(&client, "test-bucket", id.clone(), range, 4 * KB);
+            let mut data = Vec::new();
+            block_on(async {
+                while data.len() < range.len() {
+                    let offset = range.start() + data.len() as u64;
+                    let part = request.read(range.len() - data.len()).await.unwrap();
+                    data.extend_from_slice(&part.into_bytes(&id, offset).unwrap().into_bytes().unwrap());
+                }
+            });
+            assert_eq!(data[..], expected[range.start() as usize..range.end() as usize]);
+        }
+
+        assert_eq!(attributes_counter.count(), 1);
+    }
 }
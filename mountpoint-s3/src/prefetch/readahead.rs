@@ -0,0 +1,241 @@
+//! Sizing of the prefetcher's requests.
+//!
+//! A prefetch stream ramps up its request size while the reader keeps reading sequentially. How
+//! far it's worth ramping depends on who the bottleneck is. If the reader spends time waiting for
+//! data from S3, bigger requests give the client more parallelism to fetch with. If the reader
+//! never waits, data is arriving faster than it's consumed, and bigger requests just hold more of
+//! the object in memory. [ReadaheadController] watches each request as the reader consumes it and
+//! caps the request size at what the reader can consume in [READAHEAD_TARGET], shrinking the
+//! window again if the reader slows down.
+//!
+//! It also adapts the size of the first request after an out-of-order read. Readers that keep
+//! jumping around the object after only reading a little each time get smaller first requests, so
+//! we don't download data they'll never read.
+
+use std::time::{Duration, Instant};
+
+use crate::prefetch::PrefetcherConfig;
+
+/// How much data we want in flight ahead of a reader that isn't waiting on S3, measured in how
+/// long it takes the reader to consume it
+const READAHEAD_TARGET: Duration = Duration::from_secs(1);
+
+/// If the reader spent at least this fraction of the time it took to consume a request waiting for
+/// its data to arrive, the request was too small to keep up with the reader.
+const STARVED_FRACTION: f64 = 0.1;
+
+/// Weight of the newest observation in the moving average of the reader's consumption rate
+const RATE_SMOOTHING: f64 = 0.5;
+
+/// Timing of a single request, from when the reader started consuming it to when it was done
+#[derive(Debug, Clone, Copy)]
+pub struct RequestSample {
+    /// Number of bytes in the request
+    pub size: usize,
+    /// Time from the first read of the request to the last
+    pub elapsed: Duration,
+    /// Time the reader spent blocked waiting for the request's data to arrive
+    pub waited: Duration,
+}
+
+/// Tracks how the reader consumes a single request, to produce a [RequestSample]
+#[derive(Debug, Default)]
+pub struct RequestTimer {
+    first_read: Option<Instant>,
+    waited: Duration,
+}
+
+impl RequestTimer {
+    /// Record a read of the request that started at `start`. If `starved` is true, the read had to
+    /// wait for data to arrive.
+    pub fn record_read(&mut self, start: Instant, starved: bool) {
+        self.first_read.get_or_insert(start);
+        if starved {
+            self.waited += start.elapsed();
+        }
+    }
+
+    /// The sample for a request of `size` bytes that's now been fully read, or `None` if it was
+    /// never read.
+    pub fn finish(&self, size: usize) -> Option<RequestSample> {
+        let first_read = self.first_read?;
+        Some(RequestSample {
+            size,
+            elapsed: first_read.elapsed(),
+            waited: self.waited,
+        })
+    }
+}
+
+/// Chooses request sizes for a single prefetch stream
+#[derive(Debug)]
+pub struct ReadaheadController {
+    min_request_size: usize,
+    first_request_size: usize,
+    max_request_size: usize,
+    multiplier: usize,
+    /// Size of the first request after an out-of-order read
+    restart_size: usize,
+    /// Smoothed rate the reader consumes data at when it isn't waiting on S3, in bytes per second,
+    /// or `None` if the reader is limited by S3 and requests should keep growing
+    reader_rate: Option<f64>,
+}
+
+impl ReadaheadController {
+    pub fn new(config: &PrefetcherConfig) -> Self {
+        // Tolerate inconsistent configs rather than panicking: the first request size is always
+        // within bounds.
+        let min_request_size = config.min_request_size.min(config.first_request_size).max(1);
+        Self {
+            min_request_size,
+            first_request_size: config.first_request_size,
+            max_request_size: config.max_request_size,
+            multiplier: config.sequential_prefetch_multiplier,
+            restart_size: config.first_request_size,
+            reader_rate: None,
+        }
+    }
+
+    /// Size of the first request in a run of sequential reads
+    pub fn first_request_size(&self) -> usize {
+        self.restart_size
+    }
+
+    /// Size of the request to make after one of `previous_size` bytes
+    pub fn next_request_size(&self, previous_size: usize) -> usize {
+        let grown = previous_size.saturating_mul(self.multiplier);
+        let size = match self.reader_rate {
+            None => grown,
+            Some(rate) => {
+                // Shrink at most by half per request, so one slow read doesn't collapse the window
+                let target = (rate * READAHEAD_TARGET.as_secs_f64()) as usize;
+                grown.min(target).max(previous_size / 2)
+            }
+        };
+        size.max(self.min_request_size).min(self.max_request_size)
+    }
+
+    /// Update the controller after the reader finished consuming a request
+    pub fn record_request(&mut self, sample: RequestSample) {
+        let elapsed = sample.elapsed.as_secs_f64();
+        if elapsed <= 0.0 || sample.waited.as_secs_f64() >= elapsed * STARVED_FRACTION {
+            // The reader is waiting on S3, so let requests grow
+            self.reader_rate = None;
+            return;
+        }
+        let rate = sample.size as f64 / elapsed;
+        self.reader_rate = Some(match self.reader_rate {
+            Some(previous) => previous * (1.0 - RATE_SMOOTHING) + rate * RATE_SMOOTHING,
+            None => rate,
+        });
+    }
+
+    /// Update the controller after an out-of-order read ended a run of `run_length` sequential
+    /// bytes. Short runs shrink the first request of the next run, and long runs grow it back.
+    /// Empty runs, like a seek straight after opening the file, tell us nothing and are ignored.
+    pub fn record_out_of_order(&mut self, run_length: u64) {
+        if run_length == 0 {
+            return;
+        }
+        let run_length = usize::try_from(run_length).unwrap_or(usize::MAX);
+        self.restart_size = run_length
+            .saturating_mul(2)
+            .max(self.restart_size / 2)
+            .max(self.min_request_size)
+            .min(self.first_request_size);
+        // The next run starts over, so forget what we learned about this one's reader
+        self.reader_rate = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: usize = 1024 * 1024;
+
+    fn controller() -> ReadaheadController {
+        let config = PrefetcherConfig {
+            min_request_size: 128 * 1024,
+            first_request_size: MB,
+            max_request_size: 1024 * MB,
+            sequential_prefetch_multiplier: 8,
+            ..Default::default()
+        };
+        ReadaheadController::new(&config)
+    }
+
+    fn sample(size: usize, elapsed_ms: u64, waited_ms: u64) -> RequestSample {
+        RequestSample {
+            size,
+            elapsed: Duration::from_millis(elapsed_ms),
+            waited: Duration::from_millis(waited_ms),
+        }
+    }
+
+    #[test]
+    fn test_grows_while_starved() {
+        let mut controller = controller();
+        assert_eq!(controller.next_request_size(MB), 8 * MB);
+        controller.record_request(sample(8 * MB, 100, 50));
+        assert_eq!(controller.next_request_size(8 * MB), 64 * MB);
+        assert_eq!(controller.next_request_size(512 * MB), 1024 * MB);
+    }
+
+    #[test]
+    fn test_slow_reader_caps_window() {
+        let mut controller = controller();
+        // 8MiB in 1s without waiting: the reader consumes 8MiB/s
+        controller.record_request(sample(8 * MB, 1000, 0));
+        assert_eq!(controller.next_request_size(8 * MB), 8 * MB);
+        assert_eq!(controller.next_request_size(MB), 8 * MB);
+        // Shrink by at most half per request
+        assert_eq!(controller.next_request_size(64 * MB), 32 * MB);
+
+        // The reader slows down to 2MiB/s, averaging 5MiB/s
+        controller.record_request(sample(8 * MB, 4000, 0));
+        assert_eq!(controller.next_request_size(8 * MB), 5 * MB);
+
+        // Once the reader waits on S3 again, the window grows
+        controller.record_request(sample(8 * MB, 1000, 500));
+        assert_eq!(controller.next_request_size(8 * MB), 64 * MB);
+    }
+
+    #[test]
+    fn test_restart_size_adapts_to_run_length() {
+        let mut controller = controller();
+        assert_eq!(controller.first_request_size(), MB);
+
+        // Short runs halve the first request down to the minimum
+        controller.record_out_of_order(4096);
+        assert_eq!(controller.first_request_size(), 512 * 1024);
+        controller.record_out_of_order(4096);
+        controller.record_out_of_order(4096);
+        controller.record_out_of_order(4096);
+        assert_eq!(controller.first_request_size(), 128 * 1024);
+
+        // A long run restores it, but never beyond the configured first request size
+        controller.record_out_of_order(384 * 1024);
+        assert_eq!(controller.first_request_size(), 768 * 1024);
+        controller.record_out_of_order(16 * MB as u64);
+        assert_eq!(controller.first_request_size(), MB);
+    }
+
+    #[test]
+    fn test_inconsistent_config() {
+        let config = PrefetcherConfig {
+            min_request_size: 64,
+            first_request_size: 16,
+            max_request_size: 32,
+            sequential_prefetch_multiplier: 1,
+            ..Default::default()
+        };
+        let mut controller = ReadaheadController::new(&config);
+        controller.record_request(sample(16, 1000, 0));
+        assert_eq!(controller.next_request_size(16), 16);
+        controller.record_out_of_order(1);
+        assert_eq!(controller.first_request_size(), 16);
+        controller.record_out_of_order(0);
+        assert_eq!(controller.first_request_size(), 16);
+    }
+}
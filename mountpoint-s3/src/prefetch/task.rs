@@ -1,7 +1,11 @@
+use std::time::Instant;
+
 use futures::future::RemoteHandle;
 
+use crate::prefetch::mem_limiter::MemoryReservation;
 use crate::prefetch::part::Part;
 use crate::prefetch::part_queue::{unbounded_part_queue, PartQueue};
+use crate::prefetch::readahead::{RequestSample, RequestTimer};
 use crate::prefetch::PrefetchReadError;
 
 /// A single GetObject request submitted to the S3 client
@@ -14,6 +18,9 @@ pub struct RequestTask<E: std::error::Error> {
     start_offset: u64,
     total_size: usize,
     part_queue: PartQueue<E>,
+    timer: RequestTimer,
+    /// Memory reserved for this request's data, released as the data is read
+    reservation: Option<MemoryReservation>,
 }
 
 impl<E: std::error::Error + Send + Sync> RequestTask<E> {
@@ -24,6 +31,8 @@ impl<E: std::error::Error + Send + Sync> RequestTask<E> {
             start_offset: offset,
             total_size: size,
             part_queue,
+            timer: Default::default(),
+            reservation: None,
         }
     }
 
@@ -40,16 +49,30 @@ impl<E: std::error::Error + Send + Sync> RequestTask<E> {
             start_offset: offset,
             total_size: size,
             part_queue,
+            timer: Default::default(),
+            reservation: None,
         }
     }
 
     pub async fn read(&mut self, length: usize) -> Result<Part, PrefetchReadError<E>> {
+        let start = Instant::now();
+        let starved = self.part_queue.bytes_received() <= self.total_size - self.remaining;
         let part = self.part_queue.read(length).await?;
         debug_assert!(part.len() <= self.remaining);
         self.remaining -= part.len();
+        self.timer.record_read(start, starved);
+        if let Some(reservation) = self.reservation.as_mut() {
+            reservation.release(part.len() as u64);
+        }
         Ok(part)
     }
 
+    /// Attach a memory reservation for this request's data. It's released as the data is read, or
+    /// when the request is dropped.
+    pub fn set_reservation(&mut self, reservation: MemoryReservation) {
+        self.reservation = Some(reservation);
+    }
+
     pub fn start_offset(&self) -> u64 {
         self.start_offset
     }
@@ -76,4 +99,13 @@ impl<E: std::error::Error + Send + Sync> RequestTask<E> {
     pub fn is_streaming(&self) -> bool {
         self.task_handle.is_some()
     }
+
+    /// How the reader consumed this request, once it's been fully read. Requests that aren't
+    /// streaming don't have a sample.
+    pub fn sample(&self) -> Option<RequestSample> {
+        if !self.is_streaming() || self.remaining > 0 {
+            return None;
+        }
+        self.timer.finish(self.total_size)
+    }
 }
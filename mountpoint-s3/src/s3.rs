@@ -1,6 +1,9 @@
 //! Personalities of different S3 implementations. We use this to auto-configure some sensible
 //! defaults that differ between implementations.
 
+pub mod arn;
+pub mod router;
+
 /// The type of S3 we're talking to.
 ///
 /// This enum intentionally doesn't implement PartialEq/Eq. You shouldn't test it directly. Instead,
@@ -11,6 +14,9 @@ pub enum S3Personality {
     Standard,
     ExpressOneZone,
     Outposts,
+    /// An S3-compatible object store that isn't Amazon S3, like MinIO or Ceph RGW. We stick to the
+    /// core S3 API for these, and don't use optional features that many of them don't implement.
+    ThirdParty,
 }
 
 impl S3Personality {
@@ -19,6 +25,7 @@ impl S3Personality {
             S3Personality::Standard => true,
             S3Personality::ExpressOneZone => false,
             S3Personality::Outposts => true,
+            S3Personality::ThirdParty => true,
         }
     }
 
@@ -27,6 +34,29 @@ impl S3Personality {
             S3Personality::Standard => true,
             S3Personality::ExpressOneZone => true,
             S3Personality::Outposts => false,
+            S3Personality::ThirdParty => false,
+        }
+    }
+
+    /// Whether new objects can be encrypted with KMS keys (`--sse aws:kms`). S3 on Outposts only
+    /// supports SSE-S3 and SSE-C.
+    pub fn supports_sse_kms(&self) -> bool {
+        match self {
+            S3Personality::Standard => true,
+            S3Personality::ExpressOneZone => true,
+            S3Personality::Outposts => false,
+            S3Personality::ThirdParty => false,
+        }
+    }
+
+    /// Whether files opened with O_APPEND can append to existing objects, by seeding a multipart
+    /// upload with a server-side copy of the object.
+    pub fn supports_append(&self) -> bool {
+        match self {
+            S3Personality::Standard => false,
+            S3Personality::ExpressOneZone => true,
+            S3Personality::Outposts => false,
+            S3Personality::ThirdParty => false,
         }
     }
 }
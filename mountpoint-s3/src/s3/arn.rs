@@ -0,0 +1,111 @@
+//! Parsing of the ARNs that can be mounted in place of a bucket name.
+//!
+//! The CRT's endpoint resolver works out the endpoint and signing configuration for an ARN by
+//! itself, so we only parse ARNs to reject ones that can't be mounted with a helpful error, and to
+//! pick the region to send the first request to.
+
+use anyhow::anyhow;
+
+/// The resource an ARN passed as the bucket argument refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BucketArnResource {
+    /// A bucket, like `arn:aws:s3:::DOC-EXAMPLE-BUCKET`, with its name
+    Bucket(String),
+    /// An access point, like `arn:aws:s3:us-east-1:111122223333:accesspoint/my-access-point`
+    AccessPoint,
+    /// A Multi-Region Access Point, like `arn:aws:s3::111122223333:accesspoint/mfzwi23gnjvgw.mrap`
+    MultiRegionAccessPoint,
+    /// An Object Lambda Access Point, like
+    /// `arn:aws:s3-object-lambda:us-east-1:111122223333:accesspoint/my-olap`
+    ObjectLambdaAccessPoint,
+    /// An S3 on Outposts access point, like
+    /// `arn:aws:s3-outposts:us-east-1:111122223333:outpost/op-01ac5d28a6a232904/accesspoint/my-access-point`
+    OutpostsAccessPoint,
+}
+
+/// An ARN passed as the bucket argument
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BucketArn {
+    /// The region in the ARN. Buckets and Multi-Region Access Points don't have one.
+    pub region: Option<String>,
+    pub resource: BucketArnResource,
+}
+
+impl BucketArn {
+    /// Parse an ARN of the form `arn:partition:service:region:account-id:resource`.
+    pub fn parse(arn: &str) -> anyhow::Result<Self> {
+        let invalid = |reason: &str| anyhow!("invalid ARN {arn}: {reason}");
+
+        let parts: Vec<_> = arn.splitn(6, ':').collect();
+        let [prefix, partition, service, region, account_id, resource] = parts[..] else {
+            return Err(invalid("expected arn:partition:service:region:account-id:resource"));
+        };
+        if prefix != "arn" || partition.is_empty() {
+            return Err(invalid("expected arn:partition:service:region:account-id:resource"));
+        }
+        // Access point resources can separate their parts with either `/` or `:`
+        let resource: Vec<_> = resource.split(['/', ':']).collect();
+
+        let resource = match (service, &resource[..]) {
+            ("s3", [bucket]) if region.is_empty() && account_id.is_empty() => {
+                BucketArnResource::Bucket(bucket.to_string())
+            }
+            ("s3", ["accesspoint", name]) if region.is_empty() && name.ends_with(".mrap") => {
+                BucketArnResource::MultiRegionAccessPoint
+            }
+            ("s3", ["accesspoint", _]) => BucketArnResource::AccessPoint,
+            ("s3-object-lambda", ["accesspoint", _]) => BucketArnResource::ObjectLambdaAccessPoint,
+            ("s3-outposts", ["outpost", _, "accesspoint", _]) => BucketArnResource::OutpostsAccessPoint,
+            _ => {
+                return Err(invalid(
+                    "only bucket, access point, Multi-Region Access Point, Object Lambda Access Point, \
+                    and S3 on Outposts access point ARNs can be mounted",
+                ))
+            }
+        };
+        if !matches!(resource, BucketArnResource::Bucket(_)) {
+            // Other than Multi-Region Access Points, access points belong to a single region
+            if resource != BucketArnResource::MultiRegionAccessPoint && region.is_empty() {
+                return Err(invalid("access point ARNs must have a region"));
+            }
+            if account_id.is_empty() {
+                return Err(invalid("access point ARNs must have an account ID"));
+            }
+        }
+
+        let region = (!region.is_empty()).then(|| region.to_owned());
+        Ok(Self { region, resource })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case("arn:aws:s3:::doc-example-bucket", None, BucketArnResource::Bucket("doc-example-bucket".to_owned()); "bucket")]
+    #[test_case("arn:aws:s3:us-west-2:111122223333:accesspoint/my-access-point", Some("us-west-2"), BucketArnResource::AccessPoint; "access point")]
+    #[test_case("arn:aws:s3:us-west-2:111122223333:accesspoint:my-access-point", Some("us-west-2"), BucketArnResource::AccessPoint; "access point with colon")]
+    #[test_case("arn:aws-cn:s3:cn-north-1:111122223333:accesspoint/my-access-point", Some("cn-north-1"), BucketArnResource::AccessPoint; "access point in China")]
+    #[test_case("arn:aws:s3::111122223333:accesspoint/mfzwi23gnjvgw.mrap", None, BucketArnResource::MultiRegionAccessPoint; "multi-region access point")]
+    #[test_case("arn:aws-us-gov:s3-object-lambda:us-gov-west-1:111122223333:accesspoint/my-olap", Some("us-gov-west-1"), BucketArnResource::ObjectLambdaAccessPoint; "object lambda access point")]
+    #[test_case("arn:aws:s3-outposts:us-east-1:111122223333:outpost/op-01ac5d28a6a232904/accesspoint/my-access-point", Some("us-east-1"), BucketArnResource::OutpostsAccessPoint; "outposts access point")]
+    fn parse_arn(arn: &str, region: Option<&str>, resource: BucketArnResource) {
+        let parsed = BucketArn::parse(arn).expect("valid ARN");
+        assert_eq!(parsed.region.as_deref(), region);
+        assert_eq!(parsed.resource, resource);
+    }
+
+    #[test_case("arn:aws:s3"; "too short")]
+    #[test_case("arn::s3:us-west-2:111122223333:accesspoint/my-access-point"; "no partition")]
+    #[test_case("arn:aws:s3::111122223333:accesspoint/my-access-point"; "access point without region")]
+    #[test_case("arn:aws:s3:us-west-2::accesspoint/my-access-point"; "access point without account")]
+    #[test_case("arn:aws:s3:us-west-2:111122223333:doc-example-bucket"; "bucket with region")]
+    #[test_case("arn:aws:s3:::doc-example-bucket/key"; "object")]
+    #[test_case("arn:aws:s3express:us-west-2:111122223333:bucket/doc-example-bucket--usw2-az1--x-s3"; "directory bucket")]
+    #[test_case("arn:aws:iam::111122223333:role/my-role"; "other service")]
+    fn parse_invalid_arn(arn: &str) {
+        BucketArn::parse(arn).expect_err("invalid ARN");
+    }
+}
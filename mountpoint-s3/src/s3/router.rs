@@ -0,0 +1,274 @@
+//! An [ObjectClient] that sends each request to the client for its bucket, so that one file system
+//! can mount several buckets that each need their own client configuration (region, endpoint,
+//! and so on).
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use mountpoint_s3_client::error::{
+    CopyObjectError, DeleteObjectError, GetObjectAttributesError, GetObjectError, HeadObjectError, ListObjectsError,
+    MultipartUploadError, PutObjectError,
+};
+use mountpoint_s3_client::types::{
+    AbortMultipartUploadResult, CompletedPart, CopyObjectParams, CopyObjectResult, CreateMultipartUploadResult,
+    DeleteObjectResult, GetObjectAttributesResult, GetObjectParams, HeadObjectResult, ListMultipartUploadsResult,
+    ListObjectVersionsResult, ListObjectsResult, ObjectAttribute, ObjectClientResult, PutObjectParams, PutObjectResult,
+    UploadPartCopyParams, UploadPartResult,
+};
+use mountpoint_s3_client::ObjectClient;
+
+/// Routes requests to a client for each bucket, falling back to a default client for buckets
+/// without one of their own.
+///
+/// All the clients should use the same part size, as callers can only ask the router for one.
+#[derive(Debug)]
+pub struct BucketRouter<Client> {
+    default: Client,
+    clients: HashMap<String, Client>,
+}
+
+impl<Client> BucketRouter<Client> {
+    /// Create a router that sends every request to `default`, until other buckets are added.
+    pub fn new(default: Client) -> Self {
+        Self {
+            default,
+            clients: HashMap::new(),
+        }
+    }
+
+    /// Send requests for `bucket` to `client`.
+    pub fn add_bucket(&mut self, bucket: &str, client: Client) {
+        self.clients.insert(bucket.to_owned(), client);
+    }
+
+    /// The client for requests to `bucket`
+    pub fn client(&self, bucket: &str) -> &Client {
+        self.clients.get(bucket).unwrap_or(&self.default)
+    }
+
+    /// The client for buckets without one of their own
+    pub fn default_client(&self) -> &Client {
+        &self.default
+    }
+}
+
+#[async_trait]
+impl<Client> ObjectClient for BucketRouter<Client>
+where
+    Client: ObjectClient + Send + Sync + 'static,
+{
+    type GetObjectResult = Client::GetObjectResult;
+    type PutObjectRequest = Client::PutObjectRequest;
+    type ClientError = Client::ClientError;
+
+    fn read_part_size(&self) -> Option<usize> {
+        self.default.read_part_size()
+    }
+
+    fn write_part_size(&self) -> Option<usize> {
+        self.default.write_part_size()
+    }
+
+    async fn delete_object(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> ObjectClientResult<DeleteObjectResult, DeleteObjectError, Self::ClientError> {
+        self.client(bucket).delete_object(bucket, key).await
+    }
+
+    async fn get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &GetObjectParams,
+    ) -> ObjectClientResult<Self::GetObjectResult, GetObjectError, Self::ClientError> {
+        self.client(bucket).get_object(bucket, key, params).await
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        continuation_token: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectsResult, ListObjectsError, Self::ClientError> {
+        self.client(bucket)
+            .list_objects(bucket, continuation_token, delimiter, max_keys, prefix)
+            .await
+    }
+
+    async fn list_object_versions(
+        &self,
+        bucket: &str,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        delimiter: &str,
+        max_keys: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListObjectVersionsResult, ListObjectsError, Self::ClientError> {
+        self.client(bucket)
+            .list_object_versions(bucket, key_marker, version_id_marker, delimiter, max_keys, prefix)
+            .await
+    }
+
+    async fn head_object(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> ObjectClientResult<HeadObjectResult, HeadObjectError, Self::ClientError> {
+        self.client(bucket).head_object(bucket, key).await
+    }
+
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+    ) -> ObjectClientResult<Self::PutObjectRequest, PutObjectError, Self::ClientError> {
+        self.client(bucket).put_object(bucket, key, params).await
+    }
+
+    async fn get_object_attributes(
+        &self,
+        bucket: &str,
+        key: &str,
+        max_parts: Option<usize>,
+        part_number_marker: Option<usize>,
+        object_attributes: &[ObjectAttribute],
+    ) -> ObjectClientResult<GetObjectAttributesResult, GetObjectAttributesError, Self::ClientError> {
+        self.client(bucket)
+            .get_object_attributes(bucket, key, max_parts, part_number_marker, object_attributes)
+            .await
+    }
+
+    /// Copies are sent to the client for the destination bucket, which is the one S3 sends the
+    /// request to.
+    async fn copy_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        destination_bucket: &str,
+        destination_key: &str,
+        params: &CopyObjectParams,
+    ) -> ObjectClientResult<CopyObjectResult, CopyObjectError, Self::ClientError> {
+        self.client(destination_bucket)
+            .copy_object(source_bucket, source_key, destination_bucket, destination_key, params)
+            .await
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: &PutObjectParams,
+    ) -> ObjectClientResult<CreateMultipartUploadResult, MultipartUploadError, Self::ClientError> {
+        self.client(bucket).create_multipart_upload(bucket, key, params).await
+    }
+
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        data: &[u8],
+    ) -> ObjectClientResult<UploadPartResult, MultipartUploadError, Self::ClientError> {
+        self.client(bucket)
+            .upload_part(bucket, key, upload_id, part_number, data)
+            .await
+    }
+
+    async fn upload_part_copy(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        source_bucket: &str,
+        source_key: &str,
+        params: &UploadPartCopyParams,
+    ) -> ObjectClientResult<UploadPartResult, MultipartUploadError, Self::ClientError> {
+        self.client(bucket)
+            .upload_part_copy(bucket, key, upload_id, part_number, source_bucket, source_key, params)
+            .await
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        parts: &[CompletedPart],
+    ) -> ObjectClientResult<PutObjectResult, MultipartUploadError, Self::ClientError> {
+        self.client(bucket)
+            .complete_multipart_upload(bucket, key, upload_id, parts)
+            .await
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ObjectClientResult<AbortMultipartUploadResult, MultipartUploadError, Self::ClientError> {
+        self.client(bucket).abort_multipart_upload(bucket, key, upload_id).await
+    }
+
+    async fn list_multipart_uploads(
+        &self,
+        bucket: &str,
+        key_marker: Option<&str>,
+        upload_id_marker: Option<&str>,
+        max_uploads: usize,
+        prefix: &str,
+    ) -> ObjectClientResult<ListMultipartUploadsResult, MultipartUploadError, Self::ClientError> {
+        self.client(bucket)
+            .list_multipart_uploads(bucket, key_marker, upload_id_marker, max_uploads, prefix)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mountpoint_s3_client::mock_client::{MockClient, MockClientConfig, MockObject};
+    use mountpoint_s3_client::types::ETag;
+
+    use super::*;
+
+    fn mock_client(bucket: &str) -> MockClient {
+        MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_routes_by_bucket() {
+        let mut router = BucketRouter::new(mock_client("bucket-a"));
+        router.add_bucket("bucket-b", mock_client("bucket-b"));
+        router
+            .default_client()
+            .add_object("a.txt", MockObject::constant(0xaa, 10, ETag::for_tests()));
+        router
+            .client("bucket-b")
+            .add_object("b.txt", MockObject::constant(0xbb, 20, ETag::for_tests()));
+
+        let a = router.head_object("bucket-a", "a.txt").await.unwrap();
+        assert_eq!(a.object.size, 10);
+        let b = router.head_object("bucket-b", "b.txt").await.unwrap();
+        assert_eq!(b.object.size, 20);
+        router
+            .head_object("bucket-b", "a.txt")
+            .await
+            .expect_err("each bucket should only see its own objects");
+
+        // Buckets without a client of their own go to the default client, which rejects them
+        router
+            .head_object("bucket-c", "a.txt")
+            .await
+            .expect_err("unknown bucket should fail");
+    }
+}
@@ -1,21 +1,90 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::str::FromStr;
+use std::time::Duration;
 use std::{fmt::Debug, sync::Arc};
 
+use async_lock::Semaphore;
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::task::{Spawn, SpawnExt};
+use futures::{pin_mut, FutureExt, StreamExt, TryStreamExt};
 use mountpoint_s3_client::checksums::crc32c_from_base64;
-use mountpoint_s3_client::error::{ObjectClientError, PutObjectError};
-use mountpoint_s3_client::types::{PutObjectParams, PutObjectResult, PutObjectTrailingChecksums, UploadReview};
+use mountpoint_s3_client::error::{
+    GetObjectError, HeadObjectError, MultipartUploadError, ObjectClientError, PutObjectError,
+};
+use mountpoint_s3_client::types::{
+    CompletedPart, ETag, GetObjectParams, PutObjectParams, PutObjectResult, PutObjectTrailingChecksums,
+    UploadPartCopyParams, UploadReview,
+};
 use mountpoint_s3_client::{ObjectClient, PutObjectRequest};
 
 use mountpoint_s3_crt::checksums::crc32c::{Crc32c, Hasher};
 use thiserror::Error;
-use tracing::error;
+use tracing::{debug, error, trace, warn};
 
 use crate::checksums::combine_checksums;
-use crate::fs::{ServerSideEncryption, SseCorruptedError};
+use crate::fs::{ServerSideEncryption, SseCorruptedError, WriteConflictPolicy};
 
-type PutRequestError<Client> = ObjectClientError<PutObjectError, <Client as ObjectClient>::ClientError>;
+mod cleanup;
+
+use cleanup::WritingGuard;
+pub use cleanup::{IncompleteUploadCleaner, UploadTracker};
+
+type RequestError<Client> = UploadRequestError<<Client as ObjectClient>::ClientError>;
+type HeadRequestError<Client> = ObjectClientError<HeadObjectError, <Client as ObjectClient>::ClientError>;
+type MultipartUploadRequestError<Client> =
+    ObjectClientError<MultipartUploadError, <Client as ObjectClient>::ClientError>;
 
 const MAX_S3_MULTIPART_UPLOAD_PARTS: usize = 10000;
 
+/// Every part of a multipart upload except the last must be at least this big, so smaller objects
+/// can't be copied into the first part of an append.
+const MIN_S3_MULTIPART_UPLOAD_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+/// The most that an UploadPartCopy request can copy into a single part. Larger objects are copied
+/// into several parts, a range at a time.
+const MAX_S3_UPLOAD_PART_COPY_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+
+/// The part size for appends if the client doesn't have one.
+const DEFAULT_APPEND_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// How many parts of each append to have in flight at once by default. Each part in flight holds a
+/// part of memory, so this bounds an append to 64 MiB of buffered parts with the default part size.
+pub const DEFAULT_APPEND_UPLOAD_CONCURRENCY: usize = 8;
+
+/// Limits on how many UploadPart requests appends have in flight. Appends are the only multipart
+/// uploads that Mountpoint drives itself. PutObject requests for new objects are split into parts
+/// by the client, which schedules them on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AppendUploadConcurrency {
+    /// Most parts of a single append to have in flight at once
+    pub per_file: usize,
+    /// Most parts to have in flight at once across all appends
+    pub total: usize,
+}
+
+impl Default for AppendUploadConcurrency {
+    fn default() -> Self {
+        Self {
+            per_file: DEFAULT_APPEND_UPLOAD_CONCURRENCY,
+            total: 8 * DEFAULT_APPEND_UPLOAD_CONCURRENCY,
+        }
+    }
+}
+
+/// The runtime that drives the parts of appends in the background, so that each part gives back
+/// its slot in the limit across all appends as soon as it finishes, even if its append isn't being
+/// written to.
+#[derive(Clone)]
+struct PartRuntime(Arc<dyn Spawn + Send + Sync>);
+
+impl Debug for PartRuntime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PartRuntime").finish_non_exhaustive()
+    }
+}
+
 /// An [Uploader] creates and manages streaming PutObject requests.
 #[derive(Debug)]
 pub struct Uploader<Client> {
@@ -28,6 +97,16 @@ struct UploaderInner<Client> {
     storage_class: Option<String>,
     server_side_encryption: ServerSideEncryption,
     use_additional_checksums: bool,
+    write_conflict_policy: Option<WriteConflictPolicy>,
+    write_reorder_window: u64,
+    max_parts_in_flight: usize,
+    /// Limits the parts in flight across all appends
+    parts_in_flight: Arc<Semaphore>,
+    runtime: PartRuntime,
+    /// The most to copy into each part when appending to an existing object
+    max_copy_part_size: u64,
+    /// The keys we're uploading to, so their multipart uploads aren't cleaned up
+    uploads: Arc<UploadTracker>,
 }
 
 #[derive(Debug, Error)]
@@ -38,30 +117,82 @@ pub enum UploadPutError<S, C> {
     SseCorruptedError(#[from] SseCorruptedError),
 }
 
-impl<Client: ObjectClient> Uploader<Client> {
-    /// Create a new [Uploader] that will make requests to the given client.
-    pub fn new(
+impl<Client: ObjectClient + Send + Sync + 'static> Uploader<Client> {
+    /// Create a new [Uploader] that will make requests to the given client, and upload the parts of
+    /// appends in the background on `runtime`.
+    ///
+    /// Uploads accept writes up to `write_reorder_window` bytes ahead of the next sequential
+    /// offset, and hold them in memory until the writes before them arrive.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<Runtime>(
         client: Arc<Client>,
+        runtime: Runtime,
         storage_class: Option<String>,
         server_side_encryption: ServerSideEncryption,
         use_additional_checksums: bool,
-    ) -> Self {
+        write_conflict_policy: Option<WriteConflictPolicy>,
+        write_reorder_window: u64,
+        concurrency: AppendUploadConcurrency,
+    ) -> Self
+    where
+        Runtime: Spawn + Send + Sync + 'static,
+    {
         let inner = UploaderInner {
             client,
             storage_class,
             server_side_encryption,
             use_additional_checksums,
+            write_conflict_policy,
+            write_reorder_window,
+            max_parts_in_flight: concurrency.per_file.max(1),
+            parts_in_flight: Arc::new(Semaphore::new(concurrency.total)),
+            runtime: PartRuntime(Arc::new(runtime)),
+            max_copy_part_size: MAX_S3_UPLOAD_PART_COPY_SIZE,
+            uploads: Default::default(),
         };
         Self { inner: Arc::new(inner) }
     }
 
     /// Start a new put request to the specified object.
+    ///
+    /// `existing_etag` is the ETag of the object this upload replaces, or [None] if the object did
+    /// not exist when the file was opened. It is used to detect conflicting writes on completion.
     pub async fn put(
         &self,
         bucket: &str,
         key: &str,
+        existing_etag: Option<String>,
     ) -> Result<UploadRequest<Client>, UploadPutError<PutObjectError, Client::ClientError>> {
-        UploadRequest::new(Arc::clone(&self.inner), bucket, key).await
+        UploadRequest::new(Arc::clone(&self.inner), bucket, key, existing_etag).await
+    }
+
+    /// Start a new request that appends to the existing object, which must be `existing_size` bytes
+    /// long. Writes to the returned request start at offset `existing_size`.
+    ///
+    /// Large objects are copied into the first parts of a multipart upload on the server side.
+    /// Objects smaller than the minimum part size can't be, so they are downloaded and written
+    /// back at the start of a new upload instead. Either way, only the version of the object with
+    /// ETag `existing_etag` is used, so the append fails if the ETag isn't known or the object has
+    /// since been replaced.
+    pub async fn append(
+        &self,
+        bucket: &str,
+        key: &str,
+        existing_size: u64,
+        existing_etag: Option<String>,
+    ) -> Result<UploadRequest<Client>, UploadAppendError<Client::ClientError>> {
+        UploadRequest::new_append(Arc::clone(&self.inner), bucket, key, existing_size, existing_etag).await
+    }
+
+    /// Create an [IncompleteUploadCleaner] for the multipart uploads under each `(bucket, prefix)`
+    /// in `prefixes`, which aborts the ones still incomplete after `max_age` unless this uploader
+    /// is still writing to them.
+    pub fn incomplete_upload_cleaner(
+        &self,
+        prefixes: Vec<(String, String)>,
+        max_age: Duration,
+    ) -> IncompleteUploadCleaner<Client> {
+        IncompleteUploadCleaner::new(self.inner.client.clone(), self.inner.uploads.clone(), prefixes, max_age)
     }
 
     #[cfg(test)]
@@ -71,6 +202,46 @@ impl<Client: ObjectClient> Uploader<Client> {
             .server_side_encryption
             .corrupt_data(sse_type, sse_kms_key_id)
     }
+
+    #[cfg(test)]
+    fn set_max_copy_part_size(&mut self, max_copy_part_size: u64) {
+        std::sync::Arc::get_mut(&mut self.inner).unwrap().max_copy_part_size = max_copy_part_size;
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum UploadAppendError<C: std::error::Error + Send + Sync + 'static> {
+    #[error("put request creation failed")]
+    PutFailed(#[from] UploadPutError<PutObjectError, C>),
+    #[error("multipart upload request failed")]
+    MultipartUploadFailed(#[from] ObjectClientError<MultipartUploadError, C>),
+    #[error("failed to read the existing object")]
+    GetFailed(#[from] ObjectClientError<GetObjectError, C>),
+    #[error("failed to write the existing object to the new upload")]
+    WriteFailed(#[from] UploadWriteError<UploadRequestError<C>>),
+    #[error("object of {size} bytes is too big to append to")]
+    ObjectTooBig { size: u64 },
+    #[error("the ETag of the object to append to is not known")]
+    MissingETag,
+    #[error("SSE settings corrupted")]
+    SseCorruptedError(#[from] SseCorruptedError),
+}
+
+/// An error from one of the S3 requests that make up an upload.
+#[derive(Debug, Error)]
+pub enum UploadRequestError<C: std::error::Error + Send + Sync + 'static> {
+    #[error("put object request failed")]
+    PutObject(#[from] ObjectClientError<PutObjectError, C>),
+    #[error("multipart upload request failed")]
+    MultipartUpload(#[from] ObjectClientError<MultipartUploadError, C>),
+}
+
+#[derive(Debug, Error)]
+pub enum UploadMetadataError<C: std::error::Error + Send + Sync + 'static> {
+    #[error("object metadata can only be changed before any data is written")]
+    AlreadyStarted,
+    #[error("put request creation failed")]
+    PutFailed(#[from] ObjectClientError<PutObjectError, C>),
 }
 
 #[derive(Debug, Error, Clone)]
@@ -81,29 +252,65 @@ pub enum UploadWriteError<E: std::error::Error> {
     #[error("out of order write is NOT supported by Mountpoint, aborting the upload; expected offset {expected_offset:?} but got {write_offset:?}")]
     OutOfOrderWrite { write_offset: u64, expected_offset: u64 },
 
+    #[error("write at offset {write_offset:?} overlaps an out of order write buffered at offset {buffered_offset:?}, aborting the upload")]
+    OverlapsBufferedWrite { write_offset: u64, buffered_offset: u64 },
+
     #[error("object exceeded maximum upload size of {maximum_size} bytes")]
     ObjectTooBig { maximum_size: usize },
 }
 
+#[derive(Debug, Error)]
+pub enum UploadCompleteError<PutError: std::error::Error, HeadError: std::error::Error> {
+    #[error("put request failed")]
+    PutRequestFailed(#[source] PutError),
+
+    #[error("failed to check for conflicting writes")]
+    ConflictCheckFailed(#[source] HeadError),
+
+    #[error("object was replaced in S3 while it was being written; expected ETag {expected:?} but found {actual:?}")]
+    Conflict { expected: Option<String>, actual: String },
+
+    #[error(
+        "out of order write at offset {write_offset:?} was never preceded by a write at offset {expected_offset:?}"
+    )]
+    MissingWrite { write_offset: u64, expected_offset: u64 },
+}
+
+type UploadRequestCompleteError<Client> = UploadCompleteError<RequestError<Client>, HeadRequestError<Client>>;
+
 /// Manages the upload of an object to S3.
 ///
-/// Wraps a PutObject request and enforces sequential writes.
+/// Wraps a PutObject request, or a multipart upload when appending to an existing object, and
+/// enforces sequential writes. Writes that arrive slightly ahead of the next sequential offset are
+/// buffered until the writes before them arrive.
 pub struct UploadRequest<Client: ObjectClient> {
+    client: Arc<Client>,
     bucket: String,
     key: String,
+    params: PutObjectParams,
     next_request_offset: u64,
+    /// How far ahead of `next_request_offset` a write can end and still be buffered
+    reorder_window: u64,
+    /// Writes that arrived ahead of `next_request_offset`, by offset. They never overlap.
+    pending_writes: BTreeMap<u64, Vec<u8>>,
     hasher: Hasher,
-    request: Client::PutObjectRequest,
-    maximum_upload_size: Option<usize>,
+    request: UploadRequestKind<Client>,
+    maximum_upload_size: Option<u64>,
     sse: ServerSideEncryption,
+    conflict_check: Option<ConflictCheck<Client>>,
+    /// Keeps the [IncompleteUploadCleaner] away from this upload until it's finished
+    _writing: WritingGuard,
 }
 
-impl<Client: ObjectClient> UploadRequest<Client> {
-    async fn new(
-        inner: Arc<UploaderInner<Client>>,
-        bucket: &str,
-        key: &str,
-    ) -> Result<UploadRequest<Client>, UploadPutError<PutObjectError, Client::ClientError>> {
+enum UploadRequestKind<Client: ObjectClient> {
+    /// A PutObject request that uploads the whole object
+    Put(Client::PutObjectRequest),
+    /// A multipart upload that starts with a copy of the existing object
+    Append(AppendRequest<Client>),
+}
+
+impl<Client: ObjectClient + Send + Sync + 'static> UploadRequest<Client> {
+    fn put_params(inner: &UploaderInner<Client>) -> Result<PutObjectParams, SseCorruptedError> {
         let mut params = PutObjectParams::new();
 
         if inner.use_additional_checksums {
@@ -122,18 +329,102 @@ impl<Client: ObjectClient> UploadRequest<Client> {
         let (sse_type, key_id) = inner.server_side_encryption.clone().into_inner()?;
         params = params.server_side_encryption(sse_type);
         params = params.ssekms_key_id(key_id);
+        Ok(params)
+    }
 
+    async fn new(
+        inner: Arc<UploaderInner<Client>>,
+        bucket: &str,
+        key: &str,
+        existing_etag: Option<String>,
+    ) -> Result<UploadRequest<Client>, UploadPutError<PutObjectError, Client::ClientError>> {
+        let params = Self::put_params(&inner)?;
+        let writing = inner.uploads.start_writing(bucket, key);
         let request = inner.client.put_object(bucket, key, &params).await?;
-        let maximum_upload_size = inner.client.part_size().map(|ps| ps * MAX_S3_MULTIPART_UPLOAD_PARTS);
+        let maximum_upload_size = inner
+            .client
+            .write_part_size()
+            .map(|ps| (ps * MAX_S3_MULTIPART_UPLOAD_PARTS) as u64);
+        let conflict_check = inner.write_conflict_policy.map(|policy| ConflictCheck {
+            client: inner.client.clone(),
+            policy,
+            existing_etag,
+        });
 
         Ok(Self {
+            client: inner.client.clone(),
             bucket: bucket.to_owned(),
             key: key.to_owned(),
+            params,
             next_request_offset: 0,
+            reorder_window: inner.write_reorder_window,
+            pending_writes: BTreeMap::new(),
             hasher: Hasher::new(),
-            request,
+            request: UploadRequestKind::Put(request),
             maximum_upload_size,
             sse: inner.server_side_encryption.clone(),
+            conflict_check,
+            _writing: writing,
+        })
+    }
+
+    async fn new_append(
+        inner: Arc<UploaderInner<Client>>,
+        bucket: &str,
+        key: &str,
+        existing_size: u64,
+        existing_etag: Option<String>,
+    ) -> Result<UploadRequest<Client>, UploadAppendError<Client::ClientError>> {
+        let Some(existing_etag) = existing_etag else {
+            return Err(UploadAppendError::MissingETag);
+        };
+        let if_match = ETag::from_str(&existing_etag).expect("ETag parsing is infallible");
+        if existing_size < MIN_S3_MULTIPART_UPLOAD_PART_SIZE {
+            debug!(key, existing_size, "appending to small object by uploading it again");
+            let mut request = Self::new(inner.clone(), bucket, key, Some(existing_etag)).await?;
+            let get_object_result = inner
+                .client
+                .get_object(bucket, key, &GetObjectParams::new().if_match(Some(if_match)))
+                .await?;
+            pin_mut!(get_object_result);
+            while let Some((offset, body)) = get_object_result.next().await.transpose()? {
+                request.write(offset as i64, &body).await?;
+            }
+            return Ok(request);
+        }
+
+        let copy_ranges = copy_part_ranges(existing_size, inner.max_copy_part_size);
+        if copy_ranges.len() >= MAX_S3_MULTIPART_UPLOAD_PARTS {
+            return Err(UploadAppendError::ObjectTooBig { size: existing_size });
+        }
+
+        let params = Self::put_params(&inner)?;
+        let part_size = inner.client.write_part_size().unwrap_or(DEFAULT_APPEND_PART_SIZE);
+        // The existing object takes up the first parts, leaving the rest for new data.
+        let maximum_upload_size =
+            existing_size + (part_size * (MAX_S3_MULTIPART_UPLOAD_PARTS - copy_ranges.len())) as u64;
+        let writing = inner.uploads.start_writing(bucket, key);
+        let request = AppendRequest::new(&inner, bucket, key, copy_ranges, if_match, part_size, &params).await?;
+        let conflict_check = inner.write_conflict_policy.map(|policy| ConflictCheck {
+            client: inner.client.clone(),
+            policy,
+            existing_etag: Some(existing_etag),
+        });
+
+        Ok(Self {
+            client: inner.client.clone(),
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            params,
+            next_request_offset: existing_size,
+            reorder_window: inner.write_reorder_window,
+            pending_writes: BTreeMap::new(),
+            hasher: Hasher::new(),
+            request: UploadRequestKind::Append(request),
+            maximum_upload_size: Some(maximum_upload_size),
+            sse: inner.server_side_encryption.clone(),
+            conflict_check,
+            _writing: writing,
         })
     }
 
@@ -141,37 +432,144 @@ impl<Client: ObjectClient> UploadRequest<Client> {
         self.next_request_offset
     }
 
-    pub async fn write(
+    /// The ID of the multipart upload, if it's known. The CRT creates the multipart upload for a
+    /// PutObject request internally and doesn't expose its ID.
+    pub fn upload_id(&self) -> Option<&str> {
+        match &self.request {
+            UploadRequestKind::Put(_) => None,
+            UploadRequestKind::Append(request) => Some(&request.upload_id),
+        }
+    }
+
+    /// The largest object this upload can create, if it's limited
+    pub fn maximum_size(&self) -> Option<u64> {
+        self.maximum_upload_size
+    }
+
+    /// The user-defined metadata the object will be created with
+    pub fn object_metadata(&self) -> &HashMap<String, String> {
+        &self.params.object_metadata
+    }
+
+    /// Replace the user-defined metadata the object will be created with.
+    ///
+    /// Metadata is sent when the upload starts, so this restarts the PutObject request, and fails if
+    /// any data has already been written to it.
+    pub async fn set_object_metadata(
         &mut self,
-        offset: i64,
-        data: &[u8],
-    ) -> Result<usize, UploadWriteError<PutRequestError<Client>>> {
+        object_metadata: HashMap<String, String>,
+    ) -> Result<(), UploadMetadataError<Client::ClientError>> {
+        if !matches!(self.request, UploadRequestKind::Put(_)) || self.next_request_offset > 0 {
+            return Err(UploadMetadataError::AlreadyStarted);
+        }
+        let params = self.params.clone().object_metadata(object_metadata);
+        let request = self.client.put_object(&self.bucket, &self.key, &params).await?;
+        // Dropping the old request aborts it
+        self.request = UploadRequestKind::Put(request);
+        self.params = params;
+        Ok(())
+    }
+
+    pub async fn write(&mut self, offset: i64, data: &[u8]) -> Result<usize, UploadWriteError<RequestError<Client>>> {
         let next_offset = self.next_request_offset;
-        if offset != next_offset as i64 {
-            return Err(UploadWriteError::OutOfOrderWrite {
-                write_offset: offset as u64,
-                expected_offset: next_offset,
-            });
+        let out_of_order = UploadWriteError::OutOfOrderWrite {
+            write_offset: offset as u64,
+            expected_offset: next_offset,
+        };
+        if offset < next_offset as i64 {
+            return Err(out_of_order);
         }
+        let offset = offset as u64;
+        let end = offset + data.len() as u64;
         if let Some(maximum_size) = self.maximum_upload_size {
-            if next_offset + data.len() as u64 > maximum_size as u64 {
-                return Err(UploadWriteError::ObjectTooBig { maximum_size });
+            if end > maximum_size {
+                return Err(UploadWriteError::ObjectTooBig {
+                    maximum_size: maximum_size as usize,
+                });
+            }
+        }
+
+        // Writes must not overlap writes we're already holding. For a write at the next offset,
+        // that would also move the next offset past the held write, so it would never be written.
+        let overlapping = self
+            .pending_writes
+            .range(..offset)
+            .next_back()
+            .filter(|(previous, pending)| *previous + pending.len() as u64 > offset)
+            .or_else(|| {
+                self.pending_writes
+                    .range(offset..)
+                    .next()
+                    .filter(|(next, _)| **next < end)
+            });
+        if let Some((&buffered_offset, _)) = overlapping {
+            return Err(UploadWriteError::OverlapsBufferedWrite {
+                write_offset: offset,
+                buffered_offset,
+            });
+        }
+
+        if offset > next_offset {
+            // Hold on to writes a little ahead of the next offset
+            if end > next_offset + self.reorder_window {
+                return Err(out_of_order);
             }
+            trace!(key = self.key, offset, next_offset, "buffering out of order write");
+            self.pending_writes.insert(offset, data.to_vec());
+            return Ok(data.len());
+        }
+
+        self.write_in_order(data).await?;
+        while let Some(pending) = self.pending_writes.remove(&self.next_request_offset) {
+            self.write_in_order(&pending).await?;
         }
+        Ok(data.len())
+    }
 
+    /// Write `data` at the next offset of the upload.
+    async fn write_in_order(&mut self, data: &[u8]) -> Result<(), UploadWriteError<RequestError<Client>>> {
         self.hasher.update(data);
-        self.request.write(data).await?;
+        match &mut self.request {
+            UploadRequestKind::Put(request) => request.write(data).await.map_err(UploadRequestError::from)?,
+            UploadRequestKind::Append(request) => request.write(data).await.map_err(UploadRequestError::from)?,
+        }
         self.next_request_offset += data.len() as u64;
-        Ok(data.len())
+        Ok(())
     }
 
-    pub async fn complete(self) -> Result<PutObjectResult, PutRequestError<Client>> {
-        let size = self.size();
-        let checksum = self.hasher.finalize();
-        let result = self
-            .request
-            .review_and_complete(move |review| verify_checksums(review, size, checksum))
-            .await?;
+    pub async fn complete(self) -> Result<PutObjectResult, UploadRequestCompleteError<Client>> {
+        if let Some(&write_offset) = self.pending_writes.keys().next() {
+            // There's a gap before the buffered writes, so the object would be incomplete.
+            let expected_offset = self.next_request_offset;
+            self.abort().await;
+            return Err(UploadCompleteError::MissingWrite {
+                write_offset,
+                expected_offset,
+            });
+        }
+        let conflict_check_result = match &self.conflict_check {
+            Some(conflict_check) => conflict_check.check(&self.bucket, &self.key).await,
+            None => Ok(()),
+        };
+        if let Err(e) = conflict_check_result {
+            // Abort the upload to leave the remote object as it is.
+            self.abort().await;
+            return Err(e);
+        }
+        let result = match self.request {
+            UploadRequestKind::Put(request) => {
+                let size = self.next_request_offset;
+                let checksum = self.hasher.finalize();
+                request
+                    .review_and_complete(move |review| verify_checksums(review, size, checksum))
+                    .await
+                    .map_err(|e| UploadCompleteError::PutRequestFailed(e.into()))?
+            }
+            UploadRequestKind::Append(request) => request
+                .complete()
+                .await
+                .map_err(|e| UploadCompleteError::PutRequestFailed(e.into()))?,
+        };
         if let Err(err) = self
             .sse
             .verify_response(result.sse_type.as_deref(), result.sse_kms_key_id.as_deref())
@@ -186,6 +584,15 @@ impl<Client: ObjectClient> UploadRequest<Client> {
         }
         Ok(result)
     }
+
+    /// Abandon the upload, leaving the remote object as it is.
+    pub async fn abort(self) {
+        match self.request {
+            // Dropping a PutObject request aborts it.
+            UploadRequestKind::Put(_) => {}
+            UploadRequestKind::Append(request) => request.abort().await,
+        }
+    }
 }
 
 impl<Client: ObjectClient> Debug for UploadRequest<Client> {
@@ -199,6 +606,278 @@ impl<Client: ObjectClient> Debug for UploadRequest<Client> {
     }
 }
 
+/// A multipart upload that appends to an existing object. The first parts are server-side copies of
+/// the existing object, and new data is buffered until there's enough for another part. Parts are
+/// uploaded in the background, with up to `max_in_flight` of them in flight at once.
+struct AppendRequest<Client: ObjectClient> {
+    client: Arc<Client>,
+    bucket: String,
+    key: String,
+    upload_id: String,
+    part_size: usize,
+    buffer: Vec<u8>,
+    /// Parts that have been uploaded, in the order they finished
+    parts: Vec<CompletedPart>,
+    next_part_number: usize,
+    in_flight: FuturesUnordered<UploadPartFuture<Client>>,
+    max_in_flight: usize,
+    /// Limits the parts in flight across all appends
+    limiter: Arc<Semaphore>,
+    runtime: PartRuntime,
+    /// Aborts the upload on S3. Taken once the upload is completed or aborted, and run in the
+    /// background if the request is dropped before then.
+    pending_abort: Option<BoxFuture<'static, ()>>,
+}
+
+type UploadPartFuture<Client> = BoxFuture<'static, Result<CompletedPart, MultipartUploadRequestError<Client>>>;
+
+impl<Client: ObjectClient + Send + Sync + 'static> AppendRequest<Client> {
+    async fn new(
+        inner: &UploaderInner<Client>,
+        bucket: &str,
+        key: &str,
+        copy_ranges: Vec<Range<u64>>,
+        if_match: ETag,
+        part_size: usize,
+        params: &PutObjectParams,
+    ) -> Result<Self, MultipartUploadRequestError<Client>> {
+        let client = inner.client.clone();
+        let upload_id = client.create_multipart_upload(bucket, key, params).await?.upload_id;
+        let pending_abort = Self::abort_upload(client.clone(), bucket, key, &upload_id);
+        let mut request = Self {
+            client,
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            upload_id,
+            part_size,
+            buffer: Vec::new(),
+            parts: Vec::new(),
+            next_part_number: copy_ranges.len() + 1,
+            in_flight: FuturesUnordered::new(),
+            max_in_flight: inner.max_parts_in_flight,
+            limiter: inner.parts_in_flight.clone(),
+            runtime: inner.runtime.clone(),
+            pending_abort: Some(pending_abort),
+        };
+
+        // Only copy the object we were asked to append to, even if it has since been replaced
+        let copies = copy_ranges.into_iter().enumerate().map(|(i, range)| {
+            let part_number = i + 1;
+            let params = UploadPartCopyParams::new()
+                .range(Some(range))
+                .if_match(Some(if_match.clone()));
+            let (client, upload_id) = (&request.client, &request.upload_id);
+            async move {
+                let result = client
+                    .upload_part_copy(bucket, key, upload_id, part_number, bucket, key, &params)
+                    .await?;
+                Ok(CompletedPart {
+                    part_number,
+                    etag: result.etag,
+                })
+            }
+        });
+        let result = futures::stream::iter(copies)
+            .buffered(request.max_in_flight)
+            .try_collect()
+            .await;
+        match result {
+            Ok(parts) => request.parts = parts,
+            Err(e) => {
+                request.abort().await;
+                return Err(e);
+            }
+        }
+        Ok(request)
+    }
+
+    /// A future that aborts the upload. Nothing is sent until it's polled. If the abort fails, the
+    /// [IncompleteUploadCleaner] can try again later.
+    fn abort_upload(client: Arc<Client>, bucket: &str, key: &str, upload_id: &str) -> BoxFuture<'static, ()> {
+        let (bucket, key, upload_id) = (bucket.to_owned(), key.to_owned(), upload_id.to_owned());
+        async move {
+            if let Err(error) = client.abort_multipart_upload(&bucket, &key, &upload_id).await {
+                warn!(?key, ?upload_id, ?error, "failed to abort multipart upload");
+            }
+        }
+        .boxed()
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), MultipartUploadRequestError<Client>> {
+        self.buffer.extend_from_slice(data);
+        while self.buffer.len() >= self.part_size {
+            let remaining = self.buffer.split_off(self.part_size);
+            let part = std::mem::replace(&mut self.buffer, remaining);
+            self.start_part(part).await?;
+        }
+        Ok(())
+    }
+
+    /// Start uploading the next part, once this append and all appends together have room for
+    /// another part in flight.
+    async fn start_part(&mut self, data: Vec<u8>) -> Result<(), MultipartUploadRequestError<Client>> {
+        while self.in_flight.len() >= self.max_in_flight {
+            self.finish_part().await?;
+        }
+
+        let part_number = self.next_part_number;
+        self.next_part_number += 1;
+        let client = self.client.clone();
+        let (bucket, key, upload_id) = (self.bucket.clone(), self.key.clone(), self.upload_id.clone());
+        let part = async move {
+            let result = client.upload_part(&bucket, &key, &upload_id, part_number, &data).await;
+            result.map(|result| CompletedPart {
+                part_number,
+                etag: result.etag,
+            })
+        };
+        // The permit is released as soon as the part finishes. Dropping the handle cancels the part,
+        // like dropping the part itself would.
+        let permit = self.limiter.acquire_arc().await;
+        let part = part.map(move |result| {
+            drop(permit);
+            result
+        });
+        let part = self.runtime.0.spawn_with_handle(part).unwrap().boxed();
+        self.in_flight.push(part);
+        metrics::gauge!("upload.parts_in_flight").increment(1.0);
+
+        // Collect any parts that already finished, so that failures are reported promptly
+        while let Some(Some(result)) = self.in_flight.next().now_or_never() {
+            self.part_finished(result)?;
+        }
+        Ok(())
+    }
+
+    /// Wait for one of the parts in flight to finish uploading
+    async fn finish_part(&mut self) -> Result<(), MultipartUploadRequestError<Client>> {
+        match self.in_flight.next().await {
+            Some(result) => self.part_finished(result),
+            None => Ok(()),
+        }
+    }
+
+    fn part_finished(
+        &mut self,
+        result: Result<CompletedPart, MultipartUploadRequestError<Client>>,
+    ) -> Result<(), MultipartUploadRequestError<Client>> {
+        metrics::gauge!("upload.parts_in_flight").decrement(1.0);
+        self.parts.push(result?);
+        Ok(())
+    }
+
+    async fn complete(mut self) -> Result<PutObjectResult, MultipartUploadRequestError<Client>> {
+        let mut result = Ok(());
+        if !self.buffer.is_empty() {
+            let part = std::mem::take(&mut self.buffer);
+            result = self.start_part(part).await;
+        }
+        while result.is_ok() && !self.in_flight.is_empty() {
+            result = self.finish_part().await;
+        }
+        if let Err(e) = result {
+            self.abort().await;
+            return Err(e);
+        }
+
+        self.parts.sort_by_key(|part| part.part_number);
+        let result = self
+            .client
+            .complete_multipart_upload(&self.bucket, &self.key, &self.upload_id, &self.parts)
+            .await;
+        match result {
+            Ok(result) => {
+                self.pending_abort = None;
+                Ok(result)
+            }
+            Err(e) => {
+                self.abort().await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn abort(mut self) {
+        // Parts still in flight can't be added to the upload once it's aborted
+        metrics::gauge!("upload.parts_in_flight").decrement(self.in_flight.len() as f64);
+        self.in_flight.clear();
+        if let Some(abort) = self.pending_abort.take() {
+            abort.await;
+        }
+    }
+}
+
+impl<Client: ObjectClient> Drop for AppendRequest<Client> {
+    fn drop(&mut self) {
+        metrics::gauge!("upload.parts_in_flight").decrement(self.in_flight.len() as f64);
+        self.in_flight.clear();
+        let Some(abort) = self.pending_abort.take() else {
+            return;
+        };
+        debug!(key = ?self.key, upload_id = ?self.upload_id, "aborting multipart upload that was dropped");
+        // We might be dropped inside an executor, so we can't block on the abort here.
+        let spawned = std::thread::Builder::new()
+            .name("abort-upload".to_owned())
+            .spawn(move || futures::executor::block_on(abort));
+        if let Err(error) = spawned {
+            warn!(
+                key = ?self.key,
+                upload_id = ?self.upload_id,
+                ?error,
+                "failed to abort dropped multipart upload, its parts will be left in the bucket"
+            );
+        }
+    }
+}
+
+/// Detects whether the object being uploaded was replaced in S3 since the file was opened.
+struct ConflictCheck<Client> {
+    client: Arc<Client>,
+    policy: WriteConflictPolicy,
+    existing_etag: Option<String>,
+}
+
+impl<Client: ObjectClient> ConflictCheck<Client> {
+    async fn check(&self, bucket: &str, key: &str) -> Result<(), UploadRequestCompleteError<Client>> {
+        let current_etag = match self.client.head_object(bucket, key).await {
+            Ok(result) => result.object.etag,
+            // Deleted objects don't conflict with our write, so we can go ahead and recreate them.
+            Err(ObjectClientError::ServiceError(HeadObjectError::NotFound)) => return Ok(()),
+            Err(e) => return Err(UploadCompleteError::ConflictCheckFailed(e)),
+        };
+        if self.existing_etag.as_deref() == Some(current_etag.as_str()) {
+            return Ok(());
+        }
+
+        metrics::counter!("upload.write_conflicts", "policy" => self.policy.as_str()).increment(1);
+        match self.policy {
+            WriteConflictPolicy::Overwrite => {
+                warn!(
+                    key,
+                    expected_etag = ?self.existing_etag,
+                    ?current_etag,
+                    "object was replaced in S3 while it was being written, overwriting it"
+                );
+                Ok(())
+            }
+            WriteConflictPolicy::Fail => Err(UploadCompleteError::Conflict {
+                expected: self.existing_etag.clone(),
+                actual: current_etag,
+            }),
+        }
+    }
+}
+
+/// Split an object of `size` bytes into ranges of at most `max_part_size` bytes, of as close to the
+/// same size as possible, to copy into the parts of a multipart upload.
+fn copy_part_ranges(size: u64, max_part_size: u64) -> Vec<Range<u64>> {
+    let count = size.div_ceil(max_part_size).max(1);
+    let part_size = size.div_ceil(count);
+    (0..count)
+        .map(|i| i * part_size..((i + 1) * part_size).min(size))
+        .collect()
+}
+
 fn verify_checksums(review: UploadReview, expected_size: u64, expected_checksum: Crc32c) -> bool {
     let mut uploaded_size = 0u64;
     let mut uploaded_checksum = Crc32c::new(0);
@@ -243,11 +922,14 @@ fn verify_checksums(review: UploadReview, expected_size: u64, expected_checksum:
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
+    use std::str::FromStr;
 
     use super::*;
+    use futures::executor::ThreadPool;
     use mountpoint_s3_client::{
         failure_client::countdown_failure_client,
-        mock_client::{MockClient, MockClientConfig, MockClientError},
+        mock_client::{MockClient, MockClientConfig, MockClientError, MockObject, Operation},
+        types::ETag,
     };
     use test_case::test_case;
 
@@ -262,8 +944,17 @@ mod tests {
             part_size: 32,
             ..Default::default()
         }));
-        let uploader = Uploader::new(client.clone(), None, ServerSideEncryption::default(), true);
-        let request = uploader.put(bucket, key).await.unwrap();
+        let uploader = Uploader::new(
+            client.clone(),
+            ThreadPool::builder().pool_size(1).create().unwrap(),
+            None,
+            ServerSideEncryption::default(),
+            true,
+            None,
+            0,
+            Default::default(),
+        );
+        let request = uploader.put(bucket, key, None).await.unwrap();
 
         assert!(!client.contains_key(key));
         assert!(client.is_upload_in_progress(key));
@@ -288,12 +979,16 @@ mod tests {
         }));
         let uploader = Uploader::new(
             client.clone(),
+            ThreadPool::builder().pool_size(1).create().unwrap(),
             Some(storage_class.to_owned()),
             ServerSideEncryption::default(),
             true,
+            None,
+            0,
+            Default::default(),
         );
 
-        let mut request = uploader.put(bucket, key).await.unwrap();
+        let mut request = uploader.put(bucket, key, None).await.unwrap();
 
         let data = b"foo";
         let mut offset = 0;
@@ -340,11 +1035,20 @@ mod tests {
             put_failures,
         ));
 
-        let uploader = Uploader::new(failure_client.clone(), None, ServerSideEncryption::default(), true);
+        let uploader = Uploader::new(
+            failure_client.clone(),
+            ThreadPool::builder().pool_size(1).create().unwrap(),
+            None,
+            ServerSideEncryption::default(),
+            true,
+            None,
+            0,
+            Default::default(),
+        );
 
         // First request fails on first write.
         {
-            let mut request = uploader.put(bucket, key).await.unwrap();
+            let mut request = uploader.put(bucket, key, None).await.unwrap();
 
             let data = b"foo";
             request.write(0, data).await.expect_err("first write should fail");
@@ -354,7 +1058,7 @@ mod tests {
 
         // Second request fails on complete (after one write).
         {
-            let mut request = uploader.put(bucket, key).await.unwrap();
+            let mut request = uploader.put(bucket, key, None).await.unwrap();
 
             let data = b"foo";
             _ = request.write(0, data).await.unwrap();
@@ -365,6 +1069,586 @@ mod tests {
         assert!(!client.contains_key(key));
     }
 
+    #[tokio::test]
+    async fn object_metadata_test() {
+        let bucket = "bucket";
+        let key = "hello";
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 32,
+            ..Default::default()
+        }));
+        let uploader = Uploader::new(
+            client.clone(),
+            ThreadPool::builder().pool_size(1).create().unwrap(),
+            None,
+            ServerSideEncryption::default(),
+            true,
+            None,
+            0,
+            Default::default(),
+        );
+        let mut request = uploader.put(bucket, key, None).await.unwrap();
+
+        let object_metadata = HashMap::from([("color".to_owned(), "blue".to_owned())]);
+        request.set_object_metadata(object_metadata.clone()).await.unwrap();
+        assert_eq!(request.object_metadata(), &object_metadata);
+        assert!(client.is_upload_in_progress(key));
+
+        request.write(0, b"foo").await.unwrap();
+        let err = request
+            .set_object_metadata(HashMap::new())
+            .await
+            .expect_err("can't change metadata after writing");
+        assert!(matches!(err, UploadMetadataError::AlreadyStarted));
+
+        request.complete().await.unwrap();
+        let head = client.head_object(bucket, key).await.unwrap();
+        assert_eq!(head.object_metadata, object_metadata);
+        assert!(!client.is_upload_in_progress(key));
+    }
+
+    #[test_case(WriteConflictPolicy::Overwrite, true; "overwrite replaced object")]
+    #[test_case(WriteConflictPolicy::Overwrite, false; "overwrite created object")]
+    #[test_case(WriteConflictPolicy::Fail, true; "fail on replaced object")]
+    #[test_case(WriteConflictPolicy::Fail, false; "fail on created object")]
+    #[tokio::test]
+    async fn write_conflict_test(policy: WriteConflictPolicy, existed_at_open: bool) {
+        let bucket = "bucket";
+        let key = "hello";
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 32,
+            ..Default::default()
+        }));
+        let uploader = Uploader::new(
+            client.clone(),
+            ThreadPool::builder().pool_size(1).create().unwrap(),
+            None,
+            ServerSideEncryption::default(),
+            true,
+            Some(policy),
+            0,
+            Default::default(),
+        );
+
+        // Uploads that don't race with anyone else complete regardless of the policy.
+        let existing_etag = if existed_at_open {
+            let etag = ETag::from_str("original").unwrap();
+            client.add_object(key, MockObject::from_bytes(b"original", etag.clone()));
+            Some(etag.into_inner())
+        } else {
+            None
+        };
+        let mut request = uploader.put(bucket, key, existing_etag.clone()).await.unwrap();
+        request.write(0, b"foo").await.unwrap();
+        request
+            .complete()
+            .await
+            .expect("upload without conflict should succeed");
+
+        // Now replace the object remotely while the upload is in progress.
+        if !existed_at_open {
+            client.remove_object(key);
+        }
+        let mut request = uploader.put(bucket, key, existing_etag).await.unwrap();
+        request.write(0, b"foo").await.unwrap();
+        let remote_etag = ETag::from_str("remote").unwrap();
+        client.add_object(key, MockObject::from_bytes(b"remote", remote_etag.clone()));
+
+        let result = request.complete().await;
+        let current_etag = client.head_object(bucket, key).await.unwrap().object.etag;
+        match policy {
+            WriteConflictPolicy::Overwrite => {
+                result.expect("overwrite policy should complete the upload");
+                assert_ne!(current_etag, remote_etag.as_str());
+            }
+            WriteConflictPolicy::Fail => {
+                let err = result.expect_err("fail policy should reject the upload");
+                assert!(matches!(err, UploadCompleteError::Conflict { .. }));
+                assert_eq!(current_etag, remote_etag.as_str());
+            }
+        }
+        assert!(!client.is_upload_in_progress(key));
+    }
+
+    #[test_case(100; "small object")]
+    #[test_case(6 * 1024 * 1024; "large object")]
+    #[tokio::test]
+    async fn append_test(existing_size: usize) {
+        const PART_SIZE: usize = 1024 * 1024;
+
+        let bucket = "bucket";
+        let key = "hello";
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: PART_SIZE,
+            ..Default::default()
+        }));
+        let uploader = Uploader::new(
+            client.clone(),
+            ThreadPool::builder().pool_size(1).create().unwrap(),
+            None,
+            ServerSideEncryption::default(),
+            true,
+            None,
+            0,
+            Default::default(),
+        );
+
+        let existing = MockObject::ramp(0xaa, existing_size, ETag::for_tests());
+        client.add_object(key, existing.clone());
+
+        let mut request = uploader
+            .append(bucket, key, existing_size as u64, Some(existing.etag().into_inner()))
+            .await
+            .unwrap();
+        assert_eq!(request.size(), existing_size as u64);
+        assert!(client.is_upload_in_progress(key));
+
+        request
+            .write(0, b"foo")
+            .await
+            .expect_err("writes must start at the end of the existing object");
+
+        // Write enough to fill a few parts
+        let data = vec![0xbb; PART_SIZE * 5 / 2];
+        request.write(existing_size as i64, &data).await.unwrap();
+        request.complete().await.unwrap();
+        assert!(!client.is_upload_in_progress(key));
+
+        let get = client.get_object(bucket, key, &GetObjectParams::new()).await.unwrap();
+        let actual = get.collect().await.unwrap();
+        let mut expected = existing.read(0, existing_size).into_vec();
+        expected.extend_from_slice(&data);
+        assert_eq!(&actual[..], &expected[..]);
+    }
+
+    #[tokio::test]
+    async fn incomplete_upload_cleaner_skips_open_appends() {
+        let bucket = "bucket";
+        let key = "dir/hello";
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        }));
+        let uploader = Uploader::new(
+            client.clone(),
+            ThreadPool::builder().pool_size(1).create().unwrap(),
+            None,
+            ServerSideEncryption::default(),
+            true,
+            None,
+            0,
+            Default::default(),
+        );
+        let existing = MockObject::ramp(0xaa, 16, ETag::for_tests());
+        client.add_object(key, existing.clone());
+
+        let request = uploader
+            .append(bucket, key, 16, Some(existing.etag().into_inner()))
+            .await
+            .unwrap();
+        // Left behind by an earlier process that crashed while appending
+        client
+            .create_multipart_upload(bucket, "dir/crashed", &Default::default())
+            .await
+            .unwrap();
+
+        // The cleaner aborts old uploads, but not one that's still being appended to
+        let cleaner = uploader.incomplete_upload_cleaner(vec![(bucket.to_owned(), "dir/".to_owned())], Duration::ZERO);
+        assert_eq!(cleaner.abort_old_uploads().await, 1);
+        assert!(client.is_upload_in_progress(key));
+        assert!(!client.is_upload_in_progress("dir/crashed"));
+
+        request.complete().await.unwrap();
+        assert!(!client.is_upload_in_progress(key));
+    }
+
+    #[tokio::test]
+    async fn append_copies_large_object_in_ranges() {
+        const PART_SIZE: usize = 1024 * 1024;
+
+        let bucket = "bucket";
+        let key = "hello";
+        let existing_size = 10 * PART_SIZE;
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: PART_SIZE,
+            ..Default::default()
+        }));
+        let mut uploader = Uploader::new(
+            client.clone(),
+            ThreadPool::builder().pool_size(1).create().unwrap(),
+            None,
+            ServerSideEncryption::default(),
+            true,
+            None,
+            0,
+            Default::default(),
+        );
+        uploader.set_max_copy_part_size(4 * PART_SIZE as u64);
+
+        let existing = MockObject::ramp(0xaa, existing_size, ETag::for_tests());
+        client.add_object(key, existing.clone());
+
+        let copy_counter = client.new_counter(Operation::UploadPartCopy);
+        let mut request = uploader
+            .append(bucket, key, existing_size as u64, Some(existing.etag().into_inner()))
+            .await
+            .unwrap();
+        assert_eq!(copy_counter.count(), 3);
+        // The copies use up three parts, leaving the rest for new data
+        let expected_maximum_size = existing_size + PART_SIZE * (MAX_S3_MULTIPART_UPLOAD_PARTS - 3);
+        assert_eq!(request.maximum_size(), Some(expected_maximum_size as u64));
+
+        let data = vec![0xbb; PART_SIZE * 3 / 2];
+        request.write(existing_size as i64, &data).await.unwrap();
+        request.complete().await.unwrap();
+
+        let get = client.get_object(bucket, key, &GetObjectParams::new()).await.unwrap();
+        let actual = get.collect().await.unwrap();
+        let mut expected = existing.read(0, existing_size).into_vec();
+        expected.extend_from_slice(&data);
+        assert_eq!(&actual[..], &expected[..]);
+    }
+
+    #[test_case(100; "small object")]
+    #[test_case(6 * 1024 * 1024; "large object")]
+    #[tokio::test]
+    async fn append_replaced_object(existing_size: usize) {
+        let bucket = "bucket";
+        let key = "hello";
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        }));
+        let uploader = Uploader::new(
+            client.clone(),
+            ThreadPool::builder().pool_size(1).create().unwrap(),
+            None,
+            ServerSideEncryption::default(),
+            true,
+            None,
+            0,
+            Default::default(),
+        );
+
+        let etag = ETag::from_str("original").unwrap();
+        let remote_etag = ETag::from_str("remote").unwrap();
+        client.add_object(key, MockObject::constant(0xaa, existing_size, remote_etag.clone()));
+
+        // The object was replaced since we looked it up, so we mustn't append to the new one
+        let err = uploader
+            .append(bucket, key, existing_size as u64, Some(etag.into_inner()))
+            .await
+            .expect_err("append should fail");
+        assert!(matches!(
+            err,
+            UploadAppendError::GetFailed(ObjectClientError::ServiceError(GetObjectError::PreconditionFailed))
+                | UploadAppendError::MultipartUploadFailed(ObjectClientError::ServiceError(
+                    MultipartUploadError::PreconditionFailed
+                ))
+        ));
+        assert!(!client.is_upload_in_progress(key));
+        let current_etag = client.head_object(bucket, key).await.unwrap().object.etag;
+        assert_eq!(current_etag, remote_etag.as_str());
+
+        let err = uploader
+            .append(bucket, key, existing_size as u64, None)
+            .await
+            .expect_err("append needs an ETag");
+        assert!(matches!(err, UploadAppendError::MissingETag));
+    }
+
+    #[tokio::test]
+    async fn append_concurrent_parts() {
+        const PART_SIZE: usize = 1024 * 1024;
+
+        let bucket = "bucket";
+        let existing_size = 6 * 1024 * 1024;
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: PART_SIZE,
+            ..Default::default()
+        }));
+        // Fewer parts in flight across all appends than for each append, so each append waits for
+        // the other's parts to finish even though the other isn't being written to
+        let uploader = Uploader::new(
+            client.clone(),
+            ThreadPool::builder().pool_size(1).create().unwrap(),
+            None,
+            ServerSideEncryption::default(),
+            true,
+            None,
+            0,
+            AppendUploadConcurrency { per_file: 4, total: 1 },
+        );
+
+        let mut requests = Vec::new();
+        for key in ["a", "b"] {
+            let existing = MockObject::constant(0xaa, existing_size, ETag::for_tests());
+            client.add_object(key, existing);
+            let etag = Some(ETag::for_tests().into_inner());
+            let request = uploader.append(bucket, key, existing_size as u64, etag).await.unwrap();
+            requests.push((key, request));
+        }
+
+        // Interleave the appends' writes, so their parts are in flight at the same time
+        let data = vec![0xbb; PART_SIZE / 2];
+        for i in 0..8 {
+            for (_, request) in requests.iter_mut() {
+                let offset = existing_size + i * data.len();
+                request.write(offset as i64, &data).await.unwrap();
+            }
+        }
+
+        for (key, request) in requests {
+            request.complete().await.unwrap();
+            let get = client.get_object(bucket, key, &GetObjectParams::new()).await.unwrap();
+            let actual = get.collect().await.unwrap();
+            assert_eq!(actual.len(), existing_size + 8 * data.len());
+            assert!(actual[..existing_size].iter().all(|&b| b == 0xaa));
+            assert!(actual[existing_size..].iter().all(|&b| b == 0xbb));
+        }
+    }
+
+    #[tokio::test]
+    async fn append_conflict_aborts_upload() {
+        let bucket = "bucket";
+        let key = "hello";
+        let existing_size = 6 * 1024 * 1024;
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        }));
+        let uploader = Uploader::new(
+            client.clone(),
+            ThreadPool::builder().pool_size(1).create().unwrap(),
+            None,
+            ServerSideEncryption::default(),
+            true,
+            Some(WriteConflictPolicy::Fail),
+            0,
+            Default::default(),
+        );
+
+        let etag = ETag::from_str("original").unwrap();
+        client.add_object(key, MockObject::constant(0xaa, existing_size, etag.clone()));
+
+        let mut request = uploader
+            .append(bucket, key, existing_size as u64, Some(etag.into_inner()))
+            .await
+            .unwrap();
+        request.write(existing_size as i64, b"foo").await.unwrap();
+
+        let remote_etag = ETag::from_str("remote").unwrap();
+        client.add_object(key, MockObject::from_bytes(b"remote", remote_etag.clone()));
+
+        let err = request.complete().await.expect_err("append should conflict");
+        assert!(matches!(err, UploadCompleteError::Conflict { .. }));
+        assert!(!client.is_upload_in_progress(key));
+        let current_etag = client.head_object(bucket, key).await.unwrap().object.etag;
+        assert_eq!(current_etag, remote_etag.as_str());
+    }
+
+    #[tokio::test]
+    async fn append_drop_aborts_upload() {
+        let bucket = "bucket";
+        let key = "hello";
+        let existing_size = 6 * 1024 * 1024;
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 1024 * 1024,
+            ..Default::default()
+        }));
+        let uploader = Uploader::new(
+            client.clone(),
+            ThreadPool::builder().pool_size(1).create().unwrap(),
+            None,
+            ServerSideEncryption::default(),
+            true,
+            None,
+            0,
+            Default::default(),
+        );
+
+        client.add_object(key, MockObject::constant(0xaa, existing_size, ETag::for_tests()));
+        let etag = Some(ETag::for_tests().into_inner());
+        let mut request = uploader.append(bucket, key, existing_size as u64, etag).await.unwrap();
+        request.write(existing_size as i64, b"foo").await.unwrap();
+        assert!(client.is_upload_in_progress(key));
+
+        // The abort runs in the background, so give it a while to finish
+        drop(request);
+        for _ in 0..100 {
+            if !client.is_upload_in_progress(key) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(!client.is_upload_in_progress(key));
+        assert_eq!(
+            client.head_object(bucket, key).await.unwrap().object.size,
+            existing_size as u64
+        );
+    }
+
+    #[tokio::test]
+    async fn reorder_write_test() {
+        let bucket = "bucket";
+        let key = "hello";
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 32,
+            ..Default::default()
+        }));
+        let uploader = Uploader::new(
+            client.clone(),
+            ThreadPool::builder().pool_size(1).create().unwrap(),
+            None,
+            ServerSideEncryption::default(),
+            true,
+            None,
+            10,
+            Default::default(),
+        );
+        let mut request = uploader.put(bucket, key, None).await.unwrap();
+
+        request.write(0, b"abc").await.unwrap();
+        // Writes ahead of the next offset are buffered if they end within the window
+        request.write(6, b"ghi").await.unwrap();
+        request.write(9, b"jkl").await.unwrap();
+        assert_eq!(request.size(), 3);
+        request
+            .write(12, b"mn")
+            .await
+            .expect_err("write ends beyond the window");
+        let err = request
+            .write(8, b"xy")
+            .await
+            .expect_err("write overlaps a buffered write");
+        assert!(matches!(
+            err,
+            UploadWriteError::OverlapsBufferedWrite {
+                write_offset: 8,
+                buffered_offset: 6
+            }
+        ));
+        request.write(1, b"xy").await.expect_err("write before the next offset");
+
+        // Filling the gap writes everything that was buffered
+        request.write(3, b"def").await.unwrap();
+        assert_eq!(request.size(), 12);
+        request.complete().await.unwrap();
+
+        let get = client.get_object(bucket, key, &GetObjectParams::new()).await.unwrap();
+        let actual = get.collect().await.unwrap();
+        assert_eq!(&actual[..], b"abcdefghijkl");
+    }
+
+    #[tokio::test]
+    async fn reorder_write_gap_test() {
+        let bucket = "bucket";
+        let key = "hello";
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 32,
+            ..Default::default()
+        }));
+        let uploader = Uploader::new(
+            client.clone(),
+            ThreadPool::builder().pool_size(1).create().unwrap(),
+            None,
+            ServerSideEncryption::default(),
+            true,
+            None,
+            10,
+            Default::default(),
+        );
+        let mut request = uploader.put(bucket, key, None).await.unwrap();
+
+        request.write(0, b"abc").await.unwrap();
+        request.write(6, b"ghi").await.unwrap();
+
+        let err = request.complete().await.expect_err("can't complete with a gap");
+        assert!(matches!(
+            err,
+            UploadCompleteError::MissingWrite {
+                write_offset: 6,
+                expected_offset: 3
+            }
+        ));
+        assert!(!client.contains_key(key));
+        assert!(!client.is_upload_in_progress(key));
+    }
+
+    #[test_case(3, b"defg"; "in order write runs into a buffered write")]
+    #[test_case(3, b"defghijk"; "in order write spans a buffered write")]
+    #[tokio::test]
+    async fn reorder_write_overlap_test(offset: i64, data: &[u8]) {
+        let bucket = "bucket";
+        let key = "hello";
+
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 32,
+            ..Default::default()
+        }));
+        let uploader = Uploader::new(
+            client.clone(),
+            ThreadPool::builder().pool_size(1).create().unwrap(),
+            None,
+            ServerSideEncryption::default(),
+            true,
+            None,
+            10,
+            Default::default(),
+        );
+        let mut request = uploader.put(bucket, key, None).await.unwrap();
+
+        request.write(0, b"abc").await.unwrap();
+        request.write(6, b"ghi").await.unwrap();
+
+        // The write would move the next offset past the buffered write, which would then never be
+        // written, so it's rejected instead
+        let err = request
+            .write(offset, data)
+            .await
+            .expect_err("write overlaps a buffered write");
+        assert!(matches!(
+            err,
+            UploadWriteError::OverlapsBufferedWrite {
+                write_offset: 3,
+                buffered_offset: 6
+            }
+        ));
+        assert_eq!(request.size(), 3);
+
+        // Writes that don't overlap can still fill the gap
+        request.write(3, b"def").await.unwrap();
+        assert_eq!(request.size(), 9);
+        request.complete().await.unwrap();
+
+        let get = client.get_object(bucket, key, &GetObjectParams::new()).await.unwrap();
+        let actual = get.collect().await.unwrap();
+        assert_eq!(&actual[..], b"abcdefghi");
+    }
+
     #[test_case(8000; "divisible by max size")]
     #[test_case(7000; "not divisible by max size")]
     #[test_case(320001; "single write too big")]
@@ -381,8 +1665,17 @@ mod tests {
             part_size: PART_SIZE,
             ..Default::default()
         }));
-        let uploader = Uploader::new(client.clone(), None, ServerSideEncryption::default(), true);
-        let mut request = uploader.put(bucket, key).await.unwrap();
+        let uploader = Uploader::new(
+            client.clone(),
+            ThreadPool::builder().pool_size(1).create().unwrap(),
+            None,
+            ServerSideEncryption::default(),
+            true,
+            None,
+            0,
+            Default::default(),
+        );
+        let mut request = uploader.put(bucket, key, None).await.unwrap();
 
         let successful_writes = PART_SIZE * MAX_S3_MULTIPART_UPLOAD_PARTS / write_size;
         let data = vec![0xaa; write_size];
@@ -412,16 +1705,20 @@ mod tests {
         let client = Arc::new(MockClient::new(Default::default()));
         let mut uploader = Uploader::new(
             client,
+            ThreadPool::builder().pool_size(1).create().unwrap(),
             None,
             ServerSideEncryption::new(Some("aws:kms".to_string()), Some("some_key_alias".to_string())),
             true,
+            None,
+            0,
+            Default::default(),
         );
         std::sync::Arc::<UploaderInner<MockClient>>::get_mut(&mut uploader.inner)
             .unwrap()
             .server_side_encryption
             .corrupt_data(sse_type_corrupted.map(String::from), key_id_corrupted.map(String::from));
         let err = uploader
-            .put("bucket", "hello")
+            .put("bucket", "hello", None)
             .await
             .expect_err("sse checksum must be checked");
         assert!(matches!(
@@ -443,10 +1740,21 @@ mod tests {
         }));
         let uploader = Uploader::new(
             client,
+            ThreadPool::builder().pool_size(1).create().unwrap(),
             None,
             ServerSideEncryption::new(Some("aws:kms".to_string()), Some("some_key".to_string())),
             true,
+            None,
+            0,
+            Default::default(),
         );
-        uploader.put(bucket, key).await.expect("put with sse should succeed");
+        let mut request = uploader
+            .put(bucket, key, None)
+            .await
+            .expect("put with sse should succeed");
+        request.write(0, b"data").await.unwrap();
+        let result = request.complete().await.expect("upload should succeed");
+        assert_eq!(result.sse_type.as_deref(), Some("aws:kms"));
+        assert_eq!(result.sse_kms_key_id.as_deref(), Some("some_key"));
     }
 }
@@ -0,0 +1,284 @@
+//! Cleanup of incomplete multipart uploads.
+//!
+//! S3 keeps (and bills for) the parts of a multipart upload until it's completed or aborted.
+//! Mountpoint aborts uploads that fail, but the abort itself can fail, for example if the network
+//! is down, and uploads are left behind entirely if Mountpoint is killed or crashes while writing.
+//! The CRT also creates multipart uploads for large PutObject requests without telling us their
+//! IDs. So rather than remembering the uploads it started, [IncompleteUploadCleaner] lists the
+//! incomplete uploads under the mounted prefix and aborts the ones started long enough ago that no
+//! writer should still be using them.
+//!
+//! [UploadTracker] records the keys this process is writing to right now, and uploads to those keys
+//! are never aborted, however old they are.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use mountpoint_s3_client::error::{MultipartUploadError, ObjectClientError};
+use mountpoint_s3_client::types::MultipartUploadInfo;
+use mountpoint_s3_client::ObjectClient;
+use time::OffsetDateTime;
+use tracing::{debug, info, warn};
+
+/// How many incomplete uploads to ask for in each ListMultipartUploads request
+const LIST_UPLOADS_PAGE_SIZE: usize = 1000;
+
+/// The keys this process has open write handles for, with how many are open for each.
+#[derive(Debug, Default)]
+pub struct UploadTracker {
+    writing: Mutex<HashMap<(String, String), usize>>,
+}
+
+impl UploadTracker {
+    /// Record that an upload to `key` has started. The returned guard records that it's finished
+    /// when it's dropped, whether the upload was completed or abandoned.
+    pub fn start_writing(self: &Arc<Self>, bucket: &str, key: &str) -> WritingGuard {
+        let id = (bucket.to_owned(), key.to_owned());
+        *self.writing.lock().unwrap().entry(id.clone()).or_default() += 1;
+        WritingGuard {
+            tracker: self.clone(),
+            id,
+        }
+    }
+
+    /// Whether this process is uploading to `key` right now
+    fn is_writing(&self, bucket: &str, key: &str) -> bool {
+        self.writing
+            .lock()
+            .unwrap()
+            .contains_key(&(bucket.to_owned(), key.to_owned()))
+    }
+}
+
+/// Marks a key as being written until it's dropped. See [UploadTracker::start_writing].
+#[derive(Debug)]
+pub struct WritingGuard {
+    tracker: Arc<UploadTracker>,
+    id: (String, String),
+}
+
+impl Drop for WritingGuard {
+    fn drop(&mut self) {
+        let mut writing = self.tracker.writing.lock().unwrap();
+        if let Some(count) = writing.get_mut(&self.id) {
+            *count -= 1;
+            if *count == 0 {
+                writing.remove(&self.id);
+            }
+        }
+    }
+}
+
+/// Aborts the incomplete multipart uploads under the mounted prefixes that are older than a maximum
+/// age, except for the ones this process is still writing.
+#[derive(Debug)]
+pub struct IncompleteUploadCleaner<Client> {
+    client: Arc<Client>,
+    uploads: Arc<UploadTracker>,
+    /// The bucket and prefix of each part of the file system
+    prefixes: Vec<(String, String)>,
+    max_age: Duration,
+}
+
+impl<Client: ObjectClient> IncompleteUploadCleaner<Client> {
+    /// Create a new [IncompleteUploadCleaner] for the uploads to keys under each `(bucket, prefix)`
+    /// in `prefixes`. Uploads are only aborted once they were started at least `max_age` ago, and
+    /// never while `uploads` says they're being written.
+    pub fn new(
+        client: Arc<Client>,
+        uploads: Arc<UploadTracker>,
+        prefixes: Vec<(String, String)>,
+        max_age: Duration,
+    ) -> Self {
+        Self {
+            client,
+            uploads,
+            prefixes,
+            max_age,
+        }
+    }
+
+    /// Abort every incomplete upload that's older than the maximum age, and return how many were
+    /// aborted. Uploads that fail to abort are found again next time.
+    pub async fn abort_old_uploads(&self) -> usize {
+        let mut aborted = 0;
+        for (bucket, prefix) in &self.prefixes {
+            aborted += self.abort_old_uploads_under(bucket, prefix).await;
+        }
+        debug!(aborted, "finished cleaning up incomplete uploads");
+        aborted
+    }
+
+    async fn abort_old_uploads_under(&self, bucket: &str, prefix: &str) -> usize {
+        let mut aborted = 0;
+        let (mut key_marker, mut upload_id_marker) = (None, None);
+        loop {
+            let result = self
+                .client
+                .list_multipart_uploads(
+                    bucket,
+                    key_marker.as_deref(),
+                    upload_id_marker.as_deref(),
+                    LIST_UPLOADS_PAGE_SIZE,
+                    prefix,
+                )
+                .await;
+            let page = match result {
+                Ok(page) => page,
+                Err(error) => {
+                    warn!(bucket, prefix, ?error, "failed to list incomplete multipart uploads");
+                    return aborted;
+                }
+            };
+            for upload in page.uploads {
+                if self.abort_if_old(bucket, upload).await {
+                    aborted += 1;
+                }
+            }
+            if page.next_key_marker.is_none() {
+                return aborted;
+            }
+            key_marker = page.next_key_marker;
+            upload_id_marker = page.next_upload_id_marker;
+        }
+    }
+
+    async fn abort_if_old(&self, bucket: &str, upload: MultipartUploadInfo) -> bool {
+        let age = OffsetDateTime::now_utc() - upload.initiated;
+        if age < self.max_age {
+            return false;
+        }
+        // An upload this old may still belong to a slow writer, like a file being appended to for
+        // days, so leave it alone until the file is closed
+        if self.uploads.is_writing(bucket, &upload.key) {
+            debug!(
+                key = upload.key,
+                upload_id = upload.upload_id,
+                "skipping upload in progress"
+            );
+            return false;
+        }
+        match self
+            .client
+            .abort_multipart_upload(bucket, &upload.key, &upload.upload_id)
+            .await
+        {
+            Ok(_) => {
+                info!(
+                    key = upload.key,
+                    upload_id = upload.upload_id,
+                    ?age,
+                    "aborted incomplete multipart upload"
+                );
+                metrics::counter!("upload.incomplete_uploads_aborted").increment(1);
+                true
+            }
+            // Someone else got to it first
+            Err(ObjectClientError::ServiceError(MultipartUploadError::NoSuchUpload)) => {
+                debug!(
+                    key = upload.key,
+                    upload_id = upload.upload_id,
+                    "incomplete multipart upload is gone"
+                );
+                false
+            }
+            Err(error) => {
+                warn!(
+                    key = upload.key,
+                    upload_id = upload.upload_id,
+                    ?error,
+                    "failed to abort incomplete multipart upload"
+                );
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mountpoint_s3_client::mock_client::{MockClient, MockClientConfig};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_abort_old_uploads() {
+        let bucket = "bucket";
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 32,
+            ..Default::default()
+        }));
+        let uploads = Arc::new(UploadTracker::default());
+        // Uploads left behind, for example by a process that crashed
+        for i in 0..3 {
+            client
+                .create_multipart_upload(bucket, &format!("dir/file{i}"), &Default::default())
+                .await
+                .unwrap();
+        }
+        // An upload outside the mounted prefix
+        client
+            .create_multipart_upload(bucket, "other/file", &Default::default())
+            .await
+            .unwrap();
+        // An upload that's still being written
+        let writing = uploads.start_writing(bucket, "dir/writing");
+        client
+            .create_multipart_upload(bucket, "dir/writing", &Default::default())
+            .await
+            .unwrap();
+
+        // Uploads that are too new are left alone
+        let prefixes = vec![(bucket.to_owned(), "dir/".to_owned())];
+        let cleaner = IncompleteUploadCleaner::new(
+            client.clone(),
+            uploads.clone(),
+            prefixes.clone(),
+            Duration::from_secs(3600),
+        );
+        assert_eq!(cleaner.abort_old_uploads().await, 0);
+        assert!(client.is_upload_in_progress("dir/file0"));
+
+        // Only old uploads under the prefix that aren't being written are aborted
+        let cleaner = IncompleteUploadCleaner::new(client.clone(), uploads.clone(), prefixes, Duration::ZERO);
+        assert_eq!(cleaner.abort_old_uploads().await, 3);
+        for i in 0..3 {
+            assert!(!client.is_upload_in_progress(&format!("dir/file{i}")));
+        }
+        assert!(client.is_upload_in_progress("other/file"));
+        assert!(client.is_upload_in_progress("dir/writing"));
+
+        // Once the file is closed, its upload can be cleaned up too
+        drop(writing);
+        assert_eq!(cleaner.abort_old_uploads().await, 1);
+        assert!(!client.is_upload_in_progress("dir/writing"));
+    }
+
+    #[tokio::test]
+    async fn test_abort_old_uploads_paginates() {
+        let bucket = "bucket";
+        let client = Arc::new(MockClient::new(MockClientConfig {
+            bucket: bucket.to_owned(),
+            part_size: 32,
+            ..Default::default()
+        }));
+        let count = LIST_UPLOADS_PAGE_SIZE + 5;
+        for i in 0..count {
+            client
+                .create_multipart_upload(bucket, &format!("file{i}"), &Default::default())
+                .await
+                .unwrap();
+        }
+
+        let uploads = Arc::new(UploadTracker::default());
+        let cleaner = IncompleteUploadCleaner::new(
+            client.clone(),
+            uploads,
+            vec![(bucket.to_owned(), String::new())],
+            Duration::ZERO,
+        );
+        assert_eq!(cleaner.abort_old_uploads().await, count);
+    }
+}
@@ -224,7 +224,8 @@ fn max_ttl_exceeded() -> Result<(), Box<dyn std::error::Error>> {
         .arg(cache_dir.path())
         .arg("--metadata-ttl")
         .arg(format!("{}", INVALID_TTL));
-    let error_message = "'--metadata-ttl <SECONDS>': TTL must not be greater than 3153600000s (~100 years)";
+    let error_message =
+        "'--metadata-ttl <SECONDS|indefinite|minimal>': TTL must not be greater than 3153600000s (~100 years)";
     cmd.assert().failure().stderr(predicate::str::contains(error_message));
 
     Ok(())
@@ -243,7 +244,7 @@ fn invalid_ttl() -> Result<(), Box<dyn std::error::Error>> {
         .arg(cache_dir.path())
         .arg("--metadata-ttl")
         .arg(INVALID_TTL_STRING);
-    let error_message = "'--metadata-ttl <SECONDS>': number too large to fit in target type";
+    let error_message = "'--metadata-ttl <SECONDS|indefinite|minimal>': number too large to fit in target type";
     cmd.assert().failure().stderr(predicate::str::contains(error_message));
 
     Ok(())
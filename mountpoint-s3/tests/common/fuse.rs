@@ -4,6 +4,7 @@ use std::path::Path;
 use std::sync::Arc;
 
 use fuser::{BackgroundSession, MountOption, Session};
+use futures::task::Spawn;
 use mountpoint_s3::data_cache::DataCache;
 use mountpoint_s3::fuse::S3FuseFilesystem;
 use mountpoint_s3::prefetch::{Prefetch, PrefetcherConfig};
@@ -16,6 +17,12 @@ use mountpoint_s3_crt::auth::credentials::{CredentialsProvider, CredentialsProvi
 use mountpoint_s3_crt::common::allocator::Allocator;
 use tempfile::TempDir;
 
+/// Direct access to the objects behind a test mount, bypassing the file system, so that tests can set
+/// up and check the bucket's contents. Keys are relative to the mount's prefix.
+///
+/// Implemented for the mock client and for S3 (or an S3-compatible store when `S3_ENDPOINT_URL` is
+/// set). A new backend can run the FUSE test suite by implementing this trait and a session
+/// constructor with the same signature as [mock_session::new].
 pub trait TestClient: Send {
     fn put_object(&mut self, key: &str, value: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
         self.put_object_params(key, value, PutObjectParams::default())
@@ -78,9 +85,10 @@ impl TestSessionConfig {
     }
 }
 
-fn create_fuse_session<Client, Prefetcher>(
+fn create_fuse_session<Client, Prefetcher, Runtime>(
     client: Client,
     prefetcher: Prefetcher,
+    runtime: Runtime,
     bucket: &str,
     prefix: &str,
     mount_dir: &Path,
@@ -89,6 +97,7 @@ fn create_fuse_session<Client, Prefetcher>(
 where
     Client: ObjectClient + Send + Sync + 'static,
     Prefetcher: Prefetch + Send + Sync + 'static,
+    Runtime: Spawn + Send + Sync + 'static,
 {
     let options = vec![
         MountOption::DefaultPermissions,
@@ -99,7 +108,7 @@ where
 
     let prefix = Prefix::new(prefix).expect("valid prefix");
     let session = Session::new(
-        S3FuseFilesystem::new(client, prefetcher, bucket, &prefix, filesystem_config),
+        S3FuseFilesystem::new(client, prefetcher, runtime, bucket, &prefix, filesystem_config),
         mount_dir,
         &options,
     )
@@ -137,10 +146,11 @@ pub mod mock_session {
         };
         let client = Arc::new(MockClient::new(client_config));
         let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
-        let prefetcher = default_prefetch(runtime, test_config.prefetcher_config);
+        let prefetcher = default_prefetch(runtime.clone(), test_config.prefetcher_config);
         let session = create_fuse_session(
             client.clone(),
             prefetcher,
+            runtime,
             BUCKET_NAME,
             &prefix,
             mount_dir.path(),
@@ -174,10 +184,11 @@ pub mod mock_session {
             };
             let client = Arc::new(MockClient::new(client_config));
             let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
-            let prefetcher = caching_prefetch(cache, runtime, test_config.prefetcher_config);
+            let prefetcher = caching_prefetch(cache, runtime.clone(), test_config.prefetcher_config);
             let session = create_fuse_session(
                 client.clone(),
                 prefetcher,
+                runtime,
                 BUCKET_NAME,
                 &prefix,
                 mount_dir.path(),
@@ -276,11 +287,13 @@ pub mod s3_session {
     use aws_sdk_s3::types::{ChecksumAlgorithm, GlacierJobParameters, RestoreRequest, Tier};
     use aws_sdk_s3::Client;
     use mountpoint_s3::prefetch::{caching_prefetch, default_prefetch};
-    use mountpoint_s3_client::config::{EndpointConfig, S3ClientConfig};
+    use mountpoint_s3_client::config::S3ClientConfig;
     use mountpoint_s3_client::types::{Checksum, PutObjectTrailingChecksums};
     use mountpoint_s3_client::S3CrtClient;
 
-    use crate::common::s3::{get_test_bucket_and_prefix, get_test_region, get_test_sdk_client, tokio_block_on};
+    use crate::common::s3::{
+        get_test_bucket_and_prefix, get_test_endpoint_config, get_test_region, get_test_sdk_client, tokio_block_on,
+    };
 
     /// Create a FUSE mount backed by a real S3 client
     pub fn new(test_name: &str, test_config: TestSessionConfig) -> (TempDir, BackgroundSession, TestClientBox) {
@@ -291,14 +304,15 @@ pub mod s3_session {
 
         let client_config = S3ClientConfig::default()
             .part_size(test_config.part_size)
-            .endpoint_config(EndpointConfig::new(&region))
+            .endpoint_config(get_test_endpoint_config(&region))
             .auth_config(test_config.auth_config);
         let client = S3CrtClient::new(client_config).unwrap();
         let runtime = client.event_loop_group();
-        let prefetcher = default_prefetch(runtime, test_config.prefetcher_config);
+        let prefetcher = default_prefetch(runtime.clone(), test_config.prefetcher_config);
         let session = create_fuse_session(
             client,
             prefetcher,
+            runtime,
             &bucket,
             &prefix,
             mount_dir.path(),
@@ -324,13 +338,14 @@ pub mod s3_session {
 
             let client_config = S3ClientConfig::default()
                 .part_size(test_config.part_size)
-                .endpoint_config(EndpointConfig::new(&region));
+                .endpoint_config(get_test_endpoint_config(&region));
             let client = S3CrtClient::new(client_config).unwrap();
             let runtime = client.event_loop_group();
-            let prefetcher = caching_prefetch(cache, runtime, test_config.prefetcher_config);
+            let prefetcher = caching_prefetch(cache, runtime.clone(), test_config.prefetcher_config);
             let session = create_fuse_session(
                 client,
                 prefetcher,
+                runtime,
                 &bucket,
                 &prefix,
                 mount_dir.path(),
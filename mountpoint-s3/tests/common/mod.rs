@@ -8,6 +8,7 @@ pub mod fuse;
 #[cfg(feature = "s3_tests")]
 pub mod s3;
 
+use async_trait::async_trait;
 use fuser::{FileAttr, FileType};
 use futures::executor::ThreadPool;
 use mountpoint_s3::fs::{DirectoryEntry, DirectoryReplier};
@@ -48,8 +49,8 @@ where
     Client: ObjectClient + Send + Sync + 'static,
 {
     let runtime = ThreadPool::builder().pool_size(1).create().unwrap();
-    let prefetcher = default_prefetch(runtime, Default::default());
-    S3Filesystem::new(client, prefetcher, bucket, prefix, config)
+    let prefetcher = default_prefetch(runtime.clone(), Default::default());
+    S3Filesystem::new(client, prefetcher, runtime, bucket, prefix, config)
 }
 
 #[track_caller]
@@ -67,8 +68,9 @@ pub struct DirectoryReply {
     pub entries: VecDeque<DirectoryEntry>,
 }
 
+#[async_trait]
 impl DirectoryReplier for &mut DirectoryReply {
-    fn add(&mut self, entry: DirectoryEntry) -> bool {
+    async fn add(&mut self, entry: DirectoryEntry) -> bool {
         if self.readdir_limit > 0 && !self.entries.is_empty() && self.entries.len() % self.readdir_limit == 0 {
             true
         } else {
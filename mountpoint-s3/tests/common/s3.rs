@@ -2,6 +2,9 @@ use aws_config::{BehaviorVersion, Region};
 use aws_sdk_s3::primitives::ByteStream;
 use aws_sdk_sts::config::Credentials;
 use futures::Future;
+use mountpoint_s3_client::config::{AddressingStyle, EndpointConfig};
+use mountpoint_s3_crt::common::allocator::Allocator;
+use mountpoint_s3_crt::common::uri::Uri;
 use rand::RngCore;
 use rand_chacha::rand_core::OsRng;
 
@@ -33,6 +36,23 @@ pub fn get_test_region() -> String {
     std::env::var("S3_REGION").expect("Set S3_REGION to run integration tests")
 }
 
+/// Optional endpoint for an S3-compatible store to run the integration tests against instead of S3.
+/// Requests to a custom endpoint use path-style addressing.
+pub fn get_test_endpoint_url() -> Option<String> {
+    std::env::var("S3_ENDPOINT_URL").ok()
+}
+
+/// The endpoint configuration for the CRT client under test, taking [get_test_endpoint_url] into account.
+pub fn get_test_endpoint_config(region: &str) -> EndpointConfig {
+    let endpoint_config = EndpointConfig::new(region);
+    match get_test_endpoint_url() {
+        Some(url) => endpoint_config
+            .endpoint(Uri::new_from_str(&Allocator::default(), url).expect("S3_ENDPOINT_URL should be a valid URL"))
+            .addressing_style(AddressingStyle::Path),
+        None => endpoint_config,
+    }
+}
+
 pub fn get_subsession_iam_role() -> String {
     std::env::var("S3_SUBSESSION_IAM_ROLE").expect("Set S3_SUBSESSION_IAM_ROLE to run integration tests")
 }
@@ -44,6 +64,10 @@ pub async fn get_test_sdk_client(region: &str) -> aws_sdk_s3::Client {
         .await;
     let mut s3_config = aws_sdk_s3::config::Builder::from(&sdk_config);
 
+    if let Some(url) = get_test_endpoint_url() {
+        s3_config = s3_config.endpoint_url(url).force_path_style(true);
+    }
+
     // TODO: remove when the Rust SDK supports S3 Express One Zone. For now, we force the SDK to
     // always use SigV4, because it doesn't yet know about the `sigv4-s3express` auth scheme.
     if cfg!(feature = "s3express_tests") {
@@ -1,14 +1,18 @@
 //! Manually implemented tests executing the FUSE protocol against [S3Filesystem]
 
+use async_trait::async_trait;
 use fuser::FileType;
 use libc::S_IFREG;
-use mountpoint_s3::fs::{CacheConfig, ToErrno, FUSE_ROOT_INODE};
+use mountpoint_s3::fs::{
+    AccessMode, CacheConfig, Caller, ControlDirectoryMode, DirectoryEntry, DirectoryReplier, IdentityMap,
+    ObjectVersionPolicy, OverlayDirectory, ReadPolicy, TimeToLive, ToErrno, WriteConflictPolicy, FUSE_ROOT_INODE,
+};
 use mountpoint_s3::prefix::Prefix;
 use mountpoint_s3::s3::S3Personality;
 use mountpoint_s3::S3FilesystemConfig;
 use mountpoint_s3_client::failure_client::countdown_failure_client;
 use mountpoint_s3_client::mock_client::{MockClient, MockClientConfig, MockClientError, MockObject, Operation};
-use mountpoint_s3_client::types::{ETag, RestoreStatus};
+use mountpoint_s3_client::types::{ETag, GetObjectParams, RestoreStatus};
 use mountpoint_s3_client::ObjectClient;
 use nix::unistd::{getgid, getuid};
 use rand::{Rng, SeedableRng};
@@ -20,6 +24,7 @@ use std::str::FromStr;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 use test_case::test_case;
+use time::macros::datetime;
 
 mod common;
 use common::{assert_attr, make_test_filesystem, make_test_filesystem_with_client, DirectoryReply, TestS3Filesystem};
@@ -178,6 +183,7 @@ async fn test_lookup_negative_cached() {
             serve_lookup_from_cache: true,
             dir_ttl: Duration::from_secs(600),
             file_ttl: Duration::from_secs(600),
+            negative_cache_ttl: Duration::from_secs(600),
             ..Default::default()
         },
         ..Default::default()
@@ -242,6 +248,87 @@ async fn test_lookup_negative_cached() {
     assert_eq!(list_counter.count(), 2);
 }
 
+#[test_case(Duration::ZERO, 3; "disabled")]
+#[test_case(Duration::from_secs(600), 1; "enabled")]
+#[tokio::test]
+async fn test_lookup_negative_cache_without_metadata_cache(negative_cache_ttl: Duration, expected_heads: u64) {
+    let fs_config = S3FilesystemConfig {
+        cache_config: CacheConfig {
+            serve_lookup_from_cache: false,
+            negative_cache_ttl,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(
+        "test_lookup_negative_cache_without_metadata_cache",
+        &Default::default(),
+        fs_config,
+    );
+
+    let head_counter = client.new_counter(Operation::HeadObject);
+
+    for _ in 0..3 {
+        let err = fs
+            .lookup(FUSE_ROOT_INODE, "libfoo.so".as_ref())
+            .await
+            .expect_err("should fail as no object exists");
+        assert_eq!(err.to_errno(), libc::ENOENT);
+    }
+    assert_eq!(head_counter.count(), expected_heads);
+
+    // Creating the file must not be hidden by the negative cache
+    let dentry = fs
+        .mknod(
+            FUSE_ROOT_INODE,
+            "libfoo.so".as_ref(),
+            libc::S_IFREG | libc::S_IRWXU,
+            0,
+            0,
+        )
+        .await
+        .unwrap();
+    let lookup = fs.lookup(FUSE_ROOT_INODE, "libfoo.so".as_ref()).await.unwrap();
+    assert_eq!(lookup.attr.ino, dentry.attr.ino);
+}
+
+#[test_case(Duration::ZERO, 3; "disabled")]
+#[test_case(Duration::from_secs(600), 1; "enabled")]
+#[tokio::test]
+async fn test_getattr_attr_cache(attr_cache_ttl: Duration, expected_heads: u64) {
+    let fs_config = S3FilesystemConfig {
+        cache_config: CacheConfig {
+            serve_lookup_from_cache: false,
+            dir_ttl: Duration::ZERO,
+            file_ttl: Duration::ZERO,
+            attr_cache_ttl,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_getattr_attr_cache", &Default::default(), fs_config);
+
+    client.add_object("file1.txt", MockObject::constant(0xa1, 15, ETag::for_tests()));
+
+    let entry = fs.lookup(FUSE_ROOT_INODE, "file1.txt".as_ref()).await.unwrap();
+    let ino = entry.attr.ino;
+
+    let head_counter = client.new_counter(Operation::HeadObject);
+    for _ in 0..3 {
+        let attr = fs.getattr(ino).await.unwrap();
+        assert_eq!(attr.attr.size, 15);
+        assert_eq!(attr.ttl, Duration::ZERO);
+    }
+    assert_eq!(head_counter.count(), expected_heads);
+
+    // Trying to change the attributes drops them from the cache, even though it fails
+    fs.setattr(ino, None, None, None, None, None, 0)
+        .await
+        .expect_err("can't modify attributes of remote files");
+    fs.getattr(ino).await.unwrap();
+    assert_eq!(head_counter.count(), expected_heads + 1);
+}
+
 #[tokio::test]
 async fn test_lookup_then_open_cached() {
     let fs_config = S3FilesystemConfig {
@@ -408,6 +495,316 @@ async fn test_unlink_cached() {
     assert_eq!(list_counter.count(), 2);
 }
 
+#[test_case(true; "cached")]
+#[test_case(false; "not cached")]
+#[tokio::test]
+async fn test_rename_into_place(cached: bool) {
+    const BUCKET_NAME: &str = "test_rename_into_place";
+    let fs_config = S3FilesystemConfig {
+        cache_config: CacheConfig {
+            serve_lookup_from_cache: cached,
+            dir_ttl: Duration::from_secs(600),
+            file_ttl: Duration::from_secs(600),
+            ..Default::default()
+        },
+        allow_delete: true,
+        allow_overwrite: true,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), fs_config);
+
+    client.add_object("dir/file.txt", MockObject::constant(0xa1, 15, ETag::for_tests()));
+    let dir_ino = fs.lookup(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap().attr.ino;
+    let old_ino = fs.lookup(dir_ino, "file.txt".as_ref()).await.unwrap().attr.ino;
+
+    // Write the new contents to a temporary file, like an editor would
+    let body = vec![0xb2; 30];
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let dentry = fs.mknod(dir_ino, ".file.txt.swp".as_ref(), mode, 0, 0).await.unwrap();
+    let tmp_ino = dentry.attr.ino;
+    let fh = fs
+        .open(tmp_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+    fs.write(tmp_ino, fh, 0, &body, 0, 0, None).await.unwrap();
+    fs.release(tmp_ino, fh, 0, None, false).await.unwrap();
+
+    // Then move it over the original file
+    let copy_counter = client.new_counter(Operation::CopyObject);
+    fs.rename(dir_ino, ".file.txt.swp".as_ref(), dir_ino, "file.txt".as_ref(), 0)
+        .await
+        .expect("rename should succeed");
+    assert_eq!(copy_counter.count(), 1);
+    assert!(!client.contains_key("dir/.file.txt.swp"));
+
+    let get = client
+        .get_object(BUCKET_NAME, "dir/file.txt", &GetObjectParams::new())
+        .await
+        .unwrap();
+    let actual = get.collect().await.unwrap();
+    assert_eq!(&actual[..], &body[..]);
+
+    // The kernel keeps using the renamed inode at its new name, so it moves there
+    let attr = fs
+        .getattr(tmp_ino)
+        .await
+        .expect("renamed inode should be at its new name")
+        .attr;
+    assert_eq!(attr.ino, tmp_ino);
+    assert_eq!(attr.size, body.len() as u64);
+
+    fs.lookup(dir_ino, ".file.txt.swp".as_ref())
+        .await
+        .expect_err("temporary file should be gone");
+    let entry = fs.lookup(dir_ino, "file.txt".as_ref()).await.unwrap();
+    assert_ne!(entry.attr.ino, old_ino);
+    assert_eq!(entry.attr.ino, tmp_ino);
+    assert_eq!(entry.attr.size, body.len() as u64);
+}
+
+#[tokio::test]
+async fn test_read_and_write_after_rename() {
+    const BUCKET_NAME: &str = "test_read_and_write_after_rename";
+    let fs_config = S3FilesystemConfig {
+        allow_delete: true,
+        allow_overwrite: true,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), fs_config);
+
+    client.add_object("a", MockObject::constant(0xa1, 15, ETag::for_tests()));
+    let ino = fs.lookup(FUSE_ROOT_INODE, "a".as_ref()).await.unwrap().attr.ino;
+
+    // Rename twice, so the inode has to follow both renames
+    fs.rename(FUSE_ROOT_INODE, "a".as_ref(), FUSE_ROOT_INODE, "b".as_ref(), 0)
+        .await
+        .expect("first rename should succeed");
+    fs.rename(FUSE_ROOT_INODE, "b".as_ref(), FUSE_ROOT_INODE, "c".as_ref(), 0)
+        .await
+        .expect("second rename should succeed");
+    assert!(!client.contains_key("a"));
+    assert!(!client.contains_key("b"));
+    assert!(client.contains_key("c"));
+
+    let attr = fs.getattr(ino).await.expect("inode should be at its new name").attr;
+    assert_eq!(attr.size, 15);
+    let entry = fs.lookup(FUSE_ROOT_INODE, "c".as_ref()).await.unwrap();
+    assert_eq!(entry.attr.ino, ino);
+
+    // Reads through the renamed inode get the object at its new key
+    let fh = fs.open(ino, libc::O_RDONLY, 0).await.unwrap().fh;
+    let data = fs.read(ino, fh, 0, 4096, 0, None).await.expect("read should succeed");
+    assert_eq!(&data[..], &[0xa1; 15][..]);
+    fs.release(ino, fh, 0, None, true).await.unwrap();
+
+    // Writes through the renamed inode upload to its new key
+    let body = vec![0xb2; 30];
+    let fh = fs.open(ino, libc::O_WRONLY | libc::O_TRUNC, 0).await.unwrap().fh;
+    fs.write(ino, fh, 0, &body, 0, 0, None).await.unwrap();
+    fs.release(ino, fh, 0, None, true).await.unwrap();
+    assert!(!client.contains_key("a"));
+    assert!(!client.contains_key("b"));
+
+    let get = client
+        .get_object(BUCKET_NAME, "c", &GetObjectParams::new())
+        .await
+        .unwrap();
+    let actual = get.collect().await.unwrap();
+    assert_eq!(&actual[..], &body[..]);
+}
+
+#[tokio::test]
+async fn test_write_after_fsync_and_rename() {
+    const BUCKET_NAME: &str = "test_write_after_fsync_and_rename";
+    let fs_config = S3FilesystemConfig {
+        allow_delete: true,
+        allow_overwrite: true,
+        s3_personality: S3Personality::ExpressOneZone,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), fs_config);
+
+    let mode = libc::S_IFREG | libc::S_IRWXU;
+    let ino = fs
+        .mknod(FUSE_ROOT_INODE, "a".as_ref(), mode, 0, 0)
+        .await
+        .unwrap()
+        .attr
+        .ino;
+    let fh = fs.open(ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0).await.unwrap().fh;
+    let first = b"first line\n";
+    fs.write(ino, fh, 0, first, 0, 0, None).await.unwrap();
+    fs.fsync(ino, fh, false)
+        .await
+        .expect("fsync should complete the upload");
+
+    // The upload is complete, so the file can be renamed while the handle is still open
+    fs.rename(FUSE_ROOT_INODE, "a".as_ref(), FUSE_ROOT_INODE, "b".as_ref(), 0)
+        .await
+        .expect("rename should succeed");
+
+    // Writing through the open handle continues the upload at the new key
+    let second = b"second line\n";
+    fs.write(ino, fh, first.len() as i64, second, 0, 0, None)
+        .await
+        .expect("writes should continue in a new upload");
+    fs.release(ino, fh, 0, None, true).await.unwrap();
+    assert!(!client.contains_key("a"));
+
+    let get = client
+        .get_object(BUCKET_NAME, "b", &GetObjectParams::new())
+        .await
+        .unwrap();
+    let actual = get.collect().await.unwrap();
+    assert_eq!(&actual[..], b"first line\nsecond line\n");
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_rename_noreplace() {
+    let fs_config = S3FilesystemConfig {
+        allow_delete: true,
+        allow_overwrite: true,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_rename_noreplace", &Default::default(), fs_config);
+
+    client.add_object("file.txt", MockObject::constant(0xa1, 15, ETag::for_tests()));
+    client.add_object("other.txt", MockObject::constant(0xb2, 5, ETag::for_tests()));
+    let err = fs
+        .rename(
+            FUSE_ROOT_INODE,
+            "other.txt".as_ref(),
+            FUSE_ROOT_INODE,
+            "file.txt".as_ref(),
+            libc::RENAME_NOREPLACE,
+        )
+        .await
+        .expect_err("rename with RENAME_NOREPLACE should not replace existing file");
+    assert_eq!(err.to_errno(), libc::EEXIST);
+
+    let err = fs
+        .rename(
+            FUSE_ROOT_INODE,
+            "other.txt".as_ref(),
+            FUSE_ROOT_INODE,
+            "file.txt".as_ref(),
+            libc::RENAME_EXCHANGE,
+        )
+        .await
+        .expect_err("RENAME_EXCHANGE is not supported");
+    assert_eq!(err.to_errno(), libc::EINVAL);
+}
+
+#[tokio::test]
+async fn test_rename_requires_allow_delete() {
+    let (client, fs) = make_test_filesystem(
+        "test_rename_requires_allow_delete",
+        &Default::default(),
+        Default::default(),
+    );
+
+    client.add_object("file.txt", MockObject::constant(0xa1, 15, ETag::for_tests()));
+    let err = fs
+        .rename(
+            FUSE_ROOT_INODE,
+            "file.txt".as_ref(),
+            FUSE_ROOT_INODE,
+            "new.txt".as_ref(),
+            0,
+        )
+        .await
+        .expect_err("rename should fail without allow_delete");
+    assert_eq!(err.to_errno(), libc::EPERM);
+    assert!(client.contains_key("file.txt"));
+    assert!(!client.contains_key("new.txt"));
+}
+
+#[tokio::test]
+async fn test_symlink() {
+    let fs_config = S3FilesystemConfig {
+        allow_symlinks: true,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_symlink", &Default::default(), fs_config);
+
+    let entry = fs
+        .symlink(FUSE_ROOT_INODE, "link".as_ref(), "target/file.txt".as_ref())
+        .await
+        .expect("symlink should succeed");
+    assert_eq!(entry.attr.kind, FileType::Symlink);
+    assert_eq!(entry.attr.size, "target/file.txt".len() as u64);
+    assert_eq!(entry.attr.perm, 0o777);
+    assert!(client.contains_key("link"));
+
+    let target = fs.readlink(entry.attr.ino).await.expect("readlink should succeed");
+    assert_eq!(target, "target/file.txt");
+
+    let lookup = fs.lookup(FUSE_ROOT_INODE, "link".as_ref()).await.unwrap();
+    assert_eq!(lookup.attr.kind, FileType::Symlink);
+    let target = fs.readlink(lookup.attr.ino).await.expect("readlink should succeed");
+    assert_eq!(target, "target/file.txt");
+
+    let err = fs
+        .open(lookup.attr.ino, libc::O_RDONLY, 0)
+        .await
+        .expect_err("can't open a symlink");
+    assert_eq!(err.to_errno(), libc::ELOOP);
+}
+
+#[tokio::test]
+async fn test_symlink_requires_allow_symlinks() {
+    let (client, fs) = make_test_filesystem(
+        "test_symlink_requires_allow_symlinks",
+        &Default::default(),
+        Default::default(),
+    );
+
+    let err = fs
+        .symlink(FUSE_ROOT_INODE, "link".as_ref(), "target".as_ref())
+        .await
+        .expect_err("symlink should fail without allow_symlinks");
+    assert_eq!(err.to_errno(), libc::EPERM);
+    assert!(!client.contains_key("link"));
+
+    client.add_object("file.txt", MockObject::constant(0xa1, 15, ETag::for_tests()));
+    let lookup = fs.lookup(FUSE_ROOT_INODE, "file.txt".as_ref()).await.unwrap();
+    let err = fs
+        .readlink(lookup.attr.ino)
+        .await
+        .expect_err("readlink should fail on a regular file");
+    assert_eq!(err.to_errno(), libc::EINVAL);
+}
+
+#[test_case(libc::S_IFREG, Ok(()); "regular file")]
+#[test_case(0, Ok(()); "no file type")]
+#[test_case(libc::S_IFIFO, Err(libc::EPERM); "fifo")]
+#[test_case(libc::S_IFCHR, Err(libc::EPERM); "character device")]
+#[test_case(libc::S_IFBLK, Err(libc::EPERM); "block device")]
+#[test_case(libc::S_IFSOCK, Err(libc::EPERM); "socket")]
+#[tokio::test]
+async fn test_mknod_file_types(file_type: libc::mode_t, expected: Result<(), i32>) {
+    let (_client, fs) = make_test_filesystem("test_mknod_file_types", &Default::default(), Default::default());
+
+    let mode = file_type | libc::S_IRWXU;
+    let result = fs.mknod(FUSE_ROOT_INODE, "node".as_ref(), mode, 0, 0).await;
+    match expected {
+        Ok(()) => {
+            let attr = result.expect("mknod should succeed").attr;
+            assert_eq!(attr.kind, FileType::RegularFile);
+        }
+        Err(errno) => {
+            assert_eq!(result.expect_err("mknod should fail").to_errno(), errno);
+            let err = fs
+                .lookup(FUSE_ROOT_INODE, "node".as_ref())
+                .await
+                .expect_err("nothing should be created");
+            assert_eq!(err.to_errno(), libc::ENOENT);
+        }
+    }
+}
+
 #[tokio::test]
 async fn test_mknod_cached() {
     const BUCKET_NAME: &str = "test_mknod_cached";
@@ -490,6 +887,55 @@ async fn test_random_read(object_size: usize) {
     fs.releasedir(FUSE_ROOT_INODE, dir_handle, 0).await.unwrap();
 }
 
+#[tokio::test]
+async fn test_concurrent_handles_read_independently() {
+    let (client, fs) = make_test_filesystem(
+        "test_concurrent_handles_read_independently",
+        &Default::default(),
+        Default::default(),
+    );
+
+    let object_size = 8 * 1024 * 1024;
+    let mut rng = ChaCha20Rng::seed_from_u64(0x12345678);
+    let mut expected = vec![0; object_size];
+    rng.fill(&mut expected[..]);
+    client.add_object("file", MockObject::from_bytes(&expected[..], ETag::for_tests()));
+
+    let ino = fs
+        .lookup(FUSE_ROOT_INODE, "file".as_ref())
+        .await
+        .expect("lookup should succeed")
+        .attr
+        .ino;
+    let get_counter = client.new_counter(Operation::GetObject);
+
+    // Two handles on the same file reading sequentially from different offsets, like two
+    // processes would, each keep their own prefetch stream
+    let fh1 = fs.open(ino, libc::O_RDONLY, 0).await.unwrap().fh;
+    let fh2 = fs.open(ino, libc::O_RDONLY, 0).await.unwrap().fh;
+    let read_size = 128 * 1024;
+    for i in 0..16 {
+        for (fh, start) in [(fh1, 0), (fh2, object_size / 2)] {
+            let offset = start + i * read_size;
+            let bytes_read = fs
+                .read(ino, fh, offset as i64, read_size as u32, 0, None)
+                .await
+                .expect("fs read should succeed");
+            assert_eq!(&bytes_read[..], &expected[offset..offset + read_size]);
+        }
+    }
+
+    // If either handle's reads reset the other's stream, every read would need a new request
+    assert!(
+        get_counter.count() <= 4,
+        "expected each handle to keep its stream, but made {} requests",
+        get_counter.count()
+    );
+
+    fs.release(ino, fh1, 0, None, true).await.unwrap();
+    fs.release(ino, fh2, 0, None, true).await.unwrap();
+}
+
 #[test_case(""; "unprefixed")]
 #[test_case("test_prefix/"; "prefixed")]
 #[tokio::test]
@@ -586,7 +1032,7 @@ async fn test_sequential_write(write_size: usize) {
 
     // Check that the object made it to S3 as we expected
     let get = client
-        .get_object(BUCKET_NAME, "dir1/file2.bin", None, None)
+        .get_object(BUCKET_NAME, "dir1/file2.bin", &GetObjectParams::new())
         .await
         .unwrap();
     let actual = get.collect().await.unwrap();
@@ -906,29 +1352,509 @@ async fn test_upload_aborted_on_release_failure() {
 }
 
 #[tokio::test]
-async fn test_stat_block_size() {
-    let (client, fs) = make_test_filesystem("test_stat_block_size", &Default::default(), Default::default());
+async fn test_release_failure_reported_on_next_fsync() {
+    const BUCKET_NAME: &str = "test_release_failure_reported_on_next_fsync";
+    const FILE_NAME: &str = "foo.bin";
 
-    client.add_object(
-        "file0.txt",
-        MockObject::constant(0xa1, 0, ETag::from_str("test_etag_1").unwrap()),
-    );
-    client.add_object(
-        "file1.txt",
-        MockObject::constant(0xa2, 1, ETag::from_str("test_etag_2").unwrap()),
-    );
-    client.add_object(
-        "file4096.txt",
-        MockObject::constant(0xa3, 4096, ETag::from_str("test_etag_3").unwrap()),
+    let client_config = MockClientConfig {
+        bucket: BUCKET_NAME.to_string(),
+        part_size: 1024 * 1024,
+        ..Default::default()
+    };
+
+    let client = Arc::new(MockClient::new(client_config));
+    let mut put_failures = HashMap::new();
+    put_failures.insert(1, Ok((2, MockClientError("error".to_owned().into()))));
+
+    let failure_client = countdown_failure_client(
+        client.clone(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        put_failures,
     );
-    client.add_object(
-        "file4097.txt",
-        MockObject::constant(0xa3, 4097, ETag::from_str("test_etag_4").unwrap()),
+    let fs = make_test_filesystem_with_client(
+        Arc::new(failure_client),
+        BUCKET_NAME,
+        &Default::default(),
+        Default::default(),
     );
 
-    let lookup = fs.lookup(FUSE_ROOT_INODE, "file0.txt".as_ref()).await.unwrap();
-    assert_eq!(lookup.attr.blocks, 0);
-    assert_eq!(lookup.attr.blksize, 4096);
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let dentry = fs.mknod(FUSE_ROOT_INODE, FILE_NAME.as_ref(), mode, 0, 0).await.unwrap();
+    let file_ino = dentry.attr.ino;
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+    _ = fs.write(file_ino, fh, 0, &[0xaa; 27], 0, 0, None).await.unwrap();
+    fs.release(file_ino, fh, 0, None, true)
+        .await
+        .expect_err("release should fail");
+    assert!(!client.is_upload_in_progress(FILE_NAME));
+
+    // Write the file again. This upload succeeds, but the first fsync reports the earlier failure.
+    let dentry = fs.mknod(FUSE_ROOT_INODE, FILE_NAME.as_ref(), mode, 0, 0).await.unwrap();
+    let file_ino = dentry.attr.ino;
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+    _ = fs.write(file_ino, fh, 0, &[0xbb; 27], 0, 0, None).await.unwrap();
+    let err = fs
+        .fsync(file_ino, fh, true)
+        .await
+        .expect_err("fsync should report the earlier failure")
+        .to_errno();
+    assert_eq!(err, libc::EIO);
+    assert!(client.contains_key(FILE_NAME));
+
+    // The failure is only reported once
+    fs.fsync(file_ino, fh, true).await.expect("second fsync should succeed");
+    fs.release(file_ino, fh, 0, None, true).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_write_conflict_fails_release() {
+    const BUCKET_NAME: &str = "test_write_conflict_fails_release";
+    const FILE_NAME: &str = "foo.bin";
+
+    let fs_config = S3FilesystemConfig {
+        write_conflict_policy: Some(WriteConflictPolicy::Fail),
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), fs_config);
+
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let dentry = fs.mknod(FUSE_ROOT_INODE, FILE_NAME.as_ref(), mode, 0, 0).await.unwrap();
+    let file_ino = dentry.attr.ino;
+
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+
+    _ = fs
+        .write(file_ino, fh, 0, &[0xaa; 27], 0, 0, None)
+        .await
+        .expect("write should succeed");
+
+    // Another writer creates the same object while our upload is in progress
+    let remote_etag = ETag::from_str("remote").unwrap();
+    client.add_object(FILE_NAME, MockObject::constant(0xbb, 13, remote_etag.clone()));
+
+    let err = fs
+        .release(file_ino, fh, 0, None, true)
+        .await
+        .expect_err("release should fail because of the conflict")
+        .to_errno();
+    assert_eq!(err, libc::EBUSY);
+
+    assert!(!client.is_upload_in_progress(FILE_NAME));
+    let head = client.head_object(BUCKET_NAME, FILE_NAME).await.unwrap();
+    assert_eq!(head.object.etag, remote_etag.as_str());
+}
+
+#[tokio::test]
+async fn test_read_fails_when_object_changes() {
+    const BUCKET_NAME: &str = "test_read_fails_when_object_changes";
+    const FILE_NAME: &str = "file.bin";
+    const OBJECT_SIZE: usize = 8 * 1024 * 1024;
+    const READ_SIZE: usize = 128 * 1024;
+
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), Default::default());
+    let original = MockObject::ramp(0x11, OBJECT_SIZE, ETag::from_str("original").unwrap());
+    client.add_object(FILE_NAME, original.clone());
+
+    let file_ino = fs.lookup(FUSE_ROOT_INODE, FILE_NAME.as_ref()).await.unwrap().attr.ino;
+    let fh = fs.open(file_ino, S_IFREG as i32, 0).await.unwrap().fh;
+    let bytes_read = fs
+        .read(file_ino, fh, 0, READ_SIZE as u32, 0, None)
+        .await
+        .expect("read before the change should succeed");
+    assert_eq!(&bytes_read[..], &original.read(0, READ_SIZE)[..]);
+
+    // Another client replaces the object while it's open
+    client.add_object(
+        FILE_NAME,
+        MockObject::ramp(0x22, OBJECT_SIZE, ETag::from_str("replaced").unwrap()),
+    );
+
+    // Reads keep returning the original data until they need a new request, which fails. The object
+    // is larger than the first request, so we must see the failure before reaching the end.
+    let mut offset = READ_SIZE;
+    let err = loop {
+        assert!(
+            offset < OBJECT_SIZE,
+            "read should fail before reaching the end of the object"
+        );
+        match fs.read(file_ino, fh, offset as i64, READ_SIZE as u32, 0, None).await {
+            Ok(bytes_read) => {
+                assert_eq!(&bytes_read[..], &original.read(offset as u64, READ_SIZE)[..]);
+                offset += bytes_read.len();
+            }
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(err.to_errno(), libc::ESTALE);
+    fs.release(file_ino, fh, 0, None, true).await.unwrap();
+}
+
+// Objects smaller than the minimum part size are uploaded again rather than copied
+#[test_case(15, 0; "small object")]
+#[test_case(6 * 1024 * 1024, 1; "large object")]
+#[tokio::test]
+async fn test_append_express(existing_size: usize, expected_copies: u64) {
+    const BUCKET_NAME: &str = "test_append_express";
+    const FILE_NAME: &str = "log.txt";
+
+    let fs_config = S3FilesystemConfig {
+        allow_overwrite: true,
+        s3_personality: S3Personality::ExpressOneZone,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), fs_config);
+
+    let existing = MockObject::ramp(0x11, existing_size, ETag::for_tests());
+    client.add_object(FILE_NAME, existing.clone());
+
+    let file_ino = fs.lookup(FUSE_ROOT_INODE, FILE_NAME.as_ref()).await.unwrap().attr.ino;
+    let copy_counter = client.new_counter(Operation::UploadPartCopy);
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY | libc::O_APPEND, 0)
+        .await
+        .expect("O_APPEND should be allowed on S3 Express")
+        .fh;
+    assert_eq!(copy_counter.count(), expected_copies);
+
+    // The file keeps its size, so the kernel sends appends to the end of it
+    let attr = fs.getattr(file_ino).await.unwrap().attr;
+    assert_eq!(attr.size, existing_size as u64);
+
+    let mut offset = existing_size as i64;
+    for line in [&b"first line\n"[..], &b"second line\n"[..]] {
+        offset += fs.write(file_ino, fh, offset, line, 0, 0, None).await.unwrap() as i64;
+    }
+    fs.release(file_ino, fh, 0, None, false).await.unwrap();
+    assert!(!client.is_upload_in_progress(FILE_NAME));
+
+    let get = client
+        .get_object(BUCKET_NAME, FILE_NAME, &GetObjectParams::new())
+        .await
+        .unwrap();
+    let actual = get.collect().await.unwrap();
+    let mut expected = existing.read(0, existing_size).into_vec();
+    expected.extend_from_slice(b"first line\nsecond line\n");
+    assert_eq!(&actual[..], &expected[..]);
+}
+
+#[tokio::test]
+async fn test_append_failure_releases_file() {
+    const BUCKET_NAME: &str = "test_append_failure_releases_file";
+    const FILE_NAME: &str = "log.txt";
+
+    let fs_config = S3FilesystemConfig {
+        allow_overwrite: true,
+        s3_personality: S3Personality::ExpressOneZone,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), fs_config);
+
+    client.add_object(FILE_NAME, MockObject::from_bytes(b"first line\n", ETag::for_tests()));
+    let file_ino = fs.lookup(FUSE_ROOT_INODE, FILE_NAME.as_ref()).await.unwrap().attr.ino;
+
+    // Replace the object behind our back, so the append can't read the version we looked up
+    let replaced = b"first line\nsecond line\n";
+    client.add_object(
+        FILE_NAME,
+        MockObject::from_bytes(replaced, ETag::from_str("replaced").unwrap()),
+    );
+    fs.open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY | libc::O_APPEND, 0)
+        .await
+        .expect_err("append to a replaced object should fail");
+    assert!(!client.is_upload_in_progress(FILE_NAME));
+
+    // The file isn't stuck being written, so it can be read and appended to again
+    let lookup = fs.lookup(FUSE_ROOT_INODE, FILE_NAME.as_ref()).await.unwrap();
+    assert_eq!(lookup.attr.size, replaced.len() as u64);
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_RDONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+    fs.release(file_ino, fh, 0, None, false).await.unwrap();
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY | libc::O_APPEND, 0)
+        .await
+        .expect("append should succeed once the file is looked up again")
+        .fh;
+    fs.write(file_ino, fh, replaced.len() as i64, b"third line\n", 0, 0, None)
+        .await
+        .unwrap();
+    fs.release(file_ino, fh, 0, None, false).await.unwrap();
+
+    let get = client
+        .get_object(BUCKET_NAME, FILE_NAME, &GetObjectParams::new())
+        .await
+        .unwrap();
+    let actual = get.collect().await.unwrap();
+    assert_eq!(&actual[..], b"first line\nsecond line\nthird line\n");
+}
+
+#[test_case(S3Personality::Standard, true, Err(libc::EIO); "standard")]
+#[test_case(S3Personality::ExpressOneZone, false, Err(libc::EPERM); "express without overwrite")]
+#[test_case(S3Personality::ExpressOneZone, true, Ok(()); "express")]
+#[tokio::test]
+async fn test_write_after_fsync(s3_personality: S3Personality, allow_overwrite: bool, expected: Result<(), i32>) {
+    const BUCKET_NAME: &str = "test_write_after_fsync";
+    const FILE_NAME: &str = "log.txt";
+
+    let fs_config = S3FilesystemConfig {
+        allow_overwrite,
+        s3_personality,
+        write_conflict_policy: Some(WriteConflictPolicy::Fail),
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), fs_config);
+
+    let mode = libc::S_IFREG | libc::S_IRWXU;
+    let dentry = fs.mknod(FUSE_ROOT_INODE, FILE_NAME.as_ref(), mode, 0, 0).await.unwrap();
+    let file_ino = dentry.attr.ino;
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+
+    let first = b"first line\n";
+    fs.write(file_ino, fh, 0, first, 0, 0, None).await.unwrap();
+    fs.fsync(file_ino, fh, false)
+        .await
+        .expect("fsync should complete the upload");
+    assert!(!client.is_upload_in_progress(FILE_NAME));
+    assert!(client.contains_key(FILE_NAME));
+
+    let second = b"second line\n";
+    let result = fs.write(file_ino, fh, first.len() as i64, second, 0, 0, None).await;
+    let expected: &[u8] = match expected {
+        Ok(()) => {
+            // The new upload checks for conflicts against the object that fsync created
+            result.expect("writes should continue in a new upload");
+            fs.release(file_ino, fh, 0, None, true).await.unwrap();
+            b"first line\nsecond line\n"
+        }
+        Err(errno) => {
+            assert_eq!(result.expect_err("writes after fsync should fail").to_errno(), errno);
+            fs.release(file_ino, fh, 0, None, true).await.unwrap();
+            b"first line\n"
+        }
+    };
+    assert!(!client.is_upload_in_progress(FILE_NAME));
+
+    let get = client
+        .get_object(BUCKET_NAME, FILE_NAME, &GetObjectParams::new())
+        .await
+        .unwrap();
+    let actual = get.collect().await.unwrap();
+    assert_eq!(&actual[..], expected);
+}
+
+#[tokio::test]
+async fn test_append_requires_express_and_allow_overwrite() {
+    const FILE_NAME: &str = "log.txt";
+
+    for (s3_personality, allow_overwrite, expected_errno) in [
+        (S3Personality::Standard, true, libc::EINVAL),
+        (S3Personality::ExpressOneZone, false, libc::EPERM),
+    ] {
+        let fs_config = S3FilesystemConfig {
+            allow_overwrite,
+            s3_personality,
+            ..Default::default()
+        };
+        let (client, fs) = make_test_filesystem("test_append_requires_express", &Default::default(), fs_config);
+        client.add_object(FILE_NAME, MockObject::constant(0xaa, 15, ETag::for_tests()));
+
+        let file_ino = fs.lookup(FUSE_ROOT_INODE, FILE_NAME.as_ref()).await.unwrap().attr.ino;
+        let err = fs
+            .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY | libc::O_APPEND, 0)
+            .await
+            .expect_err("O_APPEND should not be allowed");
+        assert_eq!(err.to_errno(), expected_errno);
+        assert!(!client.is_upload_in_progress(FILE_NAME));
+    }
+}
+
+#[test_case(true; "through file handle")]
+#[test_case(false; "by path")]
+#[tokio::test]
+async fn test_truncate_remote_file(with_handle: bool) {
+    const BUCKET_NAME: &str = "test_truncate_remote_file";
+    const FILE_NAME: &str = "file.txt";
+
+    let fs_config = S3FilesystemConfig {
+        allow_overwrite: true,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), fs_config);
+    client.add_object(FILE_NAME, MockObject::constant(0xaa, 15, ETag::for_tests()));
+
+    let file_ino = fs.lookup(FUSE_ROOT_INODE, FILE_NAME.as_ref()).await.unwrap().attr.ino;
+    let fh = if with_handle {
+        // Without O_TRUNC, an existing file opened for reading and writing gets a read handle
+        let fh = fs.open(file_ino, libc::O_RDWR, 0).await.unwrap().fh;
+        Some(fh)
+    } else {
+        None
+    };
+
+    let err = fs
+        .setattr(file_ino, None, None, Some(5), fh, None, 0)
+        .await
+        .expect_err("can only truncate remote files to zero");
+    assert_eq!(err.to_errno(), libc::EPERM);
+
+    let attr = fs
+        .setattr(file_ino, None, None, Some(0), fh, None, 0)
+        .await
+        .expect("truncating to zero should succeed");
+    assert_eq!(attr.attr.size, 0);
+
+    if let Some(fh) = fh {
+        // The object is replaced once the new contents are written through the handle
+        assert!(client.is_upload_in_progress(FILE_NAME));
+        fs.write(file_ino, fh, 0, b"new contents", 0, 0, None).await.unwrap();
+        fs.release(file_ino, fh, 0, None, false).await.unwrap();
+    }
+    assert!(!client.is_upload_in_progress(FILE_NAME));
+
+    let get = client
+        .get_object(BUCKET_NAME, FILE_NAME, &GetObjectParams::new())
+        .await
+        .unwrap();
+    let actual = get.collect().await.unwrap();
+    let expected: &[u8] = if with_handle { b"new contents" } else { b"" };
+    assert_eq!(&actual[..], expected);
+}
+
+#[test_case(0, libc::SEEK_DATA, Ok(0); "data at start")]
+#[test_case(100, libc::SEEK_DATA, Ok(100); "data in middle")]
+#[test_case(0, libc::SEEK_HOLE, Ok(1024); "hole at start")]
+#[test_case(1023, libc::SEEK_HOLE, Ok(1024); "hole at end")]
+#[test_case(1024, libc::SEEK_DATA, Err(libc::ENXIO); "data beyond end")]
+#[test_case(1024, libc::SEEK_HOLE, Err(libc::ENXIO); "hole beyond end")]
+#[test_case(-1, libc::SEEK_DATA, Err(libc::EINVAL); "negative offset")]
+#[tokio::test]
+async fn test_lseek(offset: i64, whence: i32, expected: Result<i64, i32>) {
+    let (client, fs) = make_test_filesystem("test_lseek", &Default::default(), Default::default());
+    client.add_object("file.bin", MockObject::constant(0xaa, 1024, ETag::for_tests()));
+
+    let ino = fs.lookup(FUSE_ROOT_INODE, "file.bin".as_ref()).await.unwrap().attr.ino;
+    let fh = fs.open(ino, libc::O_RDONLY, 0).await.unwrap().fh;
+    let result = fs.lseek(ino, fh, offset, whence).await.map_err(|e| e.to_errno());
+    assert_eq!(result, expected);
+    fs.release(ino, fh, 0, None, true).await.unwrap();
+}
+
+// The test filesystem uses 1MiB parts, so uploads can be at most 10000MiB
+#[test_case(0, 4096, 0, Ok(()); "small")]
+#[test_case(1024, 4096, libc::FALLOC_FL_KEEP_SIZE, Ok(()); "keep size")]
+#[test_case(0, 10000 * 1024 * 1024, 0, Ok(()); "maximum size")]
+#[test_case(1, 10000 * 1024 * 1024, 0, Err(libc::EFBIG); "beyond maximum size")]
+#[test_case(i64::MAX, i64::MAX, 0, Err(libc::EFBIG); "overflow")]
+#[test_case(0, 0, 0, Err(libc::EINVAL); "empty")]
+#[test_case(-1, 4096, 0, Err(libc::EINVAL); "negative offset")]
+#[test_case(0, 4096, libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE, Err(libc::EOPNOTSUPP); "punch hole")]
+#[tokio::test]
+async fn test_fallocate(offset: i64, length: i64, mode: i32, expected: Result<(), i32>) {
+    let (_client, fs) = make_test_filesystem("test_fallocate", &Default::default(), Default::default());
+
+    let mode_t = libc::S_IFREG | libc::S_IRWXU;
+    let dentry = fs
+        .mknod(FUSE_ROOT_INODE, "file.bin".as_ref(), mode_t, 0, 0)
+        .await
+        .unwrap();
+    let ino = dentry.attr.ino;
+    let fh = fs.open(ino, libc::O_WRONLY, 0).await.unwrap().fh;
+
+    let result = fs
+        .fallocate(ino, fh, offset, length, mode)
+        .await
+        .map_err(|e| e.to_errno());
+    assert_eq!(result, expected);
+
+    // Allocating space doesn't change the file
+    let attr = fs.getattr(ino).await.unwrap().attr;
+    assert_eq!(attr.size, 0);
+    fs.write(ino, fh, 0, b"hello", 0, 0, None).await.unwrap();
+    fs.release(ino, fh, 0, None, true).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_fallocate_read_handle() {
+    let (client, fs) = make_test_filesystem("test_fallocate_read_handle", &Default::default(), Default::default());
+    client.add_object("file.bin", MockObject::constant(0xaa, 1024, ETag::for_tests()));
+
+    let ino = fs.lookup(FUSE_ROOT_INODE, "file.bin".as_ref()).await.unwrap().attr.ino;
+    let fh = fs.open(ino, libc::O_RDONLY, 0).await.unwrap().fh;
+    let err = fs
+        .fallocate(ino, fh, 0, 4096, 0)
+        .await
+        .expect_err("read handles can't allocate");
+    assert_eq!(err.to_errno(), libc::EBADF);
+    fs.release(ino, fh, 0, None, true).await.unwrap();
+}
+
+#[test_case(None, None, (1 << 48, 1 << 48); "default")]
+#[test_case(Some(1 << 30), None, (1 << 18, 1 << 18); "size only")]
+#[test_case(Some(1 << 30), Some(1 << 20), (1 << 18, 1 << 8); "size and free")]
+#[test_case(Some(1 << 20), Some(1 << 30), (1 << 8, 1 << 8); "free larger than size")]
+#[tokio::test]
+async fn test_statfs(fs_size: Option<u64>, fs_free: Option<u64>, expected_blocks: (u64, u64)) {
+    let mut config = S3FilesystemConfig::default();
+    if let Some(fs_size) = fs_size {
+        config.fs_size = fs_size;
+        config.fs_free = fs_size;
+    }
+    if let Some(fs_free) = fs_free {
+        config.fs_free = fs_free;
+    }
+    let (_client, fs) = make_test_filesystem("test_statfs", &Default::default(), config);
+
+    let statfs = fs.statfs(FUSE_ROOT_INODE).await.unwrap();
+    assert_eq!(statfs.fragment_size, 4096);
+    assert_eq!((statfs.total_blocks, statfs.free_blocks), expected_blocks);
+    assert_eq!(statfs.available_blocks, statfs.free_blocks);
+    assert!(statfs.free_inodes > 0);
+}
+
+#[tokio::test]
+async fn test_stat_block_size() {
+    let (client, fs) = make_test_filesystem("test_stat_block_size", &Default::default(), Default::default());
+
+    client.add_object(
+        "file0.txt",
+        MockObject::constant(0xa1, 0, ETag::from_str("test_etag_1").unwrap()),
+    );
+    client.add_object(
+        "file1.txt",
+        MockObject::constant(0xa2, 1, ETag::from_str("test_etag_2").unwrap()),
+    );
+    client.add_object(
+        "file4096.txt",
+        MockObject::constant(0xa3, 4096, ETag::from_str("test_etag_3").unwrap()),
+    );
+    client.add_object(
+        "file4097.txt",
+        MockObject::constant(0xa3, 4097, ETag::from_str("test_etag_4").unwrap()),
+    );
+
+    let lookup = fs.lookup(FUSE_ROOT_INODE, "file0.txt".as_ref()).await.unwrap();
+    assert_eq!(lookup.attr.blocks, 0);
+    assert_eq!(lookup.attr.blksize, 4096);
 
     let lookup = fs.lookup(FUSE_ROOT_INODE, "file1.txt".as_ref()).await.unwrap();
     assert_eq!(lookup.attr.blocks, 1);
@@ -1021,6 +1947,404 @@ async fn test_local_dir(prefix: &str) {
     assert!(matches!(lookup, Err(e) if e.to_errno() == libc::ENOENT));
 }
 
+#[test_case(""; "unprefixed")]
+#[test_case("test_prefix/"; "prefixed")]
+#[tokio::test]
+async fn test_overlay_directory(prefix: &str) {
+    let overlay = tempfile::tempdir().unwrap();
+    std::fs::create_dir(overlay.path().join("dir")).unwrap();
+    std::fs::write(overlay.path().join("dir/patched.txt"), b"patched contents").unwrap();
+
+    let prefix = Prefix::new(prefix).expect("valid prefix");
+    let fs_config = S3FilesystemConfig {
+        overlay_directory: Some(OverlayDirectory::new(overlay.path())),
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_overlay_directory", &prefix, fs_config);
+    client.add_object(
+        &format!("{prefix}dir/patched.txt"),
+        MockObject::constant(0xa1, 5, ETag::for_tests()),
+    );
+    client.add_object(
+        &format!("{prefix}dir/original.txt"),
+        MockObject::constant(0xa2, 5, ETag::for_tests()),
+    );
+
+    let dir = fs.lookup(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap();
+
+    // The overlay file shadows the object with the same name
+    let patched = fs.lookup(dir.attr.ino, "patched.txt".as_ref()).await.unwrap();
+    assert_eq!(patched.attr.size, b"patched contents".len() as u64);
+    let fh = fs.open(patched.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+    let data = fs.read(patched.attr.ino, fh, 0, 4096, 0, None).await.unwrap();
+    assert_eq!(&data[..], b"patched contents");
+    fs.release(patched.attr.ino, fh, 0, None, true).await.unwrap();
+
+    // Shadowed files can't be written
+    let err = fs
+        .open(patched.attr.ino, libc::O_WRONLY | libc::O_TRUNC, 0)
+        .await
+        .expect_err("overlay files should not be writable");
+    assert_eq!(err.to_errno(), libc::EPERM);
+    let fh = fs.open(patched.attr.ino, libc::O_RDWR, 0).await.unwrap().fh;
+    let err = fs
+        .write(patched.attr.ino, fh, 0, b"hello", 0, 0, None)
+        .await
+        .expect_err("overlay files should not be writable");
+    assert_eq!(err.to_errno(), libc::EPERM);
+    fs.release(patched.attr.ino, fh, 0, None, true).await.unwrap();
+
+    // Other objects are still served from S3
+    let original = fs.lookup(dir.attr.ino, "original.txt".as_ref()).await.unwrap();
+    assert_eq!(original.attr.size, 5);
+    let fh = fs.open(original.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+    let data = fs.read(original.attr.ino, fh, 0, 4096, 0, None).await.unwrap();
+    assert_eq!(&data[..], &[0xa2; 5]);
+    fs.release(original.attr.ino, fh, 0, None, true).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_object_version_policy_latest_at() {
+    let before = datetime!(2024-01-01 0:00 UTC);
+    let as_of = datetime!(2024-01-02 0:00 UTC);
+    let after = datetime!(2024-01-03 0:00 UTC);
+
+    let fs_config = S3FilesystemConfig {
+        object_version_policy: ObjectVersionPolicy::LatestAt(as_of),
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_object_version_policy_latest_at", &Default::default(), fs_config);
+    let add_object = |key: &str, byte: u8, last_modified| {
+        let mut object = MockObject::constant(byte, 5, ETag::from_str(&format!("etag-{byte}")).unwrap());
+        object.set_last_modified(last_modified);
+        client.add_object(key, object);
+    };
+    // Overwritten after the point in time
+    add_object("dir/overwritten.txt", 0xa1, before);
+    add_object("dir/overwritten.txt", 0xa2, after);
+    // Created after the point in time
+    add_object("dir/new.txt", 0xa3, after);
+    add_object("new/file.txt", 0xa4, after);
+    // Deleted after the point in time
+    add_object("dir/deleted.txt", 0xa5, before);
+    client.remove_object("dir/deleted.txt");
+
+    let new_dir = fs
+        .lookup(FUSE_ROOT_INODE, "new".as_ref())
+        .await
+        .expect_err("new should not exist");
+    assert_eq!(new_dir.to_errno(), libc::ENOENT);
+
+    // Directories whose keys were all created later aren't listed either
+    let root_handle = fs.opendir(FUSE_ROOT_INODE, 0).await.unwrap().fh;
+    let mut reply = Default::default();
+    let _reply = fs
+        .readdirplus(FUSE_ROOT_INODE, root_handle, 0, &mut reply)
+        .await
+        .unwrap();
+    let names = reply.entries.iter().skip(2).map(|e| e.name.clone()).collect::<Vec<_>>();
+    assert_eq!(names, ["dir"]);
+    fs.releasedir(FUSE_ROOT_INODE, root_handle, 0).await.unwrap();
+
+    let dir = fs.lookup(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap();
+    assert_eq!(dir.attr.kind, FileType::Directory);
+    let new_file = fs
+        .lookup(dir.attr.ino, "new.txt".as_ref())
+        .await
+        .expect_err("new.txt should not exist");
+    assert_eq!(new_file.to_errno(), libc::ENOENT);
+
+    let dir_handle = fs.opendir(dir.attr.ino, 0).await.unwrap().fh;
+    let mut reply = Default::default();
+    let _reply = fs.readdirplus(dir.attr.ino, dir_handle, 0, &mut reply).await.unwrap();
+    let names = reply.entries.iter().skip(2).map(|e| e.name.clone()).collect::<Vec<_>>();
+    assert_eq!(names, ["deleted.txt", "overwritten.txt"]);
+    fs.releasedir(dir.attr.ino, dir_handle, 0).await.unwrap();
+
+    // Reads return the versions from the point in time, even though they have since been replaced
+    for (name, expected) in [("overwritten.txt", 0xa1), ("deleted.txt", 0xa5)] {
+        let file = fs.lookup(dir.attr.ino, name.as_ref()).await.unwrap();
+        assert_eq!(file.attr.mtime, SystemTime::from(before));
+        let fh = fs.open(file.attr.ino, libc::O_RDONLY, 0).await.unwrap().fh;
+        let data = fs.read(file.attr.ino, fh, 0, 4096, 0, None).await.unwrap();
+        assert_eq!(&data[..], &[expected; 5], "wrong contents for {name}");
+        fs.release(file.attr.ino, fh, 0, None, true).await.unwrap();
+    }
+}
+
+#[test_case(""; "unprefixed")]
+#[test_case("test_prefix/"; "prefixed")]
+#[tokio::test]
+async fn test_read_policy(prefix: &str) {
+    let prefix = Prefix::new(prefix).expect("valid prefix");
+    let policy = format!(
+        r#"{{ "rules": [
+            {{ "prefix": "{prefix}public/" }},
+            {{ "prefix": "{prefix}headers/", "range": {{ "start": 0, "end": 16 }} }}
+        ] }}"#
+    );
+    let fs_config = S3FilesystemConfig {
+        read_policy: Some(ReadPolicy::from_json(&policy).unwrap()),
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_read_policy", &prefix, fs_config);
+    for (i, dir) in ["public", "headers", "private"].iter().enumerate() {
+        client.add_object(
+            &format!("{prefix}{dir}/file"),
+            MockObject::constant(i as u8, 32, ETag::for_tests()),
+        );
+    }
+    let get_counter = client.new_counter(Operation::GetObject);
+
+    // Files appear to end where the policy stops allowing reads
+    let mut inos = HashMap::new();
+    for (dir, size) in [("public", 32), ("headers", 16), ("private", 32)] {
+        let dir_entry = fs.lookup(FUSE_ROOT_INODE, dir.as_ref()).await.unwrap();
+        let file_entry = fs.lookup(dir_entry.attr.ino, "file".as_ref()).await.unwrap();
+        assert_eq!(file_entry.attr.size, size, "size of {dir}/file");
+        inos.insert(dir, file_entry.attr.ino);
+    }
+
+    // Objects without a matching rule can't be opened
+    let ino = inos["private"];
+    let err = fs
+        .open(ino, libc::O_RDONLY, 0)
+        .await
+        .expect_err("open should be denied");
+    assert_eq!(err.to_errno(), libc::EACCES);
+    assert_eq!(get_counter.count(), 0);
+
+    // Reads are limited to the allowed range
+    let ino = inos["headers"];
+    let fh = fs.open(ino, libc::O_RDONLY, 0).await.unwrap().fh;
+    let data = fs.read(ino, fh, 0, 4096, 0, None).await.unwrap();
+    assert_eq!(&data[..], &[1; 16]);
+    let err = fs
+        .read(ino, fh, 16, 4096, 0, None)
+        .await
+        .expect_err("read should be denied");
+    assert_eq!(err.to_errno(), libc::EACCES);
+    fs.release(ino, fh, 0, None, true).await.unwrap();
+
+    // Rules without a range allow reading the whole object
+    let ino = inos["public"];
+    let fh = fs.open(ino, libc::O_RDONLY, 0).await.unwrap().fh;
+    let data = fs.read(ino, fh, 0, 4096, 0, None).await.unwrap();
+    assert_eq!(&data[..], &[0; 32]);
+    fs.release(ino, fh, 0, None, true).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_read_only_access_mode() {
+    let fs_config = S3FilesystemConfig {
+        access_mode: AccessMode::ReadOnly,
+        allow_delete: true,
+        allow_overwrite: true,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_read_only_access_mode", &Default::default(), fs_config);
+    client.add_object("dir/file", MockObject::constant(0xa1, 32, ETag::for_tests()));
+    let put_counter = client.new_counter(Operation::PutObject);
+    let mpu_counter = client.new_counter(Operation::CreateMultipartUpload);
+    let delete_counter = client.new_counter(Operation::DeleteObject);
+
+    let dir_ino = fs.lookup(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap().attr.ino;
+    let file_ino = fs.lookup(dir_ino, "file".as_ref()).await.unwrap().attr.ino;
+
+    // Reads still work
+    let fh = fs.open(file_ino, libc::O_RDONLY, 0).await.unwrap().fh;
+    let data = fs.read(file_ino, fh, 0, 4096, 0, None).await.unwrap();
+    assert_eq!(&data[..], &[0xa1; 32]);
+    fs.release(file_ino, fh, 0, None, true).await.unwrap();
+
+    // Anything that would modify the bucket fails with EROFS
+    let mode = libc::S_IFREG | libc::S_IRWXU;
+    let err = fs.mknod(dir_ino, "new".as_ref(), mode, 0, 0).await.unwrap_err();
+    assert_eq!(err.to_errno(), libc::EROFS);
+    let err = fs.mkdir(dir_ino, "new".as_ref(), 0o755, 0).await.unwrap_err();
+    assert_eq!(err.to_errno(), libc::EROFS);
+    let err = fs.open(file_ino, libc::O_WRONLY | libc::O_TRUNC, 0).await.unwrap_err();
+    assert_eq!(err.to_errno(), libc::EROFS);
+    let err = fs.open(file_ino, libc::O_RDWR, 0).await.unwrap_err();
+    assert_eq!(err.to_errno(), libc::EROFS);
+    let err = fs
+        .setattr(file_ino, None, None, Some(0), None, None, 0)
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_errno(), libc::EROFS);
+    let err = fs.unlink(dir_ino, "file".as_ref()).await.unwrap_err();
+    assert_eq!(err.to_errno(), libc::EROFS);
+    let err = fs
+        .rename(dir_ino, "file".as_ref(), dir_ino, "renamed".as_ref(), 0)
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_errno(), libc::EROFS);
+
+    assert_eq!(put_counter.count(), 0);
+    assert_eq!(mpu_counter.count(), 0);
+    assert_eq!(delete_counter.count(), 0);
+    assert!(client.contains_key("dir/file"));
+}
+
+#[tokio::test]
+async fn test_write_only_access_mode() {
+    let fs_config = S3FilesystemConfig {
+        access_mode: AccessMode::WriteOnly,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_write_only_access_mode", &Default::default(), fs_config);
+    client.add_object("dir/file", MockObject::constant(0xa1, 32, ETag::for_tests()));
+    let get_counter = client.new_counter(Operation::GetObject);
+
+    // Metadata is still visible
+    let dir_ino = fs.lookup(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap().attr.ino;
+    let file = fs.lookup(dir_ino, "file".as_ref()).await.unwrap();
+    assert_eq!(file.attr.size, 32);
+
+    // Existing files can't be opened for reading
+    let err = fs.open(file.attr.ino, libc::O_RDONLY, 0).await.unwrap_err();
+    assert_eq!(err.to_errno(), libc::EACCES);
+    let err = fs.open(file.attr.ino, libc::O_RDWR, 0).await.unwrap_err();
+    assert_eq!(err.to_errno(), libc::EACCES);
+
+    // New files can be written, but not read back through the write handle
+    let mode = libc::S_IFREG | libc::S_IRWXU;
+    let new_ino = fs.mknod(dir_ino, "new".as_ref(), mode, 0, 0).await.unwrap().attr.ino;
+    let fh = fs.open(new_ino, libc::O_RDWR, 0).await.unwrap().fh;
+    fs.write(new_ino, fh, 0, &[0xb2; 16], 0, 0, None).await.unwrap();
+    let err = fs.read(new_ino, fh, 0, 4096, 0, None).await.unwrap_err();
+    assert_eq!(err.to_errno(), libc::EACCES);
+    fs.release(new_ino, fh, 0, None, true).await.unwrap();
+
+    assert!(client.contains_key("dir/new"));
+    assert_eq!(get_counter.count(), 0);
+}
+
+#[tokio::test]
+async fn test_write_only_access_mode_append() {
+    let fs_config = S3FilesystemConfig {
+        access_mode: AccessMode::WriteOnly,
+        allow_overwrite: true,
+        s3_personality: S3Personality::ExpressOneZone,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_write_only_access_mode_append", &Default::default(), fs_config);
+    client.add_object("file", MockObject::constant(0xa1, 32, ETag::for_tests()));
+    let get_counter = client.new_counter(Operation::GetObject);
+    let mpu_counter = client.new_counter(Operation::CreateMultipartUpload);
+
+    // Appending would download the existing object
+    let file_ino = fs.lookup(FUSE_ROOT_INODE, "file".as_ref()).await.unwrap().attr.ino;
+    let err = fs.open(file_ino, libc::O_WRONLY | libc::O_APPEND, 0).await.unwrap_err();
+    assert_eq!(err.to_errno(), libc::EACCES);
+
+    // So would writing after fsync, which appends to the object fsync created
+    let mode = libc::S_IFREG | libc::S_IRWXU;
+    let new_ino = fs
+        .mknod(FUSE_ROOT_INODE, "new".as_ref(), mode, 0, 0)
+        .await
+        .unwrap()
+        .attr
+        .ino;
+    let fh = fs.open(new_ino, libc::O_WRONLY, 0).await.unwrap().fh;
+    fs.write(new_ino, fh, 0, &[0xb2; 16], 0, 0, None).await.unwrap();
+    fs.fsync(new_ino, fh, false).await.unwrap();
+    let err = fs.write(new_ino, fh, 16, &[0xb2; 16], 0, 0, None).await.unwrap_err();
+    assert_eq!(err.to_errno(), libc::EACCES);
+    fs.release(new_ino, fh, 0, None, true).await.unwrap();
+
+    assert_eq!(
+        client
+            .head_object("test_write_only_access_mode_append", "new")
+            .await
+            .unwrap()
+            .object
+            .size,
+        16
+    );
+    assert_eq!(get_counter.count(), 0);
+    assert_eq!(mpu_counter.count(), 0);
+}
+
+#[tokio::test]
+async fn test_identity_map() {
+    let identity_map = r#"{ "rules": [
+        { "prefix": "alice/", "uid": 1001, "gid": 1001, "dir_mode": "0750", "file_mode": "0640" }
+    ] }"#;
+    let fs_config = S3FilesystemConfig {
+        uid: 1000,
+        gid: 1000,
+        identity_map: Some(IdentityMap::from_json(identity_map).unwrap()),
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_identity_map", &Default::default(), fs_config);
+    client.add_object("alice/file", MockObject::constant(0xa1, 32, ETag::for_tests()));
+    client.add_object("public/file", MockObject::constant(0xb2, 32, ETag::for_tests()));
+
+    let alice = Caller {
+        uid: 1001,
+        gid: 1001,
+        pid: 0,
+    };
+    let bob = Caller {
+        uid: 1002,
+        gid: 1002,
+        pid: 0,
+    };
+
+    let alice_dir = fs.lookup(FUSE_ROOT_INODE, "alice".as_ref()).await.unwrap();
+    assert_attr(alice_dir.attr, FileType::Directory, 0, 1001, 1001, 0o750);
+    let alice_file = fs.lookup(alice_dir.attr.ino, "file".as_ref()).await.unwrap();
+    assert_attr(alice_file.attr, FileType::RegularFile, 32, 1001, 1001, 0o640);
+    let public_dir = fs.lookup(FUSE_ROOT_INODE, "public".as_ref()).await.unwrap();
+    assert_attr(public_dir.attr, FileType::Directory, 0, 1000, 1000, 0o755);
+    let public_file = fs.lookup(public_dir.attr.ino, "file".as_ref()).await.unwrap();
+
+    // Alice owns her directory and file
+    fs.check_access(alice_dir.attr.ino, alice, libc::X_OK | libc::W_OK)
+        .await
+        .unwrap();
+    fs.check_access(alice_file.attr.ino, alice, libc::R_OK | libc::W_OK)
+        .await
+        .unwrap();
+
+    // Bob can't even search Alice's directory, but can read public files
+    let err = fs
+        .check_access(alice_dir.attr.ino, bob, libc::X_OK)
+        .await
+        .expect_err("bob can't search alice's directory");
+    assert_eq!(err.to_errno(), libc::EACCES);
+    let err = fs
+        .check_access(alice_file.attr.ino, bob, libc::R_OK)
+        .await
+        .expect_err("bob can't read alice's file");
+    assert_eq!(err.to_errno(), libc::EACCES);
+    fs.check_access(public_file.attr.ino, bob, libc::R_OK).await.unwrap();
+    let err = fs
+        .check_access(public_file.attr.ino, bob, libc::W_OK)
+        .await
+        .expect_err("bob can't write public files");
+    assert_eq!(err.to_errno(), libc::EACCES);
+
+    // Root can read and write anything
+    let root = Caller { uid: 0, gid: 0, pid: 0 };
+    fs.check_access(alice_file.attr.ino, root, libc::R_OK | libc::W_OK)
+        .await
+        .unwrap();
+
+    // New files that haven't been uploaded yet are checked against the map too
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let new_file = fs.mknod(alice_dir.attr.ino, "new".as_ref(), mode, 0, 0).await.unwrap();
+    fs.check_access(new_file.attr.ino, alice, libc::R_OK | libc::W_OK)
+        .await
+        .unwrap();
+    let err = fs
+        .check_access(new_file.attr.ino, bob, libc::W_OK)
+        .await
+        .expect_err("bob can't write alice's new file");
+    assert_eq!(err.to_errno(), libc::EACCES);
+}
+
 #[tokio::test]
 async fn test_directory_shadowing_lookup() {
     let (client, fs) = make_test_filesystem(
@@ -1188,6 +2512,94 @@ async fn test_readdir_vs_readdirplus() {
     }
 }
 
+#[tokio::test]
+async fn test_readdirplus_disabled() {
+    let fs_config = S3FilesystemConfig {
+        readdirplus: false,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_readdirplus_disabled", &Default::default(), fs_config);
+
+    client.add_object("bar", b"bar".into());
+
+    let dir_handle = fs.opendir(FUSE_ROOT_INODE, 0).await.unwrap().fh;
+    let mut reply = Default::default();
+    let err = fs
+        .readdirplus(FUSE_ROOT_INODE, dir_handle, 0, &mut reply)
+        .await
+        .expect_err("readdirplus should be disabled")
+        .to_errno();
+    assert_eq!(err, libc::ENOSYS);
+    assert!(reply.entries.is_empty());
+
+    // Plain readdir still works on the same handle
+    let mut reply = Default::default();
+    let _reply = fs.readdir(FUSE_ROOT_INODE, dir_handle, 0, &mut reply).await.unwrap();
+    fs.releasedir(FUSE_ROOT_INODE, dir_handle, 0).await.unwrap();
+    assert_eq!(
+        reply.entries.iter().map(|e| &e.name).collect::<Vec<_>>(),
+        &[".", "..", "bar"]
+    );
+}
+
+#[test_case(ControlDirectoryMode::Hidden, &[".", "..", "bar"]; "hidden")]
+#[test_case(ControlDirectoryMode::Visible, &[".", "..", ".mountpoint", "bar"]; "visible")]
+#[tokio::test]
+async fn test_control_directory(mode: ControlDirectoryMode, root_entries: &[&str]) {
+    let fs_config = S3FilesystemConfig {
+        control_directory: mode,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_control_directory", &Default::default(), fs_config);
+
+    client.add_object("bar", b"bar".into());
+    client.add_object(".mountpoint/shadowed", b"shadowed".into());
+
+    // Keys under .mountpoint/ are shadowed by the control directory, whether it's listed or not
+    let dir_handle = fs.opendir(FUSE_ROOT_INODE, 0).await.unwrap().fh;
+    let mut reply = Default::default();
+    let _reply = fs.readdir(FUSE_ROOT_INODE, dir_handle, 0, &mut reply).await.unwrap();
+    fs.releasedir(FUSE_ROOT_INODE, dir_handle, 0).await.unwrap();
+    assert_eq!(reply.entries.iter().map(|e| &e.name).collect::<Vec<_>>(), root_entries);
+}
+
+#[tokio::test]
+async fn test_control_directory_off() {
+    let fs_config = S3FilesystemConfig {
+        control_directory: ControlDirectoryMode::Off,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_control_directory_off", &Default::default(), fs_config);
+
+    client.add_object(".mountpoint/shadowed", b"shadowed".into());
+
+    let dir = fs.lookup(FUSE_ROOT_INODE, ".mountpoint".as_ref()).await.unwrap();
+    assert_eq!(dir.attr.kind, FileType::Directory);
+    let file = fs.lookup(dir.attr.ino, "shadowed".as_ref()).await.unwrap();
+    assert_eq!(file.attr.size, 8);
+}
+
+#[tokio::test]
+async fn test_dir_metadata_ttl() {
+    let cache_config = CacheConfig::new(TimeToLive::Duration(Duration::from_secs(1)))
+        .with_dir_metadata_ttl(TimeToLive::Duration(Duration::from_secs(600)));
+    let fs_config = S3FilesystemConfig {
+        cache_config,
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem("test_dir_metadata_ttl", &Default::default(), fs_config);
+
+    client.add_object("dir/file", b"foo".into());
+
+    let dir = fs.lookup(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap();
+    assert_eq!(dir.attr.kind, FileType::Directory);
+    assert!(dir.ttl > Duration::from_secs(1), "directory should use its own TTL");
+
+    let file = fs.lookup(dir.attr.ino, "file".as_ref()).await.unwrap();
+    assert_eq!(file.attr.kind, FileType::RegularFile);
+    assert!(file.ttl <= Duration::from_secs(1), "file should use the metadata TTL");
+}
+
 #[tokio::test]
 async fn test_flexible_retrieval_objects() {
     const NAMES: &[&str] = &[
@@ -1276,6 +2688,176 @@ async fn test_flexible_retrieval_objects() {
     }
 }
 
+#[tokio::test]
+async fn test_object_xattrs() {
+    let (client, fs) = make_test_filesystem("test_object_xattrs", &Default::default(), Default::default());
+
+    let mut object = MockObject::constant(0xaa, 30, ETag::for_tests());
+    object.set_storage_class(Some("GLACIER".to_owned()));
+    object.set_restored(Some(RestoreStatus::InProgress));
+    client.add_object("dir/glacier", object);
+    client.add_object("dir/standard", MockObject::constant(0xaa, 30, ETag::for_tests()));
+
+    let dir = fs.lookup(FUSE_ROOT_INODE, "dir".as_ref()).await.unwrap();
+
+    let glacier = fs.lookup(dir.attr.ino, "glacier".as_ref()).await.unwrap();
+    let names = fs.listxattr(glacier.attr.ino).await.unwrap();
+    assert_eq!(
+        names,
+        b"user.mountpoint.storage_class\0user.mountpoint.etag\0user.mountpoint.restore_status\0"
+    );
+    let storage_class = fs
+        .getxattr(glacier.attr.ino, "user.mountpoint.storage_class".as_ref())
+        .await
+        .unwrap();
+    assert_eq!(storage_class.as_deref(), Some(&b"GLACIER"[..]));
+    let etag = fs
+        .getxattr(glacier.attr.ino, "user.mountpoint.etag".as_ref())
+        .await
+        .unwrap();
+    assert_eq!(etag.as_deref(), Some(ETag::for_tests().as_str().as_bytes()));
+    let restore_status = fs
+        .getxattr(glacier.attr.ino, "user.mountpoint.restore_status".as_ref())
+        .await
+        .unwrap();
+    assert_eq!(restore_status.as_deref(), Some(&br#"ongoing-request="true""#[..]));
+
+    // Objects only have the attributes S3 reported
+    let standard = fs.lookup(dir.attr.ino, "standard".as_ref()).await.unwrap();
+    let names = fs.listxattr(standard.attr.ino).await.unwrap();
+    assert_eq!(names, b"user.mountpoint.etag\0");
+    let restore_status = fs
+        .getxattr(standard.attr.ino, "user.mountpoint.restore_status".as_ref())
+        .await
+        .unwrap();
+    assert_eq!(restore_status, None);
+
+    // Directories and unknown attributes have no values
+    assert!(fs.listxattr(dir.attr.ino).await.unwrap().is_empty());
+    let unknown = fs
+        .getxattr(glacier.attr.ino, "security.capability".as_ref())
+        .await
+        .unwrap();
+    assert_eq!(unknown, None);
+}
+
+#[tokio::test]
+async fn test_user_metadata_xattrs() {
+    const BUCKET_NAME: &str = "test_user_metadata_xattrs";
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), Default::default());
+
+    let mode = libc::S_IFREG | libc::S_IRWXU; // regular file + 0700 permissions
+    let dentry = fs
+        .mknod(FUSE_ROOT_INODE, "file.txt".as_ref(), mode, 0, 0)
+        .await
+        .unwrap();
+    let file_ino = dentry.attr.ino;
+
+    // Attributes can only be set through a handle that is open for writing
+    let err = fs
+        .setxattr(file_ino, "user.color".as_ref(), b"blue", 0)
+        .await
+        .expect_err("file isn't open");
+    assert_eq!(err.to_errno(), libc::EPERM);
+
+    let fh = fs
+        .open(file_ino, libc::S_IFREG as i32 | libc::O_WRONLY, 0)
+        .await
+        .unwrap()
+        .fh;
+    fs.setxattr(file_ino, "user.color".as_ref(), b"blue", 0).await.unwrap();
+    let err = fs
+        .setxattr(file_ino, "user.color".as_ref(), b"red", libc::XATTR_CREATE)
+        .await
+        .expect_err("attribute already exists");
+    assert_eq!(err.to_errno(), libc::EEXIST);
+    for (name, value) in [
+        ("user.mountpoint.etag", &b"etag"[..]),
+        ("user.Color", b"blue"),
+        ("user.shape", b"\n"),
+        ("user.symlink-target", b"elsewhere"),
+    ] {
+        let err = fs
+            .setxattr(file_ino, name.as_ref(), value, 0)
+            .await
+            .expect_err("invalid attribute");
+        assert!(matches!(err.to_errno(), libc::EPERM | libc::EINVAL), "{name}: {err:?}");
+    }
+    let err = fs
+        .setxattr(file_ino, "trusted.color".as_ref(), b"blue", 0)
+        .await
+        .expect_err("only the user namespace is supported");
+    assert_eq!(err.to_errno(), libc::ENOTSUP);
+
+    let value = fs.getxattr(file_ino, "user.color".as_ref()).await.unwrap();
+    assert_eq!(value.as_deref(), Some(&b"blue"[..]));
+    assert_eq!(fs.listxattr(file_ino).await.unwrap(), b"user.color\0");
+
+    // Once data has been written, the upload has already started
+    fs.write(file_ino, fh, 0, &[0xaa; 10], 0, 0, None).await.unwrap();
+    let err = fs
+        .setxattr(file_ino, "user.shape".as_ref(), b"round", 0)
+        .await
+        .expect_err("upload has started");
+    assert_eq!(err.to_errno(), libc::EBUSY);
+    fs.release(file_ino, fh, 0, None, false).await.unwrap();
+
+    let head = client.head_object(BUCKET_NAME, "file.txt").await.unwrap();
+    let expected = HashMap::from([("color".to_owned(), "blue".to_owned())]);
+    assert_eq!(head.object_metadata, expected);
+
+    // The uploaded object's metadata is visible through the same attributes
+    let value = fs.getxattr(file_ino, "user.color".as_ref()).await.unwrap();
+    assert_eq!(value.as_deref(), Some(&b"blue"[..]));
+    let names = fs.listxattr(file_ino).await.unwrap();
+    assert!(names.ends_with(b"user.color\0"), "{names:?}");
+}
+
+#[tokio::test]
+async fn test_user_metadata_xattrs_cached() {
+    const BUCKET_NAME: &str = "test_user_metadata_xattrs_cached";
+    let fs_config = S3FilesystemConfig {
+        cache_config: CacheConfig {
+            serve_lookup_from_cache: true,
+            dir_ttl: Duration::from_secs(600),
+            file_ttl: Duration::from_secs(600),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let (client, fs) = make_test_filesystem(BUCKET_NAME, &Default::default(), fs_config);
+
+    let mut object = MockObject::from(b"hello world");
+    object.set_object_metadata(HashMap::from([("color".to_owned(), "blue".to_owned())]));
+    client.add_object("file.txt", object);
+
+    // Listing doesn't return metadata, so the first getxattr asks for it
+    let dir_handle = fs.opendir(FUSE_ROOT_INODE, 0).await.unwrap().fh;
+    let mut reply = DirectoryReply::new(5);
+    fs.readdirplus(FUSE_ROOT_INODE, dir_handle, 0, &mut reply)
+        .await
+        .unwrap();
+    let file_ino = reply
+        .entries
+        .iter()
+        .find(|entry| entry.name == "file.txt")
+        .expect("file should be listed")
+        .ino;
+
+    let head_counter = client.new_counter(Operation::HeadObject);
+    for _ in 0..3 {
+        let value = fs.getxattr(file_ino, "user.color".as_ref()).await.unwrap();
+        assert_eq!(value.as_deref(), Some(&b"blue"[..]));
+    }
+    let names = fs.listxattr(file_ino).await.unwrap();
+    assert!(names.ends_with(b"user.color\0"), "{names:?}");
+    assert_eq!(
+        head_counter.count(),
+        1,
+        "metadata should be cached with the file's stat"
+    );
+}
+
 #[tokio::test]
 async fn test_readdir_rewind_ordered() {
     let (client, fs) = make_test_filesystem("test_readdir_rewind", &Default::default(), Default::default());
@@ -1357,6 +2939,51 @@ async fn test_readdir_rewind_ordered() {
     }
 }
 
+#[tokio::test]
+async fn test_readdir_backpressure() {
+    /// Forwards entries through a channel with room for a single entry, so each `add` waits for the
+    /// consumer to take the previous entry.
+    struct ChannelReply(async_channel::Sender<DirectoryEntry>);
+
+    #[async_trait]
+    impl DirectoryReplier for ChannelReply {
+        async fn add(&mut self, entry: DirectoryEntry) -> bool {
+            self.0.send(entry).await.is_err()
+        }
+    }
+
+    let (client, fs) = make_test_filesystem("test_readdir_backpressure", &Default::default(), Default::default());
+
+    for i in 0..10 {
+        client.add_object(&format!("foo{i}"), b"foo".into());
+    }
+
+    let dir_handle = fs.opendir(FUSE_ROOT_INODE, 0).await.unwrap().fh;
+
+    let (sender, receiver) = async_channel::bounded(1);
+    let readdir = async {
+        // Dropping the reply closes the channel, which ends the consumer
+        let _ = fs
+            .readdir(FUSE_ROOT_INODE, dir_handle, 0, ChannelReply(sender))
+            .await
+            .unwrap();
+    };
+    let consume = async {
+        let mut names = Vec::new();
+        while let Ok(entry) = receiver.recv().await {
+            names.push(entry.name);
+        }
+        names
+    };
+    let ((), names) = futures::join!(readdir, consume);
+
+    let expected: Vec<OsString> = [".".into(), "..".into()]
+        .into_iter()
+        .chain((0..10).map(|i| format!("foo{i}").into()))
+        .collect();
+    assert_eq!(names, expected);
+}
+
 #[tokio::test]
 async fn test_readdir_rewind_unordered() {
     let config = S3FilesystemConfig {
@@ -0,0 +1,75 @@
+use std::fs::{self, read_dir, read_to_string, OpenOptions};
+use std::io::ErrorKind;
+use std::os::unix::prelude::PermissionsExt;
+
+use fuser::BackgroundSession;
+use mountpoint_s3::fs::ControlDirectoryMode;
+use mountpoint_s3::S3FilesystemConfig;
+use tempfile::TempDir;
+
+use crate::common::fuse::{self, read_dir_to_entry_names, TestClientBox, TestSessionConfig};
+
+fn control_directory_test<F>(creator_fn: F, prefix: &str)
+where
+    F: FnOnce(&str, TestSessionConfig) -> (TempDir, BackgroundSession, TestClientBox),
+{
+    let filesystem_config = S3FilesystemConfig {
+        control_directory: ControlDirectoryMode::Visible,
+        mount_config: "{}\n".to_owned(),
+        ..Default::default()
+    };
+    let test_config = TestSessionConfig {
+        filesystem_config,
+        ..Default::default()
+    };
+    let (mount_point, _session, mut test_client) = creator_fn(prefix, test_config);
+
+    test_client.put_object("bar", b"bar").unwrap();
+    test_client.put_object(".mountpoint/shadowed", b"shadowed").unwrap();
+
+    let root_entries = read_dir_to_entry_names(read_dir(mount_point.path()).unwrap());
+    assert_eq!(root_entries, vec![".mountpoint", "bar"]);
+
+    let control_dir = mount_point.path().join(".mountpoint");
+    let control_entries = read_dir_to_entry_names(read_dir(&control_dir).unwrap());
+    assert_eq!(control_entries, vec!["config", "stats", "cache", "uploads"]);
+    let err = fs::metadata(control_dir.join("shadowed")).expect_err("keys in S3 are shadowed");
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    let metadata = fs::metadata(&control_dir).unwrap();
+    assert!(metadata.is_dir());
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o500);
+    let metadata = fs::metadata(control_dir.join("stats")).unwrap();
+    assert!(metadata.is_file());
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o400);
+
+    // The files report a size of zero, but are read to the end anyway
+    assert_eq!(read_to_string(control_dir.join("config")).unwrap(), "{}\n");
+    let stats = read_to_string(control_dir.join("stats")).unwrap();
+    assert!(stats.starts_with("file_handles "), "unexpected stats: {stats:?}");
+
+    // Nothing in the control directory can be changed
+    let err = OpenOptions::new()
+        .write(true)
+        .open(control_dir.join("stats"))
+        .expect_err("control files are read-only");
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    let err = fs::create_dir(control_dir.join("new")).expect_err("the control directory is read-only");
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    let err = fs::remove_dir(&control_dir).expect_err("the control directory can't be removed");
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    let err =
+        fs::rename(&control_dir, mount_point.path().join("renamed")).expect_err("the control directory can't move");
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+}
+
+#[cfg(feature = "s3_tests")]
+#[test]
+fn control_directory_test_s3() {
+    control_directory_test(fuse::s3_session::new, "control_directory_test");
+}
+
+#[test]
+fn control_directory_test_mock() {
+    control_directory_test(fuse::mock_session::new, "control_directory_test");
+}
@@ -454,6 +454,41 @@ fn mount_scoped_credentials() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+// S3 Express One Zone doesn't support scoped credentials
+#[cfg(not(feature = "s3express_tests"))]
+fn mount_scope_credentials_option() -> Result<(), Box<dyn std::error::Error>> {
+    let (bucket, prefix) = get_test_bucket_and_prefix("mount_scope_credentials_option");
+    let mount_point = assert_fs::TempDir::new()?;
+    let region = get_test_region();
+    let subsession_role = get_subsession_iam_role();
+
+    // Mountpoint assumes the role with a session policy for the prefix it mounts
+    let mut cmd = Command::cargo_bin("mount-s3")?;
+    let child = cmd
+        .arg(&bucket)
+        .arg(mount_point.path())
+        .arg(format!("--prefix={prefix}"))
+        .arg("--auto-unmount")
+        .arg(format!("--region={region}"))
+        .arg("--scope-credentials")
+        .arg(format!("--role-arn={subsession_role}"))
+        .spawn()
+        .expect("unable to spawn child");
+
+    let exit_status = wait_for_exit(child);
+
+    // verify mount status and mount entry
+    assert!(exit_status.success());
+    assert!(mount_exists("mountpoint-s3", mount_point.path().to_str().unwrap()));
+
+    test_read_files(&bucket, &prefix, &region, &mount_point.to_path_buf());
+
+    unmount(mount_point.path());
+
+    Ok(())
+}
+
 #[cfg(not(feature = "s3express_tests"))]
 fn mount_with_sse(
     bucket: &str,
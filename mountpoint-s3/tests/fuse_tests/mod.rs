@@ -1,4 +1,5 @@
 mod consistency_test;
+mod control_test;
 mod fork_test;
 mod lookup_test;
 mod mkdir_test;
@@ -6,8 +7,10 @@ mod perm_test;
 mod prefetch_test;
 mod read_test;
 mod readdir_test;
+mod rename_test;
 mod rmdir_test;
 mod semantics_doc_test;
 mod setattr_test;
+mod symlink_test;
 mod unlink_test;
 mod write_test;
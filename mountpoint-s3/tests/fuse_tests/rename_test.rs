@@ -0,0 +1,98 @@
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Write};
+
+use fuser::BackgroundSession;
+use mountpoint_s3::S3FilesystemConfig;
+use tempfile::TempDir;
+use test_case::test_case;
+
+use crate::common::fuse::{self, read_dir_to_entry_names, TestClientBox, TestSessionConfig};
+
+fn rename_file_test<F>(creator_fn: F, prefix: &str)
+where
+    F: FnOnce(&str, TestSessionConfig) -> (TempDir, BackgroundSession, TestClientBox),
+{
+    let test_session_config = TestSessionConfig {
+        filesystem_config: S3FilesystemConfig {
+            allow_delete: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let (mount_point, _session, test_client) = creator_fn(prefix, test_session_config);
+
+    let main_dir = mount_point.path().join("dir");
+    fs::create_dir(&main_dir).unwrap();
+
+    // Write a file, then move it into place like an editor or rsync would
+    let tmp_path = main_dir.join(".hello.txt.tmp");
+    let mut f = File::create(&tmp_path).unwrap();
+    f.write_all(b"hello world").unwrap();
+    drop(f);
+
+    let path = main_dir.join("hello.txt");
+    fs::rename(&tmp_path, &path).expect("rename should succeed");
+
+    assert!(!test_client.contains_key("dir/.hello.txt.tmp").unwrap());
+    assert!(test_client.contains_key("dir/hello.txt").unwrap());
+
+    let mut contents = String::new();
+    File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "hello world");
+    assert_eq!(fs::metadata(&path).unwrap().len(), 11);
+
+    let err = fs::metadata(&tmp_path).expect_err("old name should be gone");
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+
+    let read_dir_iter = fs::read_dir(&main_dir).unwrap();
+    let dir_entry_names = read_dir_to_entry_names(read_dir_iter);
+    assert_eq!(dir_entry_names, vec!["hello.txt"]);
+
+    // Renaming over an existing file needs --allow-overwrite
+    let other_path = main_dir.join("other.txt");
+    File::create(&other_path).unwrap().write_all(b"other").unwrap();
+    fs::rename(&other_path, &path).expect_err("rename over existing file should fail");
+    assert!(test_client.contains_key("dir/other.txt").unwrap());
+
+    // Directories can't be renamed
+    fs::rename(&main_dir, mount_point.path().join("dir2")).expect_err("rename of directory should fail");
+}
+
+#[cfg(feature = "s3_tests")]
+#[test]
+fn rename_file_test_s3() {
+    rename_file_test(fuse::s3_session::new, "rename_file_test");
+}
+
+#[test_case(""; "no prefix")]
+#[test_case("rename_file_test"; "prefix")]
+fn rename_file_test_mock(prefix: &str) {
+    rename_file_test(fuse::mock_session::new, prefix);
+}
+
+fn rename_disabled_test<F>(creator_fn: F, prefix: &str)
+where
+    F: FnOnce(&str, TestSessionConfig) -> (TempDir, BackgroundSession, TestClientBox),
+{
+    let (mount_point, _session, mut test_client) = creator_fn(prefix, Default::default());
+
+    test_client.put_object("hello.txt", b"hello world").unwrap();
+
+    let err = fs::rename(mount_point.path().join("hello.txt"), mount_point.path().join("new.txt"))
+        .expect_err("rename should fail without --allow-delete");
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    assert!(test_client.contains_key("hello.txt").unwrap());
+    assert!(!test_client.contains_key("new.txt").unwrap());
+}
+
+#[cfg(feature = "s3_tests")]
+#[test]
+fn rename_disabled_test_s3() {
+    rename_disabled_test(fuse::s3_session::new, "rename_disabled_test");
+}
+
+#[test_case(""; "no prefix")]
+#[test_case("rename_disabled_test"; "prefix")]
+fn rename_disabled_test_mock(prefix: &str) {
+    rename_disabled_test(fuse::mock_session::new, prefix);
+}
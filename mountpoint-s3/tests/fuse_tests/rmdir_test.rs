@@ -1,5 +1,6 @@
 use crate::common::fuse::{self, read_dir_to_entry_names, TestClientBox, TestSessionConfig};
 use fuser::BackgroundSession;
+use mountpoint_s3::S3FilesystemConfig;
 use std::fs::{self, DirBuilder, File};
 use std::io::Write;
 use tempfile::TempDir;
@@ -93,8 +94,8 @@ where
         .put_object(&format!("{main_dirname}/{remote_dirname}/hello.txt"), b"hello world")
         .unwrap();
     let remote_path = main_path.join(remote_dirname);
-    let err = fs::remove_dir(remote_path).expect_err("removing remote directory should fail");
-    assert_eq!(err.raw_os_error(), Some(libc::EPERM));
+    let err = fs::remove_dir(remote_path).expect_err("removing non-empty remote directory should fail");
+    assert_eq!(err.raw_os_error(), Some(libc::ENOTEMPTY));
 
     // checking if the test directory has correct entries
     let read_dir_iter = fs::read_dir(&main_path).unwrap();
@@ -107,7 +108,7 @@ where
         .put_object(&format!("{main_dirname}/{empty_remote_dirname}/"), b"")
         .unwrap();
     let empty_remote_path = main_path.join(empty_remote_dirname);
-    let err = fs::remove_dir(empty_remote_path).expect_err("removing remote directory should fail");
+    let err = fs::remove_dir(empty_remote_path).expect_err("deleting directory marker needs --allow-delete");
     assert_eq!(err.raw_os_error(), Some(libc::EPERM));
 
     let remote_filename = "remote_file";
@@ -183,3 +184,52 @@ fn create_after_rmdir_test_mock(prefix: &str) {
 fn create_after_rmdir_test_s3(prefix: &str) {
     create_after_rmdir_test(fuse::s3_session::new, prefix);
 }
+
+fn rmdir_empty_remote_dir_test<F>(creator_fn: F, prefix: &str)
+where
+    F: FnOnce(&str, TestSessionConfig) -> (TempDir, BackgroundSession, TestClientBox),
+{
+    let test_session_config = TestSessionConfig {
+        filesystem_config: S3FilesystemConfig {
+            allow_delete: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let (mount_point, _session, mut test_client) = creator_fn(prefix, test_session_config);
+
+    let main_dirname = "test_dir";
+    let main_path = mount_point.path().join(main_dirname);
+    test_client
+        .put_object(&format!("{main_dirname}/marker_dir/"), b"")
+        .unwrap();
+    test_client
+        .put_object(&format!("{main_dirname}/file_dir/hello.txt"), b"hello world")
+        .unwrap();
+
+    // Removing a directory with only a marker deletes the marker
+    fs::remove_dir(main_path.join("marker_dir")).expect("removing empty remote directory should succeed");
+    assert!(!test_client
+        .contains_key(&format!("{main_dirname}/marker_dir/"))
+        .unwrap());
+
+    let err = fs::remove_dir(main_path.join("file_dir")).expect_err("directory is not empty");
+    assert_eq!(err.raw_os_error(), Some(libc::ENOTEMPTY));
+
+    let read_dir_iter = fs::read_dir(&main_path).unwrap();
+    let dir_entry_names = read_dir_to_entry_names(read_dir_iter);
+    assert_eq!(dir_entry_names, vec!["file_dir"]);
+}
+
+#[test_case(""; "no prefix")]
+#[test_case("rmdir_test"; "prefix")]
+fn rmdir_empty_remote_dir_test_mock(prefix: &str) {
+    rmdir_empty_remote_dir_test(fuse::mock_session::new, prefix);
+}
+
+#[cfg(feature = "s3_tests")]
+#[test_case(""; "no prefix")]
+#[test_case("rmdir_test"; "prefix")]
+fn rmdir_empty_remote_dir_test_s3(prefix: &str) {
+    rmdir_empty_remote_dir_test(fuse::s3_session::new, prefix);
+}
@@ -0,0 +1,93 @@
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read};
+use std::os::unix::fs::symlink;
+use std::path::Path;
+
+use fuser::BackgroundSession;
+use mountpoint_s3::S3FilesystemConfig;
+use tempfile::TempDir;
+use test_case::test_case;
+
+use crate::common::fuse::{self, TestClientBox, TestSessionConfig};
+
+fn symlink_test<F>(creator_fn: F, prefix: &str)
+where
+    F: FnOnce(&str, TestSessionConfig) -> (TempDir, BackgroundSession, TestClientBox),
+{
+    let test_session_config = TestSessionConfig {
+        filesystem_config: S3FilesystemConfig {
+            allow_symlinks: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let (mount_point, _session, mut test_client) = creator_fn(prefix, test_session_config);
+
+    test_client.put_object("dir/hello.txt", b"hello world").unwrap();
+
+    let main_dir = mount_point.path().join("dir");
+    let link_path = main_dir.join("link.txt");
+    symlink("hello.txt", &link_path).expect("symlink should succeed");
+    assert!(test_client.contains_key("dir/link.txt").unwrap());
+
+    assert_eq!(fs::read_link(&link_path).unwrap(), Path::new("hello.txt"));
+    let metadata = fs::symlink_metadata(&link_path).unwrap();
+    assert!(metadata.file_type().is_symlink());
+    assert_eq!(metadata.len(), "hello.txt".len() as u64);
+
+    // Reading through the link reads the target
+    let mut contents = String::new();
+    File::open(&link_path).unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "hello world");
+
+    // Links show up as links when listing the directory too
+    let mut entries = fs::read_dir(&main_dir)
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            (entry.file_name(), entry.file_type().unwrap().is_symlink())
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+    assert_eq!(entries, vec![("hello.txt".into(), false), ("link.txt".into(), true)]);
+
+    // Links can't replace existing files
+    let err = symlink("hello.txt", main_dir.join("hello.txt")).expect_err("symlink over existing file should fail");
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+}
+
+#[cfg(feature = "s3_tests")]
+#[test]
+fn symlink_test_s3() {
+    symlink_test(fuse::s3_session::new, "symlink_test");
+}
+
+#[test_case(""; "no prefix")]
+#[test_case("symlink_test"; "prefix")]
+fn symlink_test_mock(prefix: &str) {
+    symlink_test(fuse::mock_session::new, prefix);
+}
+
+fn symlink_disabled_test<F>(creator_fn: F, prefix: &str)
+where
+    F: FnOnce(&str, TestSessionConfig) -> (TempDir, BackgroundSession, TestClientBox),
+{
+    let (mount_point, _session, test_client) = creator_fn(prefix, Default::default());
+
+    let err = symlink("hello.txt", mount_point.path().join("link.txt"))
+        .expect_err("symlink should fail without --allow-symlinks");
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    assert!(!test_client.contains_key("link.txt").unwrap());
+}
+
+#[cfg(feature = "s3_tests")]
+#[test]
+fn symlink_disabled_test_s3() {
+    symlink_disabled_test(fuse::s3_session::new, "symlink_disabled_test");
+}
+
+#[test_case(""; "no prefix")]
+#[test_case("symlink_disabled_test"; "prefix")]
+fn symlink_disabled_test_mock(prefix: &str) {
+    symlink_disabled_test(fuse::mock_session::new, prefix);
+}
@@ -1,6 +1,6 @@
 use std::fs::{metadata, read, read_dir, File};
 use std::io::{ErrorKind, Read, Seek, Write};
-use std::os::unix::prelude::OpenOptionsExt;
+use std::os::unix::prelude::{FileExt, OpenOptionsExt};
 use std::path::Path;
 use std::process::Command;
 use std::thread;
@@ -417,6 +417,69 @@ fn out_of_order_write_test_mock(offset: i64) {
     out_of_order_write_test(fuse::mock_session::new, offset);
 }
 
+fn reordered_write_test<F>(creator_fn: F)
+where
+    F: FnOnce(&str, TestSessionConfig) -> (TempDir, BackgroundSession, TestClientBox),
+{
+    const CHUNK_SIZE: usize = 32;
+    const KEY: &str = "new.txt";
+
+    let config = TestSessionConfig {
+        filesystem_config: S3FilesystemConfig {
+            write_reorder_window: 2 * CHUNK_SIZE as u64,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let (mount_point, _session, _test_client) = creator_fn("reordered_write_test", config);
+
+    let path = mount_point.path().join(KEY);
+
+    let f = open_for_write(&path, false, true).unwrap();
+
+    let mut rng = ChaCha20Rng::seed_from_u64(0x12345678);
+    let mut body = vec![0u8; 4 * CHUNK_SIZE];
+    rng.fill(&mut body[..]);
+    let chunks: Vec<_> = body.chunks(CHUNK_SIZE).collect();
+
+    // Writes within the window are accepted in any order
+    f.write_all_at(chunks[0], 0).unwrap();
+    f.write_all_at(chunks[2], 2 * CHUNK_SIZE as u64).unwrap();
+    f.write_all_at(chunks[1], CHUNK_SIZE as u64).unwrap();
+
+    // Writes that end beyond the window still fail
+    let err = f
+        .write_all_at(chunks[0], 5 * CHUNK_SIZE as u64)
+        .expect_err("write beyond the window should fail");
+    assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+    drop(f);
+
+    let err = metadata(&path).expect_err("upload shouldn't have succeeded");
+    assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+
+    // Once the gaps are filled, the buffered writes are uploaded
+    let path = mount_point.path().join("reordered.txt");
+    let f = open_for_write(&path, false, true).unwrap();
+    f.write_all_at(chunks[1], CHUNK_SIZE as u64).unwrap();
+    f.write_all_at(chunks[0], 0).unwrap();
+    f.write_all_at(chunks[3], 3 * CHUNK_SIZE as u64).unwrap();
+    f.write_all_at(chunks[2], 2 * CHUNK_SIZE as u64).unwrap();
+    drop(f);
+
+    assert_eq!(read(&path).unwrap(), body);
+}
+
+#[cfg(feature = "s3_tests")]
+#[test]
+fn reordered_write_test_s3() {
+    reordered_write_test(fuse::s3_session::new);
+}
+
+#[test]
+fn reordered_write_test_mock() {
+    reordered_write_test(fuse::mock_session::new);
+}
+
 #[cfg(not(feature = "s3express_tests"))]
 fn write_with_storage_class_test<F>(creator_fn: F, storage_class: Option<&str>)
 where
@@ -725,6 +788,51 @@ fn overwrite_test_mock(write_only: bool) {
     overwrite_test(fuse::mock_session::new, "overwrite_test", write_only);
 }
 
+fn overwrite_with_ftruncate_test<F>(creator_fn: F, prefix: &str)
+where
+    F: FnOnce(&str, TestSessionConfig) -> (TempDir, BackgroundSession, TestClientBox),
+{
+    let filesystem_config = S3FilesystemConfig {
+        allow_overwrite: true,
+        ..Default::default()
+    };
+    let test_config = TestSessionConfig {
+        filesystem_config,
+        ..Default::default()
+    };
+    let (mount_point, _session, mut test_client) = creator_fn(prefix, test_config);
+
+    test_client.put_object("dir/hello.txt", b"hello world").unwrap();
+    let path = mount_point.path().join("dir/hello.txt");
+
+    // Open without O_TRUNC, then truncate through the file handle and write something new
+    let mut fh = File::options().read(true).write(true).open(&path).unwrap();
+    fh.set_len(0).expect("ftruncate to zero should succeed");
+    assert_eq!(fh.metadata().unwrap().len(), 0);
+    fh.write_all(b"overwrite").expect("write should succeed");
+    fh.sync_all().unwrap();
+    drop(fh);
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(contents, "overwrite");
+
+    // Truncating to any other size is still not supported
+    let fh = File::options().read(true).write(true).open(&path).unwrap();
+    let err = fh.set_len(3).expect_err("ftruncate to non-zero size should fail");
+    assert_eq!(err.raw_os_error(), Some(libc::EPERM));
+}
+
+#[cfg(feature = "s3_tests")]
+#[test]
+fn overwrite_with_ftruncate_test_s3() {
+    overwrite_with_ftruncate_test(fuse::s3_session::new, "overwrite_with_ftruncate_test");
+}
+
+#[test]
+fn overwrite_with_ftruncate_test_mock() {
+    overwrite_with_ftruncate_test(fuse::mock_session::new, "overwrite_with_ftruncate_test");
+}
+
 fn overwrite_disallowed_on_concurrent_read_test<F>(creator_fn: F, prefix: &str)
 where
     F: FnOnce(&str, TestSessionConfig) -> (TempDir, BackgroundSession, TestClientBox),
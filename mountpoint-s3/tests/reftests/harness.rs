@@ -519,12 +519,28 @@ impl Harness {
             panic!("node must be a directory");
         };
 
-        // Only empty local directories can be removed
+        let (is_local, is_empty) = (*is_local, children.is_empty());
+
+        // Empty local directories can be removed. Empty remote directories can be removed if the
+        // only key left under them is a directory marker, which rmdir deletes.
+        let marker_key = format!("{}/", full_path.strip_prefix("/").unwrap().display());
+        let has_marker = self.reference.remote_keys().any(|key| key == marker_key);
+        let marker_only = self
+            .reference
+            .remote_keys()
+            .filter(|key| key.starts_with(&marker_key))
+            .all(|key| key == marker_key);
+
         let dir_name = full_path.file_name().expect("directory must have a name");
         let rmdir = self.fs.rmdir(parent_inode, dir_name).await;
-        if *is_local && children.is_empty() {
+        if is_local && is_empty {
             rmdir.expect("should be able to remove empty local directory");
             self.reference.remove_local_directory(&full_path);
+        } else if is_empty && marker_only {
+            rmdir.expect("should be able to remove empty remote directory");
+            if has_marker {
+                self.reference.remove_remote_key(&marker_key);
+            }
         } else {
             rmdir.expect_err("rmdir should fail");
         }
@@ -4,6 +4,7 @@ set -euo pipefail
 FUSER_VENDOR_PATH="vendor/fuser"
 BASE_PATH=$( cd -- "$( dirname -- "${BASH_SOURCE[0]}" )" &> /dev/null && pwd )
 FUSER_FULL_PATH="$BASE_PATH/$FUSER_VENDOR_PATH"
+FUSER_PATCHES_PATH="$BASE_PATH/vendor/fuser-patches"
 
 STATUS=$(git status --porcelain $FUSER_FULL_PATH)
 if [ -n "$STATUS"  ]; then
@@ -18,6 +19,14 @@ COMMIT=$(git -C $FUSER_FULL_PATH rev-parse --short HEAD)
 
 rm -rf $FUSER_FULL_PATH/.git
 
+# Re-apply changes that are not yet on the fork branch. Drop a patch from this
+# directory once the fork carries it, otherwise it will fail to apply here.
+for PATCH in "$FUSER_PATCHES_PATH"/*.patch; do
+    [ -e "$PATCH" ] || continue
+    echo "Applying $(basename "$PATCH")"
+    git -C "$BASE_PATH" apply "$PATCH"
+done
+
 git add $FUSER_FULL_PATH
 
 git commit -m "Update vendored fuser to $COMMIT" -s